@@ -0,0 +1 @@
+ALTER TABLE movie_collection ADD COLUMN credits_start DOUBLE PRECISION;
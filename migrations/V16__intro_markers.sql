@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS intro_markers (
+    show TEXT NOT NULL,
+    season INTEGER NOT NULL,
+    intro_start DOUBLE PRECISION NOT NULL,
+    intro_end DOUBLE PRECISION NOT NULL,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (show, season)
+);
+
+ALTER TABLE movie_collection ADD COLUMN intro_start DOUBLE PRECISION;
+ALTER TABLE movie_collection ADD COLUMN intro_end DOUBLE PRECISION;
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS scan_exclusions (
+    glob TEXT NOT NULL PRIMARY KEY,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS offline_files (
+    token TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL,
+    collection_idx INTEGER NOT NULL REFERENCES movie_collection (idx),
+    path TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
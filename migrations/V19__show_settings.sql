@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS show_settings (
+    link TEXT NOT NULL PRIMARY KEY,
+    alias TEXT,
+    numbering_scheme TEXT,
+    destination_dir TEXT,
+    ordering TEXT,
+    auto_queue BOOLEAN NOT NULL DEFAULT false,
+    dropped BOOLEAN NOT NULL DEFAULT false,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS media_ids (
+    show_id INTEGER NOT NULL REFERENCES imdb_ratings (index) ON DELETE CASCADE,
+    id_type TEXT NOT NULL,
+    id_value TEXT NOT NULL,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (id_type, id_value)
+);
+
+CREATE INDEX IF NOT EXISTS media_ids_show_id_idx ON media_ids (show_id);
+
+INSERT INTO media_ids (show_id, id_type, id_value)
+SELECT index, 'imdb', link FROM imdb_ratings WHERE link IS NOT NULL
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS user_preferences (
+    email TEXT NOT NULL PRIMARY KEY,
+    plex_account TEXT,
+    history_private BOOLEAN NOT NULL DEFAULT false,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
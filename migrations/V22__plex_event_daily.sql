@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS plex_event_daily (
+    day DATE NOT NULL,
+    account TEXT NOT NULL,
+    event TEXT NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (day, account, event)
+);
+
+CREATE INDEX IF NOT EXISTS plex_event_created_at_idx ON plex_event (created_at);
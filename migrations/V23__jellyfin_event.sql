@@ -0,0 +1,20 @@
+CREATE SEQUENCE IF NOT EXISTS jellyfin_event_id_seq;
+
+CREATE TABLE IF NOT EXISTS jellyfin_event (
+    id INTEGER NOT NULL PRIMARY KEY DEFAULT nextval('jellyfin_event_id_seq'::regclass),
+    event TEXT NOT NULL,
+    account TEXT NOT NULL,
+    server TEXT NOT NULL,
+    item_id TEXT,
+    item_type TEXT,
+    device_name TEXT,
+    client_name TEXT,
+    title TEXT,
+    series_name TEXT,
+    season_number INTEGER,
+    episode_number INTEGER,
+    filename TEXT,
+    collection_idx INTEGER,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS transcode_jobs (
+    job_type TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    queue TEXT NOT NULL,
+    input_path TEXT NOT NULL,
+    output_path TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    message TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    started_at TIMESTAMP WITH TIME ZONE,
+    finished_at TIMESTAMP WITH TIME ZONE,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (job_type, prefix)
+);
+
+CREATE INDEX IF NOT EXISTS transcode_jobs_status_idx ON transcode_jobs (status);
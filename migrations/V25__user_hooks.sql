@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS user_hooks (
+    id SERIAL PRIMARY KEY,
+    email TEXT NOT NULL,
+    event TEXT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS user_hooks_event_idx ON user_hooks (event);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS plex_metadata (
+    collection_idx INTEGER NOT NULL PRIMARY KEY REFERENCES movie_collection (idx),
+    server_uuid TEXT NOT NULL,
+    metadata_key TEXT NOT NULL,
+    view_offset BIGINT NOT NULL DEFAULT 0,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS user_watched (
+    email TEXT NOT NULL,
+    collection_idx INTEGER NOT NULL REFERENCES movie_collection (idx),
+    watched_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (email, collection_idx)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE transcode_jobs ADD COLUMN input_size BIGINT;
+ALTER TABLE transcode_jobs ADD COLUMN preset TEXT;
+ALTER TABLE transcode_jobs ADD COLUMN worker TEXT;
@@ -0,0 +1,29 @@
+ALTER TABLE movie_collection ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector('simple', coalesce(show, '') || ' ' || translate(coalesce(path, ''), '/_.-', '    '))
+    ) STORED;
+CREATE INDEX movie_collection_search_idx ON movie_collection USING GIN (search_vector);
+
+ALTER TABLE imdb_ratings ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector('simple', translate(coalesce(show, ''), '_', ' ') || ' ' || coalesce(title, ''))
+    ) STORED;
+CREATE INDEX imdb_ratings_search_idx ON imdb_ratings USING GIN (search_vector);
+
+ALTER TABLE imdb_episodes ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (to_tsvector('simple', coalesce(eptitle, ''))) STORED;
+CREATE INDEX imdb_episodes_search_idx ON imdb_episodes USING GIN (search_vector);
+
+ALTER TABLE trakt_watchlist ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (to_tsvector('simple', coalesce(title, ''))) STORED;
+CREATE INDEX trakt_watchlist_search_idx ON trakt_watchlist USING GIN (search_vector);
+
+ALTER TABLE plex_event ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector(
+            'simple',
+            coalesce(title, '') || ' ' || coalesce(parent_title, '') || ' ' ||
+            coalesce(grandparent_title, '')
+        )
+    ) STORED;
+CREATE INDEX plex_event_search_idx ON plex_event USING GIN (search_vector);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS show_availability (
+    link TEXT NOT NULL,
+    region TEXT NOT NULL,
+    providers JSONB NOT NULL DEFAULT '[]',
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (link, region)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS queue_share (
+    token TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS collection_keep (
+    collection_idx INTEGER NOT NULL PRIMARY KEY REFERENCES movie_collection (idx),
+    email TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS collection_trash (
+    collection_idx INTEGER NOT NULL PRIMARY KEY REFERENCES movie_collection (idx),
+    path TEXT NOT NULL,
+    trash_path TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    trashed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,28 @@
+WITH ranked AS (
+    SELECT id,
+           row_number() OVER w AS rn,
+           first_value(epurl) OVER (
+               PARTITION BY show, season, episode
+               ORDER BY (epurl = ''), last_modified DESC, id DESC
+           ) AS best_epurl
+    FROM imdb_episodes
+    WINDOW w AS (PARTITION BY show, season, episode ORDER BY last_modified DESC, id DESC)
+)
+UPDATE imdb_episodes a
+SET epurl = r.best_epurl
+FROM ranked r
+WHERE a.id = r.id AND r.rn = 1 AND a.epurl <> r.best_epurl;
+
+DELETE FROM imdb_episodes a
+USING (
+    SELECT id,
+           row_number() OVER (
+               PARTITION BY show, season, episode
+               ORDER BY last_modified DESC, id DESC
+           ) AS rn
+    FROM imdb_episodes
+) r
+WHERE a.id = r.id AND r.rn > 1;
+
+ALTER TABLE imdb_episodes
+    ADD CONSTRAINT imdb_episodes_show_season_episode_key UNIQUE (show, season, episode);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS music_collection (
+    id SERIAL PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    artist TEXT,
+    album TEXT,
+    title TEXT,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS webhook_failures (
+    id SERIAL PRIMARY KEY,
+    source TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    replayed_at TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS scan_history (
+    id SERIAL PRIMARY KEY,
+    trigger TEXT NOT NULL,
+    status TEXT NOT NULL,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    finished_at TIMESTAMP WITH TIME ZONE,
+    added BIGINT,
+    error TEXT
+);
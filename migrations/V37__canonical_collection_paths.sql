@@ -0,0 +1,87 @@
+CREATE OR REPLACE FUNCTION canonical_path(p TEXT) RETURNS TEXT AS $$
+    SELECT CASE WHEN length(x) > 1 THEN rtrim(x, '/') ELSE x END
+    FROM (
+        SELECT regexp_replace(regexp_replace(p, '/+', '/', 'g'), '/\.(?=/|$)', '', 'g') AS x
+    ) t
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE TEMP TABLE collection_path_merge AS
+SELECT old_idx, new_idx FROM (
+    SELECT idx AS old_idx,
+           first_value(idx) OVER (
+               PARTITION BY canonical_path(path) ORDER BY is_deleted, idx
+           ) AS new_idx
+    FROM movie_collection
+    WHERE path IS NOT NULL
+) m
+WHERE old_idx != new_idx;
+
+DELETE FROM movie_queue a
+WHERE a.collection_idx IN (
+    SELECT old_idx FROM collection_path_merge
+    UNION SELECT new_idx FROM collection_path_merge
+) AND EXISTS (
+    SELECT 1 FROM movie_queue b
+    LEFT JOIN collection_path_merge mb ON b.collection_idx = mb.old_idx
+    LEFT JOIN collection_path_merge ma ON a.collection_idx = ma.old_idx
+    WHERE coalesce(mb.new_idx, b.collection_idx) = coalesce(ma.new_idx, a.collection_idx)
+      AND b.idx < a.idx
+);
+UPDATE movie_queue a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+DELETE FROM user_watched a USING collection_path_merge m
+WHERE a.collection_idx = m.old_idx AND EXISTS (
+    SELECT 1 FROM user_watched b
+    LEFT JOIN collection_path_merge m2 ON b.collection_idx = m2.old_idx
+    WHERE coalesce(m2.new_idx, b.collection_idx) = m.new_idx AND b.email = a.email
+      AND (b.collection_idx = m.new_idx OR b.collection_idx < a.collection_idx)
+);
+UPDATE user_watched a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+DELETE FROM plex_metadata a USING collection_path_merge m
+WHERE a.collection_idx = m.old_idx AND EXISTS (
+    SELECT 1 FROM plex_metadata b
+    LEFT JOIN collection_path_merge m2 ON b.collection_idx = m2.old_idx
+    WHERE coalesce(m2.new_idx, b.collection_idx) = m.new_idx
+      AND (b.collection_idx = m.new_idx OR b.collection_idx < a.collection_idx)
+);
+UPDATE plex_metadata a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+DELETE FROM collection_keep a USING collection_path_merge m
+WHERE a.collection_idx = m.old_idx AND EXISTS (
+    SELECT 1 FROM collection_keep b
+    LEFT JOIN collection_path_merge m2 ON b.collection_idx = m2.old_idx
+    WHERE coalesce(m2.new_idx, b.collection_idx) = m.new_idx
+      AND (b.collection_idx = m.new_idx OR b.collection_idx < a.collection_idx)
+);
+UPDATE collection_keep a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+DELETE FROM collection_trash a USING collection_path_merge m
+WHERE a.collection_idx = m.old_idx AND EXISTS (
+    SELECT 1 FROM collection_trash b
+    LEFT JOIN collection_path_merge m2 ON b.collection_idx = m2.old_idx
+    WHERE coalesce(m2.new_idx, b.collection_idx) = m.new_idx
+      AND (b.collection_idx = m.new_idx OR b.collection_idx < a.collection_idx)
+);
+UPDATE collection_trash a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+UPDATE offline_files a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+UPDATE jellyfin_event a SET collection_idx = m.new_idx
+FROM collection_path_merge m WHERE a.collection_idx = m.old_idx;
+
+DELETE FROM movie_collection WHERE idx IN (SELECT old_idx FROM collection_path_merge);
+
+UPDATE movie_collection SET path = canonical_path(path)
+WHERE path IS NOT NULL AND path != canonical_path(path);
+
+DROP TABLE collection_path_merge;
+
+CREATE UNIQUE INDEX IF NOT EXISTS movie_collection_canonical_path_idx
+    ON movie_collection (canonical_path(path));
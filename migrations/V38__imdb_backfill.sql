@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS imdb_backfill (
+    show TEXT PRIMARY KEY,
+    link TEXT NOT NULL,
+    status TEXT NOT NULL,
+    added BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+ALTER TABLE transcode_jobs ADD COLUMN priority SMALLINT NOT NULL DEFAULT 5;
+
+CREATE INDEX IF NOT EXISTS transcode_jobs_queued_priority_idx
+    ON transcode_jobs (queue, priority DESC, created_at) WHERE status = 'queued';
@@ -0,0 +1 @@
+ALTER TABLE webhook_failures ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS plex_servers (
+    server_id TEXT NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    host TEXT NOT NULL,
+    token TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+ALTER TABLE plex_event ADD COLUMN server_id TEXT;
+
+ALTER TABLE plex_metadata DROP CONSTRAINT plex_metadata_pkey;
+ALTER TABLE plex_metadata ADD PRIMARY KEY (collection_idx, server_uuid);
@@ -0,0 +1,2 @@
+ALTER TABLE imdb_ratings ADD COLUMN last_refreshed TIMESTAMP WITH TIME ZONE;
+ALTER TABLE imdb_ratings ADD COLUMN refresh_error TEXT;
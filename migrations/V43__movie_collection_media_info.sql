@@ -0,0 +1,5 @@
+ALTER TABLE movie_collection ADD COLUMN file_size BIGINT;
+ALTER TABLE movie_collection ADD COLUMN duration DOUBLE PRECISION;
+ALTER TABLE movie_collection ADD COLUMN resolution TEXT;
+ALTER TABLE movie_collection ADD COLUMN video_codec TEXT;
+ALTER TABLE movie_collection ADD COLUMN audio_codec TEXT;
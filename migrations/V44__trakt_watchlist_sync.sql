@@ -0,0 +1,17 @@
+ALTER TABLE trakt_watchlist ADD COLUMN last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now();
+
+CREATE TABLE IF NOT EXISTS trakt_watchlist_removed (
+    link TEXT NOT NULL PRIMARY KEY,
+    removed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS trakt_sync_report (
+    id SERIAL PRIMARY KEY,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    finished_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    added_local INTEGER NOT NULL DEFAULT 0,
+    removed_local INTEGER NOT NULL DEFAULT 0,
+    added_remote INTEGER NOT NULL DEFAULT 0,
+    removed_remote INTEGER NOT NULL DEFAULT 0,
+    errors TEXT
+);
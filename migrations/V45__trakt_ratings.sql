@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS trakt_ratings (
+    link TEXT NOT NULL,
+    season INTEGER NOT NULL DEFAULT -1,
+    episode INTEGER NOT NULL DEFAULT -1,
+    rating INTEGER NOT NULL,
+    rated_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    PRIMARY KEY (link, season, episode)
+);
@@ -0,0 +1 @@
+ALTER TABLE imdb_ratings ADD COLUMN numbering TEXT NOT NULL DEFAULT 'seasonal';
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS playback_position (
+    email TEXT NOT NULL,
+    collection_idx INTEGER NOT NULL REFERENCES movie_collection (idx),
+    position DOUBLE PRECISION NOT NULL,
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (email, collection_idx)
+);
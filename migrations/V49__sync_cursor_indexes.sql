@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS imdb_episodes_sync_idx ON imdb_episodes (last_modified, id);
+CREATE INDEX IF NOT EXISTS imdb_ratings_sync_idx ON imdb_ratings (last_modified, index);
+CREATE INDEX IF NOT EXISTS plex_event_sync_idx ON plex_event (last_modified, id);
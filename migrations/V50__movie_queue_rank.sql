@@ -0,0 +1,20 @@
+ALTER TABLE movie_queue RENAME TO movie_queue_entry;
+ALTER TABLE movie_queue_entry ADD COLUMN rank DOUBLE PRECISION;
+UPDATE movie_queue_entry SET rank = idx;
+ALTER TABLE movie_queue_entry ALTER COLUMN rank SET NOT NULL;
+
+DELETE FROM movie_queue_entry a
+USING movie_queue_entry b
+WHERE a.collection_idx = b.collection_idx AND a.rank > b.rank;
+
+ALTER TABLE movie_queue_entry DROP CONSTRAINT movie_queue_pkey;
+ALTER TABLE movie_queue_entry DROP COLUMN idx;
+ALTER TABLE movie_queue_entry ADD PRIMARY KEY (collection_idx);
+CREATE INDEX IF NOT EXISTS movie_queue_entry_rank_idx ON movie_queue_entry (rank);
+
+CREATE VIEW movie_queue AS
+    SELECT (row_number() OVER (ORDER BY rank, collection_idx) - 1)::INTEGER AS idx,
+           collection_idx,
+           rank,
+           last_modified
+    FROM movie_queue_entry;
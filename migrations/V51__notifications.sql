@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    id SERIAL PRIMARY KEY,
+    email TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    target TEXT NOT NULL,
+    new_episodes BOOLEAN NOT NULL DEFAULT true,
+    transcode_finished BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    UNIQUE (email, channel)
+);
+
+CREATE TABLE IF NOT EXISTS notification_log (
+    preference_id INTEGER NOT NULL REFERENCES notification_preferences (id) ON DELETE CASCADE,
+    event_key TEXT NOT NULL,
+    sent_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (preference_id, event_key)
+);
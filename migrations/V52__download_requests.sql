@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS download_requests (
+    id SERIAL PRIMARY KEY,
+    link TEXT NOT NULL,
+    season INTEGER NOT NULL,
+    episode INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    UNIQUE (link, season, episode)
+);
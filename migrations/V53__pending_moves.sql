@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS pending_moves (
+    id SERIAL PRIMARY KEY,
+    input_path TEXT NOT NULL UNIQUE,
+    output_path TEXT NOT NULL,
+    job_type TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    show TEXT NOT NULL,
+    season INTEGER NOT NULL,
+    episode INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_modified TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,7 @@
+ALTER TABLE show_settings ADD COLUMN video_codec TEXT;
+ALTER TABLE show_settings ADD COLUMN max_height INTEGER;
+ALTER TABLE show_settings ADD COLUMN audio_handling TEXT;
+
+ALTER TABLE transcode_jobs ADD COLUMN video_codec TEXT;
+ALTER TABLE transcode_jobs ADD COLUMN max_height INTEGER;
+ALTER TABLE transcode_jobs ADD COLUMN audio_handling TEXT;
@@ -0,0 +1 @@
+ALTER TABLE movie_collection ADD COLUMN media_kind TEXT;
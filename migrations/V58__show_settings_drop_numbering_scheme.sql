@@ -0,0 +1,7 @@
+UPDATE imdb_ratings r SET numbering = lower(trim(s.numbering_scheme))
+FROM show_settings s
+WHERE s.link = r.link
+  AND s.numbering_scheme IS NOT NULL
+  AND lower(trim(s.numbering_scheme)) IN ('seasonal', 'absolute');
+
+ALTER TABLE show_settings DROP COLUMN IF EXISTS numbering_scheme;
@@ -0,0 +1 @@
+ALTER TABLE movie_collection ADD COLUMN credits_probed_at TIMESTAMP WITH TIME ZONE;
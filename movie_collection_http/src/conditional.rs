@@ -0,0 +1,108 @@
+use hyper::Body;
+use rweb::{
+    filters::{header, BoxedFilter},
+    http::{
+        header::{HeaderValue, ETAG},
+        Response, StatusCode,
+    },
+    Filter, Rejection, Reply,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use crate::errors::ServiceError as Error;
+
+// If-Modified-Since is deliberately ignored: deletes and preference changes don't move
+// any last_modified column, so only a comparison against the body is reliable
+#[derive(Debug, Default)]
+pub struct Preconditions {
+    pub if_none_match: Option<String>,
+}
+
+impl Preconditions {
+    pub fn is_fresh(&self, etag: &str) -> bool {
+        let etag = etag.trim_start_matches("W/");
+        self.if_none_match.as_ref().map_or(false, |if_none_match| {
+            if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        })
+    }
+}
+
+fn preconditions() -> impl Filter<Extract = (Preconditions,), Error = Rejection> + Clone {
+    header::optional::<String>("if-none-match").map(|if_none_match| Preconditions { if_none_match })
+}
+
+fn body_etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!(r#""{:016x}""#, hasher.finish())
+}
+
+async fn conditional_response(
+    preconditions: Preconditions,
+    reply: impl Reply,
+) -> Result<Response<Body>, Rejection> {
+    let response = reply.into_response();
+    if response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| Error::AnyhowError(e.into()))?;
+    let etag = body_etag(&body);
+    let fresh = preconditions.is_fresh(&etag);
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(ETAG, etag);
+    }
+    if fresh {
+        parts.status = StatusCode::NOT_MODIFIED;
+        Ok(Response::from_parts(parts, Body::empty()))
+    } else {
+        Ok(Response::from_parts(parts, body.into()))
+    }
+}
+
+// Wraps a GET sync route with an ETag validator so polling clients get an empty 304 when
+// the body is unchanged
+pub fn conditional<T, F>(route: F) -> BoxedFilter<(Response<Body>,)>
+where
+    T: Reply,
+    F: Filter<Extract = (T,), Error = Rejection> + Clone + Send + Sync + 'static,
+{
+    preconditions()
+        .and(route)
+        .and_then(conditional_response)
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conditional::{body_etag, Preconditions};
+
+    #[test]
+    fn test_preconditions() {
+        let etag = body_etag(b"[]");
+        assert_eq!(etag, body_etag(b"[]"));
+        assert_ne!(etag, body_etag(b"[{}]"));
+
+        let preconditions = Preconditions::default();
+        assert!(!preconditions.is_fresh(&etag));
+
+        let preconditions = Preconditions {
+            if_none_match: Some(format!(r#""abc", W/{}"#, etag)),
+        };
+        assert!(preconditions.is_fresh(&etag));
+        assert!(!preconditions.is_fresh(r#""abcd""#));
+
+        let preconditions = Preconditions {
+            if_none_match: Some("*".into()),
+        };
+        assert!(preconditions.is_fresh(&etag));
+    }
+}
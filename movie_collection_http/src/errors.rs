@@ -2,7 +2,6 @@ use anyhow::Error as AnyhowError;
 use handlebars::RenderError;
 use http::StatusCode;
 use indexmap::IndexMap;
-use log::error;
 use rweb::{
     openapi::{Entity, Response, ResponseEntity, Responses, Schema},
     reject::{InvalidHeader, MissingCookie, Reject},
@@ -10,10 +9,19 @@ use rweb::{
 };
 use serde::Serialize;
 use stack_string::StackString;
-use std::{borrow::Cow, convert::Infallible, fmt::Debug, io::Error as IoError};
+use std::{
+    borrow::Cow, convert::Infallible, error::Error as StdError, fmt::Debug, io::Error as IoError,
+};
 use thiserror::Error;
+use tracing::error;
+
+use movie_collection_lib::{
+    imdb_ratings::ImdbRatingsError, movie_collection::CollectionError, movie_queue::QueueError,
+    plex_events::PlexEventError, plex_sessions::PlexError, trakt_connection::TraktError,
+    transcode_service::TranscodeError,
+};
 
-use crate::logged_user::TRIGGER_DB_UPDATE;
+use crate::{logged_user::TRIGGER_DB_UPDATE, sync_validation::RejectedRow};
 
 #[derive(Error, Debug)]
 #[allow(clippy::used_underscore_binding)]
@@ -22,8 +30,34 @@ pub enum ServiceError {
     InternalServerError,
     #[error("BadRequest: {0}")]
     BadRequest(StackString),
+    #[error("NotFound: {0}")]
+    NotFound(StackString),
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Invalid API token")]
+    InvalidApiToken,
+    #[error("Forbidden")]
+    Forbidden,
+    #[error("Trakt not configured")]
+    TraktNotConfigured,
+    #[error("Too Many Requests")]
+    TooManyRequests,
+    #[error("Unprocessable Entity: {} rows rejected", .0.len())]
+    UnprocessableEntity(Vec<RejectedRow>),
+    #[error(transparent)]
+    CollectionError(#[from] CollectionError),
+    #[error(transparent)]
+    QueueError(#[from] QueueError),
+    #[error(transparent)]
+    PlexError(#[from] PlexError),
+    #[error(transparent)]
+    PlexEventError(#[from] PlexEventError),
+    #[error(transparent)]
+    ImdbRatingsError(#[from] ImdbRatingsError),
+    #[error(transparent)]
+    TraktError(#[from] TraktError),
+    #[error(transparent)]
+    TranscodeError(#[from] TranscodeError),
     #[error("Anyhow error {0}")]
     AnyhowError(#[from] AnyhowError),
     #[error("Template Parse Error {0}")]
@@ -34,15 +68,82 @@ pub enum ServiceError {
 
 impl Reject for ServiceError {}
 
+fn lib_error_status(err: &(dyn StdError + 'static)) -> Option<(StatusCode, String)> {
+    let code = if let Some(e) = err.downcast_ref::<CollectionError>() {
+        match e {
+            CollectionError::NotFound(_) => StatusCode::NOT_FOUND,
+            CollectionError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+            CollectionError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<QueueError>() {
+        match e {
+            QueueError::NotFound(_) | QueueError::NotQueued(_) => StatusCode::NOT_FOUND,
+            QueueError::Collection(e) => return lib_error_status(e),
+            QueueError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<PlexError>() {
+        match e {
+            PlexError::NotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            PlexError::External(_) => StatusCode::BAD_GATEWAY,
+            PlexError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<PlexEventError>() {
+        match e {
+            PlexEventError::InvalidEventType(_) | PlexEventError::InvalidPayload(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            PlexEventError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<ImdbRatingsError>() {
+        match e {
+            ImdbRatingsError::NotFound(_) => StatusCode::NOT_FOUND,
+            ImdbRatingsError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<TraktError>() {
+        match e {
+            TraktError::NotAuthorized => StatusCode::UNAUTHORIZED,
+            TraktError::External(_) => StatusCode::BAD_GATEWAY,
+            TraktError::Internal(_) => return None,
+        }
+    } else if let Some(e) = err.downcast_ref::<TranscodeError>() {
+        match e {
+            TranscodeError::NotFound(_) => StatusCode::NOT_FOUND,
+            TranscodeError::Internal(_) => return None,
+        }
+    } else {
+        return None;
+    };
+    Some((code, err.to_string()))
+}
+
+// Library errors may arrive directly or wrapped in anyhow by an intermediate caller
+fn typed_error_status(err: &ServiceError) -> Option<(StatusCode, String)> {
+    match err {
+        ServiceError::CollectionError(e) => lib_error_status(e),
+        ServiceError::QueueError(e) => lib_error_status(e),
+        ServiceError::PlexError(e) => lib_error_status(e),
+        ServiceError::PlexEventError(e) => lib_error_status(e),
+        ServiceError::ImdbRatingsError(e) => lib_error_status(e),
+        ServiceError::TraktError(e) => lib_error_status(e),
+        ServiceError::TranscodeError(e) => lib_error_status(e),
+        ServiceError::AnyhowError(e) => e.chain().find_map(lib_error_status),
+        _ => None,
+    }
+}
+
 #[derive(Serialize)]
 struct ErrorMessage {
     code: u16,
     message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rejected: Vec<RejectedRow>,
 }
 
 pub async fn error_response(err: Rejection) -> Result<Box<dyn Reply>, Infallible> {
     let code: StatusCode;
     let message: &str;
+    let mut rejected = Vec::new();
+    let typed_message: String;
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
@@ -63,14 +164,46 @@ pub async fn error_response(err: Rejection) -> Result<Box<dyn Reply>, Infallible
                 code = StatusCode::BAD_REQUEST;
                 message = msg.as_str();
             }
+            ServiceError::NotFound(msg) => {
+                code = StatusCode::NOT_FOUND;
+                message = msg.as_str();
+            }
             ServiceError::Unauthorized => {
                 TRIGGER_DB_UPDATE.set();
                 return Ok(Box::new(login_html()));
             }
+            // Scripts authenticating with a Bearer token get a status they can check
+            // instead of the login redirect
+            ServiceError::InvalidApiToken => {
+                code = StatusCode::UNAUTHORIZED;
+                message = "Invalid API token";
+            }
+            ServiceError::Forbidden => {
+                code = StatusCode::FORBIDDEN;
+                message = "Forbidden";
+            }
+            ServiceError::TraktNotConfigured => {
+                return Ok(Box::new(trakt_not_configured_html()));
+            }
+            ServiceError::TooManyRequests => {
+                code = StatusCode::TOO_MANY_REQUESTS;
+                message = "Too Many Requests";
+            }
+            ServiceError::UnprocessableEntity(rows) => {
+                code = StatusCode::UNPROCESSABLE_ENTITY;
+                message = "Rejected rows";
+                rejected = rows.clone();
+            }
             _ => {
-                error!("Other error: {:?}", service_err);
-                code = StatusCode::INTERNAL_SERVER_ERROR;
-                message = "Internal Server Error, Please try again later";
+                if let Some((status, msg)) = typed_error_status(service_err) {
+                    code = status;
+                    typed_message = msg;
+                    message = typed_message.as_str();
+                } else {
+                    error!("Other error: {:?}", service_err);
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    message = "Internal Server Error, Please try again later";
+                }
             }
         }
     } else if err.find::<rweb::reject::MethodNotAllowed>().is_some() {
@@ -85,6 +218,7 @@ pub async fn error_response(err: Rejection) -> Result<Box<dyn Reply>, Infallible
     let reply = rweb::reply::json(&ErrorMessage {
         code: code.as_u16(),
         message: message.to_string(),
+        rejected,
     });
     let reply = rweb::reply::with_status(reply, code);
 
@@ -104,6 +238,16 @@ fn login_html() -> impl Reply {
     )
 }
 
+fn trakt_not_configured_html() -> impl Reply {
+    let reply = rweb::reply::html(
+        r#"
+            <a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>
+            Trakt not configured: set TRAKT_CLIENT_ID and TRAKT_CLIENT_SECRET to enable this page.
+        "#,
+    );
+    rweb::reply::with_status(reply, StatusCode::SERVICE_UNAVAILABLE)
+}
+
 impl Entity for ServiceError {
     fn describe() -> Schema {
         rweb::http::Error::describe()
@@ -118,7 +262,11 @@ impl ResponseEntity for ServiceError {
             (StatusCode::NOT_FOUND, "Not Found"),
             (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
             (StatusCode::BAD_REQUEST, "Bad Request"),
+            (StatusCode::UNAUTHORIZED, "Unauthorized"),
             (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+            (StatusCode::UNPROCESSABLE_ENTITY, "Unprocessable Entity"),
+            (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable"),
+            (StatusCode::BAD_GATEWAY, "Bad Gateway"),
         ];
 
         for (code, msg) in &error_responses {
@@ -140,7 +288,15 @@ mod test {
     use anyhow::Error;
     use rweb::Reply;
 
-    use crate::errors::{error_response, ServiceError};
+    use movie_collection_lib::{
+        movie_collection::CollectionError, movie_queue::QueueError, plex_events::PlexEventType,
+        plex_sessions::PlexError, transcode_service::TranscodeError,
+    };
+
+    use crate::{
+        errors::{error_response, ServiceError},
+        sync_validation::RejectedRow,
+    };
 
     #[tokio::test]
     async fn test_service_error() -> Result<(), Error> {
@@ -148,9 +304,57 @@ mod test {
         let resp = error_response(err).await?.into_response();
         assert_eq!(resp.status().as_u16(), 400);
 
+        let err = ServiceError::NotFound("TEST ERROR".into()).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 404);
+
         let err = ServiceError::InternalServerError.into();
         let resp = error_response(err).await?.into_response();
         assert_eq!(resp.status().as_u16(), 500);
+
+        let err = ServiceError::InvalidApiToken.into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 401);
+
+        let err = ServiceError::TraktNotConfigured.into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 503);
+
+        let err = ServiceError::TooManyRequests.into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 429);
+
+        let err = ServiceError::UnprocessableEntity(vec![RejectedRow {
+            index: 0,
+            reason: "empty show".into(),
+        }])
+        .into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 422);
+
+        let err = ServiceError::from(CollectionError::NotFound("12".into())).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let err = ServiceError::from(PlexError::NotConfigured).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 503);
+
+        let err = ServiceError::from(QueueError::from(CollectionError::InvalidPath("/".into())));
+        let resp = error_response(err.into()).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let err = "media.unknown".parse::<PlexEventType>().unwrap_err();
+        let resp = error_response(ServiceError::from(err).into())
+            .await?
+            .into_response();
+        assert_eq!(resp.status().as_u16(), 400);
+
+        let err: Error = TranscodeError::NotFound("running job test".into()).into();
+        let resp = error_response(ServiceError::from(err).into())
+            .await?
+            .into_response();
+        assert_eq!(resp.status().as_u16(), 404);
         Ok(())
     }
 }
@@ -12,9 +12,18 @@
 #![allow(clippy::manual_map)]
 #![allow(clippy::default_trait_access)]
 
+pub mod conditional;
 pub mod errors;
 pub mod logged_user;
 pub mod movie_queue_app;
+pub mod movie_queue_feed;
 pub mod movie_queue_requests;
 pub mod movie_queue_routes;
+pub mod offline_stream;
+pub mod rate_limit;
+pub mod request_tracing;
+pub mod sync_stream;
+pub mod sync_validation;
+#[cfg(test)]
+pub mod test_harness;
 pub mod uuid_wrapper;
@@ -2,20 +2,34 @@ pub use authorized_users::{
     get_random_key, get_secrets, token::Token, AuthorizedUser, AUTHORIZED_USERS, JWT_SECRET,
     KEY_LENGTH, SECRET_KEY, TRIGGER_DB_UPDATE,
 };
-use log::debug;
-use rweb::Schema;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rweb::{
+    filters::{cookie, header},
+    Filter, Rejection, Schema,
+};
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
 use std::{
+    collections::HashMap,
     convert::{TryFrom, TryInto},
     env::var,
     str::FromStr,
 };
+use tracing::debug;
 
-use movie_collection_lib::{pgpool::PgPool, utils::get_authorized_users};
+use movie_collection_lib::{
+    api_tokens::{hash_token, ApiToken},
+    pgpool::PgPool,
+    utils::get_authorized_users,
+};
 
 use crate::errors::ServiceError as Error;
 
+lazy_static! {
+    static ref API_TOKENS: RwLock<HashMap<StackString, StackString>> = RwLock::new(HashMap::new());
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Schema)]
 pub struct LoggedUser {
     pub email: StackString,
@@ -57,6 +71,51 @@ impl FromStr for LoggedUser {
     }
 }
 
+impl LoggedUser {
+    fn from_api_token(token: &str) -> Result<Self, Error> {
+        let email = API_TOKENS
+            .read()
+            .get(&hash_token(token))
+            .cloned()
+            .ok_or(Error::Unauthorized)?;
+        let user = Self { email };
+        if AUTHORIZED_USERS.is_authorized(&user.clone().into()) {
+            Ok(user)
+        } else {
+            debug!("NOT AUTHORIZED {:?}", user);
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+// Accepts either the session cookie or an `Authorization: Bearer <api token>` header,
+// a rejected Bearer token is reported as `InvalidApiToken` rather than the login page
+pub fn api_user() -> impl Filter<Extract = (LoggedUser,), Error = Rejection> + Clone {
+    header::optional::<String>("authorization")
+        .and(cookie::optional::<String>("jwt"))
+        .and_then(|auth: Option<String>, jwt: Option<String>| async move {
+            resolve_user(auth.as_deref(), jwt.as_deref()).map_err(rweb::reject::custom)
+        })
+}
+
+// A Bearer token takes precedence over the jwt cookie
+pub fn resolve_user(auth: Option<&str>, jwt: Option<&str>) -> Result<LoggedUser, Error> {
+    match (auth, jwt) {
+        (Some(auth), _) if auth.starts_with("Bearer ") => {
+            LoggedUser::from_api_token(auth.trim_start_matches("Bearer ").trim())
+                .map_err(|_| Error::InvalidApiToken)
+        }
+        (_, Some(jwt)) => jwt.parse(),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+pub async fn fill_api_tokens(pool: &PgPool) -> Result<(), Error> {
+    let tokens = ApiToken::get_token_map(pool).await?;
+    *API_TOKENS.write() = tokens;
+    Ok(())
+}
+
 pub async fn fill_from_db(pool: &PgPool) -> Result<(), Error> {
     debug!("{:?}", *TRIGGER_DB_UPDATE);
     let users = if TRIGGER_DB_UPDATE.check() {
@@ -68,6 +127,7 @@ pub async fn fill_from_db(pool: &PgPool) -> Result<(), Error> {
         AUTHORIZED_USERS.merge_users(&["user@test".into()])?;
     }
     AUTHORIZED_USERS.merge_users(&users)?;
+    fill_api_tokens(pool).await?;
 
     debug!("{:?}", *AUTHORIZED_USERS);
     Ok(())
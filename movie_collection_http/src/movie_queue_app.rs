@@ -3,36 +3,90 @@
 use anyhow::Error;
 use handlebars::Handlebars;
 use rweb::{
-    filters::BoxedFilter,
-    http::header::CONTENT_TYPE,
+    filters::{trace::trace, BoxedFilter},
+    http::header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE},
     openapi::{self, Info},
     Filter, Reply,
 };
+use stack_string::StackString;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
+use stdout_channel::StdoutChannel;
 use tokio::{
     fs::{create_dir, remove_dir_all},
     time::interval,
 };
+use tracing::{debug, error};
 
+use movie_collection_lib::post_processors::{CollectionPostProcessor, PostProcessors};
 use movie_collection_lib::{
-    config::Config, pgpool::PgPool, trakt_connection::TraktConnection, utils::get_templates,
+    alert_rules::evaluate_alerts,
+    clock::{SharedClock, SharedIdGen},
+    collection_watcher::CollectionWatcher,
+    config::Config,
+    hls_stream::{cleanup_hls, hls_root},
+    imdb_refresh::ImdbRefreshStatus,
+    metrics_exporter::MetricsExporter,
+    movie_collection::MovieCollection,
+    movie_queue::MovieQueueDB,
+    notifications::Notifier,
+    offline_files::OfflineFile,
+    pgpool::PgPool,
+    plex_events::PlexEventDailyCount,
+    scan_history::ScanHistory,
+    show_availability::AvailabilityConnection,
+    trakt_connection::TraktConnection,
+    trakt_sync::TraktSyncReport,
+    utils::get_templates,
+    watch_folder::WatchFolder,
 };
 
 use super::{
-    errors::error_response,
+    conditional::conditional,
+    errors::{error_response, ServiceError},
     logged_user::{fill_from_db, get_secrets, TRIGGER_DB_UPDATE},
     movie_queue_routes::{
-        find_new_episodes, frontpage, imdb_episodes_route, imdb_episodes_update,
-        imdb_ratings_route, imdb_ratings_set_source, imdb_ratings_update, imdb_show,
-        last_modified_route, movie_collection_route, movie_collection_update, movie_queue,
-        movie_queue_delete, movie_queue_play, movie_queue_remcom_directory_file,
-        movie_queue_remcom_file, movie_queue_route, movie_queue_show, movie_queue_transcode,
-        movie_queue_transcode_cleanup, movie_queue_transcode_directory, movie_queue_transcode_file,
-        movie_queue_transcode_status, movie_queue_update, plex_events, plex_events_update,
-        plex_webhook, refresh_auth, trakt_auth_url, trakt_cal, trakt_callback,
-        trakt_watched_action, trakt_watched_list, trakt_watched_seasons, trakt_watchlist,
-        trakt_watchlist_action, tvshows, user,
+        airing_today, airing_today_json, alert_rules, alert_rules_delete, alert_rules_update,
+        api_tokens, api_tokens_create, api_tokens_delete, artwork, backup_export,
+        backup_export_ndjson, backup_import, collection_duplicates, collection_feed,
+        download_request, download_requests, find_new_episodes, find_new_episodes_ical, frontpage,
+        health, imdb_episodes_route, imdb_episodes_sync, imdb_episodes_update, imdb_ratings_route,
+        imdb_ratings_set_numbering, imdb_ratings_set_source, imdb_ratings_sync,
+        imdb_ratings_update, imdb_refresh_status, imdb_show, intro_markers, intro_markers_delete,
+        intro_markers_update, jellyfin_events, jellyfin_webhook, kodi_nfo, last_modified_route,
+        movie_collection_delete, movie_collection_rename, movie_collection_route,
+        movie_collection_update, movie_queue, movie_queue_delete, movie_queue_import,
+        movie_queue_pending_move, movie_queue_play, movie_queue_play_hls,
+        movie_queue_play_hls_segment, movie_queue_remcom_directory_file, movie_queue_remcom_file,
+        movie_queue_reorder, movie_queue_route, movie_queue_show, movie_queue_subtitle_download,
+        movie_queue_transcode, movie_queue_transcode_batch, movie_queue_transcode_cancel,
+        movie_queue_transcode_cleanup, movie_queue_transcode_cleanup_confirm,
+        movie_queue_transcode_directory, movie_queue_transcode_file,
+        movie_queue_transcode_priority, movie_queue_transcode_season, movie_queue_transcode_stats,
+        movie_queue_transcode_status, movie_queue_update, music_collection_browse,
+        music_collection_scan, music_play, offline_list, offline_save, playback_position,
+        playback_position_update, plex_continue_watching, plex_event_stats, plex_events,
+        plex_events_sync, plex_events_update, plex_now_playing, plex_now_playing_json,
+        plex_servers, plex_servers_delete, plex_servers_update, plex_webhook,
+        plex_webhook_failures, plex_webhook_replay, queue_share_create, queue_share_revoke,
+        queue_share_snapshot, quick_add, quick_add_search, recent_logs, reclaim, reclaim_keep,
+        reclaim_keep_delete, reclaim_trash, reclaim_trash_list, reclaim_trash_purge,
+        reclaim_trash_restore, refresh_auth, retry_plex_webhook_failures, saved_filter_queue,
+        saved_filters, saved_filters_delete, saved_filters_update, scan_exclusions,
+        scan_exclusions_report, scan_exclusions_update, scan_status, scan_trigger, search,
+        search_html, show_availability, show_relink, show_settings, show_settings_update, tonight,
+        tonight_html, trakt_auth_url, trakt_cal, trakt_callback, trakt_sync_status,
+        trakt_watched_action, trakt_watched_list, trakt_watched_season_action,
+        trakt_watched_seasons, trakt_watchlist, trakt_watchlist_action, trakt_webhook,
+        transcode_status_ws, tvshows, up_next, user, user_hooks, user_hooks_create,
+        user_hooks_delete, user_notifications, user_notifications_delete,
+        user_notifications_update, user_preferences, user_preferences_update, user_state_export,
+        user_state_import, user_watched, user_watched_delete, user_watched_set, viewing_stats,
+        viewing_stats_html,
     },
+    offline_stream::offline_download,
+    rate_limit::{rate_limit, RateLimiter},
+    request_tracing::request_span,
+    sync_stream::sync_ndjson,
 };
 
 #[derive(Clone)]
@@ -41,9 +95,106 @@ pub struct AppState {
     pub db: PgPool,
     pub trakt: TraktConnection,
     pub hbr: Arc<Handlebars<'static>>,
+    pub metrics: MetricsExporter,
+    pub stdout: StdoutChannel<StackString>,
+    pub mc: MovieCollection,
+    pub mq: MovieQueueDB,
+    pub clock: SharedClock,
+    pub id_gen: SharedIdGen,
+}
+
+pub struct AppStateBuilder {
+    config: Config,
+    db: PgPool,
+    trakt: TraktConnection,
+    post_processors: PostProcessors,
+    clock: SharedClock,
+    id_gen: SharedIdGen,
+}
+
+impl AppStateBuilder {
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_id_gen(mut self, id_gen: SharedIdGen) -> Self {
+        self.id_gen = id_gen;
+        self
+    }
+
+    pub fn with_post_processor(mut self, processor: Arc<dyn CollectionPostProcessor>) -> Self {
+        self.post_processors.register(processor);
+        self
+    }
+
+    pub fn with_post_processors(mut self, post_processors: PostProcessors) -> Self {
+        self.post_processors = post_processors;
+        self
+    }
+
+    // Library output from every request lands in the server log through one shared channel
+    pub fn build(self) -> Result<AppState, Error> {
+        let Self {
+            config,
+            db,
+            trakt,
+            post_processors,
+            clock,
+            id_gen,
+        } = self;
+        let stdout = StdoutChannel::new();
+        let mc = MovieCollection::new(&config, &db, &stdout)
+            .with_post_processors(post_processors)
+            .with_clock(clock.clone());
+        let mq = MovieQueueDB::new(&config, &db, &stdout).with_clock(clock.clone());
+        Ok(AppState {
+            metrics: MetricsExporter::new(&config),
+            hbr: Arc::new(get_templates()?),
+            config,
+            db,
+            trakt,
+            stdout,
+            mc,
+            mq,
+            clock,
+            id_gen,
+        })
+    }
+}
+
+impl AppState {
+    pub fn builder(config: Config, db: PgPool, trakt: TraktConnection) -> AppStateBuilder {
+        AppStateBuilder {
+            config,
+            db,
+            trakt,
+            post_processors: PostProcessors::default(),
+            clock: SharedClock::default(),
+            id_gen: SharedIdGen::default(),
+        }
+    }
+
+    pub fn new(config: Config, db: PgPool, trakt: TraktConnection) -> Result<Self, Error> {
+        Self::builder(config, db, trakt).build()
+    }
+
+    pub fn require_trakt(&self) -> Result<&TraktConnection, ServiceError> {
+        if self.trakt.is_configured() {
+            Ok(&self.trakt)
+        } else {
+            Err(ServiceError::TraktNotConfigured)
+        }
+    }
 }
 
 pub async fn start_app() -> Result<(), Error> {
+    start_app_with_post_processors(PostProcessors::default()).await
+}
+
+// Entry point for binaries that register their own collection post-processors, the
+// registry is shared by the web app, the scan scheduler and the collection watcher
+pub async fn start_app_with_post_processors(post_processors: PostProcessors) -> Result<(), Error> {
     async fn _update_db(pool: PgPool) {
         let mut i = interval(Duration::from_secs(60));
         loop {
@@ -51,6 +202,148 @@ pub async fn start_app() -> Result<(), Error> {
             i.tick().await;
         }
     }
+    async fn _archive_plex_events(config: Config, pool: PgPool, clock: SharedClock) {
+        let mut i = interval(Duration::from_secs(86400));
+        loop {
+            i.tick().await;
+            match PlexEventDailyCount::archive_expired(&config, &pool, &*clock).await {
+                Ok(archived) => debug!("archived plex events {}", archived),
+                Err(e) => error!("failed to archive plex events {}", e),
+            }
+        }
+    }
+    async fn _refresh_availability(config: Config, pool: PgPool) {
+        let conn = match AvailabilityConnection::new(&config) {
+            Some(conn) => conn,
+            None => return,
+        };
+        let mut i = interval(Duration::from_secs(86400));
+        loop {
+            i.tick().await;
+            match conn.refresh_watchlist(&pool).await {
+                Ok(updated) => debug!("refreshed availability {}", updated),
+                Err(e) => error!("failed to refresh availability {}", e),
+            }
+        }
+    }
+    async fn _rescan_collection(mc: MovieCollection) {
+        if mc.config.scan_interval_minutes == 0 {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(mc.config.scan_interval_minutes * 60));
+        loop {
+            i.tick().await;
+            match ScanHistory::run_scan(&mc, "scheduled").await {
+                Ok(scan) => debug!("collection scan added {:?}", scan.added),
+                Err(e) => error!("collection scan failed {}", e),
+            }
+        }
+    }
+    // Queue filters on media_kind skip unclassified rows, so classify them right away
+    // instead of waiting for the next full scan
+    async fn _classify_media_kind(mc: MovieCollection) {
+        match mc.update_media_kind().await {
+            Ok(classified) => debug!("media kind updated {}", classified),
+            Err(e) => error!("media kind update failed {}", e),
+        }
+    }
+    async fn _watch_collection(mc: MovieCollection) {
+        if !mc.config.watch_collection {
+            return;
+        }
+        let watcher = CollectionWatcher::new(&mc.config, &mc.pool, &StdoutChannel::default())
+            .with_post_processors(mc.post_processors.clone());
+        if let Err(e) = watcher.run().await {
+            error!("collection watcher failed {}", e);
+        }
+    }
+    async fn _watch_folder(config: Config, pool: PgPool) {
+        if config.watch_folder.is_none() {
+            return;
+        }
+        let watcher = WatchFolder::new(&config, &pool, &StdoutChannel::default());
+        if let Err(e) = watcher.run().await {
+            error!("watch folder failed {}", e);
+        }
+    }
+    async fn _refresh_imdb_episodes(config: Config, pool: PgPool) {
+        if config.imdb_refresh_hours == 0 {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(config.imdb_refresh_hours * 3600));
+        let stdout = StdoutChannel::default();
+        let delay = Duration::from_secs(1);
+        loop {
+            i.tick().await;
+            match ImdbRefreshStatus::run_refresh(&config, &pool, &stdout, delay).await {
+                Ok(report) => debug!("imdb refresh {}", report),
+                Err(e) => error!("imdb refresh failed {}", e),
+            }
+        }
+    }
+    async fn _sync_trakt_watchlist(config: Config, pool: PgPool, trakt: TraktConnection) {
+        if config.trakt_sync_minutes == 0 || !trakt.is_configured() {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(config.trakt_sync_minutes * 60));
+        loop {
+            i.tick().await;
+            match TraktSyncReport::run_sync(&trakt, &pool).await {
+                Ok(report) => debug!("trakt watchlist sync {}", report),
+                Err(e) => error!("trakt watchlist sync failed {}", e),
+            }
+        }
+    }
+    async fn _notify_new_episodes(mc: MovieCollection) {
+        let notifier = Notifier::new(&mc.config);
+        if mc.config.notification_check_minutes == 0 || notifier.is_empty() {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(
+            mc.config.notification_check_minutes * 60,
+        ));
+        loop {
+            i.tick().await;
+            match notifier.notify_new_episodes(&mc).await {
+                Ok(sent) => debug!("new episode notifications {}", sent),
+                Err(e) => error!("new episode notifications failed {}", e),
+            }
+        }
+    }
+    async fn _evaluate_alerts(config: Config, pool: PgPool) {
+        let notifier = Notifier::new(&config);
+        if config.notification_check_minutes == 0 || notifier.is_empty() {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(config.notification_check_minutes * 60));
+        loop {
+            i.tick().await;
+            match evaluate_alerts(&pool, &config, &notifier).await {
+                Ok(fired) => debug!("alert rules fired {}", fired),
+                Err(e) => error!("alert rule evaluation failed {}", e),
+            }
+        }
+    }
+    async fn _cleanup_offline_files(pool: PgPool, clock: SharedClock) {
+        let mut i = interval(Duration::from_secs(3600));
+        loop {
+            i.tick().await;
+            match OfflineFile::cleanup_expired(&pool, &*clock).await {
+                Ok(removed) => debug!("removed expired offline files {}", removed),
+                Err(e) => error!("failed to remove expired offline files {}", e),
+            }
+        }
+    }
+    async fn _cleanup_hls(config: Config) {
+        let mut i = interval(Duration::from_secs(3600));
+        loop {
+            i.tick().await;
+            match cleanup_hls(&config).await {
+                Ok(removed) => debug!("removed expired hls sessions {}", removed),
+                Err(e) => error!("failed to remove expired hls sessions {}", e),
+            }
+        }
+    }
     TRIGGER_DB_UPDATE.set();
     let config = Config::with_config()?;
     get_secrets(&config.secret_path, &config.jwt_secret_path).await?;
@@ -62,18 +355,54 @@ pub async fn start_app() -> Result<(), Error> {
             create_dir(&partial_path).await?;
         }
     }
+    // Segments from a previous run may be incomplete, they are regenerated on demand
+    if let Ok(hls_path) = hls_root(&config) {
+        if hls_path.exists() {
+            remove_dir_all(&hls_path).await?;
+        }
+    }
 
     let pool = PgPool::new(&config.pgurl);
     let trakt = TraktConnection::new(config.clone());
+    let app = AppState::builder(config.clone(), pool.clone(), trakt.clone())
+        .with_post_processors(post_processors)
+        .build()?;
 
     tokio::task::spawn(_update_db(pool.clone()));
+    tokio::task::spawn(_archive_plex_events(
+        config.clone(),
+        pool.clone(),
+        app.clock.clone(),
+    ));
+    tokio::task::spawn(_refresh_availability(config.clone(), pool.clone()));
+    tokio::task::spawn(_classify_media_kind(app.mc.clone()));
+    tokio::task::spawn(_rescan_collection(app.mc.clone()));
+    tokio::task::spawn(_watch_collection(app.mc.clone()));
+    tokio::task::spawn(_watch_folder(config.clone(), pool.clone()));
+    tokio::task::spawn(_refresh_imdb_episodes(config.clone(), pool.clone()));
+    tokio::task::spawn(_sync_trakt_watchlist(
+        config.clone(),
+        pool.clone(),
+        trakt.clone(),
+    ));
+    tokio::task::spawn(_notify_new_episodes(app.mc.clone()));
+    tokio::task::spawn(_evaluate_alerts(config.clone(), pool.clone()));
+    tokio::task::spawn(_cleanup_offline_files(pool.clone(), app.clock.clone()));
+    tokio::task::spawn(_cleanup_hls(config.clone()));
 
-    run_app(config, pool, trakt).await
+    run_app(app).await
 }
 
-fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
-    let frontpage_path = frontpage().boxed();
+pub(crate) fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
+    let frontpage_path = frontpage(app.clone()).boxed();
     let find_new_episodes_path = find_new_episodes(app.clone()).boxed();
+    let find_new_episodes_ical_path = find_new_episodes_ical(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "text/calendar"))
+        .boxed();
+    let airing_today_path = airing_today(app.clone())
+        .or(airing_today_json(app.clone()))
+        .map(|reply| rweb::reply::with_header(reply, CACHE_CONTROL, "public, max-age=900"))
+        .boxed();
     let tvshows_path = tvshows(app.clone()).boxed();
     let movie_queue_delete_path = movie_queue_delete(app.clone()).boxed();
     let movie_queue_transcode_status_path = movie_queue_transcode_status(app.clone()).boxed();
@@ -84,6 +413,15 @@ fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
     let movie_queue_transcode_path = movie_queue_transcode(app.clone()).boxed();
     let movie_queue_transcode_directory_path = movie_queue_transcode_directory(app.clone()).boxed();
     let movie_queue_transcode_cleanup_path = movie_queue_transcode_cleanup(app.clone()).boxed();
+    let movie_queue_transcode_cleanup_confirm_path =
+        movie_queue_transcode_cleanup_confirm().boxed();
+    let movie_queue_transcode_batch_path = movie_queue_transcode_batch(app.clone()).boxed();
+    let movie_queue_transcode_season_path = movie_queue_transcode_season(app.clone()).boxed();
+    let movie_queue_transcode_stats_path = movie_queue_transcode_stats(app.clone()).boxed();
+    let movie_queue_transcode_priority_path = movie_queue_transcode_priority(app.clone()).boxed();
+    let movie_queue_transcode_cancel_path = movie_queue_transcode_cancel(app.clone()).boxed();
+    let movie_queue_pending_move_path = movie_queue_pending_move(app.clone()).boxed();
+    let movie_queue_subtitle_download_path = movie_queue_subtitle_download(app.clone()).boxed();
     let transcode_path = movie_queue_transcode_status_path
         .or(movie_queue_transcode_file_path)
         .or(movie_queue_remcom_file_path)
@@ -91,48 +429,236 @@ fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
         .or(movie_queue_transcode_path)
         .or(movie_queue_transcode_directory_path)
         .or(movie_queue_transcode_cleanup_path)
+        .or(movie_queue_transcode_cleanup_confirm_path)
+        .or(movie_queue_transcode_batch_path)
+        .or(movie_queue_transcode_season_path)
+        .or(movie_queue_transcode_stats_path)
+        .or(movie_queue_transcode_priority_path)
+        .or(movie_queue_transcode_cancel_path)
+        .or(movie_queue_pending_move_path)
+        .or(movie_queue_subtitle_download_path)
         .boxed();
     let movie_queue_play_path = movie_queue_play(app.clone()).boxed();
+    let movie_queue_play_hls_path = movie_queue_play_hls(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "application/vnd.apple.mpegurl"))
+        .or(movie_queue_play_hls_segment(app.clone())
+            .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "video/mp2t")))
+        .boxed();
+    // Webhooks and imdb updates are driven by other services and scripts, so a
+    // misbehaving client gets throttled instead of hammering the database
+    let webhook_limiter = Arc::new(RateLimiter::new(
+        app.config.rate_limit_burst,
+        app.config.rate_limit_per_minute,
+    ));
+    let imdb_limiter = Arc::new(RateLimiter::new(
+        app.config.rate_limit_burst,
+        app.config.rate_limit_per_minute,
+    ));
     let imdb_episodes_get = imdb_episodes_route(app.clone());
-    let imdb_episodes_post = imdb_episodes_update(app.clone());
-    let imdb_episodes_path = imdb_episodes_get.or(imdb_episodes_post).boxed();
+    let imdb_episodes_post =
+        rate_limit(imdb_limiter.clone()).and(imdb_episodes_update(app.clone()));
+    let imdb_episodes_path = imdb_episodes_get
+        .or(imdb_episodes_post)
+        .or(imdb_episodes_sync(app.clone()))
+        .boxed();
     let imdb_ratings_set_source_path = imdb_ratings_set_source(app.clone()).boxed();
+    let imdb_ratings_set_numbering_path = imdb_ratings_set_numbering(app.clone()).boxed();
     let imdb_ratings_get = imdb_ratings_route(app.clone());
-    let imdb_ratings_post = imdb_ratings_update(app.clone());
-    let imdb_ratings_path = imdb_ratings_get.or(imdb_ratings_post).boxed();
-    let movie_queue_get = movie_queue_route(app.clone());
+    let imdb_ratings_post = rate_limit(imdb_limiter).and(imdb_ratings_update(app.clone()));
+    let imdb_ratings_path = imdb_ratings_get
+        .or(imdb_ratings_post)
+        .or(imdb_ratings_sync(app.clone()))
+        .boxed();
+    let movie_queue_get = conditional(movie_queue_route(app.clone()));
     let movie_queue_post = movie_queue_update(app.clone());
     let movie_queue_path = movie_queue_get.or(movie_queue_post).boxed();
-    let movie_collection_get = movie_collection_route(app.clone());
+    let movie_collection_get = conditional(movie_collection_route(app.clone()));
     let movie_collection_post = movie_collection_update(app.clone());
-    let movie_collection_path = movie_collection_get.or(movie_collection_post).boxed();
+    let movie_collection_delete_path = movie_collection_delete(app.clone());
+    let movie_collection_path = movie_collection_get
+        .or(movie_collection_post)
+        .or(movie_collection_delete_path)
+        .boxed();
+    let imdb_refresh_status_path = imdb_refresh_status(app.clone()).boxed();
     let imdb_show_path = imdb_show(app.clone()).boxed();
     let last_modified_path = last_modified_route(app.clone()).boxed();
-    let user_path = user().boxed();
-    let full_queue_path = movie_queue(app.clone()).boxed();
+    let quick_add_path = quick_add_search(app.clone())
+        .or(quick_add(app.clone()))
+        .boxed();
+    let user_path = user()
+        .or(recent_logs(app.clone()))
+        .or(kodi_nfo(app.clone()))
+        .or(user_preferences(app.clone()))
+        .or(user_preferences_update(app.clone()))
+        .or(user_state_export(app.clone()))
+        .or(user_state_import(app.clone()))
+        .or(user_hooks(app.clone()))
+        .or(user_hooks_create(app.clone()))
+        .or(user_hooks_delete(app.clone()))
+        .or(user_notifications(app.clone()))
+        .or(user_notifications_update(app.clone()))
+        .or(user_notifications_delete(app.clone()))
+        .or(alert_rules(app.clone()))
+        .or(alert_rules_update(app.clone()))
+        .or(alert_rules_delete(app.clone()))
+        .or(api_tokens(app.clone()))
+        .or(api_tokens_create(app.clone()))
+        .or(api_tokens_delete(app.clone()))
+        .or(user_watched(app.clone()))
+        .or(user_watched_set(app.clone()))
+        .or(user_watched_delete(app.clone()))
+        .or(playback_position(app.clone()))
+        .or(playback_position_update(app.clone()))
+        .or(queue_share_create(app.clone()))
+        .or(queue_share_revoke(app.clone()))
+        .boxed();
+    let full_queue_path = movie_queue(app.clone())
+        .or(movie_queue_reorder(app.clone()))
+        .or(movie_queue_import(app.clone()))
+        .or(saved_filters(app.clone()))
+        .or(saved_filters_update(app.clone()))
+        .or(saved_filters_delete(app.clone()))
+        .or(saved_filter_queue(app.clone()))
+        .boxed();
+    let queue_share_path = queue_share_snapshot(app.clone()).boxed();
+    let collection_feed_path = collection_feed(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "application/atom+xml"))
+        .boxed();
     let movie_queue_show_path = movie_queue_show(app.clone()).boxed();
-    let plex_webhook_path = plex_webhook(app.clone()).boxed();
-    let plex_events_path = plex_events(app.clone()).boxed();
+    let duplicates_path = collection_duplicates(app.clone()).boxed();
+    let plex_webhook_path = rate_limit(webhook_limiter.clone())
+        .and(plex_webhook(app.clone()))
+        .or(plex_webhook_failures(app.clone()))
+        .or(plex_webhook_replay(app.clone()))
+        .boxed();
+    let plex_now_playing_path = plex_now_playing(app.clone())
+        .or(plex_now_playing_json(app.clone()))
+        .boxed();
+    let plex_events_get = conditional(plex_events(app.clone()));
+    let plex_events_sync_get = conditional(plex_events_sync(app.clone()));
+    let plex_events_path = plex_events_get.or(plex_events_sync_get).boxed();
     let plex_events_update_path = plex_events_update(app.clone()).boxed();
+    let plex_event_stats_path = plex_event_stats(app.clone()).boxed();
+    let plex_continue_path = plex_continue_watching(app.clone()).boxed();
+    let plex_servers_path = plex_servers(app.clone())
+        .or(plex_servers_update(app.clone()))
+        .or(plex_servers_delete(app.clone()))
+        .boxed();
+    let search_path = search(app.clone()).or(search_html(app.clone())).boxed();
+    let up_next_path = up_next(app.clone()).boxed();
+    let download_path = download_request(app.clone())
+        .or(download_requests(app.clone()))
+        .boxed();
+    let tonight_path = tonight(app.clone()).or(tonight_html(app.clone())).boxed();
+    let viewing_stats_path = viewing_stats(app.clone())
+        .or(viewing_stats_html(app.clone()))
+        .boxed();
+    let jellyfin_path = rate_limit(webhook_limiter.clone())
+        .and(jellyfin_webhook(app.clone()))
+        .or(jellyfin_events(app.clone()))
+        .boxed();
+    let intro_markers_path = intro_markers(app.clone())
+        .or(intro_markers_update(app.clone()))
+        .or(intro_markers_delete(app.clone()))
+        .boxed();
+    let scan_exclusions_path = scan_exclusions(app.clone())
+        .or(scan_exclusions_update(app.clone()))
+        .or(scan_exclusions_report(app.clone()))
+        .boxed();
+    let scan_path = scan_status(app.clone())
+        .or(scan_trigger(app.clone()))
+        .boxed();
+    let offline_path = offline_list(app.clone())
+        .or(offline_save(app.clone()))
+        .boxed();
+    let reclaim_path = reclaim(app.clone())
+        .or(reclaim_keep(app.clone()))
+        .or(reclaim_keep_delete(app.clone()))
+        .or(reclaim_trash(app.clone()))
+        .or(reclaim_trash_list(app.clone()))
+        .or(reclaim_trash_restore(app.clone()))
+        .or(reclaim_trash_purge(app.clone()))
+        .or(movie_collection_rename(app.clone()))
+        .boxed();
+    let music_collection_path = music_collection_scan(app.clone())
+        .or(music_collection_browse(app.clone()))
+        .or(music_play(app.clone()))
+        .boxed();
+    let show_settings_path = show_settings(app.clone())
+        .or(show_settings_update(app.clone()))
+        .or(show_relink(app.clone()))
+        .or(show_availability(app.clone()))
+        .boxed();
+    let backup_export_path = backup_export(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "application/json"))
+        .boxed();
+    let backup_export_ndjson_path = backup_export_ndjson(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "application/gzip"))
+        .map(|reply| {
+            rweb::reply::with_header(
+                reply,
+                CONTENT_DISPOSITION,
+                r#"attachment; filename="movie_collection_backup.ndjson.gz""#,
+            )
+        })
+        .boxed();
+    let backup_path = backup_export_path
+        .or(backup_export_ndjson_path)
+        .or(backup_import(app.clone()))
+        .boxed();
+    let artwork_path = artwork(app.clone())
+        .map(|reply| rweb::reply::with_header(reply, CONTENT_TYPE, "image/jpeg"))
+        .map(|reply| rweb::reply::with_header(reply, CACHE_CONTROL, "private, max-age=86400"))
+        .boxed();
+    let health_path = health(app.clone()).boxed();
     let list_path = frontpage_path
         .or(find_new_episodes_path)
+        .or(find_new_episodes_ical_path)
+        .or(airing_today_path)
         .or(tvshows_path)
         .or(movie_queue_delete_path)
         .or(transcode_path)
+        .or(movie_queue_play_hls_path)
         .or(movie_queue_play_path)
         .or(imdb_episodes_path)
         .or(imdb_ratings_set_source_path)
+        .or(imdb_ratings_set_numbering_path)
         .or(imdb_ratings_path)
         .or(movie_queue_path)
         .or(movie_collection_path)
+        .or(imdb_refresh_status_path)
         .or(imdb_show_path)
         .or(last_modified_path)
+        .or(quick_add_path)
         .or(user_path)
         .or(full_queue_path)
+        .or(queue_share_path)
+        .or(collection_feed_path)
         .or(movie_queue_show_path)
+        .or(duplicates_path)
         .or(plex_webhook_path)
+        .or(plex_now_playing_path)
         .or(plex_events_path)
-        .or(plex_events_update_path);
+        .or(plex_events_update_path)
+        .or(plex_event_stats_path)
+        .or(plex_continue_path)
+        .or(plex_servers_path)
+        .or(tonight_path)
+        .or(download_path)
+        .or(up_next_path)
+        .or(viewing_stats_path)
+        .or(search_path)
+        .or(jellyfin_path)
+        .or(intro_markers_path)
+        .or(scan_exclusions_path)
+        .or(scan_path)
+        .or(offline_path)
+        .or(reclaim_path)
+        .or(music_collection_path)
+        .or(show_settings_path)
+        .or(backup_path)
+        .or(artwork_path)
+        .or(health_path);
     let auth_url_path = trakt_auth_url(app.clone()).boxed();
     let trakt_callback_path = trakt_callback(app.clone()).boxed();
     let refresh_auth_path = refresh_auth(app.clone()).boxed();
@@ -142,6 +668,11 @@ fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
     let trakt_watched_seasons_path = trakt_watched_seasons(app.clone()).boxed();
     let trakt_watched_list_path = trakt_watched_list(app.clone()).boxed();
     let trakt_watched_action_path = trakt_watched_action(app.clone()).boxed();
+    let trakt_watched_season_action_path = trakt_watched_season_action(app.clone()).boxed();
+    let trakt_webhook_path = rate_limit(webhook_limiter)
+        .and(trakt_webhook(app.clone()))
+        .boxed();
+    let trakt_sync_status_path = trakt_sync_status(app.clone()).boxed();
     let trakt_path = auth_url_path
         .or(trakt_callback_path)
         .or(refresh_auth_path)
@@ -151,19 +682,31 @@ fn get_full_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
         .or(trakt_watched_seasons_path)
         .or(trakt_watched_list_path)
         .or(trakt_watched_action_path)
+        .or(trakt_watched_season_action_path)
+        .or(trakt_webhook_path)
+        .or(trakt_sync_status_path)
         .boxed();
 
     list_path.or(trakt_path).boxed()
 }
 
-async fn run_app(config: Config, pool: PgPool, trakt: TraktConnection) -> Result<(), Error> {
-    let port = config.port;
-    let app = AppState {
-        config,
-        db: pool,
-        trakt,
-        hbr: Arc::new(get_templates()?),
-    };
+async fn run_app(app: AppState) -> Result<(), Error> {
+    async fn _retry_webhook_failures(app: AppState) {
+        if app.config.webhook_retry_minutes == 0 {
+            return;
+        }
+        let mut i = interval(Duration::from_secs(app.config.webhook_retry_minutes * 60));
+        loop {
+            i.tick().await;
+            match retry_plex_webhook_failures(&app).await {
+                Ok(replayed) => debug!("replayed plex webhooks {}", replayed),
+                Err(e) => error!("failed to retry plex webhooks {}", e),
+            }
+        }
+    }
+    let port = app.config.port;
+
+    tokio::task::spawn(_retry_webhook_failures(app.clone()));
 
     let (spec, full_path) = openapi::spec()
         .info(Info {
@@ -189,10 +732,18 @@ async fn run_app(config: Config, pool: PgPool, trakt: TraktConnection) -> Result
             rweb::reply::with_header(reply, CONTENT_TYPE, "text/yaml")
         });
 
-    let routes = full_path
+    let transcode_ws_path = transcode_status_ws(app.clone());
+    let sync_ndjson_path = sync_ndjson(app.clone());
+    let offline_download_path = offline_download(app.clone());
+
+    let routes = sync_ndjson_path
+        .or(offline_download_path)
+        .or(full_path)
+        .or(transcode_ws_path)
         .or(spec_json_path)
         .or(spec_yaml_path)
-        .recover(error_response);
+        .recover(error_response)
+        .with(trace(request_span));
     let addr: SocketAddr = format!("127.0.0.1:{}", port).parse()?;
     rweb::serve(routes).bind(addr).await;
     Ok(())
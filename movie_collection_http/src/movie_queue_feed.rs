@@ -0,0 +1,315 @@
+use chrono::{Duration, NaiveDate, SecondsFormat, Utc};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use movie_collection_lib::{
+    collection_feed::FeedEntry, movie_collection::NewEpisodesResult,
+    naivedate_wrapper::NaiveDateWrapper, trakt_utils::TraktCalEntry, utils::escape_html,
+};
+
+fn escape_xml(s: &str) -> StackString {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+fn feed_entry(domain: &str, entry: &FeedEntry) -> StackString {
+    let link = match entry.collection_idx {
+        Some(idx) => format!(r#"<link href="https://{}/list/play/{}"/>"#, domain, idx),
+        None => String::new(),
+    };
+    format!(
+        "<entry><id>tag:{},2021:{}</id><title>{}</title>{}<updated>{}</updated><summary>{}</summary></entry>",
+        domain,
+        entry.id,
+        escape_xml(&entry.title),
+        link,
+        entry.updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+        escape_xml(&entry.summary),
+    )
+    .into()
+}
+
+pub fn atom_feed(domain: &str, entries: &[FeedEntry]) -> StackString {
+    let updated = entries
+        .iter()
+        .map(|e| *e.updated)
+        .max()
+        .unwrap_or_else(Utc::now);
+    let entries: Vec<_> = entries.iter().map(|e| feed_entry(domain, e)).collect();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><feed xmlns="http://www.w3.org/2005/Atom"><id>tag:{domain},2021:feed</id><title>Movie Collection</title><link href="https://{domain}/list/index.html"/><updated>{}</updated>{}</feed>"#,
+        updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+        entries.join(""),
+        domain = domain,
+    )
+    .into()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: StackString,
+    pub summary: StackString,
+    pub url: Option<StackString>,
+    pub airdate: NaiveDate,
+}
+
+impl From<&NewEpisodesResult> for CalendarEvent {
+    fn from(item: &NewEpisodesResult) -> Self {
+        let summary = if item.eptitle.is_empty() {
+            format!("{} s{:02} ep{:02}", item.title, item.season, item.episode)
+        } else {
+            format!(
+                "{} s{:02} ep{:02} {}",
+                item.title, item.season, item.episode, item.eptitle
+            )
+        };
+        Self {
+            uid: format!("{}-s{}-e{}", item.link, item.season, item.episode).into(),
+            summary: summary.into(),
+            url: if item.epurl.is_empty() {
+                None
+            } else {
+                Some(format!("https://www.imdb.com/title/{}", item.epurl).into())
+            },
+            airdate: item.airdate,
+        }
+    }
+}
+
+impl From<&TraktCalEntry> for CalendarEvent {
+    fn from(item: &TraktCalEntry) -> Self {
+        Self {
+            uid: format!("{}-s{}-e{}", item.link, item.season, item.episode).into(),
+            summary: format!("{} s{:02} ep{:02}", item.show, item.season, item.episode).into(),
+            url: item
+                .ep_link
+                .as_ref()
+                .map(|l| format!("https://www.imdb.com/title/{}", l).into()),
+            airdate: item.airdate,
+        }
+    }
+}
+
+fn escape_ical(s: &str) -> StackString {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+fn fold_ical_line(line: &str) -> StackString {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded.into()
+}
+
+pub fn ical_calendar(domain: &str, events: &[CalendarEvent]) -> StackString {
+    let dtstamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//movie_collection_rust//Upcoming Episodes//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "X-WR-CALNAME:Upcoming Episodes".to_string(),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@{}", event.uid, domain));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        let start = event.airdate.format("%Y%m%d");
+        let end = (event.airdate + Duration::days(1)).format("%Y%m%d");
+        lines.push(format!("DTSTART;VALUE=DATE:{}", start));
+        lines.push(format!("DTEND;VALUE=DATE:{}", end));
+        lines.push(format!("SUMMARY:{}", escape_ical(&event.summary)));
+        if let Some(url) = &event.url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    let folded: Vec<_> = lines.iter().map(|l| fold_ical_line(l)).collect();
+    folded.join("").into()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct AiringToday {
+    pub show: StackString,
+    pub title: StackString,
+    pub season: i32,
+    pub episode: i32,
+    pub eptitle: StackString,
+    pub airdate: NaiveDateWrapper,
+    pub url: Option<StackString>,
+}
+
+impl From<&NewEpisodesResult> for AiringToday {
+    fn from(item: &NewEpisodesResult) -> Self {
+        Self {
+            show: item.show.clone(),
+            title: item.title.clone(),
+            season: item.season,
+            episode: item.episode,
+            eptitle: item.eptitle.clone(),
+            airdate: item.airdate.into(),
+            url: if item.epurl.is_empty() {
+                None
+            } else {
+                Some(format!("https://www.imdb.com/title/{}", item.epurl).into())
+            },
+        }
+    }
+}
+
+pub fn airing_today_fragment(entries: &[AiringToday]) -> StackString {
+    if entries.is_empty() {
+        return r#"<ul class="airing-today"><li>Nothing airing today</li></ul>"#.into();
+    }
+    let items: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let label = format!(
+                "{} s{:02} ep{:02}",
+                escape_html(&entry.title),
+                entry.season,
+                entry.episode
+            );
+            let label = match &entry.url {
+                Some(url) => format!(r#"<a href="{}">{}</a>"#, url, label),
+                None => label,
+            };
+            if entry.eptitle.is_empty() {
+                format!("<li>{}</li>", label)
+            } else {
+                format!("<li>{} {}</li>", label, escape_html(&entry.eptitle))
+            }
+        })
+        .collect();
+    format!(r#"<ul class="airing-today">{}</ul>"#, items.join("")).into()
+}
+
+pub fn airing_today_page(entries: &[AiringToday]) -> StackString {
+    format!(
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>Airing Today</title></head><body><h3>Airing Today</h3>{}</body></html>"#,
+        airing_today_fragment(entries),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use movie_collection_lib::collection_feed::FeedEntry;
+
+    use crate::movie_queue_feed::{
+        airing_today_fragment, airing_today_page, atom_feed, fold_ical_line, ical_calendar,
+        AiringToday, CalendarEvent,
+    };
+
+    #[test]
+    fn test_atom_feed() {
+        let entries = vec![
+            FeedEntry {
+                id: "collection-12".into(),
+                title: "Law & Order".into(),
+                summary: "/tmp/television/law_and_order_s01_ep01.mp4".into(),
+                collection_idx: Some(12),
+                updated: Utc.ymd(2021, 3, 4).and_hms(5, 6, 7).into(),
+            },
+            FeedEntry {
+                id: "plex-event-3".into(),
+                title: "Mr. Robot - Season 1 - <eps1.0>".into(),
+                summary: "Added to plex".into(),
+                collection_idx: None,
+                updated: Utc.ymd(2021, 3, 1).and_hms(0, 0, 0).into(),
+            },
+        ];
+        let feed = atom_feed("www.example.com", &entries);
+        assert!(feed.contains("<updated>2021-03-04T05:06:07Z</updated><entry>"));
+        assert!(feed.contains("<title>Law &amp; Order</title>"));
+        assert!(feed.contains(r#"<link href="https://www.example.com/list/play/12"/>"#));
+        assert!(feed.contains("<title>Mr. Robot - Season 1 - &lt;eps1.0&gt;</title>"));
+        assert!(feed.contains("<id>tag:www.example.com,2021:plex-event-3</id>"));
+    }
+
+    #[test]
+    fn test_ical_calendar() {
+        let events = vec![CalendarEvent {
+            uid: "tt4158110-s4-e2".into(),
+            summary: "Mr. Robot s04 ep02 Not Found, Again; Really".into(),
+            url: Some("https://www.imdb.com/title/tt10847274".into()),
+            airdate: NaiveDate::from_ymd(2019, 10, 13),
+        }];
+        let cal = ical_calendar("www.example.com", &events);
+        assert!(cal.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(cal.contains("UID:tt4158110-s4-e2@www.example.com\r\n"));
+        assert!(cal.contains("DTSTART;VALUE=DATE:20191013\r\n"));
+        assert!(cal.contains("DTEND;VALUE=DATE:20191014\r\n"));
+        assert!(cal.contains("Not Found\\, Again\\; Really\r\n"));
+        assert!(cal.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+
+        let line = "x".repeat(100);
+        let folded = fold_ical_line(&line);
+        assert_eq!(folded.len(), 100 + 3 + 2);
+        assert!(folded.starts_with(&format!("{}\r\n {}", "x".repeat(75), "x")));
+    }
+
+    #[test]
+    fn test_airing_today_fragment() {
+        let entries = vec![
+            AiringToday {
+                show: "mr_robot".into(),
+                title: "Mr. Robot".into(),
+                season: 4,
+                episode: 2,
+                eptitle: "<Not Found>".into(),
+                airdate: NaiveDate::from_ymd(2019, 10, 13).into(),
+                url: Some("https://www.imdb.com/title/tt10847274".into()),
+            },
+            AiringToday {
+                show: "law_and_order".into(),
+                title: "Law & Order".into(),
+                season: 21,
+                episode: 1,
+                eptitle: "".into(),
+                airdate: NaiveDate::from_ymd(2019, 10, 13).into(),
+                url: None,
+            },
+        ];
+        let fragment = airing_today_fragment(&entries);
+        assert!(fragment.starts_with(r#"<ul class="airing-today"><li><a href="#));
+        assert!(fragment.contains("Mr. Robot s04 ep02</a> &lt;Not Found&gt;</li>"));
+        assert!(fragment.contains("<li>Law &amp; Order s21 ep01</li></ul>"));
+        assert!(airing_today_fragment(&[]).contains("Nothing airing today"));
+        assert!(airing_today_page(&entries).contains(fragment.as_str()));
+    }
+}
@@ -2,20 +2,25 @@ use anyhow::format_err;
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
-use stdout_channel::{MockStdout, StdoutChannel};
+use stdout_channel::StdoutChannel;
 
 use movie_collection_lib::{
     config::Config,
     datetime_wrapper::DateTimeWrapper,
+    episode_numbering::EpisodeNumbering,
     imdb_episodes::ImdbEpisodes,
     imdb_ratings::ImdbRatings,
+    imdb_utils::{extract_imdb_link, show_name_from_title, ImdbConnection},
+    media_ids::MediaId,
     movie_collection::{
         find_new_episodes_http_worker, ImdbSeason, LastModifiedResponse, MovieCollection,
-        MovieCollectionRow,
+        MovieCollectionRow, PlaybackMarkers,
     },
-    movie_queue::{MovieQueueDB, MovieQueueResult, MovieQueueRow},
+    movie_queue::{MovieQueueDB, MovieQueueResult, MovieQueueRow, QueueFilter},
     parse_imdb::{ParseImdb, ParseImdbOptions},
     pgpool::PgPool,
+    plex_events::{PlexEvent, PlexEventType},
+    sync_cursor::{sync_page_size, SyncCursor},
     trakt_connection::TraktConnection,
     trakt_utils::{
         get_watched_shows_db, get_watchlist_shows_db_map, TraktActions, WatchListMap,
@@ -37,20 +42,17 @@ impl WatchlistShowsRequest {
 #[derive(Debug)]
 pub struct MovieQueueRequest {
     pub patterns: Vec<StackString>,
+    pub filter: QueueFilter,
 }
 
 impl MovieQueueRequest {
     pub async fn handle(
         self,
-        pool: &PgPool,
-        config: &Config,
+        mq: &MovieQueueDB,
     ) -> Result<(Vec<MovieQueueResult>, Vec<StackString>), Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
         let patterns: Vec<_> = self.patterns.iter().map(StackString::as_str).collect();
-        let queue = MovieQueueDB::new(&config, &pool, &stdout)
-            .print_movie_queue(&patterns)
+        let queue = mq
+            .print_movie_queue_filtered(&patterns, &self.filter)
             .await?;
         Ok((queue, self.patterns))
     }
@@ -61,14 +63,13 @@ pub struct MoviePathRequest {
 }
 
 impl MoviePathRequest {
-    pub async fn handle(&self, pool: &PgPool, config: &Config) -> Result<StackString, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        MovieCollection::new(&config, &pool, &stdout)
-            .get_collection_path(self.idx)
-            .await
-            .map_err(Into::into)
+    pub async fn handle(
+        &self,
+        mc: &MovieCollection,
+    ) -> Result<(StackString, PlaybackMarkers), Error> {
+        let path = mc.get_collection_path(self.idx).await?;
+        let markers = mc.get_playback_markers(self.idx).await?;
+        Ok((path, markers))
     }
 }
 
@@ -90,15 +91,11 @@ pub struct ImdbSeasonsRequest {
 }
 
 impl ImdbSeasonsRequest {
-    pub async fn handle(&self, pool: &PgPool, config: &Config) -> Result<Vec<ImdbSeason>, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
+    pub async fn handle(&self, mc: &MovieCollection) -> Result<Vec<ImdbSeason>, Error> {
         if &self.show == "" {
             Ok(Vec::new())
         } else {
-            MovieCollection::new(&config, pool, &stdout)
-                .print_imdb_all_seasons(&self.show)
+            mc.print_imdb_all_seasons(&self.show)
                 .await
                 .map_err(Into::into)
         }
@@ -134,6 +131,96 @@ impl WatchlistActionRequest {
     }
 }
 
+#[derive(Serialize, Deserialize, Schema)]
+pub struct QuickAddSearchRequest {
+    pub query: StackString,
+}
+
+pub struct QuickAddCandidate {
+    pub title: StackString,
+    pub link: StackString,
+    pub rating: f64,
+    pub existing: Option<ImdbRatings>,
+}
+
+impl QuickAddSearchRequest {
+    pub async fn handle(&self, pool: &PgPool) -> Result<Vec<QuickAddCandidate>, Error> {
+        let imdb_conn = ImdbConnection::new();
+        let link = extract_imdb_link(&self.query);
+        let search = link.as_ref().unwrap_or(&self.query);
+        let mut results = imdb_conn.parse_imdb(search).await?;
+        if let Some(link) = &link {
+            results.retain(|r| &r.link == link);
+        }
+        let mut candidates = Vec::new();
+        for result in results {
+            let existing = ImdbRatings::get_show_by_link(&result.link, pool).await?;
+            candidates.push(QuickAddCandidate {
+                title: result.title,
+                link: result.link,
+                rating: result.rating,
+                existing,
+            });
+        }
+        Ok(candidates)
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct QuickAddRequest {
+    pub link: StackString,
+    pub title: StackString,
+    pub istv: Option<bool>,
+    pub source: Option<TvShowSource>,
+    #[serde(default)]
+    pub watchlist: bool,
+}
+
+impl QuickAddRequest {
+    pub async fn handle(
+        self,
+        pool: &PgPool,
+        trakt: Option<&TraktConnection>,
+    ) -> Result<ImdbRatings, Error> {
+        let mut show = if let Some(show) = ImdbRatings::get_show_by_link(&self.link, pool).await? {
+            show
+        } else {
+            let rating = ImdbConnection::new().parse_imdb_rating(&self.link).await?;
+            let show = ImdbRatings {
+                show: show_name_from_title(&self.title),
+                title: Some(self.title.clone()),
+                link: self.link.clone(),
+                rating: rating.rating,
+                istv: self.istv.or_else(|| Some(self.title.contains("TV"))),
+                ..ImdbRatings::default()
+            };
+            show.insert_show(pool).await?;
+            ImdbRatings::get_show_by_link(&self.link, pool)
+                .await?
+                .unwrap_or(show)
+        };
+        if let Some(source) = self.source {
+            show.source = if source == TvShowSource::All {
+                None
+            } else {
+                Some(source)
+            };
+            show.update_show(pool).await?;
+        }
+        if self.watchlist {
+            let trakt = trakt.ok_or(Error::TraktNotConfigured)?;
+            trakt.init().await;
+            trakt.add_watchlist_show(&self.link).await?;
+            let req = WatchlistActionRequest {
+                action: TraktActions::Add,
+                imdb_url: self.link,
+            };
+            req.handle(pool, trakt).await?;
+        }
+        Ok(show)
+    }
+}
+
 pub struct WatchedShowsRequest {
     pub show: StackString,
     pub season: i32,
@@ -153,12 +240,8 @@ pub struct ImdbEpisodesRequest {
 }
 
 impl ImdbEpisodesRequest {
-    pub async fn handle(&self, pool: &PgPool, config: &Config) -> Result<Vec<ImdbEpisodes>, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        MovieCollection::new(&config, &pool, &stdout)
-            .print_imdb_episodes(&self.show, self.season)
+    pub async fn handle(&self, mc: &MovieCollection) -> Result<Vec<ImdbEpisodes>, Error> {
+        mc.print_imdb_episodes(&self.show, self.season)
             .await
             .map_err(Into::into)
     }
@@ -203,12 +286,19 @@ impl From<ImdbShowRequest> for ParseImdbOptions {
 }
 
 impl ImdbShowRequest {
-    pub async fn handle(self, pool: &PgPool, config: &Config) -> Result<StackString, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
+    pub async fn handle(
+        mut self,
+        pool: &PgPool,
+        config: &Config,
+        stdout: &StdoutChannel<StackString>,
+    ) -> Result<StackString, Error> {
+        if self.show.contains(':') {
+            if let Some(imdb) = ImdbRatings::get_show_by_link(&self.show, pool).await? {
+                self.show = imdb.show;
+            }
+        }
         let watchlist = get_watchlist_shows_db_map(&pool).await?;
-        let pi = ParseImdb::new(&config, pool, &stdout);
+        let pi = ParseImdb::new(&config, pool, stdout);
         let body = pi.parse_imdb_http_worker(&self.into(), &watchlist).await?;
         Ok(body)
     }
@@ -221,22 +311,39 @@ pub struct FindNewEpisodeRequest {
 }
 
 impl FindNewEpisodeRequest {
-    pub async fn handle(self, pool: &PgPool, config: &Config) -> Result<Vec<StackString>, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        find_new_episodes_http_worker(config, pool, &stdout, self.shows, self.source)
+    pub async fn handle(
+        self,
+        pool: &PgPool,
+        config: &Config,
+        stdout: &StdoutChannel<StackString>,
+    ) -> Result<Vec<StackString>, Error> {
+        find_new_episodes_http_worker(config, pool, stdout, self.shows, self.source)
             .await
             .map_err(Into::into)
     }
 }
 
+// A cursor continues from a previous page, otherwise start at the timestamp
+pub fn sync_cursor(
+    start_timestamp: Option<DateTimeWrapper>,
+    cursor: Option<&StackString>,
+) -> Result<SyncCursor, Error> {
+    match (cursor, start_timestamp) {
+        (Some(cursor), _) => SyncCursor::decode(cursor)
+            .map_err(|_| Error::BadRequest(format!("Invalid cursor {}", cursor).into())),
+        (None, Some(start_timestamp)) => Ok(SyncCursor::start(start_timestamp.into())),
+        (None, None) => Err(Error::BadRequest(
+            "start_timestamp or cursor is required".into(),
+        )),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Schema)]
-pub struct ImdbEpisodesSyncRequest {
+pub struct ImdbEpisodesListRequest {
     pub start_timestamp: DateTimeWrapper,
 }
 
-impl ImdbEpisodesSyncRequest {
+impl ImdbEpisodesListRequest {
     pub async fn handle(&self, pool: &PgPool) -> Result<Vec<ImdbEpisodes>, Error> {
         ImdbEpisodes::get_episodes_after_timestamp(self.start_timestamp.into(), pool)
             .await
@@ -244,12 +351,41 @@ impl ImdbEpisodesSyncRequest {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ImdbEpisodesSyncRequest {
+    pub start_timestamp: Option<DateTimeWrapper>,
+    pub cursor: Option<StackString>,
+    pub limit: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize, Schema)]
-pub struct ImdbRatingsSyncRequest {
+pub struct ImdbEpisodesPage {
+    pub items: Vec<ImdbEpisodes>,
+    pub next_cursor: Option<StackString>,
+}
+
+impl ImdbEpisodesSyncRequest {
+    pub fn cursor(&self) -> Result<SyncCursor, Error> {
+        sync_cursor(self.start_timestamp, self.cursor.as_ref())
+    }
+
+    pub async fn handle(&self, pool: &PgPool) -> Result<ImdbEpisodesPage, Error> {
+        let (items, next_cursor) =
+            ImdbEpisodes::get_episodes_page(pool, self.cursor()?, sync_page_size(self.limit))
+                .await?;
+        Ok(ImdbEpisodesPage {
+            items,
+            next_cursor: next_cursor.map(|c| c.encode()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct ImdbRatingsListRequest {
     pub start_timestamp: DateTimeWrapper,
 }
 
-impl ImdbRatingsSyncRequest {
+impl ImdbRatingsListRequest {
     pub async fn handle(&self, pool: &PgPool) -> Result<Vec<ImdbRatings>, Error> {
         ImdbRatings::get_shows_after_timestamp(self.start_timestamp.into(), pool)
             .await
@@ -258,20 +394,79 @@ impl ImdbRatingsSyncRequest {
 }
 
 #[derive(Serialize, Deserialize, Schema)]
-pub struct MovieQueueSyncRequest {
-    pub start_timestamp: DateTimeWrapper,
+pub struct ImdbRatingsSyncRequest {
+    pub start_timestamp: Option<DateTimeWrapper>,
+    pub cursor: Option<StackString>,
+    pub limit: Option<usize>,
 }
 
-impl MovieQueueSyncRequest {
+#[derive(Serialize, Deserialize, Schema)]
+pub struct ImdbRatingsPage {
+    pub items: Vec<ImdbRatings>,
+    pub next_cursor: Option<StackString>,
+}
+
+impl ImdbRatingsSyncRequest {
+    pub fn cursor(&self) -> Result<SyncCursor, Error> {
+        sync_cursor(self.start_timestamp, self.cursor.as_ref())
+    }
+
+    pub async fn handle(&self, pool: &PgPool) -> Result<ImdbRatingsPage, Error> {
+        let (items, next_cursor) =
+            ImdbRatings::get_shows_page(pool, self.cursor()?, sync_page_size(self.limit)).await?;
+        Ok(ImdbRatingsPage {
+            items,
+            next_cursor: next_cursor.map(|c| c.encode()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlexEventSyncRequest {
+    pub start_timestamp: Option<DateTimeWrapper>,
+    pub cursor: Option<StackString>,
+    pub limit: Option<usize>,
+    pub event_type: Option<PlexEventType>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct PlexEventPage {
+    pub items: Vec<PlexEvent>,
+    pub next_cursor: Option<StackString>,
+}
+
+impl PlexEventSyncRequest {
+    pub fn cursor(&self) -> Result<SyncCursor, Error> {
+        sync_cursor(self.start_timestamp, self.cursor.as_ref())
+    }
+
     pub async fn handle(
         &self,
         pool: &PgPool,
-        config: &Config,
-    ) -> Result<Vec<MovieQueueRow>, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
+        hidden_accounts: &[StackString],
+    ) -> Result<PlexEventPage, Error> {
+        let (items, next_cursor) = PlexEvent::get_events_page(
+            pool,
+            self.cursor()?,
+            self.event_type,
+            sync_page_size(self.limit),
+            hidden_accounts,
+        )
+        .await?;
+        Ok(PlexEventPage {
+            items,
+            next_cursor: next_cursor.map(|c| c.encode()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct MovieQueueSyncRequest {
+    pub start_timestamp: DateTimeWrapper,
+}
 
-        let mq = MovieQueueDB::new(&config, pool, &stdout);
+impl MovieQueueSyncRequest {
+    pub async fn handle(&self, mq: &MovieQueueDB) -> Result<Vec<MovieQueueRow>, Error> {
         mq.get_queue_after_timestamp(self.start_timestamp.into())
             .await
             .map_err(Into::into)
@@ -284,15 +479,7 @@ pub struct MovieCollectionSyncRequest {
 }
 
 impl MovieCollectionSyncRequest {
-    pub async fn handle(
-        &self,
-        pool: &PgPool,
-        config: &Config,
-    ) -> Result<Vec<MovieCollectionRow>, Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        let mc = MovieCollection::new(&config, pool, &stdout);
+    pub async fn handle(&self, mc: &MovieCollection) -> Result<Vec<MovieCollectionRow>, Error> {
         mc.get_collection_after_timestamp(self.start_timestamp.into())
             .await
             .map_err(Into::into)
@@ -306,12 +493,7 @@ pub struct ImdbEpisodesUpdateRequest {
 
 impl ImdbEpisodesUpdateRequest {
     pub async fn handle(&self, pool: &PgPool) -> Result<(), Error> {
-        for episode in &self.episodes {
-            match episode.get_index(&pool).await? {
-                Some(_) => episode.update_episode(&pool).await?,
-                None => episode.insert_episode(&pool).await?,
-            }
-        }
+        ImdbEpisodes::upsert_episodes(pool, &self.episodes).await?;
         Ok(())
     }
 }
@@ -354,18 +536,34 @@ impl ImdbRatingsSetSourceRequest {
     }
 }
 
+#[derive(Serialize, Deserialize, Schema)]
+pub struct ImdbRatingsSetNumberingRequest {
+    pub link: StackString,
+    pub numbering: EpisodeNumbering,
+}
+
+impl ImdbRatingsSetNumberingRequest {
+    pub async fn handle(&self, pool: &PgPool) -> Result<(), Error> {
+        let link = MediaId::resolve_link(pool, &self.link)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("No show found for {}", self.link).into()))?;
+        let updated = self.numbering.set_for_link(pool, &link).await?;
+        if updated == 0 {
+            return Err(Error::NotFound(
+                format!("No show found for {}", self.link).into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Schema)]
 pub struct MovieQueueUpdateRequest {
     pub queue: Vec<MovieQueueRow>,
 }
 
 impl MovieQueueUpdateRequest {
-    pub async fn handle(&self, pool: &PgPool, config: &Config) -> Result<(), Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        let mq = MovieQueueDB::new(&config, pool, &stdout);
-        let mc = MovieCollection::new(&config, pool, &stdout);
+    pub async fn handle(&self, mc: &MovieCollection, mq: &MovieQueueDB) -> Result<(), Error> {
         for entry in &self.queue {
             let cidx = if let Some(i) = mc.get_collection_index(entry.path.as_ref()).await? {
                 i
@@ -387,28 +585,54 @@ impl MovieQueueUpdateRequest {
 #[derive(Serialize, Deserialize, Schema)]
 pub struct MovieCollectionUpdateRequest {
     pub collection: Vec<MovieCollectionRow>,
+    // Set by the smoke test so its fixture never reaches user webhooks
+    #[serde(default)]
+    pub suppress_hooks: bool,
 }
 
 impl MovieCollectionUpdateRequest {
-    pub async fn handle(&self, pool: &PgPool, config: &Config) -> Result<(), Error> {
-        let mock_stdout = MockStdout::new();
-        let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-        let mc = MovieCollection::new(&config, pool, &stdout);
+    pub async fn handle(&self, mc: &MovieCollection) -> Result<(), Error> {
+        let without_hooks;
+        let mc = if self.suppress_hooks {
+            without_hooks = mc.clone().without_hooks();
+            &without_hooks
+        } else {
+            mc
+        };
         for entry in &self.collection {
             if let Some(cidx) = mc.get_collection_index(entry.path.as_ref()).await? {
                 if cidx == entry.idx {
+                    if entry.credits_start.is_some() {
+                        mc.set_credits_start(cidx, entry.credits_start).await?;
+                    }
                     continue;
                 }
                 mc.remove_from_collection(entry.path.as_ref()).await?;
             };
             mc.insert_into_collection(entry.path.as_ref(), false)
                 .await?;
+            if entry.credits_start.is_some() {
+                if let Some(cidx) = mc.get_collection_index(entry.path.as_ref()).await? {
+                    mc.set_credits_start(cidx, entry.credits_start).await?;
+                }
+            }
         }
         Ok(())
     }
 }
 
+#[derive(Serialize, Deserialize, Schema)]
+pub struct MovieCollectionDeleteRequest {
+    pub path: StackString,
+}
+
+impl MovieCollectionDeleteRequest {
+    pub async fn handle(&self, mc: &MovieCollection) -> Result<(), Error> {
+        mc.remove_from_collection(&self.path).await?;
+        Ok(())
+    }
+}
+
 pub struct LastModifiedRequest {}
 
 impl LastModifiedRequest {
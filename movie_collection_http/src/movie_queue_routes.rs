@@ -1,83 +1,184 @@
 #![allow(clippy::needless_pass_by_value)]
 
 use anyhow::format_err;
-use bytes::Buf;
+use bytes::{Buf, Bytes};
+use chrono::{Local, Utc};
+use futures::{future::try_join_all, SinkExt};
 use itertools::Itertools;
-use log::error;
 use maplit::hashmap;
-use rweb::{get, multipart::FormData, post, Json, Query, Rejection, Schema};
+use rweb::{
+    delete,
+    filters::{
+        cookie::cookie,
+        ws::{ws, Message, WebSocket, Ws},
+        BoxedFilter,
+    },
+    get,
+    multipart::FormData,
+    patch, post, put, Filter, Json, Query, Rejection, Reply, Schema,
+};
 use rweb_helper::{
     html_response::HtmlResponse as HtmlBase, json_response::JsonResponse as JsonBase, RwebResponse,
 };
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
 use std::{
-    borrow::Borrow,
     collections::{HashMap, HashSet},
-    hash::{Hash, Hasher},
+    convert::TryInto,
     path,
     path::PathBuf,
     sync::Arc,
     time::Duration,
 };
-use stdout_channel::{MockStdout, StdoutChannel};
-use tokio::{fs::remove_file, time::timeout};
+use stdout_channel::StdoutChannel;
+use tokio::{
+    fs, select,
+    task::spawn_blocking,
+    time::{interval, timeout},
+};
 use tokio_stream::StreamExt;
+use tracing::{debug, error};
 
 use movie_collection_lib::{
+    alert_rules::{AlertKind, AlertRule},
+    api_tokens::ApiToken,
+    artwork::{thumbnail_html, Artwork},
+    backup::BackupArchive,
+    collection_feed::{FeedEntry, FEED_LIMIT},
     config::Config,
     datetime_wrapper::DateTimeWrapper,
+    delete_confirm::DeletePreview,
+    download_requests::DownloadRequest,
+    duplicates::DuplicateGroup,
+    episode_numbering::EpisodeNumbering,
+    hls_stream::{hls_file, hls_url, needs_hls, start_hls},
+    household_watched::HouseholdWatched,
     imdb_episodes::ImdbEpisodes,
     imdb_ratings::ImdbRatings,
+    imdb_refresh::ImdbRefreshStatus,
+    intro_markers::IntroMarker,
+    jellyfin_events::{JellyfinClient, JellyfinEvent},
+    kodi_nfo::export_kodi_nfo,
     make_list::FileLists,
     make_queue::movie_queue_http,
+    media_ids::{MediaId, MediaIdType},
     movie_collection::{
-        ImdbSeason, LastModifiedResponse, MovieCollection, MovieCollectionRow, TvShowsResult,
+        ImdbSeason, LastModifiedResponse, MovieCollection, MovieCollectionRow, PlaybackMarkers,
+        TvShowsPageResult, TvShowsSort,
     },
-    movie_queue::{MovieQueueDB, MovieQueueResult, MovieQueueRow},
+    movie_queue::{MovieQueueDB, MovieQueueResult, MovieQueueRow, QueueFilter},
+    music_collection::{make_music_collection, MusicBrowse, MusicBrowseFilter, MusicCollection},
+    naivedate_wrapper::NaiveDateWrapper,
+    notifications::{NotificationChannel, NotificationPreference, Notifier},
+    offline_files::OfflineFile,
+    opensubtitles::OpenSubtitles,
+    pagination::Pagination,
     pgpool::PgPool,
-    plex_events::{PlexEvent, PlexEventType},
+    playback_position::PlaybackPosition,
+    plex_events::{PlexEvent, PlexEventDailyCount, PlexEventType, WebhookPayload},
+    plex_metadata::{format_offset, PlexMetadata},
+    plex_servers::PlexServer,
+    plex_sessions::{NowPlaying, PlexClient, PlexError},
+    queue_import::parse_queue_import,
+    queue_share::{QueueShare, QueueSnapshot},
+    reclaim::{remove_keep, set_keep, ReclaimReport, TrashEntry, DEFAULT_RECLAIM_DAYS},
+    rename::RenamePlan,
+    saved_filters::{FilterExpr, SavedFilter},
+    scan_exclusions::ScanExclusions,
+    scan_history::{ScanHistory, SCAN_HISTORY_LIMIT},
+    search::{SearchResults, DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT},
+    show_availability::{AvailabilityConnection, ShowAvailability},
+    show_settings::{ShowSettings, ShowSettingsPatch, EPISODE_ORDERINGS},
+    tonight::TonightPicks,
     trakt_connection::TraktConnection,
+    trakt_ratings::{format_rating, TraktRating},
+    trakt_sync::TraktSyncReport,
     trakt_utils::{
-        get_watched_shows_db, get_watchlist_shows_db_map, TraktActions, WatchListShow,
-        WatchedEpisode, WatchedMovie,
+        get_watched_shows_db, get_watchlist_shows_db_count, get_watchlist_shows_db_page,
+        TraktActions, WatchListShow, WatchedEpisode, WatchedMovie,
+    },
+    trakt_webhook::TraktWebhookPayload,
+    transcode_jobs::{TranscodeJob, TranscodeStats},
+    transcode_service::{
+        transcode_status, TranscodeProgress, TranscodeService, TranscodeServiceRequest,
     },
-    transcode_service::{transcode_status, TranscodeService, TranscodeServiceRequest},
     tv_show_source::TvShowSource,
-    utils::HBR,
+    up_next::UpNext,
+    user_hooks::{HookEvent, UserHook},
+    user_preferences::{UserPreferences, UserStateExport},
+    user_watched::UserWatched,
+    utils::{resolve_in_dirs, HBR},
+    viewing_stats::{ViewingStats, DEFAULT_STATS_WEEKS},
+    watch_folder::PendingMove,
+    webhook_failures::{WebhookFailure, PLEX_WEBHOOK_SOURCE, TRAKT_WEBHOOK_SOURCE},
 };
 
 use crate::uuid_wrapper::UuidWrapper;
 
 use super::{
     errors::ServiceError as Error,
-    logged_user::LoggedUser,
+    logged_user::{api_user, fill_api_tokens, LoggedUser},
     movie_queue_app::AppState,
+    movie_queue_feed::{
+        airing_today_fragment, airing_today_page, atom_feed, ical_calendar, AiringToday,
+        CalendarEvent,
+    },
     movie_queue_requests::{
-        FindNewEpisodeRequest, ImdbEpisodesSyncRequest, ImdbEpisodesUpdateRequest,
-        ImdbRatingsSetSourceRequest, ImdbRatingsSyncRequest, ImdbRatingsUpdateRequest,
-        ImdbSeasonsRequest, ImdbShowRequest, LastModifiedRequest, MovieCollectionSyncRequest,
-        MovieCollectionUpdateRequest, MoviePathRequest, MovieQueueRequest, MovieQueueSyncRequest,
-        MovieQueueUpdateRequest, ParseImdbRequest, WatchlistActionRequest,
+        FindNewEpisodeRequest, ImdbEpisodesListRequest, ImdbEpisodesPage, ImdbEpisodesSyncRequest,
+        ImdbEpisodesUpdateRequest, ImdbRatingsListRequest, ImdbRatingsPage,
+        ImdbRatingsSetNumberingRequest, ImdbRatingsSetSourceRequest, ImdbRatingsSyncRequest,
+        ImdbRatingsUpdateRequest, ImdbSeasonsRequest, ImdbShowRequest, LastModifiedRequest,
+        MovieCollectionDeleteRequest, MovieCollectionSyncRequest, MovieCollectionUpdateRequest,
+        MoviePathRequest, MovieQueueRequest, MovieQueueSyncRequest, MovieQueueUpdateRequest,
+        ParseImdbRequest, PlexEventPage, PlexEventSyncRequest, QuickAddCandidate, QuickAddRequest,
+        QuickAddSearchRequest, WatchlistActionRequest,
     },
+    request_tracing::{recent_events, RecentEvent},
+    sync_validation::validate_rows,
 };
 
 pub type WarpResult<T> = Result<T, Rejection>;
 pub type HttpResult<T> = Result<T, Error>;
 
-fn movie_queue_body(patterns: &[StackString], entries: &[StackString]) -> StackString {
+fn unwatched_toggle(url: &str, unwatched: bool) -> StackString {
+    let (query, label) = if unwatched {
+        ("", "Show All")
+    } else {
+        ("?unwatched=true", "Unwatched by Everyone")
+    };
+    format!(
+        r#"<a href="javascript:updateMainArticle('{}{}')">{}</a>"#,
+        url, query, label
+    )
+    .into()
+}
+
+fn movie_queue_body(
+    patterns: &[StackString],
+    entries: &[StackString],
+    unwatched: bool,
+    pagination: &str,
+) -> StackString {
     let previous = r#"<a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>"#;
 
-    let watchlist_url = if patterns.is_empty() {
-        "/trakt/watchlist".to_string()
+    let (watchlist_url, toggle) = if patterns.is_empty() {
+        (
+            "/trakt/watchlist".to_string(),
+            unwatched_toggle("/list/full_queue", unwatched),
+        )
     } else {
-        format!("/trakt/watched/list/{}", patterns.join("_"))
+        (
+            format!("/trakt/watched/list/{}", patterns.join("_")),
+            "".into(),
+        )
     };
 
     let entries = format!(
-        r#"{}<a href="javascript:updateMainArticle('{}')">Watch List</a><table border="0">{}</table>"#,
+        r#"{}<a href="javascript:updateMainArticle('{}')">Watch List</a> {}<br>{}<table border="0">{}</table>"#,
         previous,
         watchlist_url,
+        toggle,
+        pagination,
         entries.join("")
     );
 
@@ -85,1444 +186,4571 @@ fn movie_queue_body(patterns: &[StackString], entries: &[StackString]) -> StackS
 }
 
 async fn queue_body_resp(
-    config: &Config,
+    state: &AppState,
     patterns: Vec<StackString>,
-    queue: Vec<MovieQueueResult>,
-    pool: &PgPool,
+    queue: &[MovieQueueResult],
+    unwatched: bool,
+    base_url: &str,
+    pagination: &Pagination,
 ) -> HttpResult<StackString> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-    let entries = movie_queue_http(&queue, pool, &config, &stdout).await?;
-    let body = movie_queue_body(&patterns, &entries);
+    let entries = movie_queue_http(queue, &state.db, &state.config, &state.stdout).await?;
+    let body = movie_queue_body(
+        &patterns,
+        &entries,
+        unwatched,
+        &pagination.get_html(base_url),
+    );
     Ok(body)
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, Schema)]
+pub struct PageQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct UnwatchedFilterQuery {
+    pub unwatched: Option<bool>,
+    pub sort: Option<TvShowsSort>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
 #[derive(RwebResponse)]
 #[response(description = "Movie Queue", content = "html")]
 struct MovieQueueResponse(HtmlBase<String, Error>);
 
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct FullQueueRequest {
+    pub unwatched: Option<bool>,
+    pub resolution: Option<StackString>,
+    pub codec: Option<StackString>,
+    pub media_kind: Option<StackString>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
 #[get("/list/full_queue")]
 pub async fn movie_queue(
+    query: Query<FullQueueRequest>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
 ) -> WarpResult<MovieQueueResponse> {
-    let req = MovieQueueRequest {
-        patterns: Vec::new(),
+    let query = query.into_inner();
+    let unwatched = query.unwatched.unwrap_or(false);
+    let filter = QueueFilter::new(
+        query.resolution.as_deref(),
+        query.codec.as_deref(),
+        query.media_kind.as_deref(),
+    )
+    .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let exclude = if unwatched {
+        HouseholdWatched::load(&state.config, &state.db)
+            .await
+            .map_err(Into::<Error>::into)?
+            .watched_queue_entries(&state.db)
+            .await
+            .map_err(Into::<Error>::into)?
+    } else {
+        Vec::new()
     };
-    let (queue, _) = req.handle(&state.db, &state.config).await?;
-    let body: String = queue_body_resp(&state.config, Vec::new(), queue, &state.db)
-        .await?
-        .into();
+    let total = state
+        .mq
+        .count_movie_queue(&[], &filter, &exclude)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let pagination = Pagination::new(query.offset, query.limit, total);
+    let queue = state
+        .mq
+        .print_movie_queue_page(&[], &filter, &exclude, pagination.offset, pagination.limit)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let filter_params = filter.query_params();
+    let mut params: Vec<StackString> = Vec::new();
+    if unwatched {
+        params.push("unwatched=true".into());
+    }
+    params.extend(filter_params);
+    let base_url = if params.is_empty() {
+        "/list/full_queue".to_string()
+    } else {
+        format!("/list/full_queue?{}", params.join("&"))
+    };
+    let body: String = queue_body_resp(
+        &state,
+        Vec::new(),
+        &queue,
+        unwatched,
+        &base_url,
+        &pagination,
+    )
+    .await?
+    .into();
     Ok(HtmlBase::new(body).into())
 }
 
 #[get("/list/queue/{path}")]
 pub async fn movie_queue_show(
     path: StackString,
+    query: Query<PageQuery>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
 ) -> WarpResult<MovieQueueResponse> {
-    let patterns = vec![path];
-
-    let req = MovieQueueRequest { patterns };
-    let (queue, patterns) = req.handle(&state.db, &state.config).await?;
-    let body: String = queue_body_resp(&state.config, patterns, queue, &state.db)
+    let base_url = format!("/list/queue/{}", path);
+    let page = query.into_inner();
+    let filter = QueueFilter::default();
+    let settings = ShowSettings::get_settings_for_show(&state.db, &path)
+        .await
+        .map_err(Into::<Error>::into)?
+        .filter(ShowSettings::reorders_episodes);
+    let total = state
+        .mq
+        .count_movie_queue(&[path.as_str()], &filter, &[])
+        .await
+        .map_err(Into::<Error>::into)?;
+    let pagination = Pagination::new(page.offset, page.limit, total);
+    let queue = if let Some(settings) = settings {
+        // the aired order comes from the resolved episode numbers, so the show's queue
+        // is sorted as a whole before taking the page
+        let mut queue = state
+            .mq
+            .print_movie_queue_filtered(&[path.as_str()], &filter)
+            .await
+            .map_err(Into::<Error>::into)?;
+        settings.sort_episodes(&mut queue);
+        queue
+            .into_iter()
+            .skip(pagination.offset)
+            .take(pagination.limit)
+            .collect()
+    } else {
+        state
+            .mq
+            .print_movie_queue_page(
+                &[path.as_str()],
+                &filter,
+                &[],
+                pagination.offset,
+                pagination.limit,
+            )
+            .await
+            .map_err(Into::<Error>::into)?
+    };
+    let body: String = queue_body_resp(&state, vec![path], &queue, false, &base_url, &pagination)
         .await?
         .into();
     Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Delete Queue Entry", content = "html")]
-struct DeleteMovieQueueResponse(HtmlBase<String, Error>);
+#[response(description = "Saved Filters", content = "html")]
+struct SavedFiltersResponse(HtmlBase<String, Error>);
 
-#[get("/list/delete/{path}")]
-pub async fn movie_queue_delete(
-    path: StackString,
+#[get("/list/filters")]
+pub async fn saved_filters(
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<DeleteMovieQueueResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-    if path::Path::new(path.as_str()).exists() {
-        MovieQueueDB::new(&state.config, &state.db, &stdout)
-            .remove_from_queue_by_path(&path)
-            .await
-            .map_err(Into::<Error>::into)?;
-    }
-    let body: String = path.into();
+) -> WarpResult<SavedFiltersResponse> {
+    let filters = SavedFilter::get_all(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = SavedFilter::get_html(&filters).into();
     Ok(HtmlBase::new(body).into())
 }
 
-async fn transcode_worker(
-    config: &Config,
-    directory: Option<&path::Path>,
-    entries: &[MovieQueueResult],
-    pool: &PgPool,
-) -> HttpResult<StackString> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct SavedFilterRequest {
+    pub name: StackString,
+    pub expression: StackString,
+}
 
-    let remcom_service = TranscodeService::new(&config, &config.remcom_queue, pool, &stdout);
-    let mut output = Vec::new();
-    for entry in entries {
-        let payload = TranscodeServiceRequest::create_remcom_request(
-            &config,
-            &path::Path::new(entry.path.as_str()),
-            directory,
-            false,
-        )
-        .await?;
-        remcom_service
-            .publish_transcode_job(&payload, |_| async move { Ok(()) })
-            .await?;
-        output.push(format!("{:?}", payload));
-        output.push(payload.publish_to_cli(&config).await?.into());
+#[derive(RwebResponse)]
+#[response(description = "Updated Saved Filter")]
+struct SavedFilterUpdateResponse(JsonBase<SavedFilter, Error>);
+
+#[post("/list/filters")]
+pub async fn saved_filters_update(
+    payload: Json<SavedFilterRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<SavedFilterUpdateResponse> {
+    let payload = payload.into_inner();
+    if payload.name.is_empty() {
+        return Err(Error::BadRequest("Filter name is required".into()).into());
     }
-    Ok(output.join("").into())
+    payload
+        .expression
+        .parse::<FilterExpr>()
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let filter = SavedFilter::upsert(&state.db, &payload.name, &payload.expression)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(filter).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Transcode Queue Item", content = "html")]
-struct TranscodeQueueResponse(HtmlBase<String, Error>);
+#[response(description = "Delete Saved Filter", content = "html")]
+struct SavedFilterDeleteResponse(HtmlBase<String, Error>);
 
-#[get("/list/transcode/queue/{path}")]
-pub async fn movie_queue_transcode(
-    path: StackString,
-    #[cookie = "jwt"] _: LoggedUser,
+#[delete("/list/filters/{id}")]
+pub async fn saved_filters_delete(
+    id: i32,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeQueueResponse> {
-    let patterns = vec![path];
-
-    let req = MovieQueueRequest { patterns };
-    let (entries, _) = req.handle(&state.db, &state.config).await?;
-    let body: String = transcode_worker(&state.config, None, &entries, &state.db)
-        .await?
-        .into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<SavedFilterDeleteResponse> {
+    let deleted = SavedFilter::delete(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No filter {}", id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted filter {}", id)).into())
+    }
 }
 
-#[get("/list/transcode/queue/{directory}/{file}")]
-pub async fn movie_queue_transcode_directory(
-    directory: StackString,
-    file: StackString,
+#[get("/list/filters/{id}")]
+pub async fn saved_filter_queue(
+    id: i32,
+    query: Query<PageQuery>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeQueueResponse> {
-    let patterns = vec![file];
-
-    let req = MovieQueueRequest { patterns };
-    let (entries, _) = req.handle(&state.db, &state.config).await?;
-    let body: String = transcode_worker(
-        &state.config,
-        Some(&path::Path::new(directory.as_str())),
-        &entries,
-        &state.db,
+) -> WarpResult<MovieQueueResponse> {
+    let filter = SavedFilter::get_by_id(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No filter {}", id).into()))?;
+    let expr = filter.get_filter().map_err(Into::<Error>::into)?;
+    let req = MovieQueueRequest {
+        patterns: Vec::new(),
+        filter: QueueFilter::default(),
+    };
+    let (queue, _) = req.handle(&state.mq).await?;
+    let queue = expr
+        .filter_queue(&state.config, &state.db, queue)
+        .await
+        .map_err(Into::<Error>::into)?;
+    // saved filter expressions are evaluated on the loaded rows, so this page is
+    // sliced in memory
+    let page = query.into_inner();
+    let pagination = Pagination::new(page.offset, page.limit, queue.len());
+    let base_url = format!("/list/filters/{}", id);
+    let body: String = queue_body_resp(
+        &state,
+        Vec::new(),
+        pagination.slice(&queue),
+        false,
+        &base_url,
+        &pagination,
     )
     .await?
     .into();
     Ok(HtmlBase::new(body).into())
 }
 
-fn play_worker(config: &Config, full_path: &path::Path) -> HttpResult<String> {
-    let file_name = full_path
-        .file_name()
-        .ok_or_else(|| format_err!("Invalid path"))?
-        .to_string_lossy();
-
-    if let Some(partial_path) = &config.video_playback_path {
-        let url = format!("/videos/partial/{}", file_name);
-
-        let body = format!(
-            r#"
-            {}<br>
-            <video width="720" controls>
-            <source src="{}" type="video/mp4">
-            Your browser does not support HTML5 video.
-            </video>
-        "#,
-            file_name, url
-        );
-
-        let partial_path = partial_path.join("videos").join("partial");
-        let partial_path = partial_path.join(file_name.as_ref());
-        if partial_path.exists() {
-            std::fs::remove_file(&partial_path)?;
-        }
-
-        #[cfg(target_family = "unix")]
-        std::os::unix::fs::symlink(&full_path, &partial_path).map_err(Into::<Error>::into)?;
-        Ok(body)
-    } else {
-        Err(format_err!("video playback path does not exist").into())
-    }
-}
-
 #[derive(RwebResponse)]
-#[response(description = "Play Queue Item", content = "html")]
-struct PlayQueueResponse(HtmlBase<String, Error>);
+#[response(description = "Delete Queue Entry", content = "html")]
+struct DeleteMovieQueueResponse(HtmlBase<String, Error>);
 
-#[get("/list/play/{idx}")]
-pub async fn movie_queue_play(
-    idx: i32,
+#[get("/list/delete/{path}")]
+pub async fn movie_queue_delete(
+    path: StackString,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<PlayQueueResponse> {
-    let req = MoviePathRequest { idx };
-    let movie_path = req.handle(&state.db, &state.config).await?;
-    let movie_path = path::Path::new(movie_path.as_str());
-    let body = play_worker(&state.config, &movie_path)?;
+) -> WarpResult<DeleteMovieQueueResponse> {
+    if path::Path::new(path.as_str()).exists() {
+        state
+            .mq
+            .remove_from_queue_by_path(&path)
+            .await
+            .map_err(Into::<Error>::into)?;
+    }
+    let body: String = path.into();
     Ok(HtmlBase::new(body).into())
 }
 
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct QueueReorderRequest {
+    pub collection_idx: i32,
+    pub new_position: i32,
+}
+
 #[derive(RwebResponse)]
-#[response(description = "List Imdb Show", content = "html")]
-struct ListImdbResponse(HtmlBase<String, Error>);
+#[response(description = "Reorder Queue Entry")]
+struct QueueReorderResponse(JsonBase<QueueReorderRequest, Error>);
 
-#[get("/list/imdb/{show}")]
-pub async fn imdb_show(
-    show: StackString,
-    query: Query<ParseImdbRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[patch("/list/queue/reorder")]
+pub async fn movie_queue_reorder(
+    payload: Json<QueueReorderRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListImdbResponse> {
-    let query = query.into_inner();
-    let req = ImdbShowRequest { show, query };
-    let body: String = req.handle(&state.db, &state.config).await?.into();
-    Ok(HtmlBase::new(body).into())
-}
-
-fn new_episode_worker(entries: &[StackString]) -> String {
-    let previous = r#"
-        <a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>
-        <input type="button" name="list_cal" value="TVCalendar" onclick="updateMainArticle('/list/cal');"/>
-        <input type="button" name="list_cal" value="NetflixCalendar" onclick="updateMainArticle('/list/cal?source=netflix');"/>
-        <input type="button" name="list_cal" value="AmazonCalendar" onclick="updateMainArticle('/list/cal?source=amazon');"/>
-        <input type="button" name="list_cal" value="HuluCalendar" onclick="updateMainArticle('/list/cal?source=hulu');"/><br>
-        <button name="remcomout" id="remcomoutput"> &nbsp; </button>
-    "#;
-    format!(
-        r#"{}<table border="0">{}</table>"#,
-        previous,
-        entries.join("")
-    )
+) -> WarpResult<QueueReorderResponse> {
+    let payload = payload.into_inner();
+    let new_position = state
+        .mq
+        .reorder_queue(payload.collection_idx, payload.new_position)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(QueueReorderRequest {
+        collection_idx: payload.collection_idx,
+        new_position,
+    })
+    .into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "List Calendar", content = "html")]
-struct ListCalendarResponse(HtmlBase<String, Error>);
+#[response(description = "Import Queue", content = "html", status = "CREATED")]
+struct QueueImportResponse(HtmlBase<String, Error>);
 
-#[get("/list/cal")]
-pub async fn find_new_episodes(
-    query: Query<FindNewEpisodeRequest>,
+#[post("/list/movie_queue/import")]
+pub async fn movie_queue_import(
+    #[filter = "rweb::multipart::form"] form: FormData,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListCalendarResponse> {
-    let entries = query.into_inner().handle(&state.db, &state.config).await?;
-    let body = new_episode_worker(&entries);
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<QueueImportResponse> {
+    let buf = read_form_data(form).await.map_err(Into::<Error>::into)?;
+    let rows = parse_queue_import(&buf).map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let report = state
+        .mq
+        .import_queue(&rows)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(report.get_html().into()).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "List Imdb Episodes")]
-struct ListImdbEpisodesResponse(JsonBase<Vec<ImdbEpisodes>, Error>);
+#[response(description = "Create Queue Share", status = "CREATED")]
+struct QueueShareResponse(JsonBase<QueueShare, Error>);
 
-#[get("/list/imdb_episodes")]
-pub async fn imdb_episodes_route(
-    query: Query<ImdbEpisodesSyncRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[post("/list/share")]
+pub async fn queue_share_create(
+    #[filter = "api_user"] user: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListImdbEpisodesResponse> {
-    let x = query.into_inner().handle(&state.db).await?;
-    Ok(JsonBase::new(x).into())
+) -> WarpResult<QueueShareResponse> {
+    let share = QueueShare::regenerate(&state.db, &user.email, &*state.clock, &*state.id_gen)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(share).into())
 }
 
 #[derive(RwebResponse)]
-#[response(
-    description = "Imdb Episodes Update",
-    content = "html",
-    status = "CREATED"
-)]
-struct ImdbEpisodesUpdateResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Revoke Queue Share", content = "html")]
+struct QueueShareRevokeResponse(HtmlBase<String, Error>);
 
-#[post("/list/imdb_episodes")]
-pub async fn imdb_episodes_update(
-    episodes: Json<ImdbEpisodesUpdateRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[delete("/list/share")]
+pub async fn queue_share_revoke(
+    #[filter = "api_user"] user: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ImdbEpisodesUpdateResponse> {
-    episodes.into_inner().handle(&state.db).await?;
-    Ok(HtmlBase::new("Success").into())
+) -> WarpResult<QueueShareRevokeResponse> {
+    let revoked = QueueShare::revoke(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(format!("Revoked {} share links", revoked)).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "List Imdb Shows")]
-struct ListImdbShowsResponse(JsonBase<Vec<ImdbRatings>, Error>);
+#[response(description = "Queue Snapshot", content = "html")]
+struct QueueSnapshotResponse(HtmlBase<String, Error>);
 
-#[get("/list/imdb_ratings")]
-pub async fn imdb_ratings_route(
-    query: Query<ImdbRatingsSyncRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/share/{token}")]
+pub async fn queue_share_snapshot(
+    token: StackString,
     #[data] state: AppState,
-) -> WarpResult<ListImdbShowsResponse> {
-    let x = query.into_inner().handle(&state.db).await?;
-    Ok(JsonBase::new(x).into())
+) -> WarpResult<QueueSnapshotResponse> {
+    if QueueShare::get_valid(&state.db, &token, &*state.clock)
+        .await
+        .map_err(Into::<Error>::into)?
+        .is_none()
+    {
+        return Err(Error::BadRequest("Invalid share link".into()).into());
+    }
+    let snapshot = QueueSnapshot::get_snapshot(&state.db, &*state.clock)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(snapshot.get_html().into()).into())
+}
+
+// Feed URLs are handed to feed readers and calendar apps, so they authenticate with the
+// configured `feed_key` rather than a login.
+fn check_feed_key(state: &AppState, key: UuidWrapper) -> HttpResult<()> {
+    match state.config.feed_key {
+        None => Err(Error::NotFound("Feeds are disabled".into())),
+        Some(feed_key) if feed_key == key.into() => Ok(()),
+        Some(_) => Err(Error::BadRequest("Invalid feed key".into())),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct FeedQuery {
+    pub key: UuidWrapper,
 }
 
 #[derive(RwebResponse)]
-#[response(
-    description = "Update Imdb Shows",
-    content = "html",
-    status = "CREATED"
-)]
-struct UpdateImdbShowsResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Atom Feed of New Collection Items", content = "html")]
+struct CollectionFeedResponse(HtmlBase<String, Error>);
 
-#[post("/list/imdb_ratings")]
-pub async fn imdb_ratings_update(
-    shows: Json<ImdbRatingsUpdateRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/feed.xml")]
+pub async fn collection_feed(
+    query: Query<FeedQuery>,
     #[data] state: AppState,
-) -> WarpResult<UpdateImdbShowsResponse> {
-    shows.into_inner().handle(&state.db).await?;
-    Ok(HtmlBase::new("Success").into())
+) -> WarpResult<CollectionFeedResponse> {
+    check_feed_key(&state, query.into_inner().key)?;
+    let entries = FeedEntry::get_recent(&state.db, FEED_LIMIT)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(atom_feed(&state.config.domain, &entries).into()).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ReclaimQuery {
+    pub days: Option<i64>,
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Imdb Show Set Source", content = "html")]
-struct ImdbSetSourceResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Safe to Delete Report", content = "html")]
+struct ReclaimResponse(HtmlBase<String, Error>);
 
-#[get("/list/imdb_ratings/set_source")]
-pub async fn imdb_ratings_set_source(
-    query: Query<ImdbRatingsSetSourceRequest>,
+#[get("/list/reclaim")]
+pub async fn reclaim(
+    query: Query<ReclaimQuery>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ImdbSetSourceResponse> {
-    query.into_inner().handle(&state.db).await?;
-    Ok(HtmlBase::new("Success").into())
+) -> WarpResult<ReclaimResponse> {
+    let days = query.into_inner().days.unwrap_or(DEFAULT_RECLAIM_DAYS);
+    let report = ReclaimReport::get_report(&state.db, days)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(report.get_html().into()).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "List Movie Queue Entries")]
-struct ListMovieQueueResponse(JsonBase<Vec<MovieQueueRow>, Error>);
+#[response(description = "Keep Collection Entry", content = "html")]
+struct ReclaimKeepResponse(HtmlBase<&'static str, Error>);
 
-#[get("/list/movie_queue")]
-pub async fn movie_queue_route(
-    query: Query<MovieQueueSyncRequest>,
+#[post("/list/reclaim/keep/{collection_idx}")]
+pub async fn reclaim_keep(
+    collection_idx: i32,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ReclaimKeepResponse> {
+    set_keep(&state.db, collection_idx, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[delete("/list/reclaim/keep/{collection_idx}")]
+pub async fn reclaim_keep_delete(
+    collection_idx: i32,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListMovieQueueResponse> {
-    let x = query.into_inner().handle(&state.db, &state.config).await?;
-    Ok(JsonBase::new(x).into())
+) -> WarpResult<ReclaimKeepResponse> {
+    remove_keep(&state.db, collection_idx)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ReclaimTrashRequest {
+    pub collection_idx: Vec<i32>,
+    pub days: Option<i64>,
 }
 
 #[derive(RwebResponse)]
-#[response(
-    description = "Update Movie Queue Entries",
-    content = "html",
-    status = "CREATED"
-)]
-struct UpdateMovieQueueResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Moved to Trash", status = "CREATED")]
+struct ReclaimTrashResponse(JsonBase<Vec<TrashEntry>, Error>);
 
-#[post("/list/movie_queue")]
-pub async fn movie_queue_update(
-    queue: Json<MovieQueueUpdateRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+#[post("/list/reclaim/trash")]
+pub async fn reclaim_trash(
+    payload: Json<ReclaimTrashRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<UpdateMovieQueueResponse> {
-    queue.into_inner().handle(&state.db, &state.config).await?;
-    Ok(HtmlBase::new("Success").into())
+) -> WarpResult<ReclaimTrashResponse> {
+    if state.config.trash_dir.is_none() {
+        return Err(Error::BadRequest("No trash_dir configured".into()).into());
+    }
+    let payload = payload.into_inner();
+    let days = payload.days.unwrap_or(DEFAULT_RECLAIM_DAYS);
+    let mut trashed = Vec::new();
+    for collection_idx in payload.collection_idx {
+        let entry = TrashEntry::move_to_trash(&state.config, &state.db, collection_idx, days)
+            .await
+            .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+        trashed.push(entry);
+    }
+    Ok(JsonBase::new(trashed).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "List Movie Collection Entries")]
-struct ListMovieCollectionResponse(JsonBase<Vec<MovieCollectionRow>, Error>);
+#[response(description = "Trashed Files", content = "html")]
+struct TrashListResponse(HtmlBase<String, Error>);
 
-#[get("/list/movie_collection")]
-pub async fn movie_collection_route(
-    query: Query<MovieCollectionSyncRequest>,
+#[get("/list/reclaim/trash")]
+pub async fn reclaim_trash_list(
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListMovieCollectionResponse> {
-    let x = query.into_inner().handle(&state.db, &state.config).await?;
-    Ok(JsonBase::new(x).into())
+) -> WarpResult<TrashListResponse> {
+    let entries = TrashEntry::get_all(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(TrashEntry::get_html(&entries).into()).into())
 }
 
 #[derive(RwebResponse)]
-#[response(
-    description = "Update Movie Collection Entries",
-    content = "html",
-    status = "CREATED"
-)]
-struct UpdateMovieCollectionResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Trash Entry Updated", content = "html")]
+struct TrashUpdateResponse(HtmlBase<&'static str, Error>);
 
-#[post("/list/movie_collection")]
-pub async fn movie_collection_update(
-    collection: Json<MovieCollectionUpdateRequest>,
-    #[cookie = "jwt"] _: LoggedUser,
+async fn get_trash_entry(state: &AppState, collection_idx: i32) -> Result<TrashEntry, Error> {
+    TrashEntry::get_by_idx(&state.db, collection_idx)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("{} is not in trash", collection_idx).into()))
+}
+
+#[post("/list/reclaim/trash/{collection_idx}/restore")]
+pub async fn reclaim_trash_restore(
+    collection_idx: i32,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<UpdateMovieCollectionResponse> {
-    collection
-        .into_inner()
-        .handle(&state.db, &state.config)
-        .await?;
+) -> WarpResult<TrashUpdateResponse> {
+    let entry = get_trash_entry(&state, collection_idx).await?;
+    entry
+        .restore(&state.db)
+        .await
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[delete("/list/reclaim/trash/{collection_idx}")]
+pub async fn reclaim_trash_purge(
+    collection_idx: i32,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TrashUpdateResponse> {
+    let entry = get_trash_entry(&state, collection_idx).await?;
+    entry.purge(&state.db).await.map_err(Into::<Error>::into)?;
     Ok(HtmlBase::new("Success").into())
 }
 
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct RenameQuery {
+    pub dry_run: Option<bool>,
+}
+
 #[derive(RwebResponse)]
-#[response(description = "Database Entries Last Modified Time")]
-struct ListLastModifiedResponse(JsonBase<Vec<LastModifiedResponse>, Error>);
+#[response(description = "Rename Collection File", content = "html")]
+struct RenameFileResponse(HtmlBase<String, Error>);
 
-#[get("/list/last_modified")]
-pub async fn last_modified_route(
+#[post("/list/rename/{collection_idx}")]
+pub async fn movie_collection_rename(
+    collection_idx: i32,
+    query: Query<RenameQuery>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListLastModifiedResponse> {
-    let req = LastModifiedRequest {};
-    let x = req.handle(&state.db).await?;
-    Ok(JsonBase::new(x).into())
+) -> WarpResult<RenameFileResponse> {
+    let plan = RenamePlan::new(&state.db, collection_idx)
+        .await
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let body = if query.into_inner().dry_run.unwrap_or(false) {
+        plan.get_html().into()
+    } else {
+        plan.execute(&state.db, &*state.clock)
+            .await
+            .map_err(Into::<Error>::into)?;
+        format!("Renamed {} to {}", plan.old_path, plan.new_path)
+    };
+    Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Frontpage", content = "html")]
-struct FrontpageResponse(HtmlBase<String, Error>);
+#[response(
+    description = "Music Collection Scan",
+    content = "html",
+    status = "CREATED"
+)]
+struct MusicCollectionScanResponse(HtmlBase<String, Error>);
 
-#[get("/list/index.html")]
-pub async fn frontpage(#[cookie = "jwt"] _: LoggedUser) -> WarpResult<FrontpageResponse> {
-    let body = HBR
-        .render("index.html", &hashmap! {"BODY" => ""})
+#[post("/list/music_collection/scan")]
+pub async fn music_collection_scan(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<MusicCollectionScanResponse> {
+    let count = make_music_collection(&state.config, &state.db, &state.stdout)
+        .await
         .map_err(Into::<Error>::into)?;
+    let body = format!("scanned {} music files", count);
     Ok(HtmlBase::new(body).into())
 }
 
-type TvShowsMap = HashMap<StackString, (StackString, WatchListShow, Option<TvShowSource>)>;
+#[derive(RwebResponse)]
+#[response(description = "Music Collection Browse", content = "html")]
+struct MusicCollectionBrowseResponse(HtmlBase<String, Error>);
 
-#[derive(Debug, Default, Eq)]
-struct ProcessShowItem {
-    show: StackString,
-    title: StackString,
-    link: StackString,
-    source: Option<TvShowSource>,
+#[get("/list/music_collection/browse")]
+pub async fn music_collection_browse(
+    query: Query<MusicBrowseFilter>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<MusicCollectionBrowseResponse> {
+    let browse = MusicBrowse::get(&state.db, query.into_inner())
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(browse.get_html().into()).into())
 }
 
-impl PartialEq for ProcessShowItem {
-    fn eq(&self, other: &Self) -> bool {
-        self.link == other.link
-    }
-}
+#[derive(RwebResponse)]
+#[response(description = "Play Music", content = "html")]
+struct MusicPlayResponse(HtmlBase<String, Error>);
 
-impl Hash for ProcessShowItem {
-    fn hash<H>(&self, state: &mut H)
-    where
-        H: Hasher,
-    {
-        self.link.hash(state)
-    }
+#[get("/list/music/{id}")]
+pub async fn music_play(
+    id: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<MusicPlayResponse> {
+    let track = MusicCollection::get_by_id(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No music entry {}", id).into()))?;
+    let url = link_partial(&state.config, path::Path::new(track.path.as_str()))?;
+    Ok(HtmlBase::new(track.get_player_html(&url).into()).into())
 }
 
-impl Borrow<str> for ProcessShowItem {
-    fn borrow(&self) -> &str {
-        self.link.as_str()
+async fn transcode_worker(
+    config: &Config,
+    directory: Option<&path::Path>,
+    entries: &[MovieQueueResult],
+    pool: &PgPool,
+    stdout: &StdoutChannel<StackString>,
+    dry_run: bool,
+) -> HttpResult<StackString> {
+    let remcom_service = TranscodeService::new(&config, &config.remcom_queue, pool, stdout);
+    let mut output = Vec::new();
+    for entry in entries {
+        let input_path = path::Path::new(entry.path.as_str());
+        let settings = ShowSettings::get_settings_for_path(pool, input_path).await?;
+        let payload = TranscodeServiceRequest::create_remcom_request(
+            &config,
+            input_path,
+            directory,
+            false,
+            settings.as_ref(),
+        )
+        .await?;
+        output.push(format!("{:?}", payload));
+        if dry_run {
+            continue;
+        }
+        remcom_service
+            .publish_transcode_job(&payload, |_| async move { Ok(()) })
+            .await?;
+        output.push(payload.publish_to_cli(&config).await?.into());
     }
+    Ok(output.join("").into())
 }
 
-impl From<TvShowsResult> for ProcessShowItem {
-    fn from(item: TvShowsResult) -> Self {
-        Self {
-            show: item.show,
-            title: item.title,
-            link: item.link,
-            source: item.source,
+async fn transcode_progress_worker(state: AppState, socket: WebSocket) {
+    let (mut tx, mut rx) = futures::StreamExt::split(socket);
+    let mut progress = TranscodeProgress::default();
+    let mut ticker = interval(Duration::from_secs(2));
+    loop {
+        select! {
+            msg = rx.next() => match msg {
+                Some(Ok(msg)) if !msg.is_close() => continue,
+                _ => break,
+            },
+            _ = ticker.tick() => {
+                let status = match transcode_status(&state.config, &state.db).await {
+                    Ok(status) => status,
+                    Err(e) => {
+                        error!("transcode status failed {:?}", e);
+                        continue;
+                    }
+                };
+                let new_progress = status.get_progress();
+                let diff = progress.diff(&new_progress);
+                if diff.is_empty() {
+                    continue;
+                }
+                progress = new_progress;
+                let text = match serde_json::to_string(&diff) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        error!("failed to serialize diff {:?}", e);
+                        continue;
+                    }
+                };
+                if tx.send(Message::text(text)).await.is_err() {
+                    break;
+                }
+            }
         }
     }
 }
 
-fn tvshows_worker(res1: TvShowsMap, tvshows: Vec<TvShowsResult>) -> StackString {
-    let tvshows: HashSet<_> = tvshows
-        .into_iter()
-        .map(|s| {
-            let item: ProcessShowItem = s.into();
-            item
-        })
-        .collect();
-    let watchlist: HashSet<_> = res1
-        .into_iter()
-        .map(|(link, (show, s, source))| {
-            let item = ProcessShowItem {
-                show,
-                title: s.title,
-                link: s.link,
-                source,
-            };
-            debug_assert!(link.as_str() == item.link.as_str());
-            item
+pub fn transcode_status_ws(app: AppState) -> BoxedFilter<(impl Reply,)> {
+    rweb::path!("list" / "transcode" / "ws")
+        .and(rweb::path::end())
+        .and(ws())
+        .and(cookie::<LoggedUser>("jwt"))
+        .map(move |ws: Ws, _: LoggedUser| {
+            let state = app.clone();
+            ws.on_upgrade(move |socket| transcode_progress_worker(state, socket))
         })
-        .collect();
+        .boxed()
+}
 
-    let shows = process_shows(tvshows, watchlist);
+#[derive(RwebResponse)]
+#[response(description = "Transcode Queue Item", content = "html")]
+struct TranscodeQueueResponse(HtmlBase<String, Error>);
 
-    let previous = r#"
-        <a href="javascript:updateMainArticle('/list/watchlist')">Go Back</a><br>
-        <a href="javascript:updateMainArticle('/trakt/watchlist')">Watch List</a>
-        <button name="remcomout" id="remcomoutput"> &nbsp; </button><br>
-    "#;
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TranscodeQueueQuery {
+    pub dry_run: Option<bool>,
+}
 
-    format!(
-        r#"{}<table border="0">{}</table>"#,
-        previous,
-        shows.join("")
+#[get("/list/transcode/queue/{path}")]
+pub async fn movie_queue_transcode(
+    path: StackString,
+    query: Query<TranscodeQueueQuery>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeQueueResponse> {
+    let patterns = vec![path];
+    let dry_run = query.into_inner().dry_run.unwrap_or(false);
+
+    let req = MovieQueueRequest {
+        patterns,
+        filter: QueueFilter::default(),
+    };
+    let (entries, _) = req.handle(&state.mq).await?;
+    let body: String = transcode_worker(
+        &state.config,
+        None,
+        &entries,
+        &state.db,
+        &state.stdout,
+        dry_run,
     )
-    .into()
+    .await?
+    .into();
+    Ok(HtmlBase::new(body).into())
 }
 
-#[derive(RwebResponse)]
-#[response(description = "List TvShows", content = "html")]
-struct ListTvShowsResponse(HtmlBase<String, Error>);
-
-#[get("/list/tvshows")]
-pub async fn tvshows(
+#[get("/list/transcode/queue/{directory}/{file}")]
+pub async fn movie_queue_transcode_directory(
+    directory: StackString,
+    file: StackString,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<ListTvShowsResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
+) -> WarpResult<TranscodeQueueResponse> {
+    let patterns = vec![file];
 
-    let mc = MovieCollection::new(&state.config, &state.db, &stdout);
-    let shows = mc.print_tv_shows().await.map_err(Into::<Error>::into)?;
-    let show_map = get_watchlist_shows_db_map(&state.db)
-        .await
-        .map_err(Into::<Error>::into)?;
-    let body: String = tvshows_worker(show_map, shows).into();
+    let req = MovieQueueRequest {
+        patterns,
+        filter: QueueFilter::default(),
+    };
+    let (entries, _) = req.handle(&state.mq).await?;
+    let body: String = transcode_worker(
+        &state.config,
+        Some(&path::Path::new(directory.as_str())),
+        &entries,
+        &state.db,
+        &state.stdout,
+        false,
+    )
+    .await?
+    .into();
     Ok(HtmlBase::new(body).into())
 }
 
-fn process_shows(
-    tvshows: HashSet<ProcessShowItem>,
-    watchlist: HashSet<ProcessShowItem>,
-) -> Vec<StackString> {
-    let watchlist_shows: Vec<_> = watchlist
-        .iter()
-        .filter(|item| tvshows.get(item.link.as_str()).is_none())
-        .collect();
-
-    let mut shows: Vec<_> = tvshows.iter().chain(watchlist_shows.into_iter()).collect();
-    shows.sort_by(|x, y| x.show.cmp(&y.show));
+fn link_partial(config: &Config, full_path: &path::Path) -> HttpResult<String> {
+    let file_name = full_path
+        .file_name()
+        .ok_or_else(|| format_err!("Invalid path"))?
+        .to_string_lossy();
+    let partial_path = config
+        .video_playback_path
+        .as_ref()
+        .ok_or_else(|| format_err!("video playback path does not exist"))?
+        .join("videos")
+        .join("partial")
+        .join(file_name.as_ref());
+    if partial_path.exists() {
+        std::fs::remove_file(&partial_path)?;
+    }
 
-    let button_add = r#"<td><button type="submit" id="ID" onclick="watchlist_add('SHOW');">add to watchlist</button></td>"#;
-    let button_rm = r#"<td><button type="submit" id="ID" onclick="watchlist_rm('SHOW');">remove from watchlist</button></td>"#;
+    #[cfg(target_family = "unix")]
+    std::os::unix::fs::symlink(&full_path, &partial_path).map_err(Into::<Error>::into)?;
+    Ok(format!("/videos/partial/{}", file_name))
+}
 
-    shows
-        .into_iter()
-        .map(|item| {
-            let has_watchlist = watchlist.contains(item.link.as_str());
-            format!(
-                r#"<tr><td>{}</td>
-                <td><a href="https://www.imdb.com/title/{}" target="_blank">imdb</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
-                if tvshows.contains(item.link.as_str()) {
-                    format!(r#"<a href="javascript:updateMainArticle('/list/queue/{}')">{}</a>"#, item.show, item.title)
-                } else {
-                    format!(
-                        r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a>"#,
-                        item.link, item.title
-                    )
-                },
-                item.link,
-                match item.source {
-                    Some(TvShowSource::Netflix) => r#"<a href="https://netflix.com" target="_blank">netflix</a>"#,
-                    Some(TvShowSource::Hulu) => r#"<a href="https://hulu.com" target="_blank">hulu</a>"#,
-                    Some(TvShowSource::Amazon) => r#"<a href="https://amazon.com" target="_blank">amazon</a>"#,
-                    _ => "",
-                },
-                if has_watchlist {
-                    format!(r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">watchlist</a>"#, item.link)
-                } else {
-                    "".to_string()
-                },
-                if has_watchlist {
-                    button_rm.replace("SHOW", &item.link)
-                } else {
-                    button_add.replace("SHOW", &item.link)
-                },
-            ).into()
-        })
-        .collect()
+fn play_worker(
+    config: &Config,
+    idx: i32,
+    full_path: &path::Path,
+    markers: PlaybackMarkers,
+) -> HttpResult<String> {
+    let file_name = full_path
+        .file_name()
+        .ok_or_else(|| format_err!("Invalid path"))?
+        .to_string_lossy();
+
+    if config.video_playback_path.is_some() {
+        // Browsers can't play mkv/avi directly, those get packaged into HLS on demand
+        let (hls_attr, source) = if needs_hls(full_path) {
+            (format!(r#"data-hls="{}""#, hls_url(idx)), String::new())
+        } else {
+            let url = link_partial(config, full_path)?;
+            (
+                String::new(),
+                format!(r#"<source src="{}" type="video/mp4">"#, url),
+            )
+        };
+
+        let mut timeupdate = Vec::new();
+        let mut skip_buttons = Vec::new();
+        if let (Some(intro_start), Some(intro_end)) = (markers.intro_start, markers.intro_end) {
+            timeupdate.push(format!(
+                "document.getElementById('skip_intro').hidden = this.currentTime < {} || \
+                 this.currentTime >= {};",
+                intro_start, intro_end
+            ));
+            skip_buttons.push(format!(
+                r#"<button id="skip_intro" hidden onclick="document.getElementById('movie_player').currentTime = {};">Skip Intro</button>"#,
+                intro_end
+            ));
+        }
+        if let Some(credits_start) = markers.credits_start {
+            timeupdate.push(format!(
+                "document.getElementById('skip_credits').hidden = this.currentTime < {};",
+                credits_start
+            ));
+            skip_buttons.push(
+                r#"<button id="skip_credits" hidden onclick="var v = document.getElementById('movie_player'); v.currentTime = v.duration;">Skip Credits</button>"#.to_string(),
+            );
+        }
+        let timeupdate = if timeupdate.is_empty() {
+            String::new()
+        } else {
+            format!(r#"ontimeupdate="{}""#, timeupdate.join(" "))
+        };
+
+        let body = format!(
+            r#"
+            {}<br>
+            <video id="movie_player" width="720" controls onloadedmetadata="resume_position({});" {} {}>
+            {}
+            Your browser does not support HTML5 video.
+            </video><br>{}
+            <button onclick="save_offline({});">Save Offline</button>
+            <button onclick="download_subtitle({});">Download Subtitles</button>
+            <button onclick="rename_file({}, true);">Rename</button>
+        "#,
+            file_name,
+            idx,
+            hls_attr,
+            timeupdate,
+            source,
+            skip_buttons.join(""),
+            idx,
+            idx,
+            idx,
+        );
+        Ok(body)
+    } else {
+        Err(format_err!("video playback path does not exist").into())
+    }
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Logged in User")]
-struct UserResponse(JsonBase<LoggedUser, Error>);
+#[response(description = "Play Queue Item", content = "html")]
+struct PlayQueueResponse(HtmlBase<String, Error>);
 
-#[get("/list/user")]
-pub async fn user(#[cookie = "jwt"] user: LoggedUser) -> WarpResult<UserResponse> {
-    Ok(JsonBase::new(user).into())
+#[get("/list/play/{idx}")]
+pub async fn movie_queue_play(
+    idx: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlayQueueResponse> {
+    let req = MoviePathRequest { idx };
+    let (movie_path, markers) = req.handle(&state.mc).await?;
+    let movie_path = path::Path::new(movie_path.as_str());
+    let mut body = play_worker(&state.config, idx, &movie_path, markers)?;
+    if let Some(metadata) = PlexMetadata::get_by_collection_idx(&state.db, idx)
+        .await
+        .map_err(Into::<Error>::into)?
+    {
+        body.push_str(&format!("<br>{}", metadata.get_html()));
+    }
+    Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Transcode Status", content = "html")]
-struct TranscodeStatusResponse(HtmlBase<String, Error>);
+#[response(description = "HLS Playlist", content = "html")]
+struct HlsPlaylistResponse(HtmlBase<Vec<u8>, Error>);
 
-#[get("/list/transcode/status")]
-pub async fn movie_queue_transcode_status(
+#[get("/list/play/{idx}/hls/index.m3u8")]
+pub async fn movie_queue_play_hls(
+    idx: i32,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeStatusResponse> {
-    let config = state.config.clone();
-    let task = timeout(Duration::from_secs(10), FileLists::get_file_lists(&config));
-    let status = transcode_status(&state.config)
+) -> WarpResult<HlsPlaylistResponse> {
+    let req = MoviePathRequest { idx };
+    let (movie_path, _) = req.handle(&state.mc).await?;
+    let playlist = start_hls(&state.config, idx, path::Path::new(movie_path.as_str()))
         .await
         .map_err(Into::<Error>::into)?;
-    let file_lists = task
-        .await
-        .map_or_else(|_| FileLists::default(), Result::unwrap);
-    let body = status.get_html(&file_lists, &state.config).join("");
+    let body = fs::read(&playlist).await.map_err(Into::<Error>::into)?;
     Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Transcode File", content = "html")]
-struct TranscodeFileResponse(HtmlBase<String, Error>);
+#[response(description = "HLS Segment", content = "html")]
+struct HlsSegmentResponse(HtmlBase<Vec<u8>, Error>);
 
-#[get("/list/transcode/file/{filename}")]
-pub async fn movie_queue_transcode_file(
-    filename: StackString,
+#[get("/list/play/{idx}/hls/{segment}")]
+pub async fn movie_queue_play_hls_segment(
+    idx: i32,
+    segment: StackString,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeFileResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
+) -> WarpResult<HlsSegmentResponse> {
+    let segment_path = hls_file(&state.config, idx, &segment)
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let body = fs::read(&segment_path).await.map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(body).into())
+}
 
-    let transcode_service = TranscodeService::new(
-        &state.config,
-        &state.config.transcode_queue,
-        &state.db,
-        &stdout,
-    );
-    let input_path = state
-        .config
-        .home_dir
-        .join("Documents")
-        .join("movies")
-        .join(&filename);
-    let req = TranscodeServiceRequest::create_transcode_request(&state.config, &input_path)
-        .map_err(Into::<Error>::into)?;
-    transcode_service
-        .publish_transcode_job(&req, |_| async move { Ok(()) })
-        .await
-        .map_err(Into::<Error>::into)?;
-    let body: String = req
-        .publish_to_cli(&state.config)
+#[derive(RwebResponse)]
+#[response(description = "Playback Position")]
+struct PlaybackPositionResponse(JsonBase<PlaybackPosition, Error>);
+
+#[get("/list/play/{collection_idx}/position")]
+pub async fn playback_position(
+    collection_idx: i32,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlaybackPositionResponse> {
+    let position = PlaybackPosition::get(&state.db, &user.email, collection_idx)
         .await
         .map_err(Into::<Error>::into)?
-        .into();
-    Ok(HtmlBase::new(body).into())
+        .ok_or_else(|| {
+            Error::NotFound(format!("No saved position for {}", collection_idx).into())
+        })?;
+    Ok(JsonBase::new(position).into())
 }
 
-#[get("/list/transcode/remcom/file/{filename}")]
-pub async fn movie_queue_remcom_file(
-    filename: StackString,
-    #[cookie = "jwt"] _: LoggedUser,
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlaybackPositionRequest {
+    pub position: f64,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Set Playback Position")]
+struct PlaybackPositionUpdateResponse(JsonBase<PlaybackPosition, Error>);
+
+#[put("/list/play/{collection_idx}/position")]
+pub async fn playback_position_update(
+    collection_idx: i32,
+    payload: Json<PlaybackPositionRequest>,
+    #[filter = "api_user"] user: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeFileResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
+) -> WarpResult<PlaybackPositionUpdateResponse> {
+    let position = payload.into_inner().position;
+    let position = PlaybackPosition::set(&state.db, &user.email, collection_idx, position)
+        .await
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?
+        .ok_or_else(|| Error::NotFound(format!("No collection entry {}", collection_idx).into()))?;
+    Ok(JsonBase::new(position).into())
+}
 
-    let transcode_service = TranscodeService::new(
-        &state.config,
-        &state.config.remcom_queue,
-        &state.db,
-        &stdout,
-    );
-    let input_path = state
-        .config
-        .home_dir
-        .join("Documents")
-        .join("movies")
-        .join(&filename);
-    let directory: Option<PathBuf> = None;
-    let req = TranscodeServiceRequest::create_remcom_request(
-        &state.config,
-        &input_path,
-        directory,
-        false,
-    )
-    .await
-    .map_err(Into::<Error>::into)?;
-    transcode_service
-        .publish_transcode_job(&req, |_| async move { Ok(()) })
+#[derive(RwebResponse)]
+#[response(description = "IMDB Episode Refresh Status", content = "html")]
+struct ImdbRefreshStatusResponse(HtmlBase<String, Error>);
+
+#[get("/list/imdb/refresh_status")]
+pub async fn imdb_refresh_status(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ImdbRefreshStatusResponse> {
+    let entries = ImdbRefreshStatus::get_all(&state.db)
         .await
         .map_err(Into::<Error>::into)?;
-    let body: String = req
-        .publish_to_cli(&state.config)
-        .await
-        .map_err(Into::<Error>::into)?
-        .into();
+    let body = ImdbRefreshStatus::get_html_table(&entries, ImdbRefreshStatus::is_running()).into();
     Ok(HtmlBase::new(body).into())
 }
 
-#[get("/list/transcode/remcom/directory/{directory}/{filename}")]
-pub async fn movie_queue_remcom_directory_file(
-    directory: StackString,
-    filename: StackString,
+#[derive(RwebResponse)]
+#[response(description = "List Imdb Show", content = "html")]
+struct ListImdbResponse(HtmlBase<String, Error>);
+
+#[get("/list/imdb/{show}")]
+pub async fn imdb_show(
+    show: StackString,
+    query: Query<ParseImdbRequest>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TranscodeFileResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-    let transcode_service = TranscodeService::new(
-        &state.config,
-        &state.config.remcom_queue,
-        &state.db,
-        &stdout,
-    );
-    let input_path = state
-        .config
-        .home_dir
-        .join("Documents")
-        .join("movies")
-        .join(&filename);
-    let req = TranscodeServiceRequest::create_remcom_request(
-        &state.config,
-        &input_path,
-        Some(directory),
-        false,
-    )
-    .await
-    .map_err(Into::<Error>::into)?;
-    transcode_service
-        .publish_transcode_job(&req, |_| async move { Ok(()) })
-        .await
-        .map_err(Into::<Error>::into)?;
+) -> WarpResult<ListImdbResponse> {
+    let query = query.into_inner();
+    let req = ImdbShowRequest { show, query };
     let body: String = req
-        .publish_to_cli(&state.config)
-        .await
-        .map_err(Into::<Error>::into)?
+        .handle(&state.db, &state.config, &state.stdout)
+        .await?
         .into();
     Ok(HtmlBase::new(body).into())
 }
 
+fn new_episode_worker(entries: &[StackString]) -> String {
+    let previous = r#"
+        <a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>
+        <input type="button" name="list_cal" value="TVCalendar" onclick="updateMainArticle('/list/cal');"/>
+        <input type="button" name="list_cal" value="NetflixCalendar" onclick="updateMainArticle('/list/cal?source=netflix');"/>
+        <input type="button" name="list_cal" value="AmazonCalendar" onclick="updateMainArticle('/list/cal?source=amazon');"/>
+        <input type="button" name="list_cal" value="HuluCalendar" onclick="updateMainArticle('/list/cal?source=hulu');"/><br>
+        <button name="remcomout" id="remcomoutput"> &nbsp; </button>
+    "#;
+    format!(
+        r#"{}<table border="0">{}</table>"#,
+        previous,
+        entries.join("")
+    )
+}
+
 #[derive(RwebResponse)]
-#[response(description = "Cleanup Transcode File", content = "html")]
-struct CleanupTranscodeFileResponse(HtmlBase<String, Error>);
+#[response(description = "List Calendar", content = "html")]
+struct ListCalendarResponse(HtmlBase<String, Error>);
 
-#[get("/list/transcode/cleanup/{path}")]
-pub async fn movie_queue_transcode_cleanup(
-    path: StackString,
+#[get("/list/cal")]
+pub async fn find_new_episodes(
+    query: Query<FindNewEpisodeRequest>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<CleanupTranscodeFileResponse> {
-    let movie_path = state
-        .config
-        .home_dir
-        .join("Documents")
-        .join("movies")
-        .join(&path);
-    let tmp_path = state.config.home_dir.join("tmp_avi").join(&path);
-    let body = if movie_path.exists() {
-        remove_file(&movie_path)
-            .await
-            .map_err(Into::<Error>::into)?;
-        format!("Removed {}", movie_path.to_string_lossy())
-    } else if tmp_path.exists() {
-        remove_file(&tmp_path).await.map_err(Into::<Error>::into)?;
-        format!("Removed {}", tmp_path.to_string_lossy())
-    } else {
-        format!("File not found {}", path)
-    };
+) -> WarpResult<ListCalendarResponse> {
+    let entries = query
+        .into_inner()
+        .handle(&state.db, &state.config, &state.stdout)
+        .await?;
+    let body = new_episode_worker(&entries);
     Ok(HtmlBase::new(body).into())
 }
 
-fn watchlist_worker(
-    shows: HashMap<StackString, (StackString, WatchListShow, Option<TvShowSource>)>,
-) -> StackString {
-    let mut shows: Vec<_> = shows
-        .into_iter()
-        .map(|(_, (_, s, source))| (s.title, s.link, source))
-        .collect();
+// Endpoints keyed on a show link also accept tmdb/tvdb/trakt ids through media_ids
+async fn resolve_link(pool: &PgPool, link: &str) -> Result<StackString, Error> {
+    MediaId::resolve_link(pool, link)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No show found for {}", link).into()))
+}
 
-    shows.sort();
+#[derive(RwebResponse)]
+#[response(description = "Download Request", content = "html")]
+struct DownloadRequestResponse(HtmlBase<String, Error>);
 
-    let shows = shows
-        .into_iter()
-        .map(|(title, link, source)| {
-            format!(
-                r#"<tr><td>{}</td><td>
-                   <a href="https://www.imdb.com/title/{}" target="_blank">imdb</a> {} </tr>"#,
-                format!(
-                    r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a>"#,
-                    link, title
-                ),
-                link,
-                format!(
-                    r#"<td><form action="javascript:setSource('{link}', '{link}_source_id')">
-                       <select id="{link}_source_id" onchange="setSource('{link}', '{link}_source_id');">
-                       {options}
-                       </select>
-                       </form></td>
-                    "#,
-                    link = link,
-                    options = match source {
-                        Some(TvShowSource::All) | None => {
-                            r#"
-                                <option value="all"></option>
-                                <option value="amazon">Amazon</option>
-                                <option value="hulu">Hulu</option>
-                                <option value="netflix">Netflix</option>
-                            "#
-                        }
-                        Some(TvShowSource::Amazon) => {
-                            r#"
-                                <option value="amazon">Amazon</option>
-                                <option value="all"></option>
-                                <option value="hulu">Hulu</option>
-                                <option value="netflix">Netflix</option>
-                            "#
-                        }
-                        Some(TvShowSource::Hulu) => {
-                            r#"
-                                <option value="hulu">Hulu</option>
-                                <option value="all"></option>
-                                <option value="amazon">Amazon</option>
-                                <option value="netflix">Netflix</option>
-                            "#
-                        }
-                        Some(TvShowSource::Netflix) => {
-                            r#"
-                                <option value="netflix">Netflix</option>
-                                <option value="all"></option>
-                                <option value="amazon">Amazon</option>
-                                <option value="hulu">Hulu</option>
-                            "#
-                        }
-                    },
-                )
-            )
-        })
-        .join("");
-
-    let previous = r#"<a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>"#;
-    format!(r#"{}<table border="0">{}</table>"#, previous, shows).into()
+#[post("/list/download/{link}/{season}/{episode}")]
+pub async fn download_request(
+    link: StackString,
+    season: i32,
+    episode: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<DownloadRequestResponse> {
+    let link = resolve_link(&state.db, &link).await?;
+    let request = DownloadRequest::submit(&state.config, &state.db, &link, season, episode)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = format!(
+        "download {} s{:02} ep{:02} {}",
+        request.link,
+        request.season,
+        request.episode,
+        request.get_html()
+    );
+    Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Watchlist", content = "html")]
-struct TraktWatchlistResponse(HtmlBase<String, Error>);
+#[response(description = "Download Requests")]
+struct DownloadRequestsResponse(JsonBase<Vec<DownloadRequest>, Error>);
 
-#[get("/trakt/watchlist")]
-pub async fn trakt_watchlist(
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/downloads")]
+pub async fn download_requests(
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktWatchlistResponse> {
-    let shows = get_watchlist_shows_db_map(&state.db)
+) -> WarpResult<DownloadRequestsResponse> {
+    let requests = DownloadRequest::get_all(&state.db)
         .await
         .map_err(Into::<Error>::into)?;
-    let body: String = watchlist_worker(shows).into();
-    Ok(HtmlBase::new(body).into())
+    Ok(JsonBase::new(requests).into())
 }
 
-async fn watchlist_action_worker(
-    trakt: &TraktConnection,
-    action: TraktActions,
-    imdb_url: &str,
-) -> HttpResult<StackString> {
-    trakt.init().await;
-    let body = match action {
-        TraktActions::Add => trakt.add_watchlist_show(&imdb_url).await?.to_string(),
-        TraktActions::Remove => trakt.remove_watchlist_show(&imdb_url).await?.to_string(),
-        _ => "".to_string(),
-    };
-    Ok(body.into())
+pub const ICAL_DAYS_BEFORE: i64 = 14;
+pub const ICAL_DAYS_AFTER: i64 = 60;
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct CalendarFeedQuery {
+    pub key: UuidWrapper,
+    pub source: Option<TvShowSource>,
+    pub trakt: Option<bool>,
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Watchlist Action", content = "html")]
-struct TraktWatchlistActionResponse(HtmlBase<String, Error>);
+#[response(description = "iCalendar of Upcoming Episodes", content = "html")]
+struct CalendarFeedResponse(HtmlBase<String, Error>);
 
-#[get("/trakt/watchlist/{action}/{imdb_url}")]
-pub async fn trakt_watchlist_action(
-    action: TraktActions,
-    imdb_url: StackString,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/cal.ics")]
+pub async fn find_new_episodes_ical(
+    query: Query<CalendarFeedQuery>,
     #[data] state: AppState,
-) -> WarpResult<TraktWatchlistActionResponse> {
-    let req = WatchlistActionRequest { action, imdb_url };
-    let imdb_url = req.handle(&state.db, &state.trakt).await?;
-    let body: String = watchlist_action_worker(&state.trakt, action, &imdb_url)
-        .await?
-        .into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<CalendarFeedResponse> {
+    let query = query.into_inner();
+    check_feed_key(&state, query.key)?;
+    let today = Local::today();
+    let mindate = (today - chrono::Duration::days(ICAL_DAYS_BEFORE)).naive_local();
+    let maxdate = (today + chrono::Duration::days(ICAL_DAYS_AFTER)).naive_local();
+    let mut events: Vec<CalendarEvent> = state
+        .mc
+        .get_new_episodes(mindate, maxdate, query.source)
+        .await
+        .map_err(Into::<Error>::into)?
+        .iter()
+        .map(Into::into)
+        .collect();
+    if query.trakt.unwrap_or(false) {
+        let trakt = state.require_trakt()?;
+        trakt.init().await;
+        let uids: HashSet<_> = events.iter().map(|e| e.uid.clone()).collect();
+        let cal_list = trakt.get_calendar().await.map_err(Into::<Error>::into)?;
+        events.extend(
+            cal_list
+                .iter()
+                .map(CalendarEvent::from)
+                .filter(|e| !uids.contains(&e.uid)),
+        );
+    }
+    events.sort_by(|x, y| x.airdate.cmp(&y.airdate));
+    Ok(HtmlBase::new(ical_calendar(&state.config.domain, &events).into()).into())
 }
 
-fn trakt_watched_seasons_worker(link: &str, imdb_url: &str, entries: &[ImdbSeason]) -> StackString {
-    let entries = entries
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct AiringTodayQuery {
+    pub key: UuidWrapper,
+    pub source: Option<TvShowSource>,
+    pub format: Option<StackString>,
+}
+
+async fn get_airing_today(
+    state: &AppState,
+    query: &AiringTodayQuery,
+) -> HttpResult<Vec<AiringToday>> {
+    check_feed_key(state, query.key)?;
+    let today = Local::today().naive_local();
+    let entries = state
+        .mc
+        .get_new_episodes(today, today, query.source)
+        .await?
         .iter()
-        .map(|s| {
-            let id = format!("watched_seasons_id_{}_{}_{}", &s.show, &link, &s.season);
-            let button_add = format!(
-                r#"
-            <td>
-            <button type="submit" id="{id}"
-                onclick="imdb_update('{show}', '{link}', {season}, '/trakt/watched/list/{link}');"
-                >update database</button></td>"#,
-                id = id,
-                show = s.show,
-                link = link,
-                season = s.season,
-            );
+        .map(Into::into)
+        .collect();
+    Ok(entries)
+}
 
-            format!(
-                "<tr><td>{}<td>{}<td>{}<td>{}</tr>",
-                format!(
-                    r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}/{}')">{}</t>"#,
-                    imdb_url, s.season, s.title
-                ),
-                s.season,
-                s.nepisodes,
-                button_add
-            )
-        })
-        .join("");
+#[derive(RwebResponse)]
+#[response(description = "Episodes Airing Today", content = "html")]
+struct AiringTodayResponse(HtmlBase<String, Error>);
 
-    let previous = r#"<a href="javascript:updateMainArticle('/trakt/watchlist')">Go Back</a><br>"#;
-    format!(r#"{}<table border="0">{}</table>"#, previous, entries).into()
+#[get("/list/cal/today")]
+pub async fn airing_today(
+    query: Query<AiringTodayQuery>,
+    #[data] state: AppState,
+) -> WarpResult<AiringTodayResponse> {
+    let query = query.into_inner();
+    let entries = get_airing_today(&state, &query).await?;
+    let body = match query.format.as_deref() {
+        Some("fragment") => airing_today_fragment(&entries),
+        None | Some("page") => airing_today_page(&entries),
+        Some(format) => {
+            return Err(Error::BadRequest(format!("Invalid format {}", format).into()).into());
+        }
+    };
+    Ok(HtmlBase::new(body.into()).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Watchlist Show List", content = "html")]
-struct TraktWatchlistShowListResponse(HtmlBase<String, Error>);
+#[response(description = "Episodes Airing Today")]
+struct AiringTodayJsonResponse(JsonBase<Vec<AiringToday>, Error>);
 
-#[get("/trakt/watched/list/{imdb_url}")]
-pub async fn trakt_watched_seasons(
-    imdb_url: StackString,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/cal/today.json")]
+pub async fn airing_today_json(
+    query: Query<AiringTodayQuery>,
     #[data] state: AppState,
-) -> WarpResult<TraktWatchlistShowListResponse> {
-    let show_opt = ImdbRatings::get_show_by_link(&imdb_url, &state.db)
-        .await
-        .map(|s| s.map(|sh| (imdb_url, sh)))
-        .map_err(Into::<Error>::into)?;
-
-    let empty = || ("".into(), "".into(), "".into());
-    let (imdb_url, show, link) =
-        show_opt.map_or_else(empty, |(imdb_url, t)| (imdb_url, t.show, t.link));
-    let req = ImdbSeasonsRequest { show };
-    let entries = req.handle(&state.db, &state.config).await?;
-    let body: String = trakt_watched_seasons_worker(&link, &imdb_url, &entries).into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<AiringTodayJsonResponse> {
+    let entries = get_airing_today(&state, &query.into_inner()).await?;
+    Ok(JsonBase::new(entries).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Watchlist Show Season", content = "html")]
-struct TraktWatchlistShowSeasonResponse(HtmlBase<String, Error>);
+#[response(description = "List Imdb Episodes")]
+struct ListImdbEpisodesResponse(JsonBase<Vec<ImdbEpisodes>, Error>);
 
-#[get("/trakt/watched/list/{imdb_url}/{season}")]
-pub async fn trakt_watched_list(
-    imdb_url: StackString,
-    season: i32,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/imdb_episodes")]
+pub async fn imdb_episodes_route(
+    query: Query<ImdbEpisodesListRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktWatchlistShowSeasonResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-    let body: String = watch_list_http_worker(&state.config, &state.db, &stdout, &imdb_url, season)
-        .await?
-        .into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<ListImdbEpisodesResponse> {
+    let x = query.into_inner().handle(&state.db).await?;
+    Ok(JsonBase::new(x).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Watchlist Episode Action", content = "html")]
-struct TraktWatchlistEpisodeActionResponse(HtmlBase<String, Error>);
+#[response(description = "Imdb Episodes Sync")]
+struct ImdbEpisodesSyncResponse(JsonBase<ImdbEpisodesPage, Error>);
 
-#[get("/trakt/watched/{action}/{imdb_url}/{season}/{episode}")]
-pub async fn trakt_watched_action(
-    action: TraktActions,
-    imdb_url: StackString,
-    season: i32,
-    episode: i32,
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/imdb_episodes/sync")]
+pub async fn imdb_episodes_sync(
+    query: Query<ImdbEpisodesSyncRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktWatchlistEpisodeActionResponse> {
-    let mock_stdout = MockStdout::new();
-    let stdout = StdoutChannel::with_mock_stdout(mock_stdout.clone(), mock_stdout.clone());
-
-    let body: String = watched_action_http_worker(
-        &state.trakt,
-        &state.db,
-        action,
-        &imdb_url,
-        season,
-        episode,
-        &state.config,
-        &stdout,
-    )
-    .await?
-    .into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<ImdbEpisodesSyncResponse> {
+    let page = query.into_inner().handle(&state.db).await?;
+    Ok(JsonBase::new(page).into())
 }
 
-fn trakt_cal_worker(entries: &[StackString]) -> StackString {
-    let previous = r#"<a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>"#;
-    format!(
-        r#"{}<table border="0">{}</table>"#,
-        previous,
-        entries.join("")
-    )
-    .into()
+#[derive(RwebResponse)]
+#[response(
+    description = "Imdb Episodes Update",
+    content = "html",
+    status = "CREATED"
+)]
+struct ImdbEpisodesUpdateResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/imdb_episodes")]
+pub async fn imdb_episodes_update(
+    episodes: Json<ImdbEpisodesUpdateRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ImdbEpisodesUpdateResponse> {
+    let mut episodes = episodes.into_inner();
+    validate_rows(&mut episodes.episodes)?;
+    episodes.handle(&state.db).await?;
+    Ok(HtmlBase::new("Success").into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Calendar", content = "html")]
-struct TraktCalendarResponse(HtmlBase<String, Error>);
+#[response(description = "List Imdb Shows")]
+struct ListImdbShowsResponse(JsonBase<Vec<ImdbRatings>, Error>);
 
-#[get("/trakt/cal")]
-pub async fn trakt_cal(
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/imdb_ratings")]
+pub async fn imdb_ratings_route(
+    query: Query<ImdbRatingsListRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktCalendarResponse> {
-    let entries = trakt_cal_http_worker(&state.trakt, &state.db).await?;
-    let body: String = trakt_cal_worker(&entries).into();
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<ListImdbShowsResponse> {
+    let x = query.into_inner().handle(&state.db).await?;
+    Ok(JsonBase::new(x).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Auth Url", content = "html")]
-struct TraktAuthUrlResponse(HtmlBase<String, Error>);
+#[response(description = "Imdb Shows Sync")]
+struct ImdbRatingsSyncResponse(JsonBase<ImdbRatingsPage, Error>);
 
-#[get("/trakt/auth_url")]
-pub async fn trakt_auth_url(
-    #[cookie = "jwt"] _: LoggedUser,
+#[get("/list/imdb_ratings/sync")]
+pub async fn imdb_ratings_sync(
+    query: Query<ImdbRatingsSyncRequest>,
+    #[filter = "api_user"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktAuthUrlResponse> {
-    state.trakt.init().await;
-    let url: String = state
-        .trakt
-        .get_auth_url()
-        .await
-        .map(Into::into)
-        .map_err(Into::<Error>::into)?;
-    Ok(HtmlBase::new(url).into())
+) -> WarpResult<ImdbRatingsSyncResponse> {
+    let page = query.into_inner().handle(&state.db).await?;
+    Ok(JsonBase::new(page).into())
 }
 
-#[derive(Serialize, Deserialize, Schema)]
-pub struct TraktCallbackRequest {
-    pub code: StackString,
-    pub state: StackString,
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Imdb Shows",
+    content = "html",
+    status = "CREATED"
+)]
+struct UpdateImdbShowsResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/imdb_ratings")]
+pub async fn imdb_ratings_update(
+    shows: Json<ImdbRatingsUpdateRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UpdateImdbShowsResponse> {
+    let mut shows = shows.into_inner();
+    validate_rows(&mut shows.shows)?;
+    shows.handle(&state.db).await?;
+    Ok(HtmlBase::new("Success").into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Callback", content = "html")]
-struct TraktCallbackResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Imdb Show Set Source", content = "html")]
+struct ImdbSetSourceResponse(HtmlBase<&'static str, Error>);
 
-#[get("/trakt/callback")]
-pub async fn trakt_callback(
-    query: Query<TraktCallbackRequest>,
+#[get("/list/imdb_ratings/set_source")]
+pub async fn imdb_ratings_set_source(
+    query: Query<ImdbRatingsSetSourceRequest>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktCallbackResponse> {
-    state.trakt.init().await;
-    let query = query.into_inner();
-    state
-        .trakt
-        .exchange_code_for_auth_token(query.code.as_str(), query.state.as_str())
-        .await
-        .map_err(Into::<Error>::into)?;
-    let body = r#"
-        <title>Trakt auth code received!</title>
-        This window can be closed.
-        <script language="JavaScript" type="text/javascript">window.close()</script>"#;
-    Ok(HtmlBase::new(body).into())
+) -> WarpResult<ImdbSetSourceResponse> {
+    query.into_inner().handle(&state.db).await?;
+    Ok(HtmlBase::new("Success").into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Trakt Refresh Auth", content = "html")]
-struct TraktRefreshAuthResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Imdb Show Set Episode Numbering", content = "html")]
+struct ImdbSetNumberingResponse(HtmlBase<&'static str, Error>);
 
-#[get("/trakt/refresh_auth")]
-pub async fn refresh_auth(
+#[get("/list/imdb_ratings/set_numbering")]
+pub async fn imdb_ratings_set_numbering(
+    query: Query<ImdbRatingsSetNumberingRequest>,
     #[cookie = "jwt"] _: LoggedUser,
     #[data] state: AppState,
-) -> WarpResult<TraktRefreshAuthResponse> {
-    state.trakt.init().await;
-    state
-        .trakt
-        .exchange_refresh_token()
-        .await
-        .map_err(Into::<Error>::into)?;
-    Ok(HtmlBase::new("Finished").into())
+) -> WarpResult<ImdbSetNumberingResponse> {
+    query.into_inner().handle(&state.db).await?;
+    Ok(HtmlBase::new("Success").into())
 }
 
-async fn trakt_cal_http_worker(
-    trakt: &TraktConnection,
-    pool: &PgPool,
-) -> Result<Vec<StackString>, Error> {
-    let button_add = Arc::new(format!(
-        "{}{}",
-        r#"<td><button type="submit" id="ID" "#,
-        r#"onclick="imdb_update('SHOW', 'LINK', SEASON, '/trakt/cal');"
-            >update database</button></td>"#
-    ));
-    trakt.init().await;
-    let cal_list = trakt.get_calendar().await?;
+#[derive(RwebResponse)]
+#[response(description = "List Movie Queue Entries")]
+struct ListMovieQueueResponse(JsonBase<Vec<MovieQueueRow>, Error>);
 
-    let mut lines = Vec::new();
-    for cal in cal_list {
-        let show = match ImdbRatings::get_show_by_link(&cal.link, &pool).await? {
-            Some(s) => s.show,
-            None => "".into(),
-        };
-        let exists = if show.is_empty() {
-            None
-        } else {
-            let idx_opt = ImdbEpisodes {
-                show: show.clone(),
-                season: cal.season,
-                episode: cal.episode,
-                ..ImdbEpisodes::default()
-            }
-            .get_index(&pool)
-            .await?;
+#[get("/list/movie_queue")]
+pub async fn movie_queue_route(
+    query: Query<MovieQueueSyncRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ListMovieQueueResponse> {
+    let x = query.into_inner().handle(&state.mq).await?;
+    Ok(JsonBase::new(x).into())
+}
 
-            match idx_opt {
-                Some(idx) => ImdbEpisodes::from_index(idx, &pool).await?,
-                None => None,
-            }
-        };
-        let line = format!(
-            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
-            format!(
-                r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}/{}')">{}</a>"#,
-                cal.link, cal.season, cal.show,
-            ),
-            format!(
-                r#"<a href="https://www.imdb.com/title/{}" target="_blank">imdb</a>"#,
-                cal.link
-            ),
-            if let Some(link) = cal.ep_link {
-                format!(
-                    r#"<a href="https://www.imdb.com/title/{}" target="_blank">{} {}</a>"#,
-                    link, cal.season, cal.episode,
-                )
-            } else if let Some(link) = exists.as_ref() {
-                format!(
-                    r#"<a href="https://www.imdb.com/title/{}" target="_blank">{} {}</a>"#,
-                    link, cal.season, cal.episode,
-                )
-            } else {
-                format!("{} {}", cal.season, cal.episode,)
-            },
-            cal.airdate,
-            if exists.is_some() {
-                "".to_string()
-            } else {
-                button_add
-                    .replace("SHOW", &show)
-                    .replace("LINK", &cal.link)
-                    .replace("SEASON", &cal.season.to_string())
-            },
-        )
-        .into();
-        lines.push(line);
-    }
-    Ok(lines)
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Movie Queue Entries",
+    content = "html",
+    status = "CREATED"
+)]
+struct UpdateMovieQueueResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/movie_queue")]
+pub async fn movie_queue_update(
+    queue: Json<MovieQueueUpdateRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UpdateMovieQueueResponse> {
+    let mut queue = queue.into_inner();
+    validate_rows(&mut queue.queue)?;
+    queue.handle(&state.mc, &state.mq).await?;
+    Ok(HtmlBase::new("Success").into())
 }
 
-pub async fn watch_list_http_worker(
-    config: &Config,
-    pool: &PgPool,
-    stdout: &StdoutChannel<StackString>,
-    imdb_url: &str,
-    season: i32,
-) -> HttpResult<StackString> {
-    let button_add = format!(
-        "{}{}",
-        r#"<button type="submit" id="ID" "#,
-        r#"onclick="watched_add('SHOW', SEASON, EPISODE);">add to watched</button>"#
-    );
-    let button_rm = format!(
-        "{}{}",
-        r#"<button type="submit" id="ID" "#,
-        r#"onclick="watched_rm('SHOW', SEASON, EPISODE);">remove from watched</button>"#
-    );
+#[derive(RwebResponse)]
+#[response(description = "List Movie Collection Entries")]
+struct ListMovieCollectionResponse(JsonBase<Vec<MovieCollectionRow>, Error>);
 
-    let mc = MovieCollection::new(config, pool, stdout);
-    let mq = MovieQueueDB::new(config, pool, stdout);
+#[get("/list/movie_collection")]
+pub async fn movie_collection_route(
+    query: Query<MovieCollectionSyncRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ListMovieCollectionResponse> {
+    let x = query.into_inner().handle(&state.mc).await?;
+    Ok(JsonBase::new(x).into())
+}
 
-    let show = ImdbRatings::get_show_by_link(imdb_url, &pool)
-        .await?
-        .ok_or_else(|| format_err!("Show Doesn't exist"))?;
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Movie Collection Entries",
+    content = "html",
+    status = "CREATED"
+)]
+struct UpdateMovieCollectionResponse(HtmlBase<&'static str, Error>);
 
-    let watched_episodes_db: HashSet<i32> = get_watched_shows_db(&pool, &show.show, Some(season))
-        .await?
-        .into_iter()
-        .map(|s| s.episode)
-        .collect();
+#[post("/list/movie_collection")]
+pub async fn movie_collection_update(
+    collection: Json<MovieCollectionUpdateRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UpdateMovieCollectionResponse> {
+    let mut collection = collection.into_inner();
+    validate_rows(&mut collection.collection)?;
+    collection.handle(&state.mc).await?;
+    Ok(HtmlBase::new("Success").into())
+}
 
-    let queue: HashMap<(StackString, i32, i32), _> = mq
-        .print_movie_queue(&[show.show.as_str()])
-        .await?
-        .into_iter()
-        .filter_map(|s| match &s.show {
-            Some(show) => match s.season {
-                Some(season) => match s.episode {
-                    Some(episode) => Some(((show.clone(), season, episode), s)),
-                    None => None,
-                },
-                None => None,
-            },
-            None => None,
-        })
-        .collect();
+#[derive(RwebResponse)]
+#[response(description = "Delete Movie Collection Entry", content = "html")]
+struct DeleteMovieCollectionResponse(HtmlBase<&'static str, Error>);
 
-    let entries: Vec<_> = mc.print_imdb_episodes(&show.show, Some(season)).await?;
+#[delete("/list/movie_collection")]
+pub async fn movie_collection_delete(
+    query: Query<MovieCollectionDeleteRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<DeleteMovieCollectionResponse> {
+    query.into_inner().handle(&state.mc).await?;
+    Ok(HtmlBase::new("Success").into())
+}
 
-    let mut collection_idx_map = HashMap::new();
-    for r in &entries {
-        if let Some(row) = queue.get(&(show.show.clone(), season, r.episode)) {
-            if let Some(index) = mc.get_collection_index(&row.path).await? {
-                collection_idx_map.insert(r.episode, index);
-            }
-        }
-    }
+#[derive(RwebResponse)]
+#[response(description = "Duplicate Collection Entries", content = "html")]
+struct DuplicatesResponse(HtmlBase<String, Error>);
 
-    let entries = entries
+#[get("/list/duplicates")]
+pub async fn collection_duplicates(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<DuplicatesResponse> {
+    let mc = &state.mc;
+    let groups = mc.find_duplicates().await.map_err(Into::<Error>::into)?;
+    let body = DuplicateGroup::get_html_table(&groups).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Database Entries Last Modified Time")]
+struct ListLastModifiedResponse(JsonBase<Vec<LastModifiedResponse>, Error>);
+
+#[get("/list/last_modified")]
+pub async fn last_modified_route(
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ListLastModifiedResponse> {
+    let req = LastModifiedRequest {};
+    let x = req.handle(&state.db).await?;
+    Ok(JsonBase::new(x).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Frontpage", content = "html")]
+struct FrontpageResponse(HtmlBase<String, Error>);
+
+#[get("/list/index.html")]
+pub async fn frontpage(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<FrontpageResponse> {
+    let trakt = if state.trakt.is_configured() {
+        "true"
+    } else {
+        ""
+    };
+    let up_next = UpNext::get_up_next(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?
+        .get_html();
+    let body = HBR
+        .render(
+            "index.html",
+            &hashmap! {"BODY" => "", "TRAKT" => trakt, "UP_NEXT" => up_next.as_str()},
+        )
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(body).into())
+}
+
+fn quick_add_worker(candidates: &[QuickAddCandidate], trakt: bool) -> String {
+    let source_options = TvShowSource::all()
         .iter()
-        .map(|s| {
-            let entry = if let Some(collection_idx) = collection_idx_map.get(&s.episode) {
+        .map(|s| format!(r#"<option value="{s}">{s}</option>"#, s = s))
+        .join("");
+    let rows = candidates
+        .iter()
+        .map(|c| {
+            let existing = c.existing.as_ref().map_or_else(String::new, |s| {
                 format!(
-                    r#"<a href="javascript:updateMainArticle('{}');">{}</a>"#,
-                    &format!("{}/{}", "/list/play", collection_idx),
-                    s.eptitle
+                    r#"<a href="javascript:updateMainArticle('/list/show/{}/settings')">{}</a>"#,
+                    s.link, s.show
+                )
+            });
+            let watchlist = if trakt {
+                format!(
+                    r#"<button onclick="quick_add('{}', true);">Add + Watchlist</button>"#,
+                    c.link
                 )
             } else {
-                s.eptitle.to_string()
+                String::new()
             };
-
             format!(
-                "<tr><td>{}</td><td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
-                show.show,
-                entry,
-                format!(
-                    r#"<a href="https://www.imdb.com/title/{}" target="_blank">s{} ep{}</a>"#,
-                    s.epurl, season, s.episode,
-                ),
-                format!(
-                    "rating: {:0.1} / {:0.1}",
-                    s.rating,
-                    show.rating.as_ref().unwrap_or(&-1.0)
-                ),
-                s.airdate,
-                if watched_episodes_db.contains(&s.episode) {
-                    button_rm
-                        .replace("SHOW", &show.link)
-                        .replace("SEASON", &season.to_string())
-                        .replace("EPISODE", &s.episode.to_string())
-                } else {
-                    button_add
-                        .replace("SHOW", &show.link)
-                        .replace("SEASON", &season.to_string())
-                        .replace("EPISODE", &s.episode.to_string())
-                }
+                r#"<tr><td id="quick_add_title_{link}">{title}</td>
+                <td><a href="https://www.imdb.com/title/{link}" target="_blank">{link}</a></td>
+                <td>{rating:.1}</td><td>{existing}</td>
+                <td><select id="quick_add_source_{link}">{source_options}</select></td>
+                <td><button onclick="quick_add('{link}', false);">Add</button>{watchlist}</td>
+                </tr>"#,
+                link = c.link,
+                title = c.title,
+                rating = c.rating,
+                existing = existing,
+                source_options = source_options,
+                watchlist = watchlist,
             )
         })
-        .join("\n");
+        .join("");
+    format!(
+        r#"<button name="remcomout" id="remcomoutput"> &nbsp; </button>
+        <table border="0">{}</table>"#,
+        rows
+    )
+}
 
-    let previous = format!(
-        r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">Go Back</a><br>"#,
-        imdb_url
-    );
-    let buttons = format!(
-        r#"
-        <button name="remcomout" id="remcomoutput"> &nbsp; </button>
-        <button type="submit" id="ID"
-            onclick="imdb_update('{show}', '{link}', {season},
-            '/trakt/watched/list/{link}/{season}');"
-            >update database</button><br>
-    "#,
+#[derive(RwebResponse)]
+#[response(description = "Quick Add Search", content = "html")]
+struct QuickAddSearchResponse(HtmlBase<String, Error>);
+
+#[get("/list/quick_add")]
+pub async fn quick_add_search(
+    query: Query<QuickAddSearchRequest>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<QuickAddSearchResponse> {
+    let candidates = query.into_inner().handle(&state.db).await?;
+    let body = quick_add_worker(&candidates, state.trakt.is_configured());
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Quick Add Show", status = "CREATED")]
+struct QuickAddResponse(JsonBase<ImdbRatings, Error>);
+
+#[post("/list/quick_add")]
+pub async fn quick_add(
+    payload: Json<QuickAddRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<QuickAddResponse> {
+    let trakt = state.require_trakt().ok();
+    let show = payload.into_inner().handle(&state.db, trakt).await?;
+    Ok(JsonBase::new(show).into())
+}
+
+fn tvshows_worker(
+    shows: &[TvShowsPageResult],
+    pagination: &Pagination,
+    unwatched: bool,
+    sort: TvShowsSort,
+) -> StackString {
+    let shows = process_shows(shows);
+    let sort_url = if unwatched {
+        "/list/tvshows?unwatched=true&sort="
+    } else {
+        "/list/tvshows?sort="
+    };
+    let sort_link = |s: TvShowsSort, label: &str| {
+        let label = if s == sort {
+            format!("<b>{}</b>", label)
+        } else {
+            label.to_string()
+        };
+        format!(
+            r#"<a href="javascript:updateMainArticle('{}{}')">{}</a>"#,
+            sort_url, s, label
+        )
+    };
+    let header = format!(
+        "<tr><th></th><th>{}</th><th></th><th>{}</th><th></th><th></th><th></th><th>{}</th><th>{}</th><th>{}</th></tr>",
+        sort_link(TvShowsSort::Name, "Show"),
+        sort_link(TvShowsSort::Source, "Source"),
+        sort_link(TvShowsSort::EpisodeCount, "Episodes"),
+        sort_link(TvShowsSort::Rating, "Rating"),
+        sort_link(TvShowsSort::LastAdded, "Last Added"),
+    );
+    let base_url = format!("{}{}", sort_url, sort);
+
+    let previous = format!(
+        r#"
+        <a href="javascript:updateMainArticle('/list/watchlist')">Go Back</a><br>
+        <a href="javascript:updateMainArticle('/trakt/watchlist')">Watch List</a>
+        {}
+        <button name="remcomout" id="remcomoutput"> &nbsp; </button><br>
+    "#,
+        unwatched_toggle("/list/tvshows", unwatched)
+    );
+
+    format!(
+        r#"{}{}<table border="0">{}{}</table>"#,
+        previous,
+        pagination.get_html(&base_url),
+        header,
+        shows.join("")
+    )
+    .into()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "List TvShows", content = "html")]
+struct ListTvShowsResponse(HtmlBase<String, Error>);
+
+#[get("/list/tvshows")]
+pub async fn tvshows(
+    query: Query<UnwatchedFilterQuery>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ListTvShowsResponse> {
+    let query = query.into_inner();
+    let unwatched = query.unwatched.unwrap_or(false);
+    let sort = query.sort.unwrap_or_default();
+    let exclude = if unwatched {
+        HouseholdWatched::load(&state.config, &state.db)
+            .await
+            .map_err(Into::<Error>::into)?
+            .watched_queue_entries(&state.db)
+            .await
+            .map_err(Into::<Error>::into)?
+    } else {
+        Vec::new()
+    };
+    // watchlist shows with nothing queued have nothing left to watch together
+    let include_watchlist = !unwatched;
+    let total = state
+        .mc
+        .count_tv_shows(&exclude, include_watchlist)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let pagination = Pagination::new(query.offset, query.limit, total);
+    let shows = state
+        .mc
+        .print_tv_shows_page(
+            sort,
+            &exclude,
+            include_watchlist,
+            pagination.offset,
+            pagination.limit,
+        )
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = tvshows_worker(&shows, &pagination, unwatched, sort).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+fn process_shows(shows: &[TvShowsPageResult]) -> Vec<StackString> {
+    let button_add = r#"<td><button type="submit" id="ID" onclick="watchlist_add('SHOW');">add to watchlist</button></td>"#;
+    let button_rm = r#"<td><button type="submit" id="ID" onclick="watchlist_rm('SHOW');">remove from watchlist</button></td>"#;
+
+    shows
+        .iter()
+        .map(|item| {
+            let has_watchlist = item.watchlist;
+            format!(
+                r#"<tr><td>{}</td><td>{}</td>
+                <td><a href="https://www.imdb.com/title/{}" target="_blank">imdb</a></td><td>{}</td><td>{}</td><td>{}</td>
+                <td><a href="javascript:updateMainArticle('/list/show/{}/settings')">settings</a></td>
+                <td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                thumbnail_html(&item.link),
+                if item.queued {
+                    format!(r#"<a href="javascript:updateMainArticle('/list/queue/{}')">{}</a>"#, item.show, item.title)
+                } else {
+                    format!(
+                        r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a>"#,
+                        item.link, item.title
+                    )
+                },
+                item.link,
+                match item.source.and_then(|s| s.url().map(|url| (s, url))) {
+                    Some((s, url)) => format!(r#"<a href="{}" target="_blank">{}</a>"#, url, s),
+                    None => "".to_string(),
+                },
+                if has_watchlist {
+                    format!(r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">watchlist</a>"#, item.link)
+                } else {
+                    "".to_string()
+                },
+                if has_watchlist {
+                    button_rm.replace("SHOW", &item.link)
+                } else {
+                    button_add.replace("SHOW", &item.link)
+                },
+                item.link,
+                if item.count > 0 {
+                    item.count.to_string()
+                } else {
+                    "".to_string()
+                },
+                item.rating.map_or_else(String::new, |r| format!("{:.1}", r)),
+                item.last_added
+                    .map_or_else(String::new, |d| d.format("%Y-%m-%d").to_string()),
+            ).into()
+        })
+        .collect()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Logged in User")]
+struct UserResponse(JsonBase<LoggedUser, Error>);
+
+#[get("/list/user")]
+pub async fn user(#[filter = "api_user"] user: LoggedUser) -> WarpResult<UserResponse> {
+    Ok(JsonBase::new(user).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "User Preferences")]
+struct UserPreferencesResponse(JsonBase<UserPreferences, Error>);
+
+#[get("/list/user/preferences")]
+pub async fn user_preferences(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserPreferencesResponse> {
+    let prefs = UserPreferences::get_preferences(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(prefs).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct RecentLogsQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recent Log Events")]
+struct RecentLogsResponse(JsonBase<Vec<RecentEvent>, Error>);
+
+#[get("/list/logs/recent")]
+pub async fn recent_logs(
+    query: Query<RecentLogsQuery>,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<RecentLogsResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let limit = query.into_inner().limit.unwrap_or(100);
+    Ok(JsonBase::new(recent_events(limit)).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct KodiNfoQuery {
+    pub overwrite: Option<bool>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Regenerate Kodi NFO Files", content = "html")]
+struct KodiNfoResponse(HtmlBase<String, Error>);
+
+#[post("/list/kodi_nfo")]
+pub async fn kodi_nfo(
+    query: Query<KodiNfoQuery>,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<KodiNfoResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let overwrite = query.into_inner().overwrite.unwrap_or(false);
+    let report = export_kodi_nfo(&state.db, overwrite)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(report.to_string().replace('\n', "<br>")).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct UserPreferencesUpdateRequest {
+    pub plex_account: Option<StackString>,
+    pub history_private: Option<bool>,
+}
+
+#[post("/list/user/preferences")]
+pub async fn user_preferences_update(
+    payload: Json<UserPreferencesUpdateRequest>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserPreferencesResponse> {
+    let payload = payload.into_inner();
+    let mut prefs = UserPreferences::get_preferences(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if let Some(plex_account) = payload.plex_account {
+        prefs.plex_account = if plex_account.is_empty() {
+            None
+        } else {
+            Some(plex_account)
+        };
+    }
+    if let Some(history_private) = payload.history_private {
+        prefs.history_private = history_private;
+    }
+    prefs
+        .upsert_preferences(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(prefs).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "User State Export")]
+struct UserStateExportResponse(JsonBase<UserStateExport, Error>);
+
+#[get("/list/user/export")]
+pub async fn user_state_export(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserStateExportResponse> {
+    let export = UserStateExport::export(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(export).into())
+}
+
+#[post("/list/user/import")]
+pub async fn user_state_import(
+    payload: Json<UserStateExport>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserStateExportResponse> {
+    let payload = payload.into_inner();
+    payload
+        .check_version()
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let export = payload
+        .restore(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(export).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Backup Export", content = "html")]
+struct BackupExportResponse(HtmlBase<String, Error>);
+
+#[get("/list/export")]
+pub async fn backup_export(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<BackupExportResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let archive = BackupArchive::export(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = archive.to_json().map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Backup Export as Gzipped NDJSON", content = "html")]
+struct BackupExportNdjsonResponse(HtmlBase<Vec<u8>, Error>);
+
+#[get("/list/export/ndjson.gz")]
+pub async fn backup_export_ndjson(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<BackupExportNdjsonResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let archive = BackupArchive::export(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = archive.to_ndjson_gz().map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Backup Import", content = "html", status = "CREATED")]
+struct BackupImportResponse(HtmlBase<String, Error>);
+
+#[post("/list/import")]
+pub async fn backup_import(
+    #[filter = "rweb::multipart::form"] form: FormData,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<BackupImportResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let buf = read_form_data(form).await.map_err(Into::<Error>::into)?;
+    let archive = BackupArchive::from_bytes(&buf)
+        .and_then(|archive| archive.check_version().map(|_| archive))
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let counts = archive
+        .restore(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = counts
+        .iter()
+        .map(|(table, count)| format!("{} {}", table, count))
+        .join("\n");
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Transcode Status", content = "html")]
+struct TranscodeStatusResponse(HtmlBase<String, Error>);
+
+#[get("/list/transcode/status")]
+pub async fn movie_queue_transcode_status(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeStatusResponse> {
+    let config = state.config.clone();
+    let task = timeout(Duration::from_secs(10), FileLists::get_file_lists(&config));
+    let status = transcode_status(&state.config, &state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let file_lists = task
+        .await
+        .map_or_else(|_| FileLists::default(), Result::unwrap);
+    let body = status.get_html(&file_lists, &state.config).join("");
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Transcode Throughput Stats")]
+struct TranscodeStatsResponse(JsonBase<TranscodeStats, Error>);
+
+#[get("/list/transcode/stats")]
+pub async fn movie_queue_transcode_stats(
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeStatsResponse> {
+    let stats = TranscodeStats::get_stats(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(stats).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TranscodePriorityRequest {
+    pub priority: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TranscodePriorityResult {
+    pub prefix: StackString,
+    pub priority: u8,
+    pub updated: u64,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Set Transcode Job Priority")]
+struct TranscodePriorityResponse(JsonBase<TranscodePriorityResult, Error>);
+
+#[patch("/list/transcode/priority/{filename}")]
+pub async fn movie_queue_transcode_priority(
+    filename: StackString,
+    payload: Json<TranscodePriorityRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodePriorityResponse> {
+    let priority = payload.into_inner().priority;
+    let prefix: StackString = path::Path::new(filename.as_str())
+        .file_stem()
+        .ok_or_else(|| Error::BadRequest("No file stem".into()))?
+        .to_string_lossy()
+        .into_owned()
+        .into();
+    let updated = TranscodeJob::set_priority(&state.db, &prefix, priority)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if updated == 0 {
+        return Err(Error::NotFound(format!("No queued job for {}", prefix).into()).into());
+    }
+    Ok(JsonBase::new(TranscodePriorityResult {
+        prefix,
+        priority,
+        updated,
+    })
+    .into())
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Download Subtitle",
+    content = "html",
+    status = "CREATED"
+)]
+struct SubtitleDownloadResponse(HtmlBase<String, Error>);
+
+#[post("/list/transcode/subtitle/download/{collection_idx}")]
+pub async fn movie_queue_subtitle_download(
+    collection_idx: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<SubtitleDownloadResponse> {
+    let req = MoviePathRequest {
+        idx: collection_idx,
+    };
+    let (movie_path, _) = req.handle(&state.mc).await?;
+    let opensubtitles = OpenSubtitles::new(&state.config)
+        .map_err(|e| Error::BadRequest(format!("{}", e).into()))?;
+    let output_path = opensubtitles
+        .download_subtitle(path::Path::new(movie_path.as_str()))
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = format!("Saved {}", output_path.to_string_lossy());
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Transcode File", content = "html")]
+struct TranscodeFileResponse(HtmlBase<String, Error>);
+
+#[get("/list/transcode/file/{filename}")]
+pub async fn movie_queue_transcode_file(
+    filename: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeFileResponse> {
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.transcode_queue,
+        &state.db,
+        &state.stdout,
+    );
+    let input_path = state
+        .config
+        .home_dir
+        .join("Documents")
+        .join("movies")
+        .join(&filename);
+    let settings = ShowSettings::get_settings_for_path(&state.db, &input_path)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let req = TranscodeServiceRequest::create_transcode_request(
+        &state.config,
+        &input_path,
+        settings.as_ref(),
+    )
+    .map_err(Into::<Error>::into)?;
+    transcode_service
+        .publish_transcode_job(&req, |_| async move { Ok(()) })
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = req
+        .publish_to_cli(&state.config)
+        .await
+        .map_err(Into::<Error>::into)?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TranscodeBatchRequest {
+    #[serde(default)]
+    pub indexes: Vec<i32>,
+    #[serde(default)]
+    pub paths: Vec<StackString>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TranscodeBatchStatus {
+    pub item: StackString,
+    pub queued: bool,
+    pub message: StackString,
+}
+
+impl TranscodeBatchStatus {
+    fn new(item: StackString, result: Result<StackString, anyhow::Error>) -> Self {
+        match result {
+            Ok(message) => Self {
+                item,
+                queued: true,
+                message,
+            },
+            Err(e) => Self {
+                item,
+                queued: false,
+                message: e.to_string().into(),
+            },
+        }
+    }
+}
+
+async fn queue_transcode_path(
+    config: &Config,
+    transcode_service: &TranscodeService,
+    input_path: &str,
+) -> Result<StackString, anyhow::Error> {
+    let input_path = path::Path::new(input_path);
+    let settings = ShowSettings::get_settings_for_path(&transcode_service.pool, input_path).await?;
+    let req =
+        TranscodeServiceRequest::create_transcode_request(config, input_path, settings.as_ref())?;
+    transcode_service
+        .publish_transcode_job(&req, |_| async move { Ok(()) })
+        .await?;
+    Ok(format!("queued {}", req.output_path.to_string_lossy()).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Transcode Batch", status = "CREATED")]
+struct TranscodeBatchResponse(JsonBase<Vec<TranscodeBatchStatus>, Error>);
+
+#[post("/list/transcode/queue_batch")]
+pub async fn movie_queue_transcode_batch(
+    payload: Json<TranscodeBatchRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeBatchResponse> {
+    let payload = payload.into_inner();
+    let mc = &state.mc;
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.transcode_queue,
+        &state.db,
+        &state.stdout,
+    );
+
+    let mut statuses = Vec::new();
+    for idx in payload.indexes {
+        let result = match mc.get_collection_path(idx).await {
+            Ok(path) => queue_transcode_path(&state.config, &transcode_service, &path).await,
+            Err(e) => Err(e.into()),
+        };
+        statuses.push(TranscodeBatchStatus::new(idx.to_string().into(), result));
+    }
+    // Raw paths come straight from the request, only files under movie_dirs get queued
+    for path in payload.paths {
+        let result =
+            match resolve_in_dirs(&state.config.movie_dirs, path::Path::new(path.as_str())).await {
+                Some(resolved) => {
+                    queue_transcode_path(
+                        &state.config,
+                        &transcode_service,
+                        &resolved.to_string_lossy(),
+                    )
+                    .await
+                }
+                None => Err(format_err!("{} is not inside movie_dirs", path)),
+            };
+        statuses.push(TranscodeBatchStatus::new(path, result));
+    }
+    Ok(JsonBase::new(statuses).into())
+}
+
+#[post("/list/transcode/queue_season/{directory}")]
+pub async fn movie_queue_transcode_season(
+    directory: StackString,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeBatchResponse> {
+    let relative = path::Path::new(directory.as_str());
+    if !relative
+        .components()
+        .all(|c| matches!(c, path::Component::Normal(_)))
+    {
+        return Err(Error::BadRequest(format!("Invalid directory {}", directory).into()).into());
+    }
+    let mut season_dir = None;
+    for movie_dir in &state.config.movie_dirs {
+        let candidate = movie_dir.join(relative);
+        if let Some(dir) = resolve_in_dirs(&state.config.movie_dirs, &candidate).await {
+            if fs::metadata(&dir).await.map_or(false, |m| m.is_dir()) {
+                season_dir.replace(dir);
+                break;
+            }
+        }
+    }
+    let season_dir =
+        season_dir.ok_or_else(|| Error::NotFound(format!("No directory {}", directory).into()))?;
+    let mut requests = TranscodeServiceRequest::create_season_request(&state.config, &season_dir)
+        .map_err(Into::<Error>::into)?;
+    for req in &mut requests {
+        if let Some(settings) = ShowSettings::get_settings_for_path(&state.db, &req.input_path)
+            .await
+            .map_err(Into::<Error>::into)?
+        {
+            req.options = settings.transcode_options();
+        }
+    }
+
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.transcode_queue,
+        &state.db,
+        &state.stdout,
+    );
+
+    let mut statuses = Vec::new();
+    for req in requests {
+        let item = req.input_path.to_string_lossy().into_owned().into();
+        let result = transcode_service
+            .publish_transcode_job(&req, |_| async move { Ok(()) })
+            .await
+            .map(|_| format!("queued {}", req.output_path.to_string_lossy()).into());
+        statuses.push(TranscodeBatchStatus::new(item, result));
+    }
+    Ok(JsonBase::new(statuses).into())
+}
+
+#[get("/list/transcode/remcom/file/{filename}")]
+pub async fn movie_queue_remcom_file(
+    filename: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeFileResponse> {
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.remcom_queue,
+        &state.db,
+        &state.stdout,
+    );
+    let input_path = state
+        .config
+        .home_dir
+        .join("Documents")
+        .join("movies")
+        .join(&filename);
+    let directory: Option<PathBuf> = None;
+    let settings = ShowSettings::get_settings_for_path(&state.db, &input_path)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let req = TranscodeServiceRequest::create_remcom_request(
+        &state.config,
+        &input_path,
+        directory,
+        false,
+        settings.as_ref(),
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    transcode_service
+        .publish_transcode_job(&req, |_| async move { Ok(()) })
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = req
+        .publish_to_cli(&state.config)
+        .await
+        .map_err(Into::<Error>::into)?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[get("/list/transcode/remcom/directory/{directory}/{filename}")]
+pub async fn movie_queue_remcom_directory_file(
+    directory: StackString,
+    filename: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TranscodeFileResponse> {
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.remcom_queue,
+        &state.db,
+        &state.stdout,
+    );
+    let input_path = state
+        .config
+        .home_dir
+        .join("Documents")
+        .join("movies")
+        .join(&filename);
+    let req = TranscodeServiceRequest::create_remcom_request(
+        &state.config,
+        &input_path,
+        Some(directory),
+        false,
+        None,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    transcode_service
+        .publish_transcode_job(&req, |_| async move { Ok(()) })
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = req
+        .publish_to_cli(&state.config)
+        .await
+        .map_err(Into::<Error>::into)?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+fn cleanup_candidate(config: &Config, path: &str) -> Option<path::PathBuf> {
+    let movie_path = config.home_dir.join("Documents").join("movies").join(path);
+    let tmp_path = config.home_dir.join("tmp_avi").join(path);
+    vec![movie_path, tmp_path].into_iter().find(|p| p.exists())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Cleanup Transcode File Preview", content = "html")]
+struct CleanupTranscodePreviewResponse(HtmlBase<String, Error>);
+
+#[get("/list/transcode/cleanup/{path}")]
+pub async fn movie_queue_transcode_cleanup(
+    path: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<CleanupTranscodePreviewResponse> {
+    let body = if let Some(file_path) = cleanup_candidate(&state.config, &path) {
+        let preview = DeletePreview::new(&[file_path], &*state.id_gen)
+            .await
+            .map_err(Into::<Error>::into)?;
+        let confirm_action = format!("cleanup_file_confirm('{}', '{}');", path, preview.token);
+        preview
+            .get_html(
+                &confirm_action,
+                "updateMainArticle('/list/transcode/status');",
+            )
+            .into()
+    } else {
+        format!("File not found {}", path)
+    };
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct DeleteConfirmQuery {
+    pub token: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Cleanup Transcode File", content = "html")]
+struct CleanupTranscodeFileResponse(HtmlBase<String, Error>);
+
+#[delete("/list/transcode/cleanup/{path}")]
+pub async fn movie_queue_transcode_cleanup_confirm(
+    path: StackString,
+    query: Query<DeleteConfirmQuery>,
+    #[cookie = "jwt"] _: LoggedUser,
+) -> WarpResult<CleanupTranscodeFileResponse> {
+    let query = query.into_inner();
+    let deleted = DeletePreview::confirm(&query.token)
+        .await
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let body = if deleted.is_empty() {
+        format!("File not found {}", path)
+    } else {
+        deleted
+            .iter()
+            .map(|f| format!("Removed {}", f.path))
+            .join("\n")
+    };
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Cancel Transcode Job", content = "html")]
+struct CancelTranscodeJobResponse(HtmlBase<String, Error>);
+
+#[delete("/list/transcode/job/{filename}")]
+pub async fn movie_queue_transcode_cancel(
+    filename: StackString,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<CancelTranscodeJobResponse> {
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.transcode_queue,
+        &state.db,
+        &state.stdout,
+    );
+    let job = transcode_service
+        .cancel_job(&filename)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = format!("Cancelled {} {}", job.job_type, job.prefix);
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Approve or Deny Pending Move", content = "html")]
+struct PendingMoveResponse(HtmlBase<String, Error>);
+
+#[post("/list/transcode/pending/{id}/{action}")]
+pub async fn movie_queue_pending_move(
+    id: i32,
+    action: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PendingMoveResponse> {
+    let pending = PendingMove::get_by_id(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound("Pending move does not exist".into()))?;
+    let body = match action.as_str() {
+        "approve" => {
+            let req = pending
+                .approve(&state.config, &state.db, &state.stdout)
+                .await
+                .map_err(Into::<Error>::into)?;
+            req.publish_to_cli(&state.config)
+                .await
+                .map_err(Into::<Error>::into)?
+                .into()
+        }
+        "deny" => {
+            pending.deny(&state.db).await.map_err(Into::<Error>::into)?;
+            format!("denied {}", pending.input_path)
+        }
+        _ => return Err(Error::BadRequest("Invalid action".into()).into()),
+    };
+    Ok(HtmlBase::new(body).into())
+}
+
+fn watchlist_worker(
+    shows: Vec<(WatchListShow, Option<TvShowSource>)>,
+    availability: &HashMap<StackString, ShowAvailability>,
+    ratings: &HashMap<StackString, i32>,
+    pagination: &Pagination,
+) -> StackString {
+    let shows = shows
+        .into_iter()
+        .map(|(WatchListShow { title, link, .. }, source)| {
+            let source = source.unwrap_or(TvShowSource::All);
+            let options = TvShowSource::all()
+                .iter()
+                .map(|s| {
+                    format!(
+                        r#"<option value="{}" {}>{}</option>"#,
+                        s,
+                        if *s == source { "selected" } else { "" },
+                        s.label()
+                    )
+                })
+                .join("");
+            format!(
+                r#"<tr><td>{}</td><td>{}</td><td>
+                   <a href="https://www.imdb.com/title/{}" target="_blank">imdb</a> {} {} <td>{}</td></tr>"#,
+                thumbnail_html(&link),
+                format!(
+                    r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a>"#,
+                    link, title
+                ),
+                link,
+                format_rating(ratings.get(link.as_str()).copied()),
+                format!(
+                    r#"<td><form action="javascript:setSource('{link}', '{link}_source_id')">
+                       <select id="{link}_source_id" onchange="setSource('{link}', '{link}_source_id');">
+                       {options}
+                       </select>
+                       </form></td>
+                    "#,
+                    link = link,
+                    options = options,
+                ),
+                availability
+                    .get(link.as_str())
+                    .map_or_else(String::new, |a| a.get_html().into()),
+            )
+        })
+        .join("");
+
+    let previous = r#"<a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>"#;
+    format!(
+        r#"{}{}<table border="0">{}</table>"#,
+        previous,
+        pagination.get_html("/trakt/watchlist"),
+        shows
+    )
+    .into()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Poster Artwork", content = "html")]
+struct ArtworkResponse(HtmlBase<Vec<u8>, Error>);
+
+#[get("/list/artwork/{link}")]
+pub async fn artwork(
+    link: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ArtworkResponse> {
+    let link = resolve_link(&state.db, &link).await?;
+    let poster = Artwork::new(&state.config, &state.db)
+        .get_poster(&link)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No artwork for {}", link).into()))?;
+    Ok(HtmlBase::new(poster).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Show Availability")]
+struct ShowAvailabilityResponse(JsonBase<ShowAvailability, Error>);
+
+#[get("/list/availability/{link}")]
+pub async fn show_availability(
+    link: StackString,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ShowAvailabilityResponse> {
+    let conn = AvailabilityConnection::new(&state.config)
+        .ok_or_else(|| Error::BadRequest("Availability lookup not configured".into()))?;
+    let link = resolve_link(&state.db, &link).await?;
+    let availability = conn
+        .get_availability(&state.db, &link)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(availability).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist", content = "html")]
+struct TraktWatchlistResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/watchlist")]
+pub async fn trakt_watchlist(
+    query: Query<PageQuery>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistResponse> {
+    let page = query.into_inner();
+    let total = get_watchlist_shows_db_count(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let pagination = Pagination::new(page.offset, page.limit, total);
+    let shows = get_watchlist_shows_db_page(&state.db, pagination.offset, pagination.limit)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let availability = ShowAvailability::get_map(&state.db, &state.config.availability_region)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let ratings = TraktRating::get_show_ratings(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = watchlist_worker(shows, &availability, &ratings, &pagination).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+async fn watchlist_action_worker(
+    trakt: &TraktConnection,
+    action: TraktActions,
+    imdb_url: &str,
+) -> HttpResult<StackString> {
+    trakt.init().await;
+    let body = match action {
+        TraktActions::Add => trakt.add_watchlist_show(&imdb_url).await?.to_string(),
+        TraktActions::Remove => trakt.remove_watchlist_show(&imdb_url).await?.to_string(),
+        _ => "".to_string(),
+    };
+    Ok(body.into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Action", content = "html")]
+struct TraktWatchlistActionResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/watchlist/{action}/{imdb_url}")]
+pub async fn trakt_watchlist_action(
+    action: TraktActions,
+    imdb_url: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistActionResponse> {
+    let trakt = state.require_trakt()?;
+    let imdb_url = resolve_link(&state.db, &imdb_url).await?;
+    let req = WatchlistActionRequest { action, imdb_url };
+    let imdb_url = req.handle(&state.db, trakt).await?;
+    let body: String = watchlist_action_worker(trakt, action, &imdb_url)
+        .await?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+fn trakt_watched_seasons_worker(link: &str, imdb_url: &str, entries: &[ImdbSeason]) -> StackString {
+    let entries = entries
+        .iter()
+        .map(|s| {
+            let id = format!("watched_seasons_id_{}_{}_{}", &s.show, &link, &s.season);
+            let button_add = format!(
+                r#"
+            <td>
+            <button type="submit" id="{id}"
+                onclick="imdb_update('{show}', '{link}', {season}, '/trakt/watched/list/{link}');"
+                >update database</button></td>"#,
+                id = id,
+                show = s.show,
+                link = link,
+                season = s.season,
+            );
+
+            format!(
+                "<tr><td>{}<td>{}<td>{}<td>{}</tr>",
+                format!(
+                    r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}/{}')">{}</t>"#,
+                    imdb_url, s.season, s.title
+                ),
+                s.season,
+                s.nepisodes,
+                button_add
+            )
+        })
+        .join("");
+
+    let previous = r#"<a href="javascript:updateMainArticle('/trakt/watchlist')">Go Back</a><br>"#;
+    format!(r#"{}<table border="0">{}</table>"#, previous, entries).into()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Show List", content = "html")]
+struct TraktWatchlistShowListResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/watched/list/{imdb_url}")]
+pub async fn trakt_watched_seasons(
+    imdb_url: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistShowListResponse> {
+    let show_opt = ImdbRatings::get_show_by_link(&imdb_url, &state.db)
+        .await
+        .map(|s| s.map(|sh| (imdb_url, sh)))
+        .map_err(Into::<Error>::into)?;
+
+    let empty = || ("".into(), "".into(), "".into());
+    let (imdb_url, show, link) =
+        show_opt.map_or_else(empty, |(imdb_url, t)| (imdb_url, t.show, t.link));
+    let req = ImdbSeasonsRequest { show };
+    let entries = req.handle(&state.mc).await?;
+    let body: String = trakt_watched_seasons_worker(&link, &imdb_url, &entries).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Show Season", content = "html")]
+struct TraktWatchlistShowSeasonResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/watched/list/{imdb_url}/{season}")]
+pub async fn trakt_watched_list(
+    imdb_url: StackString,
+    season: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistShowSeasonResponse> {
+    let imdb_url = resolve_link(&state.db, &imdb_url).await?;
+    let body: String = watch_list_http_worker(&state.mc, &state.mq, &imdb_url, season)
+        .await?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Episode Action", content = "html")]
+struct TraktWatchlistEpisodeActionResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/watched/{action}/{imdb_url}/{season}/{episode}")]
+pub async fn trakt_watched_action(
+    action: TraktActions,
+    imdb_url: StackString,
+    season: i32,
+    episode: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistEpisodeActionResponse> {
+    let trakt = state.require_trakt()?;
+    let imdb_url = resolve_link(&state.db, &imdb_url).await?;
+    let body: String =
+        watched_action_http_worker(trakt, &state.mc, action, &imdb_url, season, episode)
+            .await?
+            .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Season Action", content = "html")]
+struct TraktWatchlistSeasonActionResponse(HtmlBase<String, Error>);
+
+#[post("/trakt/watched/{action}/{imdb_url}/{season}")]
+pub async fn trakt_watched_season_action(
+    action: TraktActions,
+    imdb_url: StackString,
+    season: i32,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktWatchlistSeasonActionResponse> {
+    let trakt = state.require_trakt()?;
+    let imdb_url = resolve_link(&state.db, &imdb_url).await?;
+    let body: String =
+        watched_season_action_http_worker(trakt, &state.db, action, &imdb_url, season)
+            .await?
+            .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+fn trakt_cal_worker(entries: &[StackString]) -> StackString {
+    let previous = r#"<a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>"#;
+    format!(
+        r#"{}<table border="0">{}</table>"#,
+        previous,
+        entries.join("")
+    )
+    .into()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Calendar", content = "html")]
+struct TraktCalendarResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/cal")]
+pub async fn trakt_cal(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktCalendarResponse> {
+    let trakt = state.require_trakt()?;
+    let entries =
+        trakt_cal_http_worker(trakt, &state.db, &state.config.availability_region).await?;
+    let body: String = trakt_cal_worker(&entries).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Auth Url", content = "html")]
+struct TraktAuthUrlResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/auth_url")]
+pub async fn trakt_auth_url(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktAuthUrlResponse> {
+    let trakt = state.require_trakt()?;
+    trakt.init().await;
+    let url: String = trakt
+        .get_auth_url()
+        .await
+        .map(Into::into)
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new(url).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct TraktCallbackRequest {
+    pub code: StackString,
+    pub state: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Callback", content = "html")]
+struct TraktCallbackResponse(HtmlBase<&'static str, Error>);
+
+#[get("/trakt/callback")]
+pub async fn trakt_callback(
+    query: Query<TraktCallbackRequest>,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktCallbackResponse> {
+    let trakt = state.require_trakt()?;
+    trakt.init().await;
+    let query = query.into_inner();
+    trakt
+        .exchange_code_for_auth_token(query.code.as_str(), query.state.as_str())
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = r#"
+        <title>Trakt auth code received!</title>
+        This window can be closed.
+        <script language="JavaScript" type="text/javascript">window.close()</script>"#;
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Refresh Auth", content = "html")]
+struct TraktRefreshAuthResponse(HtmlBase<&'static str, Error>);
+
+#[get("/trakt/refresh_auth")]
+pub async fn refresh_auth(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktRefreshAuthResponse> {
+    let trakt = state.require_trakt()?;
+    trakt.init().await;
+    trakt
+        .exchange_refresh_token()
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new("Finished").into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Watchlist Sync Status", content = "html")]
+struct TraktSyncStatusResponse(HtmlBase<String, Error>);
+
+#[get("/trakt/sync_status")]
+pub async fn trakt_sync_status(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TraktSyncStatusResponse> {
+    let reports = TraktSyncReport::get_recent(&state.db, 20)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = TraktSyncReport::get_html_table(&reports).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+async fn trakt_cal_http_worker(
+    trakt: &TraktConnection,
+    pool: &PgPool,
+    region: &str,
+) -> Result<Vec<StackString>, Error> {
+    let button_add = Arc::new(format!(
+        "{}{}",
+        r#"<td><button type="submit" id="ID" "#,
+        r#"onclick="imdb_update('SHOW', 'LINK', SEASON, '/trakt/cal');"
+            >update database</button></td>"#
+    ));
+    trakt.init().await;
+    let cal_list = trakt.get_calendar().await?;
+    let availability = ShowAvailability::get_map(pool, region).await?;
+
+    let mut lines = Vec::new();
+    for cal in cal_list {
+        let show = match ImdbRatings::get_show_by_link(&cal.link, &pool).await? {
+            Some(s) => s.show,
+            None => "".into(),
+        };
+        let exists = if show.is_empty() {
+            None
+        } else {
+            let idx_opt = ImdbEpisodes {
+                show: show.clone(),
+                season: cal.season,
+                episode: cal.episode,
+                ..ImdbEpisodes::default()
+            }
+            .get_index(&pool)
+            .await?;
+
+            match idx_opt {
+                Some(idx) => ImdbEpisodes::from_index(idx, &pool).await?,
+                None => None,
+            }
+        };
+        let line = format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}<td>{}</td></tr>",
+            format!(
+                r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}/{}')">{}</a>"#,
+                cal.link, cal.season, cal.show,
+            ),
+            format!(
+                r#"<a href="https://www.imdb.com/title/{}" target="_blank">imdb</a>"#,
+                cal.link
+            ),
+            if let Some(link) = cal.ep_link {
+                format!(
+                    r#"<a href="https://www.imdb.com/title/{}" target="_blank">{} {}</a>"#,
+                    link, cal.season, cal.episode,
+                )
+            } else if let Some(link) = exists.as_ref() {
+                format!(
+                    r#"<a href="https://www.imdb.com/title/{}" target="_blank">{} {}</a>"#,
+                    link, cal.season, cal.episode,
+                )
+            } else {
+                format!("{} {}", cal.season, cal.episode,)
+            },
+            cal.airdate,
+            if exists.is_some() {
+                "".to_string()
+            } else {
+                button_add
+                    .replace("SHOW", &show)
+                    .replace("LINK", &cal.link)
+                    .replace("SEASON", &cal.season.to_string())
+            },
+            availability
+                .get(cal.link.as_str())
+                .map_or_else(String::new, |a| a.get_html().into()),
+        )
+        .into();
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+pub async fn watch_list_http_worker(
+    mc: &MovieCollection,
+    mq: &MovieQueueDB,
+    imdb_url: &str,
+    season: i32,
+) -> HttpResult<StackString> {
+    let pool = &mc.pool;
+    let button_add = format!(
+        "{}{}",
+        r#"<button type="submit" id="ID" "#,
+        r#"onclick="watched_add('SHOW', SEASON, EPISODE);">add to watched</button>"#
+    );
+    let button_rm = format!(
+        "{}{}",
+        r#"<button type="submit" id="ID" "#,
+        r#"onclick="watched_rm('SHOW', SEASON, EPISODE);">remove from watched</button>"#
+    );
+
+    let show = ImdbRatings::get_show_by_link(imdb_url, pool)
+        .await?
+        .ok_or_else(|| format_err!("Show Doesn't exist"))?;
+
+    let watched_episodes_db: HashSet<i32> = get_watched_shows_db(pool, &show.show, Some(season))
+        .await?
+        .into_iter()
+        .map(|s| s.episode)
+        .collect();
+
+    let queue: Vec<_> = mq.print_movie_queue(&[show.show.as_str()]).await?;
+    let queue: HashMap<(StackString, i32, i32), _> = queue
+        .iter()
+        .filter_map(|s| match &s.show {
+            Some(show) => match s.season {
+                Some(season) => match s.episode {
+                    Some(episode) => {
+                        let last = s.episode_end.unwrap_or(episode);
+                        Some((episode..=last).map(move |e| ((show.clone(), season, e), s)))
+                    }
+                    None => None,
+                },
+                None => None,
+            },
+            None => None,
+        })
+        .flatten()
+        .collect();
+
+    let entries: Vec<_> = mc.print_imdb_episodes(&show.show, Some(season)).await?;
+    let my_ratings = TraktRating::get_episode_ratings(pool, &show.link, season).await?;
+
+    let mut collection_idx_map = HashMap::new();
+    let mut span_map = HashMap::new();
+    for r in &entries {
+        if let Some(row) = queue.get(&(show.show.clone(), season, r.episode)) {
+            if let Some(index) = mc.get_collection_index(&row.path).await? {
+                collection_idx_map.insert(r.episode, index);
+            }
+            if let Some(span) = row.episode_span() {
+                span_map.insert(r.episode, span);
+            }
+        }
+    }
+
+    let entries = entries
+        .iter()
+        .map(|s| {
+            let entry = if let Some(collection_idx) = collection_idx_map.get(&s.episode) {
+                format!(
+                    r#"<a href="javascript:updateMainArticle('{}');">{}</a>"#,
+                    &format!("{}/{}", "/list/play", collection_idx),
+                    s.eptitle
+                )
+            } else {
+                s.eptitle.to_string()
+            };
+
+            format!(
+                "<tr><td>{}</td><td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                show.show,
+                entry,
+                match span_map.get(&s.episode) {
+                    Some(span) => format!(
+                        r#"<a href="https://www.imdb.com/title/{}" target="_blank">s{} {}</a>"#,
+                        s.epurl, season, span,
+                    ),
+                    None => format!(
+                        r#"<a href="https://www.imdb.com/title/{}" target="_blank">s{} ep{}</a>"#,
+                        s.epurl, season, s.episode,
+                    ),
+                },
+                format!(
+                    "rating: {:0.1} / {:0.1} {}",
+                    s.rating,
+                    show.rating.as_ref().unwrap_or(&-1.0),
+                    format_rating(my_ratings.get(&s.episode).copied()),
+                ),
+                s.airdate,
+                if watched_episodes_db.contains(&s.episode) {
+                    button_rm
+                        .replace("SHOW", &show.link)
+                        .replace("SEASON", &season.to_string())
+                        .replace("EPISODE", &s.episode.to_string())
+                } else {
+                    button_add
+                        .replace("SHOW", &show.link)
+                        .replace("SEASON", &season.to_string())
+                        .replace("EPISODE", &s.episode.to_string())
+                }
+            )
+        })
+        .join("\n");
+
+    let previous = format!(
+        r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">Go Back</a><br>"#,
+        imdb_url
+    );
+    let buttons = format!(
+        r#"
+        <button name="remcomout" id="remcomoutput"> &nbsp; </button>
+        <button type="submit" id="ID"
+            onclick="imdb_update('{show}', '{link}', {season},
+            '/trakt/watched/list/{link}/{season}');"
+            >update database</button>
+        <button type="submit" onclick="watched_season('add', '{link}', {season});"
+            >mark season watched</button>
+        <button type="submit" onclick="watched_season('rm', '{link}', {season});"
+            >mark season unwatched</button><br>
+    "#,
         show = show.show,
         link = show.link,
         season = season
     );
 
-    let entries = format!(
-        r#"{}{}<table border="0">{}</table>"#,
-        previous, buttons, entries
+    let entries = format!(
+        r#"{}{}<table border="0">{}</table>"#,
+        previous, buttons, entries
+    )
+    .into();
+    Ok(entries)
+}
+
+pub async fn watched_action_http_worker(
+    trakt: &TraktConnection,
+    mc: &MovieCollection,
+    action: TraktActions,
+    imdb_url: &str,
+    season: i32,
+    episode: i32,
+) -> HttpResult<StackString> {
+    let imdb_url = Arc::new(imdb_url.to_owned());
+    trakt.init().await;
+    let body = match action {
+        TraktActions::Add => {
+            let result = if season != -1 && episode != -1 {
+                trakt
+                    .add_episode_to_watched(&imdb_url, season, episode)
+                    .await?
+            } else {
+                trakt.add_movie_to_watched(&imdb_url).await?
+            };
+            if season != -1 && episode != -1 {
+                WatchedEpisode {
+                    imdb_url: imdb_url.to_string().into(),
+                    season,
+                    episode,
+                    ..WatchedEpisode::default()
+                }
+                .insert_episode(&mc.pool)
+                .await?;
+            } else {
+                WatchedMovie {
+                    imdb_url: imdb_url.to_string().into(),
+                    title: "".into(),
+                }
+                .insert_movie(&mc.pool)
+                .await?;
+            }
+
+            format!("{}", result)
+        }
+        TraktActions::Remove => {
+            let imdb_url_ = Arc::clone(&imdb_url);
+            let result = if season != -1 && episode != -1 {
+                trakt
+                    .remove_episode_to_watched(&imdb_url_, season, episode)
+                    .await?
+            } else {
+                trakt.remove_movie_to_watched(&imdb_url_).await?
+            };
+
+            if season != -1 && episode != -1 {
+                if let Some(epi_) =
+                    WatchedEpisode::get_watched_episode(&mc.pool, &imdb_url, season, episode)
+                        .await?
+                {
+                    epi_.delete_episode(&mc.pool).await?;
+                }
+            } else if let Some(movie) = WatchedMovie::get_watched_movie(&mc.pool, &imdb_url).await?
+            {
+                movie.delete_movie(&mc.pool).await?;
+            };
+
+            format!("{}", result)
+        }
+        _ => "".to_string(),
+    }
+    .into();
+    Ok(body)
+}
+
+pub async fn watched_season_action_http_worker(
+    trakt: &TraktConnection,
+    pool: &PgPool,
+    action: TraktActions,
+    imdb_url: &str,
+    season: i32,
+) -> HttpResult<StackString> {
+    trakt.init().await;
+    let body = match action {
+        TraktActions::Add => {
+            let result = trakt.add_season_to_watched(imdb_url, season).await?;
+            let episodes = WatchedEpisode::insert_season(pool, imdb_url, season).await?;
+            format!("{} added {} episodes", result, episodes.len())
+        }
+        TraktActions::Remove => {
+            let result = trakt.remove_season_to_watched(imdb_url, season).await?;
+            let deleted = WatchedEpisode::delete_season(pool, imdb_url, season).await?;
+            format!("{} removed {} episodes", result, deleted)
+        }
+        _ => "".to_string(),
+    }
+    .into();
+    Ok(body)
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Events")]
+struct PlexEventResponse(JsonBase<Vec<PlexEvent>, Error>);
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlexEventRequest {
+    pub start_timestamp: Option<DateTimeWrapper>,
+    pub event_type: Option<PlexEventType>,
+    pub offset: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[get("/list/plex_event")]
+pub async fn plex_events(
+    query: Query<PlexEventRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] user: LoggedUser,
+) -> WarpResult<PlexEventResponse> {
+    let query = query.into_inner();
+    let hidden_accounts =
+        UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email)
+            .await
+            .map_err(Into::<Error>::into)?;
+    let events = PlexEvent::get_events(
+        &state.db,
+        query.start_timestamp.map(Into::into),
+        query.event_type,
+        query.offset,
+        query.limit,
+        &hidden_accounts,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(events).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Events Sync")]
+struct PlexEventSyncResponse(JsonBase<PlexEventPage, Error>);
+
+#[get("/list/plex_event/sync")]
+pub async fn plex_events_sync(
+    query: Query<PlexEventSyncRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] user: LoggedUser,
+) -> WarpResult<PlexEventSyncResponse> {
+    let hidden_accounts =
+        UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email)
+            .await
+            .map_err(Into::<Error>::into)?;
+    let page = query
+        .into_inner()
+        .handle(&state.db, &hidden_accounts)
+        .await?;
+    Ok(JsonBase::new(page).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlexEventStatsRequest {
+    pub start_date: Option<NaiveDateWrapper>,
+    pub end_date: Option<NaiveDateWrapper>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Event Daily Stats")]
+struct PlexEventStatsResponse(JsonBase<Vec<PlexEventDailyCount>, Error>);
+
+#[get("/list/plex_event/stats")]
+pub async fn plex_event_stats(
+    query: Query<PlexEventStatsRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] user: LoggedUser,
+) -> WarpResult<PlexEventStatsResponse> {
+    let query = query.into_inner();
+    let end_date = query
+        .end_date
+        .map_or_else(|| Utc::now().naive_utc().date(), Into::into);
+    let start_date = query
+        .start_date
+        .map_or_else(|| end_date - chrono::Duration::days(30), Into::into);
+    let hidden_accounts =
+        UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email)
+            .await
+            .map_err(Into::<Error>::into)?;
+    let stats = PlexEventDailyCount::get_stats(&state.db, start_date, end_date, &hidden_accounts)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(stats).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlexEventUpdateRequest {
+    events: Vec<PlexEvent>,
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Plex Events",
+    content = "html",
+    status = "CREATED"
+)]
+struct PlexEventUpdateResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/plex_event")]
+pub async fn plex_events_update(
+    payload: Json<PlexEventUpdateRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<PlexEventUpdateResponse> {
+    let mut payload = payload.into_inner();
+    validate_rows(&mut payload.events)?;
+
+    for event in payload.events {
+        event
+            .write_event(&state.db)
+            .await
+            .map_err(Into::<Error>::into)?;
+    }
+
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Webhook", content = "html", status = "CREATED")]
+struct PlexWebhookResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/plex/webhook/{webhook_key}")]
+pub async fn plex_webhook(
+    #[filter = "rweb::multipart::form"] form: FormData,
+    #[data] state: AppState,
+    webhook_key: UuidWrapper,
+) -> WarpResult<PlexWebhookResponse> {
+    if state.config.plex_webhook_key == webhook_key.into() {
+        process_payload(form, &state)
+            .await
+            .map_err(Into::<Error>::into)?;
+    } else {
+        error!("Incorrect webhook key");
+    }
+    Ok(HtmlBase::new("").into())
+}
+
+async fn read_form_data(mut form: FormData) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buf = Vec::new();
+    if let Some(item) = form.next().await {
+        let mut stream = item?.stream();
+        while let Some(chunk) = stream.next().await {
+            buf.extend_from_slice(&chunk?.chunk());
+        }
+    }
+    Ok(buf)
+}
+
+async fn process_payload(form: FormData, state: &AppState) -> Result<(), anyhow::Error> {
+    let buf = read_form_data(form).await?;
+    if let Err(e) = handle_plex_payload(&buf, state).await {
+        let buf = String::from_utf8_lossy(&buf);
+        error!("failed to process payload {} {}", e, buf);
+        WebhookFailure::record(&state.db, PLEX_WEBHOOK_SOURCE, &buf, &e.to_string()).await?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+async fn handle_plex_payload(buf: &[u8], state: &AppState) -> Result<(), anyhow::Error> {
+    let payload: WebhookPayload = serde_json::from_slice(buf)?;
+    if let Err(e) = PlexMetadata::update_from_payload(&state.db, &payload).await {
+        error!("failed to update plex metadata {:?}", e);
+    }
+    let event: PlexEvent = payload.try_into()?;
+    event.write_event(&state.db).await?;
+    state.metrics.fire_plex_event(event);
+    Ok(())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Failed Plex Webhooks", content = "html")]
+struct PlexWebhookFailuresResponse(HtmlBase<String, Error>);
+
+#[get("/list/plex/failures")]
+pub async fn plex_webhook_failures(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexWebhookFailuresResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let failures = WebhookFailure::get_pending(&state.db, PLEX_WEBHOOK_SOURCE)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = WebhookFailure::get_html_table(&failures).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Replay Failed Plex Webhook", content = "html")]
+struct PlexWebhookReplayResponse(HtmlBase<String, Error>);
+
+#[post("/list/plex/failures/{id}/replay")]
+pub async fn plex_webhook_replay(
+    id: i32,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexWebhookReplayResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let failure = WebhookFailure::get_by_id(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No webhook failure {}", id).into()))?;
+    match replay_plex_failure(&failure, &state)
+        .await
+        .map_err(Into::<Error>::into)?
+    {
+        None => Ok(HtmlBase::new("Success".into()).into()),
+        Some(error) => Err(Error::BadRequest(error).into()),
+    }
+}
+
+async fn replay_plex_failure(
+    failure: &WebhookFailure,
+    state: &AppState,
+) -> Result<Option<StackString>, anyhow::Error> {
+    match handle_plex_payload(failure.payload.as_bytes(), state).await {
+        Ok(_) => {
+            failure.mark_replayed(&state.db).await?;
+            Ok(None)
+        }
+        Err(e) => {
+            let error = e.to_string();
+            failure.update_error(&state.db, &error).await?;
+            Ok(Some(error.into()))
+        }
+    }
+}
+
+pub async fn retry_plex_webhook_failures(state: &AppState) -> Result<usize, anyhow::Error> {
+    let failures = WebhookFailure::get_retryable(&state.db, PLEX_WEBHOOK_SOURCE).await?;
+    let mut replayed = 0;
+    for failure in &failures {
+        match replay_plex_failure(failure, state).await? {
+            None => replayed += 1,
+            Some(error) => debug!("plex webhook {} still failing {}", failure.id, error),
+        }
+    }
+    Ok(replayed)
+}
+
+async fn get_now_playing(state: &AppState, user: &LoggedUser) -> HttpResult<Vec<NowPlaying>> {
+    let mut clients: Vec<_> = PlexServer::get_all(&state.db)
+        .await?
+        .iter()
+        .map(PlexClient::from_server)
+        .collect();
+    let client = PlexClient::new(&state.config);
+    if client.is_configured() {
+        clients.push(client);
+    }
+    if clients.is_empty() {
+        return Err(PlexError::NotConfigured.into());
+    }
+    let futures = clients.iter().map(PlexClient::get_sessions);
+    let sessions = try_join_all(futures).await?;
+    let hidden_accounts =
+        UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email).await?;
+    Ok(NowPlaying::filter_hidden(
+        sessions.into_iter().flatten().collect(),
+        &hidden_accounts,
+    ))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Now Playing", content = "html")]
+struct PlexNowPlayingResponse(HtmlBase<String, Error>);
+
+#[get("/list/plex/now_playing")]
+pub async fn plex_now_playing(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexNowPlayingResponse> {
+    let sessions = get_now_playing(&state, &user).await?;
+    let body = NowPlaying::get_html_table(&sessions).into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Now Playing Sessions")]
+struct PlexNowPlayingJsonResponse(JsonBase<Vec<NowPlaying>, Error>);
+
+#[get("/list/plex/now_playing.json")]
+pub async fn plex_now_playing_json(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexNowPlayingJsonResponse> {
+    let sessions = get_now_playing(&state, &user).await?;
+    Ok(JsonBase::new(sessions).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Plex Servers")]
+struct PlexServersResponse(JsonBase<Vec<PlexServer>, Error>);
+
+#[get("/list/plex/servers")]
+pub async fn plex_servers(
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexServersResponse> {
+    let servers = PlexServer::get_all(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(servers).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct PlexServerRequest {
+    pub server_id: StackString,
+    pub name: StackString,
+    pub host: StackString,
+    pub token: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Saved Plex Server", status = "CREATED")]
+struct PlexServerUpdateResponse(JsonBase<PlexServer, Error>);
+
+#[post("/list/plex/servers")]
+pub async fn plex_servers_update(
+    payload: Json<PlexServerRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexServerUpdateResponse> {
+    let payload = payload.into_inner();
+    if payload.server_id.is_empty() || payload.name.is_empty() {
+        return Err(Error::BadRequest("server_id and name are required".into()).into());
+    }
+    let host = PlexServer::validate_host(&payload.host)
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let server = PlexServer::upsert(
+        &state.db,
+        &payload.server_id,
+        &payload.name,
+        host.as_str(),
+        &payload.token,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(server).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete Plex Server", content = "html")]
+struct PlexServerDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/plex/servers/{server_id}")]
+pub async fn plex_servers_delete(
+    server_id: StackString,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexServerDeleteResponse> {
+    let deleted = PlexServer::delete(&state.db, &server_id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No plex server {}", server_id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted plex server {}", server_id)).into())
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Continue Watching", content = "html")]
+struct PlexContinueWatchingResponse(HtmlBase<String, Error>);
+
+#[get("/list/plex/continue")]
+pub async fn plex_continue_watching(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<PlexContinueWatchingResponse> {
+    let entries = PlexMetadata::get_continue_watching(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let rows: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                r#"<tr><td><a href="javascript:updateMainArticle('/list/play/{}')">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+                entry.collection_idx,
+                entry.file_name(),
+                format_offset(entry.view_offset),
+                entry.get_html(),
+            )
+        })
+        .collect();
+    let body = format!(
+        r#"<table border="0"><tr><th>File</th><th>Position</th><th>Plex</th></tr>{}</table>"#,
+        rows.join("")
+    );
+    Ok(HtmlBase::new(body).into())
+}
+
+async fn tonight_picks(state: &AppState) -> HttpResult<TonightPicks> {
+    TonightPicks::get_picks(&state.mc).await.map_err(Into::into)
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Tonight's Picks")]
+struct TonightResponse(JsonBase<TonightPicks, Error>);
+
+#[get("/list/tonight")]
+pub async fn tonight(
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TonightResponse> {
+    let picks = tonight_picks(&state).await?;
+    Ok(JsonBase::new(picks).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Tonight's Picks Page", content = "html")]
+struct TonightHtmlResponse(HtmlBase<String, Error>);
+
+#[get("/list/tonight.html")]
+pub async fn tonight_html(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<TonightHtmlResponse> {
+    let picks = tonight_picks(&state).await?;
+    Ok(HtmlBase::new(picks.get_html().into()).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Next Unwatched Episode per Show")]
+struct UpNextResponse(JsonBase<UpNext, Error>);
+
+#[get("/list/up_next")]
+pub async fn up_next(
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UpNextResponse> {
+    let up_next = UpNext::get_up_next(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(up_next).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ViewingStatsRequest {
+    pub weeks: Option<i64>,
+}
+
+impl ViewingStatsRequest {
+    async fn handle(&self, state: &AppState, user: &LoggedUser) -> HttpResult<ViewingStats> {
+        let weeks = self.weeks.unwrap_or(DEFAULT_STATS_WEEKS).max(1);
+        let hidden_accounts =
+            UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email).await?;
+        ViewingStats::get_stats(&state.db, weeks, &hidden_accounts)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Viewing Statistics")]
+struct ViewingStatsResponse(JsonBase<ViewingStats, Error>);
+
+#[get("/list/stats")]
+pub async fn viewing_stats(
+    query: Query<ViewingStatsRequest>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ViewingStatsResponse> {
+    let stats = query.into_inner().handle(&state, &user).await?;
+    Ok(JsonBase::new(stats).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Viewing Statistics Page", content = "html")]
+struct ViewingStatsHtmlResponse(HtmlBase<String, Error>);
+
+#[get("/list/stats.html")]
+pub async fn viewing_stats_html(
+    query: Query<ViewingStatsRequest>,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ViewingStatsHtmlResponse> {
+    let stats = query.into_inner().handle(&state, &user).await?;
+    Ok(HtmlBase::new(stats.get_html().into()).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct SearchQuery {
+    pub q: StackString,
+    pub limit: Option<i64>,
+}
+
+impl SearchQuery {
+    async fn handle(&self, state: &AppState, user: &LoggedUser) -> HttpResult<SearchResults> {
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT);
+        let hidden_accounts =
+            UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email).await?;
+        SearchResults::search(&state.db, &self.q, limit, &hidden_accounts)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Search Results")]
+struct SearchResponse(JsonBase<SearchResults, Error>);
+
+#[get("/list/search")]
+pub async fn search(
+    query: Query<SearchQuery>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<SearchResponse> {
+    let results = query.into_inner().handle(&state, &user).await?;
+    Ok(JsonBase::new(results).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Search Results Page", content = "html")]
+struct SearchHtmlResponse(HtmlBase<String, Error>);
+
+#[get("/list/search.html")]
+pub async fn search_html(
+    query: Query<SearchQuery>,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<SearchHtmlResponse> {
+    let results = query.into_inner().handle(&state, &user).await?;
+    Ok(HtmlBase::new(results.get_html().into()).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Jellyfin Webhook", content = "html", status = "CREATED")]
+struct JellyfinWebhookResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/jellyfin/webhook/{webhook_key}")]
+pub async fn jellyfin_webhook(
+    #[filter = "rweb::body::bytes"] body: Bytes,
+    #[data] state: AppState,
+    webhook_key: UuidWrapper,
+) -> WarpResult<JellyfinWebhookResponse> {
+    if state.config.jellyfin_webhook_key == webhook_key.into() {
+        process_jellyfin_payload(&body, &state)
+            .await
+            .map_err(Into::<Error>::into)?;
+    } else {
+        error!("Incorrect webhook key");
+    }
+    Ok(HtmlBase::new("").into())
+}
+
+async fn process_jellyfin_payload(buf: &[u8], state: &AppState) -> Result<(), anyhow::Error> {
+    if let Ok(mut event) = JellyfinEvent::get_from_payload(buf) {
+        let client = JellyfinClient::new(&state.config);
+        if let Err(e) = event.link_metadata(&client, &state.db).await {
+            error!("failed to link jellyfin metadata {}", e);
+        }
+        event.write_event(&state.db).await?;
+        Ok(())
+    } else {
+        let buf = std::str::from_utf8(buf)?;
+        error!("failed deserialize {}", buf);
+        Err(format_err!("failed deserialize {}", buf))
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Trakt Webhook", content = "html", status = "CREATED")]
+struct TraktWebhookResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/trakt/webhook/{webhook_key}")]
+pub async fn trakt_webhook(
+    #[filter = "rweb::body::bytes"] body: Bytes,
+    #[data] state: AppState,
+    webhook_key: UuidWrapper,
+) -> WarpResult<TraktWebhookResponse> {
+    if state.config.trakt_webhook_key == webhook_key.into() {
+        process_trakt_payload(&body, &state)
+            .await
+            .map_err(Into::<Error>::into)?;
+    } else {
+        error!("Incorrect webhook key");
+    }
+    Ok(HtmlBase::new("").into())
+}
+
+async fn process_trakt_payload(buf: &[u8], state: &AppState) -> Result<(), anyhow::Error> {
+    let result = match TraktWebhookPayload::get_from_payload(buf) {
+        Ok(payload) => payload.record(&state.db).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(Some(watch)) => {
+            debug!("trakt webhook recorded {:?}", watch);
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(e) => {
+            let buf = String::from_utf8_lossy(buf);
+            error!("failed to process trakt payload {} {}", e, buf);
+            WebhookFailure::record(&state.db, TRAKT_WEBHOOK_SOURCE, &buf, &e.to_string()).await?;
+            Err(e)
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Jellyfin Events")]
+struct JellyfinEventResponse(JsonBase<Vec<JellyfinEvent>, Error>);
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct JellyfinEventRequest {
+    pub start_timestamp: Option<DateTimeWrapper>,
+    pub event_type: Option<StackString>,
+    pub offset: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[get("/list/jellyfin_event")]
+pub async fn jellyfin_events(
+    query: Query<JellyfinEventRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] user: LoggedUser,
+) -> WarpResult<JellyfinEventResponse> {
+    let query = query.into_inner();
+    let hidden_accounts =
+        UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email)
+            .await
+            .map_err(Into::<Error>::into)?;
+    let events = JellyfinEvent::get_events(
+        &state.db,
+        query.start_timestamp.map(Into::into),
+        query.event_type,
+        query.offset,
+        query.limit,
+        &hidden_accounts,
     )
-    .into();
-    Ok(entries)
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(events).into())
 }
 
-#[allow(clippy::too_many_arguments)]
-pub async fn watched_action_http_worker(
-    trakt: &TraktConnection,
-    pool: &PgPool,
-    action: TraktActions,
-    imdb_url: &str,
+#[derive(RwebResponse)]
+#[response(description = "Intro Markers")]
+struct IntroMarkersResponse(JsonBase<Vec<IntroMarker>, Error>);
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct IntroMarkersRequest {
+    pub show: Option<StackString>,
+}
+
+#[get("/list/intro_markers")]
+pub async fn intro_markers(
+    query: Query<IntroMarkersRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<IntroMarkersResponse> {
+    let query = query.into_inner();
+    let show = query.show.as_ref().map(StackString::as_str);
+    let markers = IntroMarker::get_markers(&state.db, show)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(markers).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct IntroMarkersUpdateRequest {
+    markers: Vec<IntroMarker>,
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Intro Markers",
+    content = "html",
+    status = "CREATED"
+)]
+struct IntroMarkersUpdateResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/intro_markers")]
+pub async fn intro_markers_update(
+    payload: Json<IntroMarkersUpdateRequest>,
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<IntroMarkersUpdateResponse> {
+    let payload = payload.into_inner();
+
+    for marker in &payload.markers {
+        marker
+            .validate()
+            .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    }
+    for marker in payload.markers {
+        marker
+            .upsert_marker(&state.db)
+            .await
+            .map_err(Into::<Error>::into)?;
+        marker
+            .apply_to_collection(&state.db)
+            .await
+            .map_err(Into::<Error>::into)?;
+    }
+
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete Intro Marker", content = "html")]
+struct IntroMarkersDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/intro_markers/{show}/{season}")]
+pub async fn intro_markers_delete(
+    show: StackString,
     season: i32,
-    episode: i32,
-    config: &Config,
-    stdout: &StdoutChannel<StackString>,
-) -> HttpResult<StackString> {
-    let mc = MovieCollection::new(config, pool, stdout);
-    let imdb_url = Arc::new(imdb_url.to_owned());
-    trakt.init().await;
-    let body = match action {
-        TraktActions::Add => {
-            let result = if season != -1 && episode != -1 {
-                trakt
-                    .add_episode_to_watched(&imdb_url, season, episode)
-                    .await?
-            } else {
-                trakt.add_movie_to_watched(&imdb_url).await?
-            };
-            if season != -1 && episode != -1 {
-                WatchedEpisode {
-                    imdb_url: imdb_url.to_string().into(),
-                    season,
-                    episode,
-                    ..WatchedEpisode::default()
-                }
-                .insert_episode(&mc.pool)
-                .await?;
-            } else {
-                WatchedMovie {
-                    imdb_url: imdb_url.to_string().into(),
-                    title: "".into(),
-                }
-                .insert_movie(&mc.pool)
-                .await?;
-            }
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<IntroMarkersDeleteResponse> {
+    let deleted = IntroMarker::delete_marker(&state.db, &show, season)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let msg = format!("intro marker for {} season {}", show, season);
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No {}", msg).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted {}", msg)).into())
+    }
+}
 
-            format!("{}", result)
-        }
-        TraktActions::Remove => {
-            let imdb_url_ = Arc::clone(&imdb_url);
-            let result = if season != -1 && episode != -1 {
-                trakt
-                    .remove_episode_to_watched(&imdb_url_, season, episode)
-                    .await?
-            } else {
-                trakt.remove_movie_to_watched(&imdb_url_).await?
-            };
+#[derive(RwebResponse)]
+#[response(description = "Scan Exclusions")]
+struct ScanExclusionsResponse(JsonBase<ScanExclusions, Error>);
 
-            if season != -1 && episode != -1 {
-                if let Some(epi_) =
-                    WatchedEpisode::get_watched_episode(&mc.pool, &imdb_url, season, episode)
-                        .await?
-                {
-                    epi_.delete_episode(&mc.pool).await?;
-                }
-            } else if let Some(movie) = WatchedMovie::get_watched_movie(&mc.pool, &imdb_url).await?
-            {
-                movie.delete_movie(&mc.pool).await?;
-            };
+#[get("/list/scan_exclusions")]
+pub async fn scan_exclusions(
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<ScanExclusionsResponse> {
+    let exclusions = ScanExclusions::load(&state.config, &state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(exclusions).into())
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Update Scan Exclusions",
+    content = "html",
+    status = "CREATED"
+)]
+struct ScanExclusionsUpdateResponse(HtmlBase<&'static str, Error>);
+
+#[post("/list/scan_exclusions")]
+pub async fn scan_exclusions_update(
+    payload: Json<ScanExclusions>,
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<ScanExclusionsUpdateResponse> {
+    let payload = payload.into_inner();
+    ScanExclusions::set_db_globs(&state.db, &payload.globs)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(HtmlBase::new("Success").into())
+}
+
+#[derive(Serialize, Debug, Schema)]
+pub struct ScanExclusionSkipped {
+    pub rule: StackString,
+    pub paths: Vec<StackString>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Files Skipped By Scan Exclusions")]
+struct ScanExclusionsReportResponse(JsonBase<Vec<ScanExclusionSkipped>, Error>);
+
+#[get("/list/scan_exclusions/report")]
+pub async fn scan_exclusions_report(
+    #[data] state: AppState,
+    #[filter = "api_user"] _: LoggedUser,
+) -> WarpResult<ScanExclusionsReportResponse> {
+    let exclusions = ScanExclusions::load(&state.config, &state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let config = state.config.clone();
+    let skipped = spawn_blocking(move || exclusions.skipped_report(&config))
+        .await
+        .map_err(|e| Error::AnyhowError(e.into()))?
+        .map_err(Into::<Error>::into)?;
+    let report = skipped
+        .into_iter()
+        .map(|(rule, paths)| {
+            let paths = paths
+                .into_iter()
+                .map(|p| p.to_string_lossy().into_owned().into())
+                .collect();
+            ScanExclusionSkipped { rule, paths }
+        })
+        .collect();
+    Ok(JsonBase::new(report).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "User Hooks")]
+struct UserHooksResponse(JsonBase<Vec<UserHook>, Error>);
+
+#[get("/list/hooks")]
+pub async fn user_hooks(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserHooksResponse> {
+    let hooks = UserHook::get_hooks(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(hooks).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct UserHookRequest {
+    pub event: HookEvent,
+    pub url: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Created User Hook", status = "CREATED")]
+struct UserHookCreateResponse(JsonBase<UserHook, Error>);
+
+#[post("/list/hooks")]
+pub async fn user_hooks_create(
+    payload: Json<UserHookRequest>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserHookCreateResponse> {
+    let payload = payload.into_inner();
+    let url = UserHook::validate_url(&payload.url)
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    let hook = UserHook::insert_hook(&state.db, &user.email, payload.event, url.as_str())
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(hook).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete User Hook", content = "html")]
+struct UserHookDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/hooks/{id}")]
+pub async fn user_hooks_delete(
+    id: i32,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserHookDeleteResponse> {
+    let deleted = UserHook::delete_hook(&state.db, &user.email, id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No hook {}", id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted hook {}", id)).into())
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Notification Preferences")]
+struct UserNotificationsResponse(JsonBase<Vec<NotificationPreference>, Error>);
+
+#[get("/list/notifications")]
+pub async fn user_notifications(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserNotificationsResponse> {
+    let prefs = NotificationPreference::get_preferences(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(prefs).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct NotificationPreferenceRequest {
+    pub channel: NotificationChannel,
+    pub target: Option<StackString>,
+    pub new_episodes: Option<bool>,
+    pub transcode_finished: Option<bool>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Updated Notification Preference")]
+struct UserNotificationUpdateResponse(JsonBase<NotificationPreference, Error>);
+
+#[post("/list/notifications")]
+pub async fn user_notifications_update(
+    payload: Json<NotificationPreferenceRequest>,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserNotificationUpdateResponse> {
+    let payload = payload.into_inner();
+    if !Notifier::new(&state.config).has_channel(payload.channel) {
+        let msg = format!("Channel {} not configured", payload.channel);
+        return Err(Error::BadRequest(msg.into()).into());
+    }
+    // Email defaults to the account address, telegram needs an explicit chat id
+    let target = match (payload.channel, payload.target) {
+        (_, Some(target)) => target,
+        (NotificationChannel::Email, None) => user.email.clone(),
+        (NotificationChannel::Telegram, None) => {
+            return Err(Error::BadRequest("Telegram requires a chat id".into()).into());
+        }
+    };
+    let pref = NotificationPreference::upsert(
+        &state.db,
+        &user.email,
+        payload.channel,
+        &target,
+        payload.new_episodes.unwrap_or(true),
+        payload.transcode_finished.unwrap_or(false),
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(pref).into())
+}
 
-            format!("{}", result)
-        }
-        _ => "".to_string(),
+#[derive(RwebResponse)]
+#[response(description = "Delete Notification Preference", content = "html")]
+struct UserNotificationDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/notifications/{id}")]
+pub async fn user_notifications_delete(
+    id: i32,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserNotificationDeleteResponse> {
+    let deleted = NotificationPreference::delete(&state.db, &user.email, id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No notification {}", id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted notification {}", id)).into())
     }
-    .into();
-    Ok(body)
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Plex Events")]
-struct PlexEventResponse(JsonBase<Vec<PlexEvent>, Error>);
+#[response(description = "Alert Rules")]
+struct AlertRulesResponse(JsonBase<Vec<AlertRule>, Error>);
+
+#[get("/list/alerts/rules")]
+pub async fn alert_rules(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<AlertRulesResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let rules = AlertRule::get_all(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(rules).into())
+}
 
 #[derive(Serialize, Deserialize, Debug, Schema)]
-pub struct PlexEventRequest {
-    pub start_timestamp: Option<DateTimeWrapper>,
-    pub event_type: Option<PlexEventType>,
-    pub offset: Option<u64>,
-    pub limit: Option<u64>,
+pub struct AlertRuleRequest {
+    pub name: StackString,
+    pub kind: AlertKind,
+    pub threshold: i64,
+    pub enabled: Option<bool>,
 }
 
-#[get("/list/plex_event")]
-pub async fn plex_events(
-    query: Query<PlexEventRequest>,
+#[derive(RwebResponse)]
+#[response(description = "Updated Alert Rule")]
+struct AlertRuleUpdateResponse(JsonBase<AlertRule, Error>);
+
+#[post("/list/alerts/rules")]
+pub async fn alert_rules_update(
+    payload: Json<AlertRuleRequest>,
+    #[filter = "api_user"] user: LoggedUser,
     #[data] state: AppState,
-    #[cookie = "jwt"] _: LoggedUser,
-) -> WarpResult<PlexEventResponse> {
-    let query = query.into_inner();
-    let events = PlexEvent::get_events(
+) -> WarpResult<AlertRuleUpdateResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let payload = payload.into_inner();
+    if payload.threshold < 0 {
+        return Err(Error::BadRequest("Threshold must not be negative".into()).into());
+    }
+    let rule = AlertRule::upsert(
         &state.db,
-        query.start_timestamp.map(Into::into),
-        query.event_type,
-        query.offset,
-        query.limit,
+        &payload.name,
+        payload.kind,
+        payload.threshold,
+        payload.enabled.unwrap_or(true),
     )
     .await
     .map_err(Into::<Error>::into)?;
-    Ok(JsonBase::new(events).into())
+    Ok(JsonBase::new(rule).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete Alert Rule", content = "html")]
+struct AlertRuleDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/alerts/rules/{id}")]
+pub async fn alert_rules_delete(
+    id: i32,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<AlertRuleDeleteResponse> {
+    if !UserPreferences::is_admin(&state.config, &user.email) {
+        return Err(Error::Forbidden.into());
+    }
+    let deleted = AlertRule::delete(&state.db, id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No alert rule {}", id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted alert rule {}", id)).into())
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "API Tokens")]
+struct ApiTokensResponse(JsonBase<Vec<ApiToken>, Error>);
+
+#[get("/list/api_tokens")]
+pub async fn api_tokens(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ApiTokensResponse> {
+    let tokens = ApiToken::get_by_email(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(tokens).into())
 }
 
 #[derive(Serialize, Deserialize, Debug, Schema)]
-pub struct PlexEventUpdateRequest {
-    events: Vec<PlexEvent>,
+pub struct ApiTokenRequest {
+    pub name: StackString,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ApiTokenCreated {
+    pub id: i32,
+    pub name: StackString,
+    pub token: StackString,
+    pub created_at: DateTimeWrapper,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Created API Token", status = "CREATED")]
+struct ApiTokenCreateResponse(JsonBase<ApiTokenCreated, Error>);
+
+#[post("/list/api_tokens")]
+pub async fn api_tokens_create(
+    payload: Json<ApiTokenRequest>,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ApiTokenCreateResponse> {
+    let name = payload.into_inner().name;
+    if name.trim().is_empty() {
+        return Err(Error::BadRequest("Token name is required".into()).into());
+    }
+    let (api_token, token) = ApiToken::create(&state.db, &user.email, name.trim())
+        .await
+        .map_err(Into::<Error>::into)?;
+    fill_api_tokens(&state.db).await?;
+    Ok(JsonBase::new(ApiTokenCreated {
+        id: api_token.id,
+        name: api_token.name,
+        token,
+        created_at: api_token.created_at,
+    })
+    .into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete API Token", content = "html")]
+struct ApiTokenDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/api_tokens/{id}")]
+pub async fn api_tokens_delete(
+    id: i32,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ApiTokenDeleteResponse> {
+    let deleted = ApiToken::delete(&state.db, &user.email, id)
+        .await
+        .map_err(Into::<Error>::into)?;
+    fill_api_tokens(&state.db).await?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("No api token {}", id).into()).into())
+    } else {
+        Ok(HtmlBase::new(format!("Deleted api token {}", id)).into())
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Save Offline", content = "html", status = "CREATED")]
+struct OfflineSaveResponse(HtmlBase<String, Error>);
+
+#[post("/list/offline/{collection_idx}")]
+pub async fn offline_save(
+    collection_idx: i32,
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<OfflineSaveResponse> {
+    let req = MoviePathRequest {
+        idx: collection_idx,
+    };
+    let (movie_path, _) = req.handle(&state.mc).await?;
+    let input_path = path::Path::new(movie_path.as_str());
+    let offline = OfflineFile::new(
+        &state.config,
+        &user.email,
+        collection_idx,
+        input_path,
+        &*state.clock,
+        &*state.id_gen,
+    )
+    .map_err(Into::<Error>::into)?;
+    offline
+        .insert(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+
+    let transcode_service = TranscodeService::new(
+        &state.config,
+        &state.config.transcode_queue,
+        &state.db,
+        &state.stdout,
+    );
+    let req = offline.transcode_request(input_path);
+    transcode_service
+        .publish_transcode_job(&req, |_| async move { Ok(()) })
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body: String = req
+        .publish_to_cli(&state.config)
+        .await
+        .map_err(Into::<Error>::into)?
+        .into();
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Collection Scan Status", content = "html")]
+struct ScanStatusResponse(HtmlBase<String, Error>);
+
+#[get("/list/scan/status")]
+pub async fn scan_status(
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ScanStatusResponse> {
+    let entries = ScanHistory::get_recent(&state.db, SCAN_HISTORY_LIMIT)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = ScanHistory::get_html_table(&entries, ScanHistory::is_running()).into();
+    Ok(HtmlBase::new(body).into())
 }
 
 #[derive(RwebResponse)]
 #[response(
-    description = "Update Plex Events",
+    description = "Trigger Collection Scan",
     content = "html",
     status = "CREATED"
 )]
-struct PlexEventUpdateResponse(HtmlBase<&'static str, Error>);
+struct ScanTriggerResponse(HtmlBase<&'static str, Error>);
 
-#[post("/list/plex_event")]
-pub async fn plex_events_update(
-    payload: Json<PlexEventUpdateRequest>,
-    #[data] state: AppState,
+#[post("/list/scan/trigger")]
+pub async fn scan_trigger(
     #[cookie = "jwt"] _: LoggedUser,
-) -> WarpResult<PlexEventUpdateResponse> {
-    let payload = payload.into_inner();
+    #[data] state: AppState,
+) -> WarpResult<ScanTriggerResponse> {
+    if ScanHistory::is_running() {
+        return Err(Error::BadRequest("Collection scan already running".into()).into());
+    }
+    tokio::task::spawn(async move {
+        if let Err(e) = ScanHistory::run_scan(&state.mc, "manual").await {
+            error!("collection scan failed {}", e);
+        }
+    });
+    Ok(HtmlBase::new("Scan started").into())
+}
 
-    for event in payload.events {
-        event
-            .write_event(&state.db)
-            .await
-            .map_err(Into::<Error>::into)?;
+#[derive(RwebResponse)]
+#[response(description = "Offline Files", content = "html")]
+struct OfflineListResponse(HtmlBase<String, Error>);
+
+#[get("/list/offline")]
+pub async fn offline_list(
+    #[cookie = "jwt"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<OfflineListResponse> {
+    OfflineFile::cleanup_expired(&state.db, &*state.clock)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let entries = OfflineFile::get_by_email(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let mut rows = Vec::new();
+    for entry in entries {
+        let (size, link) = if let Some(size) = entry.file_size().await {
+            (
+                format!("{:.1} MB", size as f64 / 1e6),
+                format!(
+                    r#"<a href="{}" download>{}</a>"#,
+                    entry.download_url(),
+                    entry.file_name()
+                ),
+            )
+        } else {
+            ("pending".to_string(), entry.file_name().to_string())
+        };
+        rows.push(format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            link,
+            size,
+            entry.expires_at.format("%Y-%m-%d %H:%M")
+        ));
     }
+    let body = format!(
+        r#"<table border="0"><tr><th>File</th><th>Size</th><th>Expires</th></tr>{}</table>"#,
+        rows.join("")
+    );
+    Ok(HtmlBase::new(body).into())
+}
 
-    Ok(HtmlBase::new("Success").into())
+#[derive(RwebResponse)]
+#[response(description = "User Watched")]
+struct UserWatchedResponse(JsonBase<Vec<UserWatched>, Error>);
+
+#[get("/list/watched")]
+pub async fn user_watched(
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserWatchedResponse> {
+    let watched = UserWatched::get_by_email(&state.db, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(watched).into())
 }
 
 #[derive(RwebResponse)]
-#[response(description = "Plex Webhook", content = "html", status = "CREATED")]
-struct PlexWebhookResponse(HtmlBase<&'static str, Error>);
+#[response(description = "Set User Watched", status = "CREATED")]
+struct UserWatchedSetResponse(JsonBase<UserWatched, Error>);
 
-#[post("/list/plex/webhook/{webhook_key}")]
-pub async fn plex_webhook(
-    #[filter = "rweb::multipart::form"] form: FormData,
+#[post("/list/watched/{collection_idx}")]
+pub async fn user_watched_set(
+    collection_idx: i32,
+    #[filter = "api_user"] user: LoggedUser,
     #[data] state: AppState,
-    webhook_key: UuidWrapper,
-) -> WarpResult<PlexWebhookResponse> {
-    if state.config.plex_webhook_key == webhook_key.into() {
-        process_payload(form, &state.db)
-            .await
-            .map_err(Into::<Error>::into)?;
+) -> WarpResult<UserWatchedSetResponse> {
+    let watched = UserWatched::set_watched(&state.db, &user.email, collection_idx)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No collection entry {}", collection_idx).into()))?;
+    Ok(JsonBase::new(watched).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Delete User Watched", content = "html")]
+struct UserWatchedDeleteResponse(HtmlBase<String, Error>);
+
+#[delete("/list/watched/{collection_idx}")]
+pub async fn user_watched_delete(
+    collection_idx: i32,
+    #[filter = "api_user"] user: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<UserWatchedDeleteResponse> {
+    let deleted = UserWatched::delete_watched(&state.db, &user.email, collection_idx)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if deleted == 0 {
+        Err(Error::NotFound(format!("{} not watched", collection_idx).into()).into())
     } else {
-        error!("Incorrect webhook key");
+        Ok(HtmlBase::new(format!("Unwatched {}", collection_idx)).into())
     }
-    Ok(HtmlBase::new("").into())
 }
 
-async fn process_payload(mut form: FormData, pool: &PgPool) -> Result<(), anyhow::Error> {
-    let mut buf = Vec::new();
-    if let Some(item) = form.next().await {
-        let mut stream = item?.stream();
-        while let Some(chunk) = stream.next().await {
-            buf.extend_from_slice(&chunk?.chunk());
-        }
+fn show_settings_body(
+    imdb: &ImdbRatings,
+    settings: &ShowSettings,
+    numbering: EpisodeNumbering,
+) -> String {
+    fn text_input(id: &str, value: Option<&StackString>) -> String {
+        format!(
+            r#"<tr><td>{id}</td><td><input type="text" id="{id}" value="{value}"/></td></tr>"#,
+            id = id,
+            value = value.map_or("", StackString::as_str)
+        )
     }
-    if let Ok(event) = PlexEvent::get_from_payload(&buf) {
-        event.write_event(pool).await?;
-        Ok(())
-    } else {
-        let buf = std::str::from_utf8(&buf)?;
-        error!("failed deserialize {}", buf);
-        Err(format_err!("failed deserialize {}", buf))
+    fn checkbox(id: &str, checked: bool) -> String {
+        format!(
+            r#"<tr><td>{id}</td><td><input type="checkbox" id="{id}" {checked}/></td></tr>"#,
+            id = id,
+            checked = if checked { "checked" } else { "" }
+        )
+    }
+    let source = imdb.source.unwrap_or(TvShowSource::All);
+    let max_height: Option<StackString> = settings.max_height.map(|h| h.to_string().into());
+    let source_options = TvShowSource::all()
+        .iter()
+        .map(|s| {
+            format!(
+                r#"<option value="{s}" {selected}>{s}</option>"#,
+                s = s,
+                selected = if *s == source { "selected" } else { "" }
+            )
+        })
+        .join("");
+    let ordering = settings.ordering.as_ref().map_or("", StackString::as_str);
+    let ordering_options = std::iter::once("")
+        .chain(EPISODE_ORDERINGS.iter().copied())
+        .map(|o| {
+            format!(
+                r#"<option value="{o}" {selected}>{o}</option>"#,
+                o = o,
+                selected = if o == ordering { "selected" } else { "" }
+            )
+        })
+        .join("");
+    let numbering_options = [EpisodeNumbering::Seasonal, EpisodeNumbering::Absolute]
+        .iter()
+        .map(|n| {
+            format!(
+                r#"<option value="{n}" {selected}>{n}</option>"#,
+                n = n,
+                selected = if *n == numbering { "selected" } else { "" }
+            )
+        })
+        .join("");
+    format!(
+        r#"
+        <a href="javascript:updateMainArticle('/list/tvshows')">Go Back</a><br>
+        {title}<br>
+        <table border="0">
+        {alias}{destination_dir}
+        <tr><td>ordering</td><td><select id="ordering">{ordering_options}</select></td></tr>
+        <tr><td>source</td><td><select id="source">{source_options}</select></td></tr>
+        <tr><td>numbering</td><td><select id="numbering">{numbering_options}</select></td></tr>
+        {auto_queue}{dropped}
+        {video_codec}{max_height}{audio_handling}
+        </table>
+        <button onclick="update_show_settings('{link}');">Save</button>
+        "#,
+        title = imdb.title.as_ref().unwrap_or(&imdb.show),
+        alias = text_input("alias", settings.alias.as_ref()),
+        destination_dir = text_input("destination_dir", settings.destination_dir.as_ref()),
+        ordering_options = ordering_options,
+        source_options = source_options,
+        numbering_options = numbering_options,
+        auto_queue = checkbox("auto_queue", settings.auto_queue),
+        dropped = checkbox("dropped", settings.dropped),
+        video_codec = text_input("video_codec", settings.video_codec.as_ref()),
+        max_height = text_input("max_height", max_height.as_ref()),
+        audio_handling = text_input("audio_handling", settings.audio_handling.as_ref()),
+        link = imdb.link,
+    )
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Show Settings", content = "html")]
+struct ShowSettingsResponse(HtmlBase<String, Error>);
+
+#[get("/list/show/{link}/settings")]
+pub async fn show_settings(
+    link: StackString,
+    #[cookie = "jwt"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ShowSettingsResponse> {
+    let imdb = ImdbRatings::get_show_by_link(&link, &state.db)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No show found for {}", link).into()))?;
+    let settings = ShowSettings::get_settings(&state.db, &imdb.link)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let numbering = EpisodeNumbering::get_for_show(&state.db, &imdb.show)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let body = show_settings_body(&imdb, &settings, numbering);
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Update Show Settings")]
+struct ShowSettingsUpdateResponse(JsonBase<ShowSettings, Error>);
+
+#[patch("/list/show/{link}/settings")]
+pub async fn show_settings_update(
+    link: StackString,
+    payload: Json<ShowSettingsPatch>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ShowSettingsUpdateResponse> {
+    let (_, settings) = ShowSettings::patch_settings(&state.db, &link, payload.into_inner())
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(settings).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct MediaIdRelinkRequest {
+    pub id_type: MediaIdType,
+    pub id_value: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Relink Show Ids")]
+struct ShowRelinkResponse(JsonBase<Vec<MediaId>, Error>);
+
+#[post("/list/show/{link}/relink")]
+pub async fn show_relink(
+    link: StackString,
+    payload: Json<MediaIdRelinkRequest>,
+    #[filter = "api_user"] _: LoggedUser,
+    #[data] state: AppState,
+) -> WarpResult<ShowRelinkResponse> {
+    let payload = payload.into_inner();
+    let imdb = ImdbRatings::get_show_by_link(&link, &state.db)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::NotFound(format!("No show found for {}", link).into()))?;
+    let media_id = MediaId {
+        show_id: imdb.index,
+        id_type: payload.id_type,
+        id_value: payload.id_value,
+    };
+    let linked = media_id
+        .relink(&state.db)
+        .await
+        .map_err(Into::<Error>::into)?;
+    if !linked {
+        let msg = format!(
+            "{} id {} is linked to another show",
+            media_id.id_type, media_id.id_value
+        );
+        return Err(Error::BadRequest(msg.into()).into());
     }
+    let ids = MediaId::get_ids(&state.db, imdb.index)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(ids).into())
+}
+
+#[derive(Serialize, Debug, Schema)]
+pub struct HealthStatus {
+    pub trakt_configured: bool,
+    pub trakt_queue_depth: u64,
+    pub trakt_last_429: Option<DateTimeWrapper>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Service Health")]
+struct HealthResponse(JsonBase<HealthStatus, Error>);
+
+#[get("/list/health")]
+pub async fn health(#[data] state: AppState) -> WarpResult<HealthResponse> {
+    let rate_limit = TraktConnection::rate_limit_status().await;
+    let status = HealthStatus {
+        trakt_configured: state.trakt.is_configured(),
+        trakt_queue_depth: rate_limit.queue_depth,
+        trakt_last_429: rate_limit.last_429,
+    };
+    Ok(JsonBase::new(status).into())
 }
@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use futures::stream;
+use hyper::Body;
+use rweb::{
+    filters::{method::get, BoxedFilter},
+    http::{
+        header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+        Response,
+    },
+    Filter, Reply,
+};
+use std::path::Path;
+use tokio::{fs::File, io::AsyncReadExt};
+
+use movie_collection_lib::offline_files::OfflineFile;
+
+use crate::{
+    errors::ServiceError as Error,
+    logged_user::{api_user, LoggedUser},
+    movie_queue_app::AppState,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn content_disposition(file_name: &str) -> HeaderValue {
+    let file_name: String = file_name
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    HeaderValue::from_str(&format!(r#"attachment; filename="{}""#, file_name))
+        .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+// Transcoded files can be several GB, so they are read a chunk at a time instead of
+// being buffered into the response
+async fn file_response(
+    path: &Path,
+    size: u64,
+    entry: &OfflineFile,
+) -> Result<Response<Body>, Error> {
+    let file = File::open(path).await?;
+    let chunks = stream::try_unfold(file, |mut file| async move {
+        let mut buf = vec![0_u8; CHUNK_SIZE];
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            Ok::<_, std::io::Error>(None)
+        } else {
+            buf.truncate(n);
+            Ok(Some((Bytes::from(buf), file)))
+        }
+    });
+    let mut response = Response::new(Body::wrap_stream(chunks));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(entry.content_type()));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(size));
+    headers.insert(CONTENT_DISPOSITION, content_disposition(&entry.file_name()));
+    Ok(response)
+}
+
+async fn offline_download_response(
+    token: String,
+    file_name: String,
+    user: LoggedUser,
+    state: AppState,
+) -> Result<Response<Body>, Error> {
+    let entry = OfflineFile::get_by_token(&state.db, &token)
+        .await?
+        .filter(|entry| entry.email == user.email && entry.file_name() == file_name)
+        .ok_or_else(|| Error::NotFound(format!("No offline file {}", token).into()))?;
+    if entry.is_expired(&*state.clock) {
+        entry.delete(&state.db).await?;
+        return Err(Error::BadRequest(
+            format!("Offline file {} expired", token).into(),
+        ));
+    }
+    let (path, size) = entry
+        .download_path(&state.config)
+        .await
+        .map_err(|e| Error::BadRequest(e.to_string().into()))?;
+    file_response(&path, size, &entry).await
+}
+
+pub fn offline_download(app: AppState) -> BoxedFilter<(impl Reply,)> {
+    rweb::path!("list" / "offline" / String / String)
+        .and(rweb::path::end())
+        .and(get())
+        .and(api_user())
+        .and_then(move |token: String, file_name: String, user: LoggedUser| {
+            let state = app.clone();
+            async move {
+                offline_download_response(token, file_name, user, state)
+                    .await
+                    .map_err(rweb::reject::custom)
+            }
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offline_stream::content_disposition;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition(r#"Some "Show" s01e01.mp4"#),
+            r#"attachment; filename="Some _Show_ s01e01.mp4""#
+        );
+        assert_eq!(
+            content_disposition("caf\u{e9}.mp4"),
+            r#"attachment; filename="caf_.mp4""#
+        );
+    }
+}
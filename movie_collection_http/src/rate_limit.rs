@@ -0,0 +1,148 @@
+use parking_lot::Mutex;
+use rweb::{
+    filters::{addr, cookie, header},
+    Filter, Rejection,
+};
+use stack_string::StackString;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Instant};
+
+use crate::{errors::ServiceError as Error, logged_user::resolve_user};
+
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token bucket per client key, `per_minute` of zero disables the limit
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Mutex<HashMap<StackString, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        Self {
+            capacity: f64::from(burst.max(1)),
+            refill_per_second: f64::from(per_minute) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn check_at(&self, key: &str, now: Instant) -> bool {
+        if self.refill_per_second <= 0.0 {
+            return true;
+        }
+        let refill = |b: &Bucket| {
+            let elapsed = now.saturating_duration_since(b.updated).as_secs_f64();
+            (b.tokens + elapsed * self.refill_per_second).min(self.capacity)
+        };
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_BUCKETS {
+            // Full buckets are indistinguishable from new ones, no need to keep them
+            buckets.retain(|_, b| refill(b) < self.capacity);
+        }
+        let bucket = buckets.entry(key.into()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+}
+
+// The app listens on localhost behind a proxy, so prefer the forwarded client address.
+// Only the last entry is appended by the proxy, anything before it comes from the client.
+fn client_ip(forwarded: Option<&str>, remote: Option<SocketAddr>) -> Option<StackString> {
+    forwarded
+        .and_then(|f| f.rsplit(',').next())
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(Into::into)
+        .or_else(|| remote.map(|r| r.ip().to_string().into()))
+}
+
+pub fn rate_limit(
+    limiter: Arc<RateLimiter>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    header::optional::<String>("x-forwarded-for")
+        .and(addr::remote())
+        .and(header::optional::<String>("authorization"))
+        .and(cookie::optional::<String>("jwt"))
+        .and_then(
+            move |forwarded: Option<String>,
+                  remote: Option<SocketAddr>,
+                  auth: Option<String>,
+                  jwt: Option<String>| {
+                let limiter = limiter.clone();
+                async move {
+                    let mut keys = Vec::new();
+                    if let Some(ip) = client_ip(forwarded.as_deref(), remote) {
+                        keys.push(format!("ip:{}", ip));
+                    }
+                    if let Ok(user) = resolve_user(auth.as_deref(), jwt.as_deref()) {
+                        keys.push(format!("user:{}", user.email));
+                    }
+                    if keys.iter().all(|key| limiter.check(key)) {
+                        Ok(())
+                    } else {
+                        Err(rweb::reject::custom(Error::TooManyRequests))
+                    }
+                }
+            },
+        )
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::rate_limit::{client_ip, RateLimiter};
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, 60);
+        let now = Instant::now();
+        assert!(limiter.check_at("ip:127.0.0.1", now));
+        assert!(limiter.check_at("ip:127.0.0.1", now));
+        assert!(!limiter.check_at("ip:127.0.0.1", now));
+        assert!(limiter.check_at("ip:10.0.0.1", now));
+        assert!(limiter.check_at("ip:127.0.0.1", now + Duration::from_secs(1)));
+        assert!(!limiter.check_at("ip:127.0.0.1", now + Duration::from_secs(1)));
+
+        let limiter = RateLimiter::new(1, 0);
+        assert!((0..10).all(|_| limiter.check_at("ip:127.0.0.1", now)));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        assert_eq!(
+            client_ip(Some("203.0.113.7"), None).as_deref(),
+            Some("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(Some("198.51.100.1, 203.0.113.7"), None).as_deref(),
+            Some("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(None, Some("127.0.0.1:3042".parse().unwrap())).as_deref(),
+            Some("127.0.0.1")
+        );
+        assert_eq!(client_ip(None, None), None);
+    }
+}
@@ -0,0 +1,179 @@
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rweb::{filters::trace::Info, Schema};
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{collections::VecDeque, fmt};
+use tracing::{
+    field::{Field, Visit},
+    info_span,
+    span::{Attributes, Id},
+    Event, Span, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::layer as fmt_layer,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+use uuid::Uuid;
+
+use movie_collection_lib::datetime_wrapper::DateTimeWrapper;
+
+const MAX_RECENT_EVENTS: usize = 1000;
+
+lazy_static! {
+    static ref RECENT_EVENTS: Mutex<VecDeque<RecentEvent>> =
+        Mutex::new(VecDeque::with_capacity(MAX_RECENT_EVENTS));
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct RecentEvent {
+    pub timestamp: DateTimeWrapper,
+    pub level: StackString,
+    pub target: StackString,
+    pub request_id: Option<StackString>,
+    pub message: StackString,
+}
+
+// Stored in the span extensions so events deeper in the call tree can find their request
+struct RequestId(StackString);
+
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    request_id: Option<StackString>,
+    fields: Vec<String>,
+}
+
+impl Visit for EventVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.into(),
+            "request_id" => self.request_id = Some(value.into()),
+            name => self.fields.push(format!("{}={}", name, value)),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            "request_id" => self.request_id = Some(format!("{:?}", value).into()),
+            name => self.fields.push(format!("{}={:?}", name, value)),
+        }
+    }
+}
+
+impl EventVisitor {
+    fn into_message(self) -> StackString {
+        if self.fields.is_empty() {
+            self.message.into()
+        } else {
+            format!("{} {}", self.message, self.fields.join(" ")).into()
+        }
+    }
+}
+
+pub struct RecentEventsLayer;
+
+impl<S> Layer<S> for RecentEventsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(request_id), Some(span)) = (visitor.request_id, ctx.span(id)) {
+            span.extensions_mut().insert(RequestId(request_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+        let request_id = visitor.request_id.take().or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<RequestId>().map(|r| r.0.clone()))
+        });
+        let metadata = event.metadata();
+        let recent = RecentEvent {
+            timestamp: Utc::now().into(),
+            level: metadata.level().as_str().into(),
+            target: metadata.target().into(),
+            request_id,
+            message: visitor.into_message(),
+        };
+        let mut events = RECENT_EVENTS.lock();
+        if events.len() >= MAX_RECENT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(recent);
+    }
+}
+
+// Most recent first
+pub fn recent_events(limit: usize) -> Vec<RecentEvent> {
+    RECENT_EVENTS
+        .lock()
+        .iter()
+        .rev()
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+pub fn init_tracing() {
+    Registry::default()
+        .with(EnvFilter::from_default_env())
+        .with(fmt_layer())
+        .with(RecentEventsLayer)
+        .init();
+}
+
+// Reuse an upstream X-Request-Id when the proxy sets one
+pub fn request_span(info: Info) -> Span {
+    let request_id: StackString = info
+        .request_headers()
+        .get("x-request-id")
+        .and_then(|h| h.to_str().ok())
+        .map_or_else(|| Uuid::new_v4().to_string().into(), Into::into);
+    info_span!(
+        "request",
+        request_id = %request_id,
+        method = %info.method(),
+        path = %info.path(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    use crate::request_tracing::{recent_events, RecentEventsLayer};
+
+    #[test]
+    fn test_recent_events_request_id() {
+        let subscriber = Registry::default().with(RecentEventsLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("request", request_id = "abc123");
+            let _guard = span.enter();
+            info!(idx = 5, "test_recent_events_request_id");
+        });
+        let event = recent_events(1000)
+            .into_iter()
+            .find(|e| e.message.starts_with("test_recent_events_request_id"))
+            .unwrap();
+        assert_eq!(
+            event.request_id.as_ref().map(|r| r.as_str()),
+            Some("abc123")
+        );
+        assert_eq!(
+            event.message.as_str(),
+            "test_recent_events_request_id idx=5"
+        );
+        assert_eq!(event.level.as_str(), "INFO");
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Error as AnyhowError;
+use bytes::Bytes;
+use futures::stream;
+use hyper::Body;
+use rweb::{
+    filters::{header, method::get, query::query, BoxedFilter},
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        Response,
+    },
+    Filter, Rejection, Reply,
+};
+use serde::Serialize;
+use std::future::Future;
+
+use movie_collection_lib::{
+    imdb_episodes::ImdbEpisodes,
+    imdb_ratings::ImdbRatings,
+    plex_events::PlexEvent,
+    sync_cursor::{SyncCursor, DEFAULT_SYNC_PAGE_SIZE},
+    user_preferences::UserPreferences,
+};
+
+use crate::{
+    errors::ServiceError as Error,
+    logged_user::{api_user, LoggedUser},
+    movie_queue_app::AppState,
+    movie_queue_requests::{ImdbEpisodesSyncRequest, ImdbRatingsSyncRequest, PlexEventSyncRequest},
+};
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+type SyncPage<T> = Result<(Vec<T>, Option<SyncCursor>), AnyhowError>;
+
+// Falls through to the paginated json routes unless the client asks for ndjson
+fn accepts_ndjson() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    header::optional::<String>("accept")
+        .and_then(|accept: Option<String>| async move {
+            match accept {
+                Some(accept) if accept.contains(NDJSON_CONTENT_TYPE) => Ok(()),
+                _ => Err(rweb::reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+async fn ndjson_chunk<T, Fut>(
+    page: Option<Fut>,
+) -> Result<Option<(Bytes, Option<SyncCursor>)>, AnyhowError>
+where
+    T: Serialize,
+    Fut: Future<Output = SyncPage<T>>,
+{
+    let (items, next_cursor) = match page {
+        Some(page) => page.await?,
+        None => return Ok(None),
+    };
+    let mut buf = Vec::new();
+    for item in &items {
+        serde_json::to_writer(&mut buf, item)?;
+        buf.push(b'\n');
+    }
+    Ok(Some((buf.into(), next_cursor)))
+}
+
+// Streams every row after the cursor, fetching a page at a time so memory use stays
+// bounded however many rows match
+fn ndjson_response<T, F, Fut>(cursor: SyncCursor, fetch_page: F) -> Response<Body>
+where
+    T: Serialize + Send + 'static,
+    F: Fn(SyncCursor) -> Fut + Send + 'static,
+    Fut: Future<Output = SyncPage<T>> + Send + 'static,
+{
+    let chunks = stream::try_unfold(Some(cursor), move |cursor| {
+        ndjson_chunk(cursor.map(&fetch_page))
+    });
+    let mut response = Response::new(Body::wrap_stream(chunks));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(NDJSON_CONTENT_TYPE));
+    response
+}
+
+pub fn sync_ndjson(app: AppState) -> BoxedFilter<(impl Reply,)> {
+    let state = app.clone();
+    let imdb_episodes = rweb::path!("list" / "imdb_episodes")
+        .and(rweb::path::end())
+        .and(get())
+        .and(accepts_ndjson())
+        .and(api_user())
+        .and(query::<ImdbEpisodesSyncRequest>())
+        .and_then(move |_: LoggedUser, req: ImdbEpisodesSyncRequest| {
+            let pool = state.db.clone();
+            async move {
+                let cursor = req.cursor().map_err(rweb::reject::custom)?;
+                Ok::<_, Rejection>(ndjson_response(cursor, move |cursor| {
+                    let pool = pool.clone();
+                    async move {
+                        ImdbEpisodes::get_episodes_page(&pool, cursor, DEFAULT_SYNC_PAGE_SIZE).await
+                    }
+                }))
+            }
+        });
+
+    let state = app.clone();
+    let imdb_ratings = rweb::path!("list" / "imdb_ratings")
+        .and(rweb::path::end())
+        .and(get())
+        .and(accepts_ndjson())
+        .and(api_user())
+        .and(query::<ImdbRatingsSyncRequest>())
+        .and_then(move |_: LoggedUser, req: ImdbRatingsSyncRequest| {
+            let pool = state.db.clone();
+            async move {
+                let cursor = req.cursor().map_err(rweb::reject::custom)?;
+                Ok::<_, Rejection>(ndjson_response(cursor, move |cursor| {
+                    let pool = pool.clone();
+                    async move {
+                        ImdbRatings::get_shows_page(&pool, cursor, DEFAULT_SYNC_PAGE_SIZE).await
+                    }
+                }))
+            }
+        });
+
+    let state = app;
+    let plex_events = rweb::path!("list" / "plex_event" / "sync")
+        .and(rweb::path::end())
+        .and(get())
+        .and(accepts_ndjson())
+        .and(api_user())
+        .and(query::<PlexEventSyncRequest>())
+        .and_then(move |user: LoggedUser, req: PlexEventSyncRequest| {
+            let state = state.clone();
+            async move {
+                let cursor = req.cursor().map_err(rweb::reject::custom)?;
+                let hidden_accounts =
+                    UserPreferences::get_hidden_accounts(&state.config, &state.db, &user.email)
+                        .await
+                        .map_err(|e| rweb::reject::custom(Error::from(e)))?;
+                let event_type = req.event_type;
+                Ok::<_, Rejection>(ndjson_response(cursor, move |cursor| {
+                    let pool = state.db.clone();
+                    let hidden_accounts = hidden_accounts.clone();
+                    async move {
+                        PlexEvent::get_events_page(
+                            &pool,
+                            cursor,
+                            event_type,
+                            DEFAULT_SYNC_PAGE_SIZE,
+                            &hidden_accounts,
+                        )
+                        .await
+                    }
+                }))
+            }
+        });
+
+    imdb_episodes
+        .or(imdb_ratings)
+        .unify()
+        .or(plex_events)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,262 @@
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use movie_collection_lib::{
+    imdb_episodes::ImdbEpisodes,
+    imdb_ratings::ImdbRatings,
+    movie_collection::MovieCollectionRow,
+    movie_queue::MovieQueueRow,
+    plex_events::{PlexEvent, PlexEventType},
+};
+
+use crate::errors::ServiceError as Error;
+
+pub const MAX_SEASON: i32 = 100;
+pub const MAX_EPISODE: i32 = 10000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct RejectedRow {
+    pub index: usize,
+    pub reason: StackString,
+}
+
+pub trait SyncValidate {
+    fn normalize(&mut self);
+
+    fn validate(&self) -> Result<(), StackString>;
+}
+
+pub fn validate_rows<T: SyncValidate>(rows: &mut [T]) -> Result<(), Error> {
+    let rejected: Vec<_> = rows
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, row)| {
+            row.normalize();
+            row.validate()
+                .err()
+                .map(|reason| RejectedRow { index, reason })
+        })
+        .collect();
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::UnprocessableEntity(rejected))
+    }
+}
+
+fn normalize_text(s: &mut StackString) {
+    if s.trim().len() != s.len() {
+        *s = s.trim().into();
+    }
+}
+
+fn normalize_show(show: &mut StackString) {
+    let normalized = show.trim().to_lowercase().replace(' ', "_");
+    if normalized != show.as_str() {
+        *show = normalized.into();
+    }
+}
+
+pub fn normalize_path(path: &str) -> StackString {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.trim().chars() {
+        if c == '/' && normalized.ends_with('/') {
+            continue;
+        }
+        normalized.push(c);
+    }
+    if normalized.len() > 1 && normalized.ends_with('/') {
+        normalized.pop();
+    }
+    normalized.into()
+}
+
+fn check_show(show: &str) -> Result<(), StackString> {
+    if show.is_empty() {
+        Err("empty show".into())
+    } else {
+        Ok(())
+    }
+}
+
+fn check_path(path: &str) -> Result<(), StackString> {
+    if !path.starts_with('/') {
+        Err(format!("path {} is not absolute", path).into())
+    } else if path.split('/').any(|p| p == "..") {
+        Err(format!("path {} contains ..", path).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn check_link(link: &str) -> Result<(), StackString> {
+    if link.is_empty() || link.contains(char::is_whitespace) {
+        Err(format!("invalid link {:?}", link).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn check_range(name: &str, value: i32, max: i32) -> Result<(), StackString> {
+    if (0..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{} {} out of range 0..={}", name, value, max).into())
+    }
+}
+
+impl SyncValidate for ImdbEpisodes {
+    fn normalize(&mut self) {
+        normalize_show(&mut self.show);
+        normalize_text(&mut self.title);
+        normalize_text(&mut self.eptitle);
+        normalize_text(&mut self.epurl);
+    }
+
+    fn validate(&self) -> Result<(), StackString> {
+        check_show(&self.show)?;
+        check_range("season", self.season, MAX_SEASON)?;
+        check_range("episode", self.episode, MAX_EPISODE)?;
+        if !self.rating.is_finite() || self.rating > 10.0 {
+            return Err(format!("rating {} out of range", self.rating).into());
+        }
+        Ok(())
+    }
+}
+
+impl SyncValidate for ImdbRatings {
+    fn normalize(&mut self) {
+        normalize_show(&mut self.show);
+        normalize_text(&mut self.link);
+        if let Some(title) = self.title.as_mut() {
+            normalize_text(title);
+        }
+    }
+
+    fn validate(&self) -> Result<(), StackString> {
+        check_show(&self.show)?;
+        check_link(&self.link)?;
+        if let Some(rating) = self.rating {
+            if !rating.is_finite() || rating > 10.0 {
+                return Err(format!("rating {} out of range", rating).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyncValidate for MovieQueueRow {
+    fn normalize(&mut self) {
+        normalize_show(&mut self.show);
+        self.path = normalize_path(&self.path);
+    }
+
+    fn validate(&self) -> Result<(), StackString> {
+        check_path(&self.path)?;
+        if self.idx < 0 || self.collection_idx <= 0 {
+            return Err(format!("invalid index {} {}", self.idx, self.collection_idx).into());
+        }
+        Ok(())
+    }
+}
+
+impl SyncValidate for MovieCollectionRow {
+    fn normalize(&mut self) {
+        normalize_show(&mut self.show);
+        self.path = normalize_path(&self.path);
+    }
+
+    fn validate(&self) -> Result<(), StackString> {
+        check_path(&self.path)?;
+        if self.idx <= 0 {
+            return Err(format!("invalid index {}", self.idx).into());
+        }
+        if let Some(credits_start) = self.credits_start {
+            if !credits_start.is_finite() || credits_start < 0.0 {
+                return Err(format!("credits_start {} out of range", credits_start).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyncValidate for PlexEvent {
+    fn normalize(&mut self) {
+        let event = self.event.trim().to_lowercase();
+        if event != self.event.as_str() {
+            self.event = event.into();
+        }
+        normalize_text(&mut self.account);
+        normalize_text(&mut self.server);
+        normalize_text(&mut self.player_title);
+        normalize_text(&mut self.player_address);
+    }
+
+    fn validate(&self) -> Result<(), StackString> {
+        if self.event.parse::<PlexEventType>().is_err() {
+            return Err(format!("unknown event {}", self.event).into());
+        }
+        if self.account.is_empty() || self.server.is_empty() {
+            return Err("empty account or server".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use movie_collection_lib::movie_collection::MovieCollectionRow;
+
+    use crate::{
+        errors::ServiceError,
+        sync_validation::{normalize_path, validate_rows},
+    };
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(" /tmp//television/mr_robot_s01_ep01.mp4 ").as_str(),
+            "/tmp/television/mr_robot_s01_ep01.mp4"
+        );
+        assert_eq!(normalize_path("/tmp/movies/").as_str(), "/tmp/movies");
+        assert_eq!(normalize_path("/").as_str(), "/");
+    }
+
+    #[test]
+    fn test_validate_rows() {
+        let mut rows = vec![
+            MovieCollectionRow {
+                idx: 1,
+                path: "/tmp//television/mr_robot_s01_ep01.mp4".into(),
+                show: " Mr Robot".into(),
+                ..MovieCollectionRow::default()
+            },
+            MovieCollectionRow {
+                idx: 2,
+                path: "television/mr_robot_s01_ep02.mp4".into(),
+                show: "mr_robot".into(),
+                ..MovieCollectionRow::default()
+            },
+            MovieCollectionRow {
+                idx: 0,
+                path: "/tmp/television/mr_robot_s01_ep03.mp4".into(),
+                show: "mr_robot".into(),
+                ..MovieCollectionRow::default()
+            },
+        ];
+        match validate_rows(&mut rows) {
+            Err(ServiceError::UnprocessableEntity(rejected)) => {
+                let indices: Vec<_> = rejected.iter().map(|r| r.index).collect();
+                assert_eq!(indices, vec![1, 2]);
+            }
+            _ => panic!("expected rejected rows"),
+        }
+        assert_eq!(rows[0].show.as_str(), "mr_robot");
+        assert_eq!(
+            rows[0].path.as_str(),
+            "/tmp/television/mr_robot_s01_ep01.mp4"
+        );
+        assert!(validate_rows(&mut rows[..1]).is_ok());
+    }
+}
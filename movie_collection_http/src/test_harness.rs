@@ -0,0 +1,209 @@
+use anyhow::{format_err, Error};
+use bytes::Bytes;
+use http::Response;
+use refinery::embed_migrations;
+use rweb::{test::request, Filter};
+use serde::Serialize;
+use stack_string::StackString;
+use std::env::var;
+use uuid::Uuid;
+
+use movie_collection_lib::{
+    config::Config, imdb_ratings::ImdbRatings, movie_collection::MovieCollection, pgpool::PgPool,
+    trakt_connection::TraktConnection,
+};
+
+use crate::{
+    errors::error_response,
+    logged_user::{
+        get_random_key, AuthorizedUser, Token, AUTHORIZED_USERS, JWT_SECRET, SECRET_KEY,
+    },
+    movie_queue_app::{get_full_path, AppState},
+};
+
+embed_migrations!("../migrations");
+
+pub struct TestApp {
+    pub state: AppState,
+    pub email: StackString,
+    pub show: ImdbRatings,
+    pub collection_path: StackString,
+    cookie: StackString,
+    admin_pool: PgPool,
+    dbname: StackString,
+}
+
+impl TestApp {
+    // Every run migrates a throwaway database on the TEST_PGURL server and signs its
+    // own tokens, returns None when TEST_PGURL is unset so the tests are skipped
+    pub async fn new() -> Result<Option<Self>, Error> {
+        let test_pgurl = match var("TEST_PGURL") {
+            Ok(pgurl) => pgurl,
+            Err(_) => return Ok(None),
+        };
+        let config = Config::with_config()?;
+        SECRET_KEY.set(get_random_key());
+        JWT_SECRET.set(get_random_key());
+
+        let suffix = Uuid::new_v4().to_simple().to_string();
+        let dbname: StackString = format!("test_harness_{}", &suffix[..8]).into();
+        let admin_pool = PgPool::new(&test_pgurl);
+        admin_pool
+            .get()
+            .await?
+            .execute(format!("CREATE DATABASE {}", dbname).as_str(), &[])
+            .await?;
+        let (server_url, _) = test_pgurl
+            .rsplit_once('/')
+            .ok_or_else(|| format_err!("Invalid TEST_PGURL {}", test_pgurl))?;
+        let pool = PgPool::new(&format!("{}/{}", server_url, dbname));
+        let mut conn = pool.get().await?;
+        migrations::runner().run_async(&mut **conn).await?;
+
+        let email: StackString = format!("test_{}@localhost", &suffix[..8]).into();
+        let user = AuthorizedUser {
+            email: email.clone(),
+            ..AuthorizedUser::default()
+        };
+        let mut users = AUTHORIZED_USERS.get_users();
+        users.push(email.clone());
+        AUTHORIZED_USERS.merge_users(&users)?;
+        let token = Token::create_token(&user, &config.domain, 3600)?;
+        let cookie = format!("jwt={}", token).into();
+
+        let trakt = TraktConnection::new(config.clone());
+        let mut state = AppState::new(config, pool, trakt)?;
+        state.mc = state.mc.without_hooks();
+
+        let show = ImdbRatings {
+            show: format!("test_harness_{}", &suffix[..8]).into(),
+            title: Some("Test Harness Show".into()),
+            link: format!("tt9{}", &suffix[..8]).into(),
+            rating: Some(5.0),
+            istv: Some(true),
+            ..ImdbRatings::default()
+        };
+        let collection_path = format!("/tmp/{}_s01_ep01.mp4", show.show).into();
+        let app = Self {
+            state,
+            email,
+            show,
+            collection_path,
+            cookie,
+            admin_pool,
+            dbname,
+        };
+        app.seed_fixtures().await?;
+        Ok(Some(app))
+    }
+
+    fn collection(&self) -> &MovieCollection {
+        &self.state.mc
+    }
+
+    async fn seed_fixtures(&self) -> Result<(), Error> {
+        self.show.insert_show(&self.state.db).await?;
+        self.collection()
+            .insert_into_collection(&self.collection_path, false)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn cleanup(&self) -> Result<(), Error> {
+        let users: Vec<_> = AUTHORIZED_USERS
+            .get_users()
+            .into_iter()
+            .filter(|u| u != &self.email)
+            .collect();
+        AUTHORIZED_USERS.merge_users(&users)?;
+        self.admin_pool
+            .get()
+            .await?
+            .execute(
+                format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.dbname).as_str(),
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, path: &str) -> Response<Bytes> {
+        let routes = get_full_path(&self.state).recover(error_response);
+        request()
+            .method("GET")
+            .path(path)
+            .header("cookie", self.cookie.as_str())
+            .reply(&routes)
+            .await
+    }
+
+    pub async fn get_anonymous(&self, path: &str) -> Response<Bytes> {
+        let routes = get_full_path(&self.state).recover(error_response);
+        request().method("GET").path(path).reply(&routes).await
+    }
+
+    pub async fn post_json<T: Serialize>(&self, path: &str, body: &T) -> Response<Bytes> {
+        self.send_json("POST", path, body).await
+    }
+
+    pub async fn patch_json<T: Serialize>(&self, path: &str, body: &T) -> Response<Bytes> {
+        self.send_json("PATCH", path, body).await
+    }
+
+    async fn send_json<T: Serialize>(&self, method: &str, path: &str, body: &T) -> Response<Bytes> {
+        let routes = get_full_path(&self.state).recover(error_response);
+        request()
+            .method(method)
+            .path(path)
+            .header("cookie", self.cookie.as_str())
+            .json(body)
+            .reply(&routes)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use maplit::hashmap;
+    use serde_json::Value;
+
+    use crate::test_harness::TestApp;
+
+    #[tokio::test]
+    async fn test_health_route() -> Result<(), Error> {
+        let app = match TestApp::new().await? {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        let resp = app.get_anonymous("/list/health").await;
+        app.cleanup().await?;
+        assert_eq!(resp.status().as_u16(), 200);
+        let status: Value = serde_json::from_slice(resp.body())?;
+        assert!(status.get("trakt_queue_depth").is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_show_settings_routes() -> Result<(), Error> {
+        let app = match TestApp::new().await? {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        let path = format!("/list/show/{}/settings", app.show.link);
+
+        let resp = app.get(&path).await;
+        let patch = hashmap! {"alias" => "harness", "numbering" => "absolute"};
+        let patch_resp = app.patch_json(&path, &patch).await;
+        app.cleanup().await?;
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains(app.show.link.as_str()));
+
+        assert_eq!(patch_resp.status().as_u16(), 200);
+        let settings: Value = serde_json::from_slice(patch_resp.body())?;
+        assert_eq!(settings["alias"], "harness");
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use chrono::{Duration, Utc};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, str::FromStr};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+use tracing::error;
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, notifications::Notifier, pgpool::PgPool,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum AlertKind {
+    // threshold is the number of hours without any plex event
+    #[serde(rename = "no_plex_events")]
+    NoPlexEvents,
+    // threshold is the number of failed transcode jobs over the last day
+    #[serde(rename = "transcode_failures")]
+    TranscodeFailures,
+    // threshold is the number of entries the latest scan may remove
+    #[serde(rename = "collection_shrank")]
+    CollectionShrank,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::NoPlexEvents => "no_plex_events",
+                Self::TranscodeFailures => "transcode_failures",
+                Self::CollectionShrank => "collection_shrank",
+            }
+        )
+    }
+}
+
+impl FromStr for AlertKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no_plex_events" => Ok(Self::NoPlexEvents),
+            "transcode_failures" => Ok(Self::TranscodeFailures),
+            "collection_shrank" => Ok(Self::CollectionShrank),
+            _ => Err(format_err!("Is not AlertKind")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for AlertKind {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for AlertKind {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct AlertRule {
+    pub id: i32,
+    pub name: StackString,
+    pub kind: AlertKind,
+    pub threshold: i64,
+    pub enabled: bool,
+    pub last_fired: Option<DateTimeWrapper>,
+}
+
+impl AlertRule {
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, name, kind, threshold, enabled, last_fired
+                FROM alert_rules
+                ORDER BY id
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn upsert(
+        pool: &PgPool,
+        name: &str,
+        kind: AlertKind,
+        threshold: i64,
+        enabled: bool,
+    ) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO alert_rules (name, kind, threshold, enabled, last_modified)
+                VALUES ($name, $kind, $threshold, $enabled, now())
+                ON CONFLICT (name) DO UPDATE
+                SET kind=$kind, threshold=$threshold, enabled=$enabled, last_modified=now()
+                RETURNING id, name, kind, threshold, enabled, last_fired
+            "#,
+            name = name,
+            kind = kind,
+            threshold = threshold,
+            enabled = enabled
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(pool: &PgPool, id: i32) -> Result<u64, Error> {
+        let query = query!("DELETE FROM alert_rules WHERE id = $id", id = id);
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    async fn set_fired(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            "UPDATE alert_rules SET last_fired=now() WHERE id = $id",
+            id = self.id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    // Returns the alert message when the rule's condition currently holds
+    pub async fn check(&self, pool: &PgPool) -> Result<Option<StackString>, Error> {
+        let conn = pool.get().await?;
+        let message = match self.kind {
+            AlertKind::NoPlexEvents => {
+                let since = Utc::now() - Duration::hours(self.threshold);
+                let query = query!(
+                    r#"
+                        SELECT max(created_at) FROM plex_event
+                        HAVING max(created_at) < $since
+                    "#,
+                    since = since
+                );
+                let last: Option<(DateTimeWrapper,)> = query.fetch_opt(&conn).await?;
+                last.map(|(last,)| format!("No plex events since {}", last))
+            }
+            AlertKind::TranscodeFailures => {
+                let query = query!(
+                    r#"
+                        SELECT count(*) FROM transcode_jobs
+                        WHERE status = 'failed' AND finished_at > now() - interval '1 day'
+                    "#
+                );
+                let (failed,): (i64,) = query.fetch_one(&conn).await?;
+                if failed > self.threshold {
+                    Some(format!("{} transcode jobs failed in the last day", failed))
+                } else {
+                    None
+                }
+            }
+            AlertKind::CollectionShrank => {
+                let query = query!(
+                    r#"
+                        SELECT added FROM scan_history
+                        WHERE status = 'success' AND added IS NOT NULL
+                        ORDER BY started_at DESC
+                        LIMIT 1
+                    "#
+                );
+                let added: Option<(i64,)> = query.fetch_opt(&conn).await?;
+                match added {
+                    Some((added,)) if added < -self.threshold => {
+                        Some(format!("Last collection scan removed {} entries", -added))
+                    }
+                    _ => None,
+                }
+            }
+        };
+        Ok(message.map(Into::into))
+    }
+
+    fn event_key(&self) -> StackString {
+        format!("alert_{}_{}", self.id, Utc::now().format("%Y-%m-%d")).into()
+    }
+}
+
+// Evaluates every enabled rule and sends admins at most one message per rule per day
+pub async fn evaluate_alerts(
+    pool: &PgPool,
+    config: &Config,
+    notifier: &Notifier,
+) -> Result<usize, Error> {
+    let mut fired = 0;
+    for rule in AlertRule::get_all(pool).await? {
+        if !rule.enabled {
+            continue;
+        }
+        let message = match rule.check(pool).await {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("alert rule {} failed {:?}", rule.name, e);
+                continue;
+            }
+        };
+        let subject = format!("Alert: {}", rule.name);
+        let sent = notifier
+            .notify_admins(pool, config, &rule.event_key(), &subject, &message)
+            .await?;
+        if sent > 0 {
+            rule.set_fired(pool).await?;
+            fired += 1;
+        }
+    }
+    Ok(fired)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alert_rules::AlertKind;
+
+    #[test]
+    fn test_alert_kind() {
+        for kind in &[
+            AlertKind::NoPlexEvents,
+            AlertKind::TranscodeFailures,
+            AlertKind::CollectionShrank,
+        ] {
+            assert_eq!(kind.to_string().parse::<AlertKind>().ok(), Some(*kind));
+        }
+        assert!("unknown".parse::<AlertKind>().is_err());
+    }
+}
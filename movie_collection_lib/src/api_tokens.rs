@@ -0,0 +1,102 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stack_string::StackString;
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+const API_TOKEN_LENGTH: usize = 40;
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct ApiToken {
+    pub id: i32,
+    pub email: StackString,
+    pub name: StackString,
+    pub created_at: DateTimeWrapper,
+}
+
+// Only the hash is stored, the token itself is shown once when it is issued
+pub fn hash_token(token: &str) -> StackString {
+    let mut hash = String::new();
+    for b in Sha256::digest(token.as_bytes()) {
+        write!(hash, "{:02x}", b).unwrap_or(());
+    }
+    hash.into()
+}
+
+impl ApiToken {
+    pub async fn create(
+        pool: &PgPool,
+        email: &str,
+        name: &str,
+    ) -> Result<(Self, StackString), Error> {
+        let token: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(API_TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+        let token_hash = hash_token(&token);
+        let query = query!(
+            r#"
+                INSERT INTO api_tokens (email, name, token_hash, created_at)
+                VALUES ($email, $name, $token_hash, now())
+                RETURNING id, email, name, created_at
+            "#,
+            email = email,
+            name = name,
+            token_hash = token_hash
+        );
+        let conn = pool.get().await?;
+        let api_token = query.fetch_one(&conn).await?;
+        Ok((api_token, token.into()))
+    }
+
+    pub async fn get_by_email(pool: &PgPool, email: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, email, name, created_at
+                FROM api_tokens
+                WHERE email = $email
+                ORDER BY created_at DESC
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(pool: &PgPool, email: &str, id: i32) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM api_tokens WHERE email = $email AND id = $id",
+            email = email,
+            id = id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    // Maps token hash to the email of the user it was issued to
+    pub async fn get_token_map(pool: &PgPool) -> Result<HashMap<StackString, StackString>, Error> {
+        let query = query!("SELECT token_hash, email FROM api_tokens");
+        let conn = pool.get().await?;
+        let rows: Vec<(StackString, StackString)> = query.fetch(&conn).await?;
+        Ok(rows.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api_tokens::hash_token;
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc").as_str(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
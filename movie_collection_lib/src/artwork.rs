@@ -0,0 +1,242 @@
+use anyhow::{format_err, Error};
+use postgres_query::query;
+use reqwest::{header::ACCEPT, Client, Url};
+use serde::Deserialize;
+use stack_string::StackString;
+use std::path::PathBuf;
+use tokio::fs;
+
+use crate::{config::Config, pgpool::PgPool};
+
+const TMDB_ENDPOINT: &str = "https://api.themoviedb.org/3/";
+const TMDB_IMAGE_ENDPOINT: &str = "https://image.tmdb.org/t/p/w185";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct PlexThumbMetadata {
+    thumb: Option<StackString>,
+    grandparent_thumb: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct PlexThumbContainer {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<PlexThumbMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlexThumbResponse {
+    #[serde(rename = "MediaContainer")]
+    media_container: PlexThumbContainer,
+}
+
+#[derive(Deserialize, Debug)]
+struct TmdbResult {
+    poster_path: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TmdbFindResponse {
+    #[serde(default)]
+    tv_results: Vec<TmdbResult>,
+    #[serde(default)]
+    movie_results: Vec<TmdbResult>,
+}
+
+impl PlexThumbResponse {
+    // Episodes carry a screenshot as thumb, the show poster is the grandparent thumb
+    fn poster(&self) -> Option<&str> {
+        self.media_container.metadata.iter().find_map(|m| {
+            m.grandparent_thumb
+                .as_ref()
+                .or_else(|| m.thumb.as_ref())
+                .map(StackString::as_str)
+        })
+    }
+}
+
+impl TmdbFindResponse {
+    fn poster(&self) -> Option<&str> {
+        self.tv_results
+            .iter()
+            .chain(self.movie_results.iter())
+            .find_map(|r| r.poster_path.as_ref().map(StackString::as_str))
+    }
+}
+
+pub fn artwork_dir(config: &Config) -> Result<PathBuf, Error> {
+    config
+        .video_playback_path
+        .as_ref()
+        .map(|p| p.join("artwork"))
+        .ok_or_else(|| format_err!("video playback path does not exist"))
+}
+
+// Links end up in a file name, so only accept imdb style identifiers
+fn is_valid_link(link: &str) -> bool {
+    !link.is_empty() && link.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+pub fn artwork_path(config: &Config, link: &str) -> Result<PathBuf, Error> {
+    if !is_valid_link(link) {
+        return Err(format_err!("Invalid link {}", link));
+    }
+    artwork_dir(config).map(|d| d.join(format!("{}.jpg", link)))
+}
+
+pub fn thumbnail_html(link: &str) -> StackString {
+    format!(
+        r#"<img src="/list/artwork/{}" alt="" height="60" loading="lazy" onerror="this.style.display='none';">"#,
+        link
+    )
+    .into()
+}
+
+pub struct Artwork {
+    config: Config,
+    pool: PgPool,
+    client: Client,
+}
+
+impl Artwork {
+    pub fn new(config: &Config, pool: &PgPool) -> Self {
+        Self {
+            config: config.clone(),
+            pool: pool.clone(),
+            client: Client::new(),
+        }
+    }
+
+    pub async fn get_poster(&self, link: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = artwork_path(&self.config, link)?;
+        if path.exists() {
+            return fs::read(&path).await.map(Some).map_err(Into::into);
+        }
+        let poster = match self.get_plex_poster(link).await? {
+            Some(poster) => Some(poster),
+            None => self.get_tmdb_poster(link).await?,
+        };
+        if let Some(poster) = &poster {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, poster).await?;
+        }
+        Ok(poster)
+    }
+
+    async fn get_plex_poster(&self, link: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (plex_host, plex_token) = match (&self.config.plex_host, &self.config.plex_token) {
+            (Some(host), Some(token)) => (host.trim_end_matches('/'), token.as_str()),
+            _ => return Ok(None),
+        };
+        let query = query!(
+            r#"
+                SELECT a.metadata_key
+                FROM plex_metadata a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                JOIN imdb_ratings c ON b.show = c.show
+                WHERE c.link = $link
+                ORDER BY a.last_modified DESC
+                LIMIT 1
+            "#,
+            link = link
+        );
+        let conn = self.pool.get().await?;
+        let metadata_key: StackString = match query.fetch_opt(&conn).await? {
+            Some((metadata_key,)) => metadata_key,
+            None => return Ok(None),
+        };
+        let url = Url::parse_with_params(
+            &format!("{}/library/metadata/{}", plex_host, metadata_key),
+            &[("X-Plex-Token", plex_token)],
+        )?;
+        let resp: PlexThumbResponse = self
+            .client
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let thumb = match resp.poster() {
+            Some(thumb) => thumb,
+            None => return Ok(None),
+        };
+        let url = Url::parse_with_params(
+            &format!("{}{}", plex_host, thumb),
+            &[("X-Plex-Token", plex_token)],
+        )?;
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(Some(body.to_vec()))
+    }
+
+    async fn get_tmdb_poster(&self, link: &str) -> Result<Option<Vec<u8>>, Error> {
+        let api_key = match &self.config.tmdb_api_key {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+        let url = Url::parse(TMDB_ENDPOINT)?.join(&format!("find/{}", link))?;
+        let url = Url::parse_with_params(
+            url.as_str(),
+            &[
+                ("api_key", api_key.as_str()),
+                ("external_source", "imdb_id"),
+            ],
+        )?;
+        let resp: TmdbFindResponse = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let poster_path = match resp.poster() {
+            Some(poster_path) => poster_path,
+            None => return Ok(None),
+        };
+        let body = self
+            .client
+            .get(format!("{}{}", TMDB_IMAGE_ENDPOINT, poster_path))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(Some(body.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::artwork::{is_valid_link, PlexThumbResponse, TmdbFindResponse};
+
+    #[test]
+    fn test_artwork_posters() -> Result<(), Error> {
+        let body = r#"{"MediaContainer": {"size": 1, "Metadata": [{
+            "thumb": "/library/metadata/12/thumb/1", "grandparentThumb": "/library/metadata/10/thumb/1"
+        }]}}"#;
+        let resp: PlexThumbResponse = serde_json::from_str(body)?;
+        assert_eq!(resp.poster(), Some("/library/metadata/10/thumb/1"));
+
+        let body = r#"{"movie_results": [], "tv_results": [{"poster_path": "/abc.jpg"}]}"#;
+        let resp: TmdbFindResponse = serde_json::from_str(body)?;
+        assert_eq!(resp.poster(), Some("/abc.jpg"));
+
+        assert!(is_valid_link("tt4158110"));
+        assert!(!is_valid_link("../tt4158110"));
+        assert!(!is_valid_link(""));
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{format_err, Error};
+use chrono::Utc;
+use deadpool_postgres::Transaction;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use stack_string::StackString;
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+pub const BACKUP_VERSION: i32 = 1;
+
+// Tables in restore order, each with the column and sequence backing its primary key
+const BACKUP_TABLES: [(&str, Option<(&str, &str)>); 13] = [
+    ("imdb_ratings", Some(("index", "imdb_ratings_id_seq"))),
+    ("imdb_episodes", Some(("id", "imdb_episodes_id_seq"))),
+    (
+        "movie_collection",
+        Some(("idx", "movie_collection_idx_seq")),
+    ),
+    ("movie_queue_entry", None),
+    ("plex_event", Some(("id", "plex_event_id_seq"))),
+    ("plex_event_daily", None),
+    ("plex_servers", None),
+    ("plex_metadata", None),
+    (
+        "trakt_watched_episodes",
+        Some(("id", "trakt_watched_episodes_id_seq")),
+    ),
+    (
+        "trakt_watched_movies",
+        Some(("id", "trakt_watched_movies_id_seq")),
+    ),
+    ("trakt_watchlist", Some(("id", "trakt_watchlist_id_seq"))),
+    ("trakt_watchlist_removed", None),
+    ("trakt_ratings", None),
+];
+
+// Credentials are blanked on export and have to be re-entered after a restore
+const MASKED_COLUMNS: [(&str, &str); 1] = [("plex_servers", "token")];
+
+fn mask_rows(table: &str, rows: &mut [Value]) {
+    for (_, column) in MASKED_COLUMNS.iter().filter(|(t, _)| *t == table) {
+        for row in rows.iter_mut() {
+            if let Value::Object(map) = row {
+                if let Some(value) = map.get_mut(*column) {
+                    *value = Value::String(String::new());
+                }
+            }
+        }
+    }
+}
+
+// Archives from before the queue moved to ranked entries carry movie_queue rows, whose
+// dense idx doubles as a rank
+const LEGACY_QUEUE_TABLE: &str = "movie_queue";
+
+fn legacy_queue_rows(rows: &[Value]) -> Vec<Value> {
+    rows.iter()
+        .map(|row| {
+            let mut row = row.clone();
+            if let Value::Object(map) = &mut row {
+                if let Some(idx) = map.remove("idx") {
+                    map.entry("rank").or_insert(idx);
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupArchive {
+    pub version: i32,
+    pub exported_at: DateTimeWrapper,
+    pub tables: BTreeMap<StackString, Vec<Value>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupHeader {
+    version: i32,
+    exported_at: DateTimeWrapper,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupLine {
+    table: StackString,
+    row: Value,
+}
+
+// Generated columns (e.g. search_vector) can't be inserted and are rebuilt on restore
+async fn get_columns(tran: &Transaction<'_>, table: &str) -> Result<StackString, Error> {
+    let query = r#"
+        SELECT column_name::TEXT
+        FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = $1 AND is_generated = 'NEVER'
+        ORDER BY ordinal_position
+    "#;
+    let columns: Vec<String> = tran
+        .query(query, &[&table])
+        .await?
+        .iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<_, _>>()?;
+    if columns.is_empty() {
+        return Err(format_err!("Table {} does not exist", table));
+    }
+    Ok(columns
+        .iter()
+        .map(|c| format!(r#""{}""#, c))
+        .join(", ")
+        .into())
+}
+
+impl BackupArchive {
+    pub async fn export(pool: &PgPool) -> Result<Self, Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        tran.execute(
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY",
+            &[],
+        )
+        .await?;
+        let mut tables = BTreeMap::new();
+        for (table, _) in &BACKUP_TABLES {
+            let columns = get_columns(&tran, table).await?;
+            let query = format!(
+                "SELECT coalesce(json_agg(t), '[]'::json) FROM (SELECT {} FROM {}) t",
+                columns, table
+            );
+            let rows: Value = tran.query_one(query.as_str(), &[]).await?.try_get(0)?;
+            let mut rows = match rows {
+                Value::Array(rows) => rows,
+                _ => return Err(format_err!("Unexpected export of {}", table)),
+            };
+            mask_rows(table, &mut rows);
+            tables.insert((*table).into(), rows);
+        }
+        tran.commit().await?;
+        Ok(Self {
+            version: BACKUP_VERSION,
+            exported_at: Utc::now().into(),
+            tables,
+        })
+    }
+
+    pub fn check_version(&self) -> Result<(), Error> {
+        if self.version < 1 || self.version > BACKUP_VERSION {
+            return Err(format_err!("Unsupported backup version {}", self.version));
+        }
+        if let Some(table) = self.tables.keys().find(|t| {
+            t.as_str() != LEGACY_QUEUE_TABLE
+                && BACKUP_TABLES.iter().all(|(table, _)| t.as_str() != *table)
+        }) {
+            return Err(format_err!("Unknown table {}", table));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(Into::into)
+    }
+
+    pub fn to_ndjson_gz(&self) -> Result<Vec<u8>, Error> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let header = BackupHeader {
+            version: self.version,
+            exported_at: self.exported_at,
+        };
+        serde_json::to_writer(&mut encoder, &header)?;
+        encoder.write_all(b"\n")?;
+        for (table, rows) in &self.tables {
+            for row in rows {
+                let line = BackupLine {
+                    table: table.clone(),
+                    row: row.clone(),
+                };
+                serde_json::to_writer(&mut encoder, &line)?;
+                encoder.write_all(b"\n")?;
+            }
+        }
+        encoder.finish().map_err(Into::into)
+    }
+
+    // Accepts the plain JSON archive or the (optionally gzipped) NDJSON form
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, Error> {
+        let text = if buf.starts_with(&[0x1f, 0x8b]) {
+            let mut text = String::new();
+            GzDecoder::new(buf).read_to_string(&mut text)?;
+            text
+        } else {
+            std::str::from_utf8(buf)?.to_string()
+        };
+        if let Ok(archive) = serde_json::from_str(&text) {
+            return Ok(archive);
+        }
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let header = lines.next().ok_or_else(|| format_err!("Empty backup"))?;
+        let header: BackupHeader = serde_json::from_str(header)?;
+        let mut tables: BTreeMap<StackString, Vec<Value>> = BTreeMap::new();
+        for line in lines {
+            let line: BackupLine = serde_json::from_str(line)?;
+            tables.entry(line.table).or_default().push(line.row);
+        }
+        Ok(Self {
+            version: header.version,
+            exported_at: header.exported_at,
+            tables,
+        })
+    }
+
+    fn table_rows(&self, table: &str) -> Option<Vec<Value>> {
+        match self.tables.get(table) {
+            Some(rows) => Some(rows.clone()),
+            None if table == "movie_queue_entry" => self
+                .tables
+                .get(LEGACY_QUEUE_TABLE)
+                .map(|rows| legacy_queue_rows(rows)),
+            None => None,
+        }
+    }
+
+    // Intended for an empty database, rows conflicting with existing ones are skipped
+    pub async fn restore(&self, pool: &PgPool) -> Result<BTreeMap<StackString, u64>, Error> {
+        self.check_version()?;
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let mut counts = BTreeMap::new();
+        for (table, sequence) in &BACKUP_TABLES {
+            let rows = match self.table_rows(table) {
+                Some(rows) if !rows.is_empty() => Value::Array(rows),
+                _ => continue,
+            };
+            let columns = get_columns(&tran, table).await?;
+            let query = format!(
+                r#"
+                    INSERT INTO {table} ({columns})
+                    SELECT {columns} FROM json_populate_recordset(NULL::{table}, $1::json)
+                    ON CONFLICT DO NOTHING
+                "#,
+                table = table,
+                columns = columns
+            );
+            let count = tran.execute(query.as_str(), &[&rows]).await?;
+            if let Some((column, sequence)) = sequence {
+                let query = format!(
+                    r#"SELECT setval('{}', coalesce((SELECT max("{}") FROM {}), 0) + 1, false)"#,
+                    sequence, column, table
+                );
+                tran.execute(query.as_str(), &[]).await?;
+            }
+            counts.insert((*table).into(), count);
+        }
+        tran.commit().await?;
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use maplit::btreemap;
+    use serde_json::json;
+
+    use crate::backup::{mask_rows, BackupArchive, BACKUP_VERSION};
+
+    #[test]
+    fn test_backup_round_trip() {
+        let archive = BackupArchive {
+            version: BACKUP_VERSION,
+            exported_at: Utc::now().into(),
+            tables: btreemap! {
+                "movie_collection".into() => vec![
+                    json!({"idx": 1, "path": "/movies/a.mp4", "show": "a"}),
+                    json!({"idx": 2, "path": "/movies/b.mp4", "show": "b"}),
+                ],
+                "movie_queue".into() => vec![json!({"idx": 0, "collection_idx": 2})],
+            },
+        };
+        assert!(archive.check_version().is_ok());
+
+        let buf = archive.to_ndjson_gz().unwrap();
+        assert_eq!(BackupArchive::from_bytes(&buf).unwrap(), archive);
+
+        let buf = serde_json::to_vec_pretty(&archive).unwrap();
+        assert_eq!(BackupArchive::from_bytes(&buf).unwrap(), archive);
+
+        assert_eq!(
+            archive.table_rows("movie_queue_entry"),
+            Some(vec![json!({"rank": 0, "collection_idx": 2})])
+        );
+
+        let mut archive = archive;
+        archive.tables.insert("authorized_users".into(), Vec::new());
+        assert!(archive.check_version().is_err());
+    }
+
+    #[test]
+    fn test_mask_rows() {
+        let mut rows = vec![json!({"server_id": "abc", "token": "secret"})];
+        mask_rows("plex_servers", &mut rows);
+        assert_eq!(rows, vec![json!({"server_id": "abc", "token": ""})]);
+
+        let mut rows = vec![json!({"idx": 1, "token": "kept"})];
+        mask_rows("movie_collection", &mut rows);
+        assert_eq!(rows, vec![json!({"idx": 1, "token": "kept"})]);
+    }
+}
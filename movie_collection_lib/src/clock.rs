@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    fmt,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use uuid::Uuid;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub trait IdGen: Send + Sync {
+    fn new_id(&self) -> Uuid;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomIdGen;
+
+impl IdGen for RandomIdGen {
+    fn new_id(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+// Starts at a fixed instant and advances by `step` on every call
+#[derive(Debug)]
+pub struct FixedClock {
+    start: DateTime<Utc>,
+    step: Duration,
+    ticks: AtomicU64,
+}
+
+impl FixedClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self::with_step(start, Duration::zero())
+    }
+
+    pub fn with_step(start: DateTime<Utc>, step: Duration) -> Self {
+        Self {
+            start,
+            step,
+            ticks: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        let ticks = self.ticks.fetch_add(1, Ordering::SeqCst);
+        self.start + self.step * ticks as i32
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SequentialIdGen {
+    counter: AtomicU64,
+}
+
+impl IdGen for SequentialIdGen {
+    fn new_id(&self) -> Uuid {
+        let count = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
+        Uuid::from_u128(u128::from(count))
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl Deref for SharedClock {
+    type Target = dyn Clock;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedClock({})", self.now())
+    }
+}
+
+#[derive(Clone)]
+pub struct SharedIdGen(Arc<dyn IdGen>);
+
+impl SharedIdGen {
+    pub fn new(id_gen: impl IdGen + 'static) -> Self {
+        Self(Arc::new(id_gen))
+    }
+}
+
+impl Default for SharedIdGen {
+    fn default() -> Self {
+        Self::new(RandomIdGen)
+    }
+}
+
+impl Deref for SharedIdGen {
+    type Target = dyn IdGen;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for SharedIdGen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedIdGen")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::clock::{Clock, FixedClock, IdGen, SequentialIdGen, SharedClock};
+
+    #[test]
+    fn test_fixed_clock() {
+        let start = Utc.ymd(2021, 3, 4).and_hms(5, 6, 7);
+        let clock = SharedClock::new(FixedClock::with_step(start, Duration::seconds(1)));
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start + Duration::seconds(1));
+        assert_eq!(FixedClock::new(start).now(), start);
+
+        let id_gen = SequentialIdGen::default();
+        let expected = "00000000-0000-0000-0000-000000000001";
+        assert_eq!(id_gen.new_id().to_string(), expected);
+        assert_ne!(id_gen.new_id(), id_gen.new_id());
+    }
+}
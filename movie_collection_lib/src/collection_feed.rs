@@ -0,0 +1,44 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use stack_string::StackString;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+pub const FEED_LIMIT: i64 = 50;
+
+#[derive(FromSqlRow, Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    pub id: StackString,
+    pub title: StackString,
+    pub summary: StackString,
+    pub collection_idx: Option<i32>,
+    pub updated: DateTimeWrapper,
+}
+
+impl FeedEntry {
+    pub async fn get_recent(pool: &PgPool, limit: i64) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM (
+                    SELECT 'collection-' || a.idx AS id, coalesce(b.title, a.show) AS title,
+                           a.path AS summary, a.idx AS collection_idx,
+                           a.last_modified AS updated
+                    FROM movie_collection a
+                    LEFT JOIN imdb_ratings b ON a.show_id = b.index
+                    WHERE NOT a.is_deleted AND a.last_modified IS NOT NULL
+                    UNION ALL
+                    SELECT 'plex-event-' || id,
+                           concat_ws(' - ', grandparent_title, parent_title, title),
+                           'Added to ' || server, NULL, created_at
+                    FROM plex_event
+                    WHERE event = 'library.new'
+                ) r
+                ORDER BY updated DESC
+                LIMIT $limit
+            "#,
+            limit = limit
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+}
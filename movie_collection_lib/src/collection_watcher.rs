@@ -0,0 +1,213 @@
+use anyhow::Error;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use stack_string::StackString;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::Duration,
+};
+use stdout_channel::StdoutChannel;
+use tokio::{
+    fs,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    task::spawn_blocking,
+    time::sleep,
+};
+use tracing::{debug, error};
+
+use crate::{
+    config::Config, movie_collection::MovieCollection, pgpool::PgPool,
+    post_processors::PostProcessors, scan_exclusions::ScanExclusions,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchAction {
+    Add(PathBuf),
+    Remove(PathBuf),
+}
+
+fn has_suffix(path: &Path, suffixes: &[StackString]) -> bool {
+    path.extension()
+        .map(OsStr::to_string_lossy)
+        .map_or(false, |ext| suffixes.iter().any(|s| s.as_str() == ext))
+}
+
+pub fn watch_actions(event: DebouncedEvent, suffixes: &[StackString]) -> Vec<WatchAction> {
+    let actions = match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+            vec![WatchAction::Add(path)]
+        }
+        DebouncedEvent::Remove(path) => vec![WatchAction::Remove(path)],
+        DebouncedEvent::Rename(from, to) => {
+            vec![WatchAction::Remove(from), WatchAction::Add(to)]
+        }
+        _ => Vec::new(),
+    };
+    actions
+        .into_iter()
+        .filter(|action| match action {
+            WatchAction::Add(path) | WatchAction::Remove(path) => has_suffix(path, suffixes),
+        })
+        .collect()
+}
+
+// A copy can pause for longer than the debounce delay, so only insert once
+// the size stops changing
+pub(crate) async fn wait_for_stable_size(path: &Path, delay: Duration) -> Result<bool, Error> {
+    let mut size = match fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(false),
+    };
+    loop {
+        sleep(delay).await;
+        let new_size = match fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(false),
+        };
+        if new_size == size {
+            return Ok(true);
+        }
+        size = new_size;
+    }
+}
+
+pub(crate) fn watch_dirs(
+    dirs: &[PathBuf],
+    delay: Duration,
+    send: &UnboundedSender<DebouncedEvent>,
+) -> Result<(), Error> {
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, delay)?;
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+    }
+    while let Ok(event) = rx.recv() {
+        if send.send(event).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub struct CollectionWatcher {
+    config: Config,
+    pool: PgPool,
+    stdout: StdoutChannel,
+    post_processors: PostProcessors,
+}
+
+impl CollectionWatcher {
+    pub fn new(config: &Config, pool: &PgPool, stdout: &StdoutChannel) -> Self {
+        Self {
+            config: config.clone(),
+            pool: pool.clone(),
+            stdout: stdout.clone(),
+            post_processors: PostProcessors::default(),
+        }
+    }
+
+    pub fn with_post_processors(mut self, post_processors: PostProcessors) -> Self {
+        self.post_processors = post_processors;
+        self
+    }
+
+    fn debounce(&self) -> Duration {
+        Duration::from_secs(self.config.watch_debounce_seconds)
+    }
+
+    fn spawn_watcher(&self, send: UnboundedSender<DebouncedEvent>) {
+        let dirs: Vec<_> = self
+            .config
+            .movie_dirs
+            .iter()
+            .filter(|d| d.exists())
+            .cloned()
+            .collect();
+        let delay = self.debounce();
+        spawn_blocking(move || {
+            if let Err(e) = watch_dirs(&dirs, delay, &send) {
+                error!("failed to watch {:?} {}", dirs, e);
+            }
+        });
+    }
+
+    pub async fn handle_action(&self, action: &WatchAction) -> Result<(), Error> {
+        let mc = MovieCollection::new(&self.config, &self.pool, &self.stdout)
+            .with_post_processors(self.post_processors.clone());
+        match action {
+            WatchAction::Add(path) => {
+                if !wait_for_stable_size(path, self.debounce()).await? {
+                    return Ok(());
+                }
+                let exclusions = ScanExclusions::load(&self.config, &self.pool).await?;
+                let (paths, _) = exclusions.filter_paths(vec![path.clone()]);
+                for path in paths {
+                    let path = path.to_string_lossy();
+                    self.stdout.send(format!("watcher add {}", path));
+                    let is_new = mc.get_collection_index(&path).await?.is_none();
+                    mc.insert_into_collection(&path, true).await?;
+                    if is_new && mc.auto_queue(&path).await? {
+                        self.stdout.send(format!("auto queued {}", path));
+                    }
+                }
+            }
+            WatchAction::Remove(path) => {
+                let path = path.to_string_lossy();
+                if mc.get_collection_index(&path).await?.is_some() {
+                    self.stdout.send(format!("watcher remove {}", path));
+                    mc.remove_from_collection(&path).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self) -> Result<(), Error> {
+        let (send, mut recv) = unbounded_channel();
+        self.spawn_watcher(send);
+        while let Some(event) = recv.recv().await {
+            debug!("watcher event {:?}", event);
+            for action in watch_actions(event, &self.config.suffixes) {
+                if let Err(e) = self.handle_action(&action).await {
+                    error!("watcher failed {:?} {}", action, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::DebouncedEvent;
+    use stack_string::StackString;
+    use std::path::PathBuf;
+
+    use crate::collection_watcher::{watch_actions, WatchAction};
+
+    #[test]
+    fn test_watch_actions() {
+        let suffixes: Vec<StackString> = vec!["mp4".into(), "mkv".into()];
+        let a = PathBuf::from("/tmp/movies/a.mp4");
+        let b = PathBuf::from("/tmp/movies/b.mkv");
+        let partial = PathBuf::from("/tmp/movies/a.mp4.part");
+
+        let actions = watch_actions(DebouncedEvent::Create(a.clone()), &suffixes);
+        assert_eq!(actions, vec![WatchAction::Add(a.clone())]);
+
+        let event = DebouncedEvent::Rename(partial.clone(), a.clone());
+        let actions = watch_actions(event, &suffixes);
+        assert_eq!(actions, vec![WatchAction::Add(a.clone())]);
+
+        let event = DebouncedEvent::Rename(a.clone(), b.clone());
+        let actions = watch_actions(event, &suffixes);
+        assert_eq!(
+            actions,
+            vec![WatchAction::Remove(a), WatchAction::Add(b.clone())]
+        );
+
+        assert!(watch_actions(DebouncedEvent::Write(partial), &suffixes).is_empty());
+        assert!(watch_actions(DebouncedEvent::NoticeWrite(b), &suffixes).is_empty());
+    }
+}
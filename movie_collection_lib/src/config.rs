@@ -9,14 +9,24 @@ use uuid::Uuid;
 
 use stack_string::StackString;
 
+use crate::{download_requests::DownloadClientKind, utils::set_filename_patterns};
+
 #[derive(Debug, Default, Deserialize)]
 pub struct ConfigInner {
     #[serde(default = "default_home_dir")]
     pub home_dir: PathBuf,
     pub pgurl: StackString,
     pub movie_dirs: Vec<PathBuf>,
+    #[serde(default)]
+    pub music_dirs: Vec<PathBuf>,
+    #[serde(default = "default_music_suffixes")]
+    pub music_suffixes: Vec<StackString>,
     #[serde(default = "default_suffixes")]
     pub suffixes: Vec<StackString>,
+    #[serde(default)]
+    pub exclude_globs: Vec<StackString>,
+    #[serde(default)]
+    pub filename_patterns: Vec<StackString>,
     #[serde(default = "default_preferred_dir")]
     pub preferred_dir: PathBuf,
     #[serde(default = "default_queue_table")]
@@ -39,22 +49,111 @@ pub struct ConfigInner {
     pub remcom_queue: StackString,
     #[serde(default = "default_trakt_endpoint")]
     pub trakt_endpoint: StackString,
-    #[serde(default = "default_trakt_endpoint")]
+    #[serde(default)]
     pub trakt_client_id: StackString,
-    #[serde(default = "default_trakt_endpoint")]
+    #[serde(default)]
     pub trakt_client_secret: StackString,
     #[serde(default = "default_secret_path")]
     pub secret_path: PathBuf,
     #[serde(default = "default_secret_path")]
     pub jwt_secret_path: PathBuf,
     pub video_playback_path: Option<PathBuf>,
+    #[serde(default = "default_hls_ttl_hours")]
+    pub hls_ttl_hours: u64,
+    pub trash_dir: Option<PathBuf>,
+    #[serde(default = "default_offline_preset")]
+    pub offline_preset: StackString,
+    #[serde(default = "default_offline_expiry_days")]
+    pub offline_expiry_days: i64,
+    #[serde(default = "default_offline_max_size_mb")]
+    pub offline_max_size_mb: u64,
+    #[serde(default = "default_scan_interval_minutes")]
+    pub scan_interval_minutes: u64,
+    #[serde(default = "default_webhook_retry_minutes")]
+    pub webhook_retry_minutes: u64,
+    #[serde(default = "default_imdb_refresh_hours")]
+    pub imdb_refresh_hours: u64,
+    #[serde(default = "default_imdb_refresh_stale_days")]
+    pub imdb_refresh_stale_days: i64,
+    #[serde(default = "default_trakt_sync_minutes")]
+    pub trakt_sync_minutes: u64,
+    #[serde(default)]
+    pub watch_collection: bool,
+    #[serde(default = "default_watch_debounce_seconds")]
+    pub watch_debounce_seconds: u64,
+    pub watch_folder: Option<PathBuf>,
+    #[serde(default = "default_plex_event_retention_days")]
+    pub plex_event_retention_days: i64,
     #[serde(default = "default_plex_webhook_key")]
     pub plex_webhook_key: Uuid,
+    pub plex_host: Option<StackString>,
+    pub plex_token: Option<StackString>,
+    pub jellyfin_url: Option<StackString>,
+    pub jellyfin_api_key: Option<StackString>,
+    #[serde(default = "default_jellyfin_webhook_key")]
+    pub jellyfin_webhook_key: Uuid,
+    #[serde(default = "default_trakt_webhook_key")]
+    pub trakt_webhook_key: Uuid,
+    // Feeds and calendars are disabled unless a key is configured
+    pub feed_key: Option<Uuid>,
+    pub influxdb_url: Option<StackString>,
+    #[serde(default)]
+    pub influxdb_org: StackString,
+    #[serde(default = "default_influxdb_bucket")]
+    pub influxdb_bucket: StackString,
+    pub influxdb_token: Option<StackString>,
+    #[serde(default)]
+    pub admin_emails: Vec<StackString>,
+    #[serde(default)]
+    pub household_accounts: Vec<StackString>,
+    #[serde(default)]
+    pub metadata_provider: MetadataProvider,
+    pub opensubtitles_api_key: Option<StackString>,
+    #[serde(default = "default_opensubtitles_language")]
+    pub opensubtitles_language: StackString,
+    pub availability_api_key: Option<StackString>,
+    #[serde(default = "default_availability_endpoint")]
+    pub availability_endpoint: StackString,
+    #[serde(default = "default_availability_region")]
+    pub availability_region: StackString,
+    pub tmdb_api_key: Option<StackString>,
+    pub sparkpost_api_key: Option<StackString>,
+    pub sending_email_address: Option<StackString>,
+    pub telegram_bot_token: Option<StackString>,
+    #[serde(default = "default_notification_check_minutes")]
+    pub notification_check_minutes: u64,
+    pub download_client_url: Option<StackString>,
+    #[serde(default)]
+    pub download_client_kind: DownloadClientKind,
+    pub download_client_username: Option<StackString>,
+    pub download_client_password: Option<StackString>,
+    pub download_torrent_template: Option<StackString>,
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    #[serde(default = "default_rate_limit_per_minute")]
+    pub rate_limit_per_minute: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum MetadataProvider {
+    #[serde(rename = "imdb")]
+    Imdb,
+    #[serde(rename = "tvmaze")]
+    TvMaze,
+}
+
+impl Default for MetadataProvider {
+    fn default() -> Self {
+        Self::Imdb
+    }
 }
 
 fn default_suffixes() -> Vec<StackString> {
     vec!["avi".into(), "mp4".into(), "mkv".into()]
 }
+fn default_music_suffixes() -> Vec<StackString> {
+    vec!["mp3".into(), "flac".into()]
+}
 fn default_preferred_dir() -> PathBuf {
     "/tmp".into()
 }
@@ -97,9 +196,69 @@ fn default_secret_path() -> PathBuf {
         .join("aws_app_rust")
         .join("secret.bin")
 }
+fn default_hls_ttl_hours() -> u64 {
+    24
+}
+fn default_offline_preset() -> StackString {
+    "Very Fast 480p30".into()
+}
+fn default_offline_expiry_days() -> i64 {
+    7
+}
+fn default_offline_max_size_mb() -> u64 {
+    4096
+}
+fn default_scan_interval_minutes() -> u64 {
+    360
+}
+fn default_webhook_retry_minutes() -> u64 {
+    15
+}
+fn default_imdb_refresh_hours() -> u64 {
+    24
+}
+fn default_imdb_refresh_stale_days() -> i64 {
+    7
+}
+fn default_trakt_sync_minutes() -> u64 {
+    60
+}
+fn default_watch_debounce_seconds() -> u64 {
+    10
+}
+fn default_plex_event_retention_days() -> i64 {
+    548
+}
+fn default_influxdb_bucket() -> StackString {
+    "movie_collection".into()
+}
+fn default_opensubtitles_language() -> StackString {
+    "en".into()
+}
+fn default_availability_endpoint() -> StackString {
+    "https://streaming-availability.p.rapidapi.com".into()
+}
+fn default_availability_region() -> StackString {
+    "us".into()
+}
+fn default_rate_limit_burst() -> u32 {
+    30
+}
+fn default_rate_limit_per_minute() -> u32 {
+    60
+}
+fn default_notification_check_minutes() -> u64 {
+    60
+}
 fn default_plex_webhook_key() -> Uuid {
     Uuid::new_v4()
 }
+fn default_jellyfin_webhook_key() -> Uuid {
+    Uuid::new_v4()
+}
+fn default_trakt_webhook_key() -> Uuid {
+    Uuid::new_v4()
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Config(Arc<ConfigInner>);
@@ -109,17 +268,23 @@ impl ConfigInner {
         Self {
             home_dir: default_home_dir(),
             suffixes: default_suffixes(),
+            music_suffixes: default_music_suffixes(),
             port: default_port(),
             domain: default_domain(),
             n_db_workers: default_n_db_workers(),
             ..Self::default()
         }
     }
+
+    pub fn trakt_configured(&self) -> bool {
+        !self.trakt_client_id.is_empty() && !self.trakt_client_secret.is_empty()
+    }
 }
 
 impl Config {
     pub fn new() -> Result<Self, Error> {
         let config: ConfigInner = envy::from_env()?;
+        set_filename_patterns(&config.filename_patterns)?;
         Ok(Self(Arc::new(config)))
     }
 
@@ -0,0 +1,130 @@
+use anyhow::{format_err, Error};
+use serde::Deserialize;
+use stack_string::StackString;
+use std::path::Path;
+use tokio::process::Command;
+
+const CREDITS_WINDOW: f64 = 600.0;
+const MIN_CREDITS_LENGTH: f64 = 30.0;
+const MIN_CHAPTER_FRACTION: f64 = 0.8;
+
+#[derive(Deserialize, Debug)]
+struct ProbeChapter {
+    start_time: StackString,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProbeFormat {
+    duration: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProbeOutput {
+    #[serde(default)]
+    chapters: Vec<ProbeChapter>,
+    format: ProbeFormat,
+}
+
+pub async fn detect_credits_start(path: &Path) -> Result<Option<f64>, Error> {
+    if !path.exists() {
+        return Err(format_err!("{:?} does not exist", path));
+    }
+    let output = Command::new("ffprobe")
+        .args(&[
+            "-v",
+            "quiet",
+            "-print_format",
+            "json",
+            "-show_chapters",
+            "-show_format",
+        ])
+        .arg(path)
+        .output()
+        .await?;
+    let (duration, chapter_start) = parse_probe_output(&output.stdout)?;
+    if chapter_start.is_some() {
+        return Ok(chapter_start);
+    }
+    let duration = match duration {
+        Some(d) if d > CREDITS_WINDOW => d,
+        _ => return Ok(None),
+    };
+    let start = format!("{}", duration - CREDITS_WINDOW);
+    let output = Command::new("ffmpeg")
+        .args(&["-hide_banner", "-copyts", "-ss", start.as_str(), "-i"])
+        .arg(path)
+        .args(&[
+            "-vf",
+            "blackdetect=d=0.5:pix_th=0.10",
+            "-an",
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(parse_blackdetect(&stderr, duration))
+}
+
+fn parse_probe_output(buf: &[u8]) -> Result<(Option<f64>, Option<f64>), Error> {
+    let probe: ProbeOutput = serde_json::from_slice(buf)?;
+    let duration = probe.format.duration.and_then(|d| d.parse::<f64>().ok());
+    let chapter_start = if probe.chapters.len() > 1 {
+        probe
+            .chapters
+            .last()
+            .and_then(|c| c.start_time.parse::<f64>().ok())
+            .filter(|start| duration.map_or(false, |d| *start >= d * MIN_CHAPTER_FRACTION))
+    } else {
+        None
+    };
+    Ok((duration, chapter_start))
+}
+
+fn parse_blackdetect(output: &str, duration: f64) -> Option<f64> {
+    output
+        .lines()
+        .filter(|line| line.contains("blackdetect"))
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find_map(|s| s.strip_prefix("black_end:"))
+                .and_then(|s| s.parse::<f64>().ok())
+        })
+        .filter(|end| *end < duration - MIN_CREDITS_LENGTH)
+        .last()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::credits_detection::{parse_blackdetect, parse_probe_output};
+
+    #[test]
+    fn test_parse_probe_output() {
+        let buf = br#"{
+            "chapters": [
+                {"start_time": "0.000000", "end_time": "1200.000000"},
+                {"start_time": "1200.000000", "end_time": "1320.500000"}
+            ],
+            "format": {"duration": "1320.500000"}
+        }"#;
+        let (duration, start) = parse_probe_output(buf).unwrap();
+        assert_eq!(duration, Some(1320.5));
+        assert_eq!(start, Some(1200.0));
+
+        let buf = br#"{"format": {"duration": "1320.500000"}}"#;
+        let (_, start) = parse_probe_output(buf).unwrap();
+        assert_eq!(start, None);
+    }
+
+    #[test]
+    fn test_parse_blackdetect() {
+        let output = "
+            [blackdetect @ 0x55] black_start:1100.1 black_end:1101.2 black_duration:1.1
+            [blackdetect @ 0x55] black_start:1250.0 black_end:1251.5 black_duration:1.5
+            [blackdetect @ 0x55] black_start:1318.0 black_end:1320.5 black_duration:2.5
+        ";
+        assert_eq!(parse_blackdetect(output, 1320.5), Some(1251.5));
+        assert_eq!(parse_blackdetect("", 1320.5), None);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{format_err, Error};
+use lazy_static::lazy_static;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::{fs, sync::Mutex};
+
+use crate::clock::IdGen;
+
+const CONFIRM_TOKEN_TTL: Duration = Duration::from_secs(600);
+
+lazy_static! {
+    static ref PENDING_DELETES: Mutex<HashMap<StackString, PendingDelete>> =
+        Mutex::new(HashMap::new());
+}
+
+struct PendingDelete {
+    files: Vec<DeleteFile>,
+    created: Instant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct DeleteFile {
+    pub path: StackString,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct DeletePreview {
+    pub token: StackString,
+    pub files: Vec<DeleteFile>,
+    pub total_size: u64,
+}
+
+pub fn format_size(size: u64) -> StackString {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", size, units[unit]).into()
+    } else {
+        format!("{:.1} {}", value, units[unit]).into()
+    }
+}
+
+async fn get_delete_files(paths: &[PathBuf]) -> Result<Vec<DeleteFile>, Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        let metadata = fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(format_err!("{} is not a file", path.to_string_lossy()));
+        }
+        files.push(DeleteFile {
+            path: path.to_string_lossy().into_owned().into(),
+            size: metadata.len(),
+        });
+    }
+    Ok(files)
+}
+
+impl DeletePreview {
+    pub async fn new(paths: &[PathBuf], id_gen: &dyn IdGen) -> Result<Self, Error> {
+        let files = get_delete_files(paths).await?;
+        let total_size = files.iter().map(|f| f.size).sum();
+        let token: StackString = id_gen.new_id().to_simple().to_string().into();
+
+        let mut pending = PENDING_DELETES.lock().await;
+        pending.retain(|_, p| p.created.elapsed() < CONFIRM_TOKEN_TTL);
+        pending.insert(
+            token.clone(),
+            PendingDelete {
+                files: files.clone(),
+                created: Instant::now(),
+            },
+        );
+        Ok(Self {
+            token,
+            files,
+            total_size,
+        })
+    }
+
+    pub async fn confirm(token: &str) -> Result<Vec<DeleteFile>, Error> {
+        let pending = PENDING_DELETES
+            .lock()
+            .await
+            .remove(token)
+            .ok_or_else(|| format_err!("Invalid confirm token"))?;
+        if pending.created.elapsed() >= CONFIRM_TOKEN_TTL {
+            return Err(format_err!("Confirm token expired"));
+        }
+        let paths: Vec<PathBuf> = pending
+            .files
+            .iter()
+            .map(|f| f.path.as_str().into())
+            .collect();
+        if get_delete_files(&paths).await? != pending.files {
+            return Err(format_err!(
+                "Files changed since preview, please confirm again"
+            ));
+        }
+        for path in &paths {
+            fs::remove_file(path).await?;
+        }
+        Ok(pending.files)
+    }
+
+    pub fn get_html(&self, confirm_action: &str, cancel_action: &str) -> StackString {
+        let rows: Vec<_> = self
+            .files
+            .iter()
+            .map(|f| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    f.path,
+                    format_size(f.size)
+                )
+            })
+            .collect();
+        format!(
+            r#"
+            <div style="border: 2px solid #cc0000; padding: 20px; margin: 20px; background-color: #fff4f4;">
+            <h3>Confirm deletion of {count} file(s), {total}</h3>
+            <table border="0"><tr><th>File</th><th>Size</th></tr>{rows}</table>
+            <button type="submit" onclick="{confirm_action}">Delete</button>
+            <button type="submit" onclick="{cancel_action}">Cancel</button>
+            </div>
+            "#,
+            count = self.files.len(),
+            total = format_size(self.total_size),
+            rows = rows.join(""),
+            confirm_action = confirm_action,
+            cancel_action = cancel_action,
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::fs::write;
+    use tempfile::TempDir;
+
+    use crate::{
+        clock::RandomIdGen,
+        delete_confirm::{format_size, DeletePreview},
+    };
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512).as_str(), "512 B");
+        assert_eq!(format_size(1536).as_str(), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024).as_str(), "3.0 GiB");
+    }
+
+    #[tokio::test]
+    async fn test_delete_preview_confirm() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let path = tmp.path().join("mr_robot_s01_ep01.mp4");
+        write(&path, b"0123456789")?;
+
+        let preview = DeletePreview::new(&[path.clone()], &RandomIdGen).await?;
+        assert_eq!(preview.total_size, 10);
+        assert!(DeletePreview::confirm("bad_token").await.is_err());
+        assert!(path.exists());
+
+        let deleted = DeletePreview::confirm(&preview.token).await?;
+        assert_eq!(deleted, preview.files);
+        assert!(!path.exists());
+        assert!(DeletePreview::confirm(&preview.token).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use postgres_query::{query, FromSqlRow};
+use reqwest::{Client, StatusCode};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use stack_string::StackString;
+use std::{collections::HashMap, fmt, str::FromStr};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, imdb_ratings::ImdbRatings, pgpool::PgPool,
+};
+
+const TRANSMISSION_SESSION_HEADER: &str = "X-Transmission-Session-Id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum DownloadClientKind {
+    #[serde(rename = "webhook")]
+    Webhook,
+    #[serde(rename = "transmission")]
+    Transmission,
+}
+
+impl Default for DownloadClientKind {
+    fn default() -> Self {
+        Self::Webhook
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum DownloadStatus {
+    #[serde(rename = "submitted")]
+    Submitted,
+    #[serde(rename = "failed")]
+    Failed,
+    #[serde(rename = "completed")]
+    Completed,
+}
+
+impl fmt::Display for DownloadStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Submitted => "submitted",
+                Self::Failed => "failed",
+                Self::Completed => "completed",
+            }
+        )
+    }
+}
+
+impl FromStr for DownloadStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "submitted" => Ok(Self::Submitted),
+            "failed" => Ok(Self::Failed),
+            "completed" => Ok(Self::Completed),
+            _ => Err(format_err!("Is not DownloadStatus")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for DownloadStatus {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for DownloadStatus {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+pub struct DownloadClient {
+    client: Client,
+    kind: DownloadClientKind,
+    url: StackString,
+    username: Option<StackString>,
+    password: Option<StackString>,
+    torrent_template: Option<StackString>,
+}
+
+impl DownloadClient {
+    pub fn new(config: &Config) -> Option<Self> {
+        let url = config.download_client_url.clone()?;
+        Some(Self {
+            client: Client::new(),
+            kind: config.download_client_kind,
+            url,
+            username: config.download_client_username.clone(),
+            password: config.download_client_password.clone(),
+            torrent_template: config.download_torrent_template.clone(),
+        })
+    }
+
+    pub fn search_query(show: &str, season: i32, episode: i32) -> StackString {
+        format!("{} S{:02}E{:02}", show, season, episode).into()
+    }
+
+    async fn submit_webhook(
+        &self,
+        show: &ImdbRatings,
+        season: i32,
+        episode: i32,
+        search: &str,
+    ) -> Result<(), Error> {
+        let data = json!({
+            "show": show.show,
+            "title": show.title,
+            "link": show.link,
+            "season": season,
+            "episode": episode,
+            "query": search,
+        });
+        let mut request = self.client.post(self.url.as_str()).json(&data);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    // Transmission rejects the first call with a 409 carrying the session id to retry with
+    async fn submit_transmission(&self, search: &str) -> Result<(), Error> {
+        let template = self
+            .torrent_template
+            .as_ref()
+            .ok_or_else(|| format_err!("No torrent template configured"))?;
+        let filename = template.replace("{query}", &search.replace(' ', "+"));
+        let data = json!({"method": "torrent-add", "arguments": {"filename": filename}});
+        let mut session_id: Option<StackString> = None;
+        for _ in 0..2 {
+            let mut request = self.client.post(self.url.as_str()).json(&data);
+            if let Some(username) = &self.username {
+                request = request.basic_auth(username, self.password.as_ref());
+            }
+            if let Some(session_id) = &session_id {
+                request = request.header(TRANSMISSION_SESSION_HEADER, session_id.as_str());
+            }
+            let resp = request.send().await?;
+            if resp.status() == StatusCode::CONFLICT {
+                session_id = resp
+                    .headers()
+                    .get(TRANSMISSION_SESSION_HEADER)
+                    .and_then(|h| h.to_str().ok())
+                    .map(Into::into);
+                continue;
+            }
+            let result: Value = resp.error_for_status()?.json().await?;
+            return match result.get("result").and_then(|r| r.as_str()) {
+                Some("success") => Ok(()),
+                r => Err(format_err!("Transmission returned {:?}", r)),
+            };
+        }
+        Err(format_err!("Failed to obtain transmission session"))
+    }
+
+    pub async fn submit(&self, show: &ImdbRatings, season: i32, episode: i32) -> Result<(), Error> {
+        let title = show.title.as_ref().unwrap_or(&show.show);
+        let search = Self::search_query(title, season, episode);
+        match self.kind {
+            DownloadClientKind::Webhook => {
+                self.submit_webhook(show, season, episode, &search).await
+            }
+            DownloadClientKind::Transmission => self.submit_transmission(&search).await,
+        }
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct DownloadRequest {
+    pub id: i32,
+    pub link: StackString,
+    pub season: i32,
+    pub episode: i32,
+    pub status: DownloadStatus,
+    pub error: Option<StackString>,
+    pub created_at: DateTimeWrapper,
+    pub last_modified: DateTimeWrapper,
+}
+
+impl DownloadRequest {
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, link, season, episode, status, error, created_at, last_modified
+                FROM download_requests
+                ORDER BY last_modified DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_map(pool: &PgPool) -> Result<HashMap<(StackString, i32, i32), Self>, Error> {
+        let requests = Self::get_all(pool).await?;
+        Ok(requests
+            .into_iter()
+            .map(|r| ((r.link.clone(), r.season, r.episode), r))
+            .collect())
+    }
+
+    async fn upsert(
+        pool: &PgPool,
+        link: &str,
+        season: i32,
+        episode: i32,
+        status: DownloadStatus,
+        error: Option<&str>,
+    ) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO download_requests
+                    (link, season, episode, status, error, created_at, last_modified)
+                VALUES ($link, $season, $episode, $status, $error, now(), now())
+                ON CONFLICT (link, season, episode) DO UPDATE
+                SET status=$status, error=$error, last_modified=now()
+                RETURNING id, link, season, episode, status, error, created_at, last_modified
+            "#,
+            link = link,
+            season = season,
+            episode = episode,
+            status = status,
+            error = error
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    // Failures are recorded rather than returned so the calendar can show what went wrong
+    pub async fn submit(
+        config: &Config,
+        pool: &PgPool,
+        link: &str,
+        season: i32,
+        episode: i32,
+    ) -> Result<Self, Error> {
+        let client =
+            DownloadClient::new(config).ok_or_else(|| format_err!("No download client"))?;
+        let show = ImdbRatings::get_show_by_link(link, pool)
+            .await?
+            .ok_or_else(|| format_err!("Show Doesn't exist"))?;
+        match client.submit(&show, season, episode).await {
+            Ok(_) => {
+                Self::upsert(pool, link, season, episode, DownloadStatus::Submitted, None).await
+            }
+            Err(e) => {
+                let error = e.to_string();
+                Self::upsert(
+                    pool,
+                    link,
+                    season,
+                    episode,
+                    DownloadStatus::Failed,
+                    Some(&error),
+                )
+                .await
+            }
+        }
+    }
+
+    pub async fn set_completed(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE download_requests
+                SET status=$status, error=null, last_modified=now()
+                WHERE id = $id
+            "#,
+            status = DownloadStatus::Completed,
+            id = self.id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub fn get_html(&self) -> StackString {
+        match &self.error {
+            Some(error) => format!(r#"<span title="{}">{}</span>"#, error, self.status).into(),
+            None => self.status.to_string().into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::download_requests::{DownloadClient, DownloadStatus};
+
+    #[test]
+    fn test_download_status() -> Result<(), Error> {
+        let status: DownloadStatus = "submitted".parse()?;
+        assert_eq!(status, DownloadStatus::Submitted);
+        assert_eq!(status.to_string().as_str(), "submitted");
+        assert!("queued".parse::<DownloadStatus>().is_err());
+        assert_eq!(
+            DownloadClient::search_query("Mr. Robot", 2, 3).as_str(),
+            "Mr. Robot S02E03"
+        );
+        Ok(())
+    }
+}
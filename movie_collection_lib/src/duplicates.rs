@@ -0,0 +1,227 @@
+use anyhow::Error;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stack_string::StackString;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    path::Path,
+};
+use tokio::{fs::File, io::AsyncReadExt};
+
+use crate::{
+    delete_confirm::format_size,
+    utils::{escape_html, parse_absolute_episode, parse_file_stem},
+};
+
+// Only the head of each file is hashed, so fingerprinting a large collection
+// stays cheap; the size is part of the key to make collisions unlikely
+const FINGERPRINT_BYTES: u64 = 1 << 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Schema)]
+pub enum DuplicateKind {
+    #[serde(rename = "episode")]
+    Episode,
+    #[serde(rename = "fingerprint")]
+    Fingerprint,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct DuplicateEntry {
+    pub idx: i32,
+    pub path: StackString,
+    pub show: Option<StackString>,
+    pub size: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    pub key: StackString,
+    pub entries: Vec<DuplicateEntry>,
+}
+
+pub fn episode_key(entry: &DuplicateEntry) -> Option<StackString> {
+    let show = entry.show.as_ref()?;
+    let file_stem = Path::new(entry.path.as_str())
+        .file_stem()?
+        .to_string_lossy();
+    let (_, season, episode) = parse_file_stem(&file_stem);
+    if let Some((_, absolute)) = parse_absolute_episode(&file_stem) {
+        Some(format!("{} ep{:04}", show, absolute).into())
+    } else if season == -1 || episode == -1 {
+        Some(show.clone())
+    } else {
+        Some(format!("{} s{:02} ep{:02}", show, season, episode).into())
+    }
+}
+
+pub fn group_duplicates<'a, T, F>(entries: T, kind: DuplicateKind, key: F) -> Vec<DuplicateGroup>
+where
+    T: IntoIterator<Item = &'a DuplicateEntry>,
+    F: Fn(&DuplicateEntry) -> Option<StackString>,
+{
+    let mut groups: BTreeMap<StackString, Vec<DuplicateEntry>> = BTreeMap::new();
+    for entry in entries {
+        if let Some(k) = key(entry) {
+            groups.entry(k).or_default().push(entry.clone());
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(key, entries)| DuplicateGroup { kind, key, entries })
+        .collect()
+}
+
+pub async fn fingerprint(path: &Path, size: i64) -> Result<StackString, Error> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .await?
+        .take(FINGERPRINT_BYTES)
+        .read_to_end(&mut buf)
+        .await?;
+    let mut key = format!("{}:", size);
+    for b in Sha256::digest(&buf) {
+        write!(key, "{:02x}", b)?;
+    }
+    Ok(key.into())
+}
+
+pub async fn find_fingerprint_duplicates(
+    entries: &[DuplicateEntry],
+) -> Result<Vec<DuplicateGroup>, Error> {
+    let same_size = group_duplicates(entries, DuplicateKind::Fingerprint, |e| {
+        e.size.map(|s| s.to_string().into())
+    });
+    let mut fingerprints = BTreeMap::new();
+    for entry in same_size.iter().flat_map(|g| g.entries.iter()) {
+        let size = entry.size.unwrap_or(0);
+        if let Ok(key) = fingerprint(Path::new(entry.path.as_str()), size).await {
+            fingerprints.insert(entry.idx, key);
+        }
+    }
+    let candidates = same_size.iter().flat_map(|g| g.entries.iter());
+    Ok(group_duplicates(
+        candidates,
+        DuplicateKind::Fingerprint,
+        |e| fingerprints.get(&e.idx).cloned(),
+    ))
+}
+
+// A fingerprint match among files that already share an episode key adds no
+// information, so only keep fingerprint groups that span different shows/episodes
+pub fn merge_groups(
+    episode_groups: Vec<DuplicateGroup>,
+    fingerprint_groups: Vec<DuplicateGroup>,
+) -> Vec<DuplicateGroup> {
+    let episode_sets: Vec<HashSet<i32>> = episode_groups
+        .iter()
+        .map(|g| g.entries.iter().map(|e| e.idx).collect())
+        .collect();
+    let extra: Vec<_> = fingerprint_groups
+        .into_iter()
+        .filter(|g| {
+            let idxs: HashSet<i32> = g.entries.iter().map(|e| e.idx).collect();
+            !episode_sets.iter().any(|s| idxs.is_subset(s))
+        })
+        .collect();
+    episode_groups.into_iter().chain(extra).collect()
+}
+
+impl DuplicateEntry {
+    fn get_html(&self) -> StackString {
+        let path = escape_html(&self.path);
+        format!(
+            r#"<tr><td>{path}</td><td>{size}</td><td><button type="submit" data-path="{path}" onclick="delete_collection_entry(this.dataset.path)">Remove</button></td></tr>"#,
+            path = path,
+            size = self
+                .size
+                .map_or_else(|| "missing".into(), |s| format_size(s as u64)),
+        )
+        .into()
+    }
+}
+
+impl DuplicateGroup {
+    fn get_html(&self) -> StackString {
+        let rows: Vec<_> = self.entries.iter().map(DuplicateEntry::get_html).collect();
+        let kind = match self.kind {
+            DuplicateKind::Episode => "same episode",
+            DuplicateKind::Fingerprint => "same content",
+        };
+        format!(
+            r#"<tr><th colspan="3">{} ({})</th></tr>{}"#,
+            escape_html(&self.key),
+            kind,
+            rows.join(""),
+        )
+        .into()
+    }
+
+    pub fn get_html_table(groups: &[Self]) -> StackString {
+        let rows: Vec<_> = groups.iter().map(Self::get_html).collect();
+        format!(
+            r#"
+            <h3>{} duplicate group(s)</h3>
+            <table border="0">
+            {}
+            </table>
+            "#,
+            groups.len(),
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::duplicates::{
+        episode_key, group_duplicates, merge_groups, DuplicateEntry, DuplicateGroup, DuplicateKind,
+    };
+
+    fn entry(idx: i32, path: &str, show: &str, size: i64) -> DuplicateEntry {
+        DuplicateEntry {
+            idx,
+            path: path.into(),
+            show: Some(show.into()),
+            size: Some(size),
+        }
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let entries = vec![
+            entry(1, "/a/television/mr_robot_s01_ep01.mp4", "mr_robot", 100),
+            entry(2, "/b/television/mr_robot_s01_ep01.mkv", "mr_robot", 200),
+            entry(3, "/a/television/mr_robot_s01_ep02.mp4", "mr_robot", 100),
+            entry(4, "/a/movies/arrival.mp4", "arrival", 300),
+        ];
+        let groups = group_duplicates(&entries, DuplicateKind::Episode, episode_key);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].key.as_str(), "mr_robot s01 ep01");
+        assert_eq!(groups[0].entries.len(), 2);
+
+        let fingerprint_groups = vec![
+            DuplicateGroup {
+                kind: DuplicateKind::Fingerprint,
+                key: "100:abc".into(),
+                entries: vec![entries[0].clone(), entries[2].clone()],
+            },
+            DuplicateGroup {
+                kind: DuplicateKind::Fingerprint,
+                key: "200:def".into(),
+                entries: vec![entries[0].clone(), entries[1].clone()],
+            },
+        ];
+        let merged = merge_groups(groups, fingerprint_groups);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1].key.as_str(), "100:abc");
+
+        let html = DuplicateGroup::get_html_table(&merged);
+        assert!(html.contains("2 duplicate group(s)"));
+        assert!(html.contains(r#"data-path="/b/television/mr_robot_s01_ep01.mkv""#));
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{format_err, Error};
+use postgres_query::query;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, str::FromStr};
+
+use crate::{
+    pgpool::PgPool,
+    utils::{parse_absolute_episode, parse_file_stem},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Schema)]
+pub enum EpisodeNumbering {
+    #[serde(rename = "seasonal")]
+    Seasonal,
+    #[serde(rename = "absolute")]
+    Absolute,
+}
+
+impl Default for EpisodeNumbering {
+    fn default() -> Self {
+        Self::Seasonal
+    }
+}
+
+impl EpisodeNumbering {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Seasonal => "seasonal",
+            Self::Absolute => "absolute",
+        }
+    }
+
+    pub async fn get_for_show(pool: &PgPool, show: &str) -> Result<Self, Error> {
+        let query = query!(
+            "SELECT numbering FROM imdb_ratings WHERE show = $show",
+            show = show
+        );
+        let conn = pool.get().await?;
+        let numbering: Option<(StackString,)> = query.fetch_opt(&conn).await?;
+        match numbering {
+            Some((n,)) => n.parse(),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub async fn set_for_link(self, pool: &PgPool, link: &str) -> Result<u64, Error> {
+        let query = query!(
+            "UPDATE imdb_ratings SET numbering = $numbering WHERE link = $link",
+            numbering = self.to_str(),
+            link = link
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+impl fmt::Display for EpisodeNumbering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl FromStr for EpisodeNumbering {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "seasonal" => Ok(Self::Seasonal),
+            "absolute" => Ok(Self::Absolute),
+            _ => Err(format_err!("Invalid numbering {}", s)),
+        }
+    }
+}
+
+// Specials (season 0) don't count towards the absolute episode number
+pub async fn get_absolute_episode_order(
+    pool: &PgPool,
+    show: &str,
+) -> Result<Vec<(i32, i32)>, Error> {
+    let query = query!(
+        r#"
+            SELECT season, episode
+            FROM imdb_episodes
+            WHERE show = $show AND season > 0
+            ORDER BY airdate, season, episode
+        "#,
+        show = show
+    );
+    let conn = pool.get().await?;
+    query.fetch(&conn).await.map_err(Into::into)
+}
+
+pub fn absolute_to_seasonal(order: &[(i32, i32)], absolute: i32) -> Option<(i32, i32)> {
+    if absolute < 1 {
+        return None;
+    }
+    order.get((absolute - 1) as usize).copied()
+}
+
+pub async fn resolve_file_stem(
+    pool: &PgPool,
+    file_stem: &str,
+) -> Result<(StackString, i32, i32), Error> {
+    let parsed = parse_file_stem(file_stem);
+    let (show, absolute) = match parse_absolute_episode(file_stem) {
+        Some(x) => x,
+        None => return Ok(parsed),
+    };
+    if EpisodeNumbering::get_for_show(pool, &show).await? != EpisodeNumbering::Absolute {
+        return Ok(parsed);
+    }
+    let order = get_absolute_episode_order(pool, &show).await?;
+    Ok(match absolute_to_seasonal(&order, absolute) {
+        Some((season, episode)) => (show, season, episode),
+        None => parsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::episode_numbering::{absolute_to_seasonal, EpisodeNumbering};
+
+    #[test]
+    fn test_absolute_to_seasonal() {
+        let order = vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 2)];
+        assert_eq!(absolute_to_seasonal(&order, 1), Some((1, 1)));
+        assert_eq!(absolute_to_seasonal(&order, 4), Some((2, 1)));
+        assert_eq!(absolute_to_seasonal(&order, 6), None);
+        assert_eq!(absolute_to_seasonal(&order, 0), None);
+        assert_eq!(
+            "absolute".parse::<EpisodeNumbering>().ok(),
+            Some(EpisodeNumbering::Absolute)
+        );
+        assert!("weekly".parse::<EpisodeNumbering>().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{format_err, Error};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, process::Command, sync::Mutex, time::sleep};
+use tracing::{debug, error};
+
+use crate::config::Config;
+
+pub const HLS_PLAYLIST: &str = "index.m3u8";
+const HLS_SOURCE: &str = "source";
+const HLS_SEGMENT_SECONDS: &str = "6";
+const HLS_STARTUP_POLLS: usize = 60;
+const HLS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const BROWSER_EXTENSIONS: [&str; 3] = ["mp4", "m4v", "webm"];
+
+lazy_static! {
+    static ref HLS_JOBS: Mutex<HashSet<i32>> = Mutex::new(HashSet::new());
+}
+
+// Containers other than these (mkv, avi, ...) won't play in a <video> element directly
+pub fn needs_hls(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .map_or(true, |e| !BROWSER_EXTENSIONS.contains(&e.as_str()))
+}
+
+pub fn hls_root(config: &Config) -> Result<PathBuf, Error> {
+    config
+        .video_playback_path
+        .as_ref()
+        .map(|p| p.join("videos").join("hls"))
+        .ok_or_else(|| format_err!("video playback path does not exist"))
+}
+
+pub fn hls_url(idx: i32) -> String {
+    format!("/list/play/{}/hls/{}", idx, HLS_PLAYLIST)
+}
+
+fn is_valid_segment(name: &str) -> bool {
+    name.strip_prefix("segment_")
+        .and_then(|s| s.strip_suffix(".ts"))
+        .map_or(false, |s| {
+            !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+pub fn hls_file(config: &Config, idx: i32, name: &str) -> Result<PathBuf, Error> {
+    if name != HLS_PLAYLIST && !is_valid_segment(name) {
+        return Err(format_err!("Invalid hls file {}", name));
+    }
+    hls_root(config).map(|p| p.join(idx.to_string()).join(name))
+}
+
+// Packaged output is only reused when it was produced from the same file with the same mtime
+async fn source_key(input: &Path) -> Result<String, Error> {
+    let modified = fs::metadata(input).await?.modified()?;
+    let mtime = modified.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(format!("{}\n{}", input.to_string_lossy(), mtime))
+}
+
+async fn is_packaged(output_dir: &Path, key: &str) -> bool {
+    fs::read_to_string(output_dir.join(HLS_SOURCE))
+        .await
+        .map_or(false, |s| s == key)
+}
+
+// Starts ffmpeg packaging the file into segments unless it is already running or
+// finished for the current version of the file, then waits for the playlist to show up
+pub async fn start_hls(config: &Config, idx: i32, input: &Path) -> Result<PathBuf, Error> {
+    let playlist = hls_file(config, idx, HLS_PLAYLIST)?;
+    let output_dir = playlist
+        .parent()
+        .ok_or_else(|| format_err!("No parent directory"))?
+        .to_path_buf();
+    {
+        let mut jobs = HLS_JOBS.lock().await;
+        if !jobs.contains(&idx) {
+            if !input.exists() {
+                return Err(format_err!("{:?} does not exist", input));
+            }
+            let key = source_key(input).await?;
+            if is_packaged(&output_dir, &key).await {
+                // Rewriting the marker refreshes its mtime, which is what cleanup_hls
+                // measures the ttl against
+                fs::write(output_dir.join(HLS_SOURCE), key).await?;
+                return Ok(playlist);
+            }
+            // Left over from a failed or interrupted run, or from an older version of the file
+            if output_dir.exists() {
+                fs::remove_dir_all(&output_dir).await?;
+            }
+            fs::create_dir_all(&output_dir).await?;
+            let mut child = Command::new("ffmpeg")
+                .args(&["-hide_banner", "-loglevel", "error", "-i"])
+                .arg(input)
+                .args(&[
+                    "-c:v", "libx264", "-preset", "veryfast", "-c:a", "aac", "-ac", "2",
+                ])
+                .args(&["-f", "hls", "-hls_time", HLS_SEGMENT_SECONDS])
+                .args(&["-hls_playlist_type", "event", "-hls_segment_filename"])
+                .arg(output_dir.join("segment_%05d.ts"))
+                .arg(&playlist)
+                .spawn()?;
+            debug!("hls packaging {:?} into {:?}", input, output_dir);
+            jobs.insert(idx);
+            tokio::task::spawn(async move {
+                let success = match child.wait().await {
+                    Ok(status) if status.success() => true,
+                    Ok(status) => {
+                        error!("hls packaging of {} failed {}", idx, status);
+                        false
+                    }
+                    Err(e) => {
+                        error!("hls packaging of {} failed {}", idx, e);
+                        false
+                    }
+                };
+                let result = if success {
+                    fs::write(output_dir.join(HLS_SOURCE), key).await
+                } else {
+                    fs::remove_dir_all(&output_dir).await
+                };
+                if let Err(e) = result {
+                    error!("hls cleanup of {} failed {}", idx, e);
+                }
+                HLS_JOBS.lock().await.remove(&idx);
+            });
+        }
+    }
+    for _ in 0..HLS_STARTUP_POLLS {
+        if playlist.exists() {
+            return Ok(playlist);
+        }
+        if !HLS_JOBS.lock().await.contains(&idx) {
+            break;
+        }
+        sleep(HLS_POLL_INTERVAL).await;
+    }
+    if playlist.exists() {
+        Ok(playlist)
+    } else {
+        Err(format_err!("No hls playlist for {}", idx))
+    }
+}
+
+// A packaged directory was last used when it was written or last reused by start_hls,
+// directories without a marker are either still being packaged or were abandoned
+async fn last_used(output_dir: &Path) -> Result<SystemTime, Error> {
+    match fs::metadata(output_dir.join(HLS_SOURCE)).await {
+        Ok(metadata) => metadata.modified().map_err(Into::into),
+        Err(_) => fs::metadata(output_dir)
+            .await?
+            .modified()
+            .map_err(Into::into),
+    }
+}
+
+async fn remove_expired(root: &Path, ttl: Duration) -> Result<usize, Error> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let idx: i32 = match entry.file_name().to_string_lossy().parse() {
+            Ok(idx) => idx,
+            Err(_) => continue,
+        };
+        if HLS_JOBS.lock().await.contains(&idx) {
+            continue;
+        }
+        let output_dir = entry.path();
+        let age = now
+            .duration_since(last_used(&output_dir).await?)
+            .unwrap_or_default();
+        if age >= ttl {
+            fs::remove_dir_all(&output_dir).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// Packaged segments are roughly the size of the source file, so directories nobody has
+// played for hls_ttl_hours are removed and regenerated on demand
+pub async fn cleanup_hls(config: &Config) -> Result<usize, Error> {
+    let root = hls_root(config)?;
+    if !root.exists() {
+        return Ok(0);
+    }
+    remove_expired(&root, Duration::from_secs(config.hls_ttl_hours * 3600)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::{
+        fs::{create_dir_all, write},
+        path::Path,
+        time::Duration,
+    };
+    use tempfile::TempDir;
+
+    use crate::hls_stream::{
+        is_packaged, is_valid_segment, needs_hls, remove_expired, source_key, HLS_SOURCE,
+    };
+
+    #[test]
+    fn test_hls_files() {
+        assert!(needs_hls(Path::new("/shows/mr_robot_s01_ep01.mkv")));
+        assert!(needs_hls(Path::new("/shows/mr_robot_s01_ep01.AVI")));
+        assert!(!needs_hls(Path::new("/shows/mr_robot_s01_ep01.mp4")));
+        assert!(is_valid_segment("segment_00012.ts"));
+        assert!(!is_valid_segment("segment_.ts"));
+        assert!(!is_valid_segment("../segment_00012.ts"));
+    }
+
+    #[tokio::test]
+    async fn test_is_packaged() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let dir = tmp.path();
+        let input = dir.join("mr_robot_s01_ep01.mkv");
+        write(&input, b"0123456789")?;
+        let output_dir = dir.join("1");
+        create_dir_all(&output_dir)?;
+
+        let key = source_key(&input).await?;
+        assert!(!is_packaged(&output_dir, &key).await);
+        write(output_dir.join(HLS_SOURCE), &key)?;
+        assert!(is_packaged(&output_dir, &key).await);
+        let other = source_key(&dir.join("1").join(HLS_SOURCE)).await?;
+        assert!(!is_packaged(&output_dir, &other).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_expired() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let output_dir = tmp.path().join("1");
+        create_dir_all(&output_dir)?;
+        write(output_dir.join(HLS_SOURCE), b"key")?;
+        create_dir_all(tmp.path().join("not_a_session"))?;
+
+        assert_eq!(
+            remove_expired(tmp.path(), Duration::from_secs(3600)).await?,
+            0
+        );
+        assert!(output_dir.exists());
+        assert_eq!(remove_expired(tmp.path(), Duration::from_secs(0)).await?, 1);
+        assert!(!output_dir.exists());
+        assert!(tmp.path().join("not_a_session").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use stack_string::StackString;
+use std::{collections::HashSet, path::Path};
+
+use crate::{config::Config, pgpool::PgPool, utils::parse_file_stem};
+
+#[derive(FromSqlRow)]
+struct WatchedEpisode {
+    show: StackString,
+    season: i32,
+    episode: i32,
+}
+
+#[derive(FromSqlRow)]
+struct QueuedEntry {
+    collection_idx: i32,
+    path: StackString,
+}
+
+// Scrobbles from any of the household accounts (or any account at all when
+// none are configured), matched to imdb shows and episodes by title, plus the
+// collection entries marked watched by a user linked to one of those accounts
+#[derive(Debug, Default, Clone)]
+pub struct HouseholdWatched {
+    movies: HashSet<StackString>,
+    episodes: HashSet<(StackString, i32, i32)>,
+    paths: HashSet<StackString>,
+}
+
+impl HouseholdWatched {
+    pub async fn load(config: &Config, pool: &PgPool) -> Result<Self, Error> {
+        let accounts: Vec<&str> = config
+            .household_accounts
+            .iter()
+            .map(StackString::as_str)
+            .collect();
+        let conn = pool.get().await?;
+
+        let query = query!(
+            r#"
+                SELECT DISTINCT c.show
+                FROM plex_event p
+                JOIN imdb_ratings c ON lower(c.title) = lower(p.title)
+                WHERE p.event = 'media.scrobble'
+                  AND p.grandparent_title IS NULL
+                  AND NOT coalesce(c.istv, false)
+                  AND (cardinality($accounts::text[]) = 0 OR p.account = ANY($accounts))
+            "#,
+            accounts = accounts
+        );
+        let movies: Vec<(StackString,)> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT DISTINCT d.show, d.season, d.episode
+                FROM plex_event p
+                JOIN imdb_ratings c ON lower(c.title) = lower(p.grandparent_title)
+                JOIN imdb_episodes d ON d.show = c.show
+                    AND p.parent_title = 'Season ' || d.season
+                    AND lower(d.eptitle) = lower(p.title)
+                WHERE p.event = 'media.scrobble'
+                  AND c.istv
+                  AND (cardinality($accounts::text[]) = 0 OR p.account = ANY($accounts))
+            "#,
+            accounts = accounts
+        );
+        let episodes: Vec<WatchedEpisode> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT DISTINCT m.path
+                FROM user_watched w
+                JOIN movie_collection m ON m.idx = w.collection_idx
+                LEFT JOIN user_preferences u ON u.email = w.email
+                WHERE cardinality($accounts::text[]) = 0 OR u.plex_account = ANY($accounts)
+            "#,
+            accounts = accounts
+        );
+        let paths: Vec<(StackString,)> = query.fetch(&conn).await?;
+
+        Ok(Self {
+            movies: movies.into_iter().map(|(show,)| show).collect(),
+            episodes: episodes
+                .into_iter()
+                .map(|e| (e.show, e.season, e.episode))
+                .collect(),
+            paths: paths.into_iter().map(|(path,)| path).collect(),
+        })
+    }
+
+    pub fn is_watched(&self, path: &str) -> bool {
+        if self.paths.contains(path) {
+            return true;
+        }
+        let file_stem = Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let (show, season, episode) = parse_file_stem(&file_stem);
+        if season == -1 {
+            self.movies.contains(&show)
+        } else {
+            self.episodes.contains(&(show, season, episode))
+        }
+    }
+
+    // Collection entries in the queue that the household has already seen, these are
+    // left out of the queue and tv show listings by the database queries
+    pub async fn watched_queue_entries(&self, pool: &PgPool) -> Result<Vec<i32>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.collection_idx, b.path
+                FROM movie_queue a
+                JOIN movie_collection b ON a.collection_idx=b.idx
+            "#
+        );
+        let conn = pool.get().await?;
+        let queued: Vec<QueuedEntry> = query.fetch(&conn).await?;
+        Ok(queued
+            .into_iter()
+            .filter(|q| self.is_watched(&q.path))
+            .map(|q| q.collection_idx)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::household_watched::HouseholdWatched;
+
+    #[test]
+    fn test_is_watched() {
+        let mut watched = HouseholdWatched::default();
+        watched.movies.insert("the_matrix".into());
+        watched.episodes.insert(("mr_robot".into(), 4, 2));
+        watched
+            .paths
+            .insert("/tmp/television/mr_robot_s04_ep05.mp4".into());
+
+        assert!(watched.is_watched("/tmp/movies/the_matrix.mp4"));
+        assert!(watched.is_watched("/tmp/television/mr_robot_s04_ep02.mp4"));
+        assert!(!watched.is_watched("/tmp/television/mr_robot_s04_ep03.mp4"));
+        assert!(watched.is_watched("/tmp/television/mr_robot_s04_ep05.mp4"));
+        assert!(!watched.is_watched("/tmp/movies/the_matrix_reloaded.mp4"));
+    }
+}
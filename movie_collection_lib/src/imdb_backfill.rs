@@ -0,0 +1,162 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use stack_string::StackString;
+use std::{collections::HashSet, fmt, time::Duration};
+use stdout_channel::StdoutChannel;
+use tokio::time::sleep;
+
+use crate::{
+    config::Config,
+    parse_imdb::{ParseImdb, ParseImdbOptions},
+    pgpool::PgPool,
+};
+
+#[derive(FromSqlRow, Debug, Clone)]
+struct BackfillShow {
+    show: StackString,
+    link: StackString,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BackfillReport {
+    pub succeeded: Vec<(StackString, usize)>,
+    pub failed: Vec<(StackString, StackString)>,
+    pub skipped: usize,
+}
+
+impl fmt::Display for BackfillReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (show, added) in &self.succeeded {
+            writeln!(f, "success {} added {}", show, added)?;
+        }
+        for (show, error) in &self.failed {
+            writeln!(f, "failed {} {}", show, error)?;
+        }
+        write!(
+            f,
+            "succeeded {} failed {} skipped {}",
+            self.succeeded.len(),
+            self.failed.len(),
+            self.skipped
+        )
+    }
+}
+
+async fn get_tv_shows(pool: &PgPool) -> Result<Vec<BackfillShow>, Error> {
+    let query = query!("SELECT show, link FROM imdb_ratings WHERE istv ORDER BY show");
+    let conn = pool.get().await?;
+    query.fetch(&conn).await.map_err(Into::into)
+}
+
+async fn get_completed(pool: &PgPool) -> Result<HashSet<StackString>, Error> {
+    let query = query!("SELECT show FROM imdb_backfill WHERE status = 'success'");
+    let conn = pool.get().await?;
+    let rows: Vec<(StackString,)> = query.fetch(&conn).await?;
+    Ok(rows.into_iter().map(|(show,)| show).collect())
+}
+
+async fn record_checkpoint(
+    pool: &PgPool,
+    show: &BackfillShow,
+    added: usize,
+    error: Option<&str>,
+) -> Result<(), Error> {
+    let status = if error.is_some() { "failed" } else { "success" };
+    let added = added as i64;
+    let query = query!(
+        r#"
+            INSERT INTO imdb_backfill (show, link, status, added, error, last_modified)
+            VALUES ($show, $link, $status, $added, $error, now())
+            ON CONFLICT (show) DO UPDATE
+            SET link=EXCLUDED.link, status=EXCLUDED.status, added=EXCLUDED.added,
+                error=EXCLUDED.error, last_modified=now()
+        "#,
+        show = show.show,
+        link = show.link,
+        status = status,
+        added = added,
+        error = error
+    );
+    let conn = pool.get().await?;
+    query.execute(&conn).await?;
+    Ok(())
+}
+
+// Returns the number of episodes that weren't in imdb_episodes before the update
+pub(crate) async fn refresh_show(
+    parse_imdb: &ParseImdb,
+    show: &str,
+    link: &str,
+) -> Result<usize, Error> {
+    let opts = ParseImdbOptions {
+        tv: true,
+        do_update: true,
+        update_database: true,
+        imdb_link: Some(link.into()),
+        show: show.into(),
+        ..ParseImdbOptions::default()
+    };
+    let output = parse_imdb.parse_imdb_worker(&opts).await?;
+    Ok(output
+        .iter()
+        .filter(|line| line.iter().any(|l| l.starts_with("not exists")))
+        .count())
+}
+
+pub async fn reset_imdb_backfill(pool: &PgPool) -> Result<u64, Error> {
+    let query = query!("DELETE FROM imdb_backfill");
+    let conn = pool.get().await?;
+    query.execute(&conn).await.map_err(Into::into)
+}
+
+// Shows already marked as successful are skipped, so an interrupted run picks
+// up where it left off; failed shows are retried on the next run
+pub async fn run_imdb_backfill(
+    config: &Config,
+    pool: &PgPool,
+    stdout: &StdoutChannel<StackString>,
+    delay: Duration,
+) -> Result<BackfillReport, Error> {
+    let completed = get_completed(pool).await?;
+    let parse_imdb = ParseImdb::new(config, pool, stdout);
+    let mut report = BackfillReport::default();
+
+    for show in get_tv_shows(pool).await? {
+        if completed.contains(&show.show) {
+            report.skipped += 1;
+            continue;
+        }
+        match refresh_show(&parse_imdb, &show.show, &show.link).await {
+            Ok(added) => {
+                record_checkpoint(pool, &show, added, None).await?;
+                stdout.send(format!("backfill {} added {}", show.show, added));
+                report.succeeded.push((show.show, added));
+            }
+            Err(e) => {
+                let error = e.to_string();
+                record_checkpoint(pool, &show, 0, Some(&error)).await?;
+                stdout.send(format!("backfill {} failed {}", show.show, error));
+                report.failed.push((show.show, error.into()));
+            }
+        }
+        sleep(delay).await;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::imdb_backfill::BackfillReport;
+
+    #[test]
+    fn test_backfill_report() {
+        let report = BackfillReport {
+            succeeded: vec![("the_expanse".into(), 3)],
+            failed: vec![("mr_robot".into(), "timed out".into())],
+            skipped: 2,
+        };
+        let expected = "success the_expanse added 3\nfailed mr_robot timed out\nsucceeded 1 \
+                        failed 1 skipped 2";
+        assert_eq!(report.to_string(), expected);
+    }
+}
@@ -4,9 +4,11 @@ use postgres_query::{query, query_dyn, FromSqlRow};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
-use crate::{naivedate_wrapper::NaiveDateWrapper, pgpool::PgPool};
+use crate::{naivedate_wrapper::NaiveDateWrapper, pgpool::PgPool, sync_cursor::SyncCursor};
+
+const UPSERT_BATCH_SIZE: usize = 1000;
 
 #[derive(Clone, Serialize, Deserialize, FromSqlRow, Schema)]
 pub struct ImdbEpisodes {
@@ -105,10 +107,62 @@ impl ImdbEpisodes {
         query.fetch(&conn).await.map_err(Into::into)
     }
 
-    pub async fn insert_episode(&self, pool: &PgPool) -> Result<(), Error> {
-        if self.get_index(pool).await?.is_some() {
-            return self.update_episode(pool).await;
+    pub async fn get_episodes_page(
+        pool: &PgPool,
+        cursor: SyncCursor,
+        limit: usize,
+    ) -> Result<(Vec<Self>, Option<SyncCursor>), Error> {
+        #[derive(FromSqlRow)]
+        struct ImdbEpisodesSyncRow {
+            id: i32,
+            last_modified: DateTime<Utc>,
+            show: StackString,
+            title: StackString,
+            season: i32,
+            episode: i32,
+            airdate: NaiveDateWrapper,
+            rating: f64,
+            eptitle: StackString,
+            epurl: StackString,
         }
+
+        let query = query!(
+            r#"
+            SELECT a.id, a.last_modified, a.show, b.title, a.season, a.episode, a.airdate,
+                   cast(a.rating as double precision) as rating, a.eptitle, a.epurl
+            FROM imdb_episodes a
+            JOIN imdb_ratings b ON a.show = b.show
+            WHERE (a.last_modified, a.id) > ($last_modified, $id)
+            ORDER BY a.last_modified, a.id
+            LIMIT $limit
+        "#,
+            last_modified = cursor.last_modified,
+            id = cursor.id,
+            limit = limit as i64
+        );
+        let conn = pool.get().await?;
+        let rows: Vec<ImdbEpisodesSyncRow> = query.fetch(&conn).await?;
+        let next_cursor = SyncCursor::next_page(&rows, limit, |r| SyncCursor {
+            last_modified: r.last_modified,
+            id: r.id,
+        });
+        let episodes = rows
+            .into_iter()
+            .map(|r| Self {
+                show: r.show,
+                title: r.title,
+                season: r.season,
+                episode: r.episode,
+                airdate: r.airdate,
+                rating: r.rating,
+                eptitle: r.eptitle,
+                epurl: r.epurl,
+            })
+            .collect();
+        Ok((episodes, next_cursor))
+    }
+
+    pub async fn upsert_episode(&self, pool: &PgPool) -> Result<(), Error> {
         let query = query_dyn!(
             &format!(
                 r#"
@@ -116,6 +170,12 @@ impl ImdbEpisodes {
                     (show, season, episode, airdate, rating, eptitle, epurl, last_modified)
                     VALUES
                     ($show, $season, $episode, $airdate, {}, $eptitle, $epurl, now())
+                    ON CONFLICT (show, season, episode) DO UPDATE
+                    SET rating=EXCLUDED.rating,
+                        eptitle=EXCLUDED.eptitle,
+                        epurl=coalesce(nullif(EXCLUDED.epurl, ''), imdb_episodes.epurl),
+                        airdate=EXCLUDED.airdate,
+                        last_modified=now()
                 "#,
                 self.rating
             ),
@@ -130,25 +190,63 @@ impl ImdbEpisodes {
         query.execute(&conn).await.map(|_| ()).map_err(Into::into)
     }
 
-    pub async fn update_episode(&self, pool: &PgPool) -> Result<(), Error> {
-        let query = query_dyn!(
-            &format!(
+    // One statement per batch instead of a round trip per episode, later entries for the
+    // same episode win since a single upsert can't touch a row twice
+    pub async fn upsert_episodes(pool: &PgPool, episodes: &[Self]) -> Result<u64, Error> {
+        let episodes: BTreeMap<_, _> = episodes
+            .iter()
+            .map(|e| ((e.show.as_str(), e.season, e.episode), e))
+            .collect();
+        let episodes: Vec<_> = episodes.values().copied().collect();
+
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let mut updated = 0;
+        for chunk in episodes.chunks(UPSERT_BATCH_SIZE) {
+            let shows: Vec<&str> = chunk.iter().map(|e| e.show.as_str()).collect();
+            let seasons: Vec<i32> = chunk.iter().map(|e| e.season).collect();
+            let episode_numbers: Vec<i32> = chunk.iter().map(|e| e.episode).collect();
+            let airdates: Vec<NaiveDate> = chunk.iter().map(|e| e.airdate.into()).collect();
+            let ratings: Vec<f64> = chunk.iter().map(|e| e.rating).collect();
+            let eptitles: Vec<&str> = chunk.iter().map(|e| e.eptitle.as_str()).collect();
+            let epurls: Vec<&str> = chunk.iter().map(|e| e.epurl.as_str()).collect();
+            let query = query!(
                 r#"
-                UPDATE imdb_episodes
-                SET rating={},eptitle=$eptitle,epurl=$epurl,airdate=$airdate,last_modified=now()
-                WHERE show=$show AND season=$season AND episode=$episode
-            "#,
-                self.rating
-            ),
-            eptitle = self.eptitle,
-            epurl = self.epurl,
-            airdate = self.airdate,
-            show = self.show,
-            season = self.season,
-            episode = self.episode
-        )?;
-        let conn = pool.get().await?;
-        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+                    INSERT INTO imdb_episodes
+                    (show, season, episode, airdate, rating, eptitle, epurl, last_modified)
+                    SELECT show, season, episode, airdate, cast(rating as numeric), eptitle,
+                           epurl, now()
+                    FROM unnest(
+                        $shows::text[], $seasons::int[], $episodes::int[], $airdates::date[],
+                        $ratings::double precision[], $eptitles::text[], $epurls::text[]
+                    ) AS t(show, season, episode, airdate, rating, eptitle, epurl)
+                    ON CONFLICT (show, season, episode) DO UPDATE
+                    SET rating=EXCLUDED.rating,
+                        eptitle=EXCLUDED.eptitle,
+                        epurl=coalesce(nullif(EXCLUDED.epurl, ''), imdb_episodes.epurl),
+                        airdate=EXCLUDED.airdate,
+                        last_modified=now()
+                "#,
+                shows = shows,
+                seasons = seasons,
+                episodes = episode_numbers,
+                airdates = airdates,
+                ratings = ratings,
+                eptitles = eptitles,
+                epurls = epurls
+            );
+            updated += tran.execute(query.sql(), query.parameters()).await?;
+        }
+        tran.commit().await?;
+        Ok(updated)
+    }
+
+    pub async fn insert_episode(&self, pool: &PgPool) -> Result<(), Error> {
+        self.upsert_episode(pool).await
+    }
+
+    pub async fn update_episode(&self, pool: &PgPool) -> Result<(), Error> {
+        self.upsert_episode(pool).await
     }
 
     pub fn get_string_vec(&self) -> Vec<StackString> {
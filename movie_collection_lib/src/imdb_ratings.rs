@@ -1,13 +1,30 @@
 use anyhow::Error;
 use chrono::{DateTime, Utc};
-use log::debug;
 use postgres_query::{query, query_dyn, FromSqlRow, Parameter};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
 use std::fmt;
+use thiserror::Error as ThisError;
+use tracing::debug;
 
-use crate::{pgpool::PgPool, tv_show_source::TvShowSource, utils::option_string_wrapper};
+use crate::{
+    media_ids::{MediaId, MediaIdType},
+    pgpool::PgPool,
+    sync_cursor::SyncCursor,
+    tv_show_source::TvShowSource,
+    utils::option_string_wrapper,
+};
+
+/// Returned by `update_show`, which would otherwise silently do nothing for an unknown
+/// show, lookups return `Option` and inserts keep returning `anyhow::Error`
+#[derive(ThisError, Debug)]
+pub enum ImdbRatingsError {
+    #[error("Show not found: {0}")]
+    NotFound(StackString),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, FromSqlRow, Schema)]
 pub struct ImdbRatings {
@@ -56,10 +73,28 @@ impl ImdbRatings {
         );
         debug!("{:?}", self);
         let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        let query = query!(
+            r#"
+                INSERT INTO media_ids (show_id, id_type, id_value, last_modified)
+                SELECT index, 'imdb', link, now()
+                FROM imdb_ratings
+                WHERE show = $show AND link IS NOT NULL
+                ON CONFLICT DO NOTHING
+            "#,
+            show = self.show
+        );
         query.execute(&conn).await.map(|_| ()).map_err(Into::into)
     }
 
-    pub async fn update_show(&self, pool: &PgPool) -> Result<(), Error> {
+    pub async fn update_show(&self, pool: &PgPool) -> Result<(), ImdbRatingsError> {
+        if self.update_show_row(pool).await? == 0 {
+            return Err(ImdbRatingsError::NotFound(self.show.clone()));
+        }
+        Ok(())
+    }
+
+    async fn update_show_row(&self, pool: &PgPool) -> Result<u64, Error> {
         let mut bindings = Vec::new();
         let query = format!(
             r#"
@@ -86,10 +121,18 @@ impl ImdbRatings {
         );
         let query = query_dyn!(&query, show = self.show, ..bindings)?;
         let conn = pool.get().await?;
-        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+        query.execute(&conn).await.map_err(Into::into)
     }
 
     pub async fn get_show_by_link(link: &str, pool: &PgPool) -> Result<Option<Self>, Error> {
+        let (id_type, id_value) = MediaId::parse_id(link);
+        if id_type != MediaIdType::Imdb {
+            return match MediaId::get_show_id(pool, id_type, id_value).await? {
+                Some(index) => Self::get_show_by_index(index, pool).await,
+                None => Ok(None),
+            };
+        }
+        let link = id_value;
         let query = query!(
             r#"
                 SELECT index, show, title, link, rating, istv, source
@@ -102,6 +145,19 @@ impl ImdbRatings {
         query.fetch_opt(&conn).await.map_err(Into::into)
     }
 
+    pub async fn get_show_by_index(index: i32, pool: &PgPool) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT index, show, title, link, rating, istv, source
+                FROM imdb_ratings
+                WHERE index = $index
+            "#,
+            index = index
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
     pub async fn get_shows_after_timestamp(
         timestamp: DateTime<Utc>,
         pool: &PgPool,
@@ -118,6 +174,56 @@ impl ImdbRatings {
         query.fetch(&conn).await.map_err(Into::into)
     }
 
+    pub async fn get_shows_page(
+        pool: &PgPool,
+        cursor: SyncCursor,
+        limit: usize,
+    ) -> Result<(Vec<Self>, Option<SyncCursor>), Error> {
+        #[derive(FromSqlRow)]
+        struct ImdbRatingsSyncRow {
+            last_modified: DateTime<Utc>,
+            index: i32,
+            show: StackString,
+            title: Option<StackString>,
+            link: StackString,
+            rating: Option<f64>,
+            istv: Option<bool>,
+            source: Option<TvShowSource>,
+        }
+
+        let query = query!(
+            r#"
+                SELECT last_modified, index, show, title, link, rating, istv, source
+                FROM imdb_ratings
+                WHERE (last_modified, index) > ($last_modified, $index)
+                ORDER BY last_modified, index
+                LIMIT $limit
+            "#,
+            last_modified = cursor.last_modified,
+            index = cursor.id,
+            limit = limit as i64
+        );
+        let conn = pool.get().await?;
+        let rows: Vec<ImdbRatingsSyncRow> = query.fetch(&conn).await?;
+        let next_cursor = SyncCursor::next_page(&rows, limit, |r| SyncCursor {
+            last_modified: r.last_modified,
+            id: r.index,
+        });
+        let shows = rows
+            .into_iter()
+            .map(|r| Self {
+                index: r.index,
+                show: r.show,
+                title: r.title,
+                link: r.link,
+                rating: r.rating,
+                istv: r.istv,
+                source: r.source,
+            })
+            .collect();
+        Ok((shows, next_cursor))
+    }
+
     pub fn get_string_vec(&self) -> Vec<StackString> {
         vec![
             self.show.clone(),
@@ -0,0 +1,177 @@
+use anyhow::{format_err, Error};
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::time::Duration as StdDuration;
+use stdout_channel::StdoutChannel;
+use tokio::{sync::Mutex, time::sleep};
+
+use crate::{
+    config::Config,
+    datetime_wrapper::DateTimeWrapper,
+    imdb_backfill::{refresh_show, BackfillReport},
+    parse_imdb::ParseImdb,
+    pgpool::PgPool,
+    utils::escape_html,
+};
+
+lazy_static! {
+    static ref REFRESH_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct ImdbRefreshStatus {
+    pub show: StackString,
+    pub link: StackString,
+    pub title: StackString,
+    pub last_refreshed: Option<DateTimeWrapper>,
+    pub refresh_error: Option<StackString>,
+}
+
+impl ImdbRefreshStatus {
+    pub fn is_running() -> bool {
+        REFRESH_LOCK.try_lock().is_err()
+    }
+
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT show, link, title, last_refreshed, refresh_error
+                FROM imdb_ratings
+                WHERE istv
+                ORDER BY last_refreshed NULLS FIRST, show
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    async fn get_stale(pool: &PgPool, stale_days: i64) -> Result<Vec<Self>, Error> {
+        let cutoff: DateTimeWrapper = (Utc::now() - Duration::days(stale_days)).into();
+        let query = query!(
+            r#"
+                SELECT show, link, title, last_refreshed, refresh_error
+                FROM imdb_ratings
+                WHERE istv
+                  AND (last_refreshed IS NULL OR last_refreshed < $cutoff)
+                ORDER BY last_refreshed NULLS FIRST, show
+            "#,
+            cutoff = cutoff
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    async fn set_refreshed(&self, pool: &PgPool, error: Option<&str>) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE imdb_ratings
+                SET last_refreshed=now(), refresh_error=$error
+                WHERE show = $show
+            "#,
+            show = self.show,
+            error = error
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub async fn run_refresh(
+        config: &Config,
+        pool: &PgPool,
+        stdout: &StdoutChannel<StackString>,
+        delay: StdDuration,
+    ) -> Result<BackfillReport, Error> {
+        let _guard = REFRESH_LOCK
+            .try_lock()
+            .map_err(|_| format_err!("IMDB refresh already running"))?;
+        let parse_imdb = ParseImdb::new(config, pool, stdout);
+        let mut report = BackfillReport::default();
+
+        let shows = Self::get_stale(pool, config.imdb_refresh_stale_days).await?;
+        for show in shows {
+            match refresh_show(&parse_imdb, &show.show, &show.link).await {
+                Ok(added) => {
+                    show.set_refreshed(pool, None).await?;
+                    report.succeeded.push((show.show, added));
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    show.set_refreshed(pool, Some(&error)).await?;
+                    report.failed.push((show.show, error.into()));
+                }
+            }
+            sleep(delay).await;
+        }
+        Ok(report)
+    }
+
+    fn get_html(&self) -> StackString {
+        let refreshed = self.last_refreshed.map_or_else(
+            || "never".to_string(),
+            |r| r.format("%Y-%m-%d %H:%M").to_string(),
+        );
+        format!(
+            r#"<tr><td><a href="javascript:updateMainArticle('/list/trakt/watched/list/{link}')">{title}</a></td><td>{refreshed}</td><td>{error}</td></tr>"#,
+            link = self.link,
+            title = escape_html(&self.title),
+            refreshed = refreshed,
+            error = escape_html(self.refresh_error.as_deref().unwrap_or("")),
+        )
+        .into()
+    }
+
+    pub fn get_html_table(entries: &[Self], running: bool) -> StackString {
+        let rows: Vec<_> = entries.iter().map(Self::get_html).collect();
+        let failed = entries.iter().filter(|e| e.refresh_error.is_some()).count();
+        format!(
+            r#"
+            <h3>IMDB episode refresh {}: {} shows, {} failed</h3>
+            <table border="0">
+            <tr><th>Show</th><th>Last Refreshed</th><th>Error</th></tr>
+            {}
+            </table>
+            "#,
+            if running { "running" } else { "idle" },
+            entries.len(),
+            failed,
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::imdb_refresh::ImdbRefreshStatus;
+
+    #[test]
+    fn test_imdb_refresh_html() {
+        let entries = vec![
+            ImdbRefreshStatus {
+                show: "mr_robot".into(),
+                link: "tt4158110".into(),
+                title: "Mr. Robot".into(),
+                last_refreshed: None,
+                refresh_error: Some("<timed out>".into()),
+            },
+            ImdbRefreshStatus {
+                show: "the_expanse".into(),
+                link: "tt3230854".into(),
+                title: "The Expanse".into(),
+                last_refreshed: Some(Utc.ymd(2021, 3, 4).and_hms(5, 6, 7).into()),
+                refresh_error: None,
+            },
+        ];
+        let html = ImdbRefreshStatus::get_html_table(&entries, false);
+        assert!(html.contains("idle: 2 shows, 1 failed"));
+        assert!(html.contains("<td>never</td><td>&lt;timed out&gt;</td>"));
+        assert!(html.contains("<td>2021-03-04 05:06</td>"));
+    }
+}
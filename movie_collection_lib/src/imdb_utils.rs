@@ -1,7 +1,7 @@
 use anyhow::Error;
 use chrono::NaiveDate;
 use futures::future::try_join_all;
-use log::debug;
+use lazy_static::lazy_static;
 use reqwest::{Client, Url};
 use select::{
     document::Document,
@@ -9,10 +9,67 @@ use select::{
 };
 use serde::Deserialize;
 use stack_string::StackString;
-use std::fmt;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::{fs, sync::Mutex, time::sleep};
+use tracing::debug;
 
 use crate::utils::{option_string_wrapper, ExponentialRetry};
 
+const IMDB_CACHE_TTL: Duration = Duration::from_secs(86400);
+const IMDB_RATE_CAPACITY: f64 = 5.0;
+const IMDB_RATE_PER_SEC: f64 = 1.0;
+
+lazy_static! {
+    static ref IMDB_RATE_LIMITER: Mutex<TokenBucket> =
+        Mutex::new(TokenBucket::new(IMDB_RATE_CAPACITY, IMDB_RATE_PER_SEC));
+}
+
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, rate: f64) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+async fn imdb_rate_limit() {
+    loop {
+        let wait = IMDB_RATE_LIMITER.lock().await.try_acquire(Instant::now());
+        match wait {
+            Some(wait) => sleep(wait).await,
+            None => return,
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct ImdbTuple {
     pub title: StackString,
@@ -62,6 +119,26 @@ impl fmt::Display for ImdbEpisodeResult {
 
 pub struct ImdbConnection {
     client: Client,
+    cache_dir: Option<PathBuf>,
+}
+
+pub fn extract_imdb_link(query: &str) -> Option<StackString> {
+    query
+        .trim()
+        .split(|c: char| c == '/' || c == '?' || c.is_whitespace())
+        .find(|s| s.len() > 2 && s.starts_with("tt") && s[2..].chars().all(|c| c.is_ascii_digit()))
+        .map(Into::into)
+}
+
+pub fn show_name_from_title(title: &str) -> StackString {
+    let title = title.split('(').next().unwrap_or(title);
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+        .into()
 }
 
 impl Default for ImdbConnection {
@@ -80,13 +157,56 @@ impl ImdbConnection {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            cache_dir: dirs::cache_dir().map(|d| d.join("movie_collection_rust").join("imdb")),
         }
     }
 
+    fn cache_path(&self, url: &Url) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        url.as_str().hash(&mut hasher);
+        self.cache_dir
+            .as_ref()
+            .map(|d| d.join(format!("{:016x}.html", hasher.finish())))
+    }
+
+    async fn read_cache(path: &PathBuf) -> Option<String> {
+        let modified = fs::metadata(path).await.ok()?.modified().ok()?;
+        if modified.elapsed().ok()? > IMDB_CACHE_TTL {
+            return None;
+        }
+        fs::read_to_string(path).await.ok()
+    }
+
+    async fn write_cache(path: &PathBuf, body: &str) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, body).await?;
+        Ok(())
+    }
+
+    async fn get_body(&self, url: &Url) -> Result<String, Error> {
+        let cache_path = self.cache_path(url);
+        if let Some(path) = &cache_path {
+            if let Some(body) = Self::read_cache(path).await {
+                debug!("imdb cache hit {}", url);
+                return Ok(body);
+            }
+        }
+        imdb_rate_limit().await;
+        let body = self.get(url).await?.error_for_status()?.text().await?;
+        if let Some(path) = &cache_path {
+            if let Err(e) = Self::write_cache(path, &body).await {
+                debug!("failed to write imdb cache {:?}", e);
+            }
+        }
+        Ok(body)
+    }
+
     pub async fn parse_imdb(&self, title: &str) -> Result<Vec<ImdbTuple>, Error> {
         let endpoint = "http://www.imdb.com/find?";
         let url = Url::parse_with_params(endpoint, &[("s", "all"), ("q", title)])?;
-        let body = self.get(&url).await?.text().await?;
+        let body = self.get_body(&url).await?;
 
         let tl_vec: Vec<_> = Document::from(body.as_str())
             .find(Class("result_text"))
@@ -125,7 +245,7 @@ impl ImdbConnection {
 
         let url = Url::parse("http://www.imdb.com/title/")?.join(title)?;
         debug!("{:?}", url);
-        let body = self.get(&url).await?.text().await?;
+        let body = self.get_body(&url).await?;
         Self::parse_imdb_rating_body(&body)
     }
 
@@ -168,7 +288,7 @@ impl ImdbConnection {
     ) -> Result<Vec<ImdbEpisodeResult>, Error> {
         let endpoint: String = format!("http://m.imdb.com/title/{}/episodes", imdb_id);
         let url = Url::parse(&endpoint)?;
-        let body = self.get(&url).await?.text().await?;
+        let body = self.get_body(&url).await?;
 
         let ep_season_vec: Vec<_> = Document::from(body.as_str())
             .find(Name("a"))
@@ -211,7 +331,7 @@ impl ImdbConnection {
         season: i32,
     ) -> Result<Vec<ImdbEpisodeResult>, Error> {
         let episodes_url = Url::parse(&episodes_url)?;
-        let body = self.get(&episodes_url).await?.text().await?;
+        let body = self.get_body(&episodes_url).await?;
 
         let mut results = Vec::new();
 
@@ -274,8 +394,59 @@ impl ImdbConnection {
 
 #[cfg(test)]
 mod tests {
-    use crate::imdb_utils::ImdbConnection;
+    use crate::imdb_utils::{extract_imdb_link, show_name_from_title, ImdbConnection, TokenBucket};
     use anyhow::Error;
+    use reqwest::Url;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_extract_imdb_link() {
+        assert_eq!(
+            extract_imdb_link("https://www.imdb.com/title/tt0903747/?ref_=nv_sr_1"),
+            Some("tt0903747".into())
+        );
+        assert_eq!(extract_imdb_link("tt0903747"), Some("tt0903747".into()));
+        assert_eq!(extract_imdb_link("breaking bad"), None);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(2.0, 1.0);
+        let now = Instant::now();
+        assert_eq!(bucket.try_acquire(now), None);
+        assert_eq!(bucket.try_acquire(now), None);
+        let wait = bucket.try_acquire(now).expect("bucket should be empty");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert_eq!(bucket.try_acquire(now + Duration::from_secs(1)), None);
+    }
+
+    #[tokio::test]
+    async fn test_imdb_cache() -> Result<(), Error> {
+        let conn = ImdbConnection {
+            cache_dir: Some("/tmp/imdb_cache_test".into()),
+            ..ImdbConnection::new()
+        };
+        let url = Url::parse("http://www.imdb.com/title/tt0903747")?;
+        let path = conn.cache_path(&url).expect("cache_dir is set");
+        assert_eq!(Some(path.clone()), conn.cache_path(&url));
+        ImdbConnection::write_cache(&path, "cached body").await?;
+        assert_eq!(
+            ImdbConnection::read_cache(&path).await.as_deref(),
+            Some("cached body")
+        );
+        assert_eq!(conn.get_body(&url).await?, "cached body");
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_name_from_title() {
+        assert_eq!(
+            show_name_from_title("Breaking Bad (2008) (TV Series)").as_str(),
+            "breaking_bad"
+        );
+        assert_eq!(show_name_from_title("Mr. Robot").as_str(), "mr_robot");
+    }
 
     #[test]
     fn test_parse_imdb_rating_body() -> Result<(), Error> {
@@ -0,0 +1,201 @@
+use anyhow::{format_err, Error};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+
+use crate::{pgpool::PgPool, utils::parse_file_stem};
+
+#[derive(FromSqlRow, Default, Debug, Serialize, Deserialize, Schema, Clone)]
+pub struct IntroMarker {
+    pub show: StackString,
+    pub season: i32,
+    pub intro_start: f64,
+    pub intro_end: f64,
+}
+
+impl IntroMarker {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.intro_start < 0.0 || self.intro_end <= self.intro_start {
+            Err(format_err!(
+                "Invalid intro marker {} {} {}",
+                self.show,
+                self.intro_start,
+                self.intro_end
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn get_markers(pool: &PgPool, show: Option<&str>) -> Result<Vec<Self>, Error> {
+        let conn = pool.get().await?;
+        if let Some(show) = show {
+            let query = query!(
+                r#"
+                    SELECT show, season, intro_start, intro_end
+                    FROM intro_markers
+                    WHERE show = $show
+                    ORDER BY season
+                "#,
+                show = show
+            );
+            query.fetch(&conn).await.map_err(Into::into)
+        } else {
+            let query = query!(
+                r#"
+                    SELECT show, season, intro_start, intro_end
+                    FROM intro_markers
+                    ORDER BY show, season
+                "#
+            );
+            query.fetch(&conn).await.map_err(Into::into)
+        }
+    }
+
+    pub async fn get_marker(pool: &PgPool, show: &str, season: i32) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT show, season, intro_start, intro_end
+                FROM intro_markers
+                WHERE show = $show AND season = $season
+            "#,
+            show = show,
+            season = season
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn upsert_marker(&self, pool: &PgPool) -> Result<(), Error> {
+        self.validate()?;
+        let query = query!(
+            r#"
+                INSERT INTO intro_markers (show, season, intro_start, intro_end, last_modified)
+                VALUES ($show, $season, $intro_start, $intro_end, now())
+                ON CONFLICT (show, season) DO UPDATE
+                SET intro_start=$intro_start, intro_end=$intro_end, last_modified=now()
+            "#,
+            show = self.show,
+            season = self.season,
+            intro_start = self.intro_start,
+            intro_end = self.intro_end
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    // Also clears the marker from the season's files so they stop skipping the old intro
+    pub async fn delete_marker(pool: &PgPool, show: &str, season: i32) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM intro_markers WHERE show = $show AND season = $season",
+            show = show,
+            season = season
+        );
+        let conn = pool.get().await?;
+        let deleted = query.execute(&conn).await?;
+        if deleted > 0 {
+            set_collection_markers(pool, show, season, None, None).await?;
+        }
+        Ok(deleted)
+    }
+
+    // Markers are the only source of intro times on the collection, so edits overwrite
+    // whatever an earlier version of the marker filled in
+    pub async fn apply_to_collection(&self, pool: &PgPool) -> Result<u64, Error> {
+        set_collection_markers(
+            pool,
+            &self.show,
+            self.season,
+            Some(self.intro_start),
+            Some(self.intro_end),
+        )
+        .await
+    }
+}
+
+fn path_season(path: &str) -> Option<i32> {
+    Path::new(path)
+        .file_stem()
+        .map(|s| parse_file_stem(&s.to_string_lossy()).1)
+}
+
+async fn set_collection_markers(
+    pool: &PgPool,
+    show: &str,
+    season: i32,
+    intro_start: Option<f64>,
+    intro_end: Option<f64>,
+) -> Result<u64, Error> {
+    #[derive(FromSqlRow)]
+    struct CollectionPath {
+        idx: i32,
+        path: StackString,
+    }
+
+    let query = query!(
+        r#"
+            SELECT idx, path
+            FROM movie_collection
+            WHERE show = $show
+              AND is_deleted = false
+              AND (intro_start IS DISTINCT FROM $intro_start
+                   OR intro_end IS DISTINCT FROM $intro_end)
+        "#,
+        show = show,
+        intro_start = intro_start,
+        intro_end = intro_end
+    );
+    let conn = pool.get().await?;
+    let entries: Vec<CollectionPath> = query.fetch(&conn).await?;
+    let mut updated = 0;
+    for entry in entries {
+        if path_season(&entry.path) != Some(season) {
+            continue;
+        }
+        let query = query!(
+            r#"
+                UPDATE movie_collection
+                SET intro_start=$intro_start, intro_end=$intro_end, last_modified=now()
+                WHERE idx = $idx
+            "#,
+            idx = entry.idx,
+            intro_start = intro_start,
+            intro_end = intro_end
+        );
+        updated += query.execute(&conn).await?;
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intro_markers::{path_season, IntroMarker};
+
+    #[test]
+    fn test_validate() {
+        let mut marker = IntroMarker {
+            show: "mr_robot".into(),
+            season: 1,
+            intro_start: 10.0,
+            intro_end: 70.0,
+        };
+        assert!(marker.validate().is_ok());
+        marker.intro_end = 5.0;
+        assert!(marker.validate().is_err());
+        marker.intro_start = -1.0;
+        marker.intro_end = 70.0;
+        assert!(marker.validate().is_err());
+    }
+
+    #[test]
+    fn test_path_season() {
+        assert_eq!(
+            path_season("/shows/mr_robot/season1/mr_robot_s01_ep01.mp4"),
+            Some(1)
+        );
+        assert_eq!(path_season("/shows/mr_robot_s02_ep10.mkv"), Some(2));
+        assert_eq!(path_season("/movies/the_matrix_reloaded.mp4"), Some(-1));
+    }
+}
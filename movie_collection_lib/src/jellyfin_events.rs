@@ -0,0 +1,276 @@
+use anyhow::{format_err, Error};
+use chrono::{DateTime, Utc};
+use postgres_query::{query, query_dyn, FromSqlRow, Parameter, Query};
+use reqwest::{Client, Url};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+
+use crate::{config::Config, datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+#[derive(FromSqlRow, Default, Debug, Serialize, Deserialize, Schema)]
+pub struct JellyfinEvent {
+    pub event: StackString,
+    pub account: StackString,
+    pub server: StackString,
+    pub item_id: Option<StackString>,
+    pub item_type: Option<StackString>,
+    pub device_name: Option<StackString>,
+    pub client_name: Option<StackString>,
+    pub title: Option<StackString>,
+    pub series_name: Option<StackString>,
+    pub season_number: Option<i32>,
+    pub episode_number: Option<i32>,
+    pub filename: Option<StackString>,
+    pub collection_idx: Option<i32>,
+    pub created_at: Option<DateTimeWrapper>,
+    pub last_modified: Option<DateTimeWrapper>,
+}
+
+impl From<WebhookPayload> for JellyfinEvent {
+    fn from(item: WebhookPayload) -> Self {
+        Self {
+            event: item.notification_type,
+            account: item.notification_username.unwrap_or_default(),
+            server: item.server_name.unwrap_or_default(),
+            item_id: item.item_id,
+            item_type: item.item_type,
+            device_name: item.device_name,
+            client_name: item.client_name,
+            title: item.name,
+            series_name: item.series_name,
+            season_number: item.season_number,
+            episode_number: item.episode_number,
+            filename: None,
+            collection_idx: None,
+            created_at: Some(Utc::now().into()),
+            last_modified: Some(Utc::now().into()),
+        }
+    }
+}
+
+impl JellyfinEvent {
+    pub fn get_from_payload(buf: &[u8]) -> Result<Self, Error> {
+        let object: WebhookPayload = serde_json::from_slice(buf)?;
+        Ok(object.into())
+    }
+
+    pub async fn link_metadata(
+        &mut self,
+        client: &JellyfinClient,
+        pool: &PgPool,
+    ) -> Result<(), Error> {
+        let item_id = match &self.item_id {
+            Some(item_id) if client.is_configured() => item_id,
+            _ => return Ok(()),
+        };
+        if let Some(filename) = client.get_filename(item_id).await? {
+            self.collection_idx = get_collection_idx(pool, &filename).await?;
+            self.filename = Some(filename);
+        }
+        Ok(())
+    }
+
+    pub async fn get_events(
+        pool: &PgPool,
+        start_timestamp: Option<DateTime<Utc>>,
+        event_type: Option<StackString>,
+        offset: Option<u64>,
+        limit: Option<u64>,
+        hidden_accounts: &[StackString],
+    ) -> Result<Vec<Self>, Error> {
+        let mut constraints = Vec::new();
+        let mut bindings = Vec::new();
+        let hidden_accounts = hidden_accounts.to_vec();
+        if !hidden_accounts.is_empty() {
+            constraints.push("account != ALL($hidden_accounts)");
+            bindings.push(("hidden_accounts", &hidden_accounts as Parameter));
+        }
+        if let Some(start_timestamp) = &start_timestamp {
+            constraints.push("created_at > $start_timestamp");
+            bindings.push(("start_timestamp", start_timestamp as Parameter));
+        }
+        if let Some(event_type) = &event_type {
+            constraints.push("event = $event");
+            bindings.push(("event", event_type as Parameter));
+        }
+        let query = format!(
+            "
+                SELECT event, account, server, item_id, item_type, device_name, client_name,
+                       title, series_name, season_number, episode_number, filename,
+                       collection_idx, created_at, last_modified
+                FROM jellyfin_event
+                {where} ORDER by created_at desc {limit} {offset}
+            ",
+            where = if constraints.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", constraints.join(" AND "))
+            },
+            limit = limit.map_or_else(String::new, |limit| format!("LIMIT {}", limit)),
+            offset = offset.map_or_else(String::new, |offset| format!("OFFSET {}", offset)),
+        );
+        let query: Query = query_dyn!(&query, ..bindings)?;
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn write_event(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            "
+            INSERT INTO jellyfin_event (event, account, server, item_id, item_type, device_name,
+                client_name, title, series_name, season_number, episode_number, filename,
+                collection_idx, created_at, last_modified)
+            VALUES ($event, $account, $server, $item_id, $item_type, $device_name, $client_name,
+                $title, $series_name, $season_number, $episode_number, $filename,
+                $collection_idx, $created_at, $last_modified)",
+            event = self.event,
+            account = self.account,
+            server = self.server,
+            item_id = self.item_id,
+            item_type = self.item_type,
+            device_name = self.device_name,
+            client_name = self.client_name,
+            title = self.title,
+            series_name = self.series_name,
+            season_number = self.season_number,
+            episode_number = self.episode_number,
+            filename = self.filename,
+            collection_idx = self.collection_idx,
+            created_at = self.created_at,
+            last_modified = self.last_modified,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+}
+
+async fn get_collection_idx(pool: &PgPool, filename: &str) -> Result<Option<i32>, Error> {
+    let file_name = Path::new(filename)
+        .file_name()
+        .map_or_else(|| filename.into(), |f| f.to_string_lossy());
+    // Compared with right() rather than LIKE so '_' and '%' in file names match literally
+    let suffix = format!("/{}", file_name);
+    let suffix_len = suffix.chars().count() as i32;
+    let query = query!(
+        r#"
+            SELECT idx
+            FROM movie_collection
+            WHERE (path = $path OR right(path, $suffix_len) = $suffix) AND is_deleted = false
+            ORDER BY path = $path DESC
+            LIMIT 1
+        "#,
+        path = filename,
+        suffix = suffix,
+        suffix_len = suffix_len
+    );
+    let conn = pool.get().await?;
+    let idx: Option<(i32,)> = query.fetch_opt(&conn).await?;
+    Ok(idx.map(|(x,)| x))
+}
+
+#[derive(Clone)]
+pub struct JellyfinClient {
+    config: Config,
+    client: Client,
+}
+
+impl JellyfinClient {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.config.jellyfin_url.is_some() && self.config.jellyfin_api_key.is_some()
+    }
+
+    pub async fn get_filename(&self, item_id: &str) -> Result<Option<StackString>, Error> {
+        #[derive(Deserialize)]
+        struct Item {
+            #[serde(rename = "Path")]
+            path: Option<StackString>,
+        }
+        #[derive(Deserialize)]
+        struct ItemsResponse {
+            #[serde(rename = "Items")]
+            items: Vec<Item>,
+        }
+
+        let base_url = self
+            .config
+            .jellyfin_url
+            .as_ref()
+            .ok_or_else(|| format_err!("No jellyfin url"))?;
+        let api_key = self
+            .config
+            .jellyfin_api_key
+            .as_ref()
+            .ok_or_else(|| format_err!("No jellyfin api key"))?;
+        let url = Url::parse_with_params(
+            &format!("{}/Items", base_url.trim_end_matches('/')),
+            &[("Ids", item_id), ("Fields", "Path")],
+        )?;
+        let resp: ItemsResponse = self
+            .client
+            .get(url)
+            .header("X-Emby-Token", api_key.as_str())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp.items.into_iter().find_map(|item| item.path))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct WebhookPayload {
+    pub notification_type: StackString,
+    pub notification_username: Option<StackString>,
+    pub server_name: Option<StackString>,
+    pub item_id: Option<StackString>,
+    pub item_type: Option<StackString>,
+    pub device_name: Option<StackString>,
+    pub client_name: Option<StackString>,
+    pub name: Option<StackString>,
+    pub series_name: Option<StackString>,
+    pub season_number: Option<i32>,
+    pub episode_number: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::jellyfin_events::JellyfinEvent;
+
+    #[test]
+    fn test_get_from_payload() -> Result<(), Error> {
+        let buf = br#"{
+            "NotificationType": "PlaybackStart",
+            "NotificationUsername": "user",
+            "ServerName": "jellyfin",
+            "ItemId": "a1b2c3",
+            "ItemType": "Episode",
+            "DeviceName": "Living Room",
+            "ClientName": "Jellyfin Web",
+            "Name": "Pilot",
+            "SeriesName": "The Show",
+            "SeasonNumber": 1,
+            "EpisodeNumber": 1
+        }"#;
+        let event = JellyfinEvent::get_from_payload(buf)?;
+        assert_eq!(event.event.as_str(), "PlaybackStart");
+        assert_eq!(event.account.as_str(), "user");
+        assert_eq!(event.item_id.as_ref().map(|s| s.as_str()), Some("a1b2c3"));
+        assert_eq!(event.season_number, Some(1));
+        assert!(event.filename.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::Error;
+use chrono::NaiveDate;
+use postgres_query::{query, FromSqlRow};
+use stack_string::StackString;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+use tokio::fs::write;
+
+use crate::{
+    pgpool::PgPool,
+    utils::{escape_html, parse_file_stem},
+};
+
+#[derive(FromSqlRow, Debug, Clone)]
+struct NfoEntry {
+    path: StackString,
+    show: StackString,
+    title: Option<StackString>,
+    link: StackString,
+    rating: Option<f64>,
+    istv: Option<bool>,
+}
+
+#[derive(FromSqlRow, Debug, Clone)]
+struct NfoEpisode {
+    show: StackString,
+    season: i32,
+    episode: i32,
+    epurl: StackString,
+    airdate: NaiveDate,
+    rating: Option<f64>,
+    eptitle: StackString,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NfoReport {
+    pub written: usize,
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, StackString)>,
+}
+
+impl fmt::Display for NfoReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (path, error) in &self.failed {
+            writeln!(f, "failed {} {}", path.to_string_lossy(), error)?;
+        }
+        write!(
+            f,
+            "written {} skipped {} failed {}",
+            self.written,
+            self.skipped,
+            self.failed.len()
+        )
+    }
+}
+
+fn rating_element(rating: Option<f64>) -> String {
+    rating.map_or_else(String::new, |r| format!("<rating>{:0.1}</rating>", r))
+}
+
+pub fn movie_nfo(title: &str, link: &str, rating: Option<f64>) -> StackString {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<movie><title>{}</title>{}<uniqueid type="imdb" default="true">{}</uniqueid></movie>
+"#,
+        escape_html(title),
+        rating_element(rating),
+        link
+    )
+    .into()
+}
+
+pub fn tvshow_nfo(title: &str, link: &str, rating: Option<f64>) -> StackString {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<tvshow><title>{}</title>{}<uniqueid type="imdb" default="true">{}</uniqueid></tvshow>
+"#,
+        escape_html(title),
+        rating_element(rating),
+        link
+    )
+    .into()
+}
+
+pub fn season_nfo(season: i32) -> StackString {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<season><title>Season {season}</title><seasonnumber>{season}</seasonnumber></season>
+"#,
+        season = season
+    )
+    .into()
+}
+
+fn episode_nfo(showtitle: &str, epi: &NfoEpisode) -> StackString {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<episodedetails><title>{}</title><showtitle>{}</showtitle><season>{}</season><episode>{}</episode><aired>{}</aired>{}<uniqueid type="imdb" default="true">{}</uniqueid></episodedetails>
+"#,
+        escape_html(&epi.eptitle),
+        escape_html(showtitle),
+        epi.season,
+        epi.episode,
+        epi.airdate,
+        rating_element(epi.rating),
+        epi.epurl
+    )
+    .into()
+}
+
+// Episodes live either directly in the show directory or in a season_N subdirectory
+pub fn show_directories(path: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
+    let parent = path.parent()?;
+    let is_season_dir = parent.file_name().map_or(false, |d| {
+        d.to_string_lossy().to_lowercase().starts_with("season")
+    });
+    if is_season_dir {
+        let show_dir = parent.parent()?;
+        Some((show_dir.to_path_buf(), Some(parent.to_path_buf())))
+    } else {
+        Some((parent.to_path_buf(), None))
+    }
+}
+
+async fn write_nfo(path: &Path, body: &str, overwrite: bool, report: &mut NfoReport) {
+    if path.exists() && !overwrite {
+        report.skipped += 1;
+        return;
+    }
+    match write(path, body).await {
+        Ok(_) => report.written += 1,
+        Err(e) => report
+            .failed
+            .push((path.to_path_buf(), e.to_string().into())),
+    }
+}
+
+pub async fn export_kodi_nfo(pool: &PgPool, overwrite: bool) -> Result<NfoReport, Error> {
+    let query = query!(
+        r#"
+            SELECT b.path, c.show, c.title, c.link, c.rating, c.istv
+            FROM movie_collection b
+            JOIN imdb_ratings c ON b.show_id = c.index
+            WHERE NOT b.is_deleted
+            ORDER BY b.path
+        "#
+    );
+    let conn = pool.get().await?;
+    let entries: Vec<NfoEntry> = query.fetch(&conn).await?;
+
+    let query = query!(
+        r#"
+            SELECT a.show, a.season, a.episode, a.epurl, a.airdate,
+                   cast(a.rating as double precision) as rating, a.eptitle
+            FROM imdb_episodes a
+            JOIN imdb_ratings b ON a.show = b.show
+            WHERE b.istv
+        "#
+    );
+    let episodes: Vec<NfoEpisode> = query.fetch(&conn).await?;
+    let episodes: HashMap<(StackString, i32, i32), NfoEpisode> = episodes
+        .into_iter()
+        .map(|e| ((e.show.clone(), e.season, e.episode), e))
+        .collect();
+
+    let mut report = NfoReport::default();
+    let mut nfo_dirs = HashSet::new();
+    for entry in entries {
+        let path = Path::new(entry.path.as_str());
+        if !path.exists() {
+            continue;
+        }
+        let nfo_path = path.with_extension("nfo");
+        let title = entry.title.as_ref().unwrap_or(&entry.show);
+        if !entry.istv.unwrap_or(false) {
+            let body = movie_nfo(title, &entry.link, entry.rating);
+            write_nfo(&nfo_path, &body, overwrite, &mut report).await;
+            continue;
+        }
+        let file_stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let (_, season, episode) = parse_file_stem(&file_stem);
+        let epi = match episodes.get(&(entry.show.clone(), season, episode)) {
+            Some(epi) => epi,
+            None => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        write_nfo(&nfo_path, &episode_nfo(title, epi), overwrite, &mut report).await;
+
+        if let Some((show_dir, season_dir)) = show_directories(path) {
+            if nfo_dirs.insert(show_dir.clone()) {
+                let body = tvshow_nfo(title, &entry.link, entry.rating);
+                write_nfo(&show_dir.join("tvshow.nfo"), &body, overwrite, &mut report).await;
+            }
+            if let Some(season_dir) = season_dir {
+                if nfo_dirs.insert(season_dir.clone()) {
+                    let body = season_nfo(season);
+                    write_nfo(
+                        &season_dir.join("season.nfo"),
+                        &body,
+                        overwrite,
+                        &mut report,
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::kodi_nfo::{movie_nfo, season_nfo, show_directories};
+
+    #[test]
+    fn test_nfo_contents() {
+        let nfo = movie_nfo("Bill & Ted", "tt0096928", Some(6.9));
+        assert!(nfo.contains("<title>Bill &amp; Ted</title><rating>6.9</rating>"));
+        assert!(nfo.contains(r#"<uniqueid type="imdb" default="true">tt0096928</uniqueid>"#));
+        assert!(season_nfo(2).contains("<seasonnumber>2</seasonnumber>"));
+    }
+
+    #[test]
+    fn test_show_directories() {
+        let path = Path::new("/media/television/mr_robot/season_2/mr_robot_s02_ep03.mp4");
+        assert_eq!(
+            show_directories(path),
+            Some((
+                PathBuf::from("/media/television/mr_robot"),
+                Some(PathBuf::from("/media/television/mr_robot/season_2"))
+            ))
+        );
+        let path = Path::new("/media/television/mr_robot/mr_robot_s02_ep03.mp4");
+        assert_eq!(
+            show_directories(path),
+            Some((PathBuf::from("/media/television/mr_robot"), None))
+        );
+    }
+}
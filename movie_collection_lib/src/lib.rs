@@ -14,22 +14,79 @@
 #![allow(clippy::inconsistent_struct_constructor)]
 #![allow(clippy::default_trait_access)]
 
+pub mod alert_rules;
+pub mod api_tokens;
+pub mod artwork;
+pub mod backup;
+pub mod clock;
+pub mod collection_feed;
+pub mod collection_watcher;
 pub mod config;
+pub mod credits_detection;
 pub mod datetime_wrapper;
+pub mod delete_confirm;
+pub mod download_requests;
+pub mod duplicates;
+pub mod episode_numbering;
+pub mod hls_stream;
+pub mod household_watched;
+pub mod imdb_backfill;
 pub mod imdb_episodes;
 pub mod imdb_ratings;
+pub mod imdb_refresh;
 pub mod imdb_utils;
+pub mod intro_markers;
 pub mod iso_8601_datetime;
+pub mod jellyfin_events;
+pub mod kodi_nfo;
 pub mod make_list;
 pub mod make_queue;
+pub mod media_ids;
+pub mod media_info;
+pub mod media_kind;
+pub mod metrics_exporter;
 pub mod movie_collection;
 pub mod movie_queue;
+pub mod music_collection;
 pub mod naivedate_wrapper;
+pub mod notifications;
+pub mod offline_files;
+pub mod opensubtitles;
+pub mod pagination;
 pub mod parse_imdb;
 pub mod pgpool;
+pub mod playback_position;
 pub mod plex_events;
+pub mod plex_metadata;
+pub mod plex_servers;
+pub mod plex_sessions;
+pub mod post_processors;
+pub mod queue_import;
+pub mod queue_share;
+pub mod reclaim;
+pub mod rename;
+pub mod saved_filters;
+pub mod scan_exclusions;
+pub mod scan_history;
+pub mod search;
+pub mod show_availability;
+pub mod show_settings;
+pub mod sync_cursor;
+pub mod tonight;
 pub mod trakt_connection;
+pub mod trakt_ratings;
+pub mod trakt_sync;
 pub mod trakt_utils;
+pub mod trakt_webhook;
+pub mod transcode_jobs;
 pub mod transcode_service;
 pub mod tv_show_source;
+pub mod tvmaze_utils;
+pub mod up_next;
+pub mod user_hooks;
+pub mod user_preferences;
+pub mod user_watched;
 pub mod utils;
+pub mod viewing_stats;
+pub mod watch_folder;
+pub mod webhook_failures;
@@ -1,7 +1,6 @@
 use anyhow::Error;
 use futures::future::join_all;
 use itertools::Itertools;
-use log::debug;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use stack_string::StackString;
 use std::{collections::HashMap, ffi::OsStr, path::PathBuf};
@@ -11,9 +10,11 @@ use tokio::{
     task::{spawn, spawn_blocking},
 };
 use tokio_stream::{wrappers::ReadDirStream, StreamExt};
+use tracing::debug;
 
 use crate::{
     config::Config,
+    pgpool::PgPool,
     transcode_service::transcode_status,
     utils::{get_video_runtime, walk_directory},
 };
@@ -95,9 +96,10 @@ impl FileLists {
 
 pub async fn make_list(stdout: &StdoutChannel<StackString>) -> Result<(), Error> {
     let config = Config::with_config()?;
+    let pool = PgPool::new(&config.pgurl);
     let transcode_task = {
         let config = config.clone();
-        spawn(async move { transcode_status(&config).await })
+        spawn(async move { transcode_status(&config, &pool).await })
     };
 
     let file_lists = FileLists::get_file_lists(&config).await?;
@@ -6,16 +6,15 @@ use stack_string::StackString;
 use std::{
     ffi::OsStr,
     path::{Path, PathBuf},
-    sync::Arc,
 };
 use stdout_channel::StdoutChannel;
 
 use crate::{
     config::Config,
-    movie_collection::MovieCollection,
+    movie_collection::{MovieCollection, TvShowsSort},
     movie_queue::{MovieQueueDB, MovieQueueResult},
     pgpool::PgPool,
-    utils::{get_video_runtime, parse_file_stem},
+    utils::{canonicalize_path, get_video_runtime, parse_file_stem},
 };
 
 #[derive(Debug, Display)]
@@ -63,7 +62,7 @@ pub async fn make_queue_worker(
 
     if do_shows {
         let shows = mc
-            .print_tv_shows()
+            .print_tv_shows(TvShowsSort::default())
             .await?
             .into_iter()
             .map(|s| s.to_string())
@@ -140,13 +139,17 @@ pub async fn movie_queue_http(
     config: &Config,
     stdout: &StdoutChannel<StackString>,
 ) -> Result<Vec<StackString>, Error> {
-    let mc = Arc::new(MovieCollection::new(&config, pool, &stdout));
+    let mc = MovieCollection::new(&config, pool, &stdout);
 
     let button = r#"<td><button type="submit" id="ID" onclick="delete_show('SHOW');"> remove </button></td>"#;
 
-    let futures = queue.iter().map(|row| {
-        let mc = mc.clone();
-        async move {
+    // Two queries for the whole page rather than two per row
+    let paths: Vec<StackString> = queue.iter().map(|row| row.path.clone()).collect();
+    let collection_indices = mc.get_collection_indices(&paths).await?;
+    let ids: Vec<i32> = collection_indices.values().copied().collect();
+    let plex_metadata = mc.get_plex_metadata_keys(&ids).await?;
+
+    queue.iter().map(|row| -> Result<StackString, Error> {
         let path = Path::new(row.path.as_str());
         let ext = path
             .extension()
@@ -163,8 +166,16 @@ pub async fn movie_queue_http(
             .to_string_lossy();
         let (_, season, episode) = parse_file_stem(&file_stem);
 
+        let collection_idx = collection_indices
+            .get(&canonicalize_path(&row.path))
+            .copied()
+            .unwrap_or(-1);
+        let drag = format!(
+            r#"draggable="true" ondragstart="queue_drag_start(event, {});" ondragover="event.preventDefault();" ondrop="queue_drop(event, {});""#,
+            collection_idx, row.idx
+        );
+
         let entry = if ext == "mp4" {
-            let collection_idx = mc.get_collection_index(&row.path).await?.unwrap_or(-1);
             format!(
                 r#"<a href="javascript:updateMainArticle('{}');">{}</a>"#,
                 &format!("{}/{}", "/list/play", collection_idx),
@@ -174,19 +185,26 @@ pub async fn movie_queue_http(
             file_name.clone()
         };
 
+        let entry = match row.episode_span() {
+            Some(span) => format!("{} ({})", entry, span),
+            None => entry,
+        };
+
         let entry = if let Some(link) = row.link.as_ref() {
             format!(
-                r#"<tr><td>{}</td><td><a href={} target="_blank">imdb</a></td>"#,
+                r#"<tr {}><td>{}</td><td><a href={} target="_blank">imdb</a></td>"#,
+                drag,
                 entry,
                 &format!("https://www.imdb.com/title/{}", link)
             )
         } else {
-            format!("<tr>\n<td>{}</td>\n", entry)
+            format!("<tr {}>\n<td>{}</td>\n", drag, entry)
         };
 
         let entry = format!(
-            "{}\n{}",
+            "{}<td>{}</td>\n{}",
             entry,
+            row.media_info,
             button.replace("ID", &file_name).replace("SHOW", &file_name)
         ).into();
 
@@ -206,8 +224,11 @@ pub async fn movie_queue_http(
                 entry=entry, file_name=file_name, directory=directory
             ).into()
         };
+
+        let entry = match plex_metadata.get(&collection_idx) {
+            Some(metadata) => format!("{}<td>{}</td>", entry, metadata.get_html()).into(),
+            None => entry,
+        };
         Ok(entry)
-        }
-    });
-    try_join_all(futures).await
+    }).collect()
 }
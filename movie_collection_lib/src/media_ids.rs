@@ -0,0 +1,230 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, str::FromStr};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+
+use crate::pgpool::PgPool;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum MediaIdType {
+    #[serde(rename = "imdb")]
+    Imdb,
+    #[serde(rename = "tmdb")]
+    Tmdb,
+    #[serde(rename = "tvdb")]
+    Tvdb,
+    #[serde(rename = "trakt")]
+    Trakt,
+}
+
+impl fmt::Display for MediaIdType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Imdb => "imdb",
+                Self::Tmdb => "tmdb",
+                Self::Tvdb => "tvdb",
+                Self::Trakt => "trakt",
+            }
+        )
+    }
+}
+
+impl FromStr for MediaIdType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "imdb" => Ok(Self::Imdb),
+            "tmdb" => Ok(Self::Tmdb),
+            "tvdb" => Ok(Self::Tvdb),
+            "trakt" => Ok(Self::Trakt),
+            _ => Err(format_err!("Is not MediaIdType")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for MediaIdType {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for MediaIdType {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct MediaId {
+    pub show_id: i32,
+    pub id_type: MediaIdType,
+    pub id_value: StackString,
+}
+
+impl MediaId {
+    pub fn parse_id(id: &str) -> (MediaIdType, &str) {
+        if let Some((prefix, value)) = id.split_once(':') {
+            if let Ok(id_type) = prefix.parse() {
+                return (id_type, value);
+            }
+        }
+        (MediaIdType::Imdb, id)
+    }
+
+    pub async fn get_ids(pool: &PgPool, show_id: i32) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT show_id, id_type, id_value
+                FROM media_ids
+                WHERE show_id = $show_id
+                ORDER BY id_type
+            "#,
+            show_id = show_id
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_show_id(
+        pool: &PgPool,
+        id_type: MediaIdType,
+        id_value: &str,
+    ) -> Result<Option<i32>, Error> {
+        let query = query!(
+            r#"
+                SELECT show_id
+                FROM media_ids
+                WHERE id_type = $id_type AND id_value = $id_value
+            "#,
+            id_type = id_type,
+            id_value = id_value
+        );
+        let conn = pool.get().await?;
+        let show_id: Option<(i32,)> = query.fetch_opt(&conn).await?;
+        Ok(show_id.map(|(x,)| x))
+    }
+
+    // Maps any supported id (tmdb:1396, trakt:1388, ...) to the show's imdb link, imdb
+    // links are returned unchanged
+    pub async fn resolve_link(pool: &PgPool, id: &str) -> Result<Option<StackString>, Error> {
+        let (id_type, id_value) = Self::parse_id(id);
+        if id_type == MediaIdType::Imdb {
+            return Ok(Some(id_value.into()));
+        }
+        let query = query!(
+            r#"
+                SELECT b.link
+                FROM media_ids a
+                JOIN imdb_ratings b ON a.show_id = b.index
+                WHERE a.id_type = $id_type AND a.id_value = $id_value
+            "#,
+            id_type = id_type,
+            id_value = id_value
+        );
+        let conn = pool.get().await?;
+        let link: Option<(Option<StackString>,)> = query.fetch_opt(&conn).await?;
+        Ok(link.and_then(|(l,)| l))
+    }
+
+    // Returns false without changing anything when the id already belongs to another show
+    pub async fn relink(&self, pool: &PgPool) -> Result<bool, Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            "DELETE FROM media_ids WHERE show_id = $show_id AND id_type = $id_type",
+            show_id = self.show_id,
+            id_type = self.id_type
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            r#"
+                INSERT INTO media_ids (show_id, id_type, id_value, last_modified)
+                VALUES ($show_id, $id_type, $id_value, now())
+                ON CONFLICT (id_type, id_value) DO NOTHING
+            "#,
+            show_id = self.show_id,
+            id_type = self.id_type,
+            id_value = self.id_value
+        );
+        // This show's own id of this type was just deleted, so a conflict means the id is
+        // linked to a different show; dropping the transaction rolls back the delete
+        if tran.execute(query.sql(), query.parameters()).await? == 0 {
+            return Ok(false);
+        }
+        if self.id_type == MediaIdType::Imdb {
+            let query = query!(
+                "UPDATE imdb_ratings SET link=$link, last_modified=now() WHERE index = $show_id",
+                link = self.id_value,
+                show_id = self.show_id
+            );
+            tran.execute(query.sql(), query.parameters()).await?;
+        }
+        tran.commit().await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media_ids::{MediaId, MediaIdType};
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(
+            MediaId::parse_id("tt0903747"),
+            (MediaIdType::Imdb, "tt0903747")
+        );
+        assert_eq!(MediaId::parse_id("tmdb:1396"), (MediaIdType::Tmdb, "1396"));
+        assert_eq!(
+            MediaId::parse_id("tvdb:81189"),
+            (MediaIdType::Tvdb, "81189")
+        );
+        assert_eq!(
+            MediaId::parse_id("trakt:1388"),
+            (MediaIdType::Trakt, "1388")
+        );
+        assert_eq!(
+            MediaId::parse_id("imdb:tt0903747"),
+            (MediaIdType::Imdb, "tt0903747")
+        );
+        assert_eq!(MediaId::parse_id("foo:bar"), (MediaIdType::Imdb, "foo:bar"));
+    }
+}
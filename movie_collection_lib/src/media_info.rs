@@ -0,0 +1,161 @@
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, path::Path};
+use tokio::{fs, process::Command};
+
+use crate::delete_confirm::format_size;
+
+#[derive(Deserialize, Debug)]
+struct ProbeStream {
+    codec_type: Option<StackString>,
+    codec_name: Option<StackString>,
+    width: Option<i64>,
+    height: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ProbeFormat {
+    duration: Option<StackString>,
+    size: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    #[serde(default)]
+    format: ProbeFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    pub file_size: Option<i64>,
+    pub duration: Option<f64>,
+    pub resolution: Option<StackString>,
+    pub video_codec: Option<StackString>,
+    pub audio_codec: Option<StackString>,
+}
+
+impl MediaInfo {
+    pub async fn probe(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Err(format_err!("{:?} does not exist", path));
+        }
+        let file_size = fs::metadata(path).await?.len() as i64;
+        let output = Command::new("ffprobe")
+            .args(&[
+                "-v",
+                "quiet",
+                "-print_format",
+                "json",
+                "-show_streams",
+                "-show_format",
+            ])
+            .arg(path)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(format_err!("ffprobe failed for {:?}", path));
+        }
+        let mut info = Self::from_probe_output(&output.stdout)?;
+        info.file_size = Some(file_size);
+        Ok(info)
+    }
+
+    fn from_probe_output(buf: &[u8]) -> Result<Self, Error> {
+        let probe: ProbeOutput = serde_json::from_slice(buf)?;
+        let video = probe
+            .streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some("video"));
+        let audio = probe
+            .streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some("audio"));
+        let resolution = video.and_then(|v| match (v.width, v.height) {
+            (Some(w), Some(h)) => Some(format!("{}x{}", w, h).into()),
+            _ => None,
+        });
+        Ok(Self {
+            file_size: probe.format.size.and_then(|s| s.parse().ok()),
+            duration: probe.format.duration.and_then(|d| d.parse().ok()),
+            resolution,
+            video_codec: video.and_then(|v| v.codec_name.clone()),
+            audio_codec: audio.and_then(|a| a.codec_name.clone()),
+        })
+    }
+}
+
+// Accepts "480p", "480" or "640x480", returning the height
+pub fn parse_resolution_height(resolution: &str) -> Option<i32> {
+    let resolution = resolution.trim().to_lowercase();
+    let height = match resolution.split_once('x') {
+        Some((_, height)) => height,
+        None => resolution.trim_end_matches('p'),
+    };
+    height.parse().ok()
+}
+
+impl fmt::Display for MediaInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut items: Vec<StackString> = Vec::new();
+        if let Some(resolution) = &self.resolution {
+            items.push(resolution.clone());
+        }
+        let codecs: Vec<_> = self
+            .video_codec
+            .iter()
+            .chain(self.audio_codec.iter())
+            .map(StackString::as_str)
+            .collect();
+        if !codecs.is_empty() {
+            items.push(codecs.join("/").into());
+        }
+        if let Some(duration) = self.duration {
+            let secs = duration as u64;
+            let runtime = format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60);
+            items.push(runtime.into());
+        }
+        if let Some(file_size) = self.file_size {
+            items.push(format_size(file_size as u64));
+        }
+        write!(f, "{}", items.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::media_info::{parse_resolution_height, MediaInfo};
+
+    #[test]
+    fn test_from_probe_output() -> Result<(), Error> {
+        let buf = br#"{
+            "streams": [
+                {"index": 0, "codec_name": "h264", "codec_type": "video", "width": 1920, "height": 1080},
+                {"index": 1, "codec_name": "aac", "codec_type": "audio", "channels": 2}
+            ],
+            "format": {"duration": "2581.504000", "size": "1073741824"}
+        }"#;
+        let info = MediaInfo::from_probe_output(buf)?;
+        assert_eq!(info.resolution.as_deref(), Some("1920x1080"));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(info.file_size, Some(1_073_741_824));
+        assert_eq!(info.to_string(), "1920x1080 h264/aac 0:43:01 1.0 GiB");
+
+        let info = MediaInfo::from_probe_output(br#"{"streams": [{"codec_type": "audio"}]}"#)?;
+        assert_eq!(info, MediaInfo::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_resolution_height() {
+        assert_eq!(parse_resolution_height("480p"), Some(480));
+        assert_eq!(parse_resolution_height("720"), Some(720));
+        assert_eq!(parse_resolution_height("1920x1080"), Some(1080));
+        assert_eq!(parse_resolution_height("hd"), None);
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path, str::FromStr};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+
+use crate::utils::{parse_absolute_episode, parse_file_stem};
+
+// Directory names used by Plex / Kodi for bonus material
+const EXTRA_DIRECTORIES: &[&str] = &[
+    "extras",
+    "featurettes",
+    "behind the scenes",
+    "behind_the_scenes",
+    "deleted scenes",
+    "deleted_scenes",
+    "interviews",
+    "trailers",
+];
+
+// Plex style suffixes, e.g. the_matrix-featurette.mkv
+const EXTRA_SUFFIXES: &[&str] = &[
+    "-featurette",
+    "-behindthescenes",
+    "-deleted",
+    "-interview",
+    "-scene",
+    "-short",
+    "-trailer",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum MediaKind {
+    #[serde(rename = "episode")]
+    Episode,
+    #[serde(rename = "movie")]
+    Movie,
+    #[serde(rename = "special")]
+    Special,
+    #[serde(rename = "extra")]
+    Extra,
+    #[serde(rename = "sample")]
+    Sample,
+}
+
+impl Default for MediaKind {
+    fn default() -> Self {
+        Self::Movie
+    }
+}
+
+impl MediaKind {
+    pub fn classify(path: &str) -> Self {
+        let path = Path::new(path);
+        let file_stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let directories: Vec<String> = path
+            .parent()
+            .into_iter()
+            .flat_map(Path::iter)
+            .map(|d| d.to_string_lossy().to_lowercase())
+            .collect();
+
+        // Only a trailing token counts, "sample" elsewhere is usually part of the title
+        if directories.iter().any(|d| d == "sample")
+            || file_stem.rsplit(&['_', '-', '.'][..]).next() == Some("sample")
+        {
+            return Self::Sample;
+        }
+        if directories
+            .iter()
+            .any(|d| EXTRA_DIRECTORIES.contains(&d.as_str()))
+            || EXTRA_SUFFIXES.iter().any(|s| file_stem.ends_with(s))
+        {
+            return Self::Extra;
+        }
+        let (_, season, _) = parse_file_stem(&file_stem);
+        if season == 0 || directories.iter().any(|d| d == "specials") {
+            Self::Special
+        } else if season != -1 || parse_absolute_episode(&file_stem).is_some() {
+            Self::Episode
+        } else {
+            Self::Movie
+        }
+    }
+
+    // Comma separated list of kinds, e.g. "episode,movie"
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, Error> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Episode => "episode",
+                Self::Movie => "movie",
+                Self::Special => "special",
+                Self::Extra => "extra",
+                Self::Sample => "sample",
+            }
+        )
+    }
+}
+
+impl FromStr for MediaKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "episode" => Ok(Self::Episode),
+            "movie" => Ok(Self::Movie),
+            "special" => Ok(Self::Special),
+            "extra" | "featurette" => Ok(Self::Extra),
+            "sample" => Ok(Self::Sample),
+            _ => Err(format_err!("Is not MediaKind")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for MediaKind {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for MediaKind {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::media_kind::MediaKind;
+
+    #[test]
+    fn test_classify() {
+        let cases = [
+            (
+                "/tmp/television/mr_robot/season1/mr_robot_s01_ep01.mp4",
+                MediaKind::Episode,
+            ),
+            ("/tmp/television/mr_robot_s00_ep02.mp4", MediaKind::Special),
+            (
+                "/tmp/television/specials/mr_robot_christmas.mp4",
+                MediaKind::Special,
+            ),
+            ("/tmp/movies/scifi/the_matrix.mp4", MediaKind::Movie),
+            (
+                "/tmp/movies/the_matrix/extras/making_of.mkv",
+                MediaKind::Extra,
+            ),
+            ("/tmp/movies/the_matrix-featurette.mkv", MediaKind::Extra),
+            (
+                "/tmp/movies/the_matrix/sample/the_matrix.mkv",
+                MediaKind::Sample,
+            ),
+            ("/tmp/movies/the_matrix_sample.mkv", MediaKind::Sample),
+            ("/tmp/movies/the_matrix-sample.mkv", MediaKind::Sample),
+            ("/tmp/movies/free_sample_day.mkv", MediaKind::Movie),
+            (
+                "/tmp/television/sample_show_s01_ep01.mp4",
+                MediaKind::Episode,
+            ),
+            ("/tmp/television/one_piece_ep0134.mp4", MediaKind::Episode),
+        ];
+        for (path, kind) in &cases {
+            assert_eq!(MediaKind::classify(path), *kind, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_parse_list() -> Result<(), Error> {
+        assert_eq!(
+            MediaKind::parse_list("episode, movie,")?,
+            vec![MediaKind::Episode, MediaKind::Movie]
+        );
+        assert_eq!(MediaKind::parse_list("featurette")?, vec![MediaKind::Extra]);
+        assert!(MediaKind::parse_list("trailer").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{format_err, Error};
+use chrono::Utc;
+use reqwest::{Client, Url};
+use stack_string::StackString;
+use std::fmt::Write;
+use tokio::task::spawn;
+use tracing::error;
+
+use crate::{config::Config, plex_events::PlexEvent, transcode_service::TranscodeServiceRequest};
+
+#[derive(Clone)]
+pub struct MetricsExporter {
+    config: Config,
+    client: Client,
+}
+
+impl MetricsExporter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.config.influxdb_url.is_some()
+    }
+
+    pub async fn export_plex_event(&self, event: &PlexEvent) {
+        if !self.is_configured() {
+            return;
+        }
+        if let Err(e) = self.write_lines(&[plex_event_line(event)]).await {
+            error!("Failed to export plex event {}", e);
+        }
+    }
+
+    // Webhook handlers shouldn't wait on influxdb, so the export runs in the background
+    pub fn fire_plex_event(&self, event: PlexEvent) {
+        if !self.is_configured() {
+            return;
+        }
+        let exporter = self.clone();
+        spawn(async move {
+            exporter.export_plex_event(&event).await;
+        });
+    }
+
+    pub async fn export_transcode_job(
+        &self,
+        job: &TranscodeServiceRequest,
+        duration: f64,
+        success: bool,
+    ) {
+        if !self.is_configured() {
+            return;
+        }
+        let line = transcode_job_line(job, duration, success);
+        if let Err(e) = self.write_lines(&[line]).await {
+            error!("Failed to export transcode job {}", e);
+        }
+    }
+
+    async fn write_lines(&self, lines: &[StackString]) -> Result<(), Error> {
+        let base_url = self
+            .config
+            .influxdb_url
+            .as_ref()
+            .ok_or_else(|| format_err!("No influxdb url"))?;
+        let url = Url::parse_with_params(
+            &format!("{}/api/v2/write", base_url.trim_end_matches('/')),
+            &[
+                ("org", self.config.influxdb_org.as_str()),
+                ("bucket", self.config.influxdb_bucket.as_str()),
+                ("precision", "s"),
+            ],
+        )?;
+        let body = lines.join("\n");
+        let mut request = self.client.post(url).body(body);
+        if let Some(token) = &self.config.influxdb_token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+fn escape_tag(s: &str) -> StackString {
+    let s = if s.is_empty() { "none" } else { s };
+    s.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+        .into()
+}
+
+fn escape_field(s: &str) -> StackString {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")).into()
+}
+
+fn plex_event_line(event: &PlexEvent) -> StackString {
+    let timestamp = event
+        .created_at
+        .map_or_else(Utc::now, Into::into)
+        .timestamp();
+    let mut line = format!(
+        "plex_event,event={},account={},server={},player={} ",
+        escape_tag(&event.event),
+        escape_tag(&event.account),
+        escape_tag(&event.server),
+        escape_tag(&event.player_title),
+    );
+    let fields: Vec<_> = [
+        ("title", &event.title),
+        ("parent_title", &event.parent_title),
+        ("grandparent_title", &event.grandparent_title),
+    ]
+    .iter()
+    .map(|(k, v)| {
+        let v = v.as_ref().map_or("", StackString::as_str);
+        format!("{}={}", k, escape_field(v))
+    })
+    .collect();
+    write!(line, "{} {}", fields.join(","), timestamp).unwrap_or(());
+    line.into()
+}
+
+fn transcode_job_line(job: &TranscodeServiceRequest, duration: f64, success: bool) -> StackString {
+    format!(
+        "transcode_job,job_type={},success={} prefix={},duration={} {}",
+        job.job_type,
+        success,
+        escape_field(&job.prefix),
+        duration,
+        Utc::now().timestamp(),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        metrics_exporter::{plex_event_line, transcode_job_line},
+        plex_events::PlexEvent,
+        transcode_service::{JobType, TranscodeServiceRequest},
+    };
+
+    #[test]
+    fn test_plex_event_line() {
+        let event = PlexEvent {
+            event: "media.play".into(),
+            account: "some user".into(),
+            server: "server".into(),
+            player_title: "player".into(),
+            title: Some("A \"title\"".into()),
+            ..PlexEvent::default()
+        };
+        let line = plex_event_line(&event);
+        assert!(line.starts_with(
+            "plex_event,event=media.play,account=some\\ user,server=server,player=player \
+             title=\"A \\\"title\\\"\",parent_title=\"\",grandparent_title=\"\" "
+        ));
+    }
+
+    #[test]
+    fn test_transcode_job_line() {
+        let job = TranscodeServiceRequest::new(
+            JobType::Transcode,
+            "test",
+            Path::new("test.avi"),
+            Path::new("test.mp4"),
+        );
+        let line = transcode_job_line(&job, 1.5, true);
+        assert!(line.starts_with(
+            "transcode_job,job_type=transcode,success=true prefix=\"test\",duration=1.5 "
+        ));
+    }
+}
@@ -2,31 +2,67 @@ use anyhow::{format_err, Error};
 use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
 use futures::future::try_join_all;
 use itertools::Itertools;
-use postgres_query::{query, query_dyn, FromSqlRow};
+use postgres_query::{query, query_dyn, FromSqlRow, Parameter};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use stack_string::StackString;
 use std::{
     collections::{HashMap, HashSet},
     ffi::OsStr,
     fmt,
     path::Path,
+    str::FromStr,
     sync::Arc,
 };
 use stdout_channel::StdoutChannel;
+use thiserror::Error as ThisError;
+use tokio::fs;
 
 use crate::{
+    clock::SharedClock,
     config::Config,
+    credits_detection::detect_credits_start,
     datetime_wrapper::DateTimeWrapper,
+    download_requests::{DownloadRequest, DownloadStatus},
+    duplicates::{
+        episode_key, find_fingerprint_duplicates, group_duplicates, merge_groups, DuplicateEntry,
+        DuplicateGroup, DuplicateKind,
+    },
     imdb_episodes::ImdbEpisodes,
     imdb_ratings::ImdbRatings,
+    intro_markers::IntroMarker,
+    media_info::MediaInfo,
+    media_kind::MediaKind,
     movie_queue::MovieQueueDB,
     pgpool::PgPool,
+    plex_metadata::PlexMetadata,
+    post_processors::{CollectionPostProcessor, PostProcessors},
+    scan_exclusions::ScanExclusions,
+    show_availability::ShowAvailability,
+    show_settings::ShowSettings,
     tv_show_source::TvShowSource,
-    utils::{option_string_wrapper, parse_file_stem, walk_directory},
+    user_hooks::{HookEvent, UserHook},
+    utils::{
+        canonicalize_path, dedup_linked_paths, option_string_wrapper, parse_absolute_episode,
+        parse_episode_span, parse_file_stem, walk_directory,
+    },
 };
 
+/// Returned by the collection lookups and inserts that take user supplied indices or
+/// paths (`get_collection_path`, `insert_into_collection`), the rest of `MovieCollection`
+/// only fails on database or filesystem errors and keeps returning `anyhow::Error`
+#[derive(ThisError, Debug)]
+pub enum CollectionError {
+    #[error("Collection entry not found: {0}")]
+    NotFound(StackString),
+    #[error("Invalid collection path: {0}")]
+    InvalidPath(StackString),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
+
 #[derive(FromSqlRow)]
 pub struct NewEpisodesResult {
     pub show: StackString,
@@ -67,6 +103,133 @@ pub struct TvShowsResult {
     pub count: i64,
     pub title: StackString,
     pub source: Option<TvShowSource>,
+    pub rating: Option<f64>,
+    pub last_added: Option<DateTimeWrapper>,
+}
+
+// A row of the tv shows listing, either a show with queued episodes or a watchlist show
+// with nothing queued
+#[derive(Default, FromSqlRow)]
+pub struct TvShowsPageResult {
+    pub show: StackString,
+    pub link: StackString,
+    pub count: i64,
+    pub title: StackString,
+    pub source: Option<TvShowSource>,
+    pub rating: Option<f64>,
+    pub last_added: Option<DateTimeWrapper>,
+    pub queued: bool,
+    pub watchlist: bool,
+}
+
+// Queued shows (not counting the `$exclude` collection entries) followed by watchlist
+// shows without queued episodes when `$include_watchlist` is set, dropped shows are left
+// out of both
+const TV_SHOWS_CTE: &str = r#"
+    WITH queued AS (
+        SELECT b.show, c.link, c.title, c.source, count(*) AS count, c.rating,
+               max(b.last_modified) AS last_added
+        FROM movie_queue a
+        JOIN movie_collection b ON a.collection_idx=b.idx
+        JOIN imdb_ratings c ON b.show_id=c.index
+        LEFT JOIN show_settings s ON s.link=c.link
+        WHERE c.istv AND NOT coalesce(s.dropped, false)
+          AND a.collection_idx <> ALL($exclude)
+        GROUP BY 1,2,3,4,6
+    ), shows AS (
+        SELECT q.show, q.link, q.count, q.title, q.source, q.rating, q.last_added,
+               true AS queued
+        FROM queued q
+        UNION ALL
+        SELECT b.show, a.link, 0::BIGINT AS count, a.title, b.source,
+               NULL::DOUBLE PRECISION AS rating,
+               NULL::TIMESTAMP WITH TIME ZONE AS last_added, false AS queued
+        FROM trakt_watchlist a
+        JOIN imdb_ratings b ON a.link=b.link
+        LEFT JOIN show_settings s ON s.link=a.link
+        WHERE $include_watchlist AND NOT coalesce(s.dropped, false)
+          AND a.link NOT IN (SELECT link FROM queued)
+    )
+"#;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Schema)]
+pub enum TvShowsSort {
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "rating")]
+    Rating,
+    #[serde(rename = "last_added")]
+    LastAdded,
+    #[serde(rename = "episode_count")]
+    EpisodeCount,
+    #[serde(rename = "source")]
+    Source,
+}
+
+impl Default for TvShowsSort {
+    fn default() -> Self {
+        Self::Name
+    }
+}
+
+impl TvShowsSort {
+    pub fn all() -> [Self; 5] {
+        [
+            Self::Name,
+            Self::Rating,
+            Self::LastAdded,
+            Self::EpisodeCount,
+            Self::Source,
+        ]
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Rating => "rating",
+            Self::LastAdded => "last_added",
+            Self::EpisodeCount => "episode_count",
+            Self::Source => "source",
+        }
+    }
+
+    fn page_order_by(self) -> &'static str {
+        match self {
+            Self::Name => "t.show",
+            Self::Rating => "t.rating DESC NULLS LAST, t.show",
+            Self::LastAdded => "t.last_added DESC NULLS LAST, t.show",
+            Self::EpisodeCount => "t.count DESC, t.show",
+            Self::Source => "t.source NULLS LAST, t.show",
+        }
+    }
+
+    fn order_by(self) -> &'static str {
+        match self {
+            Self::Name => "b.show",
+            Self::Rating => "c.rating DESC NULLS LAST, b.show",
+            Self::LastAdded => "last_added DESC NULLS LAST, b.show",
+            Self::EpisodeCount => "count DESC, b.show",
+            Self::Source => "c.source NULLS LAST, b.show",
+        }
+    }
+}
+
+impl fmt::Display for TvShowsSort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl FromStr for TvShowsSort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .find(|sort| sort.to_str() == s)
+            .copied()
+            .ok_or_else(|| format_err!("Invalid sort {}", s))
+    }
 }
 
 impl fmt::Display for TvShowsResult {
@@ -89,6 +252,19 @@ pub struct MovieCollectionRow {
     pub idx: i32,
     pub path: StackString,
     pub show: StackString,
+    pub credits_start: Option<f64>,
+    pub file_size: Option<i64>,
+    pub duration: Option<f64>,
+    pub resolution: Option<StackString>,
+    pub video_codec: Option<StackString>,
+    pub audio_codec: Option<StackString>,
+}
+
+#[derive(Default, Debug, Clone, Copy, FromSqlRow)]
+pub struct PlaybackMarkers {
+    pub intro_start: Option<f64>,
+    pub intro_end: Option<f64>,
+    pub credits_start: Option<f64>,
 }
 
 #[derive(Default, FromSqlRow)]
@@ -165,6 +341,9 @@ pub struct MovieCollection {
     pub config: Config,
     pub pool: PgPool,
     pub stdout: StdoutChannel<StackString>,
+    pub post_processors: PostProcessors,
+    pub clock: SharedClock,
+    pub dispatch_hooks: bool,
 }
 
 impl Default for MovieCollection {
@@ -186,29 +365,55 @@ impl MovieCollection {
             pool,
             config,
             stdout,
+            post_processors: PostProcessors::default(),
+            clock: SharedClock::default(),
+            dispatch_hooks: true,
         }
     }
 
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_post_processor(
+        mut self,
+        processor: impl CollectionPostProcessor + 'static,
+    ) -> Self {
+        self.post_processors.register(Arc::new(processor));
+        self
+    }
+
+    pub fn with_post_processors(mut self, post_processors: PostProcessors) -> Self {
+        self.post_processors = post_processors;
+        self
+    }
+
+    // Fixture writes from tests and smoke checks should not reach user webhooks or
+    // post-processors
+    pub fn without_hooks(mut self) -> Self {
+        self.dispatch_hooks = false;
+        self
+    }
+
     pub async fn print_imdb_shows(
         &self,
         show: &str,
         istv: bool,
     ) -> Result<Vec<ImdbRatings>, Error> {
-        let query = format!("SELECT show FROM imdb_ratings WHERE show like '%{}%'", show);
-        let query = if istv {
-            format!("{} AND istv", query)
-        } else {
-            query
-        };
-        let shows: HashSet<StackString> = self
-            .pool
-            .get()
-            .await?
-            .query(query.as_str(), &[])
-            .await?
-            .iter()
-            .map(|r| r.get(0))
-            .collect();
+        let pattern = format!("%{}%", show);
+        let query = query!(
+            r#"
+                SELECT show
+                FROM imdb_ratings
+                WHERE show like $pattern AND (NOT $istv OR istv)
+            "#,
+            pattern = pattern,
+            istv = istv
+        );
+        let conn = self.pool.get().await?;
+        let shows: Vec<(StackString,)> = query.fetch(&conn).await?;
+        let shows: HashSet<StackString> = shows.into_iter().map(|(s,)| s).collect();
 
         let shows = if shows.contains(show) {
             vec![show.into()]
@@ -316,7 +521,11 @@ impl MovieCollection {
             istv: Option<bool>,
         }
 
-        let query = query_dyn!(&format!(
+        let patterns: Vec<String> = search_strs
+            .iter()
+            .map(|s| format!("%{}%", s.as_ref()))
+            .collect();
+        let query = query!(
             r#"
                 SELECT a.path, a.show,
                 COALESCE(b.rating, -1) as rating,
@@ -324,18 +533,11 @@ impl MovieCollection {
                 COALESCE(b.istv, FALSE) as istv
                 FROM movie_collection a
                 LEFT JOIN imdb_ratings b ON a.show_id = b.index
-                WHERE a.is_deleted = false {}
+                WHERE a.is_deleted = false
+                  AND (cardinality($patterns::text[]) = 0 OR a.path like ANY($patterns))
             "#,
-            if search_strs.is_empty() {
-                "".to_string()
-            } else {
-                let search_strs = search_strs
-                    .iter()
-                    .map(|s| format!("a.path like '%{}%'", s.as_ref()))
-                    .join(" OR ");
-                format!("AND ({})", search_strs)
-            },
-        ),)?;
+            patterns = patterns
+        );
         let conn = self.pool.get().await?;
         let results: Vec<SearchMovieCollection> = query.fetch(&conn).await?;
 
@@ -392,6 +594,7 @@ impl MovieCollection {
     }
 
     pub async fn remove_from_collection(&self, path: &str) -> Result<(), Error> {
+        let path = canonicalize_path(path);
         let query = query!(
             r#"UPDATE movie_collection SET is_deleted=true WHERE path = $path"#,
             path = path
@@ -401,6 +604,7 @@ impl MovieCollection {
     }
 
     pub async fn get_collection_index(&self, path: &str) -> Result<Option<i32>, Error> {
+        let path = canonicalize_path(path);
         let query = query!(
             r#"SELECT idx FROM movie_collection WHERE path = $path"#,
             path = path
@@ -410,20 +614,101 @@ impl MovieCollection {
         Ok(id.map(|(x,)| x))
     }
 
-    pub async fn get_collection_path(&self, idx: i32) -> Result<StackString, Error> {
+    // Keyed by canonicalized path, paths missing from the collection are left out
+    pub async fn get_collection_indices(
+        &self,
+        paths: &[StackString],
+    ) -> Result<HashMap<StackString, i32>, Error> {
+        let paths: Vec<StackString> = paths.iter().map(|p| canonicalize_path(p)).collect();
+        let query = query!(
+            r#"SELECT path, idx FROM movie_collection WHERE path = ANY($paths)"#,
+            paths = paths
+        );
+        let conn = self.pool.get().await?;
+        let rows: Vec<(StackString, i32)> = query.fetch(&conn).await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    // Most recently seen plex metadata for each collection entry
+    pub async fn get_plex_metadata_keys(
+        &self,
+        ids: &[i32],
+    ) -> Result<HashMap<i32, PlexMetadata>, Error> {
+        let query = query!(
+            r#"
+                SELECT DISTINCT ON (a.collection_idx)
+                       a.collection_idx, b.path, a.server_uuid, a.metadata_key, a.view_offset,
+                       a.last_modified
+                FROM plex_metadata a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                WHERE a.collection_idx = ANY($ids)
+                ORDER BY a.collection_idx, a.last_modified DESC
+            "#,
+            ids = ids
+        );
+        let conn = self.pool.get().await?;
+        let rows: Vec<PlexMetadata> = query.fetch(&conn).await?;
+        Ok(rows.into_iter().map(|m| (m.collection_idx, m)).collect())
+    }
+
+    pub async fn find_duplicates(&self) -> Result<Vec<DuplicateGroup>, Error> {
+        let query = query!(
+            r#"
+                SELECT idx, path, show
+                FROM movie_collection
+                WHERE NOT is_deleted
+                ORDER BY path
+            "#
+        );
+        let conn = self.pool.get().await?;
+        let rows: Vec<(i32, StackString, Option<StackString>)> = query.fetch(&conn).await?;
+        let mut entries = Vec::with_capacity(rows.len());
+        for (idx, path, show) in rows {
+            let size = fs::metadata(path.as_str())
+                .await
+                .ok()
+                .map(|m| m.len() as i64);
+            entries.push(DuplicateEntry {
+                idx,
+                path,
+                show,
+                size,
+            });
+        }
+        let episode_groups = group_duplicates(&entries, DuplicateKind::Episode, episode_key);
+        let fingerprint_groups = find_fingerprint_duplicates(&entries).await?;
+        Ok(merge_groups(episode_groups, fingerprint_groups))
+    }
+
+    pub async fn get_collection_path(&self, idx: i32) -> Result<StackString, CollectionError> {
         let query = query!(
             "SELECT path FROM movie_collection WHERE idx = $idx",
             idx = idx
         );
         let conn = self.pool.get().await?;
-        let (path,) = query.fetch_one(&conn).await?;
-        Ok(path)
+        let path: Option<(StackString,)> = query.fetch_opt(&conn).await.map_err(Error::from)?;
+        path.map(|(p,)| p)
+            .ok_or_else(|| CollectionError::NotFound(idx.to_string().into()))
     }
 
-    pub async fn insert_into_collection(&self, path: &str, check_path: bool) -> Result<(), Error> {
+    pub async fn insert_into_collection(
+        &self,
+        path: &str,
+        check_path: bool,
+    ) -> Result<(), CollectionError> {
         if check_path && !Path::new(&path).exists() {
-            return Err(format_err!("No such file"));
+            return Err(CollectionError::NotFound(path.into()));
         }
+        let path = canonicalize_path(path);
+        if Path::new(path.as_str()).file_stem().is_none() {
+            return Err(CollectionError::InvalidPath(path));
+        }
+        self.insert_collection_entry(path.as_str())
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn insert_collection_entry(&self, path: &str) -> Result<(), Error> {
         let conn = self.pool.get().await?;
         if let Some(idx) = self.get_collection_index(path).await? {
             let query = query!(
@@ -436,20 +721,83 @@ impl MovieCollection {
                 .file_stem()
                 .ok_or_else(|| format_err!("No file stem"))?
                 .to_string_lossy();
-            let (show, _, _) = parse_file_stem(&file_stem);
+            let (mut show, season, _) = parse_file_stem(&file_stem);
+            if let Some((absolute_show, _)) = parse_absolute_episode(&file_stem) {
+                show = absolute_show;
+            }
+            let marker = if season == -1 {
+                None
+            } else {
+                IntroMarker::get_marker(&self.pool, &show, season).await?
+            };
+            let last_modified: DateTimeWrapper = self.clock.now().into();
+            let media_kind = MediaKind::classify(path);
             let query = query!(
                 r#"
-                    INSERT INTO movie_collection (path, show, last_modified)
-                    VALUES ($path, $show, now())
+                    INSERT INTO movie_collection
+                        (path, show, last_modified, intro_start, intro_end, media_kind)
+                    VALUES
+                        ($path, $show, $last_modified, $intro_start, $intro_end, $media_kind)
                 "#,
                 path = path,
-                show = show
+                show = show,
+                last_modified = last_modified,
+                media_kind = media_kind,
+                intro_start = marker.as_ref().map(|m| m.intro_start),
+                intro_end = marker.as_ref().map(|m| m.intro_end)
             );
             query.execute(&conn).await?;
+            if !self.dispatch_hooks {
+                return Ok(());
+            }
+            if !self.post_processors.is_empty() {
+                if let Some(idx) = self.get_collection_index(path).await? {
+                    let row = MovieCollectionRow {
+                        idx,
+                        path: path.into(),
+                        show: show.clone(),
+                        ..MovieCollectionRow::default()
+                    };
+                    self.post_processors.run(self, &row).await;
+                }
+            }
+            UserHook::fire(
+                &self.pool,
+                HookEvent::CollectionAdded,
+                json!({"path": path, "show": show}),
+            );
         }
         Ok(())
     }
 
+    // New episodes of a show with auto_queue set (and not dropped) go to the end of
+    // the queue
+    pub async fn auto_queue(&self, path: &str) -> Result<bool, Error> {
+        let path = canonicalize_path(path);
+        let file_stem = Path::new(path.as_str())
+            .file_stem()
+            .ok_or_else(|| format_err!("No file stem"))?
+            .to_string_lossy();
+        let (_, season, episode) = parse_file_stem(&file_stem);
+        if season == -1 || episode == -1 {
+            return Ok(false);
+        }
+        match ShowSettings::get_settings_for_path(&self.pool, path.as_str()).await? {
+            Some(settings) if settings.auto_queue && !settings.dropped => {}
+            _ => return Ok(false),
+        }
+        let collection_idx = match self.get_collection_index(&path).await? {
+            Some(idx) => idx,
+            None => return Ok(false),
+        };
+        let mq = MovieQueueDB::new(&self.config, &self.pool, &self.stdout)
+            .with_clock(self.clock.clone());
+        let max_idx = mq.get_max_queue_index().await?;
+        mq.insert_into_queue_by_collection_idx(max_idx + 1, collection_idx)
+            .await?;
+        Ok(true)
+    }
+
     pub async fn fix_collection_show_id(&self) -> Result<u64, Error> {
         let query = r#"
             WITH a AS (
@@ -460,10 +808,16 @@ impl MovieCollection {
             )
             UPDATE movie_collection b
             SET show_id=(SELECT c.index FROM imdb_ratings c WHERE b.show=c.show),
-                last_modified=now()
+                last_modified=$1
             WHERE idx in (SELECT a.idx FROM a)
         "#;
-        let rows = self.pool.get().await?.execute(query, &[]).await?;
+        let last_modified: DateTimeWrapper = self.clock.now().into();
+        let rows = self
+            .pool
+            .get()
+            .await?
+            .execute(query, &[&last_modified])
+            .await?;
         Ok(rows)
     }
 
@@ -481,32 +835,44 @@ impl MovieCollection {
             return Ok(());
         }
 
+        let exclusions = ScanExclusions::load(&self.config, &self.pool).await?;
+        let (file_list, skipped) =
+            exclusions.filter_paths(file_list.into_iter().flatten().collect());
+        for (rule, paths) in &skipped {
+            self.stdout
+                .send(format!("excluded by {} {}", rule, paths.len()));
+        }
+        let (file_list, duplicates) = dedup_linked_paths(file_list, &self.config.movie_dirs);
+        for path in &duplicates {
+            self.stdout
+                .send(format!("linked duplicate {}", path.to_string_lossy()));
+        }
+
         let file_list: HashSet<_> = file_list
-            .into_iter()
-            .flatten()
-            .map(|f| f.to_string_lossy().into_owned())
+            .into_par_iter()
+            .map(|f| canonicalize_path(&f.to_string_lossy()).to_string())
             .collect();
         let file_list = Arc::new(file_list);
 
-        let episode_list: Result<HashSet<(StackString, _, _, _)>, Error> = file_list
+        let episode_list: Result<Vec<Vec<(StackString, _, _, _)>>, Error> = file_list
             .par_iter()
-            .filter_map(|f| {
-                let res = || {
-                    let file_stem = Path::new(f)
-                        .file_stem()
-                        .map(OsStr::to_string_lossy)
-                        .ok_or_else(|| format_err!("file_stem failed"))?;
-                    let (show, season, episode) = parse_file_stem(&file_stem);
-                    if season == -1 || episode == -1 {
-                        Ok(None)
-                    } else {
-                        Ok(Some((show, season, episode, f)))
-                    }
-                };
-                res().transpose()
+            .map(|f| -> Result<Vec<_>, Error> {
+                let file_stem = Path::new(f)
+                    .file_stem()
+                    .map(OsStr::to_string_lossy)
+                    .ok_or_else(|| format_err!("file_stem failed"))?;
+                let (show, season, episode) = parse_file_stem(&file_stem);
+                if season == -1 || episode == -1 {
+                    return Ok(Vec::new());
+                }
+                // multi-episode files map onto every episode they cover
+                let last = parse_episode_span(&file_stem).map_or(episode, |(_, _, _, last)| last);
+                Ok((episode..=last)
+                    .map(|e| (show.clone(), season, e, f))
+                    .collect())
             })
             .collect();
-        let episode_list = episode_list?;
+        let episode_list: HashSet<_> = episode_list?.into_iter().flatten().collect();
 
         let query = r#"
             SELECT b.path, a.idx
@@ -574,6 +940,9 @@ impl MovieCollection {
                     if self.config.suffixes.contains(&ext) {
                         self.stdout.send(format!("not in collection {}", f));
                         self.insert_into_collection(f, true).await?;
+                        if self.auto_queue(f).await? {
+                            self.stdout.send(format!("auto queued {}", f));
+                        }
                     }
                 }
                 Ok(())
@@ -587,7 +956,8 @@ impl MovieCollection {
                 if let Some(v) = movie_queue.get(key) {
                     self.stdout
                         .send(format!("in queue but not disk {} {}", key, v));
-                    let mq = MovieQueueDB::new(&self.config, &self.pool, &self.stdout);
+                    let mq = MovieQueueDB::new(&self.config, &self.pool, &self.stdout)
+                        .with_clock(self.clock.clone());
                     mq.remove_from_queue_by_path(&key).await?;
                 } else {
                     self.stdout.send(format!("not on disk {} {}", key, val));
@@ -629,9 +999,38 @@ impl MovieCollection {
             self.stdout
                 .send(format!("show has episode not in db {} ", show));
         }
+
+        let updated = self.update_media_info().await?;
+        if updated > 0 {
+            self.stdout.send(format!("media info updated {}", updated));
+        }
+        let classified = self.update_media_kind().await?;
+        if classified > 0 {
+            self.stdout
+                .send(format!("media kind updated {}", classified));
+        }
         Ok(())
     }
 
+    // Classify entries added before media_kind existed
+    pub async fn update_media_kind(&self) -> Result<usize, Error> {
+        let query = query!("SELECT idx, path FROM movie_collection WHERE media_kind IS NULL");
+        let conn = self.pool.get().await?;
+        let rows: Vec<(i32, StackString)> = query.fetch(&conn).await?;
+        let mut updated = 0;
+        for (idx, path) in rows {
+            let media_kind = MediaKind::classify(&path);
+            let query = query!(
+                "UPDATE movie_collection SET media_kind=$media_kind WHERE idx=$idx",
+                media_kind = media_kind,
+                idx = idx
+            );
+            query.execute(&conn).await?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
     pub async fn get_imdb_show_map(&self) -> Result<HashMap<StackString, ImdbRatings>, Error> {
         #[derive(FromSqlRow)]
         struct ImdbShowMap {
@@ -679,18 +1078,68 @@ impl MovieCollection {
             .collect()
     }
 
-    pub async fn print_tv_shows(&self) -> Result<Vec<TvShowsResult>, Error> {
-        let query = query!(
+    pub async fn print_tv_shows(&self, sort: TvShowsSort) -> Result<Vec<TvShowsResult>, Error> {
+        let query = query_dyn!(&format!(
             r#"
-            SELECT b.show, c.link, c.title, c.source, count(*) as count
+            SELECT b.show, c.link, c.title, c.source, count(*) as count, c.rating,
+                   max(b.last_modified) as last_added
             FROM movie_queue a
             JOIN movie_collection b ON a.collection_idx=b.idx
             JOIN imdb_ratings c ON b.show_id=c.index
-            WHERE c.istv
-            GROUP BY 1,2,3,4
-            ORDER BY 1,2,3,4
-        "#
-        );
+            LEFT JOIN show_settings s ON s.link=c.link
+            WHERE c.istv AND NOT coalesce(s.dropped, false)
+            GROUP BY 1,2,3,4,6
+            ORDER BY {}
+        "#,
+            sort.order_by()
+        ))?;
+        let conn = self.pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn count_tv_shows(
+        &self,
+        exclude: &[i32],
+        include_watchlist: bool,
+    ) -> Result<usize, Error> {
+        let query = query_dyn!(
+            &format!("{} SELECT count(*) FROM shows", TV_SHOWS_CTE),
+            exclude = exclude,
+            include_watchlist = include_watchlist
+        )?;
+        let conn = self.pool.get().await?;
+        let (count,): (i64,) = query.fetch_one(&conn).await?;
+        Ok(count as usize)
+    }
+
+    pub async fn print_tv_shows_page(
+        &self,
+        sort: TvShowsSort,
+        exclude: &[i32],
+        include_watchlist: bool,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<TvShowsPageResult>, Error> {
+        let offset = offset as i64;
+        let limit = limit as i64;
+        let query = query_dyn!(
+            &format!(
+                r#"
+                    {}
+                    SELECT t.*, EXISTS(SELECT 1 FROM trakt_watchlist w WHERE w.link = t.link)
+                                AS watchlist
+                    FROM shows t
+                    ORDER BY {}
+                    OFFSET $offset LIMIT $limit
+                "#,
+                TV_SHOWS_CTE,
+                sort.page_order_by()
+            ),
+            exclude = exclude,
+            include_watchlist = include_watchlist,
+            offset = offset,
+            limit = limit
+        )?;
         let conn = self.pool.get().await?;
         query.fetch(&conn).await.map_err(Into::into)
     }
@@ -701,6 +1150,18 @@ impl MovieCollection {
         maxdate: NaiveDate,
         source: Option<TvShowSource>,
     ) -> Result<Vec<NewEpisodesResult>, Error> {
+        let mut bindings = vec![
+            ("mindate", &mindate as Parameter),
+            ("maxdate", &maxdate as Parameter),
+        ];
+        let source_constraint = match &source {
+            Some(TvShowSource::All) => "",
+            Some(s) => {
+                bindings.push(("source", s as Parameter));
+                "AND c.source = $source"
+            }
+            None => "AND c.source is null",
+        };
         let query = query_dyn!(
             &format!(
                 r#"
@@ -729,20 +1190,16 @@ impl MovieCollection {
                     LEFT JOIN trakt_watched_episodes e
                         ON c.link=e.link AND d.season=e.season AND d.episode=e.episode
                     WHERE c.link in (SELECT link FROM active_links GROUP BY link) AND
+                        c.link NOT IN (SELECT link FROM show_settings WHERE dropped) AND
                         e.episode is null AND
                         c.istv AND d.airdate >= $mindate AND
                         d.airdate <= $maxdate {}
                     GROUP BY 1,2,3,4,5,6,7,8,9,10
                     ORDER BY d.airdate, c.show, d.season, d.episode
                 "#,
-                match source {
-                    Some(TvShowSource::All) => "".to_string(),
-                    Some(s) => format!("AND c.source = '{}'", s),
-                    None => "AND c.source is null".to_string(),
-                }
+                source_constraint
             ),
-            mindate = mindate,
-            maxdate = maxdate
+            ..bindings
         )?;
         let conn = self.pool.get().await?;
         query.fetch(&conn).await.map_err(Into::into)
@@ -756,7 +1213,8 @@ impl MovieCollection {
         let mindate = Local::today() + Duration::days(-14);
         let maxdate = Local::today() + Duration::days(7);
 
-        let mq = MovieQueueDB::new(&self.config, &self.pool, &self.stdout);
+        let mq = MovieQueueDB::new(&self.config, &self.pool, &self.stdout)
+            .with_clock(self.clock.clone());
 
         let mut output = Vec::new();
 
@@ -793,7 +1251,8 @@ impl MovieCollection {
     ) -> Result<Vec<MovieCollectionRow>, Error> {
         let query = query!(
             r#"
-                SELECT idx, path, show
+                SELECT idx, path, show, credits_start, file_size, duration, resolution,
+                       video_codec, audio_codec
                 FROM movie_collection
                 WHERE last_modified >= $timestamp
             "#,
@@ -802,6 +1261,153 @@ impl MovieCollection {
         let conn = self.pool.get().await?;
         query.fetch(&conn).await.map_err(Into::into)
     }
+
+    pub async fn get_playback_markers(&self, idx: i32) -> Result<PlaybackMarkers, Error> {
+        let query = query!(
+            r#"
+                SELECT intro_start, intro_end, credits_start
+                FROM movie_collection
+                WHERE idx = $idx
+            "#,
+            idx = idx
+        );
+        let conn = self.pool.get().await?;
+        let markers: Option<PlaybackMarkers> = query.fetch_opt(&conn).await?;
+        Ok(markers.unwrap_or_default())
+    }
+
+    pub async fn set_credits_start(
+        &self,
+        idx: i32,
+        credits_start: Option<f64>,
+    ) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE movie_collection
+                SET credits_start=$credits_start, last_modified=$last_modified
+                WHERE idx = $idx
+            "#,
+            idx = idx,
+            credits_start = credits_start,
+            last_modified = DateTimeWrapper::from(self.clock.now())
+        );
+        let conn = self.pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    // Stores the outcome of a credits probe, including "no credits found", so unchanged
+    // files aren't run through ffmpeg again on every pass
+    async fn record_credits_probe(
+        &self,
+        idx: i32,
+        credits_start: Option<f64>,
+    ) -> Result<(), Error> {
+        let now = DateTimeWrapper::from(self.clock.now());
+        let query = query!(
+            r#"
+                UPDATE movie_collection
+                SET credits_start=$credits_start, credits_probed_at=$now, last_modified=$now
+                WHERE idx = $idx
+            "#,
+            idx = idx,
+            credits_start = credits_start,
+            now = now
+        );
+        let conn = self.pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub async fn detect_credits(&self) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                SELECT idx, path, credits_probed_at
+                FROM movie_collection
+                WHERE credits_start IS NULL AND is_deleted = false
+            "#
+        );
+        let conn = self.pool.get().await?;
+        let entries: Vec<(i32, StackString, Option<DateTimeWrapper>)> = query.fetch(&conn).await?;
+        for (idx, entry_path, probed_at) in entries {
+            let path = Path::new(entry_path.as_str());
+            let modified = match fs::metadata(path).await.and_then(|m| m.modified()) {
+                Ok(modified) => DateTime::<Utc>::from(modified),
+                Err(_) => continue,
+            };
+            if probed_at.map_or(false, |probed_at| *probed_at >= modified) {
+                continue;
+            }
+            match detect_credits_start(path).await {
+                Ok(credits_start) => {
+                    self.record_credits_probe(idx, credits_start).await?;
+                    if let Some(credits_start) = credits_start {
+                        self.stdout
+                            .send(format!("credits {} {:.1}", entry_path, credits_start));
+                    }
+                }
+                Err(e) => self
+                    .stdout
+                    .send(format!("credits detection failed {} {}", entry_path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn set_media_info(&self, idx: i32, info: &MediaInfo) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE movie_collection
+                SET file_size=$file_size, duration=$duration, resolution=$resolution,
+                    video_codec=$video_codec, audio_codec=$audio_codec,
+                    last_modified=$last_modified
+                WHERE idx = $idx
+            "#,
+            idx = idx,
+            file_size = info.file_size,
+            duration = info.duration,
+            resolution = info.resolution,
+            video_codec = info.video_codec,
+            audio_codec = info.audio_codec,
+            last_modified = DateTimeWrapper::from(self.clock.now())
+        );
+        let conn = self.pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub async fn update_media_info(&self) -> Result<usize, Error> {
+        let query = query!(
+            r#"
+                SELECT idx, path
+                FROM movie_collection
+                WHERE file_size IS NULL AND is_deleted = false
+            "#
+        );
+        let conn = self.pool.get().await?;
+        let entries: Vec<(i32, StackString)> = query.fetch(&conn).await?;
+        let mut updated = 0;
+        for (idx, path) in entries {
+            let path = Path::new(path.as_str());
+            if !path.exists() {
+                continue;
+            }
+            // Record the size even when ffprobe fails so the file isn't
+            // probed again on every scan
+            let info = match MediaInfo::probe(path).await {
+                Ok(info) => info,
+                Err(e) => {
+                    self.stdout
+                        .send(format!("media probe failed {:?} {}", path, e));
+                    let file_size = fs::metadata(path).await?.len() as i64;
+                    MediaInfo {
+                        file_size: Some(file_size),
+                        ..MediaInfo::default()
+                    }
+                }
+            };
+            self.set_media_info(idx, &info).await?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
 }
 
 pub async fn find_new_episodes_http_worker(
@@ -872,13 +1478,27 @@ pub async fn find_new_episodes_http_worker(
     }
 
     let queue: HashMap<(StackString, i32, i32), i32> = queue.into_iter().collect();
+    let availability = ShowAvailability::get_map(pool, &config.availability_region).await?;
+    let downloads_enabled = config.download_client_url.is_some();
+    let downloads = DownloadRequest::get_map(pool).await?;
+    for epi in &episodes {
+        let key = (epi.show.clone(), epi.season, epi.episode);
+        if !queue.contains_key(&key) {
+            continue;
+        }
+        if let Some(request) = downloads.get(&(epi.link.clone(), epi.season, epi.episode)) {
+            if request.status != DownloadStatus::Completed {
+                request.set_completed(pool).await?;
+            }
+        }
+    }
 
     let output = episodes
         .into_iter()
         .map(|epi| {
             let key = (epi.show.clone(), epi.season, epi.episode);
             format!(
-                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}<td>{}</td><td>{}</td></tr>",
                 format!(
                     r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}/{}')">{}</a>"#,
                     epi.link, epi.season, epi.title
@@ -901,6 +1521,14 @@ pub async fn find_new_episodes_http_worker(
                     .replace("SHOW", &epi.show)
                     .replace("LINK", &epi.link)
                     .replace("SEASON", &epi.season.to_string()),
+                availability
+                    .get(epi.link.as_str())
+                    .map_or_else(String::new, |a| a.get_html().into()),
+                download_cell(
+                    downloads.get(&(epi.link.clone(), epi.season, epi.episode)),
+                    &epi,
+                    downloads_enabled && !queue.contains_key(&key),
+                ),
             )
             .into()
         })
@@ -909,6 +1537,32 @@ pub async fn find_new_episodes_http_worker(
     Ok(output)
 }
 
+fn download_cell(
+    request: Option<&DownloadRequest>,
+    epi: &NewEpisodesResult,
+    can_request: bool,
+) -> StackString {
+    let button = format!(
+        r#"<button type="submit" onclick="download_request('{}', {}, {});">{}</button>"#,
+        epi.link,
+        epi.season,
+        epi.episode,
+        if request.is_some() {
+            "retry"
+        } else {
+            "download"
+        },
+    );
+    match request {
+        Some(request) if request.status == DownloadStatus::Failed && can_request => {
+            format!("{} {}", request.get_html(), button).into()
+        }
+        Some(request) => request.get_html(),
+        None if can_request => button.into(),
+        None => "".into(),
+    }
+}
+
 #[derive(Serialize, Deserialize, Schema)]
 pub struct LastModifiedResponse {
     pub table: StackString,
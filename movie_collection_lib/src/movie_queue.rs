@@ -1,18 +1,48 @@
 use anyhow::{format_err, Error};
 use chrono::{DateTime, Utc};
+use deadpool_postgres::Transaction;
 use futures::future::try_join_all;
-use itertools::Itertools;
-use log::debug;
-use postgres_query::{query, query_dyn, FromSqlRow};
+use postgres_query::{query, query_dyn, FromSqlRow, Parameter, Query};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
-use std::{fmt, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
 use stdout_channel::StdoutChannel;
+use thiserror::Error as ThisError;
+use tracing::debug;
 
-use crate::{config::Config, movie_collection::MovieCollection, pgpool::PgPool};
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    episode_numbering::resolve_file_stem,
+    media_info::{parse_resolution_height, MediaInfo},
+    media_kind::MediaKind,
+    movie_collection::{CollectionError, MovieCollection},
+    pgpool::PgPool,
+    queue_import::{apply_queue_import, QueueImportRejection, QueueImportReport, QueueImportRow},
+    utils::{canonicalize_path, format_episode_span, parse_episode_span, percent_encode},
+};
 use crate::datetime_wrapper::DateTimeWrapper;
-use crate::utils::{option_string_wrapper, parse_file_stem};
+use crate::utils::option_string_wrapper;
+
+/// Returned by the queue operations that take user supplied paths or indices
+/// (`insert_into_queue`, `reorder_queue`), the rest of `MovieQueueDB` keeps returning
+/// `anyhow::Error`
+#[derive(ThisError, Debug)]
+pub enum QueueError {
+    #[error("File not found: {0}")]
+    NotFound(StackString),
+    #[error("Not in queue: {0}")]
+    NotQueued(i32),
+    #[error(transparent)]
+    Collection(#[from] CollectionError),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
 
 #[derive(Default, Serialize)]
 pub struct MovieQueueResult {
@@ -24,6 +54,131 @@ pub struct MovieQueueResult {
     pub eplink: Option<StackString>,
     pub season: Option<i32>,
     pub episode: Option<i32>,
+    pub episode_end: Option<i32>,
+    #[serde(flatten)]
+    pub media_info: MediaInfo,
+}
+
+impl MovieQueueResult {
+    // Label for files covering several episodes, e.g. "ep01–02"
+    pub fn episode_span(&self) -> Option<StackString> {
+        self.episode
+            .zip(self.episode_end)
+            .map(|(first, last)| format_episode_span(first, last))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueueFilter {
+    pub max_height: Option<i32>,
+    pub codec: Option<StackString>,
+    pub media_kinds: Vec<MediaKind>,
+}
+
+impl QueueFilter {
+    pub fn new(
+        resolution: Option<&str>,
+        codec: Option<&str>,
+        media_kind: Option<&str>,
+    ) -> Result<Self, Error> {
+        let max_height = match resolution.filter(|r| !r.is_empty()) {
+            Some(r) => {
+                let height = parse_resolution_height(r)
+                    .ok_or_else(|| format_err!("Invalid resolution {}", r))?;
+                Some(height)
+            }
+            None => None,
+        };
+        let codec = match codec.filter(|c| !c.is_empty()) {
+            Some(c) => {
+                let c = c.to_lowercase();
+                if !c
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                {
+                    return Err(format_err!("Invalid codec {}", c));
+                }
+                Some(c.into())
+            }
+            None => None,
+        };
+        let media_kinds = match media_kind {
+            Some(k) => MediaKind::parse_list(&k.to_lowercase())?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            max_height,
+            codec,
+            media_kinds,
+        })
+    }
+
+    // Query string parameters reproducing this filter, percent-encoded
+    pub fn query_params(&self) -> Vec<StackString> {
+        let mut params = Vec::new();
+        if let Some(max_height) = self.max_height {
+            params.push(format!("resolution={}p", max_height).into());
+        }
+        if let Some(codec) = &self.codec {
+            params.push(format!("codec={}", percent_encode(codec)).into());
+        }
+        if !self.media_kinds.is_empty() {
+            let media_kinds: Vec<String> =
+                self.media_kinds.iter().map(ToString::to_string).collect();
+            params.push(format!("media_kind={}", percent_encode(&media_kinds.join(","))).into());
+        }
+        params
+    }
+}
+
+// Holds the values bound into the WHERE clause shared by the queue listing and count
+struct QueueConstraints<'a> {
+    patterns: Vec<String>,
+    filter: &'a QueueFilter,
+    media_kinds: Vec<String>,
+    exclude: &'a [i32],
+}
+
+impl<'a> QueueConstraints<'a> {
+    fn new(patterns: &[&str], filter: &'a QueueFilter, exclude: &'a [i32]) -> Self {
+        Self {
+            patterns: patterns.iter().map(|p| format!("%{}%", p)).collect(),
+            filter,
+            media_kinds: filter.media_kinds.iter().map(ToString::to_string).collect(),
+            exclude,
+        }
+    }
+
+    fn bindings(&self) -> (String, Vec<(&'static str, Parameter)>) {
+        let mut constraints: Vec<&str> = Vec::new();
+        let mut bindings = Vec::new();
+        if !self.patterns.is_empty() {
+            constraints.push("b.path like ANY($patterns)");
+            bindings.push(("patterns", &self.patterns as Parameter));
+        }
+        if let Some(max_height) = &self.filter.max_height {
+            constraints.push("split_part(b.resolution, 'x', 2)::INT <= $max_height");
+            bindings.push(("max_height", max_height as Parameter));
+        }
+        if let Some(codec) = &self.filter.codec {
+            constraints.push("(lower(b.video_codec) = $codec OR lower(b.audio_codec) = $codec)");
+            bindings.push(("codec", codec as Parameter));
+        }
+        if !self.media_kinds.is_empty() {
+            constraints.push("b.media_kind = ANY($media_kinds)");
+            bindings.push(("media_kinds", &self.media_kinds as Parameter));
+        }
+        if !self.exclude.is_empty() {
+            constraints.push("a.collection_idx <> ALL($exclude)");
+            bindings.push(("exclude", &self.exclude as Parameter));
+        }
+        let where_clause = if constraints.is_empty() {
+            "".to_string()
+        } else {
+            format!("WHERE {}", constraints.join(" AND "))
+        };
+        (where_clause, bindings)
+    }
 }
 
 impl fmt::Display for MovieQueueResult {
@@ -43,6 +198,7 @@ pub struct MovieQueueDB {
     pub config: Config,
     pub pool: PgPool,
     pub stdout: StdoutChannel<StackString>,
+    pub clock: SharedClock,
 }
 
 impl MovieQueueDB {
@@ -51,50 +207,30 @@ impl MovieQueueDB {
             config: config.clone(),
             pool: pool.clone(),
             stdout: stdout.clone(),
+            clock: SharedClock::default(),
         }
     }
 
-    pub async fn remove_from_queue_by_idx(&self, idx: i32) -> Result<(), Error> {
-        let mut conn = self.pool.get().await?;
-        let tran = conn.transaction().await?;
-
-        let query = r#"SELECT max(idx) FROM movie_queue"#;
-        let max_idx: i32 = tran
-            .query(query, &[])
-            .await?
-            .get(0)
-            .map_or(-1, |r| r.get(0));
-        if idx > max_idx || idx < 0 {
-            return Ok(());
-        }
-        let diff = max_idx - idx;
-
-        let query = query!(r#"DELETE FROM movie_queue WHERE idx = $idx"#, idx = idx);
-        tran.execute(query.sql(), query.parameters()).await?;
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
 
-        let query = query!(
-            r#"
-                UPDATE movie_queue
-                SET idx = idx + $diff, last_modified = now()
-                WHERE idx > $idx
-            "#,
-            diff = diff,
-            idx = idx
-        );
-        tran.execute(query.sql(), query.parameters()).await?;
+    fn collection(&self) -> MovieCollection {
+        MovieCollection::new(&self.config, &self.pool, &self.stdout).with_clock(self.clock.clone())
+    }
 
+    pub async fn remove_from_queue_by_idx(&self, idx: i32) -> Result<(), Error> {
         let query = query!(
             r#"
-                UPDATE movie_queue
-                SET idx = idx - $diff - 1, last_modified = now()
-                WHERE idx > $idx
+                DELETE FROM movie_queue_entry
+                WHERE collection_idx = (SELECT collection_idx FROM movie_queue WHERE idx = $idx)
             "#,
-            diff = diff,
             idx = idx
         );
-        tran.execute(query.sql(), query.parameters()).await?;
-
-        tran.commit().await.map_err(Into::into)
+        let conn = self.pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
     }
 
     pub async fn remove_from_queue_by_collection_idx(
@@ -102,18 +238,16 @@ impl MovieQueueDB {
         collection_idx: i32,
     ) -> Result<(), Error> {
         let query = query!(
-            r#"SELECT idx FROM movie_queue WHERE collection_idx=$idx"#,
-            idx = collection_idx
+            r#"DELETE FROM movie_queue_entry WHERE collection_idx = $collection_idx"#,
+            collection_idx = collection_idx
         );
         let conn = self.pool.get().await?;
-        if let Some((idx,)) = query.fetch_opt(&conn).await? {
-            self.remove_from_queue_by_idx(idx).await?;
-        }
+        query.execute(&conn).await?;
         Ok(())
     }
 
     pub async fn remove_from_queue_by_path(&self, path: &str) -> Result<(), Error> {
-        let mc = MovieCollection::new(&self.config, &self.pool, &self.stdout);
+        let mc = self.collection();
         if let Some(collection_idx) = mc.get_collection_index(&path).await? {
             self.remove_from_queue_by_collection_idx(collection_idx)
                 .await
@@ -122,11 +256,11 @@ impl MovieQueueDB {
         }
     }
 
-    pub async fn insert_into_queue(&self, idx: i32, path: &str) -> Result<(), Error> {
+    pub async fn insert_into_queue(&self, idx: i32, path: &str) -> Result<(), QueueError> {
         if !Path::new(&path).exists() {
-            return Err(format_err!("File doesn't exist"));
+            return Err(QueueError::NotFound(path.into()));
         }
-        let mc = MovieCollection::new(&self.config, &self.pool, &self.stdout);
+        let mc = self.collection();
         let collection_idx = if let Some(i) = mc.get_collection_index(&path).await? {
             i
         } else {
@@ -138,6 +272,7 @@ impl MovieQueueDB {
 
         self.insert_into_queue_by_collection_idx(idx, collection_idx)
             .await
+            .map_err(Into::into)
     }
 
     pub async fn insert_into_queue_by_collection_idx(
@@ -145,60 +280,152 @@ impl MovieQueueDB {
         idx: i32,
         collection_idx: i32,
     ) -> Result<(), Error> {
+        let last_modified: DateTimeWrapper = self.clock.now().into();
+        let mut conn = self.pool.get().await?;
+        let tran = conn.transaction().await?;
+
+        tran.execute(
+            "LOCK TABLE movie_queue_entry IN SHARE ROW EXCLUSIVE MODE",
+            &[],
+        )
+        .await?;
+        set_queue_position(&tran, idx, collection_idx, last_modified).await?;
+
+        tran.commit().await.map_err(Into::into)
+    }
+
+    pub async fn reorder_queue(
+        &self,
+        collection_idx: i32,
+        new_position: i32,
+    ) -> Result<i32, QueueError> {
+        self.reorder_queue_entry(collection_idx, new_position)
+            .await?
+            .ok_or(QueueError::NotQueued(collection_idx))
+    }
+
+    async fn reorder_queue_entry(
+        &self,
+        collection_idx: i32,
+        new_position: i32,
+    ) -> Result<Option<i32>, Error> {
+        let last_modified: DateTimeWrapper = self.clock.now().into();
+        let mut conn = self.pool.get().await?;
+        let tran = conn.transaction().await?;
+
+        tran.execute(
+            "LOCK TABLE movie_queue_entry IN SHARE ROW EXCLUSIVE MODE",
+            &[],
+        )
+        .await?;
+
         let query = query!(
-            r#"SELECT idx FROM movie_queue WHERE collection_idx = $idx"#,
-            idx = collection_idx
+            r#"SELECT idx FROM movie_queue WHERE collection_idx = $collection_idx"#,
+            collection_idx = collection_idx
         );
-        let conn = self.pool.get().await?;
-        if let Some((current_idx,)) = query.fetch_opt(&conn).await? {
-            self.remove_from_queue_by_idx(current_idx).await?;
+        let current_idx: i32 = match tran.query_opt(query.sql(), query.parameters()).await? {
+            Some(row) => row.try_get(0)?,
+            None => return Ok(None),
+        };
+
+        let query = r#"SELECT min(idx), max(idx) FROM movie_queue"#;
+        let row = tran.query_one(query, &[]).await?;
+        let min_idx: i32 = row.try_get(0)?;
+        let max_idx: i32 = row.try_get(1)?;
+        let new_idx = new_position.max(min_idx).min(max_idx);
+        if new_idx == current_idx {
+            tran.commit().await?;
+            return Ok(Some(new_idx));
         }
+        set_queue_position(&tran, new_idx, collection_idx, last_modified).await?;
+
+        tran.commit().await?;
+        Ok(Some(new_idx))
+    }
 
+    pub async fn import_queue(&self, rows: &[QueueImportRow]) -> Result<QueueImportReport, Error> {
+        let last_modified: DateTimeWrapper = self.clock.now().into();
         let mut conn = self.pool.get().await?;
         let tran = conn.transaction().await?;
 
-        let query = r#"SELECT max(idx) FROM movie_queue"#;
-        let max_idx: i32 = tran
+        tran.execute(
+            "LOCK TABLE movie_queue_entry IN SHARE ROW EXCLUSIVE MODE",
+            &[],
+        )
+        .await?;
+
+        let query = r#"SELECT idx, collection_idx FROM movie_queue ORDER BY idx"#;
+        let mut queue: Vec<(i32, i32)> = tran
             .query(query, &[])
             .await?
-            .get(0)
-            .map_or(-1, |r| r.get(0));
-        let diff = max_idx - idx + 2;
-        debug!("{} {} {}", max_idx, idx, diff);
-
-        let query = query!(
-            r#"
-                UPDATE movie_queue
-                SET idx = idx + $diff, last_modified = now()
-                WHERE idx >= $idx
-            "#,
-            diff = diff,
-            idx = idx
-        );
-        tran.execute(query.sql(), query.parameters()).await?;
+            .iter()
+            .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+            .collect::<Result<_, Error>>()?;
 
-        let query = query!(
-            r#"
-                INSERT INTO movie_queue (idx, collection_idx, last_modified)
-                VALUES ($idx, $collection_idx, now())
-            "#,
-            idx = idx,
-            collection_idx = collection_idx
-        );
-        tran.execute(query.sql(), query.parameters()).await?;
+        let mut report = QueueImportReport::default();
+        let mut seen = HashSet::new();
+        for (i, entry) in rows.iter().enumerate() {
+            let reject = |reason: &str| QueueImportRejection {
+                row: i + 1,
+                path: entry.path.clone(),
+                reason: reason.into(),
+            };
+            if entry.idx.map_or(false, |idx| idx < 0) {
+                report.rejected.push(reject("Invalid position"));
+                continue;
+            }
+            let path = canonicalize_path(&entry.path);
+            let query = query!(
+                r#"SELECT idx FROM movie_collection WHERE path = $path AND NOT is_deleted"#,
+                path = path
+            );
+            let collection_idx: Option<i32> = tran
+                .query_opt(query.sql(), query.parameters())
+                .await?
+                .map(|row| row.try_get(0))
+                .transpose()?;
+            match collection_idx {
+                None => report.rejected.push(reject("Not in collection")),
+                Some(collection_idx) if !seen.insert(collection_idx) => {
+                    report.rejected.push(reject("Duplicate entry"));
+                }
+                Some(collection_idx) => {
+                    apply_queue_import(&mut queue, collection_idx, entry.idx);
+                    report.imported += 1;
+                }
+            }
+        }
 
-        let query = query!(
-            r#"
-                UPDATE movie_queue
-                SET idx = idx - $diff + 1, last_modified = now()
-                WHERE idx > $idx
-            "#,
-            diff = diff,
-            idx = idx
-        );
-        tran.execute(query.sql(), query.parameters()).await?;
+        // Imported positions are written back as whole number ranks, rows already holding
+        // their rank are left alone
+        let query = r#"SELECT collection_idx, rank FROM movie_queue_entry"#;
+        let ranks: HashMap<i32, f64> = tran
+            .query(query, &[])
+            .await?
+            .iter()
+            .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+            .collect::<Result<_, Error>>()?;
+        for (rank, (_, collection_idx)) in queue.iter().enumerate() {
+            let rank = rank as f64;
+            if ranks.get(collection_idx) == Some(&rank) {
+                continue;
+            }
+            let query = query!(
+                r#"
+                    INSERT INTO movie_queue_entry (collection_idx, rank, last_modified)
+                    VALUES ($collection_idx, $rank, $last_modified)
+                    ON CONFLICT (collection_idx) DO UPDATE
+                    SET rank = $rank, last_modified = $last_modified
+                "#,
+                collection_idx = collection_idx,
+                rank = rank,
+                last_modified = last_modified
+            );
+            tran.execute(query.sql(), query.parameters()).await?;
+        }
 
-        tran.commit().await.map_err(Into::into)
+        tran.commit().await?;
+        Ok(report)
     }
 
     pub async fn get_max_queue_index(&self) -> Result<i32, Error> {
@@ -214,6 +441,63 @@ impl MovieQueueDB {
     pub async fn print_movie_queue(
         &self,
         patterns: &[&str],
+    ) -> Result<Vec<MovieQueueResult>, Error> {
+        self.print_movie_queue_filtered(patterns, &QueueFilter::default())
+            .await
+    }
+
+    pub async fn print_movie_queue_filtered(
+        &self,
+        patterns: &[&str],
+        filter: &QueueFilter,
+    ) -> Result<Vec<MovieQueueResult>, Error> {
+        self.get_movie_queue(patterns, filter, &[], None).await
+    }
+
+    // Number of queue entries matching `patterns` and `filter`, leaving out the
+    // collection entries in `exclude`
+    pub async fn count_movie_queue(
+        &self,
+        patterns: &[&str],
+        filter: &QueueFilter,
+        exclude: &[i32],
+    ) -> Result<usize, Error> {
+        let constraints = QueueConstraints::new(patterns, filter, exclude);
+        let (where_clause, bindings) = constraints.bindings();
+        let query = format!(
+            r#"
+                SELECT count(*)
+                FROM movie_queue a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                {}
+            "#,
+            where_clause
+        );
+        let query: Query = query_dyn!(&query, ..bindings)?;
+        let conn = self.pool.get().await?;
+        let (count,): (i64,) = query.fetch_one(&conn).await?;
+        Ok(count as usize)
+    }
+
+    // One page of the queue in queue order, the counterpart of `count_movie_queue`
+    pub async fn print_movie_queue_page(
+        &self,
+        patterns: &[&str],
+        filter: &QueueFilter,
+        exclude: &[i32],
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<MovieQueueResult>, Error> {
+        self.get_movie_queue(patterns, filter, exclude, Some((offset, limit)))
+            .await
+    }
+
+    async fn get_movie_queue(
+        &self,
+        patterns: &[&str],
+        filter: &QueueFilter,
+        exclude: &[i32],
+        page: Option<(usize, usize)>,
     ) -> Result<Vec<MovieQueueResult>, Error> {
         #[derive(FromSqlRow)]
         struct PrintMovieQueue {
@@ -221,27 +505,37 @@ impl MovieQueueDB {
             path: StackString,
             link: Option<StackString>,
             istv: Option<bool>,
+            file_size: Option<i64>,
+            duration: Option<f64>,
+            resolution: Option<StackString>,
+            video_codec: Option<StackString>,
+            audio_codec: Option<StackString>,
         }
-        let constraints = patterns
-            .iter()
-            .map(|p| format!("b.path like '%{}%'", p))
-            .join(" OR ");
+        let (offset, limit) = page.map_or((0, 0), |(offset, limit)| (offset as i64, limit as i64));
+        let constraints = QueueConstraints::new(patterns, filter, exclude);
+        let (where_clause, mut bindings) = constraints.bindings();
+        let page_clause = if page.is_some() {
+            bindings.push(("offset", &offset as Parameter));
+            bindings.push(("limit", &limit as Parameter));
+            "OFFSET $offset LIMIT $limit"
+        } else {
+            ""
+        };
 
-        let query = query_dyn!(&format!(
+        let query = format!(
             r#"
-                SELECT a.idx, b.path, c.link, c.istv
+                SELECT a.idx, b.path, c.link, c.istv, b.file_size, b.duration, b.resolution,
+                       b.video_codec, b.audio_codec
                 FROM movie_queue a
                 JOIN movie_collection b ON a.collection_idx = b.idx
                 LEFT JOIN imdb_ratings c ON b.show_id = c.index
                 {}
                 ORDER BY a.idx
+                {}
             "#,
-            if constraints.is_empty() {
-                "".to_string()
-            } else {
-                format!("WHERE {}", constraints)
-            }
-        ),)?;
+            where_clause, page_clause
+        );
+        let query: Query = query_dyn!(&query, ..bindings)?;
         let conn = self.pool.get().await?;
         let results: Vec<PrintMovieQueue> = query.fetch(&conn).await?;
 
@@ -251,6 +545,13 @@ impl MovieQueueDB {
                 path: row.path,
                 link: row.link,
                 istv: row.istv.unwrap_or(false),
+                media_info: MediaInfo {
+                    file_size: row.file_size,
+                    duration: row.duration,
+                    resolution: row.resolution,
+                    video_codec: row.video_codec,
+                    audio_codec: row.audio_codec,
+                },
                 ..MovieQueueResult::default()
             };
 
@@ -259,7 +560,7 @@ impl MovieQueueDB {
                     .file_stem()
                     .ok_or_else(|| format_err!("No file stem"))?
                     .to_string_lossy();
-                let (show, season, episode) = parse_file_stem(&file_stem);
+                let (show, season, episode) = resolve_file_stem(&self.pool, &file_stem).await?;
                 let query = query!(
                     r#"
                             SELECT epurl
@@ -277,6 +578,9 @@ impl MovieQueueDB {
                     result.show = Some(show.to_string().into());
                     result.season = Some(season);
                     result.episode = Some(episode);
+                    result.episode_end = parse_episode_span(&file_stem)
+                        .filter(|(_, _, first, _)| *first == episode)
+                        .map(|(_, _, _, last)| last);
                 }
             }
             Ok(result)
@@ -306,6 +610,106 @@ impl MovieQueueDB {
     }
 }
 
+// Queue order is kept as a floating point rank so placing an entry only writes that one
+// row. Returns a rank between the neighbouring ranks `before` and `after`, or None once
+// they are too close together to split.
+pub fn insert_rank(before: Option<f64>, after: Option<f64>) -> Option<f64> {
+    match (before, after) {
+        (None, None) => Some(0.0),
+        (Some(before), None) => Some(before.floor() + 1.0),
+        (None, Some(after)) => Some(after.ceil() - 1.0),
+        (Some(before), Some(after)) => {
+            let rank = (before + after) / 2.0;
+            if rank > before && rank < after {
+                Some(rank)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// Ranks on either side of position `idx`, ignoring the entry being placed.
+async fn queue_neighbours(
+    tran: &Transaction<'_>,
+    idx: i32,
+    collection_idx: i32,
+) -> Result<(Option<f64>, Option<f64>), Error> {
+    let idx = i64::from(idx.max(0));
+    let offset = (idx - 1).max(0);
+    let query = query!(
+        r#"
+            SELECT rank
+            FROM movie_queue_entry
+            WHERE collection_idx != $collection_idx
+            ORDER BY rank, collection_idx
+            OFFSET $offset LIMIT 2
+        "#,
+        collection_idx = collection_idx,
+        offset = offset
+    );
+    let ranks: Vec<f64> = tran
+        .query(query.sql(), query.parameters())
+        .await?
+        .iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<_, _>>()?;
+    if idx == 0 {
+        return Ok((None, ranks.first().copied()));
+    }
+    if ranks.is_empty() {
+        // Past the end of the queue, append after the last entry.
+        let query = query!(
+            r#"
+                SELECT max(rank)
+                FROM movie_queue_entry
+                WHERE collection_idx != $collection_idx
+            "#,
+            collection_idx = collection_idx
+        );
+        let row = tran.query_one(query.sql(), query.parameters()).await?;
+        return Ok((row.try_get(0)?, None));
+    }
+    Ok((ranks.first().copied(), ranks.get(1).copied()))
+}
+
+async fn set_queue_position(
+    tran: &Transaction<'_>,
+    idx: i32,
+    collection_idx: i32,
+    last_modified: DateTimeWrapper,
+) -> Result<(), Error> {
+    let (before, after) = queue_neighbours(tran, idx, collection_idx).await?;
+    let rank = match insert_rank(before, after) {
+        Some(rank) => rank,
+        None => {
+            debug!("renumbering movie_queue ranks");
+            let query = r#"
+                UPDATE movie_queue_entry a
+                SET rank = b.idx
+                FROM movie_queue b
+                WHERE a.collection_idx = b.collection_idx
+            "#;
+            tran.execute(query, &[]).await?;
+            let (before, after) = queue_neighbours(tran, idx, collection_idx).await?;
+            insert_rank(before, after).ok_or_else(|| format_err!("No rank for position {}", idx))?
+        }
+    };
+    let query = query!(
+        r#"
+            INSERT INTO movie_queue_entry (collection_idx, rank, last_modified)
+            VALUES ($collection_idx, $rank, $last_modified)
+            ON CONFLICT (collection_idx) DO UPDATE
+            SET rank = $rank, last_modified = $last_modified
+        "#,
+        collection_idx = collection_idx,
+        rank = rank,
+        last_modified = last_modified
+    );
+    tran.execute(query.sql(), query.parameters()).await?;
+    Ok(())
+}
+
 #[derive(Default, Debug, Serialize, Deserialize, FromSqlRow, Schema)]
 pub struct MovieQueueRow {
     pub idx: i32,
@@ -314,3 +718,38 @@ pub struct MovieQueueRow {
     pub show: StackString,
     pub last_modified: Option<DateTimeWrapper>,
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::movie_queue::{insert_rank, QueueFilter};
+
+    #[test]
+    fn test_insert_rank() {
+        assert_eq!(insert_rank(None, None), Some(0.0));
+        assert_eq!(insert_rank(None, Some(0.0)), Some(-1.0));
+        assert_eq!(insert_rank(Some(0.0), Some(1.0)), Some(0.5));
+        assert_eq!(insert_rank(Some(2.0), None), Some(3.0));
+        assert_eq!(insert_rank(Some(2.5), None), Some(3.0));
+
+        let before = 1.0;
+        let after = before + f64::EPSILON;
+        assert_eq!(insert_rank(Some(before), Some(after)), None);
+    }
+
+    #[test]
+    fn test_queue_filter_query_params() -> Result<(), Error> {
+        let filter = QueueFilter::new(Some("1280x720"), Some("HEVC"), Some("episode,movie"))?;
+        assert_eq!(
+            filter.query_params(),
+            vec![
+                "resolution=720p",
+                "codec=hevc",
+                "media_kind=episode%2Cmovie"
+            ]
+        );
+        assert!(QueueFilter::new(None, Some("');alert(1);//"), None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,582 @@
+use anyhow::Error;
+use postgres_query::{query, query_dyn, FromSqlRow, Parameter, Query};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use reqwest::Url;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::{Path, PathBuf};
+use stdout_channel::StdoutChannel;
+
+use crate::{
+    config::Config,
+    datetime_wrapper::DateTimeWrapper,
+    pgpool::PgPool,
+    utils::{escape_html, walk_directory},
+};
+
+pub const MUSIC_PAGE_SIZE: i64 = 50;
+const ALPHABET: &str = "#ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const UPSERT_BATCH_SIZE: usize = 1000;
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct MusicCollection {
+    pub id: i32,
+    pub path: StackString,
+    pub artist: Option<StackString>,
+    pub album: Option<StackString>,
+    pub title: Option<StackString>,
+    pub last_modified: DateTimeWrapper,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MusicTags {
+    pub artist: Option<StackString>,
+    pub album: Option<StackString>,
+    pub title: Option<StackString>,
+}
+
+impl MusicTags {
+    pub fn from_file(path: &Path) -> Self {
+        let tags = match path.extension().and_then(|e| e.to_str()) {
+            Some("mp3") => Self::from_id3(path),
+            Some("flac") => Self::from_flac(path),
+            _ => None,
+        };
+        tags.unwrap_or_default().fill_from_path(path)
+    }
+
+    fn from_id3(path: &Path) -> Option<Self> {
+        let tag = id3::Tag::read_from_path(path).ok()?;
+        Some(Self {
+            artist: tag.artist().map(Into::into),
+            album: tag.album().map(Into::into),
+            title: tag.title().map(Into::into),
+        })
+    }
+
+    fn from_flac(path: &Path) -> Option<Self> {
+        let tag = metaflac::Tag::read_from_path(path).ok()?;
+        let get = |key: &str| {
+            tag.get_vorbis(key)
+                .and_then(|mut v| v.next())
+                .map(Into::into)
+        };
+        Some(Self {
+            artist: get("ARTIST"),
+            album: get("ALBUM"),
+            title: get("TITLE"),
+        })
+    }
+
+    // Fall back to the Artist/Album/Title.ext layout for untagged files
+    fn fill_from_path(mut self, path: &Path) -> Self {
+        let album_dir = path.parent();
+        let artist_dir = album_dir.and_then(Path::parent);
+        let name = |p: Option<&Path>| {
+            p.and_then(Path::file_name)
+                .map(|s| s.to_string_lossy().into_owned().into())
+        };
+        if self.title.is_none() {
+            self.title = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned().into());
+        }
+        if self.album.is_none() {
+            self.album = name(album_dir);
+        }
+        if self.artist.is_none() {
+            self.artist = name(artist_dir);
+        }
+        self
+    }
+}
+
+impl MusicCollection {
+    pub async fn get_by_id(pool: &PgPool, id: i32) -> Result<Option<Self>, Error> {
+        let query = query!("SELECT * FROM music_collection WHERE id = $id", id = id);
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub fn get_player_html(&self, url: &str) -> StackString {
+        let mime_type = match Path::new(self.path.as_str())
+            .extension()
+            .and_then(|e| e.to_str())
+        {
+            Some("flac") => "audio/flac",
+            _ => "audio/mpeg",
+        };
+        let filter = MusicBrowseFilter {
+            artist: Some(self.artist.clone().unwrap_or_default()),
+            album: Some(self.album.clone().unwrap_or_default()),
+            ..MusicBrowseFilter::default()
+        };
+        format!(
+            r#"
+            <a href="javascript:updateMainArticle('{back}')">Go Back</a><br>
+            <h3>{artist} - {album} - {title}</h3>
+            <audio id="music_player" controls autoplay>
+            <source src="{url}" type="{mime_type}">
+            Your browser does not support HTML5 audio.
+            </audio>
+            "#,
+            back = filter.link(),
+            artist = escape_html(self.artist.as_deref().unwrap_or("")),
+            album = escape_html(self.album.as_deref().unwrap_or("")),
+            title = escape_html(self.title.as_deref().unwrap_or("")),
+            url = url,
+            mime_type = mime_type,
+        )
+        .into()
+    }
+
+    pub async fn get_by_path(pool: &PgPool, path: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM music_collection WHERE path = $path",
+            path = path
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn upsert(pool: &PgPool, path: &str, tags: &MusicTags) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO music_collection (path, artist, album, title, last_modified)
+                VALUES ($path, $artist, $album, $title, now())
+                ON CONFLICT (path) DO UPDATE
+                SET artist=$artist, album=$album, title=$title, last_modified=now()
+            "#,
+            path = path,
+            artist = tags.artist,
+            album = tags.album,
+            title = tags.title
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    // Returns the paths that weren't in the collection before
+    pub async fn upsert_batch(
+        pool: &PgPool,
+        entries: &[(String, MusicTags)],
+    ) -> Result<Vec<StackString>, Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let mut inserted = Vec::new();
+        for chunk in entries.chunks(UPSERT_BATCH_SIZE) {
+            let paths: Vec<&str> = chunk.iter().map(|(p, _)| p.as_str()).collect();
+            let artists: Vec<Option<&str>> =
+                chunk.iter().map(|(_, t)| t.artist.as_deref()).collect();
+            let albums: Vec<Option<&str>> = chunk.iter().map(|(_, t)| t.album.as_deref()).collect();
+            let titles: Vec<Option<&str>> = chunk.iter().map(|(_, t)| t.title.as_deref()).collect();
+            let query = query!(
+                r#"
+                    INSERT INTO music_collection (path, artist, album, title, last_modified)
+                    SELECT path, artist, album, title, now()
+                    FROM unnest($paths::text[], $artists::text[], $albums::text[], $titles::text[])
+                        AS t(path, artist, album, title)
+                    ON CONFLICT (path) DO UPDATE
+                    SET artist=EXCLUDED.artist, album=EXCLUDED.album, title=EXCLUDED.title,
+                        last_modified=now()
+                    RETURNING path, (xmax = 0) AS inserted
+                "#,
+                paths = paths,
+                artists = artists,
+                albums = albums,
+                titles = titles
+            );
+            for row in tran.query(query.sql(), query.parameters()).await? {
+                let is_new: bool = row.try_get("inserted")?;
+                if is_new {
+                    let path: String = row.try_get("path")?;
+                    inserted.push(path.into());
+                }
+            }
+        }
+        tran.commit().await?;
+        Ok(inserted)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct MusicGroup {
+    pub name: StackString,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Schema)]
+pub struct MusicBrowseFilter {
+    pub artist: Option<StackString>,
+    pub album: Option<StackString>,
+    pub letter: Option<StackString>,
+    pub search: Option<StackString>,
+    pub offset: Option<i64>,
+}
+
+impl MusicBrowseFilter {
+    fn link(&self) -> String {
+        let mut params = Vec::new();
+        let fields = [
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("letter", &self.letter),
+            ("search", &self.search),
+        ];
+        for (key, value) in &fields {
+            if let Some(value) = value {
+                params.push((*key, value.to_string()));
+            }
+        }
+        if let Some(offset) = self.offset.filter(|o| *o > 0) {
+            params.push(("offset", offset.to_string()));
+        }
+        let base = "http://localhost/list/music_collection/browse";
+        match Url::parse_with_params(base, &params) {
+            Ok(url) => match url.query() {
+                Some(q) if !q.is_empty() => format!("{}?{}", url.path(), q),
+                _ => url.path().to_string(),
+            },
+            Err(_) => "/list/music_collection/browse".to_string(),
+        }
+    }
+
+    fn with_offset(&self, offset: i64) -> Self {
+        Self {
+            offset: Some(offset),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MusicBrowseEntries {
+    Artists(Vec<MusicGroup>),
+    Albums(Vec<MusicGroup>),
+    Tracks(Vec<MusicCollection>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicBrowse {
+    pub filter: MusicBrowseFilter,
+    pub entries: MusicBrowseEntries,
+    pub has_more: bool,
+}
+
+impl MusicBrowse {
+    pub async fn get(pool: &PgPool, filter: MusicBrowseFilter) -> Result<Self, Error> {
+        let offset = filter.offset.unwrap_or(0).max(0);
+        let letter = filter
+            .letter
+            .as_ref()
+            .and_then(|l| l.chars().next())
+            .map(|c| c.to_ascii_uppercase().to_string());
+        let search = filter.search.as_ref().map(|s| format!("%{}%", s));
+
+        let mut constraints = Vec::new();
+        let mut bindings = Vec::new();
+        if let Some(artist) = &filter.artist {
+            constraints.push("coalesce(artist, '') = $artist");
+            bindings.push(("artist", artist as Parameter));
+        }
+        if let Some(album) = &filter.album {
+            constraints.push("coalesce(album, '') = $album");
+            bindings.push(("album", album as Parameter));
+        }
+        let group_column = if filter.artist.is_none() {
+            "artist"
+        } else {
+            "album"
+        };
+        if let Some(letter) = &letter {
+            if letter == "#" {
+                constraints.push(if filter.artist.is_none() {
+                    "coalesce(artist, '') !~ '^[A-Za-z]'"
+                } else {
+                    "coalesce(album, '') !~ '^[A-Za-z]'"
+                });
+            } else {
+                constraints.push(if filter.artist.is_none() {
+                    "upper(left(coalesce(artist, ''), 1)) = $letter"
+                } else {
+                    "upper(left(coalesce(album, ''), 1)) = $letter"
+                });
+                bindings.push(("letter", letter as Parameter));
+            }
+        }
+        if let Some(search) = &search {
+            constraints
+                .push("(artist ILIKE $search OR album ILIKE $search OR title ILIKE $search)");
+            bindings.push(("search", search as Parameter));
+        }
+        let where_str = if constraints.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", constraints.join(" AND "))
+        };
+        let conn = pool.get().await?;
+
+        let (entries, has_more) = if filter.album.is_some() {
+            let query = format!(
+                "SELECT * FROM music_collection {} ORDER BY title, path LIMIT {} OFFSET {}",
+                where_str,
+                MUSIC_PAGE_SIZE + 1,
+                offset
+            );
+            let query: Query = query_dyn!(&query, ..bindings)?;
+            let mut tracks: Vec<MusicCollection> = query.fetch(&conn).await?;
+            let has_more = tracks.len() as i64 > MUSIC_PAGE_SIZE;
+            tracks.truncate(MUSIC_PAGE_SIZE as usize);
+            (MusicBrowseEntries::Tracks(tracks), has_more)
+        } else {
+            let query = format!(
+                r#"
+                    SELECT coalesce({column}, '') AS name, count(*) AS count
+                    FROM music_collection {where_str}
+                    GROUP BY 1 ORDER BY 1 LIMIT {limit} OFFSET {offset}
+                "#,
+                column = group_column,
+                where_str = where_str,
+                limit = MUSIC_PAGE_SIZE + 1,
+                offset = offset,
+            );
+            let query: Query = query_dyn!(&query, ..bindings)?;
+            let mut groups: Vec<MusicGroup> = query.fetch(&conn).await?;
+            let has_more = groups.len() as i64 > MUSIC_PAGE_SIZE;
+            groups.truncate(MUSIC_PAGE_SIZE as usize);
+            if filter.artist.is_none() {
+                (MusicBrowseEntries::Artists(groups), has_more)
+            } else {
+                (MusicBrowseEntries::Albums(groups), has_more)
+            }
+        };
+        Ok(Self {
+            filter: MusicBrowseFilter {
+                offset: Some(offset),
+                ..filter
+            },
+            entries,
+            has_more,
+        })
+    }
+
+    fn nav_link(filter: &MusicBrowseFilter, label: &str) -> String {
+        format!(
+            r#"<a href="javascript:updateMainArticle('{}')">{}</a>"#,
+            filter.link(),
+            escape_html(label)
+        )
+    }
+
+    fn alphabet_bar(&self) -> String {
+        let links: Vec<_> = ALPHABET
+            .chars()
+            .map(|c| {
+                let filter = MusicBrowseFilter {
+                    artist: self.filter.artist.clone(),
+                    letter: Some(c.to_string().into()),
+                    ..MusicBrowseFilter::default()
+                };
+                Self::nav_link(&filter, &c.to_string())
+            })
+            .collect();
+        links.join(" ")
+    }
+
+    fn breadcrumbs(&self) -> String {
+        let mut crumbs = vec![Self::nav_link(&MusicBrowseFilter::default(), "Artists")];
+        if let Some(artist) = &self.filter.artist {
+            let filter = MusicBrowseFilter {
+                artist: Some(artist.clone()),
+                ..MusicBrowseFilter::default()
+            };
+            crumbs.push(Self::nav_link(&filter, artist));
+        }
+        if let Some(album) = &self.filter.album {
+            crumbs.push(escape_html(album).to_string());
+        }
+        crumbs.join(" &gt; ")
+    }
+
+    fn pager(&self) -> String {
+        let offset = self.filter.offset.unwrap_or(0);
+        let mut links = Vec::new();
+        if offset > 0 {
+            let prev = self.filter.with_offset((offset - MUSIC_PAGE_SIZE).max(0));
+            links.push(Self::nav_link(&prev, "Previous"));
+        }
+        if self.has_more {
+            let next = self.filter.with_offset(offset + MUSIC_PAGE_SIZE);
+            links.push(Self::nav_link(&next, "Next"));
+        }
+        links.join(" ")
+    }
+
+    fn rows(&self) -> Vec<String> {
+        let group_row = |group: &MusicGroup, filter: MusicBrowseFilter| {
+            format!(
+                "<tr><td>{}</td><td>{}</td></tr>",
+                Self::nav_link(&filter, &group.name),
+                group.count
+            )
+        };
+        match &self.entries {
+            MusicBrowseEntries::Artists(groups) => groups
+                .iter()
+                .map(|g| {
+                    let filter = MusicBrowseFilter {
+                        artist: Some(g.name.clone()),
+                        ..MusicBrowseFilter::default()
+                    };
+                    group_row(g, filter)
+                })
+                .collect(),
+            MusicBrowseEntries::Albums(groups) => groups
+                .iter()
+                .map(|g| {
+                    let filter = MusicBrowseFilter {
+                        artist: self.filter.artist.clone(),
+                        album: Some(g.name.clone()),
+                        ..MusicBrowseFilter::default()
+                    };
+                    group_row(g, filter)
+                })
+                .collect(),
+            MusicBrowseEntries::Tracks(tracks) => tracks
+                .iter()
+                .map(|t| {
+                    format!(
+                        r#"<tr><td><a href="javascript:updateMainArticle('/list/music/{}')">{}</a></td><td>{}</td></tr>"#,
+                        t.id,
+                        escape_html(t.title.as_deref().unwrap_or("")),
+                        escape_html(&t.path)
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let search = self.filter.search.as_deref().unwrap_or("");
+        format!(
+            r#"
+            <h3>{breadcrumbs}</h3>
+            <p>{alphabet}</p>
+            <input type="text" id="music_search" value="{search}" placeholder="Search Music"/>
+            <input type="button" name="music_search" value="Search" onclick="music_search();"/>
+            <table border="0">{rows}</table>
+            <p>{pager}</p>
+            "#,
+            breadcrumbs = self.breadcrumbs(),
+            alphabet = self.alphabet_bar(),
+            search = escape_html(search),
+            rows = self.rows().join(""),
+            pager = self.pager(),
+        )
+        .into()
+    }
+}
+
+fn is_music_file(path: &Path, suffixes: &[StackString]) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy())
+        .map_or(false, |e| suffixes.iter().any(|s| s.as_str() == e))
+}
+
+pub async fn make_music_collection(
+    config: &Config,
+    pool: &PgPool,
+    stdout: &StdoutChannel<StackString>,
+) -> Result<usize, Error> {
+    let file_list: Result<Vec<_>, Error> = config
+        .music_dirs
+        .par_iter()
+        .filter(|d| d.exists())
+        .map(|d| walk_directory(d, &config.music_suffixes))
+        .collect();
+    let file_list: Vec<PathBuf> = file_list?
+        .into_iter()
+        .flatten()
+        .filter(|f| is_music_file(f, &config.music_suffixes))
+        .collect();
+
+    let tagged: Vec<_> = file_list
+        .par_iter()
+        .map(|f| (f.to_string_lossy().into_owned(), MusicTags::from_file(f)))
+        .collect();
+
+    for path in MusicCollection::upsert_batch(pool, &tagged).await? {
+        stdout.send(format!("not in music collection {}", path));
+    }
+    Ok(tagged.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use stack_string::StackString;
+    use std::path::Path;
+
+    use crate::music_collection::{
+        is_music_file, MusicBrowse, MusicBrowseEntries, MusicBrowseFilter, MusicCollection,
+        MusicGroup, MusicTags, MUSIC_PAGE_SIZE,
+    };
+
+    #[test]
+    fn test_music_tags_from_path() {
+        let path = Path::new("/tmp/music/Radiohead/OK Computer/Airbag.mp3");
+        let tags = MusicTags::from_file(path);
+        assert_eq!(tags.artist.as_deref(), Some("Radiohead"));
+        assert_eq!(tags.album.as_deref(), Some("OK Computer"));
+        assert_eq!(tags.title.as_deref(), Some("Airbag"));
+
+        let suffixes: Vec<StackString> = vec!["mp3".into(), "flac".into()];
+        assert!(is_music_file(path, &suffixes));
+        assert!(!is_music_file(Path::new("/tmp/music/cover.jpg"), &suffixes));
+    }
+
+    #[test]
+    fn test_music_player_html() {
+        let track = MusicCollection {
+            id: 7,
+            path: "/tmp/music/Radiohead/OK Computer/Airbag.flac".into(),
+            artist: Some("Radiohead".into()),
+            album: Some("OK Computer".into()),
+            title: Some("Airbag".into()),
+            last_modified: Utc::now().into(),
+        };
+        let html = track.get_player_html("/videos/partial/Airbag.flac");
+        assert!(html.contains("<h3>Radiohead - OK Computer - Airbag</h3>"));
+        assert!(html.contains(r#"type="audio/flac""#));
+        assert!(html.contains("artist=Radiohead&album=OK+Computer"));
+    }
+
+    #[test]
+    fn test_music_browse_html() {
+        let filter = MusicBrowseFilter {
+            artist: Some("Simon & Garfunkel".into()),
+            offset: Some(MUSIC_PAGE_SIZE),
+            ..MusicBrowseFilter::default()
+        };
+        let link = filter.link();
+        let expected = "/list/music_collection/browse?artist=Simon+%26+Garfunkel&offset=50";
+        assert_eq!(link, expected);
+
+        let browse = MusicBrowse {
+            filter,
+            entries: MusicBrowseEntries::Albums(vec![MusicGroup {
+                name: "Bookends".into(),
+                count: 12,
+            }]),
+            has_more: false,
+        };
+        let html = browse.get_html();
+        assert!(html.contains("Simon &amp; Garfunkel</a></h3>"));
+        assert!(html.contains("artist=Simon+%26+Garfunkel&album=Bookends"));
+        assert!(html.contains("letter=Q"));
+        assert!(html.contains(">Previous</a>"));
+        assert!(!html.contains(">Next</a>"));
+    }
+}
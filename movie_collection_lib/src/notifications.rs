@@ -0,0 +1,503 @@
+use anyhow::{format_err, Error};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use chrono::{Duration, Local};
+use postgres_query::{query, FromSqlRow};
+use reqwest::Client;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use stack_string::StackString;
+use std::{collections::HashSet, fmt, str::FromStr, sync::Arc};
+use tokio::task::spawn;
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+use tracing::error;
+
+use crate::{
+    config::Config,
+    movie_collection::{MovieCollection, NewEpisodesResult},
+    pgpool::PgPool,
+    tv_show_source::TvShowSource,
+    user_preferences::UserPreferences,
+};
+
+const SPARKPOST_ENDPOINT: &str = "https://api.sparkpost.com/api/v1/transmissions";
+const TELEGRAM_ENDPOINT: &str = "https://api.telegram.org";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum NotificationChannel {
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "telegram")]
+    Telegram,
+}
+
+impl fmt::Display for NotificationChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Email => "email",
+                Self::Telegram => "telegram",
+            }
+        )
+    }
+}
+
+impl FromStr for NotificationChannel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "email" => Ok(Self::Email),
+            "telegram" => Ok(Self::Telegram),
+            _ => Err(format_err!("Is not NotificationChannel")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for NotificationChannel {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for NotificationChannel {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[async_trait]
+pub trait NotificationSender: Send + Sync {
+    fn channel(&self) -> NotificationChannel;
+
+    async fn send(&self, target: &str, subject: &str, body: &str) -> Result<(), Error>;
+}
+
+pub struct SparkPostSender {
+    client: Client,
+    api_key: StackString,
+    from: StackString,
+}
+
+#[async_trait]
+impl NotificationSender for SparkPostSender {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Email
+    }
+
+    async fn send(&self, target: &str, subject: &str, body: &str) -> Result<(), Error> {
+        let data = json!({
+            "content": {"from": self.from, "subject": subject, "text": body},
+            "recipients": [{"address": target}],
+        });
+        self.client
+            .post(SPARKPOST_ENDPOINT)
+            .header("Authorization", self.api_key.as_str())
+            .json(&data)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+pub struct TelegramSender {
+    client: Client,
+    token: StackString,
+}
+
+#[async_trait]
+impl NotificationSender for TelegramSender {
+    fn channel(&self) -> NotificationChannel {
+        NotificationChannel::Telegram
+    }
+
+    async fn send(&self, target: &str, subject: &str, body: &str) -> Result<(), Error> {
+        let url = format!("{}/bot{}/sendMessage", TELEGRAM_ENDPOINT, self.token);
+        let data = json!({"chat_id": target, "text": format!("{}\n{}", subject, body)});
+        self.client
+            .post(url.as_str())
+            .json(&data)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct NotificationPreference {
+    pub id: i32,
+    pub email: StackString,
+    pub channel: NotificationChannel,
+    pub target: StackString,
+    pub new_episodes: bool,
+    pub transcode_finished: bool,
+}
+
+impl NotificationPreference {
+    pub async fn get_preferences(pool: &PgPool, email: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, email, channel, target, new_episodes, transcode_finished
+                FROM notification_preferences
+                WHERE email = $email
+                ORDER BY id
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, email, channel, target, new_episodes, transcode_finished
+                FROM notification_preferences
+                ORDER BY id
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn upsert(
+        pool: &PgPool,
+        email: &str,
+        channel: NotificationChannel,
+        target: &str,
+        new_episodes: bool,
+        transcode_finished: bool,
+    ) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO notification_preferences
+                    (email, channel, target, new_episodes, transcode_finished, last_modified)
+                VALUES ($email, $channel, $target, $new_episodes, $transcode_finished, now())
+                ON CONFLICT (email, channel) DO UPDATE
+                SET target=$target,
+                    new_episodes=$new_episodes,
+                    transcode_finished=$transcode_finished,
+                    last_modified=now()
+                RETURNING id, email, channel, target, new_episodes, transcode_finished
+            "#,
+            email = email,
+            channel = channel,
+            target = target,
+            new_episodes = new_episodes,
+            transcode_finished = transcode_finished
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(pool: &PgPool, email: &str, id: i32) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM notification_preferences WHERE id = $id AND email = $email",
+            id = id,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    async fn was_sent(&self, pool: &PgPool, event_key: &str) -> Result<bool, Error> {
+        let query = query!(
+            r#"
+                SELECT 1 FROM notification_log
+                WHERE preference_id = $id AND event_key = $event_key
+            "#,
+            id = self.id,
+            event_key = event_key
+        );
+        let conn = pool.get().await?;
+        let row: Option<(i32,)> = query.fetch_opt(&conn).await?;
+        Ok(row.is_some())
+    }
+
+    // Only called once delivery succeeded, so a failed send is retried on the next run
+    async fn mark_sent(&self, pool: &PgPool, event_key: &str) -> Result<bool, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO notification_log (preference_id, event_key, sent_at)
+                VALUES ($id, $event_key, now())
+                ON CONFLICT DO NOTHING
+            "#,
+            id = self.id,
+            event_key = event_key
+        );
+        let conn = pool.get().await?;
+        let inserted = query.execute(&conn).await?;
+        Ok(inserted > 0)
+    }
+}
+
+fn episode_key(epi: &NewEpisodesResult) -> StackString {
+    format!("episode_{}_{}_{}", epi.link, epi.season, epi.episode).into()
+}
+
+fn episode_line(epi: &NewEpisodesResult) -> StackString {
+    format!(
+        "{} s{:02} ep{:02} {} ({})",
+        epi.title, epi.season, epi.episode, epi.eptitle, epi.airdate
+    )
+    .into()
+}
+
+#[derive(Clone, Default)]
+pub struct Notifier(Arc<Vec<Arc<dyn NotificationSender>>>);
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|s| s.channel()))
+            .finish()
+    }
+}
+
+impl Notifier {
+    pub fn new(config: &Config) -> Self {
+        let client = Client::new();
+        let mut notifier = Self::default();
+        if let (Some(api_key), Some(from)) =
+            (&config.sparkpost_api_key, &config.sending_email_address)
+        {
+            notifier.register(Arc::new(SparkPostSender {
+                client: client.clone(),
+                api_key: api_key.clone(),
+                from: from.clone(),
+            }));
+        }
+        if let Some(token) = &config.telegram_bot_token {
+            notifier.register(Arc::new(TelegramSender {
+                client,
+                token: token.clone(),
+            }));
+        }
+        notifier
+    }
+
+    pub fn register(&mut self, sender: Arc<dyn NotificationSender>) {
+        Arc::make_mut(&mut self.0).push(sender);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn has_channel(&self, channel: NotificationChannel) -> bool {
+        self.sender(channel).is_some()
+    }
+
+    fn sender(&self, channel: NotificationChannel) -> Option<&Arc<dyn NotificationSender>> {
+        self.0.iter().find(|s| s.channel() == channel)
+    }
+
+    async fn deliver(
+        &self,
+        pref: &NotificationPreference,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), Error> {
+        let sender = self
+            .sender(pref.channel)
+            .ok_or_else(|| format_err!("Channel {} not configured", pref.channel))?;
+        sender.send(&pref.target, subject, body).await
+    }
+
+    // Looks for episodes of watchlisted shows that aired since yesterday and sends each
+    // subscriber a single message covering the ones they haven't been told about yet
+    pub async fn notify_new_episodes(&self, mc: &MovieCollection) -> Result<usize, Error> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let today = Local::today().naive_local();
+        let yesterday = today - Duration::days(1);
+
+        let query = query!("SELECT link FROM trakt_watchlist");
+        let conn = mc.pool.get().await?;
+        let watchlist: Vec<(StackString,)> = query.fetch(&conn).await?;
+        let watchlist: HashSet<StackString> = watchlist.into_iter().map(|(l,)| l).collect();
+
+        let episodes: Vec<_> = mc
+            .get_new_episodes(yesterday, today, Some(TvShowSource::All))
+            .await?
+            .into_iter()
+            .filter(|epi| watchlist.contains(&epi.link))
+            .collect();
+        if episodes.is_empty() {
+            return Ok(0);
+        }
+
+        let mut sent = 0;
+        for pref in NotificationPreference::get_all(&mc.pool).await? {
+            if !pref.new_episodes || !self.has_channel(pref.channel) {
+                continue;
+            }
+            let mut unsent = Vec::new();
+            for epi in &episodes {
+                if !pref.was_sent(&mc.pool, &episode_key(epi)).await? {
+                    unsent.push(epi);
+                }
+            }
+            if unsent.is_empty() {
+                continue;
+            }
+            let lines: Vec<_> = unsent.iter().copied().map(episode_line).collect();
+            match self.deliver(&pref, "New episodes", &lines.join("\n")).await {
+                Ok(_) => {
+                    for epi in unsent {
+                        pref.mark_sent(&mc.pool, &episode_key(epi)).await?;
+                    }
+                    sent += 1;
+                }
+                Err(e) => error!("notification {} to {} failed {:?}", pref.id, pref.email, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    pub async fn notify_transcode_finished(
+        &self,
+        pool: &PgPool,
+        prefix: &str,
+    ) -> Result<usize, Error> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let body = format!("Transcode of {} finished", prefix);
+        let mut sent = 0;
+        for pref in NotificationPreference::get_all(pool).await? {
+            if !pref.transcode_finished || !self.has_channel(pref.channel) {
+                continue;
+            }
+            match self.deliver(&pref, "Transcode finished", &body).await {
+                Ok(_) => sent += 1,
+                Err(e) => error!("notification {} to {} failed {:?}", pref.id, pref.email, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    // Sends an operational alert to every admin's configured channels, event_key keeps
+    // repeated evaluations of the same condition from sending it again
+    pub async fn notify_admins(
+        &self,
+        pool: &PgPool,
+        config: &Config,
+        event_key: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<usize, Error> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let mut sent = 0;
+        for pref in NotificationPreference::get_all(pool).await? {
+            if !UserPreferences::is_admin(config, &pref.email) || !self.has_channel(pref.channel) {
+                continue;
+            }
+            if pref.was_sent(pool, event_key).await? {
+                continue;
+            }
+            match self.deliver(&pref, subject, body).await {
+                Ok(_) => {
+                    pref.mark_sent(pool, event_key).await?;
+                    sent += 1;
+                }
+                Err(e) => error!("notification {} to {} failed {:?}", pref.id, pref.email, e),
+            }
+        }
+        Ok(sent)
+    }
+
+    pub fn fire_transcode_finished(&self, pool: &PgPool, prefix: &str) {
+        if self.is_empty() {
+            return;
+        }
+        let notifier = self.clone();
+        let pool = pool.clone();
+        let prefix: StackString = prefix.into();
+        spawn(async move {
+            if let Err(e) = notifier.notify_transcode_finished(&pool, &prefix).await {
+                error!("transcode notification failed {:?}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::NaiveDate;
+
+    use crate::{
+        movie_collection::NewEpisodesResult,
+        notifications::{episode_key, episode_line, NotificationChannel, Notifier},
+    };
+
+    #[test]
+    fn test_notification_channel() -> Result<(), Error> {
+        let channel: NotificationChannel = "telegram".parse()?;
+        assert_eq!(channel, NotificationChannel::Telegram);
+        assert_eq!(channel.to_string().as_str(), "telegram");
+        assert!("sms".parse::<NotificationChannel>().is_err());
+        assert!(!Notifier::default().has_channel(channel));
+        Ok(())
+    }
+
+    #[test]
+    fn test_episode_line() {
+        let epi = NewEpisodesResult {
+            show: "mr_robot".into(),
+            link: "tt4158110".into(),
+            title: "Mr. Robot".into(),
+            season: 2,
+            episode: 3,
+            epurl: "tt4730002".into(),
+            airdate: NaiveDate::from_ymd(2016, 7, 20),
+            rating: 8.5,
+            eprating: 8.1,
+            eptitle: "eps2.1_k3rnel-pan1c.ksd".into(),
+        };
+        assert_eq!(episode_key(&epi).as_str(), "episode_tt4158110_2_3");
+        assert_eq!(
+            episode_line(&epi).as_str(),
+            "Mr. Robot s02 ep03 eps2.1_k3rnel-pan1c.ksd (2016-07-20)"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{format_err, Error};
+use chrono::Duration;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+use crate::{
+    clock::{Clock, IdGen},
+    config::Config,
+    datetime_wrapper::DateTimeWrapper,
+    pgpool::PgPool,
+    transcode_service::TranscodeServiceRequest,
+};
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone)]
+pub struct OfflineFile {
+    pub token: StackString,
+    pub email: StackString,
+    pub collection_idx: i32,
+    pub path: StackString,
+    pub created_at: DateTimeWrapper,
+    pub expires_at: DateTimeWrapper,
+}
+
+// Kept outside of the statically served videos directory, downloads go through
+// /list/offline/{token}/{file} so expiry is enforced
+pub fn offline_dir(config: &Config) -> Result<PathBuf, Error> {
+    config
+        .video_playback_path
+        .as_ref()
+        .map(|p| p.join("offline"))
+        .ok_or_else(|| format_err!("video playback path does not exist"))
+}
+
+impl OfflineFile {
+    pub fn new(
+        config: &Config,
+        email: &str,
+        collection_idx: i32,
+        input_path: &Path,
+        clock: &dyn Clock,
+        id_gen: &dyn IdGen,
+    ) -> Result<Self, Error> {
+        let token: StackString = id_gen.new_id().to_simple().to_string().into();
+        let file_stem = input_path
+            .file_stem()
+            .ok_or_else(|| format_err!("No file stem"))?
+            .to_string_lossy();
+        let path = offline_dir(config)?
+            .join(token.as_str())
+            .join(format!("{}.mp4", file_stem));
+        let created_at = clock.now();
+        let expires_at = created_at + Duration::days(config.offline_expiry_days);
+        Ok(Self {
+            token,
+            email: email.into(),
+            collection_idx,
+            path: path.to_string_lossy().into_owned().into(),
+            created_at: created_at.into(),
+            expires_at: expires_at.into(),
+        })
+    }
+
+    pub fn transcode_request(&self, input_path: &Path) -> TranscodeServiceRequest {
+        TranscodeServiceRequest::create_offline_request(
+            &self.token,
+            input_path,
+            Path::new(self.path.as_str()),
+        )
+    }
+
+    pub fn file_name(&self) -> StackString {
+        Path::new(self.path.as_str())
+            .file_name()
+            .map_or_else(|| "".into(), |f| f.to_string_lossy().into_owned().into())
+    }
+
+    pub fn download_url(&self) -> StackString {
+        format!("/list/offline/{}/{}", self.token, self.file_name()).into()
+    }
+
+    pub async fn file_size(&self) -> Option<u64> {
+        fs::metadata(self.path.as_str()).await.ok().map(|m| m.len())
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match Path::new(self.path.as_str())
+            .extension()
+            .and_then(OsStr::to_str)
+        {
+            Some("mp4") => "video/mp4",
+            Some("m4v") => "video/x-m4v",
+            Some("mkv") => "video/x-matroska",
+            Some("webm") => "video/webm",
+            _ => "application/octet-stream",
+        }
+    }
+
+    // Rows are only written by offline_save, but the stored path is still checked
+    // against the offline directory and the size limit before anything is served
+    pub async fn download_path(&self, config: &Config) -> Result<(PathBuf, u64), Error> {
+        let root = fs::canonicalize(offline_dir(config)?).await?;
+        let path = fs::canonicalize(self.path.as_str()).await?;
+        if !path.starts_with(&root) {
+            return Err(format_err!(
+                "{} is outside of {}",
+                path.to_string_lossy(),
+                root.to_string_lossy()
+            ));
+        }
+        let size = fs::metadata(&path).await?.len();
+        if size > config.offline_max_size_mb * 1_000_000 {
+            return Err(format_err!(
+                "{} is larger than {} MB",
+                path.to_string_lossy(),
+                config.offline_max_size_mb
+            ));
+        }
+        Ok((path, size))
+    }
+
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool {
+        *self.expires_at < clock.now()
+    }
+
+    pub async fn insert(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO offline_files
+                    (token, email, collection_idx, path, created_at, expires_at)
+                VALUES ($token, $email, $collection_idx, $path, $created_at, $expires_at)
+            "#,
+            token = self.token,
+            email = self.email,
+            collection_idx = self.collection_idx,
+            path = self.path,
+            created_at = self.created_at,
+            expires_at = self.expires_at
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT token, email, collection_idx, path, created_at, expires_at
+                FROM offline_files
+                ORDER BY created_at DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_token(pool: &PgPool, token: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT token, email, collection_idx, path, created_at, expires_at
+                FROM offline_files
+                WHERE token = $token
+            "#,
+            token = token
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_email(pool: &PgPool, email: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT token, email, collection_idx, path, created_at, expires_at
+                FROM offline_files
+                WHERE email = $email
+                ORDER BY created_at DESC
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            "DELETE FROM offline_files WHERE token = $token",
+            token = self.token
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        if let Some(token_dir) = Path::new(self.path.as_str()).parent() {
+            if token_dir.file_name() == Some(OsStr::new(self.token.as_str())) && token_dir.exists()
+            {
+                fs::remove_dir_all(token_dir).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn cleanup_expired(pool: &PgPool, clock: &dyn Clock) -> Result<u64, Error> {
+        let mut removed = 0;
+        for entry in Self::get_all(pool).await? {
+            if entry.is_expired(clock) {
+                entry.delete(pool).await?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
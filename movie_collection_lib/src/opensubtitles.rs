@@ -0,0 +1,216 @@
+use anyhow::{format_err, Error};
+use reqwest::{header::HeaderMap, Client, Url};
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use crate::config::Config;
+
+const OPENSUBTITLES_ENDPOINT: &str = "https://api.opensubtitles.com/api/v1/";
+const HASH_CHUNK_SIZE: u64 = 65536;
+
+pub async fn opensubtitles_hash(path: &Path) -> Result<StackString, Error> {
+    let mut f = File::open(path).await?;
+    let size = f.metadata().await?.len();
+    if size < HASH_CHUNK_SIZE {
+        return Err(format_err!(
+            "{} is too small to hash",
+            path.to_string_lossy()
+        ));
+    }
+    let mut hash = size;
+    let mut buf = vec![0_u8; HASH_CHUNK_SIZE as usize];
+    for offset in &[0, size - HASH_CHUNK_SIZE] {
+        f.seek(SeekFrom::Start(*offset)).await?;
+        f.read_exact(&mut buf).await?;
+        hash = buf.chunks_exact(8).fold(hash, |h, chunk| {
+            let mut word = [0_u8; 8];
+            word.copy_from_slice(chunk);
+            h.wrapping_add(u64::from_le_bytes(word))
+        });
+    }
+    Ok(format!("{:016x}", hash).into())
+}
+
+#[derive(Deserialize, Debug)]
+struct SubtitleFile {
+    file_id: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubtitleAttributes {
+    language: Option<StackString>,
+    download_count: Option<u64>,
+    #[serde(default)]
+    moviehash_match: bool,
+    #[serde(default)]
+    files: Vec<SubtitleFile>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SubtitleResult {
+    attributes: SubtitleAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    data: Vec<SubtitleResult>,
+}
+
+#[derive(Serialize, Debug)]
+struct DownloadRequest {
+    file_id: u64,
+    sub_format: &'static str,
+}
+
+#[derive(Deserialize, Debug)]
+struct DownloadResponse {
+    link: StackString,
+}
+
+fn best_file_id(results: &[SubtitleResult]) -> Option<u64> {
+    results
+        .iter()
+        .filter(|r| !r.attributes.files.is_empty())
+        .max_by_key(|r| {
+            (
+                r.attributes.moviehash_match,
+                r.attributes.download_count.unwrap_or(0),
+            )
+        })
+        .map(|r| r.attributes.files[0].file_id)
+}
+
+pub fn subtitle_path(path: &Path) -> PathBuf {
+    path.with_extension("srt")
+}
+
+pub struct OpenSubtitles {
+    client: Client,
+    language: StackString,
+}
+
+impl OpenSubtitles {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let api_key = config
+            .opensubtitles_api_key
+            .as_ref()
+            .ok_or_else(|| format_err!("No OpenSubtitles api key"))?;
+        let mut headers = HeaderMap::new();
+        headers.insert("Api-Key", api_key.parse()?);
+        let client = Client::builder()
+            .user_agent("movie_collection_rust")
+            .default_headers(headers)
+            .build()?;
+        Ok(Self {
+            client,
+            language: config.opensubtitles_language.clone(),
+        })
+    }
+
+    async fn search(&self, moviehash: &str) -> Result<Vec<SubtitleResult>, Error> {
+        let url = Url::parse(OPENSUBTITLES_ENDPOINT)?.join("subtitles")?;
+        let url = Url::parse_with_params(
+            url.as_str(),
+            &[
+                ("moviehash", moviehash),
+                ("languages", self.language.as_str()),
+            ],
+        )?;
+        let response: SearchResponse = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response
+            .data
+            .into_iter()
+            .filter(|r| {
+                r.attributes.language.as_ref().map(StackString::as_str)
+                    == Some(self.language.as_str())
+            })
+            .collect())
+    }
+
+    pub async fn download_subtitle(&self, path: &Path) -> Result<PathBuf, Error> {
+        let moviehash = opensubtitles_hash(path).await?;
+        let results = self.search(&moviehash).await?;
+        let file_id = best_file_id(&results)
+            .ok_or_else(|| format_err!("No subtitles found for {}", path.to_string_lossy()))?;
+        let url = Url::parse(OPENSUBTITLES_ENDPOINT)?.join("download")?;
+        let response: DownloadResponse = self
+            .client
+            .post(url)
+            .json(&DownloadRequest {
+                file_id,
+                sub_format: "srt",
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let body = self
+            .client
+            .get(response.link.as_str())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let output_path = subtitle_path(path);
+        fs::write(&output_path, &body).await?;
+        Ok(output_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    use crate::opensubtitles::{best_file_id, opensubtitles_hash, subtitle_path, SearchResponse};
+
+    #[tokio::test]
+    async fn test_opensubtitles_hash() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let path = tmp.path().join("opensubtitles_hash_test.mp4");
+        std::fs::write(&path, vec![0_u8; 131_072])?;
+        assert_eq!(
+            opensubtitles_hash(&path).await?.as_str(),
+            "0000000000020000"
+        );
+        std::fs::write(&path, b"short")?;
+        assert!(opensubtitles_hash(&path).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_file_id() -> Result<(), Error> {
+        let body = r#"{"data": [
+            {"attributes": {"language": "en", "download_count": 900, "moviehash_match": false,
+                            "files": [{"file_id": 1}]}},
+            {"attributes": {"language": "en", "download_count": 10, "moviehash_match": true,
+                            "files": [{"file_id": 2}]}},
+            {"attributes": {"language": "en", "download_count": 5000, "files": []}}
+        ]}"#;
+        let response: SearchResponse = serde_json::from_str(body)?;
+        assert_eq!(best_file_id(&response.data), Some(2));
+        assert_eq!(
+            subtitle_path(Path::new("/tmp/mr_robot_s01_ep01.mp4")),
+            Path::new("/tmp/mr_robot_s01_ep01.srt")
+        );
+        Ok(())
+    }
+}
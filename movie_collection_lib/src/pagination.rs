@@ -0,0 +1,121 @@
+use itertools::Itertools;
+use stack_string::StackString;
+use std::cmp::{max, min};
+
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pagination {
+    pub offset: usize,
+    pub limit: usize,
+    pub total: usize,
+}
+
+impl Pagination {
+    pub fn new(offset: Option<usize>, limit: Option<usize>, total: usize) -> Self {
+        let limit = limit.filter(|l| *l > 0).unwrap_or(DEFAULT_PAGE_SIZE);
+        let last_page = total.saturating_sub(1) / limit;
+        let offset = min(offset.unwrap_or(0) / limit, last_page) * limit;
+        Self {
+            offset,
+            limit,
+            total,
+        }
+    }
+
+    pub fn page(&self) -> usize {
+        self.offset / self.limit
+    }
+
+    pub fn pages(&self) -> usize {
+        max(1, (self.total + self.limit - 1) / self.limit)
+    }
+
+    pub fn slice<'a, T>(&self, items: &'a [T]) -> &'a [T] {
+        let end = min(self.offset + self.limit, items.len());
+        &items[min(self.offset, end)..end]
+    }
+
+    fn page_url(&self, base_url: &str, page: usize) -> String {
+        let sep = if base_url.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}offset={}&limit={}",
+            base_url,
+            sep,
+            page * self.limit,
+            self.limit
+        )
+    }
+
+    pub fn get_html(&self, base_url: &str) -> StackString {
+        if self.total == 0 {
+            return "Showing 0 of 0<br>".into();
+        }
+        let page = self.page();
+        let link = |page: usize, label: &str| {
+            format!(
+                r#"<a href="javascript:updateMainArticle('{}')">{}</a>"#,
+                self.page_url(base_url, page),
+                label
+            )
+        };
+        let previous = if page > 0 {
+            link(page - 1, "Previous")
+        } else {
+            String::new()
+        };
+        let next = if page + 1 < self.pages() {
+            link(page + 1, "Next")
+        } else {
+            String::new()
+        };
+        let options = (0..self.pages())
+            .map(|p| {
+                format!(
+                    r#"<option value="{}" {}>{}</option>"#,
+                    self.page_url(base_url, p),
+                    if p == page { "selected" } else { "" },
+                    p + 1
+                )
+            })
+            .join("");
+        format!(
+            r#"Showing {}&ndash;{} of {} {} page <select onchange="updateMainArticle(this.value);">{}</select> of {} {}<br>"#,
+            self.offset + 1,
+            min(self.offset + self.limit, self.total),
+            self.total,
+            previous,
+            options,
+            self.pages(),
+            next
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pagination::{Pagination, DEFAULT_PAGE_SIZE};
+
+    #[test]
+    fn test_pagination() {
+        let items: Vec<_> = (0..25).collect();
+        let page = Pagination::new(Some(12), Some(10), items.len());
+        assert_eq!(page.offset, 10);
+        assert_eq!(page.pages(), 3);
+        assert_eq!(page.slice(&items), &items[10..20]);
+
+        let page = Pagination::new(Some(100), Some(10), items.len());
+        assert_eq!(page.offset, 20);
+        assert_eq!(page.slice(&items), &items[20..]);
+
+        let page = Pagination::new(None, None, 0);
+        assert_eq!(page.limit, DEFAULT_PAGE_SIZE);
+        assert!(page.slice(&items[..0]).is_empty());
+
+        let html = Pagination::new(Some(10), Some(10), 25).get_html("/list/tvshows?unwatched=true");
+        assert!(html.contains("Showing 11&ndash;20 of 25"));
+        assert!(html.contains("/list/tvshows?unwatched=true&offset=0&limit=10')\">Previous"));
+        assert!(html.contains("/list/tvshows?unwatched=true&offset=20&limit=10')\">Next"));
+    }
+}
@@ -6,9 +6,14 @@ use stdout_channel::StdoutChannel;
 use structopt::StructOpt;
 
 use crate::{
-    config::Config, imdb_episodes::ImdbEpisodes, imdb_ratings::ImdbRatings,
-    imdb_utils::ImdbConnection, movie_collection::MovieCollection, pgpool::PgPool,
+    config::{Config, MetadataProvider},
+    imdb_episodes::ImdbEpisodes,
+    imdb_ratings::ImdbRatings,
+    imdb_utils::{ImdbConnection, ImdbEpisodeResult, ImdbTuple},
+    movie_collection::MovieCollection,
+    pgpool::PgPool,
     trakt_utils::WatchListMap,
+    tvmaze_utils::TvMazeConnection,
 };
 
 #[derive(StructOpt, Default, Debug)]
@@ -42,6 +47,38 @@ pub struct ParseImdbOptions {
     pub show: StackString,
 }
 
+enum MetadataConnection {
+    Imdb(ImdbConnection),
+    TvMaze(TvMazeConnection),
+}
+
+impl MetadataConnection {
+    fn new(provider: MetadataProvider) -> Self {
+        match provider {
+            MetadataProvider::Imdb => Self::Imdb(ImdbConnection::new()),
+            MetadataProvider::TvMaze => Self::TvMaze(TvMazeConnection::new()),
+        }
+    }
+
+    async fn parse_imdb(&self, title: &str) -> Result<Vec<ImdbTuple>, Error> {
+        match self {
+            Self::Imdb(conn) => conn.parse_imdb(title).await,
+            Self::TvMaze(conn) => conn.parse_imdb(title).await,
+        }
+    }
+
+    async fn parse_imdb_episode_list(
+        &self,
+        imdb_id: &str,
+        season: Option<i32>,
+    ) -> Result<Vec<ImdbEpisodeResult>, Error> {
+        match self {
+            Self::Imdb(conn) => conn.parse_imdb_episode_list(imdb_id, season).await,
+            Self::TvMaze(conn) => conn.parse_imdb_episode_list(imdb_id, season).await,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ParseImdb {
     pub mc: MovieCollection,
@@ -138,7 +175,13 @@ impl ParseImdb {
         episodes: &Option<HashMap<(i32, i32), ImdbEpisodes>>,
         output: &mut Vec<Vec<StackString>>,
     ) -> Result<(), Error> {
-        let imdb_conn = ImdbConnection::new();
+        // tvmaze only lists tv shows, movies always come from imdb
+        let provider = if opts.tv {
+            self.mc.config.metadata_provider
+        } else {
+            MetadataProvider::Imdb
+        };
+        let imdb_conn = MetadataConnection::new(provider);
         let results = imdb_conn.parse_imdb(&opts.show.replace("_", " ")).await?;
         let results = if let Some(ilink) = &opts.imdb_link {
             results
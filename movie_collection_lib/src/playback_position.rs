@@ -0,0 +1,62 @@
+use anyhow::{format_err, Error};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct PlaybackPosition {
+    pub email: StackString,
+    pub collection_idx: i32,
+    pub position: f64,
+    pub last_modified: DateTimeWrapper,
+}
+
+impl PlaybackPosition {
+    pub async fn get(
+        pool: &PgPool,
+        email: &str,
+        collection_idx: i32,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT email, collection_idx, position, last_modified
+                FROM playback_position
+                WHERE email = $email AND collection_idx = $collection_idx
+            "#,
+            email = email,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn set(
+        pool: &PgPool,
+        email: &str,
+        collection_idx: i32,
+        position: f64,
+    ) -> Result<Option<Self>, Error> {
+        if !position.is_finite() || position < 0.0 {
+            return Err(format_err!("Invalid position {}", position));
+        }
+        let query = query!(
+            r#"
+                INSERT INTO playback_position (email, collection_idx, position, last_modified)
+                SELECT $email, idx, $position, now()
+                FROM movie_collection
+                WHERE idx = $collection_idx
+                ON CONFLICT (email, collection_idx)
+                DO UPDATE SET position = $position, last_modified = now()
+                RETURNING email, collection_idx, position, last_modified
+            "#,
+            email = email,
+            collection_idx = collection_idx,
+            position = position
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+}
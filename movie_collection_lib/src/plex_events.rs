@@ -1,22 +1,39 @@
-use anyhow::{format_err, Error};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use anyhow::Error;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
 use postgres_query::{query, query_dyn, FromSqlRow, Parameter, Query};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use stack_string::StackString;
-use std::{
-    convert::{TryFrom, TryInto},
-    net::Ipv4Addr,
-    str::FromStr,
+use std::{convert::TryFrom, net::Ipv4Addr, str::FromStr};
+use thiserror::Error as ThisError;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::Config,
+    datetime_wrapper::DateTimeWrapper,
+    naivedate_wrapper::NaiveDateWrapper,
+    pgpool::PgPool,
+    sync_cursor::SyncCursor,
 };
 
-use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+/// Returned when parsing webhook payloads and event types, which is where bad input from
+/// plex or API clients shows up, storage and reporting functions return `anyhow::Error`
+#[derive(ThisError, Debug)]
+pub enum PlexEventError {
+    #[error("Invalid plex event type: {0}")]
+    InvalidEventType(StackString),
+    #[error("Invalid plex webhook payload: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
 
 #[derive(FromSqlRow, Default, Debug, Serialize, Deserialize, Schema)]
 pub struct PlexEvent {
     pub event: StackString,
     pub account: StackString,
     pub server: StackString,
+    pub server_id: Option<StackString>,
     pub player_title: StackString,
     pub player_address: StackString,
     pub title: Option<StackString>,
@@ -29,18 +46,26 @@ pub struct PlexEvent {
 }
 
 impl TryFrom<WebhookPayload> for PlexEvent {
-    type Error = Error;
+    type Error = PlexEventError;
     fn try_from(item: WebhookPayload) -> Result<Self, Self::Error> {
+        Self::from_webhook(item, &SystemClock)
+    }
+}
+
+impl PlexEvent {
+    pub fn from_webhook(item: WebhookPayload, clock: &dyn Clock) -> Result<Self, PlexEventError> {
         fn dt_from_tm(x: u64) -> DateTimeWrapper {
             let dt = NaiveDateTime::from_timestamp(x as i64, 0);
             let dt = DateTime::from_utc(dt, Utc);
             dt.into()
         }
+        let now: DateTimeWrapper = clock.now().into();
         let event = item.event.to_str().into();
         let payload = Self {
             event,
             account: item.account.title,
             server: item.server.title,
+            server_id: Some(item.server.uuid),
             player_title: item.player.title,
             player_address: item.player.public_address.to_string().into(),
             title: item.metadata.title,
@@ -48,17 +73,22 @@ impl TryFrom<WebhookPayload> for PlexEvent {
             grandparent_title: item.metadata.grandparent_title,
             added_at: item.metadata.added_at.map(dt_from_tm),
             updated_at: item.metadata.updated_at.map(dt_from_tm),
-            created_at: Some(Utc::now().into()),
-            last_modified: Some(Utc::now().into()),
+            created_at: Some(now),
+            last_modified: Some(now),
         };
         Ok(payload)
     }
-}
 
-impl PlexEvent {
-    pub fn get_from_payload(buf: &[u8]) -> Result<Self, Error> {
+    pub fn get_from_payload(buf: &[u8]) -> Result<Self, PlexEventError> {
+        Self::get_from_payload_with_clock(buf, &SystemClock)
+    }
+
+    pub fn get_from_payload_with_clock(
+        buf: &[u8],
+        clock: &dyn Clock,
+    ) -> Result<Self, PlexEventError> {
         let object: WebhookPayload = serde_json::from_slice(buf)?;
-        object.try_into()
+        Self::from_webhook(object, clock)
     }
 
     pub async fn get_events(
@@ -67,9 +97,15 @@ impl PlexEvent {
         event_type: Option<PlexEventType>,
         offset: Option<u64>,
         limit: Option<u64>,
+        hidden_accounts: &[StackString],
     ) -> Result<Vec<Self>, Error> {
         let mut constraints = Vec::new();
         let mut bindings = Vec::new();
+        let hidden_accounts = hidden_accounts.to_vec();
+        if !hidden_accounts.is_empty() {
+            constraints.push("account != ALL($hidden_accounts)");
+            bindings.push(("hidden_accounts", &hidden_accounts as Parameter));
+        }
         if let Some(start_timestamp) = &start_timestamp {
             constraints.push("created_at > $start_timestamp");
             bindings.push(("start_timestamp", start_timestamp as Parameter));
@@ -105,17 +141,66 @@ impl PlexEvent {
         query.fetch(&conn).await.map_err(Into::into)
     }
 
+    pub async fn get_events_page(
+        pool: &PgPool,
+        cursor: SyncCursor,
+        event_type: Option<PlexEventType>,
+        limit: usize,
+        hidden_accounts: &[StackString],
+    ) -> Result<(Vec<Self>, Option<SyncCursor>), Error> {
+        let limit_param = limit as i64;
+        let mut constraints = vec!["(last_modified, id) > ($last_modified, $id)"];
+        let mut bindings = vec![
+            ("last_modified", &cursor.last_modified as Parameter),
+            ("id", &cursor.id as Parameter),
+            ("limit", &limit_param as Parameter),
+        ];
+        let hidden_accounts = hidden_accounts.to_vec();
+        if !hidden_accounts.is_empty() {
+            constraints.push("account != ALL($hidden_accounts)");
+            bindings.push(("hidden_accounts", &hidden_accounts as Parameter));
+        }
+        let event_type = event_type.map(|s| s.to_str().to_string());
+        if let Some(event_type) = &event_type {
+            constraints.push("event = $event");
+            bindings.push(("event", event_type as Parameter));
+        }
+        let query = format!(
+            "
+                SELECT * FROM plex_event
+                WHERE {}
+                ORDER BY last_modified, id
+                LIMIT $limit
+            ",
+            constraints.join(" AND ")
+        );
+        let query: Query = query_dyn!(&query, ..bindings)?;
+        let conn = pool.get().await?;
+        let rows = query.query(&conn).await?;
+        let next_cursor = match rows.last() {
+            Some(row) if rows.len() >= limit => Some(SyncCursor {
+                last_modified: row.try_get("last_modified")?,
+                id: row.try_get("id")?,
+            }),
+            _ => None,
+        };
+        let events: Result<Vec<_>, _> = rows.iter().map(Self::from_row).collect();
+        Ok((events?, next_cursor))
+    }
+
     pub async fn write_event(&self, pool: &PgPool) -> Result<(), Error> {
         let query = query!(
             "
-            INSERT INTO plex_event (event, account, server, player_title, player_address, title,
-                parent_title, grandparent_title, added_at, updated_at, created_at, last_modified)
-            VALUES ($event, $account, $server, $player_title, $player_address, $title,
-                $parent_title, $grandparent_title, $added_at, $updated_at, $created_at, \
-             $last_modified)",
+            INSERT INTO plex_event (event, account, server, server_id, player_title, player_address,
+                title, parent_title, grandparent_title, added_at, updated_at, created_at,
+                last_modified)
+            VALUES ($event, $account, $server, $server_id, $player_title, $player_address,
+                $title, $parent_title, $grandparent_title, $added_at, $updated_at, $created_at,
+                $last_modified)",
             event = self.event,
             account = self.account,
             server = self.server,
+            server_id = self.server_id,
             player_title = self.player_title,
             player_address = self.player_address,
             title = self.title,
@@ -132,6 +217,90 @@ impl PlexEvent {
     }
 }
 
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema)]
+pub struct PlexEventDailyCount {
+    pub day: NaiveDateWrapper,
+    pub account: StackString,
+    pub event: StackString,
+    pub count: i64,
+}
+
+impl PlexEventDailyCount {
+    // Scrobbles are the only record of what each account has watched (daily counts
+    // carry no titles), so they are kept when everything else is rolled up
+    pub async fn archive_events(
+        pool: &PgPool,
+        before: DateTime<Utc>,
+        clock: &dyn Clock,
+    ) -> Result<u64, Error> {
+        let now: DateTimeWrapper = clock.now().into();
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            r#"
+                INSERT INTO plex_event_daily (day, account, event, count, last_modified)
+                SELECT date(created_at), account, event, count(*), $now
+                FROM plex_event
+                WHERE created_at < $before AND event != 'media.scrobble'
+                GROUP BY 1, 2, 3
+                ON CONFLICT (day, account, event) DO UPDATE
+                SET count = plex_event_daily.count + EXCLUDED.count, last_modified=$now
+            "#,
+            before = before,
+            now = now
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            "DELETE FROM plex_event WHERE created_at < $before AND event != 'media.scrobble'",
+            before = before
+        );
+        let archived = tran.execute(query.sql(), query.parameters()).await?;
+        tran.commit().await?;
+        Ok(archived)
+    }
+
+    pub async fn archive_expired(
+        config: &Config,
+        pool: &PgPool,
+        clock: &dyn Clock,
+    ) -> Result<u64, Error> {
+        let before = clock.now() - Duration::days(config.plex_event_retention_days);
+        Self::archive_events(pool, before, clock).await
+    }
+
+    pub async fn get_stats(
+        pool: &PgPool,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        hidden_accounts: &[StackString],
+    ) -> Result<Vec<Self>, Error> {
+        let hidden_accounts = hidden_accounts.to_vec();
+        let query = query!(
+            r#"
+                SELECT day, account, event, sum(count)::BIGINT AS count
+                FROM (
+                    SELECT day, account, event, count
+                    FROM plex_event_daily
+                    WHERE day >= $start_date AND day <= $end_date
+                    UNION ALL
+                    SELECT date(created_at) AS day, account, event, count(*) AS count
+                    FROM plex_event
+                    WHERE date(created_at) >= $start_date AND date(created_at) <= $end_date
+                    GROUP BY 1, 2, 3
+                ) AS t
+                WHERE account != ALL($hidden_accounts)
+                GROUP BY day, account, event
+                ORDER BY day, account, event
+            "#,
+            start_date = start_date,
+            end_date = end_date,
+            hidden_accounts = hidden_accounts
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Account {
     pub id: isize,
@@ -168,6 +337,13 @@ pub struct Metadata {
     #[serde(rename = "ratingCount")]
     pub rating_count: Option<u64>,
     pub key: Option<StackString>,
+    #[serde(rename = "ratingKey")]
+    pub rating_key: Option<StackString>,
+    pub index: Option<i32>,
+    #[serde(rename = "parentIndex")]
+    pub parent_index: Option<i32>,
+    #[serde(rename = "viewOffset")]
+    pub view_offset: Option<i64>,
     #[serde(rename = "parentKey")]
     pub parent_key: Option<StackString>,
     #[serde(rename = "grandparentKey")]
@@ -178,7 +354,7 @@ pub struct Metadata {
     pub updated_at: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Schema)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
 pub enum PlexEventType {
     #[serde(rename = "library.on.deck")]
     LibraryOnDeck,
@@ -226,7 +402,7 @@ impl PlexEventType {
 }
 
 impl FromStr for PlexEventType {
-    type Err = Error;
+    type Err = PlexEventError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "library.on.deck" => Ok(Self::LibraryOnDeck),
@@ -241,7 +417,7 @@ impl FromStr for PlexEventType {
             "admin.database.corrupted" => Ok(Self::AdminDatabaseCorrupted),
             "device.new" => Ok(Self::DeviceNew),
             "playback.started" => Ok(Self::PlaybackStarted),
-            _ => Err(format_err!("Invalid PlexEventType")),
+            _ => Err(PlexEventError::InvalidEventType(s.into())),
         }
     }
 }
@@ -260,3 +436,51 @@ pub struct WebhookPayload {
     #[serde(rename = "Metadata")]
     pub metadata: Metadata,
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::{TimeZone, Utc};
+    use stack_string::StackString;
+
+    use crate::{
+        clock::FixedClock,
+        plex_events::{PlexEvent, PlexEventError, PlexEventType},
+    };
+
+    #[test]
+    fn test_get_from_payload() -> Result<(), Error> {
+        let buf = include_bytes!("../../tests/data/plex_webhook_payload.json");
+        let event = PlexEvent::get_from_payload(buf)?;
+        assert_eq!(event.event.as_str(), "media.pause");
+        assert_eq!(event.player_title.as_str(), "smoke_test_player");
+        assert!(event.server_id.is_some());
+        assert_eq!(
+            event.title.as_ref().map(StackString::as_str),
+            Some("Smoke Test Fixture")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_from_payload_with_clock() -> Result<(), Error> {
+        let buf = include_bytes!("../../tests/data/plex_webhook_payload.json");
+        let now = Utc.ymd(2021, 3, 4).and_hms(5, 6, 7);
+        let event = PlexEvent::get_from_payload_with_clock(buf, &FixedClock::new(now))?;
+        assert_eq!(event.created_at, Some(now.into()));
+        assert_eq!(event.last_modified, event.created_at);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plex_event_error() {
+        assert!(matches!(
+            "media.unknown".parse::<PlexEventType>(),
+            Err(PlexEventError::InvalidEventType(_))
+        ));
+        assert!(matches!(
+            PlexEvent::get_from_payload(b"{}"),
+            Err(PlexEventError::InvalidPayload(_))
+        ));
+    }
+}
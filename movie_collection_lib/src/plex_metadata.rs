@@ -0,0 +1,290 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+
+use crate::{
+    datetime_wrapper::DateTimeWrapper,
+    pgpool::PgPool,
+    plex_events::{PlexEventType, WebhookPayload},
+    trakt_utils::WatchedEpisode,
+    user_watched::UserWatched,
+    utils::{parse_episode_span, parse_file_stem},
+};
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct PlexMetadata {
+    pub collection_idx: i32,
+    pub path: StackString,
+    pub server_uuid: StackString,
+    pub metadata_key: StackString,
+    pub view_offset: i64,
+    pub last_modified: DateTimeWrapper,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema, PartialEq)]
+pub struct PlexDeepLinks {
+    pub app_url: StackString,
+    pub web_url: StackString,
+}
+
+#[derive(FromSqlRow)]
+struct CollectionMatch {
+    idx: i32,
+    path: StackString,
+}
+
+pub fn format_offset(view_offset: i64) -> StackString {
+    let seconds = view_offset / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+    .into()
+}
+
+impl PlexMetadata {
+    pub fn file_name(&self) -> StackString {
+        Path::new(self.path.as_str())
+            .file_name()
+            .map_or_else(|| "".into(), |f| f.to_string_lossy().into_owned().into())
+    }
+
+    pub fn deep_links(&self) -> PlexDeepLinks {
+        let key = format!("%2Flibrary%2Fmetadata%2F{}", self.metadata_key);
+        let app_url = format!(
+            "plex://play/?metadataKey={key}&server={server}&viewOffset={offset}",
+            key = key,
+            server = self.server_uuid,
+            offset = self.view_offset,
+        );
+        let web_url = format!(
+            "https://app.plex.tv/desktop#!/server/{server}/details?key={key}&viewOffset={offset}",
+            key = key,
+            server = self.server_uuid,
+            offset = self.view_offset,
+        );
+        PlexDeepLinks {
+            app_url: app_url.into(),
+            web_url: web_url.into(),
+        }
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let links = self.deep_links();
+        let resume = if self.view_offset > 0 {
+            format!(" (resume at {})", format_offset(self.view_offset))
+        } else {
+            String::new()
+        };
+        format!(
+            r#"<a href="{}">Open in Plex</a> <a href="{}" target="_blank">Plex Web</a>{}"#,
+            links.app_url, links.web_url, resume
+        )
+        .into()
+    }
+
+    pub async fn get_by_collection_idx(pool: &PgPool, idx: i32) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.collection_idx, b.path, a.server_uuid, a.metadata_key, a.view_offset,
+                       a.last_modified
+                FROM plex_metadata a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                WHERE a.collection_idx = $idx
+                ORDER BY a.last_modified DESC
+                LIMIT 1
+            "#,
+            idx = idx
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_continue_watching(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.collection_idx, b.path, a.server_uuid, a.metadata_key, a.view_offset,
+                       a.last_modified
+                FROM plex_metadata a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                WHERE a.view_offset > 0
+                ORDER BY a.last_modified DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    async fn find_collection_idx(
+        pool: &PgPool,
+        payload: &WebhookPayload,
+    ) -> Result<Option<i32>, Error> {
+        let metadata = &payload.metadata;
+        let (title, istv, season_episode) =
+            match metadata.metadata_type.as_ref().map(StackString::as_str) {
+                Some("episode") => (
+                    metadata.grandparent_title.as_ref(),
+                    true,
+                    metadata.parent_index.zip(metadata.index),
+                ),
+                Some("movie") => (metadata.title.as_ref(), false, None),
+                _ => return Ok(None),
+            };
+        let title = match title {
+            Some(title) => title,
+            None => return Ok(None),
+        };
+        let query = query!(
+            r#"
+                SELECT a.idx, a.path
+                FROM movie_collection a
+                JOIN imdb_ratings b ON a.show = b.show
+                WHERE b.title = $title AND coalesce(b.istv, false) = $istv
+                ORDER BY a.idx
+            "#,
+            title = title,
+            istv = istv
+        );
+        let conn = pool.get().await?;
+        let rows: Vec<CollectionMatch> = query.fetch(&conn).await?;
+        let idx = rows
+            .into_iter()
+            .find(|row| {
+                if let Some((season, episode)) = season_episode {
+                    let file_stem = Path::new(row.path.as_str())
+                        .file_stem()
+                        .map(|s| s.to_string_lossy())
+                        .unwrap_or_default();
+                    let (_, s, e) = parse_file_stem(&file_stem);
+                    let last = parse_episode_span(&file_stem).map_or(e, |(_, _, _, last)| last);
+                    s == season && e <= episode && episode <= last
+                } else {
+                    true
+                }
+            })
+            .map(|row| row.idx);
+        Ok(idx)
+    }
+
+    pub async fn update_from_payload(
+        pool: &PgPool,
+        payload: &WebhookPayload,
+    ) -> Result<Option<i32>, Error> {
+        let metadata_key = match &payload.metadata.rating_key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let collection_idx = match Self::find_collection_idx(pool, payload).await? {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+        let view_offset = match payload.event {
+            PlexEventType::MediaScrobble => Some(0),
+            _ => payload.metadata.view_offset,
+        };
+        let query = query!(
+            r#"
+                INSERT INTO plex_metadata
+                    (collection_idx, server_uuid, metadata_key, view_offset, last_modified)
+                VALUES
+                    ($collection_idx, $server_uuid, $metadata_key, coalesce($view_offset, 0), now())
+                ON CONFLICT (collection_idx, server_uuid) DO UPDATE
+                SET metadata_key=$metadata_key,
+                    view_offset=coalesce($view_offset, plex_metadata.view_offset),
+                    last_modified=now()
+            "#,
+            collection_idx = collection_idx,
+            server_uuid = payload.server.uuid,
+            metadata_key = metadata_key,
+            view_offset = view_offset
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        if let PlexEventType::MediaScrobble = payload.event {
+            UserWatched::set_watched_for_plex_account(pool, &payload.account.title, collection_idx)
+                .await?;
+            Self::mark_span_watched(pool, collection_idx).await?;
+        }
+        Ok(Some(collection_idx))
+    }
+
+    // A scrobbled multi-episode file counts for every episode it covers
+    async fn mark_span_watched(pool: &PgPool, collection_idx: i32) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                SELECT a.path, b.link
+                FROM movie_collection a
+                JOIN imdb_ratings b ON a.show = b.show
+                WHERE a.idx = $idx AND b.istv AND b.link IS NOT NULL
+            "#,
+            idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        let row: Option<(StackString, StackString)> = query.fetch_opt(&conn).await?;
+        let (path, link) = match row {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+        let file_stem = Path::new(path.as_str())
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        let (season, first, last) = match parse_episode_span(&file_stem) {
+            Some((_, season, first, last)) => (season, first, last),
+            None => return Ok(()),
+        };
+        for episode in first..=last {
+            if WatchedEpisode::get_watched_episode(pool, &link, season, episode)
+                .await?
+                .is_none()
+            {
+                WatchedEpisode {
+                    imdb_url: link.clone(),
+                    season,
+                    episode,
+                    ..WatchedEpisode::default()
+                }
+                .insert_episode(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::plex_metadata::{format_offset, PlexMetadata};
+
+    #[test]
+    fn test_deep_links() {
+        let metadata = PlexMetadata {
+            collection_idx: 1,
+            path: "/tmp/television/mr_robot_s01_ep01.mp4".into(),
+            server_uuid: "abc123".into(),
+            metadata_key: "4567".into(),
+            view_offset: 3_723_000,
+            last_modified: Utc::now().into(),
+        };
+        assert_eq!(format_offset(metadata.view_offset).as_str(), "1:02:03");
+        assert_eq!(metadata.file_name().as_str(), "mr_robot_s01_ep01.mp4");
+        let links = metadata.deep_links();
+        assert_eq!(
+            links.app_url.as_str(),
+            "plex://play/?metadataKey=%2Flibrary%2Fmetadata%2F4567&server=abc123&viewOffset=3723000"
+        );
+        assert_eq!(
+            links.web_url.as_str(),
+            "https://app.plex.tv/desktop#!/server/abc123/details?key=%2Flibrary%2Fmetadata%2F4567&\
+             viewOffset=3723000"
+        );
+        assert!(metadata.get_html().contains("resume at 1:02:03"));
+    }
+}
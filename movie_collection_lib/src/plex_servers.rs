@@ -0,0 +1,91 @@
+use anyhow::{format_err, Error};
+use postgres_query::{query, FromSqlRow};
+use reqwest::Url;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct PlexServer {
+    pub server_id: StackString,
+    pub name: StackString,
+    pub host: StackString,
+    #[serde(skip_serializing)]
+    pub token: StackString,
+    pub created_at: DateTimeWrapper,
+    pub last_modified: DateTimeWrapper,
+}
+
+impl PlexServer {
+    pub fn validate_host(host: &str) -> Result<Url, Error> {
+        let url: Url = host.parse()?;
+        if url.scheme() == "http" || url.scheme() == "https" {
+            Ok(url)
+        } else {
+            Err(format_err!("Plex host must be http or https"))
+        }
+    }
+
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!("SELECT * FROM plex_servers ORDER BY name");
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_id(pool: &PgPool, server_id: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM plex_servers WHERE server_id = $server_id",
+            server_id = server_id
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn upsert(
+        pool: &PgPool,
+        server_id: &str,
+        name: &str,
+        host: &str,
+        token: &str,
+    ) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO plex_servers (server_id, name, host, token)
+                VALUES ($server_id, $name, $host, $token)
+                ON CONFLICT (server_id) DO UPDATE
+                SET name=$name, host=$host, token=$token, last_modified=now()
+                RETURNING *
+            "#,
+            server_id = server_id,
+            name = name,
+            host = host,
+            token = token
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(pool: &PgPool, server_id: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM plex_servers WHERE server_id = $server_id",
+            server_id = server_id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plex_servers::PlexServer;
+
+    #[test]
+    fn test_validate_host() {
+        assert!(PlexServer::validate_host("http://192.168.1.10:32400").is_ok());
+        assert!(PlexServer::validate_host("https://plex.example.com").is_ok());
+        assert!(PlexServer::validate_host("ftp://plex.example.com").is_err());
+        assert!(PlexServer::validate_host("plex.example.com").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::Error;
+use reqwest::{header::ACCEPT, Client, Url};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use thiserror::Error as ThisError;
+
+use crate::{
+    config::Config, plex_metadata::format_offset, plex_servers::PlexServer, utils::escape_html,
+};
+
+#[derive(ThisError, Debug)]
+pub enum PlexError {
+    #[error("Plex server not configured")]
+    NotConfigured,
+    #[error("Plex request failed {0}")]
+    External(#[from] reqwest::Error),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct SessionMetadata {
+    title: StackString,
+    grandparent_title: Option<StackString>,
+    parent_index: Option<i32>,
+    index: Option<i32>,
+    #[serde(default)]
+    duration: i64,
+    #[serde(default)]
+    view_offset: i64,
+    #[serde(rename = "User")]
+    user: Option<SessionTitle>,
+    #[serde(rename = "Player")]
+    player: Option<SessionPlayer>,
+    #[serde(rename = "TranscodeSession")]
+    transcode_session: Option<TranscodeSession>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SessionTitle {
+    title: StackString,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SessionPlayer {
+    title: StackString,
+    #[serde(default)]
+    state: StackString,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct TranscodeSession {
+    video_decision: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct MediaContainer {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<SessionMetadata>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SessionsResponse {
+    #[serde(rename = "MediaContainer")]
+    media_container: MediaContainer,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct NowPlaying {
+    pub user: StackString,
+    pub title: StackString,
+    pub player: StackString,
+    pub state: StackString,
+    pub view_offset: i64,
+    pub duration: i64,
+    pub progress: f64,
+    pub transcode: bool,
+}
+
+impl From<SessionMetadata> for NowPlaying {
+    fn from(item: SessionMetadata) -> Self {
+        let title = match (&item.grandparent_title, item.parent_index, item.index) {
+            (Some(show), Some(season), Some(episode)) => {
+                format!("{} s{:02} ep{:02} {}", show, season, episode, item.title).into()
+            }
+            _ => item.title,
+        };
+        let progress = if item.duration > 0 {
+            100.0 * item.view_offset as f64 / item.duration as f64
+        } else {
+            0.0
+        };
+        let transcode = item
+            .transcode_session
+            .map_or(false, |t| t.video_decision.as_deref() == Some("transcode"));
+        let (player, state) = item
+            .player
+            .map_or_else(Default::default, |p| (p.title, p.state));
+        Self {
+            user: item.user.map(|u| u.title).unwrap_or_default(),
+            title,
+            player,
+            state,
+            view_offset: item.view_offset,
+            duration: item.duration,
+            progress,
+            transcode,
+        }
+    }
+}
+
+impl NowPlaying {
+    // Same exact account match as the `account != ALL($hidden_accounts)` event filters
+    pub fn filter_hidden(sessions: Vec<Self>, hidden_accounts: &[StackString]) -> Vec<Self> {
+        sessions
+            .into_iter()
+            .filter(|s| !hidden_accounts.contains(&s.user))
+            .collect()
+    }
+
+    fn get_html(&self) -> StackString {
+        format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{} / {} ({:.0}%)</td><td>{}</td></tr>",
+            escape_html(&self.user),
+            escape_html(&self.title),
+            escape_html(&self.player),
+            self.state,
+            format_offset(self.view_offset),
+            format_offset(self.duration),
+            self.progress,
+            if self.transcode { "Transcode" } else { "Direct Play" },
+        )
+        .into()
+    }
+
+    pub fn get_html_table(sessions: &[Self]) -> StackString {
+        let rows: Vec<_> = sessions.iter().map(Self::get_html).collect();
+        format!(
+            r#"
+            <h3>Now Playing</h3>
+            <table border="0" id="now_playing_table">
+            <tr><th>User</th><th>Title</th><th>Player</th><th>State</th><th>Progress</th><th>Stream</th></tr>
+            {}
+            </table>
+            "#,
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[derive(Clone)]
+pub struct PlexClient {
+    host: Option<StackString>,
+    token: Option<StackString>,
+    client: Client,
+}
+
+impl PlexClient {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            host: config.plex_host.clone(),
+            token: config.plex_token.clone(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn from_server(server: &PlexServer) -> Self {
+        Self {
+            host: Some(server.host.clone()),
+            token: Some(server.token.clone()),
+            client: Client::new(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.host.is_some() && self.token.is_some()
+    }
+
+    pub async fn get_sessions(&self) -> Result<Vec<NowPlaying>, PlexError> {
+        let plex_host = self.host.as_ref().ok_or(PlexError::NotConfigured)?;
+        let plex_token = self.token.as_ref().ok_or(PlexError::NotConfigured)?;
+        let url = Url::parse_with_params(
+            &format!("{}/status/sessions", plex_host.trim_end_matches('/')),
+            &[("X-Plex-Token", plex_token.as_str())],
+        )
+        .map_err(Error::from)?;
+        let resp: SessionsResponse = self
+            .client
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp
+            .media_container
+            .metadata
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::plex_sessions::{NowPlaying, SessionsResponse};
+
+    #[test]
+    fn test_now_playing_from_sessions() -> Result<(), Error> {
+        let body = r#"{"MediaContainer": {"size": 1, "Metadata": [{
+            "title": "eps1.0_hellofriend.mov", "grandparentTitle": "Mr. Robot",
+            "parentIndex": 1, "index": 1, "duration": 3600000, "viewOffset": 900000,
+            "User": {"title": "ddboline"}, "Player": {"title": "Chrome", "state": "playing"},
+            "TranscodeSession": {"videoDecision": "transcode"}
+        }]}}"#;
+        let resp: SessionsResponse = serde_json::from_str(body)?;
+        let sessions: Vec<NowPlaying> = resp
+            .media_container
+            .metadata
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        assert_eq!(sessions.len(), 1);
+        let session = &sessions[0];
+        assert_eq!(
+            session.title.as_str(),
+            "Mr. Robot s01 ep01 eps1.0_hellofriend.mov"
+        );
+        assert_eq!(session.user.as_str(), "ddboline");
+        assert!(session.transcode);
+        assert!((session.progress - 25.0).abs() < 1e-6);
+        let html = NowPlaying::get_html_table(&sessions);
+        assert!(html.contains("0:15:00 / 1:00:00 (25%)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_now_playing_filter_hidden() {
+        let session = |user: &str| NowPlaying {
+            user: user.into(),
+            title: "Mr. Robot s01 ep01".into(),
+            player: "Chrome".into(),
+            state: "playing".into(),
+            view_offset: 0,
+            duration: 0,
+            progress: 0.0,
+            transcode: false,
+        };
+        let sessions = vec![session("ddboline"), session("guest")];
+        let visible = NowPlaying::filter_hidden(sessions.clone(), &["guest".into()]);
+        assert_eq!(visible, vec![session("ddboline")]);
+        assert_eq!(NowPlaying::filter_hidden(sessions.clone(), &[]), sessions);
+    }
+}
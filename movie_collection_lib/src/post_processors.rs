@@ -0,0 +1,157 @@
+use anyhow::{format_err, Error};
+use async_trait::async_trait;
+use futures::FutureExt;
+use stack_string::StackString;
+use std::{fmt, panic::AssertUnwindSafe, sync::Arc};
+
+use crate::movie_collection::{MovieCollection, MovieCollectionRow};
+
+#[async_trait]
+pub trait CollectionPostProcessor: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn order(&self) -> i32 {
+        0
+    }
+
+    async fn process(&self, mc: &MovieCollection, row: &MovieCollectionRow) -> Result<(), Error>;
+}
+
+#[derive(Clone, Default)]
+pub struct PostProcessors(Arc<Vec<Arc<dyn CollectionPostProcessor>>>);
+
+impl fmt::Debug for PostProcessors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|p| p.name()))
+            .finish()
+    }
+}
+
+impl PostProcessors {
+    pub fn register(&mut self, processor: Arc<dyn CollectionPostProcessor>) {
+        let processors = Arc::make_mut(&mut self.0);
+        processors.push(processor);
+        processors.sort_by_key(|p| p.order());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<StackString> {
+        self.0.iter().map(|p| p.name().into()).collect()
+    }
+
+    pub async fn run(
+        &self,
+        mc: &MovieCollection,
+        row: &MovieCollectionRow,
+    ) -> Vec<(StackString, Error)> {
+        let mut errors = Vec::new();
+        for processor in self.0.iter() {
+            let result = AssertUnwindSafe(processor.process(mc, row))
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|_| Err(format_err!("post processor panicked")));
+            if let Err(e) = result {
+                mc.stdout.send(format!(
+                    "post processor {} failed for {} {}",
+                    processor.name(),
+                    row.path,
+                    e
+                ));
+                errors.push((processor.name().into(), e));
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{format_err, Error};
+    use async_trait::async_trait;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use crate::{
+        movie_collection::{MovieCollection, MovieCollectionRow},
+        post_processors::{CollectionPostProcessor, PostProcessors},
+    };
+
+    struct Recorder {
+        name: &'static str,
+        order: i32,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl CollectionPostProcessor for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn order(&self) -> i32 {
+            self.order
+        }
+
+        async fn process(&self, _: &MovieCollection, _: &MovieCollectionRow) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(self.name);
+            Ok(())
+        }
+    }
+
+    struct Failing(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl CollectionPostProcessor for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn process(&self, _: &MovieCollection, _: &MovieCollectionRow) -> Result<(), Error> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(format_err!("failed"))
+            } else {
+                panic!("panicked");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_processors() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut processors = PostProcessors::default();
+        for (name, order) in &[("second", 10), ("first", -10)] {
+            processors.register(Arc::new(Recorder {
+                name: *name,
+                order: *order,
+                calls: calls.clone(),
+            }));
+        }
+        processors.register(Arc::new(Failing(Arc::new(AtomicUsize::new(0)))));
+        let names: Vec<_> = processors.names();
+        let names: Vec<_> = names.iter().map(|s| s.as_str()).collect();
+        assert_eq!(names, vec!["first", "failing", "second"]);
+
+        let mc = MovieCollection::default();
+        let row = MovieCollectionRow {
+            idx: 1,
+            path: "/tmp/mr_robot_s01_ep01.mp4".into(),
+            show: "mr_robot".into(),
+            ..MovieCollectionRow::default()
+        };
+        let errors = processors.run(&mc, &row).await;
+        assert_eq!(errors.len(), 1);
+        let errors = processors.run(&mc, &row).await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0.as_str(), "failing");
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["first", "second", "first", "second"]
+        );
+    }
+}
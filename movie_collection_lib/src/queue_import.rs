@@ -0,0 +1,178 @@
+use anyhow::Error;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct QueueImportRow {
+    pub path: StackString,
+    pub idx: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct QueueImportRejection {
+    pub row: usize,
+    pub path: StackString,
+    pub reason: StackString,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Schema)]
+pub struct QueueImportReport {
+    pub imported: usize,
+    pub rejected: Vec<QueueImportRejection>,
+}
+
+// Accepts either a JSON array of {"path", "idx"} objects or CSV lines of
+// path[,idx], the position being optional and defaulting to the end of the queue
+pub fn parse_queue_import(buf: &[u8]) -> Result<Vec<QueueImportRow>, Error> {
+    let text = std::str::from_utf8(buf)?.trim();
+    if text.starts_with('[') {
+        return serde_json::from_str(text).map_err(Into::into);
+    }
+    let rows = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !line.eq_ignore_ascii_case("path") && !line.eq_ignore_ascii_case("path,idx"))
+        .map(|line| {
+            let (path, idx) = match line.rsplit_once(',') {
+                Some((path, idx)) if idx.trim().is_empty() => (path, None),
+                Some((path, idx)) => match idx.trim().parse() {
+                    Ok(idx) => (path, Some(idx)),
+                    Err(_) => (line, None),
+                },
+                None => (line, None),
+            };
+            QueueImportRow {
+                path: path.trim().trim_matches('"').into(),
+                idx,
+            }
+        })
+        .collect();
+    Ok(rows)
+}
+
+// Same semantics as MovieQueueDB::insert_into_queue_by_collection_idx, applied to
+// an in-memory (idx, collection_idx) list so a whole import can be written at once
+pub fn apply_queue_import(queue: &mut Vec<(i32, i32)>, collection_idx: i32, idx: Option<i32>) {
+    if let Some(pos) = queue.iter().position(|(_, c)| *c == collection_idx) {
+        let (removed_idx, _) = queue.remove(pos);
+        for entry in queue.iter_mut().filter(|(i, _)| *i > removed_idx) {
+            entry.0 -= 1;
+        }
+    }
+    let max_idx = queue.iter().map(|(i, _)| *i).max().unwrap_or(-1);
+    let idx = idx.unwrap_or(max_idx + 1);
+    for entry in queue.iter_mut().filter(|(i, _)| *i >= idx) {
+        entry.0 += 1;
+    }
+    queue.push((idx, collection_idx));
+    queue.sort_unstable();
+}
+
+fn csv_field(field: &str) -> StackString {
+    if field.contains(',') || field.contains('"') {
+        format!(r#""{}""#, field.replace('"', r#""""#)).into()
+    } else {
+        field.into()
+    }
+}
+
+impl QueueImportReport {
+    pub fn get_error_csv(&self) -> StackString {
+        let mut csv = String::from("row,path,reason\n");
+        for r in &self.rejected {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                r.row,
+                csv_field(&r.path),
+                csv_field(&r.reason)
+            ));
+        }
+        csv.into()
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let mut body = format!(
+            "imported {} row(s), rejected {} row(s)<br>",
+            self.imported,
+            self.rejected.len()
+        );
+        if !self.rejected.is_empty() {
+            let rows: Vec<_> = self
+                .rejected
+                .iter()
+                .map(|r| {
+                    format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        r.row, r.path, r.reason
+                    )
+                })
+                .collect();
+            body.push_str(&format!(
+                r#"<a download="queue_import_errors.csv" href="data:text/csv;base64,{}">Download error report</a>
+                <table border="0"><tr><th>Row</th><th>Path</th><th>Reason</th></tr>{}</table>"#,
+                base64::encode(self.get_error_csv().as_bytes()),
+                rows.join("")
+            ));
+        }
+        body.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue_import::{
+        apply_queue_import, parse_queue_import, QueueImportRejection, QueueImportReport,
+        QueueImportRow,
+    };
+
+    #[test]
+    fn test_parse_queue_import() {
+        let csv = b"path,idx\n/shows/mr_robot_s01_ep01.mp4,3\n\"/movies/a, b.mp4\"\n";
+        let rows = parse_queue_import(csv).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                QueueImportRow {
+                    path: "/shows/mr_robot_s01_ep01.mp4".into(),
+                    idx: Some(3),
+                },
+                QueueImportRow {
+                    path: "/movies/a, b.mp4".into(),
+                    idx: None,
+                },
+            ]
+        );
+        let json = br#"[{"path": "/movies/c.mp4", "idx": 0}]"#;
+        let rows = parse_queue_import(json).unwrap();
+        assert_eq!(rows[0].idx, Some(0));
+    }
+
+    #[test]
+    fn test_apply_queue_import() {
+        let mut queue = vec![(0, 10), (1, 11), (2, 12)];
+        apply_queue_import(&mut queue, 13, Some(1));
+        assert_eq!(queue, vec![(0, 10), (1, 13), (2, 11), (3, 12)]);
+        apply_queue_import(&mut queue, 10, None);
+        assert_eq!(queue, vec![(0, 13), (1, 11), (2, 12), (3, 10)]);
+        apply_queue_import(&mut queue, 12, Some(0));
+        assert_eq!(queue, vec![(0, 12), (1, 13), (2, 11), (3, 10)]);
+    }
+
+    #[test]
+    fn test_error_csv() {
+        let report = QueueImportReport {
+            imported: 1,
+            rejected: vec![QueueImportRejection {
+                row: 2,
+                path: "/movies/a, b.mp4".into(),
+                reason: "Not in collection".into(),
+            }],
+        };
+        assert_eq!(
+            report.get_error_csv().as_str(),
+            "row,path,reason\n2,\"/movies/a, b.mp4\",Not in collection\n"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::Error;
+use chrono::Duration;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+
+use crate::{
+    clock::{Clock, IdGen},
+    datetime_wrapper::DateTimeWrapper,
+    pgpool::PgPool,
+    utils::parse_file_stem,
+};
+
+pub const SHARE_EXPIRY_DAYS: i64 = 30;
+pub const RECENT_LIMIT: i64 = 20;
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct QueueShare {
+    pub token: StackString,
+    pub email: StackString,
+    pub created_at: DateTimeWrapper,
+    pub expires_at: DateTimeWrapper,
+}
+
+impl QueueShare {
+    fn new_share(email: &str, clock: &dyn Clock, id_gen: &dyn IdGen) -> Self {
+        let now = clock.now();
+        Self {
+            token: id_gen.new_id().to_string().into(),
+            email: email.into(),
+            created_at: now.into(),
+            expires_at: (now + Duration::days(SHARE_EXPIRY_DAYS)).into(),
+        }
+    }
+
+    pub async fn regenerate(
+        pool: &PgPool,
+        email: &str,
+        clock: &dyn Clock,
+        id_gen: &dyn IdGen,
+    ) -> Result<Self, Error> {
+        let share = Self::new_share(email, clock, id_gen);
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            "DELETE FROM queue_share WHERE email = $email",
+            email = email
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            r#"
+                INSERT INTO queue_share (token, email, created_at, expires_at)
+                VALUES ($token, $email, $created_at, $expires_at)
+            "#,
+            token = share.token,
+            email = share.email,
+            created_at = share.created_at,
+            expires_at = share.expires_at
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        tran.commit().await?;
+        Ok(share)
+    }
+
+    pub async fn get_valid(
+        pool: &PgPool,
+        token: &str,
+        clock: &dyn Clock,
+    ) -> Result<Option<Self>, Error> {
+        let now: DateTimeWrapper = clock.now().into();
+        let query = query!(
+            r#"
+                SELECT token, email, created_at, expires_at
+                FROM queue_share
+                WHERE token = $token AND expires_at > $now
+            "#,
+            token = token,
+            now = now
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn revoke(pool: &PgPool, email: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM queue_share WHERE email = $email",
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub title: StackString,
+    pub path: StackString,
+    pub istv: bool,
+}
+
+impl SnapshotEntry {
+    fn get_html(&self) -> StackString {
+        let episode = if self.istv {
+            let file_stem = Path::new(self.path.as_str())
+                .file_stem()
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default();
+            match parse_file_stem(&file_stem) {
+                (_, season, episode) if season > 0 => format!("s{:02} ep{:02}", season, episode),
+                _ => String::new(),
+            }
+        } else {
+            String::new()
+        };
+        format!("<tr><td>{}</td><td>{}</td></tr>", self.title, episode).into()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Schema)]
+pub struct QueueSnapshot {
+    pub queue: Vec<SnapshotEntry>,
+    pub recent: Vec<SnapshotEntry>,
+    pub generated_at: DateTimeWrapper,
+}
+
+impl QueueSnapshot {
+    pub async fn get_snapshot(pool: &PgPool, clock: &dyn Clock) -> Result<Self, Error> {
+        let conn = pool.get().await?;
+        let query = query!(
+            r#"
+                SELECT coalesce(c.title, b.show) AS title, b.path,
+                       coalesce(c.istv, false) AS istv
+                FROM movie_queue a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                LEFT JOIN imdb_ratings c ON b.show_id = c.index
+                WHERE NOT b.is_deleted
+                ORDER BY a.idx
+            "#
+        );
+        let queue = query.fetch(&conn).await?;
+        let query = query!(
+            r#"
+                SELECT coalesce(c.title, b.show) AS title, b.path,
+                       coalesce(c.istv, false) AS istv
+                FROM movie_collection b
+                LEFT JOIN imdb_ratings c ON b.show_id = c.index
+                WHERE NOT b.is_deleted AND b.last_modified IS NOT NULL
+                ORDER BY b.last_modified DESC
+                LIMIT $limit
+            "#,
+            limit = RECENT_LIMIT
+        );
+        let recent = query.fetch(&conn).await?;
+        Ok(Self {
+            queue,
+            recent,
+            generated_at: clock.now().into(),
+        })
+    }
+
+    pub fn get_html(&self) -> StackString {
+        fn section(title: &str, entries: &[SnapshotEntry]) -> String {
+            let rows: Vec<_> = entries.iter().map(SnapshotEntry::get_html).collect();
+            format!(
+                r#"<h3>{}</h3><table border="0">{}</table>"#,
+                title,
+                rows.join("")
+            )
+        }
+        format!(
+            r#"<!DOCTYPE html><html><head><title>What's On</title></head><body><center>{}{}<p>Updated {}</p></center></body></html>"#,
+            section("Up Next", &self.queue),
+            section("Recently Added", &self.recent),
+            self.generated_at.format("%Y-%m-%d %H:%M"),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::{
+        clock::{FixedClock, SequentialIdGen},
+        queue_share::{QueueShare, QueueSnapshot, SnapshotEntry, SHARE_EXPIRY_DAYS},
+    };
+
+    #[test]
+    fn test_new_share() {
+        let now = Utc.ymd(2021, 3, 4).and_hms(5, 6, 7);
+        let share = QueueShare::new_share(
+            "user@example.com",
+            &FixedClock::new(now),
+            &SequentialIdGen::default(),
+        );
+        assert_eq!(share.token.as_str(), "00000000-0000-0000-0000-000000000001");
+        assert_eq!(*share.created_at, now);
+        assert_eq!(*share.expires_at, now + Duration::days(SHARE_EXPIRY_DAYS));
+    }
+
+    #[test]
+    fn test_snapshot_html() {
+        let snapshot = QueueSnapshot {
+            queue: vec![SnapshotEntry {
+                title: "Mr. Robot".into(),
+                path: "/tmp/television/mr_robot_s01_ep02.mp4".into(),
+                istv: true,
+            }],
+            recent: vec![SnapshotEntry {
+                title: "Inception".into(),
+                path: "/tmp/movies/inception.mp4".into(),
+                istv: false,
+            }],
+            generated_at: Utc::now().into(),
+        };
+        let html = snapshot.get_html();
+        assert!(html.contains("<tr><td>Mr. Robot</td><td>s01 ep02</td></tr>"));
+        assert!(html.contains("<tr><td>Inception</td><td></td></tr>"));
+        assert!(!html.contains("button"));
+    }
+}
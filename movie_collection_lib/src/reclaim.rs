@@ -0,0 +1,361 @@
+use anyhow::{format_err, Error};
+use chrono::{Duration, Utc};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+use tokio::fs;
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, delete_confirm::format_size, pgpool::PgPool,
+};
+
+pub const DEFAULT_RECLAIM_DAYS: i64 = 30;
+
+#[derive(FromSqlRow)]
+struct ReclaimCandidate {
+    collection_idx: i32,
+    path: StackString,
+    show: StackString,
+    last_watched: DateTimeWrapper,
+}
+
+// Files watched by every authorized user more than `days` ago that nobody asked to keep
+// and that are not sitting in a queue, optionally restricted to a single entry
+async fn get_candidates(
+    pool: &PgPool,
+    days: i64,
+    collection_idx: Option<i32>,
+) -> Result<Vec<ReclaimCandidate>, Error> {
+    let cutoff: DateTimeWrapper = (Utc::now() - Duration::days(days)).into();
+    let query = query!(
+        r#"
+            SELECT a.idx AS collection_idx, a.path, a.show,
+                   max(b.watched_at) AS last_watched
+            FROM movie_collection a
+            JOIN user_watched b ON b.collection_idx = a.idx
+            JOIN authorized_users c ON c.email = b.email
+            WHERE NOT a.is_deleted
+              AND ($collection_idx::int IS NULL OR a.idx = $collection_idx)
+              AND NOT EXISTS (SELECT 1 FROM collection_keep k WHERE k.collection_idx = a.idx)
+              AND NOT EXISTS (SELECT 1 FROM movie_queue q WHERE q.collection_idx = a.idx)
+            GROUP BY a.idx, a.path, a.show
+            HAVING count(DISTINCT b.email) = (SELECT count(*) FROM authorized_users)
+               AND max(b.watched_at) < $cutoff
+        "#,
+        cutoff = cutoff,
+        collection_idx = collection_idx
+    );
+    let conn = pool.get().await?;
+    query.fetch(&conn).await.map_err(Into::into)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct ReclaimEntry {
+    pub collection_idx: i32,
+    pub path: StackString,
+    pub show: StackString,
+    pub last_watched: DateTimeWrapper,
+    pub size: u64,
+}
+
+impl ReclaimEntry {
+    fn get_html(&self) -> StackString {
+        format!(
+            r#"
+            <tr>
+            <td><input type="checkbox" name="reclaim" value="{idx}" checked></td>
+            <td>{path}</td><td>{size}</td><td>{last_watched}</td>
+            <td><button type="submit" onclick="reclaim_keep({idx})">Keep</button></td>
+            </tr>"#,
+            idx = self.collection_idx,
+            path = self.path,
+            size = format_size(self.size),
+            last_watched = self.last_watched.format("%Y-%m-%d"),
+        )
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct ReclaimReport {
+    pub days: i64,
+    pub entries: Vec<ReclaimEntry>,
+    pub total_size: u64,
+}
+
+impl ReclaimReport {
+    pub async fn get_report(pool: &PgPool, days: i64) -> Result<Self, Error> {
+        let candidates = get_candidates(pool, days, None).await?;
+        let mut entries = Vec::new();
+        for candidate in candidates {
+            let size = match fs::metadata(candidate.path.as_str()).await {
+                Ok(metadata) if metadata.is_file() => metadata.len(),
+                _ => continue,
+            };
+            entries.push(ReclaimEntry {
+                collection_idx: candidate.collection_idx,
+                path: candidate.path,
+                show: candidate.show,
+                last_watched: candidate.last_watched,
+                size,
+            });
+        }
+        Ok(Self::from_entries(days, entries))
+    }
+
+    fn from_entries(days: i64, mut entries: Vec<ReclaimEntry>) -> Self {
+        entries.sort_by(|x, y| y.size.cmp(&x.size));
+        let total_size = entries.iter().map(|e| e.size).sum();
+        Self {
+            days,
+            entries,
+            total_size,
+        }
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let rows: Vec<_> = self.entries.iter().map(ReclaimEntry::get_html).collect();
+        format!(
+            r#"
+            <h3>{total} reclaimable in {count} file(s) watched by everyone over {days} days ago</h3>
+            <table border="0">
+            <tr><th></th><th>File</th><th>Size</th><th>Last Watched</th><th></th></tr>
+            {rows}
+            </table>
+            <button type="submit" onclick="reclaim_trash()">Move Selected to Trash</button>
+            <button type="submit" onclick="updateMainArticle('/list/reclaim/trash')">View Trash</button>
+            "#,
+            total = format_size(self.total_size),
+            count = self.entries.len(),
+            days = self.days,
+            rows = rows.join(""),
+        )
+        .into()
+    }
+}
+
+pub async fn set_keep(pool: &PgPool, collection_idx: i32, email: &str) -> Result<(), Error> {
+    let query = query!(
+        r#"
+            INSERT INTO collection_keep (collection_idx, email)
+            VALUES ($collection_idx, $email)
+            ON CONFLICT (collection_idx) DO NOTHING
+        "#,
+        collection_idx = collection_idx,
+        email = email
+    );
+    let conn = pool.get().await?;
+    query.execute(&conn).await?;
+    Ok(())
+}
+
+pub async fn remove_keep(pool: &PgPool, collection_idx: i32) -> Result<u64, Error> {
+    let query = query!(
+        "DELETE FROM collection_keep WHERE collection_idx = $collection_idx",
+        collection_idx = collection_idx
+    );
+    let conn = pool.get().await?;
+    query.execute(&conn).await.map_err(Into::into)
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct TrashEntry {
+    pub collection_idx: i32,
+    pub path: StackString,
+    pub trash_path: StackString,
+    pub size: i64,
+    pub trashed_at: DateTimeWrapper,
+}
+
+impl TrashEntry {
+    pub async fn move_to_trash(
+        config: &Config,
+        pool: &PgPool,
+        collection_idx: i32,
+        days: i64,
+    ) -> Result<Self, Error> {
+        let trash_dir = config
+            .trash_dir
+            .as_ref()
+            .ok_or_else(|| format_err!("No trash_dir configured"))?;
+        // Eligibility may have changed since the report was rendered (someone queued or
+        // kept the file, or a new user hasn't watched it yet), so check it again here
+        let path = get_candidates(pool, days, Some(collection_idx))
+            .await?
+            .pop()
+            .map(|candidate| candidate.path)
+            .ok_or_else(|| format_err!("{} is not eligible for trash", collection_idx))?;
+        let file_name = Path::new(path.as_str())
+            .file_name()
+            .ok_or_else(|| format_err!("No file name {}", path))?
+            .to_string_lossy();
+        let trash_path = trash_dir.join(format!("{}_{}", collection_idx, file_name));
+        let size = fs::metadata(path.as_str()).await?.len();
+        fs::create_dir_all(trash_dir).await?;
+        if fs::rename(path.as_str(), &trash_path).await.is_err() {
+            fs::copy(path.as_str(), &trash_path).await?;
+            fs::remove_file(path.as_str()).await?;
+        }
+        let entry = Self {
+            collection_idx,
+            trash_path: trash_path.to_string_lossy().into_owned().into(),
+            path,
+            size: size as i64,
+            trashed_at: Utc::now().into(),
+        };
+
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            r#"
+                INSERT INTO collection_trash (collection_idx, path, trash_path, size, trashed_at)
+                VALUES ($collection_idx, $path, $trash_path, $size, $trashed_at)
+                ON CONFLICT (collection_idx) DO UPDATE
+                SET path=$path, trash_path=$trash_path, size=$size, trashed_at=$trashed_at
+            "#,
+            collection_idx = entry.collection_idx,
+            path = entry.path,
+            trash_path = entry.trash_path,
+            size = entry.size,
+            trashed_at = entry.trashed_at
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            "UPDATE movie_collection SET is_deleted=true WHERE idx = $collection_idx",
+            collection_idx = collection_idx
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        tran.commit().await?;
+        Ok(entry)
+    }
+
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT collection_idx, path, trash_path, size, trashed_at
+                FROM collection_trash
+                ORDER BY trashed_at DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_idx(pool: &PgPool, collection_idx: i32) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT collection_idx, path, trash_path, size, trashed_at
+                FROM collection_trash
+                WHERE collection_idx = $collection_idx
+            "#,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// Move the file back to its original location and un-delete the collection entry
+    pub async fn restore(&self, pool: &PgPool) -> Result<(), Error> {
+        if fs::metadata(self.path.as_str()).await.is_ok() {
+            return Err(format_err!("{} already exists", self.path));
+        }
+        if let Some(parent) = Path::new(self.path.as_str()).parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::rename(self.trash_path.as_str(), self.path.as_str())
+            .await
+            .is_err()
+        {
+            fs::copy(self.trash_path.as_str(), self.path.as_str()).await?;
+            fs::remove_file(self.trash_path.as_str()).await?;
+        }
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            "DELETE FROM collection_trash WHERE collection_idx = $collection_idx",
+            collection_idx = self.collection_idx
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            "UPDATE movie_collection SET is_deleted=false WHERE idx = $collection_idx",
+            collection_idx = self.collection_idx
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        tran.commit().await?;
+        Ok(())
+    }
+
+    /// Permanently remove the trashed file, the collection entry stays deleted
+    pub async fn purge(&self, pool: &PgPool) -> Result<(), Error> {
+        match fs::remove_file(self.trash_path.as_str()).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+        let query = query!(
+            "DELETE FROM collection_trash WHERE collection_idx = $collection_idx",
+            collection_idx = self.collection_idx
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub fn get_html(entries: &[Self]) -> StackString {
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    r#"
+                    <tr>
+                    <td>{path}</td><td>{size}</td><td>{trashed_at}</td>
+                    <td><button type="submit" onclick="trash_restore({idx})">Restore</button></td>
+                    <td><button type="submit" onclick="trash_purge({idx})">Purge</button></td>
+                    </tr>"#,
+                    idx = entry.collection_idx,
+                    path = entry.path,
+                    size = format_size(entry.size as u64),
+                    trashed_at = entry.trashed_at.format("%Y-%m-%d"),
+                )
+            })
+            .collect();
+        format!(
+            r#"
+            <h3>{count} file(s) in trash</h3>
+            <table border="0">
+            <tr><th>File</th><th>Size</th><th>Trashed</th><th></th><th></th></tr>
+            {rows}
+            </table>
+            "#,
+            count = entries.len(),
+            rows = rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::reclaim::{ReclaimEntry, ReclaimReport};
+
+    #[test]
+    fn test_reclaim_report() {
+        let entry = |collection_idx: i32, size: u64| ReclaimEntry {
+            collection_idx,
+            path: format!("/tmp/movies/movie_{}.mp4", collection_idx).into(),
+            show: format!("movie_{}", collection_idx).into(),
+            last_watched: Utc::now().into(),
+            size,
+        };
+        let report = ReclaimReport::from_entries(30, vec![entry(1, 1024), entry(2, 4096)]);
+        assert_eq!(report.total_size, 5120);
+        let indices: Vec<_> = report.entries.iter().map(|e| e.collection_idx).collect();
+        assert_eq!(indices, vec![2, 1]);
+        let html = report.get_html();
+        assert!(html.contains("5.0 KiB reclaimable in 2 file(s)"));
+        assert!(html.contains("reclaim_keep(2)"));
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{format_err, Error};
+use postgres_query::query;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+use tokio::fs;
+
+use crate::{
+    clock::Clock, datetime_wrapper::DateTimeWrapper, episode_numbering::resolve_file_stem,
+    pgpool::PgPool, utils::parse_episode_span,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct RenamePlan {
+    pub collection_idx: i32,
+    pub old_path: StackString,
+    pub new_path: StackString,
+    pub show: StackString,
+    pub season: i32,
+    pub episode: i32,
+}
+
+pub fn canonical_file_name(show: &str, season: i32, episode: i32, ext: &str) -> StackString {
+    format!("{}_s{:02}_ep{:02}.{}", show, season, episode, ext).into()
+}
+
+pub fn canonical_span_file_name(
+    show: &str,
+    season: i32,
+    first: i32,
+    last: i32,
+    ext: &str,
+) -> StackString {
+    format!(
+        "{}_s{:02}_ep{:02}_ep{:02}.{}",
+        show, season, first, last, ext
+    )
+    .into()
+}
+
+impl RenamePlan {
+    pub async fn new(pool: &PgPool, collection_idx: i32) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                SELECT path
+                FROM movie_collection
+                WHERE idx = $collection_idx AND NOT is_deleted
+            "#,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        let (old_path,): (StackString,) = query
+            .fetch_opt(&conn)
+            .await?
+            .ok_or_else(|| format_err!("No collection entry {}", collection_idx))?;
+        let path = Path::new(old_path.as_str());
+        let file_stem = path
+            .file_stem()
+            .ok_or_else(|| format_err!("No file stem {}", old_path))?
+            .to_string_lossy();
+        let ext = path
+            .extension()
+            .ok_or_else(|| format_err!("No extension {}", old_path))?
+            .to_string_lossy();
+        let (show, season, episode) = resolve_file_stem(pool, &file_stem).await?;
+        if season == -1 || episode == -1 {
+            return Err(format_err!(
+                "Cannot determine season/episode of {}",
+                old_path
+            ));
+        }
+
+        let query = query!(
+            r#"
+                SELECT show
+                FROM imdb_episodes
+                WHERE show = $show AND season = $season AND episode = $episode
+            "#,
+            show = show,
+            season = season,
+            episode = episode
+        );
+        let (show,): (StackString,) = query
+            .fetch_opt(&conn)
+            .await?
+            .ok_or_else(|| format_err!("No imdb episode {} s{} ep{}", show, season, episode))?;
+        // Keep the trailing _epNN of multi-episode files
+        let file_name = match parse_episode_span(&file_stem) {
+            Some((_, s, first, last)) if s == season && first == episode => {
+                canonical_span_file_name(&show, season, episode, last, &ext)
+            }
+            _ => canonical_file_name(&show, season, episode, &ext),
+        };
+        let new_path = path.with_file_name(file_name);
+
+        Ok(Self {
+            collection_idx,
+            new_path: new_path.to_string_lossy().into_owned().into(),
+            old_path,
+            show,
+            season,
+            episode,
+        })
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.old_path == self.new_path
+    }
+
+    pub async fn execute(&self, pool: &PgPool, clock: &dyn Clock) -> Result<(), Error> {
+        if self.is_noop() {
+            return Ok(());
+        }
+        if Path::new(self.new_path.as_str()).exists() {
+            return Err(format_err!("{} already exists", self.new_path));
+        }
+        let last_modified: DateTimeWrapper = clock.now().into();
+
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            r#"
+                UPDATE movie_collection
+                SET path=$new_path, last_modified=$last_modified
+                WHERE idx = $collection_idx
+            "#,
+            new_path = self.new_path,
+            last_modified = last_modified,
+            collection_idx = self.collection_idx
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            r#"
+                UPDATE movie_queue_entry
+                SET last_modified=$last_modified
+                WHERE collection_idx = $collection_idx
+            "#,
+            last_modified = last_modified,
+            collection_idx = self.collection_idx
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+        let query = query!(
+            r#"
+                UPDATE jellyfin_event
+                SET filename=$new_path, last_modified=$last_modified
+                WHERE filename = $old_path
+            "#,
+            new_path = self.new_path,
+            last_modified = last_modified,
+            old_path = self.old_path
+        );
+        tran.execute(query.sql(), query.parameters()).await?;
+
+        fs::rename(self.old_path.as_str(), self.new_path.as_str()).await?;
+        if let Err(e) = tran.commit().await {
+            fs::rename(self.new_path.as_str(), self.old_path.as_str()).await?;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    pub fn get_html(&self) -> StackString {
+        if self.is_noop() {
+            format!("{} already has the canonical name", self.old_path).into()
+        } else {
+            format!(
+                r#"{} &rarr; {}<br><button onclick="rename_file({}, false);">Rename</button>"#,
+                self.old_path, self.new_path, self.collection_idx
+            )
+            .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rename::{canonical_file_name, canonical_span_file_name};
+
+    #[test]
+    fn test_canonical_file_name() {
+        assert_eq!(
+            canonical_file_name("mr_robot", 1, 2, "mp4").as_str(),
+            "mr_robot_s01_ep02.mp4"
+        );
+        assert_eq!(
+            canonical_file_name("the_expanse", 2, 105, "mkv").as_str(),
+            "the_expanse_s02_ep105.mkv"
+        );
+        assert_eq!(
+            canonical_span_file_name("mr_robot", 1, 1, 2, "mkv").as_str(),
+            "mr_robot_s01_ep01_ep02.mkv"
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use anyhow::{format_err, Error};
+use itertools::Itertools;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, str::FromStr};
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, household_watched::HouseholdWatched,
+    media_info::parse_resolution_height, movie_queue::MovieQueueResult, pgpool::PgPool,
+};
+
+const COMPARE_OPS: [(&str, CompareOp); 7] = [
+    ("<=", CompareOp::Le),
+    (">=", CompareOp::Ge),
+    ("!=", CompareOp::Ne),
+    ("=", CompareOp::Eq),
+    ("<", CompareOp::Lt),
+    (">", CompareOp::Gt),
+    ("~", CompareOp::Contains),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterField {
+    Duration,
+    Height,
+    Size,
+    Codec,
+    VideoCodec,
+    AudioCodec,
+    Path,
+}
+
+impl FilterField {
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Duration | Self::Height | Self::Size)
+    }
+}
+
+impl fmt::Display for FilterField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Duration => "duration",
+                Self::Height => "height",
+                Self::Size => "size",
+                Self::Codec => "codec",
+                Self::VideoCodec => "video_codec",
+                Self::AudioCodec => "audio_codec",
+                Self::Path => "path",
+            }
+        )
+    }
+}
+
+impl FromStr for FilterField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "duration" => Ok(Self::Duration),
+            "height" => Ok(Self::Height),
+            "size" => Ok(Self::Size),
+            "codec" => Ok(Self::Codec),
+            "video_codec" => Ok(Self::VideoCodec),
+            "audio_codec" => Ok(Self::AudioCodec),
+            "path" => Ok(Self::Path),
+            _ => Err(format_err!("Unknown filter field {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = COMPARE_OPS
+            .iter()
+            .find(|(_, op)| op == self)
+            .map_or("", |(s, _)| s);
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Number(f64),
+    Text(StackString),
+}
+
+impl fmt::Display for FilterValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterClause {
+    Unwatched,
+    IsTv(bool),
+    Compare {
+        field: FilterField,
+        op: CompareOp,
+        value: FilterValue,
+    },
+}
+
+impl fmt::Display for FilterClause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unwatched => write!(f, "unwatched"),
+            Self::IsTv(true) => write!(f, "tv"),
+            Self::IsTv(false) => write!(f, "movie"),
+            Self::Compare { field, op, value } => write!(f, "{} {} {}", field, op, value),
+        }
+    }
+}
+
+impl FromStr for FilterClause {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unwatched" => return Ok(Self::Unwatched),
+            "tv" => return Ok(Self::IsTv(true)),
+            "movie" => return Ok(Self::IsTv(false)),
+            _ => (),
+        }
+        let start = s
+            .find(|c| "<>=!~".contains(c))
+            .ok_or_else(|| format_err!("Invalid filter clause {}", s))?;
+        let (op_str, op) = COMPARE_OPS
+            .iter()
+            .find(|(op_str, _)| s[start..].starts_with(op_str))
+            .ok_or_else(|| format_err!("Invalid operator in {}", s))?;
+        let field: FilterField = s[..start].trim().parse()?;
+        let value = s[start + op_str.len()..].trim();
+        if value.is_empty() {
+            return Err(format_err!("Missing value in {}", s));
+        }
+        let value = if field.is_numeric() {
+            if *op == CompareOp::Contains {
+                return Err(format_err!("Cannot use ~ with {}", field));
+            }
+            let number = match field {
+                FilterField::Height => parse_resolution_height(value).map(f64::from),
+                _ => value.parse().ok(),
+            };
+            FilterValue::Number(number.ok_or_else(|| format_err!("Invalid number {}", value))?)
+        } else {
+            if !matches!(op, CompareOp::Eq | CompareOp::Ne | CompareOp::Contains) {
+                return Err(format_err!("Cannot use {} with {}", op, field));
+            }
+            FilterValue::Text(value.into())
+        };
+        Ok(Self::Compare {
+            field,
+            op: *op,
+            value,
+        })
+    }
+}
+
+fn compare_number(op: CompareOp, left: f64, right: f64) -> bool {
+    match op {
+        CompareOp::Eq => (left - right).abs() < f64::EPSILON,
+        CompareOp::Ne => (left - right).abs() >= f64::EPSILON,
+        CompareOp::Lt => left < right,
+        CompareOp::Le => left <= right,
+        CompareOp::Gt => left > right,
+        CompareOp::Ge => left >= right,
+        CompareOp::Contains => false,
+    }
+}
+
+fn compare_text(op: CompareOp, left: &str, right: &str) -> bool {
+    let left = left.to_lowercase();
+    match op {
+        CompareOp::Eq => left == right,
+        CompareOp::Ne => left != right,
+        CompareOp::Contains => left.contains(right),
+        _ => false,
+    }
+}
+
+impl FilterClause {
+    fn matches(&self, entry: &MovieQueueResult, is_watched: &impl Fn(&str) -> bool) -> bool {
+        let (field, op, value) = match self {
+            Self::Unwatched => return !is_watched(&entry.path),
+            Self::IsTv(istv) => return entry.istv == *istv,
+            Self::Compare { field, op, value } => (*field, *op, value),
+        };
+        let info = &entry.media_info;
+        match value {
+            FilterValue::Number(right) => {
+                let left = match field {
+                    FilterField::Duration => info.duration.map(|d| d / 60.0),
+                    FilterField::Height => info
+                        .resolution
+                        .as_ref()
+                        .and_then(|r| parse_resolution_height(r))
+                        .map(f64::from),
+                    FilterField::Size => info.file_size.map(|s| s as f64 / 1e6),
+                    _ => None,
+                };
+                left.map_or(false, |left| compare_number(op, left, *right))
+            }
+            FilterValue::Text(right) => {
+                let candidates = match field {
+                    FilterField::Path => return compare_text(op, &entry.path, right),
+                    FilterField::Codec => vec![&info.video_codec, &info.audio_codec],
+                    FilterField::VideoCodec => vec![&info.video_codec],
+                    FilterField::AudioCodec => vec![&info.audio_codec],
+                    _ => Vec::new(),
+                };
+                let mut values = candidates.into_iter().flatten();
+                if op == CompareOp::Ne {
+                    values.all(|left| compare_text(op, left, right))
+                } else {
+                    values.any(|left| compare_text(op, left, right))
+                }
+            }
+        }
+    }
+}
+
+// A conjunction of clauses, e.g. "unwatched and path ~ scifi and duration < 45"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterExpr {
+    pub clauses: Vec<FilterClause>,
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.clauses.iter().join(" and "))
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        if s.is_empty() {
+            return Err(format_err!("Empty filter expression"));
+        }
+        let clauses: Result<Vec<_>, Error> = s.split(" and ").map(|c| c.trim().parse()).collect();
+        Ok(Self { clauses: clauses? })
+    }
+}
+
+impl FilterExpr {
+    pub fn needs_watched(&self) -> bool {
+        self.clauses.contains(&FilterClause::Unwatched)
+    }
+
+    pub fn matches(&self, entry: &MovieQueueResult, is_watched: impl Fn(&str) -> bool) -> bool {
+        self.clauses.iter().all(|c| c.matches(entry, &is_watched))
+    }
+
+    pub async fn filter_queue(
+        &self,
+        config: &Config,
+        pool: &PgPool,
+        queue: Vec<MovieQueueResult>,
+    ) -> Result<Vec<MovieQueueResult>, Error> {
+        let watched = if self.needs_watched() {
+            Some(HouseholdWatched::load(config, pool).await?)
+        } else {
+            None
+        };
+        let is_watched = |path: &str| watched.as_ref().map_or(false, |w| w.is_watched(path));
+        Ok(queue
+            .into_iter()
+            .filter(|entry| self.matches(entry, is_watched))
+            .collect())
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct SavedFilter {
+    pub id: i32,
+    pub name: StackString,
+    pub expression: StackString,
+    pub created_at: DateTimeWrapper,
+    pub last_modified: DateTimeWrapper,
+}
+
+impl SavedFilter {
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, name, expression, created_at, last_modified
+                FROM saved_filters
+                ORDER BY name
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_id(pool: &PgPool, id: i32) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, name, expression, created_at, last_modified
+                FROM saved_filters
+                WHERE id = $id
+            "#,
+            id = id
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    // The expression is normalized through the parser so invalid filters are never stored
+    pub async fn upsert(pool: &PgPool, name: &str, expression: &str) -> Result<Self, Error> {
+        let expression = expression.parse::<FilterExpr>()?.to_string();
+        let query = query!(
+            r#"
+                INSERT INTO saved_filters (name, expression, created_at, last_modified)
+                VALUES ($name, $expression, now(), now())
+                ON CONFLICT (name) DO UPDATE
+                SET expression=$expression, last_modified=now()
+                RETURNING id, name, expression, created_at, last_modified
+            "#,
+            name = name,
+            expression = expression
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete(pool: &PgPool, id: i32) -> Result<u64, Error> {
+        let query = query!("DELETE FROM saved_filters WHERE id = $id", id = id);
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    pub fn get_filter(&self) -> Result<FilterExpr, Error> {
+        self.expression.parse()
+    }
+
+    pub fn get_html(filters: &[Self]) -> StackString {
+        let rows = filters
+            .iter()
+            .map(|f| {
+                format!(
+                    r#"<tr><td><a href="javascript:updateMainArticle('/list/filters/{id}')">{name}</a></td><td>{expression}</td><td><button type="submit" onclick="delete_saved_filter({id});"> delete </button></td></tr>"#,
+                    id = f.id,
+                    name = f.name,
+                    expression = f.expression,
+                )
+            })
+            .join("");
+        format!(
+            r#"<button name="remcomout" id="remcomoutput"> &nbsp; </button><br>
+            <table border="0"><tr><th>Name</th><th>Filter</th><th></th></tr>{}
+            <tr><td><input type="text" id="saved_filter_name"/></td>
+            <td><input type="text" id="saved_filter_expression" size="60"/></td>
+            <td><button type="submit" onclick="save_filter();"> save </button></td></tr>
+            </table>
+            <small>e.g. unwatched and path ~ scifi and duration &lt; 45, movie and height &gt;= 2160</small>"#,
+            rows
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::{
+        media_info::MediaInfo,
+        movie_queue::MovieQueueResult,
+        saved_filters::{CompareOp, FilterClause, FilterExpr, FilterField, FilterValue},
+    };
+
+    fn entry(path: &str, istv: bool, duration: f64, resolution: &str) -> MovieQueueResult {
+        MovieQueueResult {
+            path: path.into(),
+            istv,
+            media_info: MediaInfo {
+                duration: Some(duration),
+                resolution: Some(resolution.into()),
+                video_codec: Some("hevc".into()),
+                audio_codec: Some("aac".into()),
+                ..MediaInfo::default()
+            },
+            ..MovieQueueResult::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_filter_expr() -> Result<(), Error> {
+        let expr: FilterExpr = "Unwatched AND path ~ scifi and duration<45".parse()?;
+        assert_eq!(
+            expr.clauses,
+            vec![
+                FilterClause::Unwatched,
+                FilterClause::Compare {
+                    field: FilterField::Path,
+                    op: CompareOp::Contains,
+                    value: FilterValue::Text("scifi".into()),
+                },
+                FilterClause::Compare {
+                    field: FilterField::Duration,
+                    op: CompareOp::Lt,
+                    value: FilterValue::Number(45.0),
+                },
+            ]
+        );
+        assert_eq!(
+            expr.to_string(),
+            "unwatched and path ~ scifi and duration < 45"
+        );
+        assert_eq!(expr.to_string().parse::<FilterExpr>()?, expr);
+
+        let expr: FilterExpr = "movie and height >= 2160p".parse()?;
+        assert_eq!(expr.to_string(), "movie and height >= 2160");
+
+        assert!("".parse::<FilterExpr>().is_err());
+        assert!("genre = scifi".parse::<FilterExpr>().is_err());
+        assert!("duration ~ 45".parse::<FilterExpr>().is_err());
+        assert!("codec > hevc".parse::<FilterExpr>().is_err());
+        assert!("height >= ".parse::<FilterExpr>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_matches() -> Result<(), Error> {
+        let short = entry("/movies/scifi/primer.mp4", false, 77.0 * 60.0, "3840x2160");
+        let long = entry("/movies/drama/heat.mp4", false, 170.0 * 60.0, "1920x1080");
+        let episode = entry(
+            "/television/firefly_s01_ep01.mp4",
+            true,
+            42.0 * 60.0,
+            "720p",
+        );
+
+        let expr: FilterExpr = "unwatched and tv and duration < 45".parse()?;
+        assert!(expr.needs_watched());
+        assert!(expr.matches(&episode, |_| false));
+        assert!(!expr.matches(&episode, |_| true));
+        assert!(!expr.matches(&short, |_| false));
+
+        let expr: FilterExpr = "movie and height >= 2160".parse()?;
+        assert!(!expr.needs_watched());
+        assert!(expr.matches(&short, |_| false));
+        assert!(!expr.matches(&long, |_| false));
+
+        let expr: FilterExpr = "path ~ drama and codec = aac".parse()?;
+        assert!(expr.matches(&long, |_| false));
+        assert!(!expr.matches(&short, |_| false));
+
+        let expr: FilterExpr = "video_codec != hevc".parse()?;
+        assert!(!expr.matches(&long, |_| false));
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, pgpool::PgPool, utils::walk_directory};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Schema)]
+pub struct ScanExclusions {
+    pub globs: Vec<StackString>,
+}
+
+impl ScanExclusions {
+    pub async fn get_db_globs(pool: &PgPool) -> Result<Vec<StackString>, Error> {
+        #[derive(FromSqlRow)]
+        struct ExclusionGlob {
+            glob: StackString,
+        }
+
+        let query = query!("SELECT glob FROM scan_exclusions ORDER BY glob");
+        let conn = pool.get().await?;
+        let globs: Vec<ExclusionGlob> = query.fetch(&conn).await?;
+        Ok(globs.into_iter().map(|g| g.glob).collect())
+    }
+
+    pub async fn set_db_globs(pool: &PgPool, globs: &[StackString]) -> Result<(), Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        tran.execute("DELETE FROM scan_exclusions", &[]).await?;
+        for glob in globs {
+            let query = query!(
+                r#"
+                    INSERT INTO scan_exclusions (glob, last_modified)
+                    VALUES ($glob, now())
+                    ON CONFLICT (glob) DO NOTHING
+                "#,
+                glob = glob
+            );
+            tran.execute(query.sql(), query.parameters()).await?;
+        }
+        tran.commit().await?;
+        Ok(())
+    }
+
+    pub async fn load(config: &Config, pool: &PgPool) -> Result<Self, Error> {
+        let mut globs = config.exclude_globs.clone();
+        for glob in Self::get_db_globs(pool).await? {
+            if !globs.contains(&glob) {
+                globs.push(glob);
+            }
+        }
+        Ok(Self { globs })
+    }
+
+    pub fn matching_rule(&self, path: &Path) -> Option<&str> {
+        let path_name = path.to_string_lossy();
+        let file_name = path
+            .file_name()
+            .map(OsStr::to_string_lossy)
+            .unwrap_or_default();
+        self.globs
+            .iter()
+            .find(|glob| {
+                if glob.contains('/') {
+                    glob_match(glob.as_bytes(), path_name.as_bytes())
+                } else {
+                    glob_match(glob.as_bytes(), file_name.as_bytes())
+                }
+            })
+            .map(StackString::as_str)
+    }
+
+    pub fn filter_paths(
+        &self,
+        paths: Vec<PathBuf>,
+    ) -> (Vec<PathBuf>, BTreeMap<StackString, Vec<PathBuf>>) {
+        let mut skipped: BTreeMap<StackString, Vec<PathBuf>> = BTreeMap::new();
+        let paths = paths
+            .into_iter()
+            .filter(|path| {
+                if let Some(rule) = self.matching_rule(path) {
+                    skipped.entry(rule.into()).or_default().push(path.clone());
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+        (paths, skipped)
+    }
+
+    pub fn skipped_report(
+        &self,
+        config: &Config,
+    ) -> Result<BTreeMap<StackString, Vec<PathBuf>>, Error> {
+        let mut paths = Vec::new();
+        for dir in config.movie_dirs.iter().filter(|d| d.exists()) {
+            paths.extend(walk_directory(dir, &config.suffixes)?);
+        }
+        let (_, skipped) = self.filter_paths(paths);
+        Ok(skipped)
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => {
+            if let Some((b'*', rest)) = rest.split_first() {
+                // **/ spans whole directories, so the rest has to start at a / boundary
+                match rest.strip_prefix(b"/") {
+                    Some(rest) => {
+                        glob_match(rest, text)
+                            || (0..text.len())
+                                .any(|i| text[i] == b'/' && glob_match(rest, &text[i + 1..]))
+                    }
+                    None => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+                }
+            } else {
+                for i in 0..=text.len() {
+                    if glob_match(rest, &text[i..]) {
+                        return true;
+                    }
+                    if text.get(i) == Some(&b'/') {
+                        break;
+                    }
+                }
+                false
+            }
+        }
+        Some((b'?', rest)) => match text.split_first() {
+            Some((c, text)) if *c != b'/' => glob_match(rest, text),
+            _ => false,
+        },
+        Some((c, rest)) => match text.split_first() {
+            Some((t, text)) if t == c => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::scan_exclusions::{glob_match, ScanExclusions};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.sample.*", b"movie.sample.mkv"));
+        assert!(!glob_match(b"*.sample.*", b"movie.mkv"));
+        assert!(glob_match(
+            b"**/extras/**",
+            b"/media/movies/extras/trailer.mp4"
+        ));
+        assert!(!glob_match(b"**/extras/**", b"/media/movies/movie.mp4"));
+        assert!(!glob_match(
+            b"**/extras/**",
+            b"/media/movies/bonus_extras/x.mp4"
+        ));
+        assert!(glob_match(b"**/extras/**", b"extras/trailer.mp4"));
+        assert!(glob_match(b"/media/*/s??/*.mkv", b"/media/show/s01/a.mkv"));
+        assert!(!glob_match(b"/media/*.mkv", b"/media/show/a.mkv"));
+    }
+
+    #[test]
+    fn test_filter_paths() {
+        let exclusions = ScanExclusions {
+            globs: vec!["**/extras/**".into(), "*.sample.*".into()],
+        };
+        let paths = vec![
+            Path::new("/media/movies/extras/trailer.mp4").to_path_buf(),
+            Path::new("/media/movies/movie.sample.mkv").to_path_buf(),
+            Path::new("/media/movies/movie.mkv").to_path_buf(),
+        ];
+        let (paths, skipped) = exclusions.filter_paths(paths);
+        assert_eq!(
+            paths,
+            vec![Path::new("/media/movies/movie.mkv").to_path_buf()]
+        );
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped.values().all(|v| v.len() == 1));
+    }
+}
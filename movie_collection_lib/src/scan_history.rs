@@ -0,0 +1,152 @@
+use anyhow::{format_err, Error};
+use lazy_static::lazy_static;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use tokio::sync::Mutex;
+
+use crate::{
+    datetime_wrapper::DateTimeWrapper, movie_collection::MovieCollection, pgpool::PgPool,
+    utils::escape_html,
+};
+
+pub const SCAN_HISTORY_LIMIT: i64 = 20;
+
+lazy_static! {
+    static ref SCAN_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct ScanHistory {
+    pub id: i32,
+    pub trigger: StackString,
+    pub status: StackString,
+    pub started_at: DateTimeWrapper,
+    pub finished_at: Option<DateTimeWrapper>,
+    pub added: Option<i64>,
+    pub error: Option<StackString>,
+}
+
+async fn collection_count(pool: &PgPool) -> Result<i64, Error> {
+    let query = query!("SELECT count(*) FROM movie_collection WHERE NOT is_deleted");
+    let conn = pool.get().await?;
+    let (count,): (i64,) = query.fetch_one(&conn).await?;
+    Ok(count)
+}
+
+impl ScanHistory {
+    pub fn is_running() -> bool {
+        SCAN_LOCK.try_lock().is_err()
+    }
+
+    pub async fn run_scan(mc: &MovieCollection, trigger: &str) -> Result<Self, Error> {
+        let pool = &mc.pool;
+        let _guard = SCAN_LOCK
+            .try_lock()
+            .map_err(|_| format_err!("Collection scan already running"))?;
+
+        let query = query!(
+            r#"
+                INSERT INTO scan_history (trigger, status)
+                VALUES ($trigger, 'running')
+                RETURNING id
+            "#,
+            trigger = trigger
+        );
+        let conn = pool.get().await?;
+        let (id,): (i32,) = query.fetch_one(&conn).await?;
+
+        let result = async {
+            let before = collection_count(pool).await?;
+            mc.make_collection().await?;
+            mc.fix_collection_show_id().await?;
+            let after = collection_count(pool).await?;
+            Ok::<_, Error>(after - before)
+        }
+        .await;
+
+        let (status, added, error) = match &result {
+            Ok(added) => ("success", Some(*added), None),
+            Err(e) => ("failed", None, Some(e.to_string())),
+        };
+        let query = query!(
+            r#"
+                UPDATE scan_history
+                SET status=$status, finished_at=now(), added=$added, error=$error
+                WHERE id = $id
+                RETURNING *
+            "#,
+            id = id,
+            status = status,
+            added = added,
+            error = error
+        );
+        let history = query.fetch_one(&conn).await?;
+        result.map(|_| history)
+    }
+
+    pub async fn get_recent(pool: &PgPool, limit: i64) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM scan_history ORDER BY started_at DESC LIMIT $limit",
+            limit = limit
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    fn get_html(&self) -> StackString {
+        format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            self.started_at.format("%Y-%m-%d %H:%M:%S"),
+            self.finished_at
+                .map_or_else(String::new, |f| f.format("%H:%M:%S").to_string()),
+            self.trigger,
+            self.status,
+            self.added.map_or_else(String::new, |a| a.to_string()),
+            escape_html(self.error.as_deref().unwrap_or("")),
+        )
+        .into()
+    }
+
+    pub fn get_html_table(entries: &[Self], running: bool) -> StackString {
+        let rows: Vec<_> = entries.iter().map(Self::get_html).collect();
+        format!(
+            r#"
+            <h3>Collection scan {}</h3>
+            <button type="submit" onclick="scan_trigger()">Scan Now</button>
+            <table border="0">
+            <tr><th>Started</th><th>Finished</th><th>Trigger</th><th>Status</th><th>Added</th><th>Error</th></tr>
+            {}
+            </table>
+            "#,
+            if running { "running" } else { "idle" },
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::scan_history::ScanHistory;
+
+    #[test]
+    fn test_scan_history_html() {
+        let entry = ScanHistory {
+            id: 1,
+            trigger: "scheduled".into(),
+            status: "failed".into(),
+            started_at: Utc.ymd(2021, 3, 4).and_hms(5, 6, 7).into(),
+            finished_at: Some(Utc.ymd(2021, 3, 4).and_hms(5, 16, 7).into()),
+            added: None,
+            error: Some("No such file <dir>".into()),
+        };
+        let html = ScanHistory::get_html_table(&[entry], false);
+        assert!(html.contains("Collection scan idle"));
+        assert!(html.contains("<td>2021-03-04 05:06:07</td><td>05:16:07</td>"));
+        assert!(html.contains("<td>No such file &lt;dir&gt;</td>"));
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{format_err, Error};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, str::FromStr};
+use tokio_postgres::types::{FromSql, Type};
+
+use crate::pgpool::PgPool;
+
+pub const DEFAULT_SEARCH_LIMIT: i64 = 50;
+pub const MAX_SEARCH_LIMIT: i64 = 500;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Schema)]
+pub enum SearchResultType {
+    #[serde(rename = "collection")]
+    Collection,
+    #[serde(rename = "queue")]
+    Queue,
+    #[serde(rename = "show")]
+    Show,
+    #[serde(rename = "episode")]
+    Episode,
+    #[serde(rename = "watchlist")]
+    Watchlist,
+    #[serde(rename = "plex_event")]
+    PlexEvent,
+}
+
+impl SearchResultType {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Collection => "collection",
+            Self::Queue => "queue",
+            Self::Show => "show",
+            Self::Episode => "episode",
+            Self::Watchlist => "watchlist",
+            Self::PlexEvent => "plex_event",
+        }
+    }
+}
+
+impl fmt::Display for SearchResultType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl FromStr for SearchResultType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "collection" => Ok(Self::Collection),
+            "queue" => Ok(Self::Queue),
+            "show" => Ok(Self::Show),
+            "episode" => Ok(Self::Episode),
+            "watchlist" => Ok(Self::Watchlist),
+            "plex_event" => Ok(Self::PlexEvent),
+            _ => Err(format_err!("Is not SearchResultType")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for SearchResultType {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct SearchResult {
+    pub result_type: SearchResultType,
+    pub title: StackString,
+    pub detail: StackString,
+    pub show: Option<StackString>,
+    pub link: Option<StackString>,
+    pub collection_idx: Option<i32>,
+    pub rank: f32,
+}
+
+impl SearchResult {
+    fn get_html(&self) -> StackString {
+        let title = match (
+            self.result_type,
+            self.collection_idx,
+            &self.show,
+            &self.link,
+        ) {
+            (SearchResultType::Collection, Some(idx), _, _)
+            | (SearchResultType::Queue, Some(idx), _, _) => format!(
+                r#"<a href="javascript:updateMainArticle('/list/play/{}')">{}</a>"#,
+                idx, self.title
+            ),
+            (SearchResultType::Show, _, Some(show), _)
+            | (SearchResultType::Episode, _, Some(show), _) => format!(
+                r#"<a href="javascript:updateMainArticle('/list/queue/{}')">{}</a>"#,
+                show, self.title
+            ),
+            (SearchResultType::Watchlist, _, _, Some(link)) => format!(
+                r#"<a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a>"#,
+                link, self.title
+            ),
+            _ => self.title.to_string(),
+        };
+        format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            self.result_type, title, self.detail
+        )
+        .into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct SearchResults {
+    pub query: StackString,
+    pub results: Vec<SearchResult>,
+}
+
+impl SearchResults {
+    pub async fn search(
+        pool: &PgPool,
+        search: &str,
+        limit: i64,
+        hidden_accounts: &[StackString],
+    ) -> Result<Self, Error> {
+        let search = search.trim();
+        let limit = limit.clamp(1, MAX_SEARCH_LIMIT);
+        if search.is_empty() {
+            return Ok(Self {
+                query: search.into(),
+                results: Vec::new(),
+            });
+        }
+        let hidden_accounts = hidden_accounts.to_vec();
+        let query = query!(
+            r#"
+                WITH q AS (SELECT websearch_to_tsquery('simple', $search) AS query)
+                SELECT * FROM (
+                    SELECT CASE WHEN b.idx IS NULL THEN 'collection' ELSE 'queue' END
+                                AS result_type,
+                           coalesce(c.title, a.show) AS title, a.path AS detail, a.show,
+                           c.link, a.idx AS collection_idx,
+                           ts_rank(a.search_vector, q.query) AS rank
+                    FROM q, movie_collection a
+                    LEFT JOIN movie_queue b ON b.collection_idx = a.idx
+                    LEFT JOIN imdb_ratings c ON a.show_id = c.index
+                    WHERE a.search_vector @@ q.query AND NOT a.is_deleted
+                    UNION ALL
+                    SELECT 'show', coalesce(a.title, a.show), a.link, a.show, a.link, NULL,
+                           ts_rank(a.search_vector, q.query)
+                    FROM q, imdb_ratings a
+                    WHERE a.search_vector @@ q.query
+                    UNION ALL
+                    SELECT 'episode', coalesce(a.eptitle, ''),
+                           format('%s s%s ep%s', b.title, a.season, a.episode), a.show, b.link,
+                           NULL, ts_rank(a.search_vector, q.query)
+                    FROM q, imdb_episodes a
+                    JOIN imdb_ratings b ON a.show = b.show
+                    WHERE a.search_vector @@ q.query
+                    UNION ALL
+                    SELECT 'watchlist', coalesce(a.title, a.link), a.link, NULL, a.link, NULL,
+                           ts_rank(a.search_vector, q.query)
+                    FROM q, trakt_watchlist a
+                    WHERE a.search_vector @@ q.query
+                    UNION ALL
+                    SELECT 'plex_event',
+                           concat_ws(' - ', a.grandparent_title, a.parent_title, a.title),
+                           a.event || ' ' || to_char(a.created_at, 'YYYY-MM-DD HH24:MI'),
+                           NULL, NULL, NULL, ts_rank(a.search_vector, q.query)
+                    FROM q, plex_event a
+                    WHERE a.search_vector @@ q.query
+                      AND (a.account IS NULL OR a.account != ALL($hidden_accounts))
+                ) r
+                ORDER BY rank DESC, title
+                LIMIT $limit
+            "#,
+            search = search,
+            limit = limit,
+            hidden_accounts = hidden_accounts
+        );
+        let conn = pool.get().await?;
+        let results = query.fetch(&conn).await?;
+        Ok(Self {
+            query: search.into(),
+            results,
+        })
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let rows: Vec<_> = self.results.iter().map(SearchResult::get_html).collect();
+        format!(
+            r#"<h4>Results for "{}"</h4><table border="0"><tr><th>Type</th><th>Title</th><th>Detail</th></tr>{}</table>"#,
+            self.query,
+            rows.join("")
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::search::{SearchResult, SearchResultType, SearchResults};
+
+    #[test]
+    fn test_search_result_type() -> Result<(), Error> {
+        for t in &[
+            SearchResultType::Collection,
+            SearchResultType::Queue,
+            SearchResultType::Show,
+            SearchResultType::Episode,
+            SearchResultType::Watchlist,
+            SearchResultType::PlexEvent,
+        ] {
+            assert_eq!(t.to_str().parse::<SearchResultType>()?, *t);
+            assert_eq!(serde_json::to_string(t)?, format!(r#""{}""#, t));
+        }
+        assert!("movie".parse::<SearchResultType>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_results_html() {
+        let results = SearchResults {
+            query: "robot".into(),
+            results: vec![SearchResult {
+                result_type: SearchResultType::Queue,
+                title: "Mr. Robot".into(),
+                detail: "/tmp/television/mr_robot_s01_ep01.mp4".into(),
+                show: Some("mr_robot".into()),
+                link: Some("tt4158110".into()),
+                collection_idx: Some(12),
+                rank: 0.5,
+            }],
+        };
+        let html = results.get_html();
+        assert!(html.contains("/list/play/12"));
+        assert!(html.contains("<td>queue</td>"));
+    }
+}
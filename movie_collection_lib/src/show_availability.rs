@@ -0,0 +1,294 @@
+use anyhow::Error;
+use chrono::{Duration, Utc};
+use postgres_query::{query, FromSqlRow};
+use reqwest::{Client, StatusCode, Url};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use stack_string::StackString;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, pgpool::PgPool,
+    trakt_utils::get_watchlist_shows_db_map, tv_show_source::TvShowSource,
+};
+
+pub const AVAILABILITY_CACHE_DAYS: i64 = 7;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct AvailabilityProvider {
+    pub provider: StackString,
+    pub name: StackString,
+    pub monetization: StackString,
+    pub url: Option<StackString>,
+}
+
+impl AvailabilityProvider {
+    pub fn source(&self) -> Option<TvShowSource> {
+        TvShowSource::from_provider(&self.provider)
+    }
+
+    fn get_html(&self) -> StackString {
+        let label = if self.monetization == "subscription" || self.monetization == "free" {
+            self.name.to_string()
+        } else {
+            format!("{} ({})", self.name, self.monetization)
+        };
+        match &self.url {
+            Some(url) => format!(r#"<a href="{}" target="_blank">{}</a>"#, url, label).into(),
+            None => label.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct ShowAvailability {
+    pub link: StackString,
+    pub region: StackString,
+    pub providers: Vec<AvailabilityProvider>,
+    pub last_modified: DateTimeWrapper,
+}
+
+#[derive(FromSqlRow)]
+struct ShowAvailabilityRow {
+    link: StackString,
+    region: StackString,
+    providers: Value,
+    last_modified: DateTimeWrapper,
+}
+
+impl ShowAvailabilityRow {
+    fn into_availability(self) -> Result<ShowAvailability, Error> {
+        Ok(ShowAvailability {
+            link: self.link,
+            region: self.region,
+            providers: serde_json::from_value(self.providers)?,
+            last_modified: self.last_modified,
+        })
+    }
+}
+
+impl ShowAvailability {
+    pub fn is_stale(&self) -> bool {
+        Utc::now() - *self.last_modified > Duration::days(AVAILABILITY_CACHE_DAYS)
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let mut seen = HashSet::new();
+        let mut providers: Vec<_> = self.providers.iter().collect();
+        providers.sort_by_key(|p| p.monetization != "subscription");
+        providers
+            .into_iter()
+            .filter(|p| seen.insert(p.provider.clone()))
+            .map(AvailabilityProvider::get_html)
+            .collect::<Vec<_>>()
+            .join(" ")
+            .into()
+    }
+
+    pub async fn get_by_link(
+        pool: &PgPool,
+        link: &str,
+        region: &str,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT link, region, providers, last_modified
+                FROM show_availability
+                WHERE link = $link AND region = $region
+            "#,
+            link = link,
+            region = region
+        );
+        let conn = pool.get().await?;
+        let row: Option<ShowAvailabilityRow> = query.fetch_opt(&conn).await?;
+        row.map(ShowAvailabilityRow::into_availability).transpose()
+    }
+
+    pub async fn get_map(pool: &PgPool, region: &str) -> Result<HashMap<StackString, Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT link, region, providers, last_modified
+                FROM show_availability
+                WHERE region = $region
+            "#,
+            region = region
+        );
+        let conn = pool.get().await?;
+        let rows: Vec<ShowAvailabilityRow> = query.fetch(&conn).await?;
+        rows.into_iter()
+            .map(|row| {
+                let availability = row.into_availability()?;
+                Ok((availability.link.clone(), availability))
+            })
+            .collect()
+    }
+
+    pub async fn upsert(&self, pool: &PgPool) -> Result<(), Error> {
+        let providers = serde_json::to_value(&self.providers)?;
+        let query = query!(
+            r#"
+                INSERT INTO show_availability (link, region, providers, last_modified)
+                VALUES ($link, $region, $providers, $last_modified)
+                ON CONFLICT (link, region) DO UPDATE
+                SET providers=$providers, last_modified=$last_modified
+            "#,
+            link = self.link,
+            region = self.region,
+            providers = providers,
+            last_modified = self.last_modified
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamingService {
+    id: StackString,
+    name: StackString,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamingOption {
+    service: StreamingService,
+    #[serde(rename = "type")]
+    monetization: StackString,
+    link: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamingShow {
+    #[serde(rename = "streamingOptions", default)]
+    streaming_options: HashMap<StackString, Vec<StreamingOption>>,
+}
+
+impl StreamingShow {
+    fn providers(mut self, region: &str) -> Vec<AvailabilityProvider> {
+        self.streaming_options
+            .remove(region)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|o| AvailabilityProvider {
+                provider: o.service.id,
+                name: o.service.name,
+                monetization: o.monetization,
+                url: o.link,
+            })
+            .collect()
+    }
+}
+
+pub struct AvailabilityConnection {
+    client: Client,
+    endpoint: StackString,
+    api_key: StackString,
+    region: StackString,
+}
+
+impl AvailabilityConnection {
+    pub fn new(config: &Config) -> Option<Self> {
+        let api_key = config.availability_api_key.clone()?;
+        Some(Self {
+            client: Client::new(),
+            endpoint: config.availability_endpoint.clone(),
+            api_key,
+            region: config.availability_region.clone(),
+        })
+    }
+
+    async fn fetch(&self, link: &str) -> Result<ShowAvailability, Error> {
+        let url = format!("{}/shows/{}", self.endpoint, link);
+        let url = Url::parse_with_params(&url, &[("country", self.region.as_str())])?;
+        let resp = self
+            .client
+            .get(url)
+            .header("X-RapidAPI-Key", self.api_key.as_str())
+            .send()
+            .await?;
+        let providers = if resp.status() == StatusCode::NOT_FOUND {
+            Vec::new()
+        } else {
+            let show: StreamingShow = resp.error_for_status()?.json().await?;
+            show.providers(&self.region)
+        };
+        Ok(ShowAvailability {
+            link: link.into(),
+            region: self.region.clone(),
+            providers,
+            last_modified: Utc::now().into(),
+        })
+    }
+
+    pub async fn get_availability(
+        &self,
+        pool: &PgPool,
+        link: &str,
+    ) -> Result<ShowAvailability, Error> {
+        if let Some(availability) = ShowAvailability::get_by_link(pool, link, &self.region).await? {
+            if !availability.is_stale() {
+                return Ok(availability);
+            }
+        }
+        let availability = self.fetch(link).await?;
+        availability.upsert(pool).await?;
+        Ok(availability)
+    }
+
+    pub async fn refresh_watchlist(&self, pool: &PgPool) -> Result<usize, Error> {
+        let cached = ShowAvailability::get_map(pool, &self.region).await?;
+        let watchlist = get_watchlist_shows_db_map(pool).await?;
+        let mut updated = 0;
+        for link in watchlist.keys() {
+            if cached.get(link).map_or(false, |a| !a.is_stale()) {
+                continue;
+            }
+            self.fetch(link).await?.upsert(pool).await?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        show_availability::{ShowAvailability, StreamingShow},
+        tv_show_source::TvShowSource,
+    };
+
+    #[test]
+    fn test_streaming_show_providers() -> Result<(), Error> {
+        let body = r#"{"streamingOptions": {
+            "us": [
+                {"service": {"id": "prime", "name": "Prime Video"}, "type": "buy",
+                 "link": "https://www.amazon.com/gp/video/detail/B0"},
+                {"service": {"id": "hbo", "name": "Max"}, "type": "subscription",
+                 "link": "https://play.max.com/show/1"},
+                {"service": {"id": "prime", "name": "Prime Video"}, "type": "rent"}
+            ],
+            "ca": [{"service": {"id": "crave", "name": "Crave"}, "type": "subscription"}]
+        }}"#;
+        let show: StreamingShow = serde_json::from_str(body)?;
+        let providers = show.providers("us");
+        assert_eq!(providers.len(), 3);
+        assert_eq!(providers[1].source(), Some(TvShowSource::Max));
+
+        let availability = ShowAvailability {
+            link: "tt4158110".into(),
+            region: "us".into(),
+            providers,
+            last_modified: (Utc::now() - Duration::days(30)).into(),
+        };
+        assert!(availability.is_stale());
+        assert_eq!(
+            availability.get_html().as_str(),
+            r#"<a href="https://play.max.com/show/1" target="_blank">Max</a> <a href="https://www.amazon.com/gp/video/detail/B0" target="_blank">Prime Video (buy)</a>"#
+        );
+        Ok(())
+    }
+}
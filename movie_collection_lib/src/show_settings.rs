@@ -0,0 +1,330 @@
+use anyhow::{format_err, Error};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config, episode_numbering::EpisodeNumbering, imdb_ratings::ImdbRatings,
+    movie_queue::MovieQueueResult, pgpool::PgPool, transcode_service::TranscodeOptions,
+    tv_show_source::TvShowSource, utils::parse_file_stem,
+};
+
+// Accepted values of `ordering`, unset keeps the queue order
+pub const EPISODE_ORDERINGS: [&str; 2] = ["aired", "reverse"];
+
+#[derive(FromSqlRow, Default, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct ShowSettings {
+    pub link: StackString,
+    pub alias: Option<StackString>,
+    pub destination_dir: Option<StackString>,
+    pub ordering: Option<StackString>,
+    pub auto_queue: bool,
+    pub dropped: bool,
+    pub video_codec: Option<StackString>,
+    pub max_height: Option<i32>,
+    pub audio_handling: Option<StackString>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Schema)]
+pub struct ShowSettingsPatch {
+    pub alias: Option<StackString>,
+    pub destination_dir: Option<StackString>,
+    pub ordering: Option<StackString>,
+    pub auto_queue: Option<bool>,
+    pub dropped: Option<bool>,
+    pub source: Option<TvShowSource>,
+    pub numbering: Option<EpisodeNumbering>,
+    pub video_codec: Option<StackString>,
+    pub max_height: Option<i32>,
+    pub audio_handling: Option<StackString>,
+}
+
+fn empty_to_none(s: StackString) -> Option<StackString> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+impl ShowSettings {
+    pub async fn get_settings(pool: &PgPool, link: &str) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                SELECT link, alias, destination_dir, ordering, auto_queue, dropped, video_codec,
+                       max_height, audio_handling
+                FROM show_settings
+                WHERE link = $link
+            "#,
+            link = link
+        );
+        let conn = pool.get().await?;
+        let settings: Option<Self> = query.fetch_opt(&conn).await?;
+        Ok(settings.unwrap_or_else(|| Self {
+            link: link.into(),
+            ..Self::default()
+        }))
+    }
+
+    // Files are matched to a show by the show name (or alias) parsed from the file stem
+    pub async fn get_settings_for_path(
+        pool: &PgPool,
+        path: impl AsRef<Path>,
+    ) -> Result<Option<Self>, Error> {
+        let file_stem = match path.as_ref().file_stem() {
+            Some(f) => f.to_string_lossy(),
+            None => return Ok(None),
+        };
+        let (show, _, _) = parse_file_stem(&file_stem);
+        Self::get_settings_for_show(pool, &show).await
+    }
+
+    pub async fn get_settings_for_show(pool: &PgPool, show: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.link, a.alias, a.destination_dir, a.ordering, a.auto_queue, a.dropped,
+                       a.video_codec, a.max_height, a.audio_handling
+                FROM show_settings a
+                JOIN imdb_ratings b ON a.link = b.link
+                WHERE b.show = $show OR a.alias = $show
+                LIMIT 1
+            "#,
+            show = show
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_dropped_links(pool: &PgPool) -> Result<HashSet<StackString>, Error> {
+        let query = query!("SELECT link FROM show_settings WHERE dropped");
+        let conn = pool.get().await?;
+        let links: Vec<(StackString,)> = query.fetch(&conn).await?;
+        Ok(links.into_iter().map(|(link,)| link).collect())
+    }
+
+    pub async fn upsert_settings(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO show_settings
+                    (link, alias, destination_dir, ordering, auto_queue, dropped, video_codec,
+                     max_height, audio_handling, last_modified)
+                VALUES
+                    ($link, $alias, $destination_dir, $ordering, $auto_queue, $dropped,
+                     $video_codec, $max_height, $audio_handling, now())
+                ON CONFLICT (link) DO UPDATE
+                SET alias=$alias, destination_dir=$destination_dir, ordering=$ordering,
+                    auto_queue=$auto_queue, dropped=$dropped, video_codec=$video_codec,
+                    max_height=$max_height, audio_handling=$audio_handling, last_modified=now()
+            "#,
+            link = self.link,
+            alias = self.alias,
+            destination_dir = self.destination_dir,
+            ordering = self.ordering,
+            auto_queue = self.auto_queue,
+            dropped = self.dropped,
+            video_codec = self.video_codec,
+            max_height = self.max_height,
+            audio_handling = self.audio_handling
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub fn apply_patch(&mut self, patch: ShowSettingsPatch) {
+        if let Some(alias) = patch.alias {
+            self.alias = empty_to_none(alias);
+        }
+        if let Some(destination_dir) = patch.destination_dir {
+            self.destination_dir = empty_to_none(destination_dir);
+        }
+        if let Some(ordering) = patch.ordering {
+            self.ordering = empty_to_none(ordering);
+        }
+        if let Some(auto_queue) = patch.auto_queue {
+            self.auto_queue = auto_queue;
+        }
+        if let Some(dropped) = patch.dropped {
+            self.dropped = dropped;
+        }
+        if let Some(video_codec) = patch.video_codec {
+            self.video_codec = empty_to_none(video_codec);
+        }
+        if let Some(max_height) = patch.max_height {
+            self.max_height = if max_height > 0 {
+                Some(max_height)
+            } else {
+                None
+            };
+        }
+        if let Some(audio_handling) = patch.audio_handling {
+            self.audio_handling = empty_to_none(audio_handling);
+        }
+    }
+
+    // Whether `sort_episodes` changes the queue order
+    pub fn reorders_episodes(&self) -> bool {
+        matches!(
+            self.ordering.as_ref().map(StackString::as_str),
+            Some("aired") | Some("reverse")
+        )
+    }
+
+    // "aired" lists a show's queued episodes by season and episode, "reverse" puts the
+    // latest first
+    pub fn sort_episodes(&self, queue: &mut [MovieQueueResult]) {
+        let key = |q: &MovieQueueResult| (q.season.unwrap_or(-1), q.episode.unwrap_or(-1));
+        match self.ordering.as_ref().map(StackString::as_str) {
+            Some("aired") => queue.sort_by_key(key),
+            Some("reverse") => queue.sort_by(|x, y| key(y).cmp(&key(x))),
+            _ => {}
+        }
+    }
+
+    pub fn transcode_options(&self) -> TranscodeOptions {
+        TranscodeOptions {
+            video_codec: self.video_codec.clone(),
+            max_height: self.max_height,
+            audio_handling: self.audio_handling.clone(),
+        }
+    }
+
+    // Relative destinations live under the television (or movies) directory,
+    // episodes are placed in a season subdirectory
+    pub fn output_directory(&self, config: &Config, file_stem: &str) -> Option<PathBuf> {
+        let destination_dir = Path::new(self.destination_dir.as_ref()?.as_str());
+        let (_, season, episode) = parse_file_stem(file_stem);
+        let is_tv = season >= 0 && episode >= 0;
+        let d = if destination_dir.is_absolute() {
+            destination_dir.to_path_buf()
+        } else {
+            let base = if is_tv { "television" } else { "movies" };
+            config
+                .preferred_dir
+                .join("Documents")
+                .join(base)
+                .join(destination_dir)
+        };
+        if is_tv {
+            Some(d.join(format!("season{}", season)))
+        } else {
+            Some(d)
+        }
+    }
+
+    pub async fn patch_settings(
+        pool: &PgPool,
+        link: &str,
+        mut patch: ShowSettingsPatch,
+    ) -> Result<(ImdbRatings, Self), Error> {
+        if let Some(ordering) = &patch.ordering {
+            if !ordering.is_empty() && !EPISODE_ORDERINGS.contains(&ordering.as_str()) {
+                return Err(format_err!("Invalid ordering {}", ordering));
+            }
+        }
+        let mut imdb = ImdbRatings::get_show_by_link(link, pool)
+            .await?
+            .ok_or_else(|| format_err!("No show found for {}", link))?;
+        if let Some(source) = patch.source.take() {
+            imdb.source = if source == TvShowSource::All {
+                None
+            } else {
+                Some(source)
+            };
+            imdb.update_show(pool).await?;
+        }
+        // Episode numbering lives on imdb_ratings where file resolution reads it
+        if let Some(numbering) = patch.numbering.take() {
+            numbering.set_for_link(pool, &imdb.link).await?;
+        }
+        let mut settings = Self::get_settings(pool, &imdb.link).await?;
+        settings.apply_patch(patch);
+        settings.upsert_settings(pool).await?;
+        Ok((imdb, settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        movie_queue::MovieQueueResult,
+        show_settings::{ShowSettings, ShowSettingsPatch},
+    };
+
+    #[test]
+    fn test_apply_patch() {
+        let mut settings = ShowSettings {
+            link: "tt0000000".into(),
+            alias: Some("old".into()),
+            ..ShowSettings::default()
+        };
+        settings.apply_patch(ShowSettingsPatch {
+            alias: Some("".into()),
+            destination_dir: Some("/media/television".into()),
+            dropped: Some(true),
+            ..ShowSettingsPatch::default()
+        });
+        assert_eq!(settings.alias, None);
+        assert_eq!(
+            settings.destination_dir.as_ref().map(|s| s.as_str()),
+            Some("/media/television")
+        );
+        assert!(settings.dropped);
+        assert!(!settings.auto_queue);
+
+        settings.apply_patch(ShowSettingsPatch {
+            video_codec: Some("x265".into()),
+            max_height: Some(720),
+            audio_handling: Some("copy".into()),
+            ..ShowSettingsPatch::default()
+        });
+        let args = settings.transcode_options().handbrake_args();
+        let args: Vec<_> = args.iter().map(|s| s.as_str()).collect();
+        assert_eq!(
+            args,
+            vec![
+                "--encoder",
+                "x265",
+                "--maxHeight",
+                "720",
+                "--aencoder",
+                "copy"
+            ]
+        );
+        settings.apply_patch(ShowSettingsPatch {
+            max_height: Some(0),
+            ..ShowSettingsPatch::default()
+        });
+        assert_eq!(settings.max_height, None);
+    }
+
+    #[test]
+    fn test_sort_episodes() {
+        let entry = |season, episode| MovieQueueResult {
+            season: Some(season),
+            episode: Some(episode),
+            ..MovieQueueResult::default()
+        };
+        let mut queue = vec![entry(2, 1), entry(1, 2), entry(1, 1)];
+        let mut settings = ShowSettings::default();
+        settings.sort_episodes(&mut queue);
+        assert_eq!(queue[0].season, Some(2));
+
+        settings.ordering = Some("aired".into());
+        settings.sort_episodes(&mut queue);
+        let order: Vec<_> = queue.iter().map(|q| (q.season, q.episode)).collect();
+        assert_eq!(
+            order,
+            vec![(Some(1), Some(1)), (Some(1), Some(2)), (Some(2), Some(1))]
+        );
+
+        settings.ordering = Some("reverse".into());
+        settings.sort_episodes(&mut queue);
+        assert_eq!(queue[0].season, Some(2));
+        assert_eq!(queue[2].episode, Some(1));
+    }
+}
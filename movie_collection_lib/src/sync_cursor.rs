@@ -0,0 +1,93 @@
+use anyhow::{format_err, Error};
+use chrono::{DateTime, SecondsFormat, Utc};
+use stack_string::StackString;
+
+pub const DEFAULT_SYNC_PAGE_SIZE: usize = 1000;
+pub const MAX_SYNC_PAGE_SIZE: usize = 10_000;
+
+// Keyset position for sync queries ordered by (last_modified, id), handed to clients as
+// an opaque string so the encoding can change without breaking them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncCursor {
+    pub last_modified: DateTime<Utc>,
+    pub id: i32,
+}
+
+impl SyncCursor {
+    // Position just before every row modified at or after `timestamp`
+    pub fn start(timestamp: DateTime<Utc>) -> Self {
+        Self {
+            last_modified: timestamp,
+            id: i32::MIN,
+        }
+    }
+
+    pub fn encode(&self) -> StackString {
+        let raw = format!(
+            "{}|{}",
+            self.last_modified
+                .to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.id
+        );
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD).into()
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, Error> {
+        let raw = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)?;
+        let raw = String::from_utf8(raw)?;
+        let (last_modified, id) = raw
+            .split_once('|')
+            .ok_or_else(|| format_err!("Invalid cursor {}", cursor))?;
+        Ok(Self {
+            last_modified: DateTime::parse_from_rfc3339(last_modified)?.with_timezone(&Utc),
+            id: id.parse()?,
+        })
+    }
+
+    // A full page means there may be more rows after the last one
+    pub fn next_page<T>(rows: &[T], limit: usize, cursor: impl Fn(&T) -> Self) -> Option<Self> {
+        if rows.len() < limit {
+            None
+        } else {
+            rows.last().map(cursor)
+        }
+    }
+}
+
+pub fn sync_page_size(limit: Option<usize>) -> usize {
+    limit
+        .unwrap_or(DEFAULT_SYNC_PAGE_SIZE)
+        .max(1)
+        .min(MAX_SYNC_PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::{TimeZone, Utc};
+
+    use crate::sync_cursor::{sync_page_size, SyncCursor, MAX_SYNC_PAGE_SIZE};
+
+    #[test]
+    fn test_sync_cursor_roundtrip() -> Result<(), Error> {
+        let cursor = SyncCursor {
+            last_modified: Utc.ymd(2021, 5, 3).and_hms_micro(12, 30, 15, 123_456),
+            id: 4321,
+        };
+        let encoded = cursor.encode();
+        assert!(!encoded.contains('|'));
+        assert_eq!(SyncCursor::decode(&encoded)?, cursor);
+        assert!(SyncCursor::decode("not a cursor").is_err());
+
+        let next = SyncCursor::next_page(&[1, 2, 3], 3, |id| SyncCursor {
+            last_modified: cursor.last_modified,
+            id: *id,
+        });
+        assert_eq!(next.map(|c| c.id), Some(3));
+        assert!(SyncCursor::next_page(&[1, 2], 3, |_| cursor).is_none());
+
+        assert_eq!(sync_page_size(Some(0)), 1);
+        assert_eq!(sync_page_size(Some(1_000_000)), MAX_SYNC_PAGE_SIZE);
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::Error;
+use chrono::Local;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    movie_collection::MovieCollection,
+    tv_show_source::TvShowSource,
+    utils::{get_video_runtime, parse_file_stem},
+};
+
+const EPISODE_RUNTIME_ESTIMATE: i64 = 45;
+const MOVIE_RUNTIME_ESTIMATE: i64 = 110;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct TonightEntry {
+    pub show: StackString,
+    pub title: StackString,
+    pub link: StackString,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub eptitle: Option<StackString>,
+    pub collection_idx: Option<i32>,
+    pub runtime_minutes: i64,
+    pub runtime_estimated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Schema)]
+pub struct TonightPicks {
+    pub airing_today: Vec<TonightEntry>,
+    pub continue_watching: Vec<TonightEntry>,
+    pub recommendation: Option<TonightEntry>,
+    pub total_runtime_minutes: i64,
+}
+
+#[derive(FromSqlRow)]
+struct QueueEntry {
+    idx: i32,
+    path: StackString,
+    show: StackString,
+    title: Option<StackString>,
+    link: StackString,
+}
+
+pub fn runtime_minutes(timeval: &str) -> Option<i64> {
+    let mut items = timeval.split(':').map(|s| s.parse::<i64>().ok());
+    let hours = items.next()??;
+    let minutes = items.next()??;
+    let seconds = items.next()??;
+    Some(hours * 60 + minutes + (seconds + 30) / 60)
+}
+
+fn season_episode(path: &str) -> (i32, i32) {
+    let file_stem = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let (_, season, episode) = parse_file_stem(&file_stem);
+    (season, episode)
+}
+
+async fn estimate_runtime(path: Option<&str>, default: i64) -> (i64, bool) {
+    if let Some(path) = path {
+        let path = Path::new(path);
+        if path.exists() {
+            if let Ok(timeval) = get_video_runtime(path).await {
+                if let Some(minutes) = runtime_minutes(&timeval) {
+                    return (minutes, false);
+                }
+            }
+        }
+    }
+    (default, true)
+}
+
+impl TonightEntry {
+    async fn from_queue_entry(entry: QueueEntry, istv: bool) -> Self {
+        let (season, episode) = if istv {
+            let (season, episode) = season_episode(&entry.path);
+            (Some(season), Some(episode))
+        } else {
+            (None, None)
+        };
+        let default = if istv {
+            EPISODE_RUNTIME_ESTIMATE
+        } else {
+            MOVIE_RUNTIME_ESTIMATE
+        };
+        let (runtime_minutes, runtime_estimated) =
+            estimate_runtime(Some(entry.path.as_str()), default).await;
+        Self {
+            title: entry.title.unwrap_or_else(|| entry.show.clone()),
+            show: entry.show,
+            link: entry.link,
+            season,
+            episode,
+            eptitle: None,
+            collection_idx: Some(entry.idx),
+            runtime_minutes,
+            runtime_estimated,
+        }
+    }
+
+    fn get_html(&self) -> StackString {
+        let title = match self.collection_idx {
+            Some(idx) => format!(
+                r#"<a href="javascript:updateMainArticle('/list/play/{}')">{}</a>"#,
+                idx, self.title
+            ),
+            None => self.title.to_string(),
+        };
+        let episode = match (self.season, self.episode) {
+            (Some(season), Some(episode)) => format!(
+                "s{:02} ep{:02} {}",
+                season,
+                episode,
+                self.eptitle.as_ref().map_or("", StackString::as_str)
+            ),
+            _ => String::new(),
+        };
+        format!(
+            "<tr><td>{}</td><td>{}</td><td>{}{} min</td></tr>",
+            title,
+            episode,
+            if self.runtime_estimated { "~" } else { "" },
+            self.runtime_minutes,
+        )
+        .into()
+    }
+}
+
+impl TonightPicks {
+    async fn get_airing_today(mc: &MovieCollection) -> Result<Vec<TonightEntry>, Error> {
+        let today = Local::today().naive_local();
+        let episodes = mc
+            .get_new_episodes(today, today, Some(TvShowSource::All))
+            .await?;
+        let mut entries = Vec::new();
+        for epi in episodes {
+            let query = query!(
+                r#"
+                    SELECT idx, path
+                    FROM movie_collection
+                    WHERE show = $show AND is_deleted = false
+                "#,
+                show = epi.show
+            );
+            let conn = mc.pool.get().await?;
+            let rows: Vec<(i32, StackString)> = query.fetch(&conn).await?;
+            let file = rows
+                .into_iter()
+                .find(|(_, path)| season_episode(path) == (epi.season, epi.episode));
+            let (runtime_minutes, runtime_estimated) = estimate_runtime(
+                file.as_ref().map(|(_, path)| path.as_str()),
+                EPISODE_RUNTIME_ESTIMATE,
+            )
+            .await;
+            entries.push(TonightEntry {
+                show: epi.show,
+                title: epi.title,
+                link: epi.link,
+                season: Some(epi.season),
+                episode: Some(epi.episode),
+                eptitle: Some(epi.eptitle),
+                collection_idx: file.map(|(idx, _)| idx),
+                runtime_minutes,
+                runtime_estimated,
+            });
+        }
+        Ok(entries)
+    }
+
+    async fn get_continue_watching(mc: &MovieCollection) -> Result<Vec<TonightEntry>, Error> {
+        let query = query!(
+            r#"
+                SELECT b.idx, b.path, c.show, c.title, c.link
+                FROM movie_queue a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                JOIN imdb_ratings c ON b.show_id = c.index
+                WHERE c.istv AND c.link IN (SELECT link FROM trakt_watched_episodes)
+                ORDER BY a.idx
+            "#
+        );
+        let conn = mc.pool.get().await?;
+        let queue: Vec<QueueEntry> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT link, season, episode
+                FROM trakt_watched_episodes
+                WHERE season IS NOT NULL AND episode IS NOT NULL
+            "#
+        );
+        let watched: Vec<(StackString, i32, i32)> = query.fetch(&conn).await?;
+        let watched: HashSet<_> = watched.into_iter().collect();
+
+        let mut next_episodes: BTreeMap<StackString, ((i32, i32), QueueEntry)> = BTreeMap::new();
+        for entry in queue {
+            let (season, episode) = season_episode(&entry.path);
+            if season < 0 || watched.contains(&(entry.link.clone(), season, episode)) {
+                continue;
+            }
+            let is_next = next_episodes
+                .get(&entry.show)
+                .map_or(true, |(key, _)| (season, episode) < *key);
+            if is_next {
+                next_episodes.insert(entry.show.clone(), ((season, episode), entry));
+            }
+        }
+        let mut entries = Vec::new();
+        for (_, entry) in next_episodes.into_iter().map(|(_, v)| v) {
+            entries.push(TonightEntry::from_queue_entry(entry, true).await);
+        }
+        Ok(entries)
+    }
+
+    async fn get_recommendation(mc: &MovieCollection) -> Result<Option<TonightEntry>, Error> {
+        let query = query!(
+            r#"
+                SELECT b.idx, b.path, c.show, c.title, c.link
+                FROM movie_queue a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                JOIN imdb_ratings c ON b.show_id = c.index
+                LEFT JOIN trakt_watched_movies d ON c.link = d.link
+                WHERE NOT coalesce(c.istv, false) AND d.link IS NULL
+                ORDER BY c.rating DESC NULLS LAST
+                LIMIT 1
+            "#
+        );
+        let conn = mc.pool.get().await?;
+        let entry: Option<QueueEntry> = query.fetch_opt(&conn).await?;
+        match entry {
+            Some(entry) => Ok(Some(TonightEntry::from_queue_entry(entry, false).await)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn get_picks(mc: &MovieCollection) -> Result<Self, Error> {
+        let airing_today = Self::get_airing_today(mc).await?;
+        let continue_watching = Self::get_continue_watching(mc).await?;
+        let recommendation = Self::get_recommendation(mc).await?;
+        let total_runtime_minutes = airing_today
+            .iter()
+            .chain(continue_watching.iter())
+            .chain(recommendation.iter())
+            .map(|e| e.runtime_minutes)
+            .sum();
+        Ok(Self {
+            airing_today,
+            continue_watching,
+            recommendation,
+            total_runtime_minutes,
+        })
+    }
+
+    pub fn get_html(&self) -> StackString {
+        fn section(title: &str, entries: &[TonightEntry]) -> String {
+            if entries.is_empty() {
+                return String::new();
+            }
+            let rows: Vec<_> = entries.iter().map(TonightEntry::get_html).collect();
+            format!(
+                r#"<h4>{}</h4><table border="0">{}</table>"#,
+                title,
+                rows.join("")
+            )
+        }
+        let recommendation: Vec<_> = self.recommendation.iter().cloned().collect();
+        format!(
+            "{}{}{}<br>Total: {} min",
+            section("Airing Today", &self.airing_today),
+            section("Continue Watching", &self.continue_watching),
+            section("Recommended", &recommendation),
+            self.total_runtime_minutes,
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tonight::{runtime_minutes, season_episode};
+
+    #[test]
+    fn test_runtime_minutes() {
+        assert_eq!(runtime_minutes("00:42:31"), Some(43));
+        assert_eq!(runtime_minutes("01:58:10"), Some(118));
+        assert_eq!(runtime_minutes(""), None);
+    }
+
+    #[test]
+    fn test_season_episode() {
+        assert_eq!(
+            season_episode("/tmp/television/mr_robot_s01_ep02.mp4"),
+            (1, 2)
+        );
+        assert_eq!(season_episode("/tmp/movies/inception.mp4"), (-1, -1));
+    }
+}
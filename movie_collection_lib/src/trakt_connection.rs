@@ -2,34 +2,100 @@ use anyhow::{format_err, Error};
 use base64::{encode_config, URL_SAFE_NO_PAD};
 use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
-use log::debug;
 use maplit::hashmap;
 use rand::{thread_rng, Rng};
-use reqwest::{header::HeaderMap, Client, Url};
+use reqwest::{header::HeaderMap, Client, Method, RequestBuilder, Response, StatusCode, Url};
+use rweb::Schema;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use stack_string::StackString;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
+use thiserror::Error as ThisError;
 use tokio::{
     fs::{read, write},
     sync::{Mutex, RwLock},
+    time::sleep,
 };
+use tracing::{debug, error};
 
 use crate::{
     config::Config,
+    datetime_wrapper::DateTimeWrapper,
     iso_8601_datetime,
+    trakt_ratings::TraktRating,
     trakt_utils::{
         TraktCalEntry, TraktCalEntryList, TraktResult, WatchListShow, WatchedEpisode, WatchedMovie,
     },
 };
 
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(300);
+const RATE_LIMIT_CALLS: usize = 1000;
+const RATE_LIMIT_WRITE_INTERVAL: Duration = Duration::from_secs(1);
+const RATE_LIMIT_MAX_RETRIES: usize = 3;
+
+#[derive(ThisError, Debug)]
+pub enum TraktError {
+    #[error("Trakt not authorized, refresh the auth token")]
+    NotAuthorized,
+    #[error("Trakt request failed {0}")]
+    External(#[from] reqwest::Error),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
+
 lazy_static! {
     static ref CSRF_TOKEN: Mutex<Option<StackString>> = Mutex::new(None);
     static ref AUTH_TOKEN: RwLock<Option<Arc<AccessTokenResponse>>> = RwLock::new(None);
+    static ref RATE_LIMIT: Mutex<RateLimitState> = Mutex::new(RateLimitState::default());
+    static ref LAST_429: RwLock<Option<DateTime<Utc>>> = RwLock::new(None);
+}
+
+static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct RateLimitState {
+    calls: VecDeque<Instant>,
+    last_write: Option<Instant>,
+}
+
+impl RateLimitState {
+    fn try_acquire(&mut self, now: Instant, is_write: bool) -> Option<Duration> {
+        while let Some(oldest) = self.calls.front() {
+            if now.duration_since(*oldest) < RATE_LIMIT_WINDOW {
+                break;
+            }
+            self.calls.pop_front();
+        }
+        if self.calls.len() >= RATE_LIMIT_CALLS {
+            let oldest = self.calls.front()?;
+            return Some(RATE_LIMIT_WINDOW - now.duration_since(*oldest));
+        }
+        if is_write {
+            if let Some(last_write) = self.last_write {
+                let elapsed = now.duration_since(last_write);
+                if elapsed < RATE_LIMIT_WRITE_INTERVAL {
+                    return Some(RATE_LIMIT_WRITE_INTERVAL - elapsed);
+                }
+            }
+            self.last_write = Some(now);
+        }
+        self.calls.push_back(now);
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
+pub struct TraktRateLimitStatus {
+    pub queue_depth: u64,
+    pub last_429: Option<DateTimeWrapper>,
 }
 
 #[derive(Clone)]
@@ -53,11 +119,71 @@ impl TraktConnection {
         }
     }
 
+    pub fn is_configured(&self) -> bool {
+        self.config.trakt_configured()
+    }
+
+    pub async fn rate_limit_status() -> TraktRateLimitStatus {
+        TraktRateLimitStatus {
+            queue_depth: QUEUE_DEPTH.load(Ordering::SeqCst) as u64,
+            last_429: LAST_429.read().await.map(Into::into),
+        }
+    }
+
+    async fn acquire_rate_limit(is_write: bool) {
+        QUEUE_DEPTH.fetch_add(1, Ordering::SeqCst);
+        loop {
+            let wait = RATE_LIMIT
+                .lock()
+                .await
+                .try_acquire(Instant::now(), is_write);
+            match wait {
+                Some(wait) => sleep(wait).await,
+                None => break,
+            }
+        }
+        QUEUE_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let request = request.build()?;
+        let is_write = request.method() != Method::GET;
+        let mut retries = 0;
+        loop {
+            Self::acquire_rate_limit(is_write).await;
+            let current = request
+                .try_clone()
+                .ok_or_else(|| format_err!("Failed to clone request"))?;
+            let resp = self
+                .client
+                .execute(current)
+                .await
+                .map_err(TraktError::External)?;
+            let rate_limited = resp.status() == StatusCode::TOO_MANY_REQUESTS;
+            if !rate_limited || retries >= RATE_LIMIT_MAX_RETRIES {
+                return Ok(resp);
+            }
+            LAST_429.write().await.replace(Utc::now());
+            let retry_after = resp
+                .headers()
+                .get("Retry-After")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.parse().ok())
+                .unwrap_or(1);
+            debug!("Trakt rate limited, retry after {}s", retry_after);
+            sleep(Duration::from_secs(retry_after)).await;
+            retries += 1;
+        }
+    }
+
     pub async fn init(&self) {
+        if !self.is_configured() {
+            return;
+        }
         if let Ok(auth_token) = self.read_auth_token().await {
             AUTH_TOKEN.write().await.replace(Arc::new(auth_token));
         } else {
-            println!("read_auth_token failed...");
+            error!("read_auth_token failed...");
         }
     }
 
@@ -116,11 +242,8 @@ impl TraktConnection {
             };
             let mut headers = HeaderMap::new();
             headers.insert("Content-Type", "application/json".parse()?);
-            self.client
-                .post(url.as_str())
-                .headers(headers)
-                .json(&body)
-                .send()
+            let request = self.client.post(url.as_str()).headers(headers).json(&body);
+            self.send(request)
                 .await?
                 .error_for_status()?
                 .json()
@@ -145,11 +268,8 @@ impl TraktConnection {
             };
             let mut headers = HeaderMap::new();
             headers.insert("Content-Type", "application/json".parse()?);
-            self.client
-                .post(url.as_str())
-                .headers(headers)
-                .json(&body)
-                .send()
+            let request = self.client.post(url.as_str()).headers(headers).json(&body);
+            self.send(request)
                 .await?
                 .error_for_status()?
                 .json()
@@ -188,7 +308,7 @@ impl TraktConnection {
             .read()
             .await
             .clone()
-            .ok_or_else(|| format_err!("No auth token"))?;
+            .ok_or(TraktError::NotAuthorized)?;
         let bearer = format!("Bearer {}", auth_token.access_token);
         headers.insert("Authorization", bearer.parse()?);
         Ok(headers)
@@ -205,13 +325,8 @@ impl TraktConnection {
             &url,
             &[("page", &page.to_string()), ("limit", &limit.to_string())],
         )?;
-        let resp = self
-            .client
-            .get(url)
-            .headers(headers)
-            .send()
-            .await?
-            .error_for_status()?;
+        let request = self.client.get(url).headers(headers);
+        let resp = self.send(request).await?.error_for_status()?;
         let headers = resp.headers();
         if let Some(current_page) = headers.get("X-Pagination-Page") {
             let current_page: usize = current_page.to_str()?.parse()?;
@@ -221,6 +336,18 @@ impl TraktConnection {
     }
 
     pub async fn get_watchlist_shows(&self) -> Result<HashMap<StackString, WatchListShow>, Error> {
+        let watchlist = self
+            .get_watchlist_shows_listed_at()
+            .await?
+            .into_iter()
+            .map(|(link, (show, _))| (link, show))
+            .collect();
+        Ok(watchlist)
+    }
+
+    pub async fn get_watchlist_shows_listed_at(
+        &self,
+    ) -> Result<HashMap<StackString, (WatchListShow, DateTime<Utc>)>, Error> {
         let mut current_page = 1;
         let mut results = Vec::new();
         loop {
@@ -235,14 +362,12 @@ impl TraktConnection {
             .into_iter()
             .map(|r| {
                 let imdb: StackString = r.show.ids.imdb.unwrap_or_else(|| "".into());
-                (
-                    imdb.clone(),
-                    WatchListShow {
-                        link: imdb,
-                        title: r.show.title,
-                        year: r.show.year,
-                    },
-                )
+                let show = WatchListShow {
+                    link: imdb.clone(),
+                    title: r.show.title,
+                    year: r.show.year,
+                };
+                (imdb, (show, r.listed_at))
             })
             .collect();
         Ok(watchlist)
@@ -257,10 +382,8 @@ impl TraktConnection {
             "{}/search/imdb/{}?type=show",
             self.config.trakt_endpoint, imdb_id
         );
-        self.client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
+        let request = self.client.get(url.as_str()).headers(headers);
+        self.send(request)
             .await?
             .error_for_status()?
             .json()
@@ -277,10 +400,8 @@ impl TraktConnection {
             "{}/search/imdb/{}?type=movie",
             self.config.trakt_endpoint, imdb_id
         );
-        self.client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
+        let request = self.client.get(url.as_str()).headers(headers);
+        self.send(request)
             .await?
             .error_for_status()?
             .json()
@@ -302,10 +423,8 @@ impl TraktConnection {
             season = season,
             episode = episode,
         );
-        self.client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
+        let request = self.client.get(url.as_str()).headers(headers);
+        self.send(request)
             .await?
             .error_for_status()?
             .json()
@@ -325,16 +444,8 @@ impl TraktConnection {
             "shows" => vec![show_obj.show],
         };
         debug!("shows: {}", serde_json::to_string_pretty(&data)?);
-        let text = self
-            .client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        let text = self.send(request).await?.error_for_status()?.text().await?;
         Ok(TraktResult {
             status: text.into(),
         })
@@ -351,16 +462,8 @@ impl TraktConnection {
         let data = hashmap! {
             "shows" => vec![show_obj.show],
         };
-        let text = self
-            .client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        let text = self.send(request).await?.error_for_status()?.text().await?;
         Ok(TraktResult {
             status: text.into(),
         })
@@ -371,15 +474,9 @@ impl TraktConnection {
     ) -> Result<HashMap<(StackString, i32, i32), WatchedEpisode>, Error> {
         let headers = self.get_rw_headers().await?;
         let url = format!("{}/sync/watched/shows", self.config.trakt_endpoint);
-        let watched_episodes: Vec<TraktWatchedShowResponse> = self
-            .client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
+        let request = self.client.get(url.as_str()).headers(headers);
+        let watched_episodes: Vec<TraktWatchedShowResponse> =
+            self.send(request).await?.error_for_status()?.json().await?;
 
         #[allow(clippy::manual_filter_map)]
         let episode_map = watched_episodes
@@ -419,15 +516,9 @@ impl TraktConnection {
     pub async fn get_watched_movies(&self) -> Result<HashSet<WatchedMovie>, Error> {
         let headers = self.get_rw_headers().await?;
         let url = format!("{}/sync/watched/movies", self.config.trakt_endpoint);
-        let watched_movies: Vec<TraktWatchedMovieResponse> = self
-            .client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
+        let request = self.client.get(url.as_str()).headers(headers);
+        let watched_movies: Vec<TraktWatchedMovieResponse> =
+            self.send(request).await?.error_for_status()?.json().await?;
         let movie_map: HashSet<WatchedMovie> = watched_movies
             .into_iter()
             .map(|entry| {
@@ -446,18 +537,24 @@ impl TraktConnection {
         Ok(movie_map)
     }
 
+    pub async fn get_ratings(&self) -> Result<Vec<TraktRating>, Error> {
+        let headers = self.get_rw_headers().await?;
+        let url = format!("{}/sync/ratings", self.config.trakt_endpoint);
+        let request = self.client.get(url.as_str()).headers(headers);
+        let ratings: Vec<TraktRatingResponse> =
+            self.send(request).await?.error_for_status()?.json().await?;
+        Ok(ratings
+            .into_iter()
+            .filter_map(TraktRatingResponse::into_rating)
+            .collect())
+    }
+
     pub async fn get_calendar(&self) -> Result<TraktCalEntryList, Error> {
         let headers = self.get_rw_headers().await?;
         let url = format!("{}/calendars/my/shows", self.config.trakt_endpoint);
-        let new_episodes: Vec<TraktCalendarResponse> = self
-            .client
-            .get(url.as_str())
-            .headers(headers)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
+        let request = self.client.get(url.as_str()).headers(headers);
+        let new_episodes: Vec<TraktCalendarResponse> =
+            self.send(request).await?.error_for_status()?.json().await?;
         let cal_entries = new_episodes
             .into_iter()
             .map(|entry| {
@@ -492,13 +589,8 @@ impl TraktConnection {
                 }
             ]
         };
-        self.client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?
-            .error_for_status()?;
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        self.send(request).await?.error_for_status()?;
         Ok(TraktResult {
             status: "success".into(),
         })
@@ -522,13 +614,8 @@ impl TraktConnection {
                 }
             ]
         };
-        self.client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?
-            .error_for_status()?;
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        self.send(request).await?.error_for_status()?;
         Ok(TraktResult {
             status: "success".into(),
         })
@@ -551,18 +638,60 @@ impl TraktConnection {
                 }
             ]
         };
-        self.client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        self.send(request).await?.error_for_status()?;
+        Ok(TraktResult {
+            status: "success".into(),
+        })
+    }
+
+    async fn season_watched_request(
+        &self,
+        imdb_id: &str,
+        season: i32,
+        path: &str,
+    ) -> Result<TraktResult, Error> {
+        let show_obj = self
+            .get_show_by_imdb_id(imdb_id)
             .await?
-            .error_for_status()?;
+            .pop()
+            .ok_or_else(|| format_err!("No show returned"))?;
+        let headers = self.get_rw_headers().await?;
+        let url = format!("{}/{}", self.config.trakt_endpoint, path);
+        let data = hashmap! {
+            "shows" => vec![
+                WatchedShowSeasonsRequest {
+                    watched_at: Utc::now(),
+                    ids: show_obj.show.ids,
+                    seasons: vec![WatchedSeasonRequest { number: season }],
+                }
+            ]
+        };
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        self.send(request).await?.error_for_status()?;
         Ok(TraktResult {
             status: "success".into(),
         })
     }
 
+    pub async fn add_season_to_watched(
+        &self,
+        imdb_id: &str,
+        season: i32,
+    ) -> Result<TraktResult, Error> {
+        self.season_watched_request(imdb_id, season, "sync/history")
+            .await
+    }
+
+    pub async fn remove_season_to_watched(
+        &self,
+        imdb_id: &str,
+        season: i32,
+    ) -> Result<TraktResult, Error> {
+        self.season_watched_request(imdb_id, season, "sync/history/remove")
+            .await
+    }
+
     pub async fn remove_movie_to_watched(&self, imdb_id: &str) -> Result<TraktResult, Error> {
         let movie_obj = self
             .get_movie_by_imdb_id(imdb_id)
@@ -581,13 +710,8 @@ impl TraktConnection {
                 }
             ]
         };
-        self.client
-            .post(url.as_str())
-            .headers(headers)
-            .json(&data)
-            .send()
-            .await?
-            .error_for_status()?;
+        let request = self.client.post(url.as_str()).headers(headers).json(&data);
+        self.send(request).await?.error_for_status()?;
         Ok(TraktResult {
             status: "success".into(),
         })
@@ -610,6 +734,19 @@ struct WatchedEpisodeRequest {
     pub ids: TraktIdObject,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct WatchedSeasonRequest {
+    pub number: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WatchedShowSeasonsRequest {
+    #[serde(with = "iso_8601_datetime")]
+    pub watched_at: DateTime<Utc>,
+    pub ids: TraktIdObject,
+    pub seasons: Vec<WatchedSeasonRequest>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct AccessTokenResponse {
     access_token: StackString,
@@ -646,6 +783,8 @@ pub struct TraktEpisodeObject {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WatchListShowsResponse {
+    #[serde(with = "iso_8601_datetime")]
+    pub listed_at: DateTime<Utc>,
     pub show: TraktShowObject,
 }
 
@@ -681,6 +820,41 @@ pub struct TraktWatchedMovieResponse {
     pub movie: TraktShowObject,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TraktRatedItem {
+    pub season: Option<i32>,
+    pub number: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TraktRatingResponse {
+    #[serde(with = "iso_8601_datetime")]
+    pub rated_at: DateTime<Utc>,
+    pub rating: i32,
+    pub movie: Option<TraktShowObject>,
+    pub show: Option<TraktShowObject>,
+    pub season: Option<TraktRatedItem>,
+    pub episode: Option<TraktRatedItem>,
+}
+
+impl TraktRatingResponse {
+    fn into_rating(self) -> Option<TraktRating> {
+        let item = self.show.or(self.movie)?;
+        let (season, episode) = match (self.season, self.episode) {
+            (_, Some(episode)) => (episode.season.unwrap_or(-1), episode.number),
+            (Some(season), None) => (season.number, -1),
+            (None, None) => (-1, -1),
+        };
+        Some(TraktRating {
+            link: item.ids.imdb?,
+            season,
+            episode,
+            rating: self.rating,
+            rated_at: self.rated_at.into(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TraktCalendarResponse {
     #[serde(with = "iso_8601_datetime")]
@@ -691,8 +865,62 @@ pub struct TraktCalendarResponse {
 
 #[cfg(test)]
 mod tests {
-    use crate::{config::Config, trakt_connection::TraktConnection};
+    use crate::{
+        config::Config,
+        trakt_connection::{
+            RateLimitState, TraktConnection, TraktRatingResponse, RATE_LIMIT_WRITE_INTERVAL,
+        },
+    };
     use anyhow::Error;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rate_limit_state() {
+        let mut state = RateLimitState::default();
+        let now = Instant::now();
+        assert_eq!(state.try_acquire(now, false), None);
+        assert_eq!(state.try_acquire(now, true), None);
+        assert_eq!(
+            state.try_acquire(now, true),
+            Some(RATE_LIMIT_WRITE_INTERVAL)
+        );
+        let later = now + Duration::from_millis(1500);
+        assert_eq!(state.try_acquire(later, true), None);
+        assert_eq!(state.calls.len(), 3);
+    }
+
+    #[test]
+    fn test_rating_response() -> Result<(), Error> {
+        let buf = r#"[
+            {"rated_at": "2021-03-04T05:06:07.000Z", "rating": 9, "type": "episode",
+             "episode": {"season": 2, "number": 5, "title": "Home", "ids": {"trakt": 1}},
+             "show": {"title": "The Expanse", "year": 2015,
+                      "ids": {"trakt": 77199, "imdb": "tt3230854"}}},
+            {"rated_at": "2021-03-04T05:06:07.000Z", "rating": 8, "type": "season",
+             "season": {"number": 3, "ids": {"trakt": 2}},
+             "show": {"title": "The Expanse", "year": 2015,
+                      "ids": {"trakt": 77199, "imdb": "tt3230854"}}},
+            {"rated_at": "2021-03-04T05:06:07.000Z", "rating": 7, "type": "movie",
+             "movie": {"title": "Arrival", "year": 2016, "ids": {"trakt": 3, "imdb": "tt2543164"}}},
+            {"rated_at": "2021-03-04T05:06:07.000Z", "rating": 6, "type": "movie",
+             "movie": {"title": "Unknown", "year": 2016, "ids": {"trakt": 4}}}
+        ]"#;
+        let responses: Vec<TraktRatingResponse> = serde_json::from_str(buf)?;
+        let ratings: Vec<_> = responses
+            .into_iter()
+            .filter_map(TraktRatingResponse::into_rating)
+            .map(|r| (r.link.to_string(), r.season, r.episode, r.rating))
+            .collect();
+        assert_eq!(
+            ratings,
+            vec![
+                ("tt3230854".to_string(), 2, 5, 9),
+                ("tt3230854".to_string(), 3, -1, 8),
+                ("tt2543164".to_string(), -1, -1, 7),
+            ]
+        );
+        Ok(())
+    }
 
     #[test]
     #[ignore]
@@ -0,0 +1,113 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::collections::{HashMap, HashSet};
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool, trakt_connection::TraktConnection};
+
+// Ratings for a whole show or movie use -1 for season and episode, a season
+// rating only sets the season
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct TraktRating {
+    pub link: StackString,
+    pub season: i32,
+    pub episode: i32,
+    pub rating: i32,
+    pub rated_at: DateTimeWrapper,
+}
+
+impl TraktRating {
+    fn key(&self) -> (StackString, i32, i32) {
+        (self.link.clone(), self.season, self.episode)
+    }
+
+    pub async fn get_all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!("SELECT * FROM trakt_ratings");
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_show_ratings(pool: &PgPool) -> Result<HashMap<StackString, i32>, Error> {
+        let query = query!(
+            r#"
+                SELECT link, rating
+                FROM trakt_ratings
+                WHERE season = -1 AND episode = -1
+            "#
+        );
+        let conn = pool.get().await?;
+        let ratings: Vec<(StackString, i32)> = query.fetch(&conn).await?;
+        Ok(ratings.into_iter().collect())
+    }
+
+    pub async fn get_episode_ratings(
+        pool: &PgPool,
+        link: &str,
+        season: i32,
+    ) -> Result<HashMap<i32, i32>, Error> {
+        let query = query!(
+            r#"
+                SELECT episode, rating
+                FROM trakt_ratings
+                WHERE link = $link AND season = $season
+            "#,
+            link = link,
+            season = season
+        );
+        let conn = pool.get().await?;
+        let ratings: Vec<(i32, i32)> = query.fetch(&conn).await?;
+        Ok(ratings.into_iter().collect())
+    }
+
+    pub async fn upsert(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO trakt_ratings (link, season, episode, rating, rated_at)
+                VALUES ($link, $season, $episode, $rating, $rated_at)
+                ON CONFLICT (link, season, episode) DO UPDATE
+                SET rating=$rating, rated_at=$rated_at
+            "#,
+            link = self.link,
+            season = self.season,
+            episode = self.episode,
+            rating = self.rating,
+            rated_at = self.rated_at
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub async fn delete(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                DELETE FROM trakt_ratings
+                WHERE link = $link AND season = $season AND episode = $episode
+            "#,
+            link = self.link,
+            season = self.season,
+            episode = self.episode
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+}
+
+pub fn format_rating(rating: Option<i32>) -> StackString {
+    rating.map_or_else(|| "".into(), |r| format!("mine: {}", r).into())
+}
+
+pub async fn sync_trakt_ratings(trakt: &TraktConnection, pool: &PgPool) -> Result<usize, Error> {
+    let ratings = trakt.get_ratings().await?;
+    let keys: HashSet<_> = ratings.iter().map(TraktRating::key).collect();
+    for rating in &ratings {
+        rating.upsert(pool).await?;
+    }
+    for rating in TraktRating::get_all(pool).await? {
+        if !keys.contains(&rating.key()) {
+            rating.delete(pool).await?;
+        }
+    }
+    Ok(ratings.len())
+}
@@ -0,0 +1,310 @@
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    datetime_wrapper::DateTimeWrapper, pgpool::PgPool, trakt_connection::TraktConnection,
+    trakt_utils::WatchListShow, utils::escape_html,
+};
+
+type WatchlistTimes = HashMap<StackString, (WatchListShow, DateTime<Utc>)>;
+
+#[derive(Debug, PartialEq)]
+pub enum WatchlistSyncAction {
+    AddLocal(WatchListShow),
+    RemoveLocal(WatchListShow),
+    AddRemote(StackString),
+    RemoveRemote(StackString),
+}
+
+// Trakt doesn't say when a show left the watchlist, so a show that is only
+// present locally counts as removed remotely unless it changed locally after
+// the last successful sync
+pub fn plan_watchlist_sync(
+    local: &WatchlistTimes,
+    removed: &HashMap<StackString, DateTime<Utc>>,
+    remote: &WatchlistTimes,
+    last_sync: Option<DateTime<Utc>>,
+) -> Vec<WatchlistSyncAction> {
+    let mut actions = Vec::new();
+    for (link, (show, listed_at)) in remote {
+        if link.is_empty() || local.contains_key(link) {
+            continue;
+        }
+        match removed.get(link) {
+            Some(removed_at) if removed_at > listed_at => {
+                actions.push(WatchlistSyncAction::RemoveRemote(link.clone()));
+            }
+            _ => actions.push(WatchlistSyncAction::AddLocal(show.clone())),
+        }
+    }
+    for (link, (show, last_modified)) in local {
+        if remote.contains_key(link) {
+            continue;
+        }
+        if last_sync.map_or(true, |t| *last_modified > t) {
+            actions.push(WatchlistSyncAction::AddRemote(link.clone()));
+        } else {
+            actions.push(WatchlistSyncAction::RemoveLocal(show.clone()));
+        }
+    }
+    actions
+}
+
+async fn get_local_watchlist(pool: &PgPool) -> Result<WatchlistTimes, Error> {
+    #[derive(FromSqlRow)]
+    struct LocalWatchlistEntry {
+        link: StackString,
+        title: StackString,
+        year: i32,
+        last_modified: DateTimeWrapper,
+    }
+    let query = query!("SELECT link, title, year, last_modified FROM trakt_watchlist");
+    let conn = pool.get().await?;
+    let entries: Vec<LocalWatchlistEntry> = query.fetch(&conn).await?;
+    Ok(entries
+        .into_iter()
+        .map(|e| {
+            let show = WatchListShow {
+                link: e.link.clone(),
+                title: e.title,
+                year: e.year,
+            };
+            (e.link, (show, e.last_modified.into()))
+        })
+        .collect())
+}
+
+async fn get_removed_watchlist(
+    pool: &PgPool,
+) -> Result<HashMap<StackString, DateTime<Utc>>, Error> {
+    let query = query!("SELECT link, removed_at FROM trakt_watchlist_removed");
+    let conn = pool.get().await?;
+    let removed: Vec<(StackString, DateTimeWrapper)> = query.fetch(&conn).await?;
+    Ok(removed.into_iter().map(|(l, r)| (l, r.into())).collect())
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct TraktSyncReport {
+    pub id: i32,
+    pub started_at: DateTimeWrapper,
+    pub finished_at: DateTimeWrapper,
+    pub added_local: i32,
+    pub removed_local: i32,
+    pub added_remote: i32,
+    pub removed_remote: i32,
+    pub errors: Option<StackString>,
+}
+
+impl fmt::Display for TraktSyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "added local {} removed local {} added remote {} removed remote {}",
+            self.added_local, self.removed_local, self.added_remote, self.removed_remote,
+        )
+    }
+}
+
+impl TraktSyncReport {
+    fn new(started_at: DateTimeWrapper) -> Self {
+        Self {
+            id: 0,
+            started_at,
+            finished_at: started_at,
+            added_local: 0,
+            removed_local: 0,
+            added_remote: 0,
+            removed_remote: 0,
+            errors: None,
+        }
+    }
+
+    pub async fn get_recent(pool: &PgPool, limit: i64) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM trakt_sync_report ORDER BY started_at DESC LIMIT $limit",
+            limit = limit
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    async fn get_last_sync(pool: &PgPool) -> Result<Option<DateTimeWrapper>, Error> {
+        let query = query!("SELECT max(started_at) FROM trakt_sync_report WHERE errors IS NULL");
+        let conn = pool.get().await?;
+        let (last_sync,) = query.fetch_one(&conn).await?;
+        Ok(last_sync)
+    }
+
+    async fn insert(&self, pool: &PgPool) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO trakt_sync_report (
+                    started_at, added_local, removed_local, added_remote, removed_remote, errors
+                ) VALUES (
+                    $started_at, $added_local, $removed_local, $added_remote, $removed_remote,
+                    $errors
+                )
+                RETURNING *
+            "#,
+            started_at = self.started_at,
+            added_local = self.added_local,
+            removed_local = self.removed_local,
+            added_remote = self.added_remote,
+            removed_remote = self.removed_remote,
+            errors = self.errors
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    async fn get_actions(
+        trakt: &TraktConnection,
+        pool: &PgPool,
+    ) -> Result<Vec<WatchlistSyncAction>, Error> {
+        trakt.init().await;
+        let last_sync = Self::get_last_sync(pool).await?.map(Into::into);
+        let local = get_local_watchlist(pool).await?;
+        let removed = get_removed_watchlist(pool).await?;
+        let remote = trakt.get_watchlist_shows_listed_at().await?;
+        Ok(plan_watchlist_sync(&local, &removed, &remote, last_sync))
+    }
+
+    async fn apply(
+        &mut self,
+        trakt: &TraktConnection,
+        pool: &PgPool,
+        action: &WatchlistSyncAction,
+    ) -> Result<(), Error> {
+        match action {
+            WatchlistSyncAction::AddLocal(show) => {
+                show.insert_show(pool).await?;
+                self.added_local += 1;
+            }
+            WatchlistSyncAction::RemoveLocal(show) => {
+                show.delete_show(pool).await?;
+                self.removed_local += 1;
+            }
+            WatchlistSyncAction::AddRemote(link) => {
+                trakt.add_watchlist_show(link).await?;
+                self.added_remote += 1;
+            }
+            WatchlistSyncAction::RemoveRemote(link) => {
+                trakt.remove_watchlist_show(link).await?;
+                self.removed_remote += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run_sync(trakt: &TraktConnection, pool: &PgPool) -> Result<Self, Error> {
+        let mut report = Self::new(Utc::now().into());
+        let mut errors = Vec::new();
+        match Self::get_actions(trakt, pool).await {
+            Ok(actions) => {
+                for action in &actions {
+                    if let Err(e) = report.apply(trakt, pool, action).await {
+                        errors.push(format!("{:?} {}", action, e));
+                    }
+                }
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+        if !errors.is_empty() {
+            report.errors = Some(errors.join("\n").into());
+        }
+        report.insert(pool).await
+    }
+
+    fn get_html(&self) -> StackString {
+        format!(
+            r#"<tr><td>{started}</td><td>{added_local}</td><td>{removed_local}</td><td>{added_remote}</td><td>{removed_remote}</td><td>{errors}</td></tr>"#,
+            started = self.started_at.format("%Y-%m-%d %H:%M"),
+            added_local = self.added_local,
+            removed_local = self.removed_local,
+            added_remote = self.added_remote,
+            removed_remote = self.removed_remote,
+            errors = escape_html(self.errors.as_deref().unwrap_or("")),
+        )
+        .into()
+    }
+
+    pub fn get_html_table(reports: &[Self]) -> StackString {
+        let rows: Vec<_> = reports.iter().map(Self::get_html).collect();
+        format!(
+            r#"
+            <h3>Trakt watchlist sync</h3>
+            <table border="0">
+            <tr><th>Started</th><th>Added Local</th><th>Removed Local</th><th>Added Trakt</th><th>Removed Trakt</th><th>Errors</th></tr>
+            {}
+            </table>
+            "#,
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use maplit::hashmap;
+
+    use crate::{
+        trakt_sync::{plan_watchlist_sync, TraktSyncReport, WatchlistSyncAction},
+        trakt_utils::WatchListShow,
+    };
+
+    fn show(link: &str) -> WatchListShow {
+        WatchListShow {
+            link: link.into(),
+            title: link.into(),
+            year: 2020,
+        }
+    }
+
+    #[test]
+    fn test_plan_watchlist_sync() {
+        let last_sync = Utc.ymd(2021, 3, 1).and_hms(0, 0, 0);
+        let before = Utc.ymd(2021, 2, 1).and_hms(0, 0, 0);
+        let after = Utc.ymd(2021, 3, 2).and_hms(0, 0, 0);
+
+        let local = hashmap! {
+            "tt01".into() => (show("tt01"), before),
+            "tt02".into() => (show("tt02"), before),
+            "tt03".into() => (show("tt03"), after),
+        };
+        let removed = hashmap! {
+            "tt04".into() => after,
+            "tt05".into() => before,
+        };
+        let remote = hashmap! {
+            "tt01".into() => (show("tt01"), before),
+            "tt04".into() => (show("tt04"), before),
+            "tt05".into() => (show("tt05"), after),
+        };
+        let actions = plan_watchlist_sync(&local, &removed, &remote, Some(last_sync));
+        assert_eq!(actions.len(), 4);
+        assert!(actions.contains(&WatchlistSyncAction::RemoveLocal(show("tt02"))));
+        assert!(actions.contains(&WatchlistSyncAction::AddRemote("tt03".into())));
+        assert!(actions.contains(&WatchlistSyncAction::RemoveRemote("tt04".into())));
+        assert!(actions.contains(&WatchlistSyncAction::AddLocal(show("tt05"))));
+
+        let actions = plan_watchlist_sync(&local, &removed, &remote, None);
+        assert!(actions.contains(&WatchlistSyncAction::AddRemote("tt02".into())));
+    }
+
+    #[test]
+    fn test_trakt_sync_report_html() {
+        let mut report = TraktSyncReport::new(Utc.ymd(2021, 3, 4).and_hms(5, 6, 7).into());
+        report.added_local = 2;
+        report.errors = Some("<unauthorized>".into());
+        let html = TraktSyncReport::get_html_table(&[report]);
+        assert!(html.contains("<td>2021-03-04 05:06</td><td>2</td><td>0</td>"));
+        assert!(html.contains("&lt;unauthorized&gt;"));
+    }
+}
@@ -2,10 +2,10 @@ use anyhow::Error;
 use chrono::NaiveDate;
 use futures::future::try_join_all;
 use itertools::Itertools;
-use log::debug;
-use postgres_query::{query, query_dyn, FromSqlRow};
+use postgres_query::{query, FromSqlRow};
 use rweb::Schema;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use stack_string::StackString;
 use std::{
     borrow::Borrow,
@@ -16,13 +16,19 @@ use std::{
     sync::Arc,
 };
 use stdout_channel::StdoutChannel;
+use tracing::debug;
 
 use crate::{
     config::Config, imdb_episodes::ImdbEpisodes, imdb_ratings::ImdbRatings,
     movie_collection::MovieCollection, pgpool::PgPool, trakt_connection::TraktConnection,
 };
 
-use crate::{tv_show_source::TvShowSource, utils::option_string_wrapper};
+use crate::{
+    trakt_ratings::sync_trakt_ratings,
+    tv_show_source::TvShowSource,
+    user_hooks::{HookEvent, UserHook},
+    utils::option_string_wrapper,
+};
 
 #[derive(Clone, Copy, Schema)]
 pub enum TraktActions {
@@ -113,7 +119,7 @@ impl fmt::Display for TraktResult {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, FromSqlRow, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, FromSqlRow, Eq, Clone)]
 pub struct WatchListShow {
     pub link: StackString,
     pub title: StackString,
@@ -187,6 +193,11 @@ impl WatchListShow {
             year = self.year
         );
         let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        let query = query!(
+            "DELETE FROM trakt_watchlist_removed WHERE link=$link",
+            link = self.link
+        );
         query.execute(&conn).await.map(|_| ()).map_err(Into::into)
     }
 
@@ -196,6 +207,16 @@ impl WatchListShow {
             link = self.link
         );
         let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        // Keep a tombstone so the watchlist sync can tell a local removal
+        // apart from a show that was only ever added on trakt
+        let query = query!(
+            r#"
+                INSERT INTO trakt_watchlist_removed (link) VALUES ($link)
+                ON CONFLICT (link) DO UPDATE SET removed_at=now()
+            "#,
+            link = self.link
+        );
         query.execute(&conn).await.map(|_| ()).map_err(Into::into)
     }
 }
@@ -257,6 +278,61 @@ pub async fn get_watchlist_shows_db_map(pool: &PgPool) -> Result<WatchListMap, E
         .collect()
 }
 
+pub async fn get_watchlist_shows_db_count(pool: &PgPool) -> Result<usize, Error> {
+    let query = query!(
+        r#"
+            SELECT count(*)
+            FROM trakt_watchlist a
+            JOIN imdb_ratings b ON a.link=b.link
+        "#
+    );
+    let conn = pool.get().await?;
+    let (count,): (i64,) = query.fetch_one(&conn).await?;
+    Ok(count as usize)
+}
+
+// One page of the watchlist ordered by title, with each show's source
+pub async fn get_watchlist_shows_db_page(
+    pool: &PgPool,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<(WatchListShow, Option<TvShowSource>)>, Error> {
+    #[derive(FromSqlRow)]
+    struct WatchlistShowDbPage {
+        link: StackString,
+        title: StackString,
+        year: i32,
+        source: Option<StackString>,
+    }
+    let offset = offset as i64;
+    let limit = limit as i64;
+    let query = query!(
+        r#"
+            SELECT a.link, a.title, a.year, b.source
+            FROM trakt_watchlist a
+            JOIN imdb_ratings b ON a.link=b.link
+            ORDER BY a.title, a.link
+            OFFSET $offset LIMIT $limit
+        "#,
+        offset = offset,
+        limit = limit
+    );
+    let conn = pool.get().await?;
+    let rows: Vec<WatchlistShowDbPage> = query.fetch(&conn).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let source: Option<TvShowSource> = row.source.and_then(|s| s.parse().ok());
+            let show = WatchListShow {
+                link: row.link,
+                title: row.title,
+                year: row.year,
+            };
+            (show, source)
+        })
+        .collect())
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Hash, FromSqlRow)]
 pub struct WatchedEpisode {
     pub title: StackString,
@@ -327,7 +403,13 @@ impl WatchedEpisode {
             episode = self.episode
         );
         let conn = pool.get().await?;
-        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+        query.execute(&conn).await?;
+        UserHook::fire(
+            pool,
+            HookEvent::EpisodeWatched,
+            json!({"link": self.imdb_url, "season": self.season, "episode": self.episode}),
+        );
+        Ok(())
     }
 
     pub async fn delete_episode(&self, pool: &PgPool) -> Result<(), Error> {
@@ -343,6 +425,62 @@ impl WatchedEpisode {
         let conn = pool.get().await?;
         query.execute(&conn).await.map(|_| ()).map_err(Into::into)
     }
+
+    // Marks every aired episode of a season watched, returning the newly inserted episodes
+    pub async fn insert_season(pool: &PgPool, link: &str, season: i32) -> Result<Vec<i32>, Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            r#"
+                INSERT INTO trakt_watched_episodes (link, season, episode)
+                SELECT DISTINCT r.link, e.season, e.episode
+                FROM imdb_episodes e
+                JOIN imdb_ratings r ON e.show = r.show
+                WHERE r.link = $link
+                  AND e.season = $season
+                  AND e.airdate <= current_date
+                  AND NOT EXISTS (
+                      SELECT 1
+                      FROM trakt_watched_episodes w
+                      WHERE w.link = r.link AND w.season = e.season AND w.episode = e.episode
+                  )
+                RETURNING episode
+            "#,
+            link = link,
+            season = season
+        );
+        let episodes: Vec<i32> = tran
+            .query(query.sql(), query.parameters())
+            .await?
+            .iter()
+            .map(|row| row.try_get(0))
+            .collect::<Result<_, _>>()?;
+        tran.commit().await?;
+        for episode in &episodes {
+            UserHook::fire(
+                pool,
+                HookEvent::EpisodeWatched,
+                json!({"link": link, "season": season, "episode": episode}),
+            );
+        }
+        Ok(episodes)
+    }
+
+    pub async fn delete_season(pool: &PgPool, link: &str, season: i32) -> Result<u64, Error> {
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let query = query!(
+            r#"
+                DELETE FROM trakt_watched_episodes
+                WHERE link = $link AND season = $season
+            "#,
+            link = link,
+            season = season
+        );
+        let deleted = tran.execute(query.sql(), query.parameters()).await?;
+        tran.commit().await?;
+        Ok(deleted)
+    }
 }
 
 pub async fn get_watched_shows_db(
@@ -350,21 +488,7 @@ pub async fn get_watched_shows_db(
     show: &str,
     season: Option<i32>,
 ) -> Result<Vec<WatchedEpisode>, Error> {
-    let mut where_vec = Vec::new();
-    if !show.is_empty() {
-        where_vec.push(format!("show='{}'", show));
-    }
-    if let Some(season) = season {
-        where_vec.push(format!("season={}", season));
-    }
-
-    let where_str = if where_vec.is_empty() {
-        "".to_string()
-    } else {
-        format!("WHERE {}", where_vec.join(" AND "))
-    };
-
-    let query = query_dyn!(&format!(
+    let query = query!(
         r#"
             SELECT a.link as imdb_url,
                    b.title,
@@ -372,11 +496,13 @@ pub async fn get_watched_shows_db(
                    a.episode
             FROM trakt_watched_episodes a
             JOIN imdb_ratings b ON a.link = b.link
-            {}
+            WHERE ($show = '' OR b.show = $show)
+              AND ($season::int IS NULL OR a.season = $season)
             ORDER BY 2,3,4
         "#,
-        where_str
-    ))?;
+        show = show,
+        season = season
+    );
     let conn = pool.get().await?;
     query.fetch(&conn).await.map_err(Into::into)
 }
@@ -486,8 +612,14 @@ pub async fn sync_trakt_with_db(
     trakt: &TraktConnection,
     mc: &MovieCollection,
 ) -> Result<(), Error> {
+    if !trakt.is_configured() {
+        mc.stdout.send("Trakt not configured, skipping sync");
+        return Ok(());
+    }
     let watchlist_shows_db = Arc::new(get_watchlist_shows_db(&mc.pool).await?);
     trakt.init().await;
+    let ratings = sync_trakt_ratings(trakt, &mc.pool).await?;
+    mc.stdout.send(format!("trakt ratings {}", ratings));
     let watchlist_shows = trakt.get_watchlist_shows().await?;
     if watchlist_shows.is_empty() {
         return Ok(());
@@ -787,6 +919,17 @@ pub async fn trakt_app_parse(
     pool: &PgPool,
 ) -> Result<(), Error> {
     let mc = MovieCollection::new(config, pool, stdout);
+    if !trakt.is_configured() {
+        match trakt_command {
+            TraktCommands::WatchList if matches!(trakt_action, TraktActions::List) => {}
+            TraktCommands::Watched if matches!(trakt_action, TraktActions::List) => {}
+            TraktCommands::None => {}
+            _ => {
+                mc.stdout.send("Trakt not configured");
+                return mc.stdout.close().await;
+            }
+        }
+    }
     match trakt_command {
         TraktCommands::Calendar => trakt_cal_list(trakt, &mc).await?,
         TraktCommands::WatchList => match trakt_action {
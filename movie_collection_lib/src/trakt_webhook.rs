@@ -0,0 +1,160 @@
+use anyhow::Error;
+use serde::Deserialize;
+use stack_string::StackString;
+
+use crate::{
+    pgpool::PgPool,
+    trakt_utils::{WatchedEpisode, WatchedMovie},
+};
+
+// Trakt itself marks a scrobble as watched once it passes 80%
+const WATCHED_PROGRESS: f64 = 80.0;
+
+#[derive(Deserialize, Debug, Default)]
+struct TraktIds {
+    imdb: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TraktItem {
+    title: StackString,
+    #[serde(default)]
+    ids: TraktIds,
+}
+
+#[derive(Deserialize, Debug)]
+struct TraktEpisode {
+    season: i32,
+    number: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TraktWebhookPayload {
+    action: StackString,
+    progress: Option<f64>,
+    movie: Option<TraktItem>,
+    show: Option<TraktItem>,
+    episode: Option<TraktEpisode>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TraktWatch {
+    Movie(WatchedMovie),
+    Episode(WatchedEpisode),
+}
+
+impl TraktWebhookPayload {
+    pub fn get_from_payload(buf: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(buf).map_err(Into::into)
+    }
+
+    fn is_watched(&self) -> bool {
+        match self.action.as_str() {
+            "scrobble" => self.progress.map_or(false, |p| p >= WATCHED_PROGRESS),
+            "checkin" | "watched" => true,
+            _ => false,
+        }
+    }
+
+    pub fn get_watch(&self) -> Option<TraktWatch> {
+        if !self.is_watched() {
+            return None;
+        }
+        if let (Some(show), Some(episode)) = (&self.show, &self.episode) {
+            let imdb_url = show.ids.imdb.clone()?;
+            Some(TraktWatch::Episode(WatchedEpisode {
+                title: show.title.clone(),
+                imdb_url,
+                episode: episode.number,
+                season: episode.season,
+            }))
+        } else {
+            let movie = self.movie.as_ref()?;
+            let imdb_url = movie.ids.imdb.clone()?;
+            Some(TraktWatch::Movie(WatchedMovie {
+                title: movie.title.clone(),
+                imdb_url,
+            }))
+        }
+    }
+
+    pub async fn record(&self, pool: &PgPool) -> Result<Option<TraktWatch>, Error> {
+        let watch = match self.get_watch() {
+            Some(watch) => watch,
+            None => return Ok(None),
+        };
+        match &watch {
+            TraktWatch::Episode(episode) => {
+                let existing = WatchedEpisode::get_watched_episode(
+                    pool,
+                    &episode.imdb_url,
+                    episode.season,
+                    episode.episode,
+                )
+                .await?;
+                if existing.is_some() {
+                    return Ok(None);
+                }
+                episode.insert_episode(pool).await?;
+            }
+            TraktWatch::Movie(movie) => {
+                if WatchedMovie::get_watched_movie(pool, &movie.imdb_url)
+                    .await?
+                    .is_some()
+                {
+                    return Ok(None);
+                }
+                movie.insert_movie(pool).await?;
+            }
+        }
+        Ok(Some(watch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::{
+        trakt_utils::{WatchedEpisode, WatchedMovie},
+        trakt_webhook::{TraktWatch, TraktWebhookPayload},
+    };
+
+    #[test]
+    fn test_trakt_webhook_payload() -> Result<(), Error> {
+        let buf = br#"{
+            "action": "scrobble",
+            "progress": 92.5,
+            "show": {"title": "The Expanse", "ids": {"trakt": 77199, "imdb": "tt3230854"}},
+            "episode": {"season": 2, "number": 5, "title": "Home"}
+        }"#;
+        let payload = TraktWebhookPayload::get_from_payload(buf)?;
+        let expected = WatchedEpisode {
+            title: "The Expanse".into(),
+            imdb_url: "tt3230854".into(),
+            episode: 5,
+            season: 2,
+        };
+        assert_eq!(payload.get_watch(), Some(TraktWatch::Episode(expected)));
+
+        let buf = br#"{
+            "action": "scrobble",
+            "progress": 12.0,
+            "movie": {"title": "Arrival", "ids": {"imdb": "tt2543164"}}
+        }"#;
+        let payload = TraktWebhookPayload::get_from_payload(buf)?;
+        assert_eq!(payload.get_watch(), None);
+
+        let buf = br#"{
+            "action": "checkin",
+            "movie": {"title": "Arrival", "ids": {"imdb": "tt2543164"}}
+        }"#;
+        let payload = TraktWebhookPayload::get_from_payload(buf)?;
+        let expected = WatchedMovie {
+            title: "Arrival".into(),
+            imdb_url: "tt2543164".into(),
+        };
+        assert_eq!(payload.get_watch(), Some(TraktWatch::Movie(expected)));
+        Ok(())
+    }
+}
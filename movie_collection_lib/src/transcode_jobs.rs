@@ -0,0 +1,566 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+use tokio::fs;
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+
+use crate::{
+    datetime_wrapper::DateTimeWrapper,
+    naivedate_wrapper::NaiveDateWrapper,
+    pgpool::PgPool,
+    transcode_service::{TranscodeOptions, TranscodeServiceRequest},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash)]
+pub enum TranscodeJobStatus {
+    #[serde(rename = "queued")]
+    Queued,
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "finished")]
+    Finished,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+impl fmt::Display for TranscodeJobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Queued => "queued",
+                Self::Running => "running",
+                Self::Finished => "finished",
+                Self::Failed => "failed",
+            }
+        )
+    }
+}
+
+impl FromStr for TranscodeJobStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(Self::Queued),
+            "running" => Ok(Self::Running),
+            "finished" => Ok(Self::Finished),
+            "failed" => Ok(Self::Failed),
+            _ => Err(format_err!("Is not TranscodeJobStatus")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for TranscodeJobStatus {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for TranscodeJobStatus {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TranscodeJob {
+    pub job_type: StackString,
+    pub prefix: StackString,
+    pub queue: StackString,
+    pub input_path: StackString,
+    pub output_path: StackString,
+    pub status: TranscodeJobStatus,
+    pub message: Option<StackString>,
+    pub created_at: DateTimeWrapper,
+    pub started_at: Option<DateTimeWrapper>,
+    pub finished_at: Option<DateTimeWrapper>,
+    pub input_size: Option<i64>,
+    pub preset: Option<StackString>,
+    pub worker: Option<StackString>,
+    pub priority: i16,
+    pub video_codec: Option<StackString>,
+    pub max_height: Option<i32>,
+    pub audio_handling: Option<StackString>,
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Clone, PartialEq, Schema)]
+pub struct TranscodeThroughput {
+    pub preset: StackString,
+    pub worker: StackString,
+    pub jobs: i64,
+    pub total_bytes: i64,
+    pub total_seconds: f64,
+}
+
+impl TranscodeThroughput {
+    pub fn mb_per_sec(&self) -> f64 {
+        if self.total_seconds > 0.0 {
+            self.total_bytes as f64 / 1e6 / self.total_seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Clone, PartialEq, Schema)]
+pub struct TranscodeDailyThroughput {
+    pub day: NaiveDateWrapper,
+    pub preset: StackString,
+    pub jobs: i64,
+    pub total_bytes: i64,
+    pub total_seconds: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Schema)]
+pub struct TranscodeStats {
+    pub throughput: Vec<TranscodeThroughput>,
+    pub daily: Vec<TranscodeDailyThroughput>,
+}
+
+impl TranscodeStats {
+    pub async fn get_stats(pool: &PgPool) -> Result<Self, Error> {
+        let throughput = TranscodeJob::get_throughput(pool).await?;
+        let daily = TranscodeJob::get_daily_throughput(pool).await?;
+        Ok(Self { throughput, daily })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TranscodeEstimate {
+    pub duration: f64,
+    pub eta: f64,
+}
+
+pub fn estimate_jobs(
+    jobs: &[TranscodeJob],
+    throughput: &[TranscodeThroughput],
+) -> HashMap<StackString, TranscodeEstimate> {
+    let mut rates: HashMap<&str, (i64, f64)> = HashMap::new();
+    for t in throughput {
+        let rate = rates.entry(t.preset.as_str()).or_default();
+        rate.0 += t.total_bytes;
+        rate.1 += t.total_seconds;
+    }
+    let mut elapsed: HashMap<&str, f64> = HashMap::new();
+    let mut estimates = HashMap::new();
+    for job in jobs {
+        let rate = job
+            .preset
+            .as_ref()
+            .and_then(|p| rates.get(p.as_str()))
+            .filter(|(bytes, seconds)| *bytes > 0 && *seconds > 0.0);
+        if let (Some((bytes, seconds)), Some(size)) = (rate, job.input_size) {
+            let duration = size as f64 * seconds / *bytes as f64;
+            let eta = elapsed.entry(job.queue.as_str()).or_default();
+            *eta += duration;
+            estimates.insert(
+                job.prefix.clone(),
+                TranscodeEstimate {
+                    duration,
+                    eta: *eta,
+                },
+            );
+        }
+    }
+    estimates
+}
+
+pub fn format_duration(seconds: f64) -> StackString {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+    .into()
+}
+
+pub fn worker_name() -> StackString {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map_or_else(|_| "localhost".into(), |s| s.trim().into())
+}
+
+impl TranscodeJob {
+    pub fn get_request(&self) -> Result<TranscodeServiceRequest, Error> {
+        let mut request = TranscodeServiceRequest::new(
+            self.job_type.parse()?,
+            &self.prefix,
+            Path::new(self.input_path.as_str()),
+            Path::new(self.output_path.as_str()),
+        );
+        request.priority = self.priority.clamp(0, i16::from(u8::MAX)) as u8;
+        request.options = TranscodeOptions {
+            video_codec: self.video_codec.clone(),
+            max_height: self.max_height,
+            audio_handling: self.audio_handling.clone(),
+        };
+        Ok(request)
+    }
+
+    pub async fn get_job(
+        pool: &PgPool,
+        request: &TranscodeServiceRequest,
+    ) -> Result<Option<Self>, Error> {
+        let job_type = request.job_type.to_string();
+        let query = query!(
+            r#"
+                SELECT job_type, prefix, queue, input_path, output_path, status, message,
+                       created_at, started_at, finished_at, input_size, preset, worker,
+                       priority, video_codec, max_height, audio_handling
+                FROM transcode_jobs
+                WHERE job_type = $job_type AND prefix = $prefix
+            "#,
+            job_type = job_type,
+            prefix = request.prefix
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_jobs_by_status(
+        pool: &PgPool,
+        queue: Option<&str>,
+        statuses: &[TranscodeJobStatus],
+    ) -> Result<Vec<Self>, Error> {
+        let statuses: Vec<_> = statuses.iter().map(ToString::to_string).collect();
+        let query = query!(
+            r#"
+                SELECT job_type, prefix, queue, input_path, output_path, status, message,
+                       created_at, started_at, finished_at, input_size, preset, worker,
+                       priority, video_codec, max_height, audio_handling
+                FROM transcode_jobs
+                WHERE status = ANY($statuses) AND ($queue::text IS NULL OR queue = $queue)
+                ORDER BY priority DESC, created_at
+            "#,
+            statuses = statuses,
+            queue = queue
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    // Atomically moves the highest priority queued job to running for this worker, so
+    // concurrent workers never pick the same job
+    pub async fn claim_next_queued(pool: &PgPool, queue: &str) -> Result<Option<Self>, Error> {
+        let worker = worker_name();
+        let query = query!(
+            r#"
+                UPDATE transcode_jobs
+                SET status='running', worker=$worker, message=null, started_at=now(),
+                    finished_at=null, last_modified=now()
+                WHERE (job_type, prefix) = (
+                    SELECT job_type, prefix
+                    FROM transcode_jobs
+                    WHERE status = 'queued' AND queue = $queue
+                    ORDER BY priority DESC, created_at
+                    LIMIT 1
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING job_type, prefix, queue, input_path, output_path, status, message,
+                          created_at, started_at, finished_at, input_size, preset, worker,
+                          priority, video_codec, max_height, audio_handling
+            "#,
+            queue = queue,
+            worker = worker
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn set_priority(pool: &PgPool, prefix: &str, priority: u8) -> Result<u64, Error> {
+        let priority = i16::from(priority);
+        let query = query!(
+            r#"
+                UPDATE transcode_jobs
+                SET priority=$priority, last_modified=now()
+                WHERE prefix = $prefix AND status = 'queued'
+            "#,
+            prefix = prefix,
+            priority = priority
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn queue_job(
+        pool: &PgPool,
+        queue: &str,
+        request: &TranscodeServiceRequest,
+        preset: &str,
+    ) -> Result<(), Error> {
+        let job_type = request.job_type.to_string();
+        let input_size = fs::metadata(&request.input_path)
+            .await
+            .ok()
+            .map(|m| m.len() as i64);
+        let input_path = request.input_path.to_string_lossy();
+        let output_path = request.output_path.to_string_lossy();
+        let priority = i16::from(request.priority);
+        let query = query!(
+            r#"
+                INSERT INTO transcode_jobs
+                    (job_type, prefix, queue, input_path, output_path, status, input_size,
+                     preset, priority, video_codec, max_height, audio_handling, created_at,
+                     last_modified)
+                VALUES
+                    ($job_type, $prefix, $queue, $input_path, $output_path, $status, $input_size,
+                     $preset, $priority, $video_codec, $max_height, $audio_handling, now(),
+                     now())
+                ON CONFLICT (job_type, prefix) DO UPDATE
+                SET queue=$queue, input_path=$input_path, output_path=$output_path,
+                    status=$status, message=null, input_size=$input_size, preset=$preset,
+                    priority=$priority, video_codec=$video_codec, max_height=$max_height,
+                    audio_handling=$audio_handling, worker=null, created_at=now(),
+                    started_at=null, finished_at=null, last_modified=now()
+            "#,
+            job_type = job_type,
+            prefix = request.prefix,
+            queue = queue,
+            input_path = input_path,
+            output_path = output_path,
+            status = TranscodeJobStatus::Queued,
+            input_size = input_size,
+            preset = preset,
+            priority = priority,
+            video_codec = request.options.video_codec,
+            max_height = request.options.max_height,
+            audio_handling = request.options.audio_handling
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub async fn set_status(
+        pool: &PgPool,
+        request: &TranscodeServiceRequest,
+        status: TranscodeJobStatus,
+        message: Option<&str>,
+    ) -> Result<(), Error> {
+        let job_type = request.job_type.to_string();
+        let worker = worker_name();
+        let query = query!(
+            r#"
+                UPDATE transcode_jobs
+                SET status=$status, message=$message,
+                    started_at=CASE WHEN $status = 'running' THEN now() ELSE started_at END,
+                    finished_at=CASE WHEN $status IN ('finished', 'failed') THEN now() END,
+                    worker=CASE WHEN $status = 'running' THEN $worker ELSE worker END,
+                    last_modified=now()
+                WHERE job_type = $job_type AND prefix = $prefix
+            "#,
+            job_type = job_type,
+            prefix = request.prefix,
+            status = status,
+            message = message,
+            worker = worker
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub async fn get_throughput(pool: &PgPool) -> Result<Vec<TranscodeThroughput>, Error> {
+        let query = query!(
+            r#"
+                SELECT preset, coalesce(worker, '') AS worker, count(*) AS jobs,
+                       sum(input_size)::BIGINT AS total_bytes,
+                       sum(extract(epoch FROM finished_at - started_at))::DOUBLE PRECISION
+                            AS total_seconds
+                FROM transcode_jobs
+                WHERE status = 'finished' AND preset IS NOT NULL AND input_size IS NOT NULL
+                    AND started_at IS NOT NULL AND finished_at > started_at
+                GROUP BY 1, 2
+                ORDER BY 1, 2
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_daily_throughput(
+        pool: &PgPool,
+    ) -> Result<Vec<TranscodeDailyThroughput>, Error> {
+        let query = query!(
+            r#"
+                SELECT date(finished_at) AS day, preset, count(*) AS jobs,
+                       sum(input_size)::BIGINT AS total_bytes,
+                       sum(extract(epoch FROM finished_at - started_at))::DOUBLE PRECISION
+                            AS total_seconds
+                FROM transcode_jobs
+                WHERE status = 'finished' AND preset IS NOT NULL AND input_size IS NOT NULL
+                    AND started_at IS NOT NULL AND finished_at > started_at
+                GROUP BY 1, 2
+                ORDER BY 1, 2
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        transcode_jobs::{
+            estimate_jobs, format_duration, TranscodeJob, TranscodeJobStatus, TranscodeThroughput,
+        },
+        transcode_service::{JobType, TranscodeServiceRequest},
+    };
+
+    fn queued_job(prefix: &str, input_size: i64) -> TranscodeJob {
+        TranscodeJob {
+            job_type: "transcode".into(),
+            prefix: prefix.into(),
+            queue: "transcode_work_queue".into(),
+            input_path: format!("/tmp/{}.mkv", prefix).into(),
+            output_path: format!("/tmp/{}.mp4", prefix).into(),
+            status: TranscodeJobStatus::Queued,
+            message: None,
+            created_at: chrono::Utc::now().into(),
+            started_at: None,
+            finished_at: None,
+            input_size: Some(input_size),
+            preset: Some("Android 480p30".into()),
+            worker: None,
+            priority: 5,
+            video_codec: None,
+            max_height: None,
+            audio_handling: None,
+        }
+    }
+
+    #[test]
+    fn test_transcode_job_status() {
+        for status in &[
+            TranscodeJobStatus::Queued,
+            TranscodeJobStatus::Running,
+            TranscodeJobStatus::Finished,
+            TranscodeJobStatus::Failed,
+        ] {
+            assert_eq!(
+                status.to_string().parse::<TranscodeJobStatus>().ok(),
+                Some(*status)
+            );
+        }
+        assert!("paused".parse::<TranscodeJobStatus>().is_err());
+    }
+
+    #[test]
+    fn test_get_request() {
+        let job = TranscodeJob {
+            job_type: "move".into(),
+            prefix: "mr_robot_s01_ep01".into(),
+            queue: "remcom_worker_queue".into(),
+            input_path: "/tmp/mr_robot_s01_ep01.mp4".into(),
+            output_path: "/tmp/television/mr_robot_s01_ep01.mp4".into(),
+            status: TranscodeJobStatus::Queued,
+            message: None,
+            created_at: chrono::Utc::now().into(),
+            started_at: None,
+            finished_at: None,
+            input_size: None,
+            preset: None,
+            worker: None,
+            priority: 5,
+            video_codec: None,
+            max_height: None,
+            audio_handling: None,
+        };
+        let mut expected = TranscodeServiceRequest::new(
+            JobType::Move,
+            "mr_robot_s01_ep01",
+            Path::new("/tmp/mr_robot_s01_ep01.mp4"),
+            Path::new("/tmp/television/mr_robot_s01_ep01.mp4"),
+        );
+        assert_eq!(job.get_request().ok(), Some(expected.clone()));
+
+        let job = TranscodeJob { priority: 9, ..job };
+        expected.priority = 9;
+        assert_eq!(job.get_request().ok(), Some(expected.clone()));
+
+        let job = TranscodeJob {
+            video_codec: Some("x265".into()),
+            max_height: Some(720),
+            ..job
+        };
+        expected.options.video_codec = Some("x265".into());
+        expected.options.max_height = Some(720);
+        assert_eq!(job.get_request().ok(), Some(expected));
+    }
+
+    #[test]
+    fn test_estimate_jobs() {
+        let throughput = vec![
+            TranscodeThroughput {
+                preset: "Android 480p30".into(),
+                worker: "worker0".into(),
+                jobs: 2,
+                total_bytes: 3_000_000_000,
+                total_seconds: 1000.0,
+            },
+            TranscodeThroughput {
+                preset: "Android 480p30".into(),
+                worker: "worker1".into(),
+                jobs: 1,
+                total_bytes: 1_000_000_000,
+                total_seconds: 1000.0,
+            },
+        ];
+        assert!((throughput[0].mb_per_sec() - 3000.0 / 1000.0).abs() < 1e-6);
+        let mut unknown = queued_job("mr_robot_s01_ep03", 100);
+        unknown.preset = Some("Very Fast 480p30".into());
+        let jobs = vec![
+            queued_job("mr_robot_s01_ep01", 2_000_000_000),
+            unknown,
+            queued_job("mr_robot_s01_ep02", 1_000_000_000),
+        ];
+        let estimates = estimate_jobs(&jobs, &throughput);
+        assert_eq!(estimates.len(), 2);
+        let first = estimates["mr_robot_s01_ep01"];
+        let second = estimates["mr_robot_s01_ep02"];
+        assert!((first.duration - 1000.0).abs() < 1e-6);
+        assert!((second.duration - 500.0).abs() < 1e-6);
+        assert!((second.eta - 1500.0).abs() < 1e-6);
+        assert_eq!(format_duration(second.eta).as_str(), "0:25:00");
+    }
+}
@@ -7,31 +7,91 @@ use serde::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
 use stack_string::StackString;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     ffi::OsStr,
     fmt,
     future::Future,
     path::{Path, PathBuf},
     process::Stdio,
-    str,
+    str::{self, FromStr},
+    time::Instant,
 };
 use stdout_channel::StdoutChannel;
+use thiserror::Error as ThisError;
 use tokio::{
     fs::{self, File, OpenOptions},
     io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
     process::Command,
     task::{spawn, spawn_blocking, JoinHandle},
 };
+use tracing::{debug, info_span, Instrument};
+use uuid::Uuid;
 
 use crate::{
-    config::Config, make_list::FileLists, make_queue::make_queue_worker,
-    movie_collection::MovieCollection, pgpool::PgPool, utils::parse_file_stem,
+    config::Config,
+    make_list::FileLists,
+    make_queue::make_queue_worker,
+    metrics_exporter::MetricsExporter,
+    movie_collection::MovieCollection,
+    notifications::Notifier,
+    pgpool::PgPool,
+    show_settings::ShowSettings,
+    transcode_jobs::{
+        estimate_jobs, format_duration, worker_name, TranscodeEstimate, TranscodeJob,
+        TranscodeJobStatus,
+    },
+    user_hooks::{HookEvent, UserHook},
+    utils::{parse_file_stem, walk_directory},
+    watch_folder::PendingMove,
 };
 
+const TRANSCODE_PRESET: &str = "Android 480p30";
+pub const DEFAULT_TRANSCODE_PRIORITY: u8 = 5;
+
+fn default_priority() -> u8 {
+    DEFAULT_TRANSCODE_PRIORITY
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+pub struct TranscodeOptions {
+    pub video_codec: Option<StackString>,
+    pub max_height: Option<i32>,
+    pub audio_handling: Option<StackString>,
+}
+
+impl TranscodeOptions {
+    // Extra HandBrakeCLI flags applied on top of the preset
+    pub fn handbrake_args(&self) -> Vec<StackString> {
+        let mut args = Vec::new();
+        if let Some(video_codec) = &self.video_codec {
+            args.push("--encoder".into());
+            args.push(video_codec.clone());
+        }
+        if let Some(max_height) = self.max_height {
+            args.push("--maxHeight".into());
+            args.push(max_height.to_string().into());
+        }
+        if let Some(audio_handling) = &self.audio_handling {
+            args.push("--aencoder".into());
+            args.push(audio_handling.clone());
+        }
+        args
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum TranscodeError {
+    #[error("Transcode job not found: {0}")]
+    NotFound(StackString),
+    #[error(transparent)]
+    Internal(#[from] Error),
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum JobType {
     Transcode,
     Move,
+    Offline,
 }
 
 impl JobType {
@@ -39,6 +99,7 @@ impl JobType {
         match self {
             Self::Transcode => "transcode",
             Self::Move => "move",
+            Self::Offline => "offline",
         }
     }
 }
@@ -49,12 +110,29 @@ impl fmt::Display for JobType {
     }
 }
 
+impl FromStr for JobType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transcode" => Ok(Self::Transcode),
+            "move" => Ok(Self::Move),
+            "offline" => Ok(Self::Offline),
+            _ => Err(format_err!("Is not JobType")),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct TranscodeServiceRequest {
     pub job_type: JobType,
     pub prefix: StackString,
     pub input_path: PathBuf,
     pub output_path: PathBuf,
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+    #[serde(default)]
+    pub options: TranscodeOptions,
 }
 
 impl fmt::Display for TranscodeServiceRequest {
@@ -89,10 +167,16 @@ impl TranscodeServiceRequest {
             prefix: prefix.into(),
             input_path: input_path.to_path_buf(),
             output_path: output_path.to_path_buf(),
+            priority: DEFAULT_TRANSCODE_PRIORITY,
+            options: TranscodeOptions::default(),
         }
     }
 
-    pub fn create_transcode_request(config: &Config, input_path: &Path) -> Result<Self, Error> {
+    pub fn create_transcode_request(
+        config: &Config,
+        input_path: &Path,
+        settings: Option<&ShowSettings>,
+    ) -> Result<Self, Error> {
         let input_path = input_path.to_path_buf();
         let fstem = input_path
             .file_stem()
@@ -105,14 +189,49 @@ impl TranscodeServiceRequest {
             prefix,
             input_path,
             output_path: output_file,
+            priority: DEFAULT_TRANSCODE_PRIORITY,
+            options: settings
+                .map(ShowSettings::transcode_options)
+                .unwrap_or_default(),
         })
     }
 
+    pub fn create_season_request(config: &Config, directory: &Path) -> Result<Vec<Self>, Error> {
+        if !directory.is_dir() {
+            return Err(format_err!(
+                "Directory {} does not exist",
+                directory.to_string_lossy()
+            ));
+        }
+        let mut files: Vec<_> = walk_directory(directory, &config.suffixes)?
+            .into_iter()
+            .map(|path| {
+                let file_stem = path
+                    .file_stem()
+                    .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+                let (_, season, episode) = parse_file_stem(&file_stem);
+                (season, episode, path)
+            })
+            .collect();
+        files.sort_by(|(s0, e0, p0), (s1, e1, p1)| {
+            (*s0 < 0, *s0, *e0, p0).cmp(&(*s1 < 0, *s1, *e1, p1))
+        });
+        files
+            .into_iter()
+            .map(|(_, _, path)| Self::create_transcode_request(config, &path, None))
+            .collect()
+    }
+
+    pub fn create_offline_request(token: &str, input_path: &Path, output_path: &Path) -> Self {
+        Self::new(JobType::Offline, token, input_path, output_path)
+    }
+
     pub async fn create_remcom_request(
         config: &Config,
         path: impl AsRef<Path>,
         directory: Option<impl AsRef<Path>>,
         unwatched: bool,
+        settings: Option<&ShowSettings>,
     ) -> Result<Self, Error> {
         let path = path.as_ref();
         let ext = path
@@ -128,7 +247,7 @@ impl TranscodeServiceRequest {
                     .join("Documents")
                     .join("movies")
                     .join(d);
-                println!("{}", d.to_string_lossy());
+                debug!("{}", d.to_string_lossy());
                 if !d.exists() {
                     return Err(format_err!(
                         "Directory {} does not exist",
@@ -136,6 +255,13 @@ impl TranscodeServiceRequest {
                     ));
                 }
                 d
+            } else if let Some(d) =
+                settings.and_then(|s| s.output_directory(config, &file_stem.to_string_lossy()))
+            {
+                if !d.exists() {
+                    fs::create_dir_all(&d).await?;
+                }
+                d
             } else if unwatched {
                 let d = config.preferred_dir.join("television").join("unwatched");
                 if !d.exists() {
@@ -179,9 +305,11 @@ impl TranscodeServiceRequest {
                 prefix,
                 input_path,
                 output_path,
+                priority: DEFAULT_TRANSCODE_PRIORITY,
+                options: TranscodeOptions::default(),
             })
         } else {
-            Self::create_transcode_request(config, path)
+            Self::create_transcode_request(config, path, settings)
         }
     }
 
@@ -206,7 +334,9 @@ impl TranscodeServiceRequest {
 
     pub fn get_cmd_path(&self) -> PathBuf {
         match self.job_type {
-            JobType::Transcode => Path::new("/usr/bin/transcode-avi").to_path_buf(),
+            JobType::Transcode | JobType::Offline => {
+                Path::new("/usr/bin/transcode-avi").to_path_buf()
+            }
             JobType::Move => Path::new("/usr/bin/remcom").to_path_buf(),
         }
     }
@@ -217,6 +347,9 @@ impl TranscodeServiceRequest {
             JobType::Move => job_dir(config)
                 .join(&format!("{}_copy", self.prefix))
                 .with_extension("json"),
+            JobType::Offline => job_dir(config)
+                .join(&format!("{}_offline", self.prefix))
+                .with_extension("json"),
         }
     }
 
@@ -241,6 +374,7 @@ pub struct TranscodeService {
     pub pool: PgPool,
     pub stdout: StdoutChannel<StackString>,
     pub queue: StackString,
+    pub metrics: MetricsExporter,
 }
 
 impl TranscodeService {
@@ -255,6 +389,15 @@ impl TranscodeService {
             pool: pool.clone(),
             stdout: stdout.clone(),
             queue: queue.into(),
+            metrics: MetricsExporter::new(config),
+        }
+    }
+
+    fn get_preset(&self, job_type: JobType) -> &str {
+        match job_type {
+            JobType::Transcode => TRANSCODE_PRESET,
+            JobType::Offline => self.config.offline_preset.as_str(),
+            JobType::Move => "move",
         }
     }
 
@@ -272,22 +415,175 @@ impl TranscodeService {
             &serde_json::to_vec(&payload)?,
         )
         .await?;
+        let preset = self.get_preset(payload.job_type);
+        TranscodeJob::queue_job(&self.pool, &self.queue, payload, preset).await?;
         let payload = serde_json::to_vec(&payload)?;
         publish(payload).await
     }
 
+    pub async fn resume_jobs<F, T>(&self, publish: F) -> Result<usize, Error>
+    where
+        F: Fn(Vec<u8>) -> T,
+        T: Future<Output = Result<(), Error>>,
+    {
+        let is_remcom = self.queue == self.config.remcom_queue;
+        let mut published = 0;
+        for request in get_upcoming_jobs(job_dir(&self.config)).await? {
+            if (request.job_type == JobType::Move) == is_remcom
+                && TranscodeJob::get_job(&self.pool, &request).await?.is_none()
+            {
+                let preset = self.get_preset(request.job_type);
+                TranscodeJob::queue_job(&self.pool, &self.queue, &request, preset).await?;
+                publish(serde_json::to_vec(&request)?).await?;
+                published += 1;
+            }
+        }
+        // Queued jobs still have their message on the durable queue, only the jobs this
+        // worker was running when it stopped need a new one
+        let worker = worker_name();
+        let statuses = [TranscodeJobStatus::Running];
+        let queue = Some(self.queue.as_str());
+        let jobs = TranscodeJob::get_jobs_by_status(&self.pool, queue, &statuses).await?;
+        for job in jobs.iter().filter(|j| j.worker.as_ref() == Some(&worker)) {
+            let request = job.get_request()?;
+            TranscodeJob::set_status(&self.pool, &request, TranscodeJobStatus::Queued, None)
+                .await?;
+            publish(serde_json::to_vec(&request)?).await?;
+            published += 1;
+        }
+        Ok(published)
+    }
+
+    // Every queued job has exactly one message on the queue, so treat each
+    // delivery as a slot and run the highest priority job that is still queued. A
+    // delivery with nothing left to claim belongs to a job that was cancelled or already
+    // run by another worker
     pub async fn process_data(&self, data: &[u8]) -> Result<(), Error> {
         let payload: TranscodeServiceRequest = serde_json::from_slice(&data)?;
-        match payload.job_type {
+        let payload = match TranscodeJob::claim_next_queued(&self.pool, &self.queue).await? {
+            Some(job) => job.get_request()?,
+            None => {
+                debug!(
+                    "no queued job to run for {} {}",
+                    payload.job_type, payload.prefix
+                );
+                return Ok(());
+            }
+        };
+        let span = info_span!(
+            "transcode_job",
+            request_id = %Uuid::new_v4(),
+            job_type = %payload.job_type,
+            prefix = %payload.prefix,
+        );
+        self.run_job(payload).instrument(span).await
+    }
+
+    // The job has already been claimed as running by `process_data`
+    async fn run_job(&self, payload: TranscodeServiceRequest) -> Result<(), Error> {
+        let start = Instant::now();
+        let result = match payload.job_type {
             JobType::Transcode => {
-                self.run_transcode(&payload.prefix, &payload.input_path, &payload.output_path)
-                    .await
+                self.run_transcode(
+                    &payload.prefix,
+                    &payload.input_path,
+                    &payload.output_path,
+                    &payload.options,
+                )
+                .await
             }
             JobType::Move => {
                 self.run_move(&payload.prefix, &payload.input_path, &payload.output_path)
                     .await
             }
+            JobType::Offline => {
+                self.run_offline(&payload.prefix, &payload.input_path, &payload.output_path)
+                    .await
+            }
+        };
+        let duration = start.elapsed().as_secs_f64();
+        self.metrics
+            .export_transcode_job(&payload, duration, result.is_ok())
+            .await;
+        match &result {
+            Ok(_) => {
+                TranscodeJob::set_status(&self.pool, &payload, TranscodeJobStatus::Finished, None)
+                    .await?;
+                UserHook::fire(
+                    &self.pool,
+                    HookEvent::TranscodeFinished,
+                    serde_json::to_value(&payload)?,
+                );
+                Notifier::new(&self.config).fire_transcode_finished(&self.pool, &payload.prefix);
+            }
+            Err(e) => {
+                let cancelled = TranscodeJob::get_job(&self.pool, &payload)
+                    .await?
+                    .map_or(false, |job| job.status == TranscodeJobStatus::Failed);
+                if !cancelled {
+                    let message = e.to_string();
+                    TranscodeJob::set_status(
+                        &self.pool,
+                        &payload,
+                        TranscodeJobStatus::Failed,
+                        Some(&message),
+                    )
+                    .await?;
+                }
+            }
+        }
+        result
+    }
+
+    pub async fn cancel_job(&self, filename: &str) -> Result<TranscodeJob, TranscodeError> {
+        let prefix = Path::new(filename)
+            .file_stem()
+            .ok_or_else(|| format_err!("No file stem"))?
+            .to_string_lossy()
+            .into_owned();
+        let statuses = [TranscodeJobStatus::Running];
+        let (job, proc_prefix) = TranscodeJob::get_jobs_by_status(&self.pool, None, &statuses)
+            .await?
+            .into_iter()
+            .find_map(|job| {
+                let proc_prefix = if job.job_type.as_str() == JobType::Offline.get_str() {
+                    format!("{}_offline", job.prefix)
+                } else {
+                    job.prefix.to_string()
+                };
+                if job.prefix.as_str() == prefix || proc_prefix == prefix {
+                    Some((job, proc_prefix))
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| TranscodeError::NotFound(format!("running job {}", prefix).into()))?;
+        let pids: Vec<_> = get_procs()?
+            .into_iter()
+            .filter(|p| p.prefix.as_deref() == Some(proc_prefix.as_str()))
+            .map(|p| p.pid)
+            .collect();
+        if pids.is_empty() {
+            return Err(TranscodeError::NotFound(
+                format!("running process {}", prefix).into(),
+            ));
         }
+        let request = job.get_request()?;
+        TranscodeJob::set_status(
+            &self.pool,
+            &request,
+            TranscodeJobStatus::Failed,
+            Some("cancelled"),
+        )
+        .await?;
+        for pid in pids {
+            Command::new("kill")
+                .args(&["-TERM", &pid.to_string()])
+                .status()
+                .await
+                .map_err(Error::from)?;
+        }
+        Ok(job)
     }
 
     async fn output_to_file<T>(
@@ -316,6 +612,7 @@ impl TranscodeService {
         prefix: &str,
         input_file: &Path,
         output_file: &Path,
+        options: &TranscodeOptions,
     ) -> Result<(), Error> {
         let script_file = job_dir(&self.config).join(&prefix).with_extension("json");
         if script_file.exists() {
@@ -345,8 +642,9 @@ impl TranscodeService {
                 "-o",
                 output_file.to_string_lossy().as_ref(),
                 "--preset",
-                "Android 480p30",
+                TRANSCODE_PRESET,
             ])
+            .args(options.handbrake_args().iter().map(StackString::as_str))
             .kill_on_drop(true)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -363,10 +661,17 @@ impl TranscodeService {
             spawn(async move { Self::output_to_file(reader, &stderr_path, b'\n').await });
 
         let status = p.wait().await?;
-        println!("Handbrake exited with {}", status);
+        debug!("Handbrake exited with {}", status);
         stdout_task.await??;
         stderr_task.await??;
 
+        if !status.success() {
+            if output_file.exists() {
+                fs::remove_file(&output_file).await?;
+            }
+            return Err(format_err!("Handbrake exited with {}", status));
+        }
+
         if output_file.exists() && fs::rename(&output_file, &output_path).await.is_err() {
             fs::copy(&output_file, &output_path).await?;
             fs::remove_file(&output_file).await?;
@@ -389,6 +694,49 @@ impl TranscodeService {
         Ok(())
     }
 
+    async fn run_offline(
+        &self,
+        token: &str,
+        input_file: &Path,
+        output_file: &Path,
+    ) -> Result<(), Error> {
+        let script_file = job_dir(&self.config)
+            .join(&format!("{}_offline", token))
+            .with_extension("json");
+        if script_file.exists() {
+            fs::remove_file(&script_file).await?;
+        }
+        if !input_file.exists() {
+            return Err(format_err!("{:?} does not exist", input_file));
+        }
+        let tmp_file = tmp_dir(&self.config).join(&format!("{}_offline.mp4", token));
+
+        let output = Command::new("HandBrakeCLI")
+            .args(&[
+                "-i",
+                input_file.to_string_lossy().as_ref(),
+                "-o",
+                tmp_file.to_string_lossy().as_ref(),
+                "--preset",
+                self.config.offline_preset.as_str(),
+            ])
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() || !tmp_file.exists() {
+            return Err(format_err!("Offline transcode failed {}", output.status));
+        }
+
+        if let Some(parent) = output_file.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        if fs::rename(&tmp_file, &output_file).await.is_err() {
+            fs::copy(&tmp_file, &output_file).await?;
+            fs::remove_file(&tmp_file).await?;
+        }
+        Ok(())
+    }
+
     async fn run_move(
         &self,
         show: &str,
@@ -536,6 +884,16 @@ pub struct ProcInfo {
     pub name: StackString,
     pub exe: PathBuf,
     pub cmdline: Vec<StackString>,
+    pub prefix: Option<StackString>,
+}
+
+// HandBrakeCLI is run with `-o <output>`, the output file stem identifies the job
+fn job_prefix(cmdline: &[StackString]) -> Option<StackString> {
+    let idx = cmdline.iter().position(|arg| arg.as_str() == "-o")?;
+    let output = cmdline.get(idx + 1)?;
+    Path::new(output.as_str())
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned().into())
 }
 
 impl ProcInfo {
@@ -572,6 +930,9 @@ pub struct TranscodeStatus {
     pub upcoming_jobs: Vec<TranscodeServiceRequest>,
     pub current_jobs: Vec<(PathBuf, StackString)>,
     pub finished_jobs: Vec<PathBuf>,
+    pub failed_jobs: Vec<TranscodeJob>,
+    pub estimates: HashMap<StackString, TranscodeEstimate>,
+    pub pending_moves: Vec<PendingMove>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -592,7 +953,72 @@ impl fmt::Display for ProcStatus {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct TranscodeProgress {
+    pub current: BTreeMap<StackString, StackString>,
+    pub finished: BTreeSet<StackString>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct TranscodeProgressDiff {
+    pub current: BTreeMap<StackString, StackString>,
+    pub current_removed: Vec<StackString>,
+    pub finished: Vec<StackString>,
+}
+
+impl TranscodeProgressDiff {
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty() && self.current_removed.is_empty() && self.finished.is_empty()
+    }
+}
+
+fn file_name_key(p: &Path) -> StackString {
+    p.file_name()
+        .unwrap_or_else(|| OsStr::new(""))
+        .to_string_lossy()
+        .into_owned()
+        .into()
+}
+
+impl TranscodeProgress {
+    pub fn diff(&self, other: &Self) -> TranscodeProgressDiff {
+        let current = other
+            .current
+            .iter()
+            .filter(|(k, v)| self.current.get(*k) != Some(*v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let current_removed = self
+            .current
+            .keys()
+            .filter(|k| !other.current.contains_key(*k))
+            .cloned()
+            .collect();
+        let finished = other.finished.difference(&self.finished).cloned().collect();
+        TranscodeProgressDiff {
+            current,
+            current_removed,
+            finished,
+        }
+    }
+}
+
 impl TranscodeStatus {
+    pub fn get_progress(&self) -> TranscodeProgress {
+        TranscodeProgress {
+            current: self
+                .current_jobs
+                .iter()
+                .map(|(p, s)| (file_name_key(p), s.clone()))
+                .collect(),
+            finished: self
+                .finished_jobs
+                .iter()
+                .map(|p| file_name_key(p))
+                .collect(),
+        }
+    }
+
     pub fn get_proc_map(&self) -> HashMap<StackString, Option<ProcStatus>> {
         let upcoming = self.upcoming_jobs.iter().filter_map(|j| {
             j.input_path.file_name().map(|f| {
@@ -680,12 +1106,13 @@ impl TranscodeStatus {
             )
         }
 
+        output.extend(PendingMove::get_table(&self.pending_moves));
         if !self.procs.is_empty() {
             output.push("Running procs:<br>".into());
             output.push(r#"<table border="1" class="dataframe">"#.into());
             output.push(
                 format!(
-                    r#"<thead><tr><th>{}</th></tr></thead>"#,
+                    r#"<thead><tr><th>{}</th><th>Action</th></tr></thead>"#,
                     ProcInfo::get_header().join("</th><th>")
                 )
                 .into(),
@@ -695,7 +1122,15 @@ impl TranscodeStatus {
                     r#"<tbody><tr><td>{}</td></tr></tbody>"#,
                     self.procs
                         .iter()
-                        .map(|p| p.get_html().join("</td><td>"))
+                        .map(|p| {
+                            let cancel = p.prefix.as_ref().map_or_else(String::new, |prefix| {
+                                format!(
+                                    r#"<button type="submit" onclick="cancel_transcode('{}');"> cancel </button>"#,
+                                    prefix
+                                )
+                            });
+                            format!("{}</td><td>{}", p.get_html().join("</td><td>"), cancel)
+                        })
                         .join("</td></tr><tr><td>")
                 )
                 .into(),
@@ -707,7 +1142,7 @@ impl TranscodeStatus {
             output.push(r#"<table border="1" class="dataframe">"#.into());
             output.push(
                 format!(
-                    r#"<thead><tr><th>{}</th></tr></thead>"#,
+                    r#"<thead><tr><th>{}</th><th>Estimate</th><th>ETA</th></tr></thead>"#,
                     TranscodeServiceRequest::get_header().join("</th><th>")
                 )
                 .into(),
@@ -717,7 +1152,18 @@ impl TranscodeStatus {
                     r#"<tbody><tr><td>{}</td></tr></tbody>"#,
                     self.upcoming_jobs
                         .iter()
-                        .map(|t| { t.get_html().join("</td><td>") })
+                        .map(|t| {
+                            let (duration, eta) = match self.estimates.get(&t.prefix) {
+                                Some(e) => (format_duration(e.duration), format_duration(e.eta)),
+                                None => ("".into(), "".into()),
+                            };
+                            format!(
+                                "{}</td><td>{}</td><td>{}",
+                                t.get_html().join("</td><td>"),
+                                duration,
+                                eta
+                            )
+                        })
                         .join("</td></tr><tr><td>")
                 )
                 .into(),
@@ -752,6 +1198,29 @@ impl TranscodeStatus {
             );
             output.push("</table>".into());
         }
+        if !self.failed_jobs.is_empty() {
+            output.push("Failed jobs:<br>".into());
+            output.push(r#"<table border="1" class="dataframe">"#.into());
+            output.push(
+                r#"<thead><tr><th>Job Type</th><th>Prefix</th><th>Error</th></tr></thead>"#.into(),
+            );
+            output.push(
+                format!(
+                    r#"<tbody><tr><td>{}</td></tr></tbody>"#,
+                    self.failed_jobs
+                        .iter()
+                        .map(|j| format!(
+                            "{}</td><td>{}</td><td>{}",
+                            j.job_type,
+                            j.prefix,
+                            j.message.as_ref().map_or("", StackString::as_str)
+                        ))
+                        .join("</td></tr><tr><td>")
+                )
+                .into(),
+            );
+            output.push("</table>".into());
+        }
         output
     }
 }
@@ -795,6 +1264,31 @@ impl fmt::Display for TranscodeStatus {
                     .join("\n")
             )?;
         }
+        if !self.failed_jobs.is_empty() {
+            write!(
+                f,
+                "Failed jobs:\n\n{}\n\n",
+                self.failed_jobs
+                    .iter()
+                    .map(|j| format!(
+                        "{}\t{}\t{}",
+                        j.job_type,
+                        j.prefix,
+                        j.message.as_ref().map_or("", StackString::as_str)
+                    ))
+                    .join("\n")
+            )?;
+        }
+        if !self.pending_moves.is_empty() {
+            write!(
+                f,
+                "Pending moves:\n\n{}\n\n",
+                self.pending_moves
+                    .iter()
+                    .map(|m| format!("{}\t{}\t{}", m.id, m.input_path, m.output_path))
+                    .join("\n")
+            )?;
+        }
         Ok(())
     }
 }
@@ -818,11 +1312,13 @@ fn get_procs() -> Result<Vec<ProcInfo>, Error> {
                     .map(Into::into)
                     .collect();
                 let status = p.status().ok()?;
+                let prefix = job_prefix(&cmdline);
                 return Some(ProcInfo {
                     pid: p.pid as u64,
                     name: status.name.into(),
                     exe,
                     cmdline,
+                    prefix,
                 });
             }
             None
@@ -895,19 +1391,30 @@ async fn get_current_jobs(p: impl AsRef<Path>) -> Result<Vec<(PathBuf, StackStri
     try_join_all(futures).await
 }
 
-pub async fn transcode_status(config: &Config) -> Result<TranscodeStatus, Error> {
+pub async fn transcode_status(config: &Config, pool: &PgPool) -> Result<TranscodeStatus, Error> {
     let procs = get_procs()?;
-    let (upcoming_jobs, current_jobs, finished_jobs) = try_join!(
-        get_upcoming_jobs(job_dir(config)),
+    let (queued_jobs, failed_jobs, current_jobs, finished_jobs, throughput, pending_moves) = try_join!(
+        TranscodeJob::get_jobs_by_status(pool, None, &[TranscodeJobStatus::Queued]),
+        TranscodeJob::get_jobs_by_status(pool, None, &[TranscodeJobStatus::Failed]),
         get_current_jobs(log_dir(config)),
-        get_paths(tmp_dir(config), "out")
+        get_paths(tmp_dir(config), "out"),
+        TranscodeJob::get_throughput(pool),
+        PendingMove::get_pending(pool)
     )?;
+    let upcoming_jobs = queued_jobs
+        .iter()
+        .filter_map(|job| job.get_request().ok())
+        .collect();
+    let estimates = estimate_jobs(&queued_jobs, &throughput);
 
     Ok(TranscodeStatus {
         procs,
         upcoming_jobs,
         current_jobs,
         finished_jobs,
+        failed_jobs,
+        estimates,
+        pending_moves,
     })
 }
 
@@ -929,13 +1436,21 @@ pub fn movie_directories(config: &Config) -> Result<Vec<StackString>, Error> {
 #[cfg(test)]
 mod tests {
     use anyhow::Error;
-    use std::{collections::HashSet, env::set_var, fs::create_dir_all, path::Path};
+    use stack_string::StackString;
+    use std::{
+        collections::{BTreeMap, HashSet},
+        env::set_var,
+        fs::{create_dir_all, write},
+        path::Path,
+    };
+    use tempfile::TempDir;
 
     use crate::{
         config::Config,
+        pgpool::PgPool,
         transcode_service::{
-            get_current_jobs, get_last_line, get_paths, get_procs, get_upcoming_jobs,
-            transcode_status, JobType, ProcInfo, TranscodeServiceRequest,
+            get_current_jobs, get_last_line, get_paths, get_procs, get_upcoming_jobs, job_prefix,
+            transcode_status, JobType, ProcInfo, TranscodeProgress, TranscodeServiceRequest,
         },
     };
 
@@ -965,7 +1480,8 @@ mod tests {
         create_dir_all(&job_path)?;
         let p = Path::new("mr_robot_s01_ep01.mp4");
         let d: Option<&Path> = None;
-        let payload = TranscodeServiceRequest::create_remcom_request(&config, p, d, false).await?;
+        let payload =
+            TranscodeServiceRequest::create_remcom_request(&config, p, d, false, None).await?;
         println!("{:?}", payload);
         assert_eq!(payload.job_type, JobType::Move);
         assert_eq!(&payload.input_path, p);
@@ -990,6 +1506,7 @@ mod tests {
             p,
             Some(Path::new("drama")),
             false,
+            None,
         )
         .await?;
         println!("{:?}", payload);
@@ -1009,7 +1526,7 @@ mod tests {
         let job_path = config.home_dir.join("dvdrip").join("jobs");
         create_dir_all(&job_path)?;
         let p = Path::new("mr_robot_s01_ep01.mkv");
-        let payload = TranscodeServiceRequest::create_transcode_request(&config, p)?;
+        let payload = TranscodeServiceRequest::create_transcode_request(&config, p, None)?;
         println!("{:?}", payload);
         assert_eq!(&payload.input_path, p);
         let expected = config
@@ -1021,11 +1538,69 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_create_season_request() -> Result<(), Error> {
+        init_env();
+        let config = Config::new()?;
+        let tmp = TempDir::new()?;
+        let season_dir = tmp.path();
+        for f in &[
+            "mr_robot_s02_ep01.mkv",
+            "mr_robot_s01_ep10.mkv",
+            "mr_robot_s01_ep02.avi",
+            "notes.txt",
+        ] {
+            write(season_dir.join(f), b"")?;
+        }
+        let payloads = TranscodeServiceRequest::create_season_request(&config, season_dir)?;
+        let prefixes: Vec<_> = payloads.iter().map(|p| p.prefix.as_str()).collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                "mr_robot_s01_ep02",
+                "mr_robot_s01_ep10",
+                "mr_robot_s02_ep01"
+            ]
+        );
+        assert!(payloads.iter().all(|p| p.job_type == JobType::Transcode));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcode_progress_diff() {
+        let old = TranscodeProgress {
+            current: btree_map(&[("a_mp4.out", "10 %"), ("b_mp4.out", "50 %")]),
+            finished: vec!["c_mp4.out".into()].into_iter().collect(),
+        };
+        let new = TranscodeProgress {
+            current: btree_map(&[("a_mp4.out", "20 %"), ("d_mp4.out", "1 %")]),
+            finished: vec!["b_mp4.out".into(), "c_mp4.out".into()]
+                .into_iter()
+                .collect(),
+        };
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.current,
+            btree_map(&[("a_mp4.out", "20 %"), ("d_mp4.out", "1 %")])
+        );
+        assert_eq!(diff.current_removed, vec![StackString::from("b_mp4.out")]);
+        assert_eq!(diff.finished, vec![StackString::from("b_mp4.out")]);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    fn btree_map(items: &[(&str, &str)]) -> BTreeMap<StackString, StackString> {
+        items
+            .iter()
+            .map(|(k, v)| ((*k).into(), (*v).into()))
+            .collect()
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_transcode_status() -> Result<(), Error> {
         let config = Config::with_config()?;
-        let status = transcode_status(&config).await?;
+        let pool = PgPool::new(&config.pgurl);
+        let status = transcode_status(&config, &pool).await?;
         println!("{:?}", status);
         println!("{}", status);
         assert!(status.procs.len() >= 1);
@@ -1055,6 +1630,7 @@ mod tests {
             name: "HandBrakeCLI".into(),
             exe: "/usr/bin/HandBrakeCLI".into(),
             cmdline: cmdline.clone(),
+            prefix: job_prefix(&cmdline),
         };
         assert_eq!(
             p.to_string(),
@@ -1063,6 +1639,8 @@ mod tests {
                 cmdline.join(" ")
             )
         );
+        assert_eq!(p.prefix.as_deref(), Some("the_walking_dead_s10_ep02"));
+        assert_eq!(job_prefix(&cmdline[..3]), None);
         Ok(())
     }
 
@@ -15,6 +15,16 @@ pub enum TvShowSource {
     Hulu,
     #[serde(rename = "netflix")]
     Netflix,
+    #[serde(rename = "disney")]
+    Disney,
+    #[serde(rename = "max")]
+    Max,
+    #[serde(rename = "apple")]
+    Apple,
+    #[serde(rename = "peacock")]
+    Peacock,
+    #[serde(rename = "paramount")]
+    Paramount,
 }
 
 impl TvShowSource {
@@ -25,22 +35,88 @@ impl TvShowSource {
             Self::Amazon => 1,
             Self::Hulu => 2,
             Self::Netflix => 3,
+            Self::Disney => 4,
+            Self::Max => 5,
+            Self::Apple => 6,
+            Self::Peacock => 7,
+            Self::Paramount => 8,
+        }
+    }
+
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::All,
+            Self::Amazon,
+            Self::Hulu,
+            Self::Netflix,
+            Self::Disney,
+            Self::Max,
+            Self::Apple,
+            Self::Peacock,
+            Self::Paramount,
+        ]
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Netflix => "netflix",
+            Self::Hulu => "hulu",
+            Self::Amazon => "amazon",
+            Self::All => "all",
+            Self::Disney => "disney",
+            Self::Max => "max",
+            Self::Apple => "apple",
+            Self::Peacock => "peacock",
+            Self::Paramount => "paramount",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Netflix => "Netflix",
+            Self::Hulu => "Hulu",
+            Self::Amazon => "Amazon",
+            Self::All => "",
+            Self::Disney => "Disney+",
+            Self::Max => "Max",
+            Self::Apple => "Apple TV+",
+            Self::Peacock => "Peacock",
+            Self::Paramount => "Paramount+",
+        }
+    }
+
+    pub fn url(self) -> Option<&'static str> {
+        match self {
+            Self::Netflix => Some("https://netflix.com"),
+            Self::Hulu => Some("https://hulu.com"),
+            Self::Amazon => Some("https://amazon.com"),
+            Self::All => None,
+            Self::Disney => Some("https://disneyplus.com"),
+            Self::Max => Some("https://max.com"),
+            Self::Apple => Some("https://tv.apple.com"),
+            Self::Peacock => Some("https://peacocktv.com"),
+            Self::Paramount => Some("https://paramountplus.com"),
+        }
+    }
+
+    pub fn from_provider(provider: &str) -> Option<Self> {
+        match provider {
+            "netflix" => Some(Self::Netflix),
+            "hulu" => Some(Self::Hulu),
+            "prime" | "amazon" => Some(Self::Amazon),
+            "disney" => Some(Self::Disney),
+            "hbo" | "max" => Some(Self::Max),
+            "apple" => Some(Self::Apple),
+            "peacock" => Some(Self::Peacock),
+            "paramount" => Some(Self::Paramount),
+            _ => None,
         }
     }
 }
 
 impl fmt::Display for TvShowSource {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Netflix => "netflix",
-                Self::Hulu => "hulu",
-                Self::Amazon => "amazon",
-                Self::All => "all",
-            }
-        )
+        write!(f, "{}", self.to_str())
     }
 }
 
@@ -53,6 +129,11 @@ impl FromStr for TvShowSource {
             "hulu" => Ok(Self::Hulu),
             "amazon" => Ok(Self::Amazon),
             "all" => Ok(Self::All),
+            "disney" => Ok(Self::Disney),
+            "max" => Ok(Self::Max),
+            "apple" => Ok(Self::Apple),
+            "peacock" => Ok(Self::Peacock),
+            "paramount" => Ok(Self::Paramount),
             _ => Err(format_err!("Is not TvShowSource")),
         }
     }
@@ -0,0 +1,200 @@
+use anyhow::Error;
+use chrono::NaiveDate;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use stack_string::StackString;
+
+use crate::{
+    imdb_utils::{ImdbEpisodeResult, ImdbTuple, RatingOutput},
+    utils::ExponentialRetry,
+};
+
+const TVMAZE_ENDPOINT: &str = "https://api.tvmaze.com";
+
+#[derive(Deserialize, Debug, Default)]
+struct TvMazeRating {
+    average: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct TvMazeExternals {
+    imdb: Option<StackString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TvMazeShow {
+    id: u64,
+    name: StackString,
+    premiered: Option<NaiveDate>,
+    #[serde(default)]
+    rating: TvMazeRating,
+    #[serde(default)]
+    externals: TvMazeExternals,
+}
+
+impl TvMazeShow {
+    fn get_tuple(&self) -> Option<ImdbTuple> {
+        let link = self.externals.imdb.clone()?;
+        let title = if let Some(premiered) = self.premiered {
+            format!("{} ({}) (TV Series)", self.name, premiered.format("%Y"))
+        } else {
+            format!("{} (TV Series)", self.name)
+        };
+        Some(ImdbTuple {
+            title: title.into(),
+            link,
+            rating: self.rating.average.unwrap_or(-1.0),
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TvMazeSearchResult {
+    show: TvMazeShow,
+}
+
+#[derive(Deserialize, Debug)]
+struct TvMazeEpisode {
+    season: i32,
+    number: Option<i32>,
+    name: Option<StackString>,
+    airdate: Option<StackString>,
+    #[serde(default)]
+    rating: TvMazeRating,
+}
+
+impl From<TvMazeEpisode> for ImdbEpisodeResult {
+    fn from(item: TvMazeEpisode) -> Self {
+        Self {
+            season: item.season,
+            episode: item.number.unwrap_or(-1),
+            epurl: None,
+            eptitle: item.name,
+            airdate: item
+                .airdate
+                .and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()),
+            rating: item.rating.average,
+            nrating: None,
+        }
+    }
+}
+
+pub struct TvMazeConnection {
+    client: Client,
+}
+
+impl Default for TvMazeConnection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExponentialRetry for TvMazeConnection {
+    fn get_client(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl TvMazeConnection {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+        }
+    }
+
+    async fn lookup_show(&self, imdb_id: &str) -> Result<Option<TvMazeShow>, Error> {
+        if !imdb_id.starts_with("tt") {
+            return Ok(None);
+        }
+        let endpoint = format!("{}/lookup/shows", TVMAZE_ENDPOINT);
+        let url = Url::parse_with_params(&endpoint, &[("imdb", imdb_id)])?;
+        let resp = self.get(&url).await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        resp.error_for_status()?
+            .json()
+            .await
+            .map(Some)
+            .map_err(Into::into)
+    }
+
+    pub async fn parse_imdb(&self, title: &str) -> Result<Vec<ImdbTuple>, Error> {
+        let endpoint = format!("{}/search/shows", TVMAZE_ENDPOINT);
+        let url = Url::parse_with_params(&endpoint, &[("q", title)])?;
+        let results: Vec<TvMazeSearchResult> =
+            self.get(&url).await?.error_for_status()?.json().await?;
+        Ok(results
+            .into_iter()
+            .filter_map(|result| result.show.get_tuple())
+            .collect())
+    }
+
+    pub async fn parse_imdb_rating(&self, imdb_id: &str) -> Result<RatingOutput, Error> {
+        let show = self.lookup_show(imdb_id).await?;
+        Ok(RatingOutput {
+            rating: show.and_then(|s| s.rating.average),
+            count: None,
+        })
+    }
+
+    pub async fn parse_imdb_episode_list(
+        &self,
+        imdb_id: &str,
+        season: Option<i32>,
+    ) -> Result<Vec<ImdbEpisodeResult>, Error> {
+        let show = match self.lookup_show(imdb_id).await? {
+            Some(show) => show,
+            None => return Ok(Vec::new()),
+        };
+        let endpoint = format!("{}/shows/{}/episodes", TVMAZE_ENDPOINT, show.id);
+        let url = Url::parse(&endpoint)?;
+        let episodes: Vec<TvMazeEpisode> = self.get(&url).await?.error_for_status()?.json().await?;
+        Ok(episodes
+            .into_iter()
+            .filter(|e| e.number.is_some() && season.map_or(true, |s| s == e.season))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::NaiveDate;
+
+    use crate::{
+        imdb_utils::ImdbEpisodeResult,
+        tvmaze_utils::{TvMazeEpisode, TvMazeSearchResult},
+    };
+
+    #[test]
+    fn test_tvmaze_search_result() -> Result<(), Error> {
+        let body = r#"[
+            {"score": 0.9, "show": {"id": 1871, "name": "Mr. Robot", "premiered": "2015-06-24",
+             "rating": {"average": 8.6}, "externals": {"imdb": "tt4158110"}}},
+            {"score": 0.5, "show": {"id": 2, "name": "Robot", "premiered": null,
+             "rating": {"average": null}, "externals": {"imdb": null}}}
+        ]"#;
+        let results: Vec<TvMazeSearchResult> = serde_json::from_str(body)?;
+        let tuples: Vec<_> = results.iter().filter_map(|r| r.show.get_tuple()).collect();
+        assert_eq!(tuples.len(), 1);
+        assert_eq!(tuples[0].title.as_str(), "Mr. Robot (2015) (TV Series)");
+        assert_eq!(tuples[0].link.as_str(), "tt4158110");
+        assert!((tuples[0].rating - 8.6).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tvmaze_episode() -> Result<(), Error> {
+        let body = r#"{"season": 1, "number": 2, "name": "eps1.1_ones-and-zer0es.mpeg",
+                       "airdate": "2015-07-01", "rating": {"average": 8.2}}"#;
+        let episode: TvMazeEpisode = serde_json::from_str(body)?;
+        let result: ImdbEpisodeResult = episode.into();
+        assert_eq!(result.season, 1);
+        assert_eq!(result.episode, 2);
+        assert_eq!(result.airdate, Some(NaiveDate::from_ymd(2015, 7, 1)));
+        assert_eq!(result.rating, Some(8.2));
+        Ok(())
+    }
+}
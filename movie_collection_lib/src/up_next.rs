@@ -0,0 +1,178 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use crate::{pgpool::PgPool, utils::parse_file_stem};
+
+#[derive(FromSqlRow, Debug, Clone)]
+struct CollectionEpisode {
+    idx: i32,
+    path: StackString,
+    show: StackString,
+    title: Option<StackString>,
+    link: StackString,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct UpNextEntry {
+    pub show: StackString,
+    pub title: StackString,
+    pub link: StackString,
+    pub season: i32,
+    pub episode: i32,
+    pub collection_idx: i32,
+    pub path: StackString,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Schema)]
+pub struct UpNext {
+    pub entries: Vec<UpNextEntry>,
+}
+
+fn season_episode(path: &str) -> (i32, i32) {
+    let file_stem = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let (_, season, episode) = parse_file_stem(&file_stem);
+    (season, episode)
+}
+
+// Lowest (season, episode) per show that hasn't been watched and whose file passes `exists`
+fn first_unwatched(
+    episodes: Vec<CollectionEpisode>,
+    watched: &HashSet<(StackString, i32, i32)>,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<UpNextEntry> {
+    let mut candidates: BTreeMap<StackString, BTreeMap<(i32, i32), CollectionEpisode>> =
+        BTreeMap::new();
+    for entry in episodes {
+        let (season, episode) = season_episode(&entry.path);
+        if season < 0 || episode < 0 || watched.contains(&(entry.link.clone(), season, episode)) {
+            continue;
+        }
+        candidates
+            .entry(entry.show.clone())
+            .or_default()
+            .entry((season, episode))
+            .or_insert(entry);
+    }
+    candidates
+        .into_iter()
+        .filter_map(|(_, files)| {
+            files
+                .into_iter()
+                .find(|(_, entry)| exists(&entry.path))
+                .map(|((season, episode), entry)| UpNextEntry {
+                    title: entry.title.unwrap_or_else(|| entry.show.clone()),
+                    show: entry.show,
+                    link: entry.link,
+                    season,
+                    episode,
+                    collection_idx: entry.idx,
+                    path: entry.path,
+                })
+        })
+        .collect()
+}
+
+impl UpNext {
+    // Only shows with at least one watched episode are considered in progress
+    pub async fn get_up_next(pool: &PgPool) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                SELECT b.idx, b.path, c.show, c.title, c.link
+                FROM movie_collection b
+                JOIN imdb_ratings c ON b.show_id = c.index
+                WHERE c.istv
+                  AND NOT b.is_deleted
+                  AND c.link IN (SELECT link FROM trakt_watched_episodes)
+            "#
+        );
+        let conn = pool.get().await?;
+        let episodes: Vec<CollectionEpisode> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT link, season, episode
+                FROM trakt_watched_episodes
+                WHERE season IS NOT NULL AND episode IS NOT NULL
+            "#
+        );
+        let watched: Vec<(StackString, i32, i32)> = query.fetch(&conn).await?;
+        let watched: HashSet<_> = watched.into_iter().collect();
+
+        let entries = first_unwatched(episodes, &watched, |p| Path::new(p).exists());
+        Ok(Self { entries })
+    }
+
+    pub fn get_html(&self) -> StackString {
+        if self.entries.is_empty() {
+            return "".into();
+        }
+        let rows: Vec<_> = self
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    r#"<tr><td><a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a></td><td><a href="javascript:updateMainArticle('/list/play/{}')">s{:02} ep{:02}</a></td></tr>"#,
+                    e.link, e.title, e.collection_idx, e.season, e.episode
+                )
+            })
+            .collect();
+        format!(
+            r#"<h4>Up Next</h4><table border="0"><tr><th>Show</th><th>Episode</th></tr>{}</table>"#,
+            rows.join("")
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stack_string::StackString;
+    use std::collections::HashSet;
+
+    use crate::up_next::{first_unwatched, CollectionEpisode, UpNext};
+
+    fn episode(idx: i32, path: &str) -> CollectionEpisode {
+        CollectionEpisode {
+            idx,
+            path: path.into(),
+            show: "mr_robot".into(),
+            title: Some("Mr. Robot".into()),
+            link: "tt4158110".into(),
+        }
+    }
+
+    #[test]
+    fn test_first_unwatched() {
+        let episodes = vec![
+            episode(3, "/shows/mr_robot_s01_ep03.mp4"),
+            episode(1, "/shows/mr_robot_s01_ep01.mp4"),
+            episode(2, "/shows/mr_robot_s01_ep02.mp4"),
+            episode(4, "/shows/mr_robot_s02_ep01.mp4"),
+        ];
+        let watched: HashSet<(StackString, i32, i32)> =
+            vec![("tt4158110".into(), 1, 1)].into_iter().collect();
+
+        let entries = first_unwatched(episodes.clone(), &watched, |_| true);
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].season, entries[0].episode), (1, 2));
+        assert_eq!(entries[0].collection_idx, 2);
+
+        let entries = first_unwatched(episodes, &watched, |p| !p.contains("ep02"));
+        assert_eq!((entries[0].season, entries[0].episode), (1, 3));
+
+        let html = UpNext { entries }.get_html();
+        assert!(html.contains("/list/play/3"));
+        assert!(html.contains("s01 ep03"));
+        assert!(UpNext::default().get_html().is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use chrono::Utc;
+use hmac::{Hmac, Mac, NewMac};
+use postgres_query::{query, FromSqlRow};
+use reqwest::{Client, Url};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use stack_string::StackString;
+use std::{fmt, fmt::Write, str::FromStr, time::Duration};
+use tokio::{task::spawn, time::sleep};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+const HOOK_MAX_RETRIES: u32 = 3;
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+pub const HOOK_SIGNATURE_HEADER: &str = "X-Movie-Collection-Signature";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum HookEvent {
+    #[serde(rename = "collection_added")]
+    CollectionAdded,
+    #[serde(rename = "transcode_finished")]
+    TranscodeFinished,
+    #[serde(rename = "episode_watched")]
+    EpisodeWatched,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::CollectionAdded => "collection_added",
+                Self::TranscodeFinished => "transcode_finished",
+                Self::EpisodeWatched => "episode_watched",
+            }
+        )
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "collection_added" => Ok(Self::CollectionAdded),
+            "transcode_finished" => Ok(Self::TranscodeFinished),
+            "episode_watched" => Ok(Self::EpisodeWatched),
+            _ => Err(format_err!("Is not HookEvent")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for HookEvent {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for HookEvent {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct UserHook {
+    pub id: i32,
+    pub email: StackString,
+    pub event: HookEvent,
+    pub url: StackString,
+    pub secret: StackString,
+    pub created_at: DateTimeWrapper,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HookPayload {
+    pub event: HookEvent,
+    pub timestamp: DateTimeWrapper,
+    pub data: Value,
+}
+
+pub fn sign_payload(secret: &str, body: &[u8]) -> Result<StackString, Error> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| format_err!("Invalid hook secret {}", e))?;
+    mac.update(body);
+    let mut signature = String::from("sha256=");
+    for b in mac.finalize().into_bytes() {
+        write!(signature, "{:02x}", b)?;
+    }
+    Ok(signature.into())
+}
+
+impl UserHook {
+    pub fn validate_url(url: &str) -> Result<Url, Error> {
+        let url: Url = url.parse()?;
+        if url.scheme() == "http" || url.scheme() == "https" {
+            Ok(url)
+        } else {
+            Err(format_err!("Hook url must be http or https"))
+        }
+    }
+
+    pub async fn get_hooks(pool: &PgPool, email: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, email, event, url, secret, created_at
+                FROM user_hooks
+                WHERE email = $email
+                ORDER BY id
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_hooks_for_event(pool: &PgPool, event: HookEvent) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, email, event, url, secret, created_at
+                FROM user_hooks
+                WHERE event = $event
+            "#,
+            event = event
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn insert_hook(
+        pool: &PgPool,
+        email: &str,
+        event: HookEvent,
+        url: &str,
+    ) -> Result<Self, Error> {
+        let secret = Uuid::new_v4().to_simple().to_string();
+        let query = query!(
+            r#"
+                INSERT INTO user_hooks (email, event, url, secret, created_at, last_modified)
+                VALUES ($email, $event, $url, $secret, now(), now())
+                RETURNING id, email, event, url, secret, created_at
+            "#,
+            email = email,
+            event = event,
+            url = url,
+            secret = secret
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete_hook(pool: &PgPool, email: &str, id: i32) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM user_hooks WHERE id = $id AND email = $email",
+            id = id,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    async fn deliver(&self, client: &Client, body: &[u8]) -> Result<(), Error> {
+        let signature = sign_payload(&self.secret, body)?;
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+        loop {
+            let result = client
+                .post(self.url.as_str())
+                .timeout(HOOK_TIMEOUT)
+                .header("Content-Type", "application/json")
+                .header(HOOK_SIGNATURE_HEADER, signature.as_str())
+                .body(body.to_vec())
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt + 1 >= HOOK_MAX_RETRIES => return Err(e.into()),
+                Err(_) => {
+                    sleep(delay).await;
+                    delay *= 4;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    pub async fn dispatch(pool: &PgPool, event: HookEvent, data: Value) -> Result<(), Error> {
+        let hooks = Self::get_hooks_for_event(pool, event).await?;
+        if hooks.is_empty() {
+            return Ok(());
+        }
+        let payload = HookPayload {
+            event,
+            timestamp: Utc::now().into(),
+            data,
+        };
+        let body = serde_json::to_vec(&payload)?;
+        let client = Client::new();
+        for hook in hooks {
+            if let Err(e) = hook.deliver(&client, &body).await {
+                error!("hook {} to {} failed {:?}", hook.id, hook.url, e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn fire(pool: &PgPool, event: HookEvent, data: Value) {
+        let pool = pool.clone();
+        spawn(async move {
+            if let Err(e) = Self::dispatch(&pool, event, data).await {
+                error!("dispatch {} failed {:?}", event, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::user_hooks::{sign_payload, HookEvent};
+
+    #[test]
+    fn test_hook_event() -> Result<(), Error> {
+        let event: HookEvent = "transcode_finished".parse()?;
+        assert_eq!(event, HookEvent::TranscodeFinished);
+        assert_eq!(event.to_string().as_str(), "transcode_finished");
+        assert!("unknown".parse::<HookEvent>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_payload() -> Result<(), Error> {
+        let signature = sign_payload("key", b"The quick brown fox jumps over the lazy dog")?;
+        assert_eq!(
+            signature.as_str(),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::{format_err, Error};
+use chrono::Utc;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use crate::{
+    config::Config, datetime_wrapper::DateTimeWrapper, notifications::NotificationPreference,
+    pgpool::PgPool, playback_position::PlaybackPosition, reclaim::set_keep,
+    user_watched::UserWatched, utils::canonicalize_path,
+};
+
+const USER_STATE_VERSION: i32 = 3;
+
+#[derive(FromSqlRow, Default, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct UserPreferences {
+    pub email: StackString,
+    pub plex_account: Option<StackString>,
+    pub history_private: bool,
+}
+
+impl UserPreferences {
+    pub async fn get_preferences(pool: &PgPool, email: &str) -> Result<Self, Error> {
+        let query = query!(
+            r#"
+                SELECT email, plex_account, history_private
+                FROM user_preferences
+                WHERE email = $email
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        let prefs: Option<Self> = query.fetch_opt(&conn).await?;
+        Ok(prefs.unwrap_or_else(|| Self {
+            email: email.into(),
+            ..Self::default()
+        }))
+    }
+
+    pub async fn upsert_preferences(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO user_preferences (email, plex_account, history_private, last_modified)
+                VALUES ($email, $plex_account, $history_private, now())
+                ON CONFLICT (email) DO UPDATE
+                SET plex_account=$plex_account, history_private=$history_private,
+                    last_modified=now()
+            "#,
+            email = self.email,
+            plex_account = self.plex_account,
+            history_private = self.history_private
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map(|_| ()).map_err(Into::into)
+    }
+
+    pub fn is_admin(config: &Config, email: &str) -> bool {
+        config.admin_emails.iter().any(|e| e.as_str() == email)
+    }
+
+    pub async fn get_private_accounts(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Vec<StackString>, Error> {
+        #[derive(FromSqlRow)]
+        struct PrivateAccount {
+            plex_account: StackString,
+        }
+
+        let query = query!(
+            r#"
+                SELECT plex_account
+                FROM user_preferences
+                WHERE history_private
+                  AND plex_account IS NOT NULL
+                  AND email != $email
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        let accounts: Vec<PrivateAccount> = query.fetch(&conn).await?;
+        Ok(accounts.into_iter().map(|a| a.plex_account).collect())
+    }
+
+    pub async fn get_hidden_accounts(
+        config: &Config,
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Vec<StackString>, Error> {
+        if Self::is_admin(config, email) {
+            Ok(Vec::new())
+        } else {
+            Self::get_private_accounts(pool, email).await
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct UserStateExport {
+    pub version: i32,
+    pub email: StackString,
+    pub exported_at: DateTimeWrapper,
+    pub preferences: UserPreferences,
+    #[serde(default)]
+    pub watched: Vec<UserWatched>,
+    #[serde(default)]
+    pub keep: Vec<StackString>,
+    #[serde(default)]
+    pub playback_positions: Vec<PathPosition>,
+    #[serde(default)]
+    pub notification_preferences: Vec<NotificationPreference>,
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct PathPosition {
+    pub path: StackString,
+    pub position: f64,
+}
+
+// Collection indices differ between installs, exported state refers to files by path
+async fn collection_idx_for_path(pool: &PgPool, path: &str) -> Result<Option<i32>, Error> {
+    let path = canonicalize_path(path);
+    let query = query!(
+        "SELECT idx FROM movie_collection WHERE path = $path AND NOT is_deleted",
+        path = path
+    );
+    let conn = pool.get().await?;
+    let idx: Option<(i32,)> = query.fetch_opt(&conn).await?;
+    Ok(idx.map(|(idx,)| idx))
+}
+
+async fn get_keep_paths(pool: &PgPool, email: &str) -> Result<Vec<StackString>, Error> {
+    let query = query!(
+        r#"
+            SELECT b.path
+            FROM collection_keep a
+            JOIN movie_collection b ON a.collection_idx = b.idx
+            WHERE a.email = $email
+            ORDER BY b.path
+        "#,
+        email = email
+    );
+    let conn = pool.get().await?;
+    let paths: Vec<(StackString,)> = query.fetch(&conn).await?;
+    Ok(paths.into_iter().map(|(p,)| p).collect())
+}
+
+async fn get_path_positions(pool: &PgPool, email: &str) -> Result<Vec<PathPosition>, Error> {
+    let query = query!(
+        r#"
+            SELECT b.path, a.position
+            FROM playback_position a
+            JOIN movie_collection b ON a.collection_idx = b.idx
+            WHERE a.email = $email
+            ORDER BY b.path
+        "#,
+        email = email
+    );
+    let conn = pool.get().await?;
+    query.fetch(&conn).await.map_err(Into::into)
+}
+
+impl UserStateExport {
+    pub async fn export(pool: &PgPool, email: &str) -> Result<Self, Error> {
+        let preferences = UserPreferences::get_preferences(pool, email).await?;
+        let watched = UserWatched::get_by_email(pool, email).await?;
+        let keep = get_keep_paths(pool, email).await?;
+        let playback_positions = get_path_positions(pool, email).await?;
+        let notification_preferences = NotificationPreference::get_preferences(pool, email).await?;
+        Ok(Self {
+            version: USER_STATE_VERSION,
+            email: email.into(),
+            exported_at: Utc::now().into(),
+            preferences,
+            watched,
+            keep,
+            playback_positions,
+            notification_preferences,
+        })
+    }
+
+    pub fn check_version(&self) -> Result<(), Error> {
+        if self.version < 1 || self.version > USER_STATE_VERSION {
+            Err(format_err!(
+                "Unsupported user state version {}",
+                self.version
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn restore(mut self, pool: &PgPool, email: &str) -> Result<Self, Error> {
+        self.check_version()?;
+        self.preferences.email = email.into();
+        self.preferences.upsert_preferences(pool).await?;
+        // Files that aren't in this collection are skipped
+        for watched in &self.watched {
+            if let Some(idx) = collection_idx_for_path(pool, &watched.path).await? {
+                UserWatched::set_watched(pool, email, idx).await?;
+            }
+        }
+        for path in &self.keep {
+            if let Some(idx) = collection_idx_for_path(pool, path).await? {
+                set_keep(pool, idx, email).await?;
+            }
+        }
+        for p in &self.playback_positions {
+            if let Some(idx) = collection_idx_for_path(pool, &p.path).await? {
+                PlaybackPosition::set(pool, email, idx, p.position).await?;
+            }
+        }
+        for pref in &self.notification_preferences {
+            NotificationPreference::upsert(
+                pool,
+                email,
+                pref.channel,
+                &pref.target,
+                pref.new_episodes,
+                pref.transcode_finished,
+            )
+            .await?;
+        }
+        Self::export(pool, email).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use chrono::Utc;
+
+    use crate::user_preferences::{UserPreferences, UserStateExport, USER_STATE_VERSION};
+
+    #[test]
+    fn test_check_version() {
+        let mut state = UserStateExport {
+            version: USER_STATE_VERSION,
+            email: "user@localhost".into(),
+            exported_at: Utc::now().into(),
+            preferences: UserPreferences::default(),
+            watched: Vec::new(),
+            keep: Vec::new(),
+            playback_positions: Vec::new(),
+            notification_preferences: Vec::new(),
+        };
+        assert!(state.check_version().is_ok());
+        state.version = USER_STATE_VERSION + 1;
+        assert!(state.check_version().is_err());
+        state.version = 0;
+        assert!(state.check_version().is_err());
+    }
+
+    #[test]
+    fn test_older_export() -> Result<(), Error> {
+        let data = r#"{
+            "version": 2,
+            "email": "user@localhost",
+            "exported_at": "2021-01-01T00:00:00Z",
+            "preferences": {
+                "email": "user@localhost", "plex_account": null, "history_private": false
+            },
+            "watched": []
+        }"#;
+        let state: UserStateExport = serde_json::from_str(data)?;
+        assert!(state.check_version().is_ok());
+        assert!(state.keep.is_empty());
+        assert!(state.playback_positions.is_empty());
+        assert!(state.notification_preferences.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool};
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct UserWatched {
+    pub email: StackString,
+    pub collection_idx: i32,
+    pub path: StackString,
+    pub watched_at: DateTimeWrapper,
+}
+
+impl UserWatched {
+    pub async fn get_by_email(pool: &PgPool, email: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.email, a.collection_idx, b.path, a.watched_at
+                FROM user_watched a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+                WHERE a.email = $email
+                ORDER BY a.watched_at DESC
+            "#,
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn is_watched(
+        pool: &PgPool,
+        email: &str,
+        collection_idx: i32,
+    ) -> Result<bool, Error> {
+        let query = query!(
+            r#"
+                SELECT count(*)
+                FROM user_watched
+                WHERE email = $email AND collection_idx = $collection_idx
+            "#,
+            email = email,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        let (count,): (i64,) = query.fetch_one(&conn).await?;
+        Ok(count > 0)
+    }
+
+    pub async fn set_watched(
+        pool: &PgPool,
+        email: &str,
+        collection_idx: i32,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                WITH inserted AS (
+                    INSERT INTO user_watched (email, collection_idx, watched_at)
+                    SELECT $email, idx, now()
+                    FROM movie_collection
+                    WHERE idx = $collection_idx
+                    ON CONFLICT (email, collection_idx) DO UPDATE SET watched_at = now()
+                    RETURNING email, collection_idx, watched_at
+                )
+                SELECT a.email, a.collection_idx, b.path, a.watched_at
+                FROM inserted a
+                JOIN movie_collection b ON a.collection_idx = b.idx
+            "#,
+            email = email,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    // Scrobbles arrive with the plex account, mark it for every user linked to that account
+    pub async fn set_watched_for_plex_account(
+        pool: &PgPool,
+        plex_account: &str,
+        collection_idx: i32,
+    ) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO user_watched (email, collection_idx, watched_at)
+                SELECT email, $collection_idx, now()
+                FROM user_preferences
+                WHERE plex_account = $plex_account
+                ON CONFLICT (email, collection_idx) DO UPDATE SET watched_at = now()
+            "#,
+            plex_account = plex_account,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete_watched(
+        pool: &PgPool,
+        email: &str,
+        collection_idx: i32,
+    ) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM user_watched WHERE email = $email AND collection_idx = $collection_idx",
+            email = email,
+            collection_idx = collection_idx
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
@@ -1,18 +1,26 @@
 use anyhow::{format_err, Error};
 use async_trait::async_trait;
 use handlebars::Handlebars;
+use itertools::Itertools;
 use jwalk::WalkDir;
 use lazy_static::lazy_static;
 use rand::{
     distributions::{Distribution, Uniform},
     thread_rng,
 };
+use regex::Regex;
 use reqwest::{Client, Response, Url};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use stack_string::StackString;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
 use tokio::{
+    fs,
     process::Command,
     time::{sleep, Duration},
 };
@@ -21,6 +29,7 @@ use crate::pgpool::PgPool;
 
 lazy_static! {
     pub static ref HBR: Handlebars<'static> = get_templates().expect("Failed to parse templates");
+    static ref FILENAME_PATTERNS: RwLock<Vec<Regex>> = RwLock::new(Vec::new());
 }
 
 pub fn get_templates() -> Result<Handlebars<'static>, Error> {
@@ -29,12 +38,64 @@ pub fn get_templates() -> Result<Handlebars<'static>, Error> {
     Ok(h)
 }
 
+pub fn escape_html(s: &str) -> StackString {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+// Percent-encode a query string value, everything outside the RFC 3986 unreserved set
+// is escaped
+pub fn percent_encode(s: &str) -> StackString {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded.into()
+}
+
 #[inline]
 #[allow(clippy::needless_lifetimes)]
 pub fn option_string_wrapper<'a>(s: Option<&'a impl AsRef<str>>) -> &'a str {
     s.map_or("", AsRef::as_ref)
 }
 
+// Lexically normalize a collection path, collapsing repeated separators and `.`
+// components and dropping a trailing separator. These are the same rules as the
+// canonical_path() sql function behind the unique path index, symlinks and `..` are
+// left alone so a scan of a symlinked movie_dir still matches the stored rows
+pub fn canonicalize_path(path: &str) -> StackString {
+    let normalized: PathBuf = Path::new(path).components().collect();
+    normalized.to_string_lossy().into_owned().into()
+}
+
+// Resolves `..` and symlinks in `path` and only returns it if the result is inside one of
+// `dirs`, for paths that arrive from requests
+pub async fn resolve_in_dirs(dirs: &[PathBuf], path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).await.ok()?;
+    for dir in dirs {
+        if let Ok(dir) = fs::canonicalize(dir).await {
+            if path.starts_with(&dir) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
 pub fn walk_directory(path: &Path, match_strs: &[impl AsRef<str>]) -> Result<Vec<PathBuf>, Error> {
     WalkDir::new(path)
         .into_iter()
@@ -57,16 +118,140 @@ pub fn walk_directory(path: &Path, match_strs: &[impl AsRef<str>]) -> Result<Vec
         .collect()
 }
 
+fn link_priority(path: &Path, preferred_dirs: &[PathBuf]) -> (bool, usize, usize) {
+    let is_symlink = path
+        .symlink_metadata()
+        .map_or(false, |m| m.file_type().is_symlink());
+    let dir_index = preferred_dirs
+        .iter()
+        .position(|d| path.starts_with(d))
+        .unwrap_or(preferred_dirs.len());
+    (is_symlink, dir_index, path.as_os_str().len())
+}
+
+pub fn dedup_linked_paths(
+    paths: Vec<PathBuf>,
+    preferred_dirs: &[PathBuf],
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut inode_map: HashMap<(u64, u64), Vec<PathBuf>> = HashMap::new();
+    let mut unique = Vec::new();
+    for path in paths {
+        match path.metadata() {
+            Ok(metadata) => inode_map
+                .entry((metadata.dev(), metadata.ino()))
+                .or_default()
+                .push(path),
+            Err(_) => unique.push(path),
+        }
+    }
+    let mut duplicates = Vec::new();
+    for mut linked in inode_map.into_values() {
+        linked.sort_by_key(|p| (link_priority(p, preferred_dirs), p.clone()));
+        let mut linked = linked.into_iter();
+        if let Some(path) = linked.next() {
+            unique.push(path);
+        }
+        duplicates.extend(linked);
+    }
+    unique.sort();
+    duplicates.sort();
+    (unique, duplicates)
+}
+
 #[derive(Serialize, Deserialize)]
 struct ScriptStruct {
     script: PathBuf,
 }
 
+pub fn set_filename_patterns(patterns: &[StackString]) -> Result<(), Error> {
+    let patterns: Result<Vec<_>, Error> = patterns
+        .iter()
+        .map(|p| compile_filename_pattern(p))
+        .collect();
+    let patterns = patterns?;
+    *FILENAME_PATTERNS
+        .write()
+        .map_err(|_| format_err!("filename patterns lock poisoned"))? = patterns;
+    Ok(())
+}
+
+fn compile_filename_pattern(pattern: &str) -> Result<Regex, Error> {
+    let re = Regex::new(pattern)?;
+    for name in &["show", "season", "episode"] {
+        if !re.capture_names().flatten().any(|n| n == *name) {
+            return Err(format_err!(
+                "filename pattern {} has no {} group",
+                pattern,
+                name
+            ));
+        }
+    }
+    Ok(re)
+}
+
+fn normalize_show_name(show: &str) -> StackString {
+    show.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .join("_")
+        .into()
+}
+
+fn match_filename_pattern(re: &Regex, file_stem: &str) -> Option<(StackString, i32, i32)> {
+    let caps = re.captures(file_stem)?;
+    let show = normalize_show_name(caps.name("show")?.as_str());
+    let season = caps.name("season")?.as_str().parse().ok()?;
+    let episode = caps.name("episode")?.as_str().parse().ok()?;
+    if show.is_empty() {
+        None
+    } else {
+        Some((show, season, episode))
+    }
+}
+
+fn parse_file_stem_patterns(file_stem: &str) -> (StackString, i32, i32) {
+    FILENAME_PATTERNS
+        .read()
+        .ok()
+        .and_then(|patterns| {
+            patterns
+                .iter()
+                .find_map(|re| match_filename_pattern(re, file_stem))
+        })
+        .unwrap_or_else(|| (file_stem.into(), -1, -1))
+}
+
+// Longer spans are more likely a typo (_ep01_ep9999) than a real multi-episode file
+pub const MAX_EPISODE_SPAN: i32 = 10;
+
+// Multi-episode files, e.g. show_s01_ep01_ep02, returns (show, season, first, last)
+pub fn parse_episode_span(file_stem: &str) -> Option<(StackString, i32, i32, i32)> {
+    let entries: Vec<_> = file_stem.split('_').collect();
+    if entries.len() < 4 {
+        return None;
+    }
+    let n = entries.len();
+    let season: i32 = entries[n - 3].strip_prefix('s')?.parse().ok()?;
+    let first: i32 = entries[n - 2].strip_prefix("ep")?.parse().ok()?;
+    let last: i32 = entries[n - 1].strip_prefix("ep")?.parse().ok()?;
+    if last <= first || last - first >= MAX_EPISODE_SPAN {
+        return None;
+    }
+    Some((entries[..(n - 3)].join("_").into(), season, first, last))
+}
+
+pub fn format_episode_span(first: i32, last: i32) -> StackString {
+    format!("ep{:02}\u{2013}{:02}", first, last).into()
+}
+
 pub fn parse_file_stem(file_stem: &str) -> (StackString, i32, i32) {
+    if let Some((show, season, first, _)) = parse_episode_span(file_stem) {
+        return (show, season, first);
+    }
     let entries: Vec<_> = file_stem.split('_').collect();
 
     if entries.len() < 3 {
-        return (file_stem.into(), -1, -1);
+        return parse_file_stem_patterns(file_stem);
     }
 
     let show = entries[..(entries.len() - 2)].join("_").into();
@@ -86,12 +271,24 @@ pub fn parse_file_stem(file_stem: &str) -> (StackString, i32, i32) {
     };
 
     if season == -1 || episode == -1 {
-        (file_stem.into(), -1, -1)
+        parse_file_stem_patterns(file_stem)
     } else {
         (show, season, episode)
     }
 }
 
+// Absolute numbering as used for anime, e.g. one_piece_ep0134
+pub fn parse_absolute_episode(file_stem: &str) -> Option<(StackString, i32)> {
+    let (show, episode) = file_stem.rsplit_once('_')?;
+    let episode: i32 = episode.strip_prefix("ep")?.parse().ok()?;
+    let (_, season, _) = parse_file_stem(file_stem);
+    if show.is_empty() || season != -1 {
+        None
+    } else {
+        Some((show.into(), episode))
+    }
+}
+
 pub async fn get_video_runtime(f: &Path) -> Result<StackString, Error> {
     let ext = f
         .extension()
@@ -172,3 +369,142 @@ pub async fn get_authorized_users(pool: &PgPool) -> Result<Vec<StackString>, Err
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::{
+        fs::{create_dir_all, hard_link, write},
+        os::unix::fs::symlink,
+    };
+    use tempfile::TempDir;
+
+    use crate::utils::{
+        canonicalize_path, compile_filename_pattern, dedup_linked_paths, format_episode_span,
+        match_filename_pattern, parse_absolute_episode, parse_episode_span, parse_file_stem,
+        resolve_in_dirs,
+    };
+
+    #[test]
+    fn test_canonicalize_path() -> Result<(), Error> {
+        let expected = "/tmp/canonical_test/movies/a.mp4";
+        for path in &[
+            "/tmp/canonical_test/movies/a.mp4",
+            "/tmp//canonical_test/./movies/a.mp4",
+            "/tmp/canonical_test/movies/a.mp4/",
+            "/tmp/canonical_test/movies/./a.mp4/.",
+        ] {
+            assert_eq!(canonicalize_path(path).as_str(), expected);
+        }
+        assert_eq!(
+            canonicalize_path("/tmp/canonical_test/tv/../movies/a.mp4").as_str(),
+            "/tmp/canonical_test/tv/../movies/a.mp4"
+        );
+
+        let tmp = TempDir::new()?;
+        let base = tmp.path();
+        create_dir_all(base.join("real"))?;
+        symlink(base.join("real"), base.join("linked"))?;
+        let linked = base.join("linked").join("a.mp4");
+        let linked = linked.to_string_lossy();
+        assert_eq!(canonicalize_path(&linked).as_str(), linked);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_in_dirs() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let base = tmp.path();
+        create_dir_all(base.join("movies/show/season1"))?;
+        create_dir_all(base.join("private"))?;
+        symlink(base.join("private"), base.join("movies/escape"))?;
+        let dirs = vec![base.join("movies")];
+
+        let season = base.join("movies/show/season1");
+        assert_eq!(resolve_in_dirs(&dirs, &season).await, Some(season));
+        let dotdot = base.join("movies/show/../../private");
+        assert_eq!(resolve_in_dirs(&dirs, &dotdot).await, None);
+        let linked = base.join("movies/escape");
+        assert_eq!(resolve_in_dirs(&dirs, &linked).await, None);
+        let missing = base.join("movies/missing");
+        assert_eq!(resolve_in_dirs(&dirs, &missing).await, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_episode_span() {
+        assert_eq!(
+            parse_episode_span("mr_robot_s01_ep01_ep02"),
+            Some(("mr_robot".into(), 1, 1, 2))
+        );
+        assert_eq!(
+            parse_file_stem("mr_robot_s01_ep01_ep02"),
+            ("mr_robot".into(), 1, 1)
+        );
+        assert_eq!(parse_episode_span("mr_robot_s01_ep02"), None);
+        assert_eq!(parse_episode_span("mr_robot_s01_ep02_ep02"), None);
+        assert_eq!(parse_episode_span("mr_robot_s01_ep01_ep9999"), None);
+        assert_eq!(parse_absolute_episode("mr_robot_s01_ep01_ep02"), None);
+        assert_eq!(format_episode_span(1, 2).as_str(), "ep01\u{2013}02");
+    }
+
+    #[test]
+    fn test_parse_absolute_episode() {
+        assert_eq!(
+            parse_absolute_episode("one_piece_ep0134"),
+            Some(("one_piece".into(), 134))
+        );
+        assert_eq!(parse_absolute_episode("mr_robot_s01_ep01"), None);
+        assert_eq!(parse_absolute_episode("ep0134"), None);
+        assert_eq!(parse_absolute_episode("arrival"), None);
+    }
+
+    #[test]
+    fn test_match_filename_pattern() -> Result<(), Error> {
+        let scene =
+            compile_filename_pattern(r"^(?P<show>.+?)[. ][Ss](?P<season>\d+)[Ee](?P<episode>\d+)")?;
+        assert_eq!(
+            match_filename_pattern(&scene, "The.Expanse.S02E05.1080p.WEB"),
+            Some(("the_expanse".into(), 2, 5))
+        );
+        let cross = compile_filename_pattern(r"^(?P<show>.+) - (?P<season>\d+)x(?P<episode>\d+)$")?;
+        assert_eq!(
+            match_filename_pattern(&cross, "Mr Robot - 1x02"),
+            Some(("mr_robot".into(), 1, 2))
+        );
+        assert_eq!(match_filename_pattern(&cross, "arrival"), None);
+        assert!(compile_filename_pattern(r"^(?P<show>.+) - (?P<episode>\d+)$").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_linked_paths() -> Result<(), Error> {
+        let tmp = TempDir::new()?;
+        let base = tmp.path();
+        let library = base.join("library");
+        let downloads = base.join("downloads");
+        create_dir_all(&library)?;
+        create_dir_all(&downloads)?;
+
+        let download = downloads.join("mr_robot_s01_ep01.mp4");
+        let hardlink = library.join("mr_robot_s01_ep01.mp4");
+        let softlink = library.join("mr_robot_s01_ep01_link.mp4");
+        let other = library.join("mr_robot_s01_ep02.mp4");
+        write(&download, b"episode")?;
+        write(&other, b"other episode")?;
+        hard_link(&download, &hardlink)?;
+        symlink(&download, &softlink)?;
+
+        let paths = vec![
+            download.clone(),
+            softlink.clone(),
+            hardlink.clone(),
+            other.clone(),
+        ];
+        let (unique, duplicates) = dedup_linked_paths(paths, &[library, downloads]);
+
+        assert_eq!(unique, vec![hardlink, other]);
+        assert_eq!(duplicates, vec![download, softlink]);
+        Ok(())
+    }
+}
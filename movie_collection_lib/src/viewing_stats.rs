@@ -0,0 +1,367 @@
+use anyhow::Error;
+use chrono::{Duration, Utc};
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::path::Path;
+
+use crate::{naivedate_wrapper::NaiveDateWrapper, pgpool::PgPool, utils::parse_file_stem};
+
+pub const DEFAULT_STATS_WEEKS: i64 = 12;
+const TOP_LIMIT: i64 = 10;
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct WeeklyHours {
+    pub week: NaiveDateWrapper,
+    pub hours: f64,
+    pub plays: i64,
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct ShowPlays {
+    pub show: StackString,
+    pub plays: i64,
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct ShowCompletion {
+    pub show: StackString,
+    pub title: StackString,
+    pub link: StackString,
+    pub watched: i64,
+    pub aired: i64,
+}
+
+impl ShowCompletion {
+    pub fn percent(&self) -> f64 {
+        if self.aired > 0 {
+            100.0 * self.watched as f64 / self.aired as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct PlayerPlays {
+    pub player: StackString,
+    pub plays: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+pub struct ViewingStats {
+    pub weeks: i64,
+    pub hours_per_week: Vec<WeeklyHours>,
+    pub top_shows: Vec<ShowPlays>,
+    pub completion: Vec<ShowCompletion>,
+    pub top_players: Vec<PlayerPlays>,
+}
+
+impl ViewingStats {
+    // Plex only reports play/pause/stop transitions, so watch time is the gap between a
+    // play or resume and the next event on the same player, capped for missed stops.
+    // Raw events past the retention window only survive as daily counts, so those weeks
+    // still report plays even though their hours can't be reconstructed
+    pub async fn get_stats(
+        pool: &PgPool,
+        weeks: i64,
+        hidden_accounts: &[StackString],
+    ) -> Result<Self, Error> {
+        let start = Utc::now() - Duration::weeks(weeks);
+        let start_date = start.naive_utc().date();
+        let hidden_accounts = hidden_accounts.to_vec();
+        let conn = pool.get().await?;
+
+        let query = query!(
+            r#"
+                WITH sessions AS (
+                    SELECT event, created_at,
+                           lead(created_at) OVER (
+                               PARTITION BY account, player_address ORDER BY created_at
+                           ) AS ended_at
+                    FROM plex_event
+                    WHERE created_at >= $start AND account != ALL($hidden_accounts)
+                ), hours AS (
+                    SELECT date_trunc('week', created_at)::DATE AS week,
+                           sum(extract(epoch FROM
+                                least(ended_at - created_at, interval '4 hours')
+                           )) / 3600.0 AS hours
+                    FROM sessions
+                    WHERE event IN ('media.play', 'media.resume') AND ended_at IS NOT NULL
+                    GROUP BY 1
+                ), plays AS (
+                    SELECT date_trunc('week', day)::DATE AS week, sum(count) AS plays
+                    FROM (
+                        SELECT day, account, count
+                        FROM plex_event_daily
+                        WHERE day >= $start_date AND event = 'media.play'
+                        UNION ALL
+                        SELECT date(created_at) AS day, account, count(*) AS count
+                        FROM plex_event
+                        WHERE created_at >= $start AND event = 'media.play'
+                        GROUP BY 1, 2
+                    ) AS t
+                    WHERE account != ALL($hidden_accounts)
+                    GROUP BY 1
+                )
+                SELECT coalesce(h.week, p.week) AS week,
+                       coalesce(h.hours, 0)::DOUBLE PRECISION AS hours,
+                       coalesce(p.plays, 0)::BIGINT AS plays
+                FROM hours h
+                FULL OUTER JOIN plays p ON p.week = h.week
+                ORDER BY 1
+            "#,
+            start = start,
+            start_date = start_date,
+            hidden_accounts = hidden_accounts
+        );
+        let hours_per_week: Vec<WeeklyHours> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT coalesce(grandparent_title, title) AS show, count(*)::BIGINT AS plays
+                FROM plex_event
+                WHERE event = 'media.scrobble'
+                  AND created_at >= $start
+                  AND account != ALL($hidden_accounts)
+                  AND coalesce(grandparent_title, title) IS NOT NULL
+                GROUP BY 1
+                ORDER BY 2 DESC, 1
+                LIMIT $limit
+            "#,
+            start = start,
+            hidden_accounts = hidden_accounts,
+            limit = TOP_LIMIT
+        );
+        let top_shows: Vec<ShowPlays> = query.fetch(&conn).await?;
+
+        // Episodes marked watched locally count towards completion along with trakt
+        let query = query!(
+            r#"
+                SELECT DISTINCT m.path
+                FROM user_watched w
+                JOIN movie_collection m ON m.idx = w.collection_idx
+                LEFT JOIN user_preferences u ON u.email = w.email
+                WHERE u.plex_account IS NULL OR u.plex_account != ALL($hidden_accounts)
+            "#,
+            hidden_accounts = hidden_accounts
+        );
+        let marked: Vec<(StackString,)> = query.fetch(&conn).await?;
+        let (marked_shows, marked_seasons, marked_episodes) = marked_episodes(&marked);
+
+        let query = query!(
+            r#"
+                WITH marked AS (
+                    SELECT DISTINCT show, season, episode
+                    FROM unnest($marked_shows::text[], $marked_seasons::int[],
+                                $marked_episodes::int[]) AS m (show, season, episode)
+                )
+                SELECT r.show,
+                       coalesce(r.title, r.show) AS title,
+                       r.link,
+                       count(DISTINCT e.id) FILTER (
+                           WHERE w.id IS NOT NULL OR m.show IS NOT NULL
+                       )::BIGINT AS watched,
+                       count(DISTINCT e.id)::BIGINT AS aired
+                FROM imdb_ratings r
+                JOIN imdb_episodes e ON e.show = r.show AND e.airdate <= current_date
+                LEFT JOIN trakt_watched_episodes w
+                    ON w.link = r.link AND w.season = e.season AND w.episode = e.episode
+                LEFT JOIN marked m
+                    ON m.show = r.show AND m.season = e.season AND m.episode = e.episode
+                WHERE r.istv
+                GROUP BY r.show, r.title, r.link
+                HAVING count(w.id) + count(m.show) > 0
+                ORDER BY count(DISTINCT e.id) FILTER (
+                             WHERE w.id IS NOT NULL OR m.show IS NOT NULL
+                         )::DOUBLE PRECISION / count(DISTINCT e.id) DESC,
+                         r.show
+            "#,
+            marked_shows = marked_shows,
+            marked_seasons = marked_seasons,
+            marked_episodes = marked_episodes
+        );
+        let completion: Vec<ShowCompletion> = query.fetch(&conn).await?;
+
+        let query = query!(
+            r#"
+                SELECT player_title AS player, count(*)::BIGINT AS plays
+                FROM plex_event
+                WHERE event IN ('media.play', 'media.resume')
+                  AND created_at >= $start
+                  AND account != ALL($hidden_accounts)
+                GROUP BY 1
+                ORDER BY 2 DESC, 1
+                LIMIT $limit
+            "#,
+            start = start,
+            hidden_accounts = hidden_accounts,
+            limit = TOP_LIMIT
+        );
+        let top_players: Vec<PlayerPlays> = query.fetch(&conn).await?;
+
+        Ok(Self {
+            weeks,
+            hours_per_week,
+            top_shows,
+            completion,
+            top_players,
+        })
+    }
+
+    pub fn total_plays(&self) -> i64 {
+        self.hours_per_week.iter().map(|w| w.plays).sum()
+    }
+
+    pub fn total_hours(&self) -> f64 {
+        self.hours_per_week.iter().map(|w| w.hours).sum()
+    }
+
+    pub fn get_html(&self) -> StackString {
+        fn table(title: &str, header: &str, rows: &[String]) -> String {
+            if rows.is_empty() {
+                return format!("<h4>{}</h4>none<br>", title);
+            }
+            format!(
+                r#"<h4>{}</h4><table border="0"><tr>{}</tr>{}</table>"#,
+                title,
+                header,
+                rows.join("")
+            )
+        }
+
+        let hours: Vec<_> = self
+            .hours_per_week
+            .iter()
+            .map(|w| {
+                format!(
+                    "<tr><td>{}</td><td>{:.1}</td><td>{}</td></tr>",
+                    w.week, w.hours, w.plays
+                )
+            })
+            .collect();
+        let shows: Vec<_> = self
+            .top_shows
+            .iter()
+            .map(|s| format!("<tr><td>{}</td><td>{}</td></tr>", s.show, s.plays))
+            .collect();
+        let completion: Vec<_> = self
+            .completion
+            .iter()
+            .map(|c| {
+                format!(
+                    r#"<tr><td><a href="javascript:updateMainArticle('/trakt/watched/list/{}')">{}</a></td><td>{} / {}</td><td>{:.0}%</td></tr>"#,
+                    c.link,
+                    c.title,
+                    c.watched,
+                    c.aired,
+                    c.percent()
+                )
+            })
+            .collect();
+        let players: Vec<_> = self
+            .top_players
+            .iter()
+            .map(|p| format!("<tr><td>{}</td><td>{}</td></tr>", p.player, p.plays))
+            .collect();
+
+        format!(
+            "{:.1} hours watched ({} plays) over the last {} weeks<br>{}{}{}{}",
+            self.total_hours(),
+            self.total_plays(),
+            self.weeks,
+            table(
+                "Hours Watched per Week",
+                "<th>Week</th><th>Hours</th><th>Plays</th>",
+                &hours
+            ),
+            table("Top Shows", "<th>Show</th><th>Plays</th>", &shows),
+            table(
+                "Completion",
+                "<th>Show</th><th>Episodes</th><th>Complete</th>",
+                &completion
+            ),
+            table("Busiest Players", "<th>Player</th><th>Plays</th>", &players),
+        )
+        .into()
+    }
+}
+
+// Splits collection paths into parallel (show, season, episode) arrays so they can be
+// passed to postgres as unnest() arguments, movies and unparseable names are skipped
+fn marked_episodes(paths: &[(StackString,)]) -> (Vec<StackString>, Vec<i32>, Vec<i32>) {
+    let mut shows = Vec::new();
+    let mut seasons = Vec::new();
+    let mut episodes = Vec::new();
+    for (path,) in paths {
+        let file_stem = match Path::new(path.as_str()).file_stem() {
+            Some(file_stem) => file_stem.to_string_lossy(),
+            None => continue,
+        };
+        let (show, season, episode) = parse_file_stem(&file_stem);
+        if season < 0 || episode < 0 {
+            continue;
+        }
+        shows.push(show);
+        seasons.push(season);
+        episodes.push(episode);
+    }
+    (shows, seasons, episodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::viewing_stats::{marked_episodes, ShowCompletion, ViewingStats, WeeklyHours};
+
+    #[test]
+    fn test_marked_episodes() {
+        let paths = vec![
+            ("/shares/television/mr_robot/season4/mr_robot_s04_ep03.mp4".into(),),
+            ("/shares/movies/the_matrix.mp4".into(),),
+        ];
+        let (shows, seasons, episodes) = marked_episodes(&paths);
+        assert_eq!(shows.len(), 1);
+        assert_eq!(shows[0].as_str(), "mr_robot");
+        assert_eq!(seasons, vec![4]);
+        assert_eq!(episodes, vec![3]);
+    }
+
+    #[test]
+    fn test_viewing_stats_html() {
+        let completion = ShowCompletion {
+            show: "mr_robot".into(),
+            title: "Mr. Robot".into(),
+            link: "tt4158110".into(),
+            watched: 3,
+            aired: 4,
+        };
+        assert!((completion.percent() - 75.0).abs() < 1e-6);
+
+        let stats = ViewingStats {
+            weeks: 2,
+            hours_per_week: vec![
+                WeeklyHours {
+                    week: NaiveDate::from_ymd(2021, 6, 7).into(),
+                    hours: 1.5,
+                    plays: 2,
+                },
+                WeeklyHours {
+                    week: NaiveDate::from_ymd(2021, 6, 14).into(),
+                    hours: 2.25,
+                    plays: 3,
+                },
+            ],
+            top_shows: Vec::new(),
+            completion: vec![completion],
+            top_players: Vec::new(),
+        };
+        assert!((stats.total_hours() - 3.75).abs() < 1e-6);
+        let html = stats.get_html();
+        assert!(html.starts_with("3.8 hours watched (5 plays) over the last 2 weeks"));
+        assert!(html.contains("<td>3 / 4</td><td>75%</td>"));
+        assert!(html.contains("<h4>Top Shows</h4>none<br>"));
+    }
+}
@@ -0,0 +1,417 @@
+use anyhow::{format_err, Error};
+use bytes::BytesMut;
+use itertools::Itertools;
+use notify::DebouncedEvent;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::{fmt, path::Path, str::FromStr, time::Duration};
+use stdout_channel::StdoutChannel;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    task::spawn_blocking,
+};
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type};
+use tracing::{debug, error};
+
+use crate::{
+    collection_watcher::{wait_for_stable_size, watch_actions, watch_dirs, WatchAction},
+    config::Config,
+    datetime_wrapper::DateTimeWrapper,
+    pgpool::PgPool,
+    show_settings::ShowSettings,
+    transcode_service::{TranscodeService, TranscodeServiceRequest},
+    utils::parse_file_stem,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, Copy, PartialEq, Hash, Schema)]
+pub enum PendingMoveStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "approved")]
+    Approved,
+    #[serde(rename = "denied")]
+    Denied,
+}
+
+impl fmt::Display for PendingMoveStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Pending => "pending",
+                Self::Approved => "approved",
+                Self::Denied => "denied",
+            }
+        )
+    }
+}
+
+impl FromStr for PendingMoveStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "approved" => Ok(Self::Approved),
+            "denied" => Ok(Self::Denied),
+            _ => Err(format_err!("Is not PendingMoveStatus")),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for PendingMoveStatus {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + 'static + Send + Sync>> {
+        let s = String::from_sql(ty, raw)?.parse()?;
+        Ok(s)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <String as FromSql>::accepts(ty)
+    }
+}
+
+impl ToSql for PendingMoveStatus {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        self.to_string().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool
+    where
+        Self: Sized,
+    {
+        <String as ToSql>::accepts(ty)
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        self.to_string().to_sql_checked(ty, out)
+    }
+}
+
+#[derive(FromSqlRow, Debug, Serialize, Deserialize, Schema, Clone, PartialEq)]
+pub struct PendingMove {
+    pub id: i32,
+    pub input_path: StackString,
+    pub output_path: StackString,
+    pub job_type: StackString,
+    pub prefix: StackString,
+    pub show: StackString,
+    pub season: i32,
+    pub episode: i32,
+    pub status: PendingMoveStatus,
+    pub created_at: DateTimeWrapper,
+    pub last_modified: DateTimeWrapper,
+}
+
+impl PendingMove {
+    pub async fn get_pending(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, input_path, output_path, job_type, prefix, show, season, episode,
+                       status, created_at, last_modified
+                FROM pending_moves
+                WHERE status = $status
+                ORDER BY created_at
+            "#,
+            status = PendingMoveStatus::Pending
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_by_id(pool: &PgPool, id: i32) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT id, input_path, output_path, job_type, prefix, show, season, episode,
+                       status, created_at, last_modified
+                FROM pending_moves
+                WHERE id = $id
+            "#,
+            id = id
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    // A file that reappears in the watch folder goes back to pending
+    pub async fn upsert(
+        pool: &PgPool,
+        request: &TranscodeServiceRequest,
+        show: &str,
+        season: i32,
+        episode: i32,
+    ) -> Result<Self, Error> {
+        let input_path = request.input_path.to_string_lossy();
+        let output_path = request.output_path.to_string_lossy();
+        let job_type = request.job_type.to_string();
+        let query = query!(
+            r#"
+                INSERT INTO pending_moves
+                    (input_path, output_path, job_type, prefix, show, season, episode, status,
+                     created_at, last_modified)
+                VALUES ($input_path, $output_path, $job_type, $prefix, $show, $season, $episode,
+                        $status, now(), now())
+                ON CONFLICT (input_path) DO UPDATE
+                SET output_path=$output_path, job_type=$job_type, prefix=$prefix, show=$show,
+                    season=$season, episode=$episode, status=$status, last_modified=now()
+                RETURNING id, input_path, output_path, job_type, prefix, show, season, episode,
+                          status, created_at, last_modified
+            "#,
+            input_path = input_path,
+            output_path = output_path,
+            job_type = job_type,
+            prefix = request.prefix,
+            show = show,
+            season = season,
+            episode = episode,
+            status = PendingMoveStatus::Pending
+        );
+        let conn = pool.get().await?;
+        query.fetch_one(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn delete_pending_by_path(pool: &PgPool, input_path: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM pending_moves WHERE input_path = $input_path AND status = $status",
+            input_path = input_path,
+            status = PendingMoveStatus::Pending
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    async fn set_status(&self, pool: &PgPool, status: PendingMoveStatus) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE pending_moves
+                SET status=$status, last_modified=now()
+                WHERE id = $id
+            "#,
+            status = status,
+            id = self.id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub fn get_request(&self) -> Result<TranscodeServiceRequest, Error> {
+        Ok(TranscodeServiceRequest::new(
+            self.job_type.parse()?,
+            &self.prefix,
+            Path::new(self.input_path.as_str()),
+            Path::new(self.output_path.as_str()),
+        ))
+    }
+
+    pub async fn approve(
+        &self,
+        config: &Config,
+        pool: &PgPool,
+        stdout: &StdoutChannel<StackString>,
+    ) -> Result<TranscodeServiceRequest, Error> {
+        if self.status != PendingMoveStatus::Pending {
+            return Err(format_err!("Move {} is already {}", self.id, self.status));
+        }
+        if !Path::new(self.input_path.as_str()).exists() {
+            return Err(format_err!("{} does not exist", self.input_path));
+        }
+        let mut request = self.get_request()?;
+        if let Some(settings) =
+            ShowSettings::get_settings_for_path(pool, &request.input_path).await?
+        {
+            request.options = settings.transcode_options();
+        }
+        let transcode_service = TranscodeService::new(config, &config.remcom_queue, pool, stdout);
+        transcode_service
+            .publish_transcode_job(&request, |_| async move { Ok(()) })
+            .await?;
+        self.set_status(pool, PendingMoveStatus::Approved).await?;
+        Ok(request)
+    }
+
+    pub async fn deny(&self, pool: &PgPool) -> Result<(), Error> {
+        if self.status != PendingMoveStatus::Pending {
+            return Err(format_err!("Move {} is already {}", self.id, self.status));
+        }
+        self.set_status(pool, PendingMoveStatus::Denied).await
+    }
+
+    pub fn get_html(&self) -> StackString {
+        let input_name = Path::new(self.input_path.as_str())
+            .file_name()
+            .map(|f| f.to_string_lossy())
+            .unwrap_or_default();
+        format!(
+            r#"{input}</td><td>{output}</td><td>{show} s{season:02} ep{episode:02}</td><td><button type="submit" onclick="pending_move({id}, 'approve');"> approve </button><button type="submit" onclick="pending_move({id}, 'deny');"> deny </button>"#,
+            input = input_name,
+            output = self.output_path,
+            show = self.show,
+            season = self.season,
+            episode = self.episode,
+            id = self.id,
+        )
+        .into()
+    }
+
+    pub fn get_table(moves: &[Self]) -> Vec<StackString> {
+        if moves.is_empty() {
+            return Vec::new();
+        }
+        vec![
+            "Pending auto-moves:<br>".into(),
+            r#"<table border="1" class="dataframe">"#.into(),
+            r#"<thead><tr><th>File</th><th>Destination</th><th>Episode</th><th>Action</th></tr></thead>"#.into(),
+            format!(
+                r#"<tbody><tr><td>{}</td></tr></tbody>"#,
+                moves.iter().map(Self::get_html).join("</td></tr><tr><td>")
+            )
+            .into(),
+            "</table>".into(),
+        ]
+    }
+}
+
+// Only files whose stem parses to a show, season and episode can be routed to a show directory
+fn parse_episode(path: &Path) -> Option<(StackString, i32, i32)> {
+    let file_stem = path.file_stem()?.to_string_lossy();
+    let (show, season, episode) = parse_file_stem(&file_stem);
+    if season < 0 || episode < 0 {
+        None
+    } else {
+        Some((show, season, episode))
+    }
+}
+
+pub struct WatchFolder {
+    config: Config,
+    pool: PgPool,
+    stdout: StdoutChannel,
+}
+
+impl WatchFolder {
+    pub fn new(config: &Config, pool: &PgPool, stdout: &StdoutChannel) -> Self {
+        Self {
+            config: config.clone(),
+            pool: pool.clone(),
+            stdout: stdout.clone(),
+        }
+    }
+
+    fn debounce(&self) -> Duration {
+        Duration::from_secs(self.config.watch_debounce_seconds)
+    }
+
+    pub async fn handle_action(&self, action: &WatchAction) -> Result<(), Error> {
+        match action {
+            WatchAction::Add(path) => {
+                if !wait_for_stable_size(path, self.debounce()).await? {
+                    return Ok(());
+                }
+                let (show, season, episode) = match parse_episode(path) {
+                    Some(x) => x,
+                    None => {
+                        debug!("watch folder ignoring {:?}", path);
+                        return Ok(());
+                    }
+                };
+                let directory: Option<&Path> = None;
+                let settings = ShowSettings::get_settings_for_path(&self.pool, path).await?;
+                let request = TranscodeServiceRequest::create_remcom_request(
+                    &self.config,
+                    path,
+                    directory,
+                    false,
+                    settings.as_ref(),
+                )
+                .await?;
+                PendingMove::upsert(&self.pool, &request, &show, season, episode).await?;
+                self.stdout.send(format!(
+                    "watch folder pending {} -> {}",
+                    request.input_path.to_string_lossy(),
+                    request.output_path.to_string_lossy()
+                ));
+            }
+            WatchAction::Remove(path) => {
+                let path = path.to_string_lossy();
+                if PendingMove::delete_pending_by_path(&self.pool, &path).await? > 0 {
+                    self.stdout.send(format!("watch folder removed {}", path));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn spawn_watcher(&self, send: UnboundedSender<DebouncedEvent>) -> Result<(), Error> {
+        let dir = self
+            .config
+            .watch_folder
+            .clone()
+            .ok_or_else(|| format_err!("No watch folder"))?;
+        if !dir.exists() {
+            return Err(format_err!("{} does not exist", dir.to_string_lossy()));
+        }
+        let dirs = vec![dir];
+        let delay = self.debounce();
+        spawn_blocking(move || {
+            if let Err(e) = watch_dirs(&dirs, delay, &send) {
+                error!("failed to watch {:?} {}", dirs, e);
+            }
+        });
+        Ok(())
+    }
+
+    pub async fn run(&self) -> Result<(), Error> {
+        let (send, mut recv) = unbounded_channel();
+        self.spawn_watcher(send)?;
+        while let Some(event) = recv.recv().await {
+            debug!("watch folder event {:?}", event);
+            for action in watch_actions(event, &self.config.suffixes) {
+                if let Err(e) = self.handle_action(&action).await {
+                    error!("watch folder failed {:?} {}", action, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::path::Path;
+
+    use crate::watch_folder::{parse_episode, PendingMoveStatus};
+
+    #[test]
+    fn test_parse_episode() -> Result<(), Error> {
+        let path = Path::new("/tmp/incoming/mr_robot_s02_ep03.mp4");
+        assert_eq!(parse_episode(path), Some(("mr_robot".into(), 2, 3)));
+        assert_eq!(
+            parse_episode(Path::new("/tmp/incoming/the_matrix.mp4")),
+            None
+        );
+
+        let status: PendingMoveStatus = "denied".parse()?;
+        assert_eq!(status, PendingMoveStatus::Denied);
+        assert_eq!(status.to_string().as_str(), "denied");
+        assert!("moved".parse::<PendingMoveStatus>().is_err());
+        Ok(())
+    }
+}
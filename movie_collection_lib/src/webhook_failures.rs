@@ -0,0 +1,163 @@
+use anyhow::Error;
+use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use crate::{datetime_wrapper::DateTimeWrapper, pgpool::PgPool, utils::escape_html};
+
+pub const PLEX_WEBHOOK_SOURCE: &str = "plex";
+pub const TRAKT_WEBHOOK_SOURCE: &str = "trakt";
+pub const MAX_WEBHOOK_ATTEMPTS: i32 = 5;
+const PAYLOAD_PREVIEW_LEN: usize = 200;
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+pub struct WebhookFailure {
+    pub id: i32,
+    pub source: StackString,
+    pub payload: StackString,
+    pub error: StackString,
+    pub created_at: DateTimeWrapper,
+    pub replayed_at: Option<DateTimeWrapper>,
+    pub attempts: i32,
+}
+
+impl WebhookFailure {
+    pub async fn record(
+        pool: &PgPool,
+        source: &str,
+        payload: &str,
+        error: &str,
+    ) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                INSERT INTO webhook_failures (source, payload, error)
+                VALUES ($source, $payload, $error)
+            "#,
+            source = source,
+            payload = payload,
+            error = error
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub async fn get_by_id(pool: &PgPool, id: i32) -> Result<Option<Self>, Error> {
+        let query = query!("SELECT * FROM webhook_failures WHERE id = $id", id = id);
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_pending(pool: &PgPool, source: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM webhook_failures
+                WHERE source = $source AND replayed_at IS NULL
+                ORDER BY created_at DESC
+            "#,
+            source = source
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn get_retryable(pool: &PgPool, source: &str) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM webhook_failures
+                WHERE source = $source
+                  AND replayed_at IS NULL
+                  AND attempts < $max_attempts
+                ORDER BY created_at
+            "#,
+            source = source,
+            max_attempts = MAX_WEBHOOK_ATTEMPTS
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    pub async fn mark_replayed(&self, pool: &PgPool) -> Result<(), Error> {
+        let query = query!(
+            "UPDATE webhook_failures SET replayed_at = now() WHERE id = $id",
+            id = self.id
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    pub async fn update_error(&self, pool: &PgPool, error: &str) -> Result<(), Error> {
+        let query = query!(
+            r#"
+                UPDATE webhook_failures
+                SET error = $error, attempts = attempts + 1
+                WHERE id = $id
+            "#,
+            id = self.id,
+            error = error
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await?;
+        Ok(())
+    }
+
+    fn get_html(&self) -> StackString {
+        let preview: String = self.payload.chars().take(PAYLOAD_PREVIEW_LEN).collect();
+        format!(
+            r#"
+            <tr>
+            <td>{created_at}</td><td>{attempts}</td><td>{error}</td><td><code>{payload}</code></td>
+            <td><button type="submit" onclick="replay_webhook({id})">Replay</button></td>
+            </tr>"#,
+            id = self.id,
+            created_at = self.created_at.format("%Y-%m-%d %H:%M:%S"),
+            attempts = self.attempts,
+            error = escape_html(&self.error),
+            payload = escape_html(&preview),
+        )
+        .into()
+    }
+
+    pub fn get_html_table(failures: &[Self]) -> StackString {
+        let rows: Vec<_> = failures.iter().map(Self::get_html).collect();
+        format!(
+            r#"
+            <h3>{} failed webhook payload(s)</h3>
+            <table border="0">
+            <tr><th>Received</th><th>Attempts</th><th>Error</th><th>Payload</th><th></th></tr>
+            {}
+            </table>
+            "#,
+            failures.len(),
+            rows.join(""),
+        )
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::webhook_failures::WebhookFailure;
+
+    #[test]
+    fn test_webhook_failure_html() {
+        let failure = WebhookFailure {
+            id: 3,
+            source: "plex".into(),
+            payload: r#"{"event": "<media.unknown>"}"#.into(),
+            error: "unknown variant `media.unknown`".into(),
+            created_at: Utc::now().into(),
+            replayed_at: None,
+            attempts: 2,
+        };
+        let html = WebhookFailure::get_html_table(&[failure]);
+        assert!(html.contains("1 failed webhook payload(s)"));
+        assert!(html.contains("{&quot;event&quot;: &quot;&lt;media.unknown&gt;&quot;}"));
+        assert!(html.contains("replay_webhook(3)"));
+        assert!(html.contains("<td>2</td>"));
+    }
+}
@@ -3,7 +3,7 @@ use chrono::{DateTime, Duration, Utc};
 use futures::future::try_join_all;
 use refinery::embed_migrations;
 use stack_string::StackString;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration as StdDuration};
 use stdout_channel::StdoutChannel;
 use structopt::StructOpt;
 use tokio::{
@@ -12,13 +12,17 @@ use tokio::{
 };
 
 use movie_collection_lib::{
+    clock::SystemClock,
     config::Config,
+    imdb_backfill::{reset_imdb_backfill, run_imdb_backfill},
     imdb_episodes::ImdbEpisodes,
     imdb_ratings::ImdbRatings,
+    kodi_nfo::export_kodi_nfo,
     movie_collection::{LastModifiedResponse, MovieCollection, MovieCollectionRow},
     movie_queue::{MovieQueueDB, MovieQueueRow},
+    music_collection::make_music_collection,
     pgpool::PgPool,
-    plex_events::PlexEvent,
+    plex_events::{PlexEvent, PlexEventDailyCount},
     transcode_service::transcode_status,
 };
 
@@ -46,6 +50,27 @@ enum MovieQueueCli {
         start_timestamp: Option<DateTime<Utc>>,
     },
     Status,
+    /// Detect end credits start time for collection entries
+    DetectCredits,
+    /// Scan MUSIC_DIRS and update music_collection from file tags
+    MakeMusicCollection,
+    /// Roll up plex events older than the retention period into daily counts
+    ArchivePlexEvents,
+    /// Backfill imdb episodes for all tv shows, resuming from the last run
+    ImdbBackfill {
+        /// Delay between shows in milliseconds
+        #[structopt(short, long, default_value = "1000")]
+        delay_ms: u64,
+        /// Clear checkpoints and start over
+        #[structopt(short, long)]
+        restart: bool,
+    },
+    /// Write Kodi .nfo sidecar files for the collection
+    KodiNfo {
+        /// Replace existing .nfo files
+        #[structopt(short, long)]
+        overwrite: bool,
+    },
     /// Run refinery migrations
     RunMigrations,
 }
@@ -89,13 +114,7 @@ impl MovieQueueCli {
                         let episodes: Vec<ImdbEpisodes> = serde_json::from_str(&data)?;
                         let futures = episodes.into_iter().map(|episode| {
                             let pool = pool.clone();
-                            async move {
-                                match episode.get_index(&pool).await? {
-                                    Some(_) => episode.update_episode(&pool).await?,
-                                    None => episode.insert_episode(&pool).await?,
-                                };
-                                Ok(())
-                            }
+                            async move { episode.upsert_episode(&pool).await }
                         });
                         let results: Result<Vec<_>, Error> = try_join_all(futures).await;
                         stdout.send(format!("imdb_episodes {}\n", results?.len()));
@@ -195,9 +214,15 @@ impl MovieQueueCli {
                         file.write_all(&serde_json::to_vec(&episodes)?).await?;
                     }
                     "plex_event" => {
-                        let events =
-                            PlexEvent::get_events(&pool, Some(start_timestamp), None, None, None)
-                                .await?;
+                        let events = PlexEvent::get_events(
+                            &pool,
+                            Some(start_timestamp),
+                            None,
+                            None,
+                            None,
+                            &[],
+                        )
+                        .await?;
                         file.write_all(&serde_json::to_vec(&events)?).await?;
                     }
                     "movie_collection" => {
@@ -214,9 +239,39 @@ impl MovieQueueCli {
                 }
             }
             Self::Status => {
-                let status = transcode_status(&config).await?;
+                let status = transcode_status(&config, &pool).await?;
                 println!("{}", status);
             }
+            Self::DetectCredits => {
+                let mc = MovieCollection::new(&config, &pool, &stdout);
+                mc.detect_credits().await?;
+                stdout.close().await?;
+            }
+            Self::MakeMusicCollection => {
+                let count = make_music_collection(&config, &pool, &stdout).await?;
+                stdout.send(format!("music_collection {}\n", count));
+                stdout.close().await?;
+            }
+            Self::ArchivePlexEvents => {
+                let archived =
+                    PlexEventDailyCount::archive_expired(&config, &pool, &SystemClock).await?;
+                stdout.send(format!("archived plex events {}\n", archived));
+                stdout.close().await?;
+            }
+            Self::ImdbBackfill { delay_ms, restart } => {
+                if restart {
+                    reset_imdb_backfill(&pool).await?;
+                }
+                let delay = StdDuration::from_millis(delay_ms);
+                let report = run_imdb_backfill(&config, &pool, &stdout, delay).await?;
+                stdout.send(format!("{}\n", report));
+                stdout.close().await?;
+            }
+            Self::KodiNfo { overwrite } => {
+                let report = export_kodi_nfo(&pool, overwrite).await?;
+                stdout.send(format!("{}\n", report));
+                stdout.close().await?;
+            }
             Self::RunMigrations => {
                 let mut conn = pool.get().await?;
                 migrations::runner().run_async(&mut **conn).await?;
@@ -1,11 +1,11 @@
 #![allow(clippy::used_underscore_binding)]
 #![allow(clippy::needless_pass_by_value)]
 
-use movie_collection_http::movie_queue_app::start_app;
+use movie_collection_http::{movie_queue_app::start_app, request_tracing::init_tracing};
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    init_tracing();
 
     start_app().await.unwrap();
 }
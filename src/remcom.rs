@@ -10,6 +10,7 @@ use tokio::fs;
 use movie_collection_lib::{
     config::Config,
     pgpool::PgPool,
+    show_settings::ShowSettings,
     transcode_service::{TranscodeService, TranscodeServiceRequest},
 };
 use transcode_lib::transcode_channel::TranscodeChannel;
@@ -23,11 +24,14 @@ async fn remcom(
     stdout: &StdoutChannel<StackString>,
 ) -> Result<(), Error> {
     for file in files {
+        let settings =
+            ShowSettings::get_settings_for_path(&remcom_service.pool, file.as_ref()).await?;
         let payload = TranscodeServiceRequest::create_remcom_request(
             &config,
             file.as_ref(),
             directory.as_ref(),
             unwatched,
+            settings.as_ref(),
         )
         .await?;
         publish_single(&remcom_service, &payload).await?;
@@ -1,6 +1,7 @@
 #![allow(clippy::used_underscore_binding)]
 
 use anyhow::Error;
+use log::info;
 use stdout_channel::StdoutChannel;
 use tokio::task::spawn;
 use transcode_lib::transcode_channel::TranscodeChannel;
@@ -21,6 +22,16 @@ async fn main() -> Result<(), Error> {
     let remcom_channel = TranscodeChannel::open_channel().await?;
     remcom_channel.init(&remcom_service.queue).await?;
 
+    for (service, channel) in &[
+        (&transcode_service, &transcode_channel),
+        (&remcom_service, &remcom_channel),
+    ] {
+        let resumed = service
+            .resume_jobs(|data| async move { channel.publish(&service.queue, data).await })
+            .await?;
+        info!("resumed {} jobs on {}", resumed, service.queue);
+    }
+
     let transcode_task = spawn(async move {
         transcode_channel
             .read_transcode_job(&transcode_service.queue, |data| {
@@ -0,0 +1,278 @@
+use anyhow::{format_err, Error};
+use chrono::{Duration, Utc};
+use reqwest::{
+    header::{HeaderMap, COOKIE},
+    multipart::{Form, Part},
+    Client, Url,
+};
+use serde::Deserialize;
+use stack_string::StackString;
+use std::{env::var, future::Future, process::exit};
+use stdout_channel::StdoutChannel;
+use structopt::StructOpt;
+
+use movie_collection_lib::{config::Config, pgpool::PgPool};
+
+const FIXTURE_PATH: &str = "/tmp/smoketestfixture.mp4";
+const FIXTURE_ACCOUNT: &str = "smoke_test";
+const FIXTURE_SEARCH: &str = "smoketestfixture";
+const NOOP_TRANSCODE_PATTERN: &str = "smoketestnomatch";
+const PLEX_WEBHOOK_FIXTURE: &str = include_str!("../tests/data/plex_webhook_payload.json");
+
+#[derive(StructOpt)]
+/// Exercise the critical api paths of a running movie-queue-http instance
+struct SmokeTestOpt {
+    /// Base url of the instance (defaults to http://localhost:{port})
+    #[structopt(long, short)]
+    base_url: Option<StackString>,
+
+    /// JWT issued by the auth service (defaults to $SMOKE_TEST_JWT)
+    #[structopt(long, short)]
+    jwt: Option<StackString>,
+}
+
+#[derive(Deserialize)]
+struct SmokeTestUser {
+    email: StackString,
+}
+
+#[derive(Deserialize)]
+struct SmokeTestSearchResult {
+    detail: StackString,
+}
+
+#[derive(Deserialize)]
+struct SmokeTestSearchResults {
+    results: Vec<SmokeTestSearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SmokeTestPlexEvent {
+    player_title: StackString,
+}
+
+struct SmokeTest {
+    client: Client,
+    base_url: Url,
+    plex_webhook_key: StackString,
+    pool: PgPool,
+}
+
+impl SmokeTest {
+    fn new(config: &Config, opts: SmokeTestOpt) -> Result<Self, Error> {
+        let base_url = opts
+            .base_url
+            .unwrap_or_else(|| format!("http://localhost:{}", config.port).into());
+        let jwt = match opts.jwt {
+            Some(jwt) => jwt,
+            None => var("SMOKE_TEST_JWT")
+                .map_err(|_| format_err!("No jwt, pass --jwt or set SMOKE_TEST_JWT"))?
+                .into(),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, format!("jwt={}", jwt).parse()?);
+        let client = Client::builder().default_headers(headers).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.parse()?,
+            plex_webhook_key: config.plex_webhook_key.to_string().into(),
+            pool: PgPool::new(&config.pgurl),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url, Error> {
+        self.base_url.join(path).map_err(Into::into)
+    }
+
+    async fn search_fixture(&self) -> Result<bool, Error> {
+        let url = self.url("/list/search")?;
+        let results: SmokeTestSearchResults = self
+            .client
+            .get(url)
+            .query(&[("q", FIXTURE_SEARCH)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(results.results.iter().any(|r| r.detail == FIXTURE_PATH))
+    }
+
+    async fn login(&self) -> Result<StackString, Error> {
+        let url = self.url("/list/user")?;
+        let user: SmokeTestUser = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(format!("logged in as {}", user.email).into())
+    }
+
+    async fn list_queue(&self) -> Result<StackString, Error> {
+        let url = self.url("/list/full_queue")?;
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(format!("{} rows", body.matches("<tr").count()).into())
+    }
+
+    async fn collection_entry(&self) -> Result<StackString, Error> {
+        let url = self.url("/list/movie_collection")?;
+        let body = serde_json::json!({
+            "collection": [{"idx": 1, "path": FIXTURE_PATH, "show": FIXTURE_SEARCH}],
+            "suppress_hooks": true,
+        });
+        self.client
+            .post(url.clone())
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        if !self.search_fixture().await? {
+            return Err(format_err!("{} not found after insert", FIXTURE_PATH));
+        }
+        self.client
+            .delete(url)
+            .query(&[("path", FIXTURE_PATH)])
+            .send()
+            .await?
+            .error_for_status()?;
+        if self.search_fixture().await? {
+            return Err(format_err!("{} still present after delete", FIXTURE_PATH));
+        }
+        Ok(format!("added and removed {}", FIXTURE_PATH).into())
+    }
+
+    async fn dry_run_transcode(&self) -> Result<StackString, Error> {
+        let url = self.url(&format!("/list/transcode/queue/{}", NOOP_TRANSCODE_PATTERN))?;
+        let body = self
+            .client
+            .get(url)
+            .query(&[("dry_run", "true")])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        if !body.is_empty() {
+            return Err(format_err!("expected no-op, got {}", body));
+        }
+        Ok("no jobs published".into())
+    }
+
+    async fn webhook_ingest(&self) -> Result<StackString, Error> {
+        let start_timestamp = (Utc::now() - Duration::minutes(1)).to_rfc3339();
+        let url = self.url(&format!("/list/plex/webhook/{}", self.plex_webhook_key))?;
+        let form = Form::new().part("payload", Part::text(PLEX_WEBHOOK_FIXTURE));
+        self.client
+            .post(url)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        let url = self.url("/list/plex_event")?;
+        let events: Vec<SmokeTestPlexEvent> = self
+            .client
+            .get(url)
+            .query(&[
+                ("start_timestamp", start_timestamp.as_str()),
+                ("event_type", "media.pause"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if !events.iter().any(|e| e.player_title == "smoke_test_player") {
+            return Err(format_err!("fixture event not found"));
+        }
+        Ok("fixture event recorded".into())
+    }
+
+    // The collection delete route only flags the row and there is no route to delete
+    // plex events, so the fixture rows are removed from the database directly
+    async fn cleanup(&self) -> Result<StackString, Error> {
+        let conn = self.pool.get().await?;
+        let events = conn
+            .execute(
+                "DELETE FROM plex_event WHERE account = $1",
+                &[&FIXTURE_ACCOUNT],
+            )
+            .await?;
+        conn.execute(
+            r#"
+                DELETE FROM plex_metadata
+                WHERE collection_idx IN (SELECT idx FROM movie_collection WHERE path = $1)
+            "#,
+            &[&FIXTURE_PATH],
+        )
+        .await?;
+        let entries = conn
+            .execute(
+                "DELETE FROM movie_collection WHERE path = $1",
+                &[&FIXTURE_PATH],
+            )
+            .await?;
+        Ok(format!("removed {} events {} collection entries", events, entries).into())
+    }
+}
+
+async fn run_check<F>(stdout: &StdoutChannel, name: &str, check: F) -> bool
+where
+    F: Future<Output = Result<StackString, Error>>,
+{
+    match check.await {
+        Ok(detail) => {
+            stdout.send(format!("PASS {:20} {}", name, detail));
+            true
+        }
+        Err(e) => {
+            stdout.send(format!("FAIL {:20} {}", name, e));
+            false
+        }
+    }
+}
+
+async fn smoke_test() -> Result<bool, Error> {
+    let opts = SmokeTestOpt::from_args();
+    let config = Config::with_config()?;
+    let stdout = StdoutChannel::new();
+    let smoke_test = SmokeTest::new(&config, opts)?;
+
+    let results = vec![
+        run_check(&stdout, "login", smoke_test.login()).await,
+        run_check(&stdout, "list_queue", smoke_test.list_queue()).await,
+        run_check(&stdout, "collection_entry", smoke_test.collection_entry()).await,
+        run_check(&stdout, "dry_run_transcode", smoke_test.dry_run_transcode()).await,
+        run_check(&stdout, "webhook_ingest", smoke_test.webhook_ingest()).await,
+        // runs whether or not the checks above passed
+        run_check(&stdout, "cleanup", smoke_test.cleanup()).await,
+    ];
+    let passed = results.iter().filter(|x| **x).count();
+    let failed = results.len() - passed;
+    stdout.send(format!("{} passed, {} failed", passed, failed));
+    stdout.close().await?;
+    Ok(failed == 0)
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    match smoke_test().await {
+        Ok(true) => (),
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2)
+        }
+    }
+}
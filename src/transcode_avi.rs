@@ -10,6 +10,7 @@ use tokio::fs;
 use movie_collection_lib::{
     config::Config,
     pgpool::PgPool,
+    show_settings::ShowSettings,
     transcode_service::{movie_dir, TranscodeService, TranscodeServiceRequest},
 };
 use transcode_lib::transcode_channel::TranscodeChannel;
@@ -33,7 +34,9 @@ async fn transcode_avi(
         if !path.exists() {
             panic!("file doesn't exist {}", path.to_string_lossy());
         }
-        let payload = TranscodeServiceRequest::create_transcode_request(&config, &path)?;
+        let settings = ShowSettings::get_settings_for_path(&transcode_service.pool, &path).await?;
+        let payload =
+            TranscodeServiceRequest::create_transcode_request(&config, &path, settings.as_ref())?;
         publish_single(&transcode_service, &payload).await?;
         stdout.send(format!("script {:?}", payload));
     }
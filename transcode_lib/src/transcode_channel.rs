@@ -10,9 +10,9 @@ use lapin::{
     types::FieldTable,
     BasicProperties, Channel, Queue,
 };
-use log::{debug, error};
 use serde::de::DeserializeOwned;
 use std::future::Future;
+use tracing::{debug, error};
 
 #[derive(Clone, Deref, DerefMut)]
 pub struct TranscodeChannel(Channel);